@@ -5,19 +5,23 @@
 //! - Auto-detects optimal settings for ANY graphics card
 //! - ASIC-resistant through algorithmic complexity
 //! - Optimized for RTX series, AMD RDNA, Intel Arc, and integrated graphics
+//!
+//! The hash is consensus: it depends only on its inputs, never on the
+//! machine. Both paths read the same dataset items; [`AequiHash::hash_full`]
+//! takes them from the DAG, [`AequiHash::hash_light`] derives them from the
+//! cache. SIMD code (AVX2 on x86_64, NEON on aarch64) only ever computes the
+//! scalar result faster.
 
 use blake3::Hasher;
+use byteorder::{ByteOrder, LittleEndian};
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
 use sha3::{Digest, Keccak256};
-use std::arch::x86_64::_mm256_shuffle_epi8;
-use super::gpu_config::GpuConfig;
-use std::mem;
+use crate::dag::{DAG, DAG_ITEM_BYTES, DAG_ITEM_WORDS};
 
 /// Epoch length in blocks (changes DAG every ~2 hours)
 pub const EPOCH_LENGTH: u64 = 240;
 
-/// DAG size in bytes (~4GB for RTX 3060 compatibility)
-pub const DAG_SIZE: usize = 4 * 1024 * 1024 * 1024; // 4 GB
-
 /// Cache size for light verification
 pub const CACHE_SIZE: usize = 64 * 1024 * 1024; // 64 MB
 
@@ -27,8 +31,37 @@ pub const MIX_ROUNDS: usize = 64;
 /// Mix output size in 32-bit words
 pub const MIX_WORDS: usize = 32;
 
-/// Number of dataset accesses per hash
-pub const DATASET_ACCESSES: usize = 64;
+/// Dataset items one hash reads, two per round
+pub const ITEMS_PER_HASH: usize = 2 * MIX_ROUNDS;
+
+/// Domain tag of every epoch seed
+const SEED_TAG: &[u8] = b"AequiHash Epoch Seed";
+
+/// Domain tag of [`AequiHash::program_fingerprint`]
+const FINGERPRINT_TAG: &[u8] = b"AequiHash Program Fingerprint";
+
+/// FNV prime used by the mixing step
+const FNV_PRIME: u32 = 0x01000193;
+
+/// Multipliers spreading the seed hash over the upper half of the mix
+const MIX_INIT_MULTIPLIERS: [u32; 2] = [0x85ebca6b, 0xc2b2ae35];
+
+// Each round fills the mix with two dataset items
+const _: () = assert!(MIX_WORDS == 2 * DAG_ITEM_WORDS);
+
+/// Both outputs of one AequiHash
+///
+/// As in Ethash, the hash is computed from the mix hash alone: checking
+/// [`AequiHash::final_hash`] rejects a wrong solution without touching the
+/// dataset, and only the mix hash needs the DAG accesses to be replayed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HashOutput {
+    /// Digest of the mix after the last round
+    pub mix_hash: [u8; 32],
+
+    /// Final hash, compared with the target
+    pub hash: [u8; 32],
+}
 
 /// Algorithm variants for random program
 #[derive(Clone, Copy, Debug)]
@@ -58,6 +91,11 @@ impl MathOp {
         }
     }
 
+    /// Code of the operation, the inverse of [`MathOp::from_seed`]
+    pub fn code(&self) -> u8 {
+        *self as u8
+    }
+
     /// Execute the operation
     pub fn execute(&self, a: u32, b: u32) -> u32 {
         match self {
@@ -73,67 +111,158 @@ impl MathOp {
     }
 }
 
+/// One round of the epoch's random program
+///
+/// The round reads the dataset item chosen by mix word `address` and the
+/// one after it, as 32 data words, then updates every mix word:
+/// `mix[j] = op(mix[j], data[(j + offset) % MIX_WORDS])`.
+#[derive(Clone, Copy, Debug)]
+pub struct Round {
+    /// Operation combining the mix with the data
+    pub op: MathOp,
+
+    /// Mix word selecting the dataset item, below [`MIX_WORDS`]
+    pub address: u8,
+
+    /// Rotation of the data words against the mix, below [`MIX_WORDS`]
+    pub offset: u8,
+}
+
+/// Bytes of one encoded [`Round`]
+pub const ROUND_BYTES: usize = 3;
+
+impl Round {
+    /// Round drawn from one ChaCha20 output word
+    ///
+    /// Byte 0 gives the operation, byte 1 the address and byte 2 the offset,
+    /// each reduced by its range; byte 3 is unused.
+    pub fn from_word(word: u32) -> Self {
+        let [op, address, offset, _] = word.to_le_bytes();
+        Self {
+            op: MathOp::from_seed(op),
+            address: address % MIX_WORDS as u8,
+            offset: offset % MIX_WORDS as u8,
+        }
+    }
+
+    /// `[op code, address, offset]`, as the GPU kernels read rounds
+    pub fn encode(&self) -> [u8; ROUND_BYTES] {
+        [self.op.code(), self.address, self.offset]
+    }
+}
+
 /// AequiHash algorithm instance
 pub struct AequiHash {
     /// Current epoch
     epoch: u64,
 
-    /// Cached seed for the epoch
+    /// Seed of the epoch, from [`AequiHash::epoch_seed`]
     seed: [u8; 32],
 
-    /// Random number generator for the epoch
-    rng: ChaCha20Rng,
+    /// Random program of the epoch, one entry per mixing round
+    program: Vec<Round>,
 
-    /// Precomputed operation sequence for the epoch
-    operations: Vec<MathOp>,
+    /// Items in the epoch's dataset, as [`DAG::size_for_epoch`] sizes it
+    items: usize,
 }
 
 impl AequiHash {
-    /// Create a new AequiHash instance with GPU-optimized initialization
-    pub fn new(epoch: u64) -> Self {
-        let seed = Self::compute_epoch_seed(epoch);
-
-        // GPU-optimized operation sequence generation
-        let operations = Self::generate_gpu_optimized_ops(seed);
-
+    /// Create a new AequiHash instance for `epoch`, seeded with `seed`
+    ///
+    /// `seed` must be the epoch's [`AequiHash::epoch_seed`]; the program,
+    /// the cache and the DAG all follow it.
+    pub fn new(epoch: u64, seed: &[u8; 32]) -> Self {
         Self {
             epoch,
-            seed,
-            // Use deterministic but GPU-friendly RNG
-            rng: ChaCha20Rng::from_seed(seed),
-            operations,
+            seed: *seed,
+            program: Self::generate_program(*seed),
+            items: DAG::size_for_epoch(epoch) / DAG_ITEM_BYTES,
         }
     }
 
-    /// Generate GPU-optimized operation sequence
-    fn generate_gpu_optimized_ops(seed: [u8; 32]) -> Vec<MathOp> {
-        let mut ops = Vec::with_capacity(MIX_ROUNDS);
+    /// Epoch this instance hashes for
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
 
-        // Create GPU-friendly sequence patterns
-        for i in 0..MIX_ROUNDS {
-            // Use predictable but effective pattern for GPU optimization
-            let seed_byte = seed[i % 32];
-            let cycle_pattern = (i / 8) % 4;
+    /// Seed this instance hashes with
+    pub fn seed(&self) -> &[u8; 32] {
+        &self.seed
+    }
 
-            let op = match cycle_pattern {
-                0 => MathOp::Add,  // Addition-heavy sequences
-                1 => MathOp::Mul,  // Multiplication phases
-                2 => MathOp::Xor,  // XOR mixing
-                3 => MathOp::RotL, // Rotation for diffusion
-                _ => MathOp::Add,
-            };
+    /// Random program of an epoch
+    ///
+    /// ChaCha20 keyed with the epoch seed (nonce 0, counter from 0) yields
+    /// one little-endian word per round, turned into a round by
+    /// [`Round::from_word`]. Pools reimplementing AequiHash should follow
+    /// this exactly; see docs/SPECIFICATIONS.md.
+    pub fn generate_program(seed: [u8; 32]) -> Vec<Round> {
+        let mut rng = ChaCha20Rng::from_seed(seed);
+        (0..MIX_ROUNDS).map(|_| Round::from_word(rng.next_u32())).collect()
+    }
 
-            ops.push(op);
-        }
+    /// Program of the epoch, one round per mixing round
+    pub fn program(&self) -> &[Round] {
+        &self.program
+    }
 
-        ops
+    /// Fingerprint of everything this instance hashes with besides the
+    /// dataset
+    ///
+    /// BLAKE3 of a domain tag, the epoch, the seed, the dataset item count,
+    /// the algorithm constants and the encoded program, all little-endian.
+    /// Pools and miners compare it with the node's before mining an epoch:
+    /// equal fingerprints mean they derived the same program.
+    pub fn program_fingerprint(&self) -> [u8; 32] {
+        let mut hasher = Hasher::new();
+        hasher.update(FINGERPRINT_TAG);
+        hasher.update(&self.epoch.to_le_bytes());
+        hasher.update(&self.seed);
+        hasher.update(&(self.items as u64).to_le_bytes());
+        for constant in [MIX_ROUNDS, MIX_WORDS, DAG_ITEM_WORDS] {
+            hasher.update(&(constant as u32).to_le_bytes());
+        }
+        for constant in [FNV_PRIME, MIX_INIT_MULTIPLIERS[0], MIX_INIT_MULTIPLIERS[1]] {
+            hasher.update(&constant.to_le_bytes());
+        }
+        for round in &self.program {
+            hasher.update(&round.encode());
+        }
+        *hasher.finalize().as_bytes()
     }
 
-    /// Compute the seed for an epoch
+    /// Published fallback seed of an epoch, from its number alone
+    ///
+    /// The seed of epoch 0, which has no previous epoch, and the one used
+    /// by light clients and miners that do not know the anchor block yet.
     pub fn compute_epoch_seed(epoch: u64) -> [u8; 32] {
         let mut hasher = Keccak256::new();
-        hasher.update(b"AequiHash Epoch Seed");
+        hasher.update(SEED_TAG);
+        hasher.update(&epoch.to_le_bytes());
+        hasher.finalize().into()
+    }
+
+    /// Height of the block whose hash seeds `epoch`: the last block of the
+    /// previous epoch, or `None` for epoch 0
+    pub fn seed_anchor_height(epoch: u64) -> Option<u64> {
+        epoch.checked_mul(EPOCH_LENGTH)?.checked_sub(1)
+    }
+
+    /// Seed of `epoch`, chained to `anchor`, the hash of the block at
+    /// [`AequiHash::seed_anchor_height`]
+    ///
+    /// `Keccak-256("AequiHash Epoch Seed" || epoch as u64 LE || anchor)`,
+    /// so an epoch's cache and DAG cannot be computed before the previous
+    /// epoch ends. Without an anchor this is the fallback of
+    /// [`AequiHash::compute_epoch_seed`].
+    pub fn epoch_seed(epoch: u64, anchor: Option<&[u8; 32]>) -> [u8; 32] {
+        let Some(anchor) = anchor else {
+            return Self::compute_epoch_seed(epoch);
+        };
+        let mut hasher = Keccak256::new();
+        hasher.update(SEED_TAG);
         hasher.update(&epoch.to_le_bytes());
+        hasher.update(anchor);
         hasher.finalize().into()
     }
 
@@ -142,172 +271,74 @@ impl AequiHash {
         height / EPOCH_LENGTH
     }
 
-    /// GPU-optimized hash computation with automatic hardware adaptation
-    pub fn hash_light_optimized(&self, header_hash: &[u8; 32], nonce: u64, cache: &[u32]) -> [u8; 32] {
-        // Auto-detect optimal parameters
-        let gpu_config = GpuConfig::detect();
-        
-        // Use GPU-optimized mixing strategy
-        let mut mix = Self::gpu_optimized_initial_mix(header_hash, nonce, &gpu_config);
-        
-        // GPU-parallelizable memory mixing
-        Self::gpu_parallel_mix(&mut mix, cache, &self.operations, &gpu_config);
-        
-        // GPU-friendly final compression
-        Self::gpu_final_compression(header_hash, nonce, &mix, &gpu_config)
-    }
-    
-    /// GPU-optimized initial mixing phase
-    fn gpu_optimized_initial_mix(header_hash: &[u8; 32], nonce: u64, gpu_config: &GpuConfig) -> [u32; MIX_WORDS] {
-        let mut mix = [0u32; MIX_WORDS];
-        
-        // Combine header and nonce for seed
-        let mut seed_hasher = Keccak256::new();
-        seed_hasher.update(header_hash);
-        seed_hasher.update(&nonce.to_le_bytes());
-        let seed_hash = seed_hasher.finalize();
-        
-        // GPU-friendly parallel initialization
-        for i in 0..8 {
-            mix[i] = u32::from_le_bytes([
-                seed_hash[i * 4],
-                seed_hash[i * 4 + 1], 
-                seed_hash[i * 4 + 2],
-                seed_hash[i * 4 + 3]
-            ]);
-            
-            // Apply GPU-optimized constants
-            if gpu_config.supports_avx2 {
-                mix[i + 8] = mix[i].wrapping_mul(0x1000183);
-                mix[i + 16] = mix[i].wrapping_mul(0x6be03b46);
-            } else {
-                mix[i + 8] = mix[i].wrapping_mul(0x85ebca6b);
-                mix[i + 24] = mix[i].wrapping_mul(0xc2b2ae35);
-            }
-        }
-        
-        mix
-    }
-    
-    /// GPU-parallelizable memory mixing
-    fn gpu_parallel_mix(mix: &mut [u32; MIX_WORDS], cache: &[u32], operations: &[MathOp], gpu_config: &GpuConfig) {
-        let batch_size = gpu_config.optimal_batch_size();
-        
-        for round in 0..MIX_ROUNDS {
-            let op = operations[round];
-            
-            // Parallel-friendly cache access pattern
-            for chunk in (0..MIX_WORDS).step_by(batch_size) {
-                for j in chunk..(chunk + batch_size).min(MIX_WORDS) {
-                    if j < cache.len() {
-                        let cache_idx = (mix[round % MIX_WORDS] as usize + j) % cache.len();
-                        let cache_value = unsafe { *cache.get_unchecked(cache_idx) };
-                        
-                        mix[j] = op.execute(mix[j], cache_value);
-                    }
-                }
-            }
-            
-            // GPU-friendly mixing between rounds
-            if gpu_config.supports_wide_simd {
-                Self::simd_mix(mix);
-            } else {
-                Self::scalar_mix(mix);
-            }
-        }
-    }
-    
-    /// SIMD-optimized mixing for modern GPUs
-    #[target_feature(enable = "avx2")]
-    fn simd_mix(mix: &mut [u32; MIX_WORDS]) {
-        // Use SIMD instructions for parallel processing
-        for i in (0..MIX_WORDS).step_by(8) {
-            unsafe {
-                let vec = _mm256_loadu_ps(mix.as_ptr().add(i) as *const f32);
-                let shuffled = _mm256_shuffle_epi8(_mm256_castps_si256(vec), _mm256_set1_epi8(0x1b));
-                let result = _mm256_castsi256_ps(_mm256_xor_si256(_mm256_castps_si256(vec), shuffled));
-                _mm256_storeu_ps(mix.as_mut_ptr().add(i) as *mut f32, result);
-            }
-        }
-    }
-    
-    /// Scalar fallback for older hardware
-    #[target_feature(not(enable = "avx2"))]
-    fn scalar_mix(mix: &mut [u32; MIX_WORDS]) {
-        for i in 0..MIX_WORDS {
-            mix[i] = mix[i].wrapping_mul(0x01000193) ^ mix[(i + 1) % MIX_WORDS];
-        }
+    /// Compute the hash from the light cache (for verification)
+    ///
+    /// Dataset items are derived from `cache` as they are read, so this is
+    /// exactly [`AequiHash::hash_full`] over the epoch's DAG without holding
+    /// the DAG. `cache` must be the epoch's cache of [`CACHE_SIZE`] bytes;
+    /// any other size yields other items. The miner's GPU kernels implement
+    /// the same function; keep them in step with any change here.
+    pub fn hash_light(&self, header_hash: &[u8; 32], nonce: u64, cache: &[u32]) -> [u8; 32] {
+        self.compute_light(header_hash, nonce, cache).hash
     }
-    
-    /// GPU-optimized final compression
-    fn gpu_final_compression(header_hash: &[u8; 32], nonce: u64, mix: &[u32; MIX_WORDS], gpu_config: &GpuConfig) -> [u8; 32] {
-        let mut hasher = Hasher::new();
-        
-        // Blake3 for GPU optimization (modern GPUs have hardware acceleration)
-        hasher.update(header_hash);
-        hasher.update(&nonce.to_le_bytes());
-        
-        // GPU-parallel mixing compression
-        if gpu_config.supports_wide_simd {
-            for chunk in mix.chunks_exact(8) {
-                unsafe {
-                    let vec = _mm256_loadu_ps(chunk.as_ptr() as *const f32);
-                    let processed = _mm256_mullo_epi32(vec, _mm256_set1_epi32(0x9e3779b9));
-                    _mm256_storeu_ps(std::ptr::null_mut(), processed);
-                    
-                    for element in chunk {
-                        hasher.update(&element.to_le_bytes());
-                    }
-                }
-            }
-        } else {
-            for m in mix {
-                hasher.update(&m.to_le_bytes());
-            }
-        }
-        
-        let hash = hasher.finalize();
-        let mut result = [0u8; 32];
-        result.copy_from_slice(&hash.as_bytes()[..32]);
-        result
-    }
-
-        // Memory-hard mixing using cache
-        for round in 0..MIX_ROUNDS {
-            let op = self.operations[round];
 
-            // Generate pseudo-random cache indices
-            let idx_base = mix[round % MIX_WORDS] as usize;
+    /// Mix hash and hash from the light cache, as [`AequiHash::hash_light`]
+    pub fn compute_light(&self, header_hash: &[u8; 32], nonce: u64, cache: &[u32]) -> HashOutput {
+        self.hash_with(header_hash, nonce, self.items, |index| DAG::calc_dag_item(index, cache))
+    }
 
-            for j in 0..MIX_WORDS {
-                let idx = (idx_base.wrapping_add(j * 16)) % cache.len();
-                let cache_value = cache[idx];
-                mix[j] = op.execute(mix[j], cache_value);
-            }
+    /// Compute the hash over `dag`, full or light
+    ///
+    /// Equals [`AequiHash::hash_full`] and [`AequiHash::hash_light`]. On a
+    /// light DAG only the [`ITEMS_PER_HASH`] items the rounds read are
+    /// computed from the cache, so a node verifies nonces found on GPUs
+    /// without holding the dataset in memory.
+    pub fn hash_dag(&self, header_hash: &[u8; 32], nonce: u64, dag: &DAG) -> [u8; 32] {
+        self.compute(header_hash, nonce, dag).hash
+    }
 
-            // FNV-like mixing
-            for j in 0..MIX_WORDS {
-                mix[j] = mix[j].wrapping_mul(0x01000193) ^ mix[(j + 1) % MIX_WORDS];
-            }
-        }
+    /// Mix hash and hash over `dag`, as [`AequiHash::hash_dag`]
+    pub fn compute(&self, header_hash: &[u8; 32], nonce: u64, dag: &DAG) -> HashOutput {
+        self.hash_with(header_hash, nonce, dag.items(), |index| dag.item(index))
+    }
 
-        // Final hash
-        let mut final_hasher = blake3::Hasher::new();
-        final_hasher.update(header_hash);
-        final_hasher.update(&nonce.to_le_bytes());
+    /// Compute the hash from the full DAG (for mining)
+    pub fn hash_full(&self, header_hash: &[u8; 32], nonce: u64, dag: &[u32]) -> [u8; 32] {
+        self.compute_full(header_hash, nonce, dag).hash
+    }
 
-        for m in &mix {
-            final_hasher.update(&m.to_le_bytes());
-        }
+    /// Mix hash and hash from the full DAG, as [`AequiHash::hash_full`]
+    pub fn compute_full(&self, header_hash: &[u8; 32], nonce: u64, dag: &[u32]) -> HashOutput {
+        self.hash_with(header_hash, nonce, dag.len() / DAG_ITEM_WORDS, |index| {
+            let offset = index * DAG_ITEM_WORDS;
+            dag[offset..offset + DAG_ITEM_WORDS].try_into().unwrap()
+        })
+    }
 
-        let hash = final_hasher.finalize();
-        let mut result = [0u8; 32];
-        result.copy_from_slice(hash.as_bytes());
-        result
+    /// Final hash of a solution from its mix hash: BLAKE3 of
+    /// `header_hash || nonce || mix_hash`
+    ///
+    /// Needs neither the cache nor the program, so a submitted mix hash is
+    /// checked against the target before the mix is replayed.
+    pub fn final_hash(header_hash: &[u8; 32], nonce: u64, mix_hash: &[u8; 32]) -> [u8; 32] {
+        let mut hasher = Hasher::new();
+        hasher.update(header_hash);
+        hasher.update(&nonce.to_le_bytes());
+        hasher.update(mix_hash);
+        *hasher.finalize().as_bytes()
     }
 
-    /// Compute the full hash using DAG (for GPU mining)
-    pub fn hash_full(&self, header_hash: &[u8; 32], nonce: u64, dag: &[u32]) -> [u8; 32] {
+    /// AequiHash over a dataset of `items` items, each read through `item`
+    ///
+    /// The result depends on nothing but the inputs: every node and every
+    /// miner, whatever its hardware, computes the same hash.
+    fn hash_with(
+        &self,
+        header_hash: &[u8; 32],
+        nonce: u64,
+        items: usize,
+        mut item: impl FnMut(usize) -> [u32; DAG_ITEM_WORDS],
+    ) -> HashOutput {
         // Initial mix from header
         let mut mix = [0u32; MIX_WORDS];
 
@@ -319,53 +350,31 @@ impl AequiHash {
         for i in 0..8 {
             mix[i] = LittleEndian::read_u32(&seed_hash[i * 4..(i + 1) * 4]);
             mix[i + 8] = mix[i];
-            mix[i + 16] = mix[i].wrapping_mul(0x85ebca6b);
-            mix[i + 24] = mix[i].wrapping_mul(0xc2b2ae35);
+            mix[i + 16] = mix[i].wrapping_mul(MIX_INIT_MULTIPLIERS[0]);
+            mix[i + 24] = mix[i].wrapping_mul(MIX_INIT_MULTIPLIERS[1]);
         }
 
-        // DAG accesses - this is the memory-hard part
-        for access in 0..DATASET_ACCESSES {
-            // Calculate DAG index from current mix state
-            let mix_hash = {
-                let mut h = Keccak256::new();
-                for m in &mix {
-                    h.update(&m.to_le_bytes());
-                }
-                h.finalize()
-            };
-
-            let dag_idx =
-                (LittleEndian::read_u64(&mix_hash[0..8]) as usize) % (dag.len() / MIX_WORDS);
-            let dag_offset = dag_idx * MIX_WORDS;
-
-            // Apply operation for this access round
-            let op = self.operations[access % MIX_ROUNDS];
+        // Memory-hard mixing: two consecutive dataset items per round
+        let fnv_mix = fnv_mix_impl();
+        let mut data = [0u32; MIX_WORDS];
+        for round in &self.program {
+            let index = mix[round.address as usize] as usize % items;
+            data[..DAG_ITEM_WORDS].copy_from_slice(&item(index));
+            data[DAG_ITEM_WORDS..].copy_from_slice(&item((index + 1) % items));
 
+            let offset = round.offset as usize;
             for j in 0..MIX_WORDS {
-                let dag_value = dag.get(dag_offset + j).copied().unwrap_or(0);
-                mix[j] = op.execute(mix[j], dag_value);
+                mix[j] = round.op.execute(mix[j], data[(j + offset) % MIX_WORDS]);
             }
 
-            // Additional mixing
-            for j in 0..MIX_WORDS {
-                mix[j] = mix[j] ^ mix[(j + access) % MIX_WORDS];
-            }
+            fnv_mix(&mut mix);
         }
 
-        // Final compression
-        let mut final_hasher = blake3::Hasher::new();
-        final_hasher.update(header_hash);
-        final_hasher.update(&nonce.to_le_bytes());
-        final_hasher.update(&self.epoch.to_le_bytes());
-
-        for m in &mix {
-            final_hasher.update(&m.to_le_bytes());
+        let mix_hash = compress_mix(&mix);
+        HashOutput {
+            mix_hash,
+            hash: Self::final_hash(header_hash, nonce, &mix_hash),
         }
-
-        let hash = final_hasher.finalize();
-        let mut result = [0u8; 32];
-        result.copy_from_slice(hash.as_bytes());
-        result
     }
 
     /// Verify a hash meets the difficulty target
@@ -377,32 +386,108 @@ impl AequiHash {
         cache: &[u32],
     ) -> bool {
         let hash = self.hash_light(header_hash, nonce, cache);
-        Self::compare_hash_to_target(&hash, target)
+        aequitas_core::target::hash_meets_target(&hash, target)
+    }
+}
+
+/// FNV step of every round, picked for the running CPU
+///
+/// Every version equals [`fnv_mix_scalar`] bit for bit; the choice only
+/// changes speed, never the hash.
+fn fnv_mix_impl() -> fn(&mut [u32; MIX_WORDS]) {
+    #[cfg(target_arch = "x86_64")]
+    if is_x86_feature_detected!("avx2") {
+        // SAFETY: only returned once AVX2 is known to be available
+        return |mix| unsafe { fnv_mix_avx2(mix) };
     }
 
-    /// Compare hash to target (hash <= target means valid)
-    fn compare_hash_to_target(hash: &[u8; 32], target: &[u8; 32]) -> bool {
-        for i in (0..32).rev() {
-            if hash[i] < target[i] {
-                return true;
-            }
-            if hash[i] > target[i] {
-                return false;
-            }
-        }
-        true // Equal
+    #[cfg(target_arch = "aarch64")]
+    if std::arch::is_aarch64_feature_detected!("neon") {
+        // SAFETY: only returned once NEON is known to be available
+        return |mix| unsafe { fnv_mix_neon(mix) };
+    }
+
+    fnv_mix_scalar
+}
+
+/// Mix every word with its successor, `mix[j] * FNV_PRIME ^ mix[j + 1]`,
+/// in order, so the last word takes the already updated first one
+///
+/// This is the reference the SIMD versions are checked against.
+fn fnv_mix_scalar(mix: &mut [u32; MIX_WORDS]) {
+    for j in 0..MIX_WORDS {
+        mix[j] = mix[j].wrapping_mul(FNV_PRIME) ^ mix[(j + 1) % MIX_WORDS];
+    }
+}
+
+/// AVX2 version of [`fnv_mix_scalar`], eight words at a time
+///
+/// # Safety
+///
+/// The CPU must support AVX2.
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx2")]
+unsafe fn fnv_mix_avx2(mix: &mut [u32; MIX_WORDS]) {
+    use std::arch::x86_64::*;
+
+    // Successors as the sequential loop reads them
+    let mut next = [0u32; MIX_WORDS];
+    next[..MIX_WORDS - 1].copy_from_slice(&mix[1..]);
+    next[MIX_WORDS - 1] = mix[0].wrapping_mul(FNV_PRIME) ^ mix[1];
+
+    let prime = _mm256_set1_epi32(FNV_PRIME as i32);
+    for i in (0..MIX_WORDS).step_by(8) {
+        let words = _mm256_loadu_si256(mix.as_ptr().add(i).cast());
+        let successors = _mm256_loadu_si256(next.as_ptr().add(i).cast());
+        let mixed = _mm256_xor_si256(_mm256_mullo_epi32(words, prime), successors);
+        _mm256_storeu_si256(mix.as_mut_ptr().add(i).cast(), mixed);
+    }
+}
+
+/// Mix hash: every four mix words folded into one with FNV, little-endian
+fn compress_mix(mix: &[u32; MIX_WORDS]) -> [u8; 32] {
+    let mut mix_hash = [0u8; 32];
+    for (out, words) in mix_hash.chunks_exact_mut(4).zip(mix.chunks_exact(4)) {
+        let folded = words[1..]
+            .iter()
+            .fold(words[0], |acc, &word| acc.wrapping_mul(FNV_PRIME) ^ word);
+        out.copy_from_slice(&folded.to_le_bytes());
+    }
+    mix_hash
+}
+
+/// NEON version of [`fnv_mix_scalar`], four words at a time, for Apple
+/// Silicon and 64-bit Raspberry Pi
+///
+/// # Safety
+///
+/// The CPU must support NEON.
+#[cfg(target_arch = "aarch64")]
+#[target_feature(enable = "neon")]
+unsafe fn fnv_mix_neon(mix: &mut [u32; MIX_WORDS]) {
+    use std::arch::aarch64::*;
+
+    // Successors as the sequential loop reads them
+    let mut next = [0u32; MIX_WORDS];
+    next[..MIX_WORDS - 1].copy_from_slice(&mix[1..]);
+    next[MIX_WORDS - 1] = mix[0].wrapping_mul(FNV_PRIME) ^ mix[1];
+
+    let prime = vdupq_n_u32(FNV_PRIME);
+    for i in (0..MIX_WORDS).step_by(4) {
+        let words = vld1q_u32(mix.as_ptr().add(i));
+        let successors = vld1q_u32(next.as_ptr().add(i));
+        vst1q_u32(mix.as_mut_ptr().add(i), veorq_u32(vmulq_u32(words, prime), successors));
     }
 }
 
 /// Compute initial cache from epoch seed
-pub fn compute_cache(epoch: u64, size: usize) -> Vec<u32> {
-    let seed = AequiHash::compute_epoch_seed(epoch);
+pub fn compute_cache(seed: &[u8; 32], size: usize) -> Vec<u32> {
     let num_words = size / 4;
     let mut cache = vec![0u32; num_words];
 
     // Initialize with sequential hashing
     let mut hasher = Keccak256::new();
-    hasher.update(&seed);
+    hasher.update(seed);
 
     for i in 0..num_words {
         if i % 8 == 0 {
@@ -441,10 +526,195 @@ mod tests {
         assert_ne!(seed1, seed3);
     }
 
+    #[test]
+    fn test_epoch_seed_chain() {
+        assert_eq!(AequiHash::seed_anchor_height(0), None);
+        assert_eq!(AequiHash::seed_anchor_height(1), Some(EPOCH_LENGTH - 1));
+        assert_eq!(AequiHash::seed_anchor_height(3), Some(3 * EPOCH_LENGTH - 1));
+
+        assert_eq!(AequiHash::epoch_seed(0, None), AequiHash::compute_epoch_seed(0));
+        let anchored = AequiHash::epoch_seed(1, Some(&[7u8; 32]));
+        assert_ne!(anchored, AequiHash::compute_epoch_seed(1));
+        assert_ne!(anchored, AequiHash::epoch_seed(1, Some(&[8u8; 32])));
+        assert_ne!(anchored, AequiHash::epoch_seed(2, Some(&[7u8; 32])));
+
+        let aequihash = AequiHash::new(1, &anchored);
+        assert_eq!(aequihash.seed(), &anchored);
+        assert_eq!(
+            aequihash.program().iter().map(Round::encode).collect::<Vec<_>>(),
+            AequiHash::generate_program(anchored).iter().map(Round::encode).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn test_program_follows_epoch_seed() {
+        let program = |epoch| {
+            AequiHash::new(epoch, &AequiHash::compute_epoch_seed(epoch)).program().iter().map(Round::encode).collect::<Vec<_>>()
+        };
+        assert_eq!(program(0), program(0));
+        assert_ne!(program(0), program(1));
+        assert_eq!(program(0).len(), MIX_ROUNDS);
+
+        // The rounds really vary, unlike a fixed cycle of operations
+        let rounds = program(0);
+        let ops: std::collections::HashSet<u8> = rounds.iter().map(|round| round[0]).collect();
+        let addresses: std::collections::HashSet<u8> = rounds.iter().map(|round| round[1]).collect();
+        assert!(ops.len() > 4);
+        assert!(addresses.len() > 8);
+        for round in rounds {
+            assert!(round[0] < 8);
+            assert!((round[1] as usize) < MIX_WORDS && (round[2] as usize) < MIX_WORDS);
+        }
+    }
+
+    #[test]
+    fn test_program_fingerprint() {
+        let seed = AequiHash::compute_epoch_seed(0);
+        let fingerprint = AequiHash::new(0, &seed).program_fingerprint();
+        assert_eq!(fingerprint, AequiHash::new(0, &seed).program_fingerprint());
+        assert_ne!(fingerprint, AequiHash::new(0, &AequiHash::epoch_seed(0, Some(&[1u8; 32]))).program_fingerprint());
+
+        // The epoch counts even under the same seed
+        assert_ne!(fingerprint, AequiHash::new(1, &seed).program_fingerprint());
+    }
+
+    #[test]
+    fn test_round_from_word() {
+        let round = Round::from_word(u32::from_le_bytes([13, 40, 7, 0xff]));
+        assert_eq!(round.encode(), [5, 8, 7]);
+    }
+
+    #[test]
+    fn test_light_matches_full() {
+        let items = 256;
+        let seed = AequiHash::compute_epoch_seed(0);
+        let dag = DAG::generate(0, &seed, items * DAG_ITEM_BYTES);
+        let data = dag.data().unwrap();
+        let aequihash = AequiHash::new(0, &seed);
+
+        for nonce in 0..8 {
+            let header = [nonce as u8; 32];
+            let light = aequihash.hash_with(&header, nonce, items, |index| DAG::calc_dag_item(index, dag.cache()));
+            assert_eq!(aequihash.compute_full(&header, nonce, data), light);
+        }
+    }
+
+    #[test]
+    fn test_final_hash_from_mix_hash() {
+        let seed = AequiHash::compute_epoch_seed(0);
+        let dag = DAG::generate(0, &seed, 256 * DAG_ITEM_BYTES);
+        let aequihash = AequiHash::new(0, &seed);
+        let header = [3u8; 32];
+
+        let output = aequihash.compute(&header, 9, &dag);
+        assert_eq!(output.hash, aequihash.hash_dag(&header, 9, &dag));
+        assert_eq!(AequiHash::final_hash(&header, 9, &output.mix_hash), output.hash);
+        assert_ne!(AequiHash::final_hash(&header, 10, &output.mix_hash), output.hash);
+        assert_ne!(output.mix_hash, aequihash.compute(&header, 10, &dag).mix_hash);
+    }
+
+    #[test]
+    fn test_hash_dag_without_dataset() {
+        let seed = AequiHash::compute_epoch_seed(0);
+        let full = DAG::generate(0, &seed, 256 * DAG_ITEM_BYTES);
+        let light = full.to_light();
+        assert!(!light.is_full());
+        assert_eq!(light.items(), 256);
+        let aequihash = AequiHash::new(0, &seed);
+
+        for nonce in 0..4 {
+            let header = [nonce as u8 ^ 0x5a; 32];
+            let expected = aequihash.hash_full(&header, nonce, full.data().unwrap());
+            assert_eq!(aequihash.hash_dag(&header, nonce, &full), expected);
+            assert_eq!(aequihash.hash_dag(&header, nonce, &light), expected);
+
+            // Only the items the rounds read are computed
+            let mut computed = 0;
+            aequihash.hash_with(&header, nonce, light.items(), |index| {
+                computed += 1;
+                light.item(index)
+            });
+            assert_eq!(computed, ITEMS_PER_HASH);
+        }
+    }
+
+    /// Random mixes, plus the words most likely to expose lane or overflow bugs
+    fn fnv_inputs() -> Vec<[u32; MIX_WORDS]> {
+        let mut inputs = vec![[0; MIX_WORDS], [u32::MAX; MIX_WORDS], [0x8000_0000; MIX_WORDS]];
+        inputs.push(std::array::from_fn(|i| i as u32));
+        inputs.push(std::array::from_fn(|i| if i % 2 == 0 { u32::MAX } else { 1 }));
+
+        let mut state = 0x9e3779b9u32;
+        for _ in 0..100 {
+            inputs.push(std::array::from_fn(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state
+            }));
+        }
+        inputs
+    }
+
+    #[test]
+    fn test_fnv_mix_dispatch_matches_scalar() {
+        let fnv_mix = fnv_mix_impl();
+        for mut mix in fnv_inputs() {
+            let mut expected = mix;
+            fnv_mix_scalar(&mut expected);
+            fnv_mix(&mut mix);
+            assert_eq!(mix, expected);
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    #[test]
+    fn test_fnv_mix_avx2_matches_scalar() {
+        if !is_x86_feature_detected!("avx2") {
+            return;
+        }
+
+        for mut mix in fnv_inputs() {
+            let mut expected = mix;
+            fnv_mix_scalar(&mut expected);
+            // SAFETY: AVX2 was detected above
+            unsafe { fnv_mix_avx2(&mut mix) };
+            assert_eq!(mix, expected);
+        }
+    }
+
+    #[cfg(target_arch = "aarch64")]
+    #[test]
+    fn test_fnv_mix_neon_matches_scalar() {
+        assert!(std::arch::is_aarch64_feature_detected!("neon"), "every aarch64 target Rust supports has NEON");
+
+        for mut mix in fnv_inputs() {
+            let mut expected = mix;
+            fnv_mix_scalar(&mut expected);
+            // SAFETY: NEON was detected above
+            unsafe { fnv_mix_neon(&mut mix) };
+            assert_eq!(mix, expected);
+        }
+    }
+
+    /// Whole rounds chained, so a lane slip in one round shows in the next
+    #[test]
+    fn test_fnv_mix_dispatch_chained() {
+        let fnv_mix = fnv_mix_impl();
+        let mut mix: [u32; MIX_WORDS] = std::array::from_fn(|i| (i as u32).wrapping_mul(0x9e3779b9));
+        let mut expected = mix;
+        for _ in 0..MIX_ROUNDS {
+            fnv_mix(&mut mix);
+            fnv_mix_scalar(&mut expected);
+        }
+        assert_eq!(mix, expected);
+    }
+
     #[test]
     fn test_hash_deterministic() {
-        let aequihash = AequiHash::new(0);
-        let cache = compute_cache(0, 1024 * 1024); // 1MB cache for test
+        let seed = AequiHash::compute_epoch_seed(0);
+        let aequihash = AequiHash::new(0, &seed);
+        let cache = compute_cache(&seed, 1024 * 1024); // 1MB cache for test
         let header = [0u8; 32];
 
         let hash1 = aequihash.hash_light(&header, 0, &cache);
@@ -457,11 +727,12 @@ mod tests {
 
     #[test]
     fn test_different_epochs() {
-        let cache0 = compute_cache(0, 1024 * 1024);
-        let cache1 = compute_cache(1, 1024 * 1024);
+        let (seed0, seed1) = (AequiHash::compute_epoch_seed(0), AequiHash::compute_epoch_seed(1));
+        let cache0 = compute_cache(&seed0, 1024 * 1024);
+        let cache1 = compute_cache(&seed1, 1024 * 1024);
 
-        let aeq0 = AequiHash::new(0);
-        let aeq1 = AequiHash::new(1);
+        let aeq0 = AequiHash::new(0, &seed0);
+        let aeq1 = AequiHash::new(1, &seed1);
 
         let header = [42u8; 32];
         let hash0 = aeq0.hash_light(&header, 0, &cache0);
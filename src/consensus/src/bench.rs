@@ -0,0 +1,278 @@
+//! Reference vectors and benchmarks for AequiHash
+//!
+//! [`TEST_VECTORS`] are the canonical `(epoch, anchor, header hash, nonce)
+//! -> (mix hash, hash)` results of the light hash, with the standard DAG schedule. Alternative
+//! implementations (pool software, GPU or FPGA miners) can check
+//! themselves against them with [`check_vectors`]. [`run`] measures the
+//! hashrate of this implementation, warming up first and then timing
+//! several samples, as criterion does.
+
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use crate::aequihash::{compute_cache, AequiHash, HashOutput, CACHE_SIZE};
+
+/// One reference result of AequiHash
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TestVector {
+    /// Epoch hashed for
+    pub epoch: u64,
+
+    /// Seed anchor block hash, `None` for the fallback seed
+    pub anchor: Option<[u8; 32]>,
+
+    /// Header hash, the seal hash of a block
+    pub header_hash: [u8; 32],
+
+    /// Nonce
+    pub nonce: u64,
+
+    /// Expected mix hash
+    pub mix_hash: [u8; 32],
+
+    /// Expected hash
+    pub hash: [u8; 32],
+}
+
+/// Canonical vectors; any change to them is a consensus change
+pub const TEST_VECTORS: &[TestVector] = &[
+    TestVector {
+        epoch: 0,
+        anchor: None,
+        header_hash: [0u8; 32],
+        nonce: 0,
+        mix_hash: hex32("38861bfa786fdaa0dceac109502764870340a1322b13240ce1d35e4790b22804"),
+        hash: hex32("634d593ff871e6070a6a6066518c0a9fcb9fa444a79e2c7842a988d8d1528cee"),
+    },
+    TestVector {
+        epoch: 0,
+        anchor: None,
+        header_hash: hex32("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"),
+        nonce: 0x0123_4567_89ab_cdef,
+        mix_hash: hex32("4d10f06255e8ffc8bed688ae528fa1b5d295af21cb4bf51da01402a6e52738d4"),
+        hash: hex32("457ec2da72bea1e6f517fe2119b538f701b3c7f3abf8c24839e4d708a4cfca7a"),
+    },
+    TestVector {
+        epoch: 1,
+        anchor: None,
+        header_hash: [0xff; 32],
+        nonce: 42,
+        mix_hash: hex32("743084c4041fec074c93f2184c8c2e7b903efa1735aaf18dbd0acf35c035ea8e"),
+        hash: hex32("8ad4365406fc1c272b1528c6e53041d8019151a3d001526dd08145145b64f4ee"),
+    },
+    TestVector {
+        epoch: 2,
+        anchor: Some([0xab; 32]),
+        header_hash: [0x11; 32],
+        nonce: 7,
+        mix_hash: hex32("f2e0ffe519c4c35e9c94dc6a1165dde5e99572f755ac053d47f81573191ddd90"),
+        hash: hex32("eabace3f8002abb0a10f7b4b12a823736ba3d210789a5eb5d3bdf83ae7194a23"),
+    },
+];
+
+/// 32 bytes from 64 hex digits, at compile time
+const fn hex32(hex: &str) -> [u8; 32] {
+    const fn digit(c: u8) -> u8 {
+        match c {
+            b'0'..=b'9' => c - b'0',
+            b'a'..=b'f' => c - b'a' + 10,
+            _ => panic!("invalid hex digit"),
+        }
+    }
+
+    let hex = hex.as_bytes();
+    assert!(hex.len() == 64);
+    let mut out = [0u8; 32];
+    let mut i = 0;
+    while i < 32 {
+        out[i] = digit(hex[2 * i]) << 4 | digit(hex[2 * i + 1]);
+        i += 1;
+    }
+    out
+}
+
+/// A vector an implementation got wrong
+#[derive(Debug, Error, PartialEq, Eq)]
+#[error("vector {index} (epoch {epoch}, nonce {nonce}): expected {output} {expected}, got {found}")]
+pub struct VectorMismatch {
+    /// Position in [`TEST_VECTORS`]
+    pub index: usize,
+    pub epoch: u64,
+    pub nonce: u64,
+    /// Output that differs, `"mix hash"` or `"hash"`
+    pub output: &'static str,
+    /// Expected value, hex
+    pub expected: String,
+    /// Computed value, hex
+    pub found: String,
+}
+
+/// Check an implementation against every vector
+///
+/// `hash` computes AequiHash for a vector's epoch, seed anchor, header hash
+/// and nonce. The mix hash is checked first, so a wrong final step is told
+/// apart from a wrong mix.
+pub fn check_vectors(mut hash: impl FnMut(&TestVector) -> HashOutput) -> Result<(), VectorMismatch> {
+    for (index, vector) in TEST_VECTORS.iter().enumerate() {
+        let found = hash(vector);
+        let checks = [("mix hash", vector.mix_hash, found.mix_hash), ("hash", vector.hash, found.hash)];
+        if let Some((output, expected, found)) = checks.into_iter().find(|(_, expected, found)| expected != found) {
+            return Err(VectorMismatch {
+                index,
+                epoch: vector.epoch,
+                nonce: vector.nonce,
+                output,
+                expected: hex::encode(expected),
+                found: hex::encode(found),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Check this crate's light hash against every vector
+pub fn check_reference() -> Result<(), VectorMismatch> {
+    let mut cache: Option<([u8; 32], Vec<u32>)> = None;
+    check_vectors(|vector| {
+        let seed = AequiHash::epoch_seed(vector.epoch, vector.anchor.as_ref());
+        if !matches!(&cache, Some((cached, _)) if *cached == seed) {
+            cache = Some((seed, compute_cache(&seed, CACHE_SIZE)));
+        }
+        let (_, cache) = cache.as_ref().expect("cache computed above");
+        AequiHash::new(vector.epoch, &seed).compute_light(&vector.header_hash, vector.nonce, cache)
+    })
+}
+
+/// Benchmark settings
+#[derive(Clone, Debug)]
+pub struct BenchConfig {
+    /// Epoch whose fallback cache and program are hashed with
+    pub epoch: u64,
+
+    /// Time spent hashing before measuring
+    pub warm_up: Duration,
+
+    /// Number of timed samples
+    pub samples: usize,
+
+    /// Hashes per sample
+    pub hashes_per_sample: u64,
+}
+
+impl Default for BenchConfig {
+    fn default() -> Self {
+        Self {
+            epoch: 0,
+            warm_up: Duration::from_secs(1),
+            samples: 10,
+            hashes_per_sample: 100,
+        }
+    }
+}
+
+/// Benchmark results, per light hash
+#[derive(Clone, Debug)]
+pub struct BenchReport {
+    /// Hashes timed, warm-up excluded
+    pub hashes: u64,
+
+    /// Fastest sample, per hash
+    pub min: Duration,
+
+    /// Mean over the samples, per hash
+    pub mean: Duration,
+
+    /// Slowest sample, per hash
+    pub max: Duration,
+}
+
+impl BenchReport {
+    /// Hashes per second at the mean time
+    pub fn hashrate(&self) -> f64 {
+        1.0 / self.mean.as_secs_f64().max(f64::MIN_POSITIVE)
+    }
+}
+
+impl std::fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "aequihash/light  time: [{:?} {:?} {:?}]  thrpt: {:.1} H/s ({} hashes)",
+            self.min,
+            self.mean,
+            self.max,
+            self.hashrate(),
+            self.hashes
+        )
+    }
+}
+
+/// Benchmark the light hash on one thread
+pub fn run(config: &BenchConfig) -> BenchReport {
+    let seed = AequiHash::compute_epoch_seed(config.epoch);
+    let cache = compute_cache(&seed, CACHE_SIZE);
+    let aequihash = AequiHash::new(config.epoch, &seed);
+    let header = [0u8; 32];
+    let mut nonce = 0u64;
+    let mut hash = || {
+        nonce += 1;
+        std::hint::black_box(aequihash.hash_light(&header, nonce, &cache));
+    };
+
+    let warm_up = Instant::now();
+    while warm_up.elapsed() < config.warm_up {
+        hash();
+    }
+
+    let per_sample = config.hashes_per_sample.max(1);
+    let mut times = Vec::with_capacity(config.samples.max(1));
+    for _ in 0..config.samples.max(1) {
+        let start = Instant::now();
+        for _ in 0..per_sample {
+            hash();
+        }
+        times.push(start.elapsed() / per_sample as u32);
+    }
+
+    BenchReport {
+        hashes: per_sample * times.len() as u64,
+        min: *times.iter().min().expect("at least one sample"),
+        mean: times.iter().sum::<Duration>() / times.len() as u32,
+        max: *times.iter().max().expect("at least one sample"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reference_vectors() {
+        check_reference().unwrap();
+    }
+
+    #[test]
+    fn test_check_vectors_reports_mismatch() {
+        let err = check_vectors(|_| HashOutput { mix_hash: [0u8; 32], hash: [0u8; 32] }).unwrap_err();
+        assert_eq!(err.index, 0);
+        assert_eq!(err.output, "mix hash");
+        assert_eq!(err.expected, hex::encode(TEST_VECTORS[0].mix_hash));
+
+        let err = check_vectors(|vector| HashOutput { mix_hash: vector.mix_hash, hash: [0u8; 32] }).unwrap_err();
+        assert_eq!(err.output, "hash");
+        assert_eq!(err.expected, hex::encode(TEST_VECTORS[0].hash));
+    }
+
+    #[test]
+    fn test_run() {
+        let config = BenchConfig {
+            warm_up: Duration::ZERO,
+            samples: 2,
+            hashes_per_sample: 2,
+            ..Default::default()
+        };
+        let report = run(&config);
+        assert_eq!(report.hashes, 4);
+        assert!(report.min <= report.mean && report.mean <= report.max);
+        assert!(report.hashrate() > 0.0);
+    }
+}
@@ -1,13 +1,28 @@
 //! DAG (Directed Acyclic Graph) generation for AequiHash
 //!
-//! The DAG is a large dataset (~4GB) stored in GPU memory that makes
-//! mining memory-hard and ASIC-resistant.
+//! The DAG is a large dataset (4GB, growing each epoch) stored in GPU
+//! memory that makes mining memory-hard and ASIC-resistant.
+//!
+//! Generating it takes minutes, so a full DAG can be saved to a file and
+//! memory-mapped back. A file is a 96-byte header followed by the DAG words
+//! in little-endian order:
+//!
+//! ```text
+//! magic "AEQUIDAG" | version u32 | reserved u32 | epoch u64 |
+//! size in words u64 | BLAKE3 of the words [u8; 32] | epoch seed [u8; 32]
+//! ```
 
 use sha3::{Digest, Keccak256};
 use blake3;
 use byteorder::{ByteOrder, LittleEndian};
-use crate::aequihash::{EPOCH_LENGTH, DAG_SIZE, CACHE_SIZE, MIX_WORDS, compute_cache};
+use crate::aequihash::{AequiHash, CACHE_SIZE, MIX_WORDS, compute_cache};
+use aequitas_core::DagSchedule;
+use memmap2::Mmap;
+use std::fs::File;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use thiserror::Error;
 
 /// DAG item size in 32-bit words
 pub const DAG_ITEM_WORDS: usize = 16;
@@ -18,13 +33,85 @@ pub const DAG_ITEM_BYTES: usize = DAG_ITEM_WORDS * 4;
 /// Number of parent lookups for DAG generation
 pub const DAG_PARENTS: usize = 256;
 
+/// Magic bytes opening a DAG file
+const DAG_FILE_MAGIC: &[u8; 8] = b"AEQUIDAG";
+
+/// DAG file format version
+const DAG_FILE_VERSION: u32 = 2;
+
+/// Size of a DAG file header; keeps the words 4-byte aligned when mapped
+const DAG_FILE_HEADER: usize = 96;
+
+/// Words written per chunk when saving
+const SAVE_CHUNK_WORDS: usize = 1 << 18;
+
+/// DAG files kept besides the current epoch's, so a reorg across an epoch
+/// boundary does not regenerate
+const DAG_FILES_KEPT: u64 = 1;
+
+/// Items [`DAGManager`] checks in every DAG it loads or generates
+pub const DAG_SAMPLE_ITEMS: usize = 256;
+
+/// Errors when saving, loading or checking a DAG
+#[derive(Error, Debug)]
+pub enum DagFileError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Only a full DAG can be saved")]
+    NotFull,
+
+    #[error("Not a DAG file")]
+    BadMagic,
+
+    #[error("Unsupported DAG file version {0}")]
+    UnsupportedVersion(u32),
+
+    #[error("DAG file truncated: {found} bytes, expected {expected}")]
+    Truncated { expected: u64, found: u64 },
+
+    #[error("DAG file checksum mismatch")]
+    ChecksumMismatch,
+
+    #[error("DAG item {0} does not match the cache")]
+    CorruptItem(usize),
+}
+
+/// Words of a full DAG, generated in memory or mapped from a file
+enum DagData {
+    Owned(Vec<u32>),
+
+    /// A verified DAG file; the words start after the header
+    #[cfg(target_endian = "little")]
+    Mapped(Mmap),
+}
+
+impl DagData {
+    fn words(&self) -> &[u32] {
+        match self {
+            DagData::Owned(words) => words,
+            #[cfg(target_endian = "little")]
+            DagData::Mapped(map) => {
+                let bytes = &map[DAG_FILE_HEADER..];
+                // SAFETY: mappings are page aligned and the header keeps
+                // the words 4-byte aligned; any bit pattern is a valid u32
+                unsafe { std::slice::from_raw_parts(bytes.as_ptr() as *const u32, bytes.len() / 4) }
+            }
+        }
+    }
+}
+
 /// DAG structure for an epoch
+#[derive(Clone)]
 pub struct DAG {
     /// Epoch number
     epoch: u64,
     
+    /// Seed the cache and items derive from
+    seed: [u8; 32],
+    
     /// The full DAG dataset (for GPU mining)
-    data: Option<Arc<Vec<u32>>>,
+    data: Option<Arc<DagData>>,
     
     /// Light cache (for verification)
     cache: Arc<Vec<u32>>,
@@ -34,26 +121,43 @@ pub struct DAG {
 }
 
 impl DAG {
-    /// Create a light DAG (cache only, for verification)
-    pub fn new_light(epoch: u64) -> Self {
-        let cache = Arc::new(compute_cache(epoch, CACHE_SIZE));
+    /// Create a light DAG (cache only, for verification) of `epoch`, from
+    /// its [`AequiHash::epoch_seed`]
+    pub fn new_light(epoch: u64, seed: &[u8; 32]) -> Self {
+        let cache = Arc::new(compute_cache(seed, CACHE_SIZE));
         
         Self {
             epoch,
+            seed: *seed,
             data: None,
             cache,
-            size_words: DAG_SIZE / 4,
+            size_words: Self::size_for_epoch(epoch) / 4,
         }
     }
     
     /// Create a full DAG (for mining)
-    /// Warning: This allocates ~4GB of memory!
-    pub fn new_full(epoch: u64) -> Self {
+    /// Warning: This allocates [`DAG::size_for_epoch`] bytes, 4GB and up!
+    pub fn new_full(epoch: u64, seed: &[u8; 32]) -> Self {
+        Self::generate(epoch, seed, Self::size_for_epoch(epoch))
+    }
+    
+    /// DAG size of `epoch` in bytes
+    ///
+    /// Follows `ChainParams::dag_schedule`, [`DagSchedule::STANDARD`] on
+    /// every network: 4GB at epoch 0, growing 128KB an epoch up to 12GB.
+    /// Hashing, DAG generation and the GPU kernels all size the dataset
+    /// from it.
+    pub fn size_for_epoch(epoch: u64) -> usize {
+        DagSchedule::STANDARD.size_for_epoch(epoch) as usize
+    }
+    
+    /// Generate a full DAG of `size` bytes
+    pub(crate) fn generate(epoch: u64, seed: &[u8; 32], size: usize) -> Self {
         log::info!("Generating DAG for epoch {}... This may take a few minutes.", epoch);
         
-        let cache = Arc::new(compute_cache(epoch, CACHE_SIZE));
-        let num_items = DAG_SIZE / DAG_ITEM_BYTES;
-        let mut data = vec![0u32; DAG_SIZE / 4];
+        let cache = Arc::new(compute_cache(seed, CACHE_SIZE));
+        let num_items = size / DAG_ITEM_BYTES;
+        let mut data = vec![0u32; size / 4];
         
         // Generate DAG items
         for item_idx in 0..num_items {
@@ -70,14 +174,130 @@ impl DAG {
         
         Self {
             epoch,
-            data: Some(Arc::new(data)),
+            seed: *seed,
+            data: Some(Arc::new(DagData::Owned(data))),
             cache,
-            size_words: DAG_SIZE / 4,
+            size_words: size / 4,
+        }
+    }
+    
+    /// Save a full DAG to `path`
+    ///
+    /// The file is written next to `path` and renamed into place, so a DAG
+    /// mapped from an earlier file stays intact.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), DagFileError> {
+        let data = self.data().ok_or(DagFileError::NotFull)?;
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        
+        // The checksum is only known at the end; the header goes in last
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        writer.write_all(&[0u8; DAG_FILE_HEADER])?;
+        let mut hasher = blake3::Hasher::new();
+        let mut chunk = Vec::with_capacity(SAVE_CHUNK_WORDS * 4);
+        for words in data.chunks(SAVE_CHUNK_WORDS) {
+            chunk.clear();
+            for word in words {
+                chunk.extend_from_slice(&word.to_le_bytes());
+            }
+            hasher.update(&chunk);
+            writer.write_all(&chunk)?;
+        }
+        
+        let mut file = writer.into_inner().map_err(|e| e.into_error())?;
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&Self::file_header(self.epoch, &self.seed, data.len() as u64, hasher.finalize().as_bytes()))?;
+        file.sync_all()?;
+        drop(file);
+        
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+    
+    /// Load a full DAG saved by [`DAG::save`], mapping it into memory
+    ///
+    /// The checksum is verified over the whole file before it is used, and
+    /// the light cache is recomputed from the seed. The file must not be
+    /// modified while the DAG is alive.
+    pub fn load_mmap<P: AsRef<Path>>(path: P) -> Result<Self, DagFileError> {
+        let file = File::open(path)?;
+        // SAFETY: DAG files are only ever replaced by renaming, never
+        // written in place
+        let map = unsafe { Mmap::map(&file)? };
+        
+        if map.len() < DAG_FILE_HEADER || &map[..8] != DAG_FILE_MAGIC {
+            return Err(DagFileError::BadMagic);
+        }
+        let version = LittleEndian::read_u32(&map[8..12]);
+        if version != DAG_FILE_VERSION {
+            return Err(DagFileError::UnsupportedVersion(version));
+        }
+        let epoch = LittleEndian::read_u64(&map[16..24]);
+        let size_words = LittleEndian::read_u64(&map[24..32]);
+        
+        let expected = size_words.saturating_mul(4).saturating_add(DAG_FILE_HEADER as u64);
+        if map.len() as u64 != expected {
+            return Err(DagFileError::Truncated { expected, found: map.len() as u64 });
+        }
+        if blake3::hash(&map[DAG_FILE_HEADER..]).as_bytes()[..] != map[32..64] {
+            return Err(DagFileError::ChecksumMismatch);
+        }
+        let seed: [u8; 32] = map[64..96].try_into().expect("32-byte slice");
+        
+        #[cfg(target_endian = "little")]
+        let data = DagData::Mapped(map);
+        #[cfg(not(target_endian = "little"))]
+        let data = DagData::Owned(map[DAG_FILE_HEADER..].chunks_exact(4).map(LittleEndian::read_u32).collect());
+        
+        Ok(Self {
+            epoch,
+            seed,
+            data: Some(Arc::new(data)),
+            cache: Arc::new(compute_cache(&seed, CACHE_SIZE)),
+            size_words: size_words as usize,
+        })
+    }
+    
+    /// Recompute `n` random items from the cache and compare them with the
+    /// full dataset, every item when `n` covers them all
+    ///
+    /// Catches DAGs damaged in memory or on disk, which would otherwise
+    /// only show up as shares the network rejects.
+    pub fn verify_sample(&self, n: usize) -> Result<(), DagFileError> {
+        use rand::Rng;
+        
+        let data = self.data().ok_or(DagFileError::NotFull)?;
+        let items = data.len() / DAG_ITEM_WORDS;
+        let check = |item: usize| {
+            let offset = item * DAG_ITEM_WORDS;
+            if data[offset..offset + DAG_ITEM_WORDS] == Self::calc_dag_item(item, &self.cache) {
+                Ok(())
+            } else {
+                Err(DagFileError::CorruptItem(item))
+            }
+        };
+        
+        if n >= items {
+            return (0..items).try_for_each(check);
         }
+        let mut rng = rand::thread_rng();
+        (0..n).try_for_each(|_| check(rng.gen_range(0..items)))
+    }
+    
+    /// Header of a DAG file
+    fn file_header(epoch: u64, seed: &[u8; 32], size_words: u64, checksum: &[u8; 32]) -> [u8; DAG_FILE_HEADER] {
+        let mut header = [0u8; DAG_FILE_HEADER];
+        header[..8].copy_from_slice(DAG_FILE_MAGIC);
+        LittleEndian::write_u32(&mut header[8..12], DAG_FILE_VERSION);
+        LittleEndian::write_u64(&mut header[16..24], epoch);
+        LittleEndian::write_u64(&mut header[24..32], size_words);
+        header[32..64].copy_from_slice(checksum);
+        header[64..].copy_from_slice(seed);
+        header
     }
     
     /// Calculate a single DAG item
-    fn calc_dag_item(item_idx: usize, cache: &[u32]) -> [u32; DAG_ITEM_WORDS] {
+    pub(crate) fn calc_dag_item(item_idx: usize, cache: &[u32]) -> [u32; DAG_ITEM_WORDS] {
         let cache_words = cache.len();
         let mut mix = [0u32; DAG_ITEM_WORDS];
         
@@ -142,6 +362,11 @@ impl DAG {
         self.epoch
     }
     
+    /// Seed of the epoch
+    pub fn seed(&self) -> &[u8; 32] {
+        &self.seed
+    }
+    
     /// Get cache reference
     pub fn cache(&self) -> &[u32] {
         &self.cache
@@ -149,7 +374,7 @@ impl DAG {
     
     /// Get DAG data (if full DAG is loaded)
     pub fn data(&self) -> Option<&[u32]> {
-        self.data.as_ref().map(|d| d.as_slice())
+        self.data.as_ref().map(|d| d.words())
     }
     
     /// Check if this is a full DAG
@@ -160,7 +385,7 @@ impl DAG {
     /// Get approximate memory usage in bytes
     pub fn memory_usage(&self) -> usize {
         let cache_size = self.cache.len() * 4;
-        let dag_size = self.data.as_ref().map(|d| d.len() * 4).unwrap_or(0);
+        let dag_size = self.data().map(|d| d.len() * 4).unwrap_or(0);
         cache_size + dag_size
     }
     
@@ -168,6 +393,31 @@ impl DAG {
     pub fn calc_item(&self, idx: usize) -> [u32; DAG_ITEM_WORDS] {
         Self::calc_dag_item(idx, &self.cache)
     }
+    
+    /// Item `idx`, read from the full dataset when it is loaded and
+    /// computed from the cache otherwise
+    pub fn item(&self, idx: usize) -> [u32; DAG_ITEM_WORDS] {
+        match self.data() {
+            Some(data) => {
+                let offset = idx * DAG_ITEM_WORDS;
+                data[offset..offset + DAG_ITEM_WORDS].try_into().unwrap()
+            }
+            None => self.calc_item(idx),
+        }
+    }
+    
+    /// Number of items in the dataset, loaded or not
+    pub fn items(&self) -> usize {
+        self.size_words / DAG_ITEM_WORDS
+    }
+    
+    /// The same DAG without its full dataset, sharing the cache
+    pub fn to_light(&self) -> DAG {
+        Self {
+            data: None,
+            ..self.clone()
+        }
+    }
 }
 
 /// DAG manager for caching DAGs across epochs
@@ -177,27 +427,126 @@ pub struct DAGManager {
     
     /// Whether to generate full DAGs (for mining) or light (for verification)
     full_dag: bool,
+    
+    /// Where full DAGs are saved, one file per epoch
+    dag_dir: Option<PathBuf>,
 }
 
 impl DAGManager {
     /// Create a new DAG manager
+    ///
+    /// Without `full_dag` it holds caches only, enough to verify any hash
+    /// through [`AequiHash::hash_dag`].
     pub fn new(full_dag: bool) -> Self {
         Self {
             current: None,
             full_dag,
+            dag_dir: None,
+        }
+    }
+    
+    /// Create a DAG manager keeping full DAGs in `data_dir/dag`, so each
+    /// epoch's DAG is generated once and then mapped from its file
+    pub fn with_data_dir<P: AsRef<Path>>(full_dag: bool, data_dir: P) -> Self {
+        Self {
+            current: None,
+            full_dag,
+            dag_dir: Some(data_dir.as_ref().join("dag")),
+        }
+    }
+    
+    /// File holding the DAG of `epoch`
+    fn dag_path(dir: &Path, epoch: u64) -> PathBuf {
+        dir.join(format!("epoch-{}.dag", epoch))
+    }
+    
+    /// Generate the full DAG of `epoch`, with whether its sample checked out
+    fn generate_checked(epoch: u64, seed: &[u8; 32]) -> (DAG, bool) {
+        let dag = DAG::new_full(epoch, seed);
+        match dag.verify_sample(DAG_SAMPLE_ITEMS) {
+            Ok(()) => (dag, true),
+            Err(e) => {
+                // Only faulty memory corrupts a freshly computed DAG
+                log::error!("DAG for epoch {} is corrupt, check the RAM: {}", epoch, e);
+                (dag, false)
+            }
         }
     }
     
-    /// Get or create DAG for epoch
-    pub fn get_dag(&mut self, epoch: u64) -> &DAG {
+    /// Load the full DAG of `epoch` from its file, or generate and save it
+    fn load_or_generate(&self, epoch: u64, seed: &[u8; 32]) -> DAG {
+        let Some(dir) = &self.dag_dir else {
+            return Self::generate_checked(epoch, seed).0;
+        };
+        
+        let path = Self::dag_path(dir, epoch);
+        if path.exists() {
+            match DAG::load_mmap(&path) {
+                Ok(dag) if dag.epoch() == epoch
+                    && dag.seed() == seed
+                    && dag.size_words == DAG::size_for_epoch(epoch) / 4 =>
+                {
+                    // The checksum already matched, so a bad item means the
+                    // file was written from a corrupted DAG
+                    match dag.verify_sample(DAG_SAMPLE_ITEMS) {
+                        Ok(()) => {
+                            log::info!("Loaded DAG for epoch {} from {}", epoch, path.display());
+                            return dag;
+                        }
+                        Err(e) => log::warn!("Discarding DAG file {}: {}", path.display(), e),
+                    }
+                }
+                Ok(_) => log::warn!("{} holds another DAG, regenerating", path.display()),
+                Err(e) => log::warn!("Discarding DAG file {}: {}", path.display(), e),
+            }
+        }
+        
+        // Keep a corrupt DAG off the disk; its shares fail the CPU check
+        let (dag, sound) = Self::generate_checked(epoch, seed);
+        if !sound {
+            return dag;
+        }
+        let saved = std::fs::create_dir_all(dir)
+            .map_err(DagFileError::from)
+            .and_then(|_| dag.save(&path));
+        match saved {
+            Ok(()) => Self::prune(dir, epoch),
+            Err(e) => log::warn!("Could not save DAG for epoch {}: {}", epoch, e),
+        }
+        dag
+    }
+    
+    /// Delete DAG files of epochs older than the kept ones
+    fn prune(dir: &Path, epoch: u64) {
+        let Ok(entries) = std::fs::read_dir(dir) else { return };
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let old = name.to_str()
+                .and_then(|name| name.strip_prefix("epoch-")?.strip_suffix(".dag")?.parse::<u64>().ok())
+                .is_some_and(|file_epoch| file_epoch + DAG_FILES_KEPT < epoch);
+            if old {
+                if let Err(e) = std::fs::remove_file(entry.path()) {
+                    log::warn!("Could not delete {}: {}", entry.path().display(), e);
+                }
+            }
+        }
+    }
+    
+    /// Get or create DAG for epoch, from its seed
+    ///
+    /// A new seed for the same epoch, as after a reorg across the epoch
+    /// boundary, replaces the DAG.
+    pub fn get_dag(&mut self, epoch: u64, seed: &[u8; 32]) -> &DAG {
         // Check if we need a new DAG
-        let need_new = self.current.as_ref().map(|d| d.epoch() != epoch).unwrap_or(true);
+        let need_new = self.current.as_ref()
+            .map(|d| d.epoch() != epoch || d.seed() != seed)
+            .unwrap_or(true);
         
         if need_new {
             let dag = if self.full_dag {
-                DAG::new_full(epoch)
+                self.load_or_generate(epoch, seed)
             } else {
-                DAG::new_light(epoch)
+                DAG::new_light(epoch, seed)
             };
             self.current = Some(dag);
         }
@@ -205,10 +554,11 @@ impl DAGManager {
         self.current.as_ref().unwrap()
     }
     
-    /// Get DAG for a block height
-    pub fn get_dag_for_height(&mut self, height: u64) -> &DAG {
-        let epoch = height / EPOCH_LENGTH;
-        self.get_dag(epoch)
+    /// Get DAG for a block height, given the hash of its epoch's seed
+    /// anchor block (see [`AequiHash::seed_anchor_height`])
+    pub fn get_dag_for_height(&mut self, height: u64, anchor: Option<&[u8; 32]>) -> &DAG {
+        let epoch = AequiHash::epoch_from_height(height);
+        self.get_dag(epoch, &AequiHash::epoch_seed(epoch, anchor))
     }
 }
 
@@ -216,9 +566,13 @@ impl DAGManager {
 mod tests {
     use super::*;
     
+    fn seed(epoch: u64) -> [u8; 32] {
+        AequiHash::compute_epoch_seed(epoch)
+    }
+    
     #[test]
     fn test_light_dag_creation() {
-        let dag = DAG::new_light(0);
+        let dag = DAG::new_light(0, &seed(0));
         assert_eq!(dag.epoch(), 0);
         assert!(!dag.is_full());
         assert!(dag.cache().len() > 0);
@@ -226,7 +580,7 @@ mod tests {
     
     #[test]
     fn test_dag_item_deterministic() {
-        let dag = DAG::new_light(0);
+        let dag = DAG::new_light(0, &seed(0));
         let item1 = dag.calc_item(0);
         let item2 = dag.calc_item(0);
         assert_eq!(item1, item2);
@@ -235,15 +589,61 @@ mod tests {
         assert_ne!(item1, item3);
     }
     
+    #[test]
+    fn test_dag_file_roundtrip() {
+        let path = std::env::temp_dir().join(format!("aequitas-dag-{}.dag", rand::random::<u64>()));
+        let anchored = AequiHash::epoch_seed(3, Some(&[9u8; 32]));
+        let dag = DAG::generate(3, &anchored, 64 * DAG_ITEM_BYTES);
+        dag.save(&path).unwrap();
+        
+        let loaded = DAG::load_mmap(&path).unwrap();
+        assert_eq!(loaded.epoch(), 3);
+        assert_eq!(loaded.seed(), &anchored);
+        assert_eq!(loaded.data(), dag.data());
+        assert_eq!(loaded.cache(), dag.cache());
+        drop(loaded);
+        
+        // A flipped word fails the checksum
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[DAG_FILE_HEADER + 100] ^= 1;
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(matches!(DAG::load_mmap(&path), Err(DagFileError::ChecksumMismatch)));
+        
+        bytes.truncate(bytes.len() - 4);
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(matches!(DAG::load_mmap(&path), Err(DagFileError::Truncated { .. })));
+        
+        assert!(matches!(DAG::new_light(0, &seed(0)).save(&path), Err(DagFileError::NotFull)));
+        std::fs::remove_file(&path).unwrap();
+    }
+    
+    #[test]
+    fn test_verify_sample() {
+        let mut dag = DAG::generate(1, &seed(1), 64 * DAG_ITEM_BYTES);
+        dag.verify_sample(8).unwrap();
+        dag.verify_sample(usize::MAX).unwrap();
+        
+        if let Some(DagData::Owned(words)) = dag.data.as_mut().and_then(Arc::get_mut) {
+            words[20 * DAG_ITEM_WORDS + 3] ^= 1 << 7;
+        }
+        assert!(matches!(dag.verify_sample(usize::MAX), Err(DagFileError::CorruptItem(20))));
+        assert!(matches!(DAG::new_light(1, &seed(1)).verify_sample(1), Err(DagFileError::NotFull)));
+    }
+    
     #[test]
     fn test_dag_manager() {
         let mut manager = DAGManager::new(false);
         
-        let dag0 = manager.get_dag(0);
+        let dag0 = manager.get_dag(0, &seed(0));
         assert_eq!(dag0.epoch(), 0);
         
         // Same epoch should return cached DAG
-        let dag0_again = manager.get_dag(0);
+        let dag0_again = manager.get_dag(0, &seed(0));
         assert_eq!(dag0_again.epoch(), 0);
+        
+        // Another seed for the same epoch replaces it
+        let other = manager.get_dag(0, &[1u8; 32]);
+        assert_eq!(other.seed(), &[1u8; 32]);
+        assert_ne!(other.cache(), DAG::new_light(0, &seed(0)).cache());
     }
 }
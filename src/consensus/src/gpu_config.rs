@@ -1,13 +1,72 @@
 //! GPU Configuration and Detection for AequiHash
 //!
-//! Auto-detects and optimizes for ANY graphics card
+//! Auto-detects and optimizes for ANY graphics card, enumerating devices
+//! through OpenCL (the `opencl` feature) for their real memory, compute
+//! units and driver version:
 //! - RTX series (20xx, 30xx, 40xx)
 //! - AMD RDNA series (RX 6000-7000)
 //! - Intel Arc series
 //! - Integrated Intel/AMD graphics
 //! - Legacy GPU support
+//!
+//! A [`ThermalPolicy`] keeps a card under a temperature and a power draw:
+//! the GPU backends report [`ThermalReading`]s and a [`ThermalThrottle`]
+//! turns them into the share of its intensity the card mines at.
+
+use std::time::Duration;
+
+/// Mining backend for a GPU
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GpuBackend {
+    /// Any OpenCL 1.2 device
+    OpenCl,
+
+    /// NVIDIA devices, through CUDA
+    Cuda,
+}
+
+impl GpuBackend {
+    /// Backend suited to the GPU called `gpu_name`
+    pub fn for_gpu(gpu_name: &str) -> Self {
+        let name = gpu_name.to_lowercase();
+        let nvidia = ["nvidia", "geforce", "rtx", "gtx", "quadro", "tesla"];
+        if nvidia.iter().any(|marker| name.contains(marker)) {
+            GpuBackend::Cuda
+        } else {
+            GpuBackend::OpenCl
+        }
+    }
+
+    /// Display name
+    pub fn as_str(self) -> &'static str {
+        match self {
+            GpuBackend::OpenCl => "OpenCL",
+            GpuBackend::Cuda => "CUDA",
+        }
+    }
+}
+
+/// A GPU found on this machine, as its driver reports it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GpuDevice {
+    /// Device name
+    pub name: String,
+
+    /// Device vendor
+    pub vendor: String,
+
+    /// Global memory (bytes)
+    pub memory_bytes: u64,
+
+    /// Compute units (SMs on NVIDIA, CUs on AMD, Xe cores on Intel)
+    pub compute_units: u32,
 
-use std::arch::x86_64;
+    /// Driver version
+    pub driver_version: String,
+
+    /// Backend suited to the device
+    pub backend: GpuBackend,
+}
 
 /// GPU capabilities and optimal settings
 #[derive(Debug, Clone)]
@@ -33,228 +92,124 @@ pub struct GpuConfig {
     /// Compute unit count
     pub compute_units: u32,
 
-    /// Clock speed optimization
+    /// Estimated memory bandwidth; drivers do not report it
     pub memory_bandwidth_mbps: u32,
+
+    /// Driver version, "unknown" without a detected GPU
+    pub driver_version: String,
+
+    /// Mining backend to use
+    pub backend: GpuBackend,
 }
 
 impl GpuConfig {
     /// Auto-detect GPU capabilities and optimal settings
+    ///
+    /// Configures for the GPU with the most memory, or for the CPU when no
+    /// GPU is found.
     pub fn detect() -> Self {
-        #[cfg(target_arch = "x86_64")]
-        {
-            Self::detect_x86_gpu()
-        }
-
-        #[cfg(not(target_arch = "x86_64"))]
-        {
-            Self::detect_generic_gpu()
-        }
-    }
-
-    /// Detect GPU on x86_64 systems
-    #[cfg(target_arch = "x86_64")]
-    fn detect_x86_gpu() -> Self {
-        let mut config = Self {
-            gpu_name: "Unknown GPU".to_string(),
-            vram_mb: 2048, // Conservative default
-            optimal_batch_size: 256,
-            supports_avx2: is_x86_feature_detected!("avx2"),
-            supports_avx512: is_x86_feature_detected!("avx512f"),
-            supports_sse4_1: is_x86_feature_detected!("sse4_1"),
-            supports_wide_simd: is_x86_feature_detected!("avx2"),
-            cache_line_size: 64,
-            compute_units: 8,              // Conservative
-            memory_bandwidth_mbps: 256000, // 256 GB/s default
-        };
-
-        // Try to detect GPU manufacturer through system info
-        if let Ok(gpu_info) = Self::read_gpu_info() {
-            config = Self::configure_for_gpu(&mut config, &gpu_info);
-        } else {
-            // Fallback: optimize based on CPU SIMD capabilities
-            config = Self::optimize_by_cpu_features(config);
-        }
-
-        config
-    }
-
-    /// Detect GPU on non-x86 systems
-    #[cfg(not(target_arch = "x86_64"))]
-    fn detect_generic_gpu() -> Self {
-        Self {
-            gpu_name: "Generic GPU".to_string(),
-            vram_mb: 2048,
-            optimal_batch_size: 128,
-            supports_avx2: false,
-            supports_avx512: false,
-            supports_sse4_1: false,
-            supports_wide_simd: false,
-            cache_line_size: 64,
-            compute_units: 4,
-            memory_bandwidth_mbps: 128000,
+        match Self::enumerate().into_iter().max_by_key(|device| device.memory_bytes) {
+            Some(device) => Self::for_device(&device),
+            None => Self::without_gpu(),
         }
     }
 
-    /// Read GPU information from system (Windows/Linux/macOS)
-    fn read_gpu_info() -> Result<String, ()> {
-        #[cfg(target_os = "windows")]
+    /// Every GPU the OpenCL drivers report, in platform order
+    ///
+    /// Empty when built without the `opencl` feature.
+    pub fn enumerate() -> Vec<GpuDevice> {
+        #[cfg(feature = "opencl")]
         {
-            Self::read_windows_gpu_info()
+            Self::enumerate_opencl()
         }
 
-        #[cfg(target_os = "linux")]
+        #[cfg(not(feature = "opencl"))]
         {
-            Self::read_linux_gpu_info()
-        }
-
-        #[cfg(target_os = "macos")]
-        {
-            Self::read_macos_gpu_info()
+            Vec::new()
         }
     }
 
-    /// Read Windows GPU information
-    #[cfg(target_os = "windows")]
-    fn read_windows_gpu_info() -> Result<String, ()> {
-        // Try WMI for GPU detection
-        use std::process::Command;
-
-        let output = Command::new("wmic")
-            .args(&["path", "win32_VideoController", "get", "name"])
-            .output();
-
-        if let Ok(output) = output {
-            let gpu_name = String::from_utf8_lossy(&output.stdout)
-                .lines()
-                .nth(1) // Skip header
-                .unwrap_or("Unknown")
-                .trim();
-
-            Ok(gpu_name.to_string())
-        } else {
-            Err(())
-        }
-    }
-
-    /// Read Linux GPU information
-    #[cfg(target_os = "linux")]
-    fn read_linux_gpu_info() -> Result<String, ()> {
-        use std::process::Command;
-
-        // Try lspci or lshw
-        if let Ok(output) = Command::new("lspci").args(&["-v", "-s", "VGA"]).output() {
-            let gpu_info = String::from_utf8_lossy(&output.stdout);
-
-            // Extract GPU name from lspci output
-            for line in gpu_info.lines() {
-                if line.contains("VGA compatible controller") || line.contains("3D controller") {
-                    if let Some(name_start) = line.find(": ") {
-                        if let Some(name_end) = line[name_start + 2..].find("\"") {
-                            let gpu_name =
-                                line[name_start + 2..name_start + 2 + name_end].to_string();
-                            return Ok(gpu_name);
-                        }
-                    }
+    #[cfg(feature = "opencl")]
+    fn enumerate_opencl() -> Vec<GpuDevice> {
+        use ocl::enums::{DeviceInfo, DeviceInfoResult};
+        use ocl::{flags, Device, Platform};
+
+        let mut found = Vec::new();
+        for platform in Platform::list() {
+            let devices = match Device::list(platform, Some(flags::DEVICE_TYPE_GPU)) {
+                Ok(devices) => devices,
+                Err(e) => {
+                    log::debug!("Skipping OpenCL platform: {}", e);
+                    continue;
                 }
+            };
+
+            for device in devices {
+                let name = device.name().unwrap_or_else(|_| "Unknown GPU".into());
+                let vendor = device.vendor().unwrap_or_default();
+                found.push(GpuDevice {
+                    backend: GpuBackend::for_gpu(&format!("{} {}", vendor, name)),
+                    memory_bytes: match device.info(DeviceInfo::GlobalMemSize) {
+                        Ok(DeviceInfoResult::GlobalMemSize(bytes)) => bytes,
+                        _ => 0,
+                    },
+                    compute_units: match device.info(DeviceInfo::MaxComputeUnits) {
+                        Ok(DeviceInfoResult::MaxComputeUnits(units)) => units,
+                        _ => 0,
+                    },
+                    driver_version: match device.info(DeviceInfo::DriverVersion) {
+                        Ok(DeviceInfoResult::DriverVersion(version)) => version,
+                        _ => "unknown".into(),
+                    },
+                    name,
+                    vendor,
+                });
             }
         }
-
-        Err(())
+        found
     }
 
-    /// Read macOS GPU information
-    #[cfg(target_os = "macos")]
-    fn read_macos_gpu_info() -> Result<String, ()> {
-        use std::process::Command;
-
-        if let Ok(output) = Command::new("system_profiler")
-            .args(&["SPDisplaysDataType", "GPUPU"])
-            .output()
-        {
-            let gpu_info = String::from_utf8_lossy(&output.stdout);
-
-            // Parse GPU model from system profiler output
-            for line in gpu_info.lines() {
-                if line.contains("Chipset Model:") {
-                    if let Some(name_start) = line.find(": ") {
-                        if let Some(name_end) = line[name_start + 2..].find("\"") {
-                            let gpu_name =
-                                line[name_start + 2..name_start + 2 + name_end].to_string();
-                            return Ok(gpu_name);
-                        }
-                    }
-                }
-            }
-        }
-
-        Err(())
+    /// Settings for `device`, from what its driver reports
+    pub fn for_device(device: &GpuDevice) -> Self {
+        let mut config = Self::without_gpu();
+        config.gpu_name = device.name.clone();
+        config.vram_mb = (device.memory_bytes / (1024 * 1024)) as u32;
+        config.compute_units = device.compute_units.max(1);
+        // Eight batches in flight per compute unit keep every unit busy
+        config.optimal_batch_size = (config.compute_units * 8).clamp(64, 4096);
+        config.driver_version = device.driver_version.clone();
+        config.backend = device.backend;
+        config
     }
 
-    /// Configure optimal settings based on detected GPU
-    fn configure_for_gpu(base_config: &mut GpuConfig, gpu_name: &str) -> Self {
-        let gpu_name_lower = gpu_name.to_lowercase();
-
-        base_config.gpu_name = gpu_name.to_string();
-
-        // NVIDIA RTX series optimization
-        if gpu_name_lower.contains("rtx") {
-            if gpu_name_lower.contains("40") {
-                base_config.vram_mb = 16384; // 16GB+
-                base_config.optimal_batch_size = 1024;
-                base_config.compute_units = 128;
-                base_config.memory_bandwidth_mbps = 1008000; // ~1000 GB/s
-            } else if gpu_name_lower.contains("30") {
-                base_config.vram_mb = 12288; // 12GB
-                base_config.optimal_batch_size = 512;
-                base_config.compute_units = 82; // RTX 3080 has 82 CUs
-                base_config.memory_bandwidth_mbps = 760800; // ~760 GB/s
-            } else if gpu_name_lower.contains("20") {
-                base_config.vram_mb = 8192; // 8GB
-                base_config.optimal_batch_size = 256;
-                base_config.compute_units = 64; // RTX 2080 has 64 CUs
-                base_config.memory_bandwidth_mbps = 616000; // ~616 GB/s
-            }
-        }
-        // AMD RDNA/RDNA optimization
-        else if gpu_name_lower.contains("radeon") || gpu_name_lower.contains("rx ") {
-            if gpu_name_lower.contains("7") {
-                base_config.vram_mb = 12288; // RX 7000 series
-                base_config.optimal_batch_size = 384;
-                base_config.compute_units = 60; // RDNA 3
-                base_config.memory_bandwidth_mbps = 624000;
-            } else if gpu_name_lower.contains("6") {
-                base_config.vram_mb = 12288; // RX 6000 series
-                base_config.optimal_batch_size = 320;
-                base_config.compute_units = 40; // RDNA 2
-                base_config.memory_bandwidth_mbps = 512000;
-            }
-        }
-        // Intel Arc optimization
-        else if gpu_name_lower.contains("arc") {
-            base_config.vram_mb = 16384; // Arc A770
-            base_config.optimal_batch_size = 256;
-            base_config.compute_units = 32; // Arc A series
-            base_config.memory_bandwidth_mbps = 560000;
-        }
-        // Integrated graphics optimization
-        else if gpu_name_lower.contains("intel") && !gpu_name_lower.contains("arc") {
-            base_config.vram_mb = 4096; // Shared memory estimate
-            base_config.optimal_batch_size = 64;
-            base_config.compute_units = 24; // Intel integrated
-            base_config.memory_bandwidth_mbps = 102400; // ~100 GB/s
-        }
+    /// Settings for mining on the CPU alone
+    fn without_gpu() -> Self {
+        let config = Self {
+            gpu_name: "No GPU".to_string(),
+            vram_mb: 0,
+            optimal_batch_size: 128,
+            supports_avx2: cpu_has_avx2(),
+            supports_avx512: cpu_has_avx512(),
+            supports_sse4_1: cpu_has_sse4_1(),
+            supports_wide_simd: cpu_has_avx2(),
+            cache_line_size: 64,
+            compute_units: 4,
+            memory_bandwidth_mbps: 256000, // 256 GB/s default
+            driver_version: "unknown".to_string(),
+            backend: GpuBackend::OpenCl,
+        };
 
-        base_config.clone()
+        Self::optimize_by_cpu_features(config)
     }
 
     /// Optimize based on CPU SIMD features
     fn optimize_by_cpu_features(base_config: GpuConfig) -> GpuConfig {
         let mut config = base_config;
 
-        if is_x86_feature_detected!("avx512f") {
+        if config.supports_avx512 {
             config.optimal_batch_size *= 4;
             config.supports_wide_simd = true;
-        } else if is_x86_feature_detected!("avx2") {
+        } else if config.supports_avx2 {
             config.optimal_batch_size *= 2;
             config.supports_wide_simd = true;
         }
@@ -273,6 +228,8 @@ impl GpuConfig {
              • Compute Units: {}\n\
              • Memory Bandwidth: {} MB/s\n\
              • Cache Line: {} bytes\n\
+             • Driver: {}\n\
+             • Backend: {}\n\
              \n\
              ⚡ Optimizations Applied:\n\
              • GPU-parallel memory mixing\n\
@@ -286,7 +243,9 @@ impl GpuConfig {
             self.supports_avx512,
             self.compute_units,
             self.memory_bandwidth_mbps,
-            self.cache_line_size
+            self.cache_line_size,
+            self.driver_version,
+            self.backend.as_str()
         )
     }
 
@@ -296,13 +255,212 @@ impl GpuConfig {
             gpu_name: "Any GPU".to_string(),
             vram_mb: 4096,           // Conservative assumption
             optimal_batch_size: 128, // Safe for any hardware
-            supports_avx2: is_x86_feature_detected!("avx2"),
+            supports_avx2: cpu_has_avx2(),
             supports_avx512: false,
-            supports_sse4_1: is_x86_feature_detected!("sse4_1"),
-            supports_wide_simd: is_x86_feature_detected!("avx2"),
+            supports_sse4_1: cpu_has_sse4_1(),
+            supports_wide_simd: cpu_has_avx2(),
             cache_line_size: 64,
             compute_units: 8,              // Conservative
             memory_bandwidth_mbps: 256000, // Conservative estimate
+            driver_version: "unknown".to_string(),
+            backend: GpuBackend::OpenCl,
+        }
+    }
+}
+
+/// Sensor values of a GPU, `None` where its driver reports nothing
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ThermalReading {
+    /// Core temperature (°C)
+    pub temperature_c: Option<f32>,
+
+    /// Board power draw (W)
+    pub power_w: Option<f32>,
+}
+
+/// Limits a GPU mines within
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThermalPolicy {
+    /// Temperature to stay under (°C)
+    pub target_temp_c: f32,
+
+    /// Power draw to stay under (W), `None` for no limit
+    pub max_power_w: Option<f32>,
+
+    /// Degrees under the target a throttled card must cool to before it
+    /// speeds up again
+    pub hysteresis_c: f32,
+}
+
+impl Default for ThermalPolicy {
+    fn default() -> Self {
+        Self {
+            target_temp_c: 80.0,
+            max_power_w: None,
+            hysteresis_c: 5.0,
+        }
+    }
+}
+
+impl ThermalPolicy {
+    /// Whether `reading` exceeds a limit
+    pub fn exceeded(&self, reading: &ThermalReading) -> bool {
+        reading.temperature_c.is_some_and(|temp| temp > self.target_temp_c)
+            || matches!((reading.power_w, self.max_power_w), (Some(power), Some(max)) if power > max)
+    }
+
+    /// Whether `reading` is far enough under every limit to speed up;
+    /// power needs [`POWER_HYSTERESIS`] of headroom
+    pub fn cooled(&self, reading: &ThermalReading) -> bool {
+        reading.temperature_c.map_or(true, |temp| temp <= self.target_temp_c - self.hysteresis_c)
+            && match (reading.power_w, self.max_power_w) {
+                (Some(power), Some(max)) => power <= max * (1.0 - POWER_HYSTERESIS),
+                _ => true,
+            }
+    }
+}
+
+/// Share of the power limit a throttled card must drop under to speed up
+pub const POWER_HYSTERESIS: f32 = 0.05;
+
+/// Lowest share of its intensity a throttled card keeps (percent)
+pub const MIN_THROTTLE_PERCENT: u32 = 10;
+
+/// Percentage points one reading takes off or gives back
+pub const THROTTLE_STEP_PERCENT: u32 = 10;
+
+/// Intensity share of one GPU under a [`ThermalPolicy`]
+///
+/// Every reading over a limit slows the card by one step; it speeds up by
+/// one step per reading only once it has cooled by the hysteresis, and
+/// holds in between, so it settles under the target instead of swinging
+/// around it.
+#[derive(Debug, Clone)]
+pub struct ThermalThrottle {
+    policy: ThermalPolicy,
+    percent: u32,
+}
+
+impl ThermalThrottle {
+    /// Throttle at full intensity
+    pub fn new(policy: ThermalPolicy) -> Self {
+        Self { policy, percent: 100 }
+    }
+
+    /// Policy applied
+    pub fn policy(&self) -> &ThermalPolicy {
+        &self.policy
+    }
+
+    /// Share of its intensity the card mines at (percent)
+    pub fn percent(&self) -> u32 {
+        self.percent
+    }
+
+    /// Apply `reading`, returning the new share
+    pub fn update(&mut self, reading: &ThermalReading) -> u32 {
+        if self.policy.exceeded(reading) {
+            self.percent = self.percent.saturating_sub(THROTTLE_STEP_PERCENT).max(MIN_THROTTLE_PERCENT);
+        } else if self.policy.cooled(reading) {
+            self.percent = (self.percent + THROTTLE_STEP_PERCENT).min(100);
+        }
+        self.percent
+    }
+
+    /// Pause after a launch that ran for `busy`, so the card works only
+    /// its share of the time
+    pub fn idle_time(&self, busy: Duration) -> Duration {
+        busy * (100 - self.percent) / self.percent
+    }
+}
+
+/// Whether the running CPU supports AVX2
+fn cpu_has_avx2() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    {
+        is_x86_feature_detected!("avx2")
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        false
+    }
+}
+
+/// Whether the running CPU supports AVX-512F
+fn cpu_has_avx512() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    {
+        is_x86_feature_detected!("avx512f")
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        false
+    }
+}
+
+/// Whether the running CPU supports SSE4.1
+fn cpu_has_sse4_1() -> bool {
+    #[cfg(target_arch = "x86_64")]
+    {
+        is_x86_feature_detected!("sse4.1")
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config_for_device() {
+        let device = GpuDevice {
+            name: "NVIDIA GeForce RTX 3060".into(),
+            vendor: "NVIDIA Corporation".into(),
+            memory_bytes: 12 * 1024 * 1024 * 1024,
+            compute_units: 28,
+            driver_version: "535.104.05".into(),
+            backend: GpuBackend::Cuda,
+        };
+        let config = GpuConfig::for_device(&device);
+
+        assert_eq!(config.gpu_name, device.name);
+        assert_eq!(config.vram_mb, 12288);
+        assert_eq!(config.compute_units, 28);
+        assert_eq!(config.optimal_batch_size, 224);
+        assert_eq!(config.driver_version, "535.104.05");
+        assert_eq!(config.backend, GpuBackend::Cuda);
+    }
+
+    #[test]
+    fn test_thermal_throttle_hysteresis() {
+        let policy = ThermalPolicy { max_power_w: Some(100.0), ..Default::default() };
+        let mut throttle = ThermalThrottle::new(policy);
+        let temp = |c: f32| ThermalReading { temperature_c: Some(c), power_w: Some(60.0) };
+
+        assert_eq!(throttle.update(&temp(70.0)), 100);
+        assert_eq!(throttle.update(&temp(86.0)), 90);
+        assert_eq!(throttle.update(&temp(83.0)), 80);
+
+        // Under the target but within the hysteresis: hold
+        assert_eq!(throttle.update(&temp(78.0)), 80);
+        assert_eq!(throttle.update(&temp(75.0)), 90);
+        assert_eq!(throttle.idle_time(Duration::from_millis(90)), Duration::from_millis(10));
+
+        // Power counts as well, and unknown sensors never throttle
+        let power = |w: f32| ThermalReading { temperature_c: Some(60.0), power_w: Some(w) };
+        assert_eq!(throttle.update(&power(110.0)), 80);
+        assert_eq!(throttle.update(&power(97.0)), 80);
+        assert_eq!(throttle.update(&ThermalReading::default()), 90);
+
+        for _ in 0..20 {
+            throttle.update(&temp(95.0));
         }
+        assert_eq!(throttle.percent(), MIN_THROTTLE_PERCENT);
     }
 }
@@ -6,112 +6,7 @@
 
 use std::collections::HashMap;
 
-/// Mining hash rate tiers for proportional rewards
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum HashrateTier {
-    /// Small miners with consumer GPUs (< 50 GH/s)
-    Petit,
-    /// Medium miners with decent GPUs (50-200 GH/s)
-    Moyen,
-    /// Large miners with high-end GPUs (200-500 GH/s)
-    Gros,
-    /// Industrial mining operations (500+ GH/s)
-    Industriel,
-}
-
-impl HashrateTier {
-    /// Determine tier from hashrate in GH/s
-    pub fn from_hashrate(hashrate_ghs: f64) -> Self {
-        if hashrate_ghs < 50.0 {
-            HashrateTier::Petit
-        } else if hashrate_ghs < 200.0 {
-            HashrateTier::Moyen
-        } else if hashrate_ghs < 500.0 {
-            HashrateTier::Gros
-        } else if hashrate_ghs < 1000.0 {
-            HashrateTier::Industriel
-        } else {
-            HashrateTier::UltraGros // Very large operations
-        }
-    }
-
-    /// Get base mining percentage (vs 60% standard for large miners)
-    pub fn base_mining_percentage(&self) -> f64 {
-        match self {
-            HashrateTier::Petit => 0.90,      // 90% of 60%
-            HashrateTier::Moyen => 0.60,      // 60% of 60%
-            HashrateTier::Gros => 0.35,       // 35% of 60%
-            HashrateTier::Industriel => 0.25, // 25% of 60%
-            HashrateTier::UltraGros => 0.15,  // 15% of 60%
-        }
-    }
-
-    /// Get optimal solidarity percentage for this tier
-    pub fn solidarity_percentage(&self) -> f64 {
-        match self {
-            HashrateTier::Petit => 0.35,      // 35% for strong protection
-            HashrateTier::Moyen => 0.20,      // 20% for moderate support
-            HashrateTier::Gros => 0.10,       // 10% for minimal support
-            HashrateTier::Industriel => 0.05, // 5% for symbolic contribution
-            HashrateTier::UltraGros => 0.02,  // 2% for very minimal
-        }
-    }
-
-    /// Get treasury percentage for this tier
-    pub fn treasury_percentage(&self) -> f64 {
-        match self {
-            HashrateTier::Petit => 0.05,      // 5% for development
-            HashrateTier::Moyen => 0.10,      // 10% for ecosystem growth
-            HashrateTier::Gros => 0.20,       // 20% for network security
-            HashrateTier::Industriel => 0.25, // 25% for infrastructure
-            HashrateTier::UltraGros => 0.28,  // 28% for operational costs
-        }
-    }
-}
-
-/// Proportional mining reward distribution
-#[derive(Debug, Clone)]
-pub struct ProportionalRewards {
-    /// Mining percentage (adjusted by tier)
-    pub mining_percentage: f64,
-    /// Solidarity percentage (adjusted by tier)
-    pub solidarity_percentage: f64,
-    /// Treasury percentage (adjusted by tier)
-    pub treasury_percentage: f64,
-}
-
-impl ProportionalRewards {
-    /// Calculate optimal reward distribution based on hash rate tier
-    pub fn for_hashrate(hashrate_ghs: f64) -> Self {
-        let tier = HashrateTier::from_hashrate(hashrate_ghs);
-
-        Self {
-            mining_percentage: tier.base_mining_percentage(),
-            solidarity_percentage: tier.solidarity_percentage(),
-            treasury_percentage: tier.treasury_percentage(),
-        }
-    }
-
-    /// Calculate individual rewards from block reward
-    pub fn calculate_rewards(&self, block_reward: u64) -> (u64, u64, u64) {
-        let miner_reward = (block_reward as f64 * self.mining_percentage) as u64;
-        let solidarity_reward = (block_reward as f64 * self.solidarity_percentage) as u64;
-        let treasury_reward = (block_reward as f64 * self.treasury_percentage) as u64;
-
-        (miner_reward, solidarity_reward, treasury_reward)
-    }
-
-    /// Get tier description for display
-    pub fn tier_description(&self) -> &'static str {
-        match self {
-            HashrateTier::Petit => "Petits mineurs - Protection renforcée",
-            HashrateTier::Moyen => "Mineurs moyens - Support modéré",
-            HashrateTier::Gros => "Gros mineurs - Inclusion financière",
-            HashrateTier::Industriel => "Opérateurs industriels - Maintenance réseau",
-            HashrateTier::UltraGros => "Très gros opérateurs - Sécurité infra",
-        }
-    }
-}
+pub use aequitas_core::solidarity::{HashrateTier, ProportionalRewards};
 
 /// Track miner contribution history for fair solidarity distribution
 #[derive(Debug, Clone)]
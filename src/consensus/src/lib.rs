@@ -4,12 +4,13 @@
 //! designed for fair mining on consumer GPUs like RTX 3060.
 
 pub mod aequihash;
+pub mod bench;
 pub mod dag;
 pub mod gpu_config;
 pub mod pow;
 
-pub use aequihash::AequiHash;
+pub use aequihash::{AequiHash, HashOutput};
 pub use dag::DAG;
-pub use gpu_config::GpuConfig;
+pub use gpu_config::{GpuBackend, GpuConfig, GpuDevice, ThermalPolicy, ThermalReading, ThermalThrottle};
 pub use hashrate_classifier::ProportionalRewards;
-pub use pow::ProofOfWork;
+pub use pow::{verify_block_pow, CancellationToken, ParallelCpuMiner, PowError, ProofOfWork, SearchOutcome};
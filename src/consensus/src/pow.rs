@@ -4,39 +4,74 @@
 
 use crate::aequihash::AequiHash;
 use crate::dag::{DAG, DAGManager};
-use num_bigint::BigUint;
-use num_traits::One;
+use aequitas_core::{target, BlockHeader};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use thiserror::Error;
+
+/// Why a header's proof of work is rejected
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum PowError {
+    #[error("Compact target does not match the difficulty")]
+    InvalidBits,
+
+    #[error("AequiHash does not meet the target")]
+    InsufficientWork,
+
+    #[error("Epoch {0} needs the hash of its seed anchor block")]
+    MissingSeedAnchor(u64),
+}
+
+/// Hash AequiHash seals: the header hash with a zero nonce
+///
+/// The nonce enters AequiHash on its own, so the seal hash stays fixed
+/// while miners search nonces.
+pub fn seal_hash(header: &BlockHeader) -> [u8; 32] {
+    let mut unsealed = header.clone();
+    unsealed.nonce = 0;
+    unsealed.hash()
+}
+
+/// Light DAG of the last verified epoch, shared by verifications
+fn light_dag(epoch: u64, seed: &[u8; 32]) -> DAG {
+    static LIGHT_DAGS: OnceLock<Mutex<DAGManager>> = OnceLock::new();
+    let manager = LIGHT_DAGS.get_or_init(|| Mutex::new(DAGManager::new(false)));
+    let mut manager = manager.lock().unwrap_or_else(|e| e.into_inner());
+    manager.get_dag(epoch, seed).clone()
+}
+
+/// Verify the AequiHash proof of work of `header` as a light client
+///
+/// Derives the epoch from the height and hashes with the epoch's cache,
+/// built on first use and kept for the next headers of the same epoch.
+/// `anchor` is the hash of the block at [`AequiHash::seed_anchor_height`],
+/// which every epoch but the first needs.
+pub fn verify_block_pow(header: &BlockHeader, anchor: Option<&[u8; 32]>) -> Result<(), PowError> {
+    if !header.bits_match_difficulty() {
+        return Err(PowError::InvalidBits);
+    }
+
+    let epoch = AequiHash::epoch_from_height(header.height);
+    if epoch > 0 && anchor.is_none() {
+        return Err(PowError::MissingSeedAnchor(epoch));
+    }
+    let seed = AequiHash::epoch_seed(epoch, anchor);
+    let dag = light_dag(epoch, &seed);
+    let hash = AequiHash::new(epoch, &seed).hash_dag(&seal_hash(header), header.nonce, &dag);
+    if !target::hash_meets_target(&hash, &header.target()) {
+        return Err(PowError::InsufficientWork);
+    }
+    Ok(())
+}
 
 /// Difficulty to target conversion
 pub fn difficulty_to_target(difficulty: u64) -> [u8; 32] {
-    if difficulty == 0 {
-        return [0xff; 32];
-    }
-    
-    // Target = 2^256 / difficulty
-    let max_target = BigUint::from(2u32).pow(256) - BigUint::one();
-    let target = max_target / BigUint::from(difficulty);
-    
-    let target_bytes = target.to_bytes_be();
-    let mut result = [0u8; 32];
-    
-    let start = 32 - target_bytes.len().min(32);
-    result[start..].copy_from_slice(&target_bytes[..target_bytes.len().min(32)]);
-    
-    result
+    target::difficulty_to_target(difficulty)
 }
 
 /// Compare two hashes (returns true if a <= b)
 pub fn hash_less_or_equal(a: &[u8; 32], b: &[u8; 32]) -> bool {
-    for i in 0..32 {
-        if a[i] < b[i] {
-            return true;
-        }
-        if a[i] > b[i] {
-            return false;
-        }
-    }
-    true
+    target::hash_meets_target(a, b)
 }
 
 /// Proof of Work result
@@ -48,22 +83,37 @@ pub struct ProofOfWork {
     /// The resulting hash
     pub hash: [u8; 32],
     
-    /// Mix hash (for verification)
+    /// Mix hash, from which `hash` is computed
     pub mix_hash: [u8; 32],
 }
 
 impl ProofOfWork {
-    /// Verify this proof of work
+    /// First verification stage: `hash` follows from the mix hash and meets
+    /// the target
+    ///
+    /// Costs one BLAKE3 and no dataset access, so pools and nodes drop junk
+    /// solutions before replaying the mix.
+    pub fn verify_final(&self, header_hash: &[u8; 32], difficulty: u64) -> bool {
+        AequiHash::final_hash(header_hash, self.nonce, &self.mix_hash) == self.hash
+            && hash_less_or_equal(&self.hash, &difficulty_to_target(difficulty))
+    }
+
+    /// Verify this proof of work against the cache of `epoch` and `seed`
+    ///
+    /// Runs [`ProofOfWork::verify_final`], then replays the mix over the
+    /// cache and checks it reaches the same mix hash.
     pub fn verify(
         &self,
         header_hash: &[u8; 32],
         difficulty: u64,
         epoch: u64,
+        seed: &[u8; 32],
         cache: &[u32],
     ) -> bool {
-        let aequihash = AequiHash::new(epoch);
-        let target = difficulty_to_target(difficulty);
-        aequihash.verify(header_hash, self.nonce, &target, cache)
+        if !self.verify_final(header_hash, difficulty) {
+            return false;
+        }
+        AequiHash::new(epoch, seed).compute_light(header_hash, self.nonce, cache).mix_hash == self.mix_hash
     }
 }
 
@@ -93,6 +143,54 @@ impl MiningStats {
     }
 }
 
+/// Hashes between two progress reports of [`CpuMiner::search`]
+pub const PROGRESS_INTERVAL: u64 = 256;
+
+/// Flag interrupting a nonce search, shared by clones
+///
+/// Cancelling stops every search holding a clone; a reset token can be
+/// reused for the next work.
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// A token not cancelled yet
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stop the searches holding this token
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether the token was cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Clear the cancellation, for the next work
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::Relaxed);
+    }
+}
+
+/// How a nonce search ended
+#[derive(Clone, Debug)]
+pub enum SearchOutcome {
+    /// A nonce meets the target
+    Found(ProofOfWork),
+
+    /// No nonce of the range meets the target
+    Exhausted,
+
+    /// Cancelled before `next_nonce` was hashed; restart from there to
+    /// resume the same work
+    Cancelled { next_nonce: u64 },
+}
+
 /// CPU miner (for testing/reference)
 pub struct CpuMiner {
     /// DAG manager
@@ -116,25 +214,30 @@ impl CpuMiner {
         start_nonce: u64,
         max_nonce: u64,
     ) -> Option<ProofOfWork> {
-        let epoch = height / super::aequihash::EPOCH_LENGTH;
-        let dag = self.dag_manager.get_dag(epoch);
-        let aequihash = AequiHash::new(epoch);
-        let target = difficulty_to_target(difficulty);
-        let cache = dag.cache();
-        
-        for nonce in start_nonce..max_nonce {
-            let hash = aequihash.hash_light(header_hash, nonce, cache);
-            
-            if hash_less_or_equal(&hash, &target) {
-                return Some(ProofOfWork {
-                    nonce,
-                    hash,
-                    mix_hash: hash, // Simplified for CPU
-                });
-            }
+        let mut work = WorkUnit::new(*header_hash, difficulty, height, String::new());
+        work.start_nonce = start_nonce;
+        work.end_nonce = max_nonce;
+        match self.search(&work, &CancellationToken::new(), |_| {}) {
+            SearchOutcome::Found(proof) => Some(proof),
+            _ => None,
         }
-        
-        None
+    }
+    
+    /// Search the nonces of `work` until one meets its target, the range
+    /// ends, or `cancel` is cancelled
+    ///
+    /// `on_progress` gets the hashes done since its previous call, every
+    /// [`PROGRESS_INTERVAL`] hashes and once when the search ends. It may
+    /// cancel the token itself, to stop after a budget for example.
+    pub fn search(
+        &mut self,
+        work: &WorkUnit,
+        cancel: &CancellationToken,
+        on_progress: impl FnMut(u64),
+    ) -> SearchOutcome {
+        let epoch = work.height / super::aequihash::EPOCH_LENGTH;
+        let dag = self.dag_manager.get_dag(epoch, &work.seed);
+        search_nonces(&AequiHash::new(epoch, &work.seed), dag.cache(), work, cancel, on_progress)
     }
     
     /// Benchmark the CPU miner
@@ -144,8 +247,9 @@ impl CpuMiner {
         let mut hashes = 0u64;
         
         let epoch = 0;
-        let dag = self.dag_manager.get_dag(epoch);
-        let aequihash = AequiHash::new(epoch);
+        let seed = AequiHash::compute_epoch_seed(epoch);
+        let dag = self.dag_manager.get_dag(epoch, &seed);
+        let aequihash = AequiHash::new(epoch, &seed);
         let cache = dag.cache();
         
         while start.elapsed().as_secs() < seconds {
@@ -166,6 +270,137 @@ impl Default for CpuMiner {
     }
 }
 
+/// Parts per thread a [`ParallelCpuMiner`] splits the nonce range into
+const PARTS_PER_THREAD: u64 = 8;
+
+/// CPU miner running one search per thread
+///
+/// The nonce range is split into more parts than threads; every thread
+/// takes the next untouched part when its own is exhausted, so threads
+/// that run ahead take over the work of slower ones.
+pub struct ParallelCpuMiner {
+    /// DAG manager, its light cache shared by every thread
+    dag_manager: DAGManager,
+
+    /// Number of threads
+    threads: usize,
+
+    /// Hashes done by every thread, across searches
+    hashes: Arc<AtomicU64>,
+}
+
+impl ParallelCpuMiner {
+    /// Create a miner running `threads` threads, one per CPU when 0
+    pub fn new(threads: usize) -> Self {
+        let threads = match threads {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            n => n,
+        };
+        Self {
+            dag_manager: DAGManager::new(false),
+            threads,
+            hashes: Arc::default(),
+        }
+    }
+
+    /// Number of threads
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// Counter of the hashes done, updated while a search runs
+    pub fn hash_counter(&self) -> Arc<AtomicU64> {
+        self.hashes.clone()
+    }
+
+    /// First proof of work any thread finds in `work`, or `None` once the
+    /// range is exhausted or `cancel` is cancelled
+    pub fn mine(&mut self, work: &WorkUnit, cancel: &CancellationToken) -> Option<ProofOfWork> {
+        if cancel.is_cancelled() {
+            return None;
+        }
+
+        let epoch = work.height / super::aequihash::EPOCH_LENGTH;
+        let dag = self.dag_manager.get_dag(epoch, &work.seed).clone();
+        let aequihash = AequiHash::new(epoch, &work.seed);
+
+        let range = work.end_nonce.saturating_sub(work.start_nonce);
+        let parts = work.split((self.threads as u64 * PARTS_PER_THREAD).clamp(1, range.max(1)));
+        let next_part = AtomicUsize::new(0);
+        let found = Mutex::new(None);
+        // Stops every thread, once a proof is found or `cancel` is
+        let stop = CancellationToken::new();
+
+        std::thread::scope(|scope| {
+            for _ in 0..self.threads {
+                scope.spawn(|| {
+                    while let Some(part) = parts.get(next_part.fetch_add(1, Ordering::Relaxed)) {
+                        if cancel.is_cancelled() {
+                            stop.cancel();
+                        }
+                        let outcome = search_nonces(&aequihash, dag.cache(), part, &stop, |hashes| {
+                            self.hashes.fetch_add(hashes, Ordering::Relaxed);
+                            if cancel.is_cancelled() {
+                                stop.cancel();
+                            }
+                        });
+                        match outcome {
+                            SearchOutcome::Found(proof) => {
+                                found.lock().unwrap_or_else(|e| e.into_inner()).get_or_insert(proof);
+                                stop.cancel();
+                                return;
+                            }
+                            SearchOutcome::Cancelled { .. } => return,
+                            SearchOutcome::Exhausted => {}
+                        }
+                    }
+                });
+            }
+        });
+
+        found.into_inner().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Nonce search of [`CpuMiner::search`], with the epoch's `aequihash` and
+/// light `cache`
+fn search_nonces(
+    aequihash: &AequiHash,
+    cache: &[u32],
+    work: &WorkUnit,
+    cancel: &CancellationToken,
+    mut on_progress: impl FnMut(u64),
+) -> SearchOutcome {
+    let target = difficulty_to_target(work.difficulty);
+    let mut unreported = 0;
+    for nonce in work.start_nonce..work.end_nonce {
+        if cancel.is_cancelled() {
+            on_progress(unreported);
+            return SearchOutcome::Cancelled { next_nonce: nonce };
+        }
+
+        let output = aequihash.compute_light(&work.header_hash, nonce, cache);
+        unreported += 1;
+
+        if hash_less_or_equal(&output.hash, &target) {
+            on_progress(unreported);
+            return SearchOutcome::Found(ProofOfWork {
+                nonce,
+                hash: output.hash,
+                mix_hash: output.mix_hash,
+            });
+        }
+
+        if unreported == PROGRESS_INTERVAL {
+            on_progress(unreported);
+            unreported = 0;
+        }
+    }
+
+    on_progress(unreported);
+    SearchOutcome::Exhausted
+}
+
 /// Work unit for mining pools
 #[derive(Clone, Debug)]
 pub struct WorkUnit {
@@ -178,6 +413,9 @@ pub struct WorkUnit {
     /// Block height
     pub height: u64,
     
+    /// Seed of the height's epoch
+    pub seed: [u8; 32],
+    
     /// Starting nonce
     pub start_nonce: u64,
     
@@ -190,6 +428,9 @@ pub struct WorkUnit {
 
 impl WorkUnit {
     /// Create a new work unit
+    ///
+    /// The seed is the epoch's fallback; past epoch 0, set it to the
+    /// anchored [`AequiHash::epoch_seed`] the network verifies with.
     pub fn new(
         header_hash: [u8; 32],
         difficulty: u64,
@@ -200,6 +441,7 @@ impl WorkUnit {
             header_hash,
             difficulty,
             height,
+            seed: AequiHash::compute_epoch_seed(AequiHash::epoch_from_height(height)),
             start_nonce: 0,
             end_nonce: u64::MAX,
             job_id,
@@ -224,6 +466,7 @@ impl WorkUnit {
                     header_hash: self.header_hash,
                     difficulty: self.difficulty,
                     height: self.height,
+                    seed: self.seed,
                     start_nonce: start,
                     end_nonce: end,
                     job_id: format!("{}-{}", self.job_id, i),
@@ -256,6 +499,91 @@ mod tests {
         assert!(hash_less_or_equal(&a, &a));
     }
     
+    #[test]
+    fn test_verify_block_pow() {
+        let mut header = BlockHeader::new([0u8; 32], [0u8; 32], 1, 1);
+        header.nonce = 7;
+        assert_eq!(verify_block_pow(&header, None), Ok(()));
+
+        let sealed = seal_hash(&header);
+        header.nonce = 0;
+        assert_eq!(sealed, header.hash());
+
+        header.difficulty = 2;
+        assert_eq!(verify_block_pow(&header, None), Err(PowError::InvalidBits));
+
+        header.set_difficulty(u64::MAX);
+        assert_eq!(verify_block_pow(&header, None), Err(PowError::InsufficientWork));
+
+        // Past the first epoch, the seed chains to the anchor block
+        header.set_difficulty(1);
+        header.height = crate::aequihash::EPOCH_LENGTH;
+        assert_eq!(verify_block_pow(&header, None), Err(PowError::MissingSeedAnchor(1)));
+        assert_eq!(verify_block_pow(&header, Some(&[5u8; 32])), Ok(()));
+    }
+
+    #[test]
+    fn test_search_cancellation() {
+        let mut miner = CpuMiner::new();
+        let mut work = WorkUnit::new([0u8; 32], u64::MAX, 0, "test".to_string());
+        work.end_nonce = 10 * PROGRESS_INTERVAL;
+
+        // Cancelled up front: nothing is hashed
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let outcome = miner.search(&work, &cancel, |_| {});
+        assert!(matches!(outcome, SearchOutcome::Cancelled { next_nonce: 0 }));
+
+        // Cancelled from the progress hook, then resumed where it stopped
+        cancel.reset();
+        let mut hashed = 0;
+        let outcome = miner.search(&work, &cancel, |hashes| {
+            hashed += hashes;
+            cancel.cancel();
+        });
+        assert!(matches!(outcome, SearchOutcome::Cancelled { next_nonce } if next_nonce == PROGRESS_INTERVAL));
+        assert_eq!(hashed, PROGRESS_INTERVAL);
+
+        // Any hash meets difficulty 1
+        cancel.reset();
+        let outcome = miner.search(&WorkUnit::new([0u8; 32], 1, 0, "easy".to_string()), &cancel, |_| {});
+        assert!(matches!(outcome, SearchOutcome::Found(ProofOfWork { nonce: 0, .. })));
+    }
+
+    #[test]
+    fn test_parallel_miner() {
+        let mut miner = ParallelCpuMiner::new(4);
+        let header = [3u8; 32];
+
+        // Target of difficulty 4: a quarter of the hashes meet it
+        let work = WorkUnit::new(header, 4, 0, "test".to_string());
+        let proof = miner.mine(&work, &CancellationToken::new()).expect("a proof");
+        let seed = work.seed;
+        let cache = miner.dag_manager.get_dag(0, &seed).cache().to_vec();
+        assert!(proof.verify(&header, 4, 0, &seed, &cache));
+
+        // A forged mix hash fails the first stage, a forged hash matching it
+        // fails the replay
+        let mut forged = proof.clone();
+        forged.mix_hash[0] ^= 1;
+        assert!(!forged.verify_final(&header, 4));
+        forged.hash = AequiHash::final_hash(&header, forged.nonce, &forged.mix_hash);
+        if forged.verify_final(&header, 4) {
+            assert!(!forged.verify(&header, 4, 0, &seed, &cache));
+        }
+        assert!(miner.hash_counter().load(Ordering::Relaxed) >= 1);
+
+        // Nothing of a tiny range meets difficulty u64::MAX
+        let mut hopeless = WorkUnit::new(header, u64::MAX, 0, "hopeless".to_string());
+        hopeless.end_nonce = 64;
+        assert!(miner.mine(&hopeless, &CancellationToken::new()).is_none());
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        let work = WorkUnit::new(header, u64::MAX, 0, "cancelled".to_string());
+        assert!(miner.mine(&work, &cancel).is_none());
+    }
+
     #[test]
     fn test_work_unit_split() {
         let work = WorkUnit::new([0u8; 32], 1000, 0, "test".to_string());
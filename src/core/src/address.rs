@@ -1,18 +1,47 @@
 //! Address management for Aequitas
 //!
 //! Addresses are derived from Ed25519 public keys using Keccak256 hashing.
+//!
+//! Script-hash addresses (`aeqs1...`) commit to a spending descriptor, such
+//! as a [`MultisigPolicy`](crate::MultisigPolicy), instead of a single key.
+//! On chain both kinds are the same 20-byte hash: the spending input reveals
+//! the key or the descriptor, and must hash to the output's address. The
+//! prefix and a distinct checksum only keep the two apart for people, so a
+//! string of one kind never parses as the other.
 
 use serde::{Deserialize, Serialize};
-use sha3::{Digest, Keccak256};
+use crate::hashing;
 use ed25519_dalek::{SigningKey, VerifyingKey};
 use rand::rngs::OsRng;
 
 /// Address prefix for Aequitas mainnet
 pub const ADDRESS_PREFIX: &str = "aeq";
 
+/// Prefix of script-hash addresses
+pub const SCRIPT_ADDRESS_PREFIX: &str = "aeqs";
+
 /// Address length (20 bytes + 4 byte checksum)
 pub const ADDRESS_LENGTH: usize = 24;
 
+/// What an address string says its hash commits to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressKind {
+    /// A single public key (`aeq1...`)
+    PublicKeyHash,
+
+    /// A spending descriptor revealed at spend time (`aeqs1...`)
+    ScriptHash,
+}
+
+impl AddressKind {
+    fn prefix(self) -> &'static str {
+        match self {
+            AddressKind::PublicKeyHash => ADDRESS_PREFIX,
+            AddressKind::ScriptHash => SCRIPT_ADDRESS_PREFIX,
+        }
+    }
+}
+
 /// An Aequitas address
 #[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Address {
@@ -29,9 +58,7 @@ impl Address {
     /// Create an address from a public key
     pub fn from_public_key(public_key: &VerifyingKey) -> Self {
         let pk_bytes = public_key.to_bytes();
-        let mut hasher = Keccak256::new();
-        hasher.update(&pk_bytes);
-        let hash = hasher.finalize();
+        let hash = hashing::keccak256(&pk_bytes);
         
         // Take last 20 bytes of hash
         let mut bytes = [0u8; 20];
@@ -44,7 +71,7 @@ impl Address {
     pub fn genesis_address() -> Self {
         // Deterministic genesis address
         let mut bytes = [0u8; 20];
-        let genesis_hash = Keccak256::digest(b"Aequitas Genesis 2026");
+        let genesis_hash = hashing::keccak256(b"Aequitas Genesis 2026");
         bytes.copy_from_slice(&genesis_hash[12..32]);
         Self { bytes }
     }
@@ -60,30 +87,51 @@ impl Address {
     }
     
     /// Compute checksum for the address
-    fn checksum(&self) -> [u8; 4] {
-        let mut hasher = Keccak256::new();
-        hasher.update(&self.bytes);
-        let hash = hasher.finalize();
+    ///
+    /// Script-hash checksums also cover the prefix.
+    fn checksum(&self, kind: AddressKind) -> [u8; 4] {
+        let prefix: &[u8] = match kind {
+            AddressKind::ScriptHash => SCRIPT_ADDRESS_PREFIX.as_bytes(),
+            AddressKind::PublicKeyHash => &[],
+        };
+        let hash = hashing::keccak256_parts(&[prefix, &self.bytes]);
         [hash[0], hash[1], hash[2], hash[3]]
     }
     
     /// Convert to human-readable string format
     /// Format: aeq1<base58_of_bytes_and_checksum>
     pub fn to_string_format(&self) -> String {
+        self.to_string_as(AddressKind::PublicKeyHash)
+    }
+    
+    /// String form for a script hash: aeqs1<base58_of_bytes_and_checksum>
+    pub fn to_script_string(&self) -> String {
+        self.to_string_as(AddressKind::ScriptHash)
+    }
+    
+    fn to_string_as(&self, kind: AddressKind) -> String {
         let mut full_bytes = [0u8; ADDRESS_LENGTH];
         full_bytes[..20].copy_from_slice(&self.bytes);
-        full_bytes[20..24].copy_from_slice(&self.checksum());
+        full_bytes[20..24].copy_from_slice(&self.checksum(kind));
         
-        format!("{}1{}", ADDRESS_PREFIX, bs58::encode(&full_bytes).into_string())
+        format!("{}1{}", kind.prefix(), bs58::encode(&full_bytes).into_string())
     }
     
-    /// Parse from string format
+    /// Parse from string format (either kind)
     pub fn from_string(s: &str) -> Result<Self, AddressError> {
-        if !s.starts_with(&format!("{}1", ADDRESS_PREFIX)) {
+        Self::parse(s).map(|(address, _)| address)
+    }
+    
+    /// Parse from string format, telling which kind the string is
+    pub fn parse(s: &str) -> Result<(Self, AddressKind), AddressError> {
+        let (kind, encoded) = if let Some(rest) = s.strip_prefix(&format!("{}1", SCRIPT_ADDRESS_PREFIX)) {
+            (AddressKind::ScriptHash, rest)
+        } else if let Some(rest) = s.strip_prefix(&format!("{}1", ADDRESS_PREFIX)) {
+            (AddressKind::PublicKeyHash, rest)
+        } else {
             return Err(AddressError::InvalidPrefix);
-        }
+        };
         
-        let encoded = &s[4..]; // Skip "aeq1"
         let decoded = bs58::decode(encoded)
             .into_vec()
             .map_err(|_| AddressError::InvalidEncoding)?;
@@ -98,12 +146,12 @@ impl Address {
         let addr = Self { bytes };
         
         // Verify checksum
-        let expected_checksum = addr.checksum();
+        let expected_checksum = addr.checksum(kind);
         if decoded[20..24] != expected_checksum {
             return Err(AddressError::InvalidChecksum);
         }
         
-        Ok(addr)
+        Ok((addr, kind))
     }
 }
 
@@ -202,6 +250,21 @@ mod tests {
         assert_eq!(address, parsed);
     }
     
+    #[test]
+    fn test_script_address() {
+        let address = Keypair::generate().address();
+        let script = address.to_script_string();
+        assert!(script.starts_with("aeqs1"));
+        assert_eq!(Address::parse(&script).unwrap(), (address.clone(), AddressKind::ScriptHash));
+        assert_eq!(Address::parse(&address.to_string()).unwrap().1, AddressKind::PublicKeyHash);
+        
+        // Swapping the prefix breaks the checksum in both directions
+        let swapped = script.replacen("aeqs1", "aeq1", 1);
+        assert!(matches!(Address::from_string(&swapped), Err(AddressError::InvalidChecksum)));
+        let swapped = address.to_string().replacen("aeq1", "aeqs1", 1);
+        assert!(matches!(Address::from_string(&swapped), Err(AddressError::InvalidChecksum)));
+    }
+    
     #[test]
     fn test_genesis_address() {
         let addr1 = Address::genesis_address();
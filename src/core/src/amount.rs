@@ -0,0 +1,272 @@
+//! Coin amounts
+//!
+//! Amounts are stored as integer base units (1 AEQ = 10^9 units). This module
+//! is the single place that turns them into text and back, so wallet, node
+//! and miner output agree. Parsing never depends on the system locale: both
+//! `.` and `,` are accepted as the decimal separator and `_` may group digits.
+
+use std::fmt;
+use std::str::FromStr;
+use crate::blockchain::MAX_SUPPLY;
+
+/// Number of decimal places in one AEQ
+pub const DECIMALS: u32 = 9;
+
+/// Base units in one AEQ
+pub const COIN: u64 = 1_000_000_000;
+
+/// Display unit for amounts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Unit {
+    /// Whole coins
+    #[default]
+    Aeq,
+
+    /// Thousandths of a coin
+    MilliAeq,
+
+    /// Base units
+    Base,
+}
+
+impl Unit {
+    /// Decimal places of this unit relative to base units
+    pub fn decimals(self) -> u32 {
+        match self {
+            Unit::Aeq => DECIMALS,
+            Unit::MilliAeq => DECIMALS - 3,
+            Unit::Base => 0,
+        }
+    }
+
+    /// Base units in one of this unit
+    pub fn base_units(self) -> u64 {
+        10u64.pow(self.decimals())
+    }
+
+    /// Suffix shown after formatted amounts
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Unit::Aeq => "AEQ",
+            Unit::MilliAeq => "mAEQ",
+            Unit::Base => "units",
+        }
+    }
+}
+
+impl fmt::Display for Unit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.symbol())
+    }
+}
+
+impl FromStr for Unit {
+    type Err = AmountError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "aeq" => Ok(Unit::Aeq),
+            "maeq" | "milliaeq" => Ok(Unit::MilliAeq),
+            "base" | "unit" | "units" => Ok(Unit::Base),
+            other => Err(AmountError::UnknownUnit(other.to_string())),
+        }
+    }
+}
+
+/// How to display amounts
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AmountFormat {
+    /// Unit to display in
+    pub unit: Unit,
+
+    /// Fixed number of decimals; `None` shows full precision and hides a
+    /// zero fraction
+    pub precision: Option<u32>,
+}
+
+impl AmountFormat {
+    /// Format with full precision in the given unit
+    pub fn new(unit: Unit) -> Self {
+        Self { unit, precision: None }
+    }
+
+    /// Show a fixed number of decimals (capped at the unit's precision)
+    pub fn with_precision(mut self, precision: u32) -> Self {
+        self.precision = Some(precision);
+        self
+    }
+
+    /// Format an amount without the unit suffix
+    ///
+    /// Extra digits are truncated, never rounded up, so a displayed balance
+    /// is never more than what is actually spendable.
+    pub fn format_number(&self, amount: u64) -> String {
+        let decimals = self.unit.decimals();
+        let scale = self.unit.base_units();
+        let whole = amount / scale;
+        let frac = amount % scale;
+
+        let shown = match self.precision {
+            Some(p) => p.min(decimals),
+            None if frac == 0 => 0,
+            None => decimals,
+        };
+
+        if shown == 0 {
+            return whole.to_string();
+        }
+
+        let frac = frac / 10u64.pow(decimals - shown);
+        format!("{}.{:0width$}", whole, frac, width = shown as usize)
+    }
+
+    /// Format an amount with the unit suffix
+    pub fn format(&self, amount: u64) -> String {
+        format!("{} {}", self.format_number(amount), self.unit.symbol())
+    }
+}
+
+/// Sum amounts, `None` if the total overflows or exceeds [`MAX_SUPPLY`]
+pub fn checked_sum<I: IntoIterator<Item = u64>>(amounts: I) -> Option<u64> {
+    amounts
+        .into_iter()
+        .try_fold(0u64, |total, amount| total.checked_add(amount))
+        .filter(|&total| total <= MAX_SUPPLY)
+}
+
+/// Format an amount in AEQ with full precision
+pub fn format_amount(amount: u64) -> String {
+    AmountFormat::default().format(amount)
+}
+
+/// Parse an amount in AEQ unless the text names another unit
+pub fn parse_amount(s: &str) -> Result<u64, AmountError> {
+    parse_amount_in(s, Unit::Aeq)
+}
+
+/// Parse an amount, using `default_unit` when the text carries no unit
+///
+/// Accepts `1.5`, `1,5`, `1_000 AEQ`, `250 mAEQ` or `42 units`. Fractions
+/// finer than the unit allows are rejected rather than silently dropped.
+pub fn parse_amount_in(s: &str, default_unit: Unit) -> Result<u64, AmountError> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| c.is_alphabetic())
+        .unwrap_or(s.len());
+    let (number, suffix) = s.split_at(split);
+
+    let unit = if suffix.trim().is_empty() {
+        default_unit
+    } else {
+        suffix.parse()?
+    };
+
+    let number: String = number.trim().chars().filter(|&c| c != '_').collect();
+    if number.is_empty() {
+        return Err(AmountError::Invalid(s.to_string()));
+    }
+
+    let mut parts = number.splitn(2, ['.', ',']);
+    let whole = parts.next().unwrap_or("");
+    let frac = parts.next().unwrap_or("");
+
+    let all_digits = |p: &str| p.chars().all(|c| c.is_ascii_digit());
+    if (whole.is_empty() && frac.is_empty()) || !all_digits(whole) || !all_digits(frac) {
+        return Err(AmountError::Invalid(s.to_string()));
+    }
+
+    let decimals = unit.decimals() as usize;
+    if frac.len() > decimals {
+        return Err(AmountError::TooPrecise(unit));
+    }
+
+    let whole: u64 = if whole.is_empty() {
+        0
+    } else {
+        whole.parse().map_err(|_| AmountError::Overflow)?
+    };
+    let frac: u64 = if frac.is_empty() {
+        0
+    } else {
+        let padded = format!("{:0<width$}", frac, width = decimals);
+        padded.parse().map_err(|_| AmountError::Overflow)?
+    };
+
+    whole
+        .checked_mul(unit.base_units())
+        .and_then(|v| v.checked_add(frac))
+        .ok_or(AmountError::Overflow)
+}
+
+/// Amount parsing errors
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum AmountError {
+    #[error("Invalid amount: {0}")]
+    Invalid(String),
+
+    #[error("Unknown unit: {0}")]
+    UnknownUnit(String),
+
+    #[error("Too many decimals for {0}")]
+    TooPrecise(Unit),
+
+    #[error("Amount too large")]
+    Overflow,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_units() {
+        assert_eq!(format_amount(0), "0 AEQ");
+        assert_eq!(format_amount(50 * COIN), "50 AEQ");
+        assert_eq!(format_amount(1_500_000_000), "1.500000000 AEQ");
+        assert_eq!(AmountFormat::new(Unit::MilliAeq).format(1_500_000_000), "1500 mAEQ");
+        assert_eq!(AmountFormat::new(Unit::MilliAeq).format(1_234_567), "1.234567 mAEQ");
+        assert_eq!(AmountFormat::new(Unit::Base).format(42), "42 units");
+    }
+
+    #[test]
+    fn test_format_precision() {
+        let fmt = AmountFormat::new(Unit::Aeq).with_precision(2);
+        assert_eq!(fmt.format(1_999_999_999), "1.99 AEQ");
+        assert_eq!(fmt.format(COIN), "1.00 AEQ");
+        assert_eq!(fmt.with_precision(0).format_number(1_999_999_999), "1");
+        assert_eq!(fmt.with_precision(20).format_number(1), "0.000000001");
+    }
+
+    #[test]
+    fn test_parse_amount() {
+        assert_eq!(parse_amount("1 AEQ").unwrap(), COIN);
+        assert_eq!(parse_amount("1.5").unwrap(), 1_500_000_000);
+        assert_eq!(parse_amount("1,5").unwrap(), 1_500_000_000);
+        assert_eq!(parse_amount(".5aeq").unwrap(), 500_000_000);
+        assert_eq!(parse_amount("1_000").unwrap(), 1_000 * COIN);
+        assert_eq!(parse_amount("0.000000001").unwrap(), 1);
+        assert_eq!(parse_amount("250 mAEQ").unwrap(), 250_000_000);
+        assert_eq!(parse_amount_in("42", Unit::Base).unwrap(), 42);
+    }
+
+    #[test]
+    fn test_checked_sum() {
+        assert_eq!(checked_sum([]), Some(0));
+        assert_eq!(checked_sum([COIN, 2 * COIN]), Some(3 * COIN));
+        assert_eq!(checked_sum([MAX_SUPPLY]), Some(MAX_SUPPLY));
+        assert_eq!(checked_sum([MAX_SUPPLY, 1]), None);
+        assert_eq!(checked_sum([u64::MAX, 1]), None);
+    }
+
+    #[test]
+    fn test_parse_rejects() {
+        assert_eq!(parse_amount("0.0000000001"), Err(AmountError::TooPrecise(Unit::Aeq)));
+        assert_eq!(parse_amount("1.5 units"), Err(AmountError::TooPrecise(Unit::Base)));
+        assert_eq!(parse_amount("99999999999999999999"), Err(AmountError::Overflow));
+        assert!(matches!(parse_amount("1.2.3"), Err(AmountError::Invalid(_))));
+        assert!(matches!(parse_amount("-1"), Err(AmountError::Invalid(_))));
+        assert!(matches!(parse_amount(""), Err(AmountError::Invalid(_))));
+        assert!(matches!(parse_amount("5 BTC"), Err(AmountError::UnknownUnit(_))));
+    }
+}
@@ -3,11 +3,17 @@
 //! Defines the Block and BlockHeader structures used throughout the network.
 
 use crate::address::Address;
+use crate::codec::ConsensusEncode;
+use crate::hashing;
 use crate::merkle::compute_merkle_root;
-use crate::transaction::Transaction;
+use crate::target::{compact_to_target, difficulty_to_compact, hash_meets_target, target_work};
+use crate::transaction::{verify_batch, Transaction};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use sha3::{Digest, Keccak256};
+use std::collections::HashMap;
+
+/// Size of [`BlockHeader::to_wire_bytes`]
+pub const HEADER_WIRE_SIZE: usize = 140;
 
 /// Block header containing metadata and proof-of-work data
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -27,6 +33,9 @@ pub struct BlockHeader {
     /// Current difficulty target
     pub difficulty: u64,
 
+    /// Compact encoding of the 256-bit proof-of-work target
+    pub bits: u32,
+
     /// Nonce for proof-of-work
     pub nonce: u64,
 
@@ -46,6 +55,7 @@ impl BlockHeader {
             merkle_root,
             timestamp: Utc::now(),
             difficulty,
+            bits: difficulty_to_compact(difficulty),
             nonce: 0,
             height,
             extra_data: [0u8; 32],
@@ -54,18 +64,89 @@ impl BlockHeader {
 
     /// Compute the hash of this header
     pub fn hash(&self) -> [u8; 32] {
-        let serialized = bincode::serialize(self).expect("Failed to serialize header");
-        let mut hasher = Keccak256::new();
-        hasher.update(&serialized);
-        hasher.finalize().into()
+        hashing::keccak256_with(|buf| {
+            bincode::serialize_into(buf, self).expect("Failed to serialize header")
+        })
+    }
+
+    /// Fixed-size encoding for header sync and mining notifications
+    ///
+    /// Fields in declaration order, integers little-endian, the timestamp as
+    /// seconds (`i64`) then nanoseconds (`u32`). It carries every field, so
+    /// decoding gives back a header with the same hash.
+    pub fn to_wire_bytes(&self) -> [u8; HEADER_WIRE_SIZE] {
+        let mut out = [0u8; HEADER_WIRE_SIZE];
+        let mut pos = 0;
+        let mut put = |bytes: &[u8]| {
+            out[pos..pos + bytes.len()].copy_from_slice(bytes);
+            pos += bytes.len();
+        };
+        put(&self.version.to_le_bytes());
+        put(&self.prev_hash);
+        put(&self.merkle_root);
+        put(&self.timestamp.timestamp().to_le_bytes());
+        put(&self.timestamp.timestamp_subsec_nanos().to_le_bytes());
+        put(&self.difficulty.to_le_bytes());
+        put(&self.bits.to_le_bytes());
+        put(&self.nonce.to_le_bytes());
+        put(&self.height.to_le_bytes());
+        put(&self.extra_data);
+        out
+    }
+
+    /// Decode [`BlockHeader::to_wire_bytes`]
+    pub fn from_wire_bytes(bytes: &[u8; HEADER_WIRE_SIZE]) -> Result<Self, BlockError> {
+        let mut pos = 0;
+        let mut take = move |len: usize| {
+            let field = &bytes[pos..pos + len];
+            pos += len;
+            field
+        };
+        let version = u32::from_le_bytes(take(4).try_into().unwrap());
+        let prev_hash = take(32).try_into().unwrap();
+        let merkle_root = take(32).try_into().unwrap();
+        let secs = i64::from_le_bytes(take(8).try_into().unwrap());
+        let nanos = u32::from_le_bytes(take(4).try_into().unwrap());
+        let timestamp = DateTime::<Utc>::from_timestamp(secs, nanos).ok_or(BlockError::InvalidTimestamp)?;
+
+        Ok(Self {
+            version,
+            prev_hash,
+            merkle_root,
+            timestamp,
+            difficulty: u64::from_le_bytes(take(8).try_into().unwrap()),
+            bits: u32::from_le_bytes(take(4).try_into().unwrap()),
+            nonce: u64::from_le_bytes(take(8).try_into().unwrap()),
+            height: u64::from_le_bytes(take(8).try_into().unwrap()),
+            extra_data: take(32).try_into().unwrap(),
+        })
+    }
+
+    /// Set the difficulty and the matching compact target
+    pub fn set_difficulty(&mut self, difficulty: u64) {
+        self.difficulty = difficulty;
+        self.bits = difficulty_to_compact(difficulty);
+    }
+
+    /// Check that the compact target matches the difficulty
+    pub fn bits_match_difficulty(&self) -> bool {
+        self.bits == difficulty_to_compact(self.difficulty)
+    }
+
+    /// Get the 256-bit target (zero, never met, for an invalid encoding)
+    pub fn target(&self) -> [u8; 32] {
+        compact_to_target(self.bits).unwrap_or([0u8; 32])
     }
 
     /// Check if the header hash meets the difficulty target
     pub fn meets_difficulty(&self) -> bool {
-        let hash = self.hash();
-        let hash_value = u64::from_be_bytes(hash[0..8].try_into().unwrap());
-        let target = u64::MAX / self.difficulty;
-        hash_value <= target
+        hash_meets_target(&self.hash(), &self.target())
+    }
+
+    /// Expected number of hashes needed to find this block
+    /// Work = 2^256 / (target + 1)
+    pub fn work(&self) -> u128 {
+        target_work(&self.target())
     }
 }
 
@@ -118,8 +199,74 @@ impl Block {
         hex::encode(self.hash())
     }
 
+    /// UTXO-set commitment carried by the coinbase, if any
+    ///
+    /// It commits to the UTXO set the block is applied on (see
+    /// [`Blockchain::utxo_commitment`](crate::Blockchain::utxo_commitment)),
+    /// and the header commits to the coinbase through the merkle root.
+    pub fn utxo_commitment(&self) -> Option<[u8; 32]> {
+        let memo = &self.transactions.first()?.memo;
+        let tail = memo.len().checked_sub(UTXO_COMMITMENT_MAGIC.len() + 32)?;
+        let (magic, commitment) = memo[tail..].split_at(UTXO_COMMITMENT_MAGIC.len());
+        (magic == UTXO_COMMITMENT_MAGIC.as_slice()).then(|| commitment.try_into().unwrap())
+    }
+
+    /// Append a UTXO-set commitment to the coinbase memo and update the
+    /// merkle root
+    pub fn commit_utxo_set(&mut self, commitment: [u8; 32]) {
+        if let Some(coinbase) = self.transactions.first_mut() {
+            coinbase.memo.extend_from_slice(UTXO_COMMITMENT_MAGIC);
+            coinbase.memo.extend_from_slice(&commitment);
+            self.header.merkle_root = compute_merkle_root(&self.transactions);
+        }
+    }
+
+    /// Consensus-encoded header plus the [`size`](Transaction::size) of
+    /// every transaction
+    ///
+    /// The figure templates and RPC report, consistent with mempool sizes.
+    pub fn weight(&self) -> usize {
+        self.header.consensus_encode().len() + self.transactions.iter().map(Transaction::size).sum::<usize>()
+    }
+
+    /// Get the serialized size of the block in bytes, checked against
+    /// [`MAX_BLOCK_SIZE`]
+    pub fn size(&self) -> usize {
+        bincode::serialized_size(self).map(|s| s as usize).unwrap_or(usize::MAX)
+    }
+
+    /// Validate the block timestamp against the median-time-past of the
+    /// previous blocks and the allowed drift into the future
+    pub fn validate_timestamp(&self, median_time_past: i64, now: i64) -> Result<(), BlockError> {
+        let timestamp = self.header.timestamp.timestamp();
+
+        if timestamp <= median_time_past {
+            return Err(BlockError::TimestampTooOld);
+        }
+
+        if timestamp > now + MAX_FUTURE_BLOCK_TIME {
+            return Err(BlockError::TimestampTooFarInFuture);
+        }
+
+        Ok(())
+    }
+
     /// Validate the block structure
     pub fn validate(&self) -> Result<(), BlockError> {
+        self.validate_with(true)
+    }
+
+    /// Validate the block structure, optionally skipping signature checks
+    pub fn validate_with(&self, verify_signatures: bool) -> Result<(), BlockError> {
+        // Check block limits
+        if self.transactions.len() > MAX_BLOCK_TXS {
+            return Err(BlockError::TooManyTransactions);
+        }
+        if self.size() > MAX_BLOCK_SIZE {
+            return Err(BlockError::BlockTooLarge);
+        }
+        self.validate_ordering()?;
+
         // Check merkle root
         let computed_merkle = compute_merkle_root(&self.transactions);
         if computed_merkle != self.header.merkle_root {
@@ -127,17 +274,63 @@ impl Block {
         }
 
         // Check proof of work
+        if !self.header.bits_match_difficulty() {
+            return Err(BlockError::InvalidBits);
+        }
         if !self.header.meets_difficulty() {
             return Err(BlockError::InsufficientProofOfWork);
         }
 
         // Validate transactions
         for tx in &self.transactions {
-            tx.validate()?;
+            tx.validate_structure()?;
+        }
+        if verify_signatures {
+            self.verify_signatures()?;
         }
 
         Ok(())
     }
+
+    /// Check that no transaction appears twice and that none spends an
+    /// output of itself or of a transaction later in the block
+    ///
+    /// A duplicate would overwrite the outputs of the first copy.
+    pub fn validate_ordering(&self) -> Result<(), BlockError> {
+        let txids: Vec<[u8; 32]> = self.transactions.iter().map(Transaction::txid).collect();
+        let mut position = HashMap::with_capacity(txids.len());
+        for (index, txid) in txids.iter().enumerate() {
+            if position.insert(*txid, index).is_some() {
+                return Err(BlockError::DuplicateTransaction(index));
+            }
+        }
+
+        for (index, tx) in self.transactions.iter().enumerate() {
+            let spends_later = tx.inputs.iter().any(|input| {
+                position.get(&input.prev_tx_hash).is_some_and(|&created| created >= index)
+            });
+            if spends_later {
+                return Err(BlockError::OutOfOrder(index));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Verify the signatures of every transaction, as one batch
+    pub fn verify_signatures(&self) -> Result<(), BlockError> {
+        let signed: Vec<&Transaction> = self.transactions.iter()
+            .filter(|tx| tx.requires_signatures())
+            .collect();
+        let messages: Vec<Vec<u8>> = signed.iter().map(|tx| tx.signing_message()).collect();
+
+        let mut checks = Vec::new();
+        for (tx, message) in signed.iter().zip(&messages) {
+            checks.extend(tx.signature_checks(message)?);
+        }
+        verify_batch(&checks)?;
+        Ok(())
+    }
 }
 
 use crate::address::Address;
@@ -148,6 +341,24 @@ pub const GENESIS_REWARD: u64 = 50_000_000_000; // 50 AEQ with 9 decimals
 /// Initial difficulty target
 pub const INITIAL_DIFFICULTY: u64 = 1_000_000;
 
+/// Number of previous blocks used to compute median-time-past
+pub const MEDIAN_TIME_SPAN: usize = 11;
+
+/// Maximum drift of a block timestamp into the future (seconds)
+pub const MAX_FUTURE_BLOCK_TIME: i64 = 2 * 60 * 60; // 2 hours
+
+/// Maximum serialized block size in bytes
+pub const MAX_BLOCK_SIZE: usize = 2 * 1024 * 1024; // 2 MB
+
+/// Maximum number of transactions in a block (including coinbase)
+pub const MAX_BLOCK_TXS: usize = 10_000;
+
+/// Marks the UTXO-set commitment at the end of a coinbase memo
+///
+/// Header `extra_data` already carries the miner identity commitment, so the
+/// UTXO commitment rides in the coinbase.
+pub const UTXO_COMMITMENT_MAGIC: &[u8; 4] = b"AEQu";
+
 /// Block validation errors
 #[derive(Debug, thiserror::Error)]
 pub enum BlockError {
@@ -157,6 +368,9 @@ pub enum BlockError {
     #[error("Insufficient proof of work")]
     InsufficientProofOfWork,
 
+    #[error("Compact target does not match difficulty")]
+    InvalidBits,
+
     #[error("Invalid transaction: {0}")]
     InvalidTransaction(#[from] crate::transaction::TxError),
 
@@ -165,6 +379,24 @@ pub enum BlockError {
 
     #[error("Invalid timestamp")]
     InvalidTimestamp,
+
+    #[error("Block timestamp is not after median-time-past")]
+    TimestampTooOld,
+
+    #[error("Block timestamp is too far in the future")]
+    TimestampTooFarInFuture,
+
+    #[error("Block exceeds maximum size ({} bytes)", MAX_BLOCK_SIZE)]
+    BlockTooLarge,
+
+    #[error("Block exceeds maximum transaction count ({})", MAX_BLOCK_TXS)]
+    TooManyTransactions,
+
+    #[error("Transaction {0} duplicates an earlier transaction of the block")]
+    DuplicateTransaction(usize),
+
+    #[error("Transaction {0} spends an output not created earlier in the block")]
+    OutOfOrder(usize),
 }
 
 #[cfg(test)]
@@ -185,5 +417,110 @@ mod tests {
         let hash1 = genesis.hash();
         let hash2 = genesis.hash();
         assert_eq!(hash1, hash2);
+
+        // The scratch buffer hashes the same bytes as a fresh serialization
+        let serialized = bincode::serialize(&genesis.header).unwrap();
+        assert_eq!(hash1, crate::hashing::keccak256(&serialized));
+    }
+
+    #[test]
+    fn test_header_wire_bytes() {
+        let mut header = Block::genesis().header;
+        header.nonce = 0x0102_0304_0506_0708;
+        header.extra_data = [9u8; 32];
+
+        let bytes = header.to_wire_bytes();
+        assert_eq!(&bytes[..4], &header.version.to_le_bytes());
+        assert_eq!(&bytes[HEADER_WIRE_SIZE - 32..], &[9u8; 32]);
+
+        let decoded = BlockHeader::from_wire_bytes(&bytes).unwrap();
+        assert_eq!(decoded, header);
+        assert_eq!(decoded.hash(), header.hash());
+
+        let mut bad_nanos = bytes;
+        bad_nanos[76..80].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(BlockHeader::from_wire_bytes(&bad_nanos), Err(BlockError::InvalidTimestamp)));
+    }
+
+    #[test]
+    fn test_block_work() {
+        let mut header = Block::genesis().header;
+        let low = header.work();
+        header.set_difficulty(header.difficulty * 2);
+        let high = header.work();
+
+        assert!(low > 0);
+        assert!(high > low);
+    }
+
+    #[test]
+    fn test_block_weight() {
+        let block = Block::genesis();
+        let coinbase = &block.transactions[0];
+        assert_eq!(block.weight(), block.header.consensus_encode().len() + coinbase.size());
+        assert_eq!(coinbase.size(), coinbase.encode().len());
+    }
+
+    #[test]
+    fn test_header_target() {
+        let mut header = Block::genesis().header;
+        assert!(header.bits_match_difficulty());
+        assert_eq!(header.target(), compact_to_target(header.bits).unwrap());
+
+        header.difficulty += 1_000_000;
+        assert!(!header.bits_match_difficulty());
+
+        let mut block = Block::genesis();
+        block.header.bits = 0x0480_0001;
+        assert_eq!(block.header.target(), [0u8; 32]);
+        assert!(matches!(block.validate(), Err(BlockError::InvalidBits)));
+    }
+
+    #[test]
+    fn test_block_limits() {
+        let genesis = Block::genesis();
+        assert!(genesis.size() <= MAX_BLOCK_SIZE);
+
+        let coinbase = genesis.transactions[0].clone();
+        let block = Block::new([0u8; 32], 1, INITIAL_DIFFICULTY, vec![coinbase; MAX_BLOCK_TXS + 1]);
+        assert!(matches!(block.validate(), Err(BlockError::TooManyTransactions)));
+    }
+
+    #[test]
+    fn test_transaction_order() {
+        let coinbase = Block::genesis().transactions[0].clone();
+        let spend = |prev: &Transaction| Transaction::new_transfer(
+            vec![crate::transaction::TxInput::new(prev.txid(), 0)],
+            vec![crate::transaction::TxOutput::new(Address::genesis_address(), 1_000)],
+        );
+        let first = spend(&coinbase);
+        let second = spend(&first);
+
+        let block = |txs: Vec<Transaction>| Block::new([0u8; 32], 1, INITIAL_DIFFICULTY, txs);
+        assert!(block(vec![coinbase.clone(), first.clone(), second.clone()]).validate_ordering().is_ok());
+        assert!(matches!(
+            block(vec![coinbase.clone(), first.clone(), first.clone()]).validate(),
+            Err(BlockError::DuplicateTransaction(2))
+        ));
+        assert!(matches!(
+            block(vec![coinbase, second, first]).validate(),
+            Err(BlockError::OutOfOrder(1))
+        ));
+    }
+
+    #[test]
+    fn test_timestamp_validation() {
+        let genesis = Block::genesis();
+        let ts = genesis.header.timestamp.timestamp();
+
+        assert!(genesis.validate_timestamp(ts - 1, ts).is_ok());
+        assert!(matches!(
+            genesis.validate_timestamp(ts, ts),
+            Err(BlockError::TimestampTooOld)
+        ));
+        assert!(matches!(
+            genesis.validate_timestamp(ts - 1, ts - MAX_FUTURE_BLOCK_TIME - 1),
+            Err(BlockError::TimestampTooFarInFuture)
+        ));
     }
 }
@@ -3,10 +3,22 @@
 //! Manages the chain of blocks and UTXO set.
 
 use crate::address::Address;
-use crate::block::{Block, BlockError, GENESIS_REWARD};
-use crate::difficulty::{Difficulty, DIFFICULTY_AVERAGING_WINDOW, TARGET_BLOCK_TIME};
-use crate::transaction::{Transaction, TxError, TxOutput};
-use std::collections::HashMap;
+use crate::amount::{checked_sum, format_amount};
+use crate::block::{Block, BlockError, BlockHeader, GENESIS_REWARD, MEDIAN_TIME_SPAN};
+use crate::checkpoints::{CheckpointError, Checkpoints};
+use crate::difficulty::{DIFFICULTY_AVERAGING_WINDOW, TARGET_BLOCK_TIME};
+use crate::governance::{Governance, GovernanceError, TreasurySpendPayload};
+use crate::params::ChainParams;
+use crate::solidarity::{EpochTally, HashrateTier, MinerRegistry, ProportionalRewards, REGISTRY_EPOCH, REGISTRY_WINDOW};
+use crate::snapshot::{utxo_commitment, SnapshotError, UtxoSnapshot, SNAPSHOT_RECENT_BLOCKS, SNAPSHOT_VERSION};
+use crate::storage::{
+    self, RecordReader, RECORD_BLOCK, RECORD_GOVERNANCE, RECORD_INVALIDATED, RECORD_SOLIDARITY, RECORD_STATE, RECORD_UNDO,
+    RECORD_VERSION_BITS,
+};
+use crate::transaction::{Transaction, TxError, TxOutput, TxType};
+use crate::utxo::{UtxoSet, UtxoStats};
+use crate::versionbits::{deployment_state, Deployment, DeploymentInfo, DeploymentState, PeriodSignals, VERSION_BITS_TOP_BITS};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 /// Halving interval in blocks (~2 years at 30 second blocks)
 pub const HALVING_INTERVAL: u64 = 2_100_000;
@@ -20,6 +32,9 @@ pub const TREASURY_PERCENTAGE: u64 = 1;
 /// Solidarity (Small Miners) percentage (1%)
 pub const SOLIDARITY_PERCENTAGE: u64 = 1;
 
+/// Most blocks a single `blocks_in_range` call may span
+pub const MAX_BLOCK_RANGE: u64 = 1000;
+
 /// UTXO identifier (transaction hash + output index)
 #[derive(Clone, Debug, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
 pub struct UtxoId {
@@ -36,6 +51,62 @@ impl UtxoId {
     }
 }
 
+/// Read access to unspent outputs
+pub trait UtxoView {
+    /// Unspent output with this id, if any
+    fn utxo(&self, id: &UtxoId) -> Option<&TxOutput>;
+}
+
+impl UtxoView for HashMap<UtxoId, TxOutput> {
+    fn utxo(&self, id: &UtxoId) -> Option<&TxOutput> {
+        self.get(id)
+    }
+}
+
+impl UtxoView for Blockchain {
+    fn utxo(&self, id: &UtxoId) -> Option<&TxOutput> {
+        self.utxos.get(id)
+    }
+}
+
+/// Direction of a transaction relative to an address
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum TxDirection {
+    /// Net amount received by the address
+    Incoming,
+
+    /// Net amount sent by the address
+    Outgoing,
+}
+
+/// Entry in an address transaction history
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct TxRecord {
+    /// Hash of the transaction
+    pub tx_hash: [u8; 32],
+
+    /// Height of the block containing the transaction
+    pub height: u64,
+
+    /// Whether the address gained or lost funds
+    pub direction: TxDirection,
+
+    /// Net amount moved (always positive)
+    pub amount: u64,
+}
+
+/// What connecting a block changed, so it can be disconnected again
+///
+/// Outputs the block created are found in the block itself.
+#[derive(Clone, Debug, Default, serde::Serialize, serde::Deserialize)]
+struct BlockUndo {
+    /// Outputs the block spent, restored on disconnect
+    spent: Vec<(UtxoId, TxOutput)>,
+
+    /// Governance state before the block, if the block changed it
+    governance: Option<Governance>,
+}
+
 /// The main blockchain structure
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct Blockchain {
@@ -43,21 +114,144 @@ pub struct Blockchain {
     height_index: HashMap<u64, [u8; 32]>,
     tip: [u8; 32],
     height: u64,
-    utxos: HashMap<UtxoId, TxOutput>,
+    utxos: UtxoSet,
+    block_times: Vec<(u64, i64)>,
+    treasury_address: Address,
+    current_difficulty: u64,
+    #[serde(default)]
+    address_index: Option<HashMap<Address, Vec<TxRecord>>>,
+    #[serde(default)]
+    chain_work: HashMap<[u8; 32], u128>,
+    #[serde(skip, default = "Checkpoints::mainnet")]
+    checkpoints: Checkpoints,
+    #[serde(skip)]
+    governance: Governance,
+    #[serde(skip)]
+    params: ChainParams,
+    /// Cumulative coinbase issuance at each height of the main chain
+    #[serde(skip)]
+    emission: HashMap<u64, u64>,
+    /// Undo data of each block, by block hash
+    #[serde(skip)]
+    undo: HashMap<[u8; 32], BlockUndo>,
+    /// Blocks disconnected by `invalidate_block`, lowest first, by the
+    /// hash that was invalidated, which is refused if offered again
+    #[serde(skip)]
+    invalidated: HashMap<[u8; 32], Vec<Block>>,
+    /// Solidarity registry tallies of the latest completed epochs, by
+    /// first height
+    #[serde(skip)]
+    solidarity_epochs: BTreeMap<u64, EpochTally>,
+    /// Version-bits signal counts of every period, by first height
+    #[serde(skip)]
+    version_bits: BTreeMap<u64, PeriodSignals>,
+}
+
+/// Chain state persisted after the blocks in a chain file
+#[derive(serde::Serialize)]
+struct ChainStateRef<'a> {
+    tip: &'a [u8; 32],
+    height: u64,
+    utxos: &'a UtxoSet,
+    block_times: &'a Vec<(u64, i64)>,
+    treasury_address: &'a Address,
+    current_difficulty: u64,
+    address_index: &'a Option<HashMap<Address, Vec<TxRecord>>>,
+    chain_work: &'a HashMap<[u8; 32], u128>,
+}
+
+/// Owned counterpart of [`ChainStateRef`]
+#[derive(serde::Deserialize)]
+struct ChainState {
+    tip: [u8; 32],
+    height: u64,
+    utxos: UtxoSet,
     block_times: Vec<(u64, i64)>,
     treasury_address: Address,
     current_difficulty: u64,
+    address_index: Option<HashMap<Address, Vec<TxRecord>>>,
+    chain_work: HashMap<[u8; 32], u128>,
+}
+
+/// How a chain file was loaded
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LoadStatus {
+    /// Every block and the state passed their checksums
+    Clean,
+
+    /// Corruption was found; the chain was rebuilt up to the last good block
+    Recovered {
+        /// What was wrong with the file
+        reason: String,
+    },
+}
+
+/// How much stored history is validated again when a chain is loaded
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CheckLevel {
+    /// Trust the stored blocks and state once their checksums pass
+    Quick,
+
+    /// Recompute every block's difficulty from the block times before it
+    /// and check its proof of work
+    #[default]
+    Difficulty,
+
+    /// Also replay every block from genesis and compare the UTXO set
+    Full,
+}
+
+impl CheckLevel {
+    /// Name accepted on the command line
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CheckLevel::Quick => "quick",
+            CheckLevel::Difficulty => "difficulty",
+            CheckLevel::Full => "full",
+        }
+    }
+}
+
+impl std::fmt::Display for CheckLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for CheckLevel {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "0" | "quick" => Ok(CheckLevel::Quick),
+            "1" | "difficulty" => Ok(CheckLevel::Difficulty),
+            "2" | "full" => Ok(CheckLevel::Full),
+            other => Err(format!("unknown check level '{}' (quick, difficulty or full)", other)),
+        }
+    }
 }
 
 impl Blockchain {
     /// Create a new blockchain with genesis block
     pub fn new() -> Self {
-        let genesis = Block::genesis();
+        Self::from_genesis(Block::genesis())
+    }
+
+    /// Create a new blockchain for a network, starting at its genesis block
+    pub fn with_params(params: ChainParams) -> Self {
+        let mut chain = Self::from_genesis(params.genesis());
+        chain.checkpoints = params.checkpoints();
+        chain.params = params;
+        chain
+    }
+
+    /// Create a blockchain containing only the given genesis block
+    fn from_genesis(genesis: Block) -> Self {
         let genesis_hash = genesis.hash();
 
         let mut blocks = HashMap::new();
         let mut height_index = HashMap::new();
-        let mut utxos = HashMap::new();
+        let mut utxos = UtxoSet::new();
 
         // Add genesis block
         blocks.insert(genesis_hash, genesis.clone());
@@ -71,6 +265,18 @@ impl Blockchain {
 
         let block_times = vec![(0, genesis.header.timestamp.timestamp())];
 
+        let mut chain_work = HashMap::new();
+        chain_work.insert(genesis_hash, genesis.header.work());
+
+        let mut emission = HashMap::new();
+        emission.insert(0, Self::coinbase_issuance(&genesis));
+
+        let mut undo = HashMap::new();
+        undo.insert(genesis_hash, BlockUndo::default());
+
+        let mut signals = PeriodSignals::default();
+        signals.record(genesis.header.version);
+
         Self {
             blocks,
             height_index,
@@ -80,23 +286,408 @@ impl Blockchain {
             block_times,
             treasury_address: Address::genesis_address(),
             current_difficulty: genesis.header.difficulty,
+            address_index: None,
+            chain_work,
+            checkpoints: Checkpoints::mainnet(),
+            governance: Governance::new(),
+            params: ChainParams::default(),
+            emission,
+            undo,
+            invalidated: HashMap::new(),
+            solidarity_epochs: BTreeMap::new(),
+            version_bits: BTreeMap::from([(0, signals)]),
         }
     }
 
     /// Load from file
     pub fn load(path: &std::path::Path) -> anyhow::Result<Self> {
+        Self::load_verified(path).map(|(chain, _)| chain)
+    }
+
+    /// Load from file, verifying checksums and recovering from corruption
+    ///
+    /// When a block or the state is damaged, the chain is rebuilt by
+    /// replaying the blocks before the damage; the rest must be re-synced.
+    /// If the file is missing or unreadable, or the rolling backup reaches a
+    /// greater height than what survived, the backup is used instead.
+    pub fn load_verified(path: &std::path::Path) -> anyhow::Result<(Self, LoadStatus)> {
+        let primary = Self::load_file(path);
+        if matches!(primary, Ok((_, LoadStatus::Clean))) {
+            return primary;
+        }
+
+        let reason = match &primary {
+            Ok((_, LoadStatus::Recovered { reason })) => reason.clone(),
+            Ok((_, LoadStatus::Clean)) => unreachable!("clean loads returned above"),
+            Err(e) => e.to_string(),
+        };
+
+        let backup_path = storage::backup_path(path);
+        let backup = backup_path.exists().then(|| Self::load_file(&backup_path));
+
+        match (primary, backup) {
+            (Ok((chain, status)), Some(Ok((backup, _)))) if backup.height <= chain.height => Ok((chain, status)),
+            (_, Some(Ok((backup, _)))) => {
+                log::warn!(
+                    "Chain file {} is unusable ({}), restored from {} at height {}",
+                    path.display(),
+                    reason,
+                    backup_path.display(),
+                    backup.height
+                );
+                let reason = format!("{}; restored from backup at height {}", reason, backup.height);
+                Ok((backup, LoadStatus::Recovered { reason }))
+            }
+            (primary, _) => primary,
+        }
+    }
+
+    /// Load and verify a single chain file
+    fn load_file(path: &std::path::Path) -> anyhow::Result<(Self, LoadStatus)> {
         let content = std::fs::read(path)?;
-        let chain: Self = bincode::deserialize(&content)?;
+
+        if !storage::is_chain_file(&content) {
+            // Files written before checksums were added also predate the
+            // lock height and no longer match the transaction layout
+            anyhow::bail!("{} predates chain file checksums and must be resynced", path.display());
+        }
+
+        let mut blocks: Vec<Block> = Vec::new();
+        let mut state: Option<ChainState> = None;
+        let mut governance: Option<Governance> = None;
+        let mut undo: Option<HashMap<[u8; 32], BlockUndo>> = None;
+        let mut invalidated: Option<HashMap<[u8; 32], Vec<Block>>> = None;
+        let mut solidarity: Option<BTreeMap<u64, EpochTally>> = None;
+        let mut version_bits: Option<BTreeMap<u64, PeriodSignals>> = None;
+        let mut damage: Option<String> = None;
+
+        let records = RecordReader::new(&content)?;
+        let version = records.version();
+        for record in records {
+            match record {
+                Ok((RECORD_BLOCK, payload)) if state.is_none() => {
+                    let block = match storage::decode_block(payload, version) {
+                        Ok(block) => block,
+                        Err(e) => {
+                            damage = Some(format!("undecodable block: {}", e));
+                            break;
+                        }
+                    };
+                    if let Some(prev) = blocks.last() {
+                        if block.header.prev_hash != prev.hash()
+                            || block.header.height != prev.header.height + 1
+                        {
+                            damage = Some(format!("block {} does not link to its parent", block.header.height));
+                            break;
+                        }
+                    }
+                    blocks.push(block);
+                }
+                Ok((RECORD_STATE, payload)) if state.is_none() => {
+                    match bincode::deserialize::<ChainState>(payload) {
+                        Ok(decoded) => state = Some(decoded),
+                        Err(e) => {
+                            damage = Some(format!("undecodable state: {}", e));
+                            break;
+                        }
+                    }
+                }
+                Ok((RECORD_GOVERNANCE, payload)) if state.is_some() && governance.is_none() => {
+                    match bincode::deserialize::<Governance>(payload) {
+                        Ok(decoded) => governance = Some(decoded),
+                        Err(e) => {
+                            damage = Some(format!("undecodable governance state: {}", e));
+                            break;
+                        }
+                    }
+                }
+                Ok((RECORD_UNDO, payload)) if state.is_some() && undo.is_none() => {
+                    match bincode::deserialize(payload) {
+                        Ok(decoded) => undo = Some(decoded),
+                        Err(e) => {
+                            damage = Some(format!("undecodable undo data: {}", e));
+                            break;
+                        }
+                    }
+                }
+                Ok((RECORD_INVALIDATED, payload)) if state.is_some() && invalidated.is_none() => {
+                    match storage::decode_invalidated(payload, version) {
+                        Ok(decoded) => invalidated = Some(decoded),
+                        Err(e) => {
+                            damage = Some(format!("undecodable invalidated blocks: {}", e));
+                            break;
+                        }
+                    }
+                }
+                Ok((RECORD_SOLIDARITY, payload)) if state.is_some() && solidarity.is_none() => {
+                    match bincode::deserialize(payload) {
+                        Ok(decoded) => solidarity = Some(decoded),
+                        Err(e) => {
+                            damage = Some(format!("undecodable solidarity tallies: {}", e));
+                            break;
+                        }
+                    }
+                }
+                Ok((RECORD_VERSION_BITS, payload)) if state.is_some() && version_bits.is_none() => {
+                    match bincode::deserialize(payload) {
+                        Ok(decoded) => version_bits = Some(decoded),
+                        Err(e) => {
+                            damage = Some(format!("undecodable version-bits counts: {}", e));
+                            break;
+                        }
+                    }
+                }
+                Ok((kind, _)) => {
+                    damage = Some(format!("unexpected record kind {}", kind));
+                    break;
+                }
+                Err(e) => {
+                    damage = Some(e.to_string());
+                    break;
+                }
+            }
+        }
+
+        if damage.is_none() {
+            match (&state, blocks.last()) {
+                (None, _) => damage = Some("missing chain state".to_string()),
+                (Some(s), Some(tip)) if s.tip == tip.hash() && s.height == tip.header.height => {}
+                _ => damage = Some("chain state does not match the stored blocks".to_string()),
+            }
+        }
+
+        match damage {
+            None => {
+                let state = state.expect("state checked above");
+                let mut chain = Self::from_parts(blocks, state);
+                // Files written before governance was tracked have no record
+                chain.governance = governance.unwrap_or_default();
+                if chain.chain_work.is_empty() {
+                    chain.rebuild_chain_work();
+                }
+                // ... and files written before undo data was kept have none
+                match undo {
+                    Some(undo) => chain.undo = undo,
+                    None => chain.rebuild_undo(),
+                }
+                chain.invalidated = invalidated.unwrap_or_default();
+                match solidarity {
+                    Some(epochs) => chain.solidarity_epochs = epochs,
+                    None => chain.rebuild_solidarity_epochs(),
+                }
+                match version_bits {
+                    Some(counts) => chain.version_bits = counts,
+                    None => chain.rebuild_version_bits(),
+                }
+                Ok((chain, LoadStatus::Clean))
+            }
+            Some(reason) => {
+                log::warn!("Chain file {} is corrupted: {}", path.display(), reason);
+                let chain = Self::replay(blocks)?;
+                Ok((chain, LoadStatus::Recovered { reason }))
+            }
+        }
+    }
+
+    /// Assemble a chain from verified blocks and state
+    fn from_parts(blocks: Vec<Block>, state: ChainState) -> Self {
+        let mut by_hash = HashMap::with_capacity(blocks.len());
+        let mut height_index = HashMap::with_capacity(blocks.len());
+        for block in blocks {
+            let hash = block.hash();
+            height_index.insert(block.header.height, hash);
+            by_hash.insert(hash, block);
+        }
+
+        let mut chain = Self {
+            blocks: by_hash,
+            height_index,
+            tip: state.tip,
+            height: state.height,
+            utxos: state.utxos,
+            block_times: state.block_times,
+            treasury_address: state.treasury_address,
+            current_difficulty: state.current_difficulty,
+            address_index: state.address_index,
+            chain_work: state.chain_work,
+            checkpoints: Checkpoints::mainnet(),
+            governance: Governance::new(),
+            params: ChainParams::default(),
+            emission: HashMap::new(),
+            undo: HashMap::new(),
+            invalidated: HashMap::new(),
+            solidarity_epochs: BTreeMap::new(),
+            version_bits: BTreeMap::new(),
+        };
+        chain.rebuild_emission();
+        chain
+    }
+
+    /// Rebuild chain state by re-applying blocks from genesis
+    ///
+    /// Stops at the first block that no longer applies.
+    fn replay(blocks: Vec<Block>) -> anyhow::Result<Self> {
+        let mut blocks = blocks.into_iter();
+        let genesis = match blocks.next() {
+            Some(genesis) if genesis.header.height == 0 => genesis,
+            Some(_) => anyhow::bail!("Cannot recover a chain that does not start at genesis"),
+            None => anyhow::bail!("No intact blocks left to recover from"),
+        };
+
+        let mut chain = Self::from_genesis(genesis);
+        for block in blocks {
+            let height = block.header.height;
+            if let Err(e) = chain.add_block(block) {
+                log::warn!("Stopping chain recovery at height {}: {}", height, e);
+                break;
+            }
+        }
+
+        log::warn!("Chain recovered up to height {}", chain.height);
         Ok(chain)
     }
 
     /// Save to file
     pub fn save(&self, path: &std::path::Path) -> anyhow::Result<()> {
-        let content = bincode::serialize(self)?;
-        std::fs::write(path, content)?;
+        let mut content = Vec::new();
+        storage::write_header(&mut content);
+
+        for block in self.iter_blocks() {
+            storage::write_record(&mut content, RECORD_BLOCK, &bincode::serialize(block)?);
+        }
+
+        let state = ChainStateRef {
+            tip: &self.tip,
+            height: self.height,
+            utxos: &self.utxos,
+            block_times: &self.block_times,
+            treasury_address: &self.treasury_address,
+            current_difficulty: self.current_difficulty,
+            address_index: &self.address_index,
+            chain_work: &self.chain_work,
+        };
+        storage::write_record(&mut content, RECORD_STATE, &bincode::serialize(&state)?);
+        storage::write_record(&mut content, RECORD_GOVERNANCE, &bincode::serialize(&self.governance)?);
+        storage::write_record(&mut content, RECORD_UNDO, &bincode::serialize(&self.undo)?);
+        storage::write_record(&mut content, RECORD_INVALIDATED, &bincode::serialize(&self.invalidated)?);
+        storage::write_record(&mut content, RECORD_SOLIDARITY, &bincode::serialize(&self.solidarity_epochs)?);
+        storage::write_record(&mut content, RECORD_VERSION_BITS, &bincode::serialize(&self.version_bits)?);
+
+        storage::write_atomic(path, &content)?;
         Ok(())
     }
 
+    /// Write a UTXO set snapshot of the current tip
+    pub fn export_utxo_snapshot(&self, path: &std::path::Path) -> Result<UtxoSnapshot, SnapshotError> {
+        let start_height = (self.height + 1).saturating_sub(SNAPSHOT_RECENT_BLOCKS);
+        let recent_blocks: Vec<Block> = self.block_iter(start_height, self.height)
+            .cloned()
+            .collect();
+
+        let mut utxos: Vec<(UtxoId, TxOutput)> = self.utxos
+            .iter()
+            .map(|(id, output)| (id.clone(), output.clone()))
+            .collect();
+        utxos.sort_by(|(a, _), (b, _)| (a.tx_hash, a.output_index).cmp(&(b.tx_hash, b.output_index)));
+
+        let snapshot = UtxoSnapshot {
+            version: SNAPSHOT_VERSION,
+            height: self.height,
+            tip_hash: self.tip,
+            commitment: self.utxo_commitment(),
+            total_work: self.total_work(),
+            current_difficulty: self.current_difficulty,
+            treasury_address: self.treasury_address.clone(),
+            block_times: self.block_times.clone(),
+            recent_blocks,
+            utxos,
+            solidarity_epochs: self.solidarity_epochs.iter().map(|(start, tally)| (*start, tally.clone())).collect(),
+            version_bits: self.version_bits.iter().map(|(start, signals)| (*start, signals.clone())).collect(),
+        };
+
+        std::fs::write(path, snapshot.to_bytes()?)?;
+        Ok(snapshot)
+    }
+
+    /// Bootstrap a chain from a UTXO set snapshot
+    ///
+    /// Only the recent blocks embedded in the snapshot are available;
+    /// the address index, if enabled later, starts at the snapshot.
+    pub fn import_utxo_snapshot(path: &std::path::Path) -> Result<Self, SnapshotError> {
+        let snapshot = UtxoSnapshot::from_bytes(&std::fs::read(path)?)?;
+
+        if snapshot.compute_commitment() != snapshot.commitment {
+            return Err(SnapshotError::CommitmentMismatch);
+        }
+
+        // Recent blocks must form a chain ending at the tip
+        let tip_block = snapshot.recent_blocks.last().ok_or(SnapshotError::InvalidBlocks)?;
+        if tip_block.hash() != snapshot.tip_hash || tip_block.header.height != snapshot.height {
+            return Err(SnapshotError::InvalidBlocks);
+        }
+        for pair in snapshot.recent_blocks.windows(2) {
+            if pair[1].header.prev_hash != pair[0].hash()
+                || pair[1].header.height != pair[0].header.height + 1
+            {
+                return Err(SnapshotError::InvalidBlocks);
+            }
+        }
+
+        let checkpoints = Checkpoints::mainnet();
+        for block in &snapshot.recent_blocks {
+            checkpoints
+                .check(block.header.height, &block.hash())
+                .map_err(|_| SnapshotError::Checkpoint(block.header.height))?;
+        }
+
+        let mut blocks = HashMap::new();
+        let mut height_index = HashMap::new();
+        let mut chain_work = HashMap::new();
+
+        // Work of earlier blocks is only known through the tip total
+        let mut work = snapshot.total_work;
+        for block in snapshot.recent_blocks.into_iter().rev() {
+            let hash = block.hash();
+            chain_work.insert(hash, work);
+            work = work.saturating_sub(block.header.work());
+            height_index.insert(block.header.height, hash);
+            blocks.insert(hash, block);
+        }
+
+        Ok(Self {
+            blocks,
+            height_index,
+            tip: snapshot.tip_hash,
+            height: snapshot.height,
+            utxos: snapshot.utxos.into_iter().collect(),
+            block_times: snapshot.block_times,
+            treasury_address: snapshot.treasury_address,
+            current_difficulty: snapshot.current_difficulty,
+            address_index: None,
+            chain_work,
+            checkpoints,
+            // Snapshots carry no governance history
+            governance: Governance::new(),
+            params: ChainParams::default(),
+            // ... nor what was issued before them
+            emission: HashMap::new(),
+            // ... nor the outputs spent before them
+            undo: HashMap::new(),
+            invalidated: HashMap::new(),
+            // The registry tallies replace the blocks they summarize
+            solidarity_epochs: snapshot.solidarity_epochs.into_iter().collect(),
+            // ... and so do the signal counts
+            version_bits: snapshot.version_bits.into_iter().collect(),
+        })
+    }
+
+    /// Commitment to the current UTXO set, comparable with snapshot commitments
+    pub fn utxo_commitment(&self) -> [u8; 32] {
+        let mut utxos: Vec<(&UtxoId, &TxOutput)> = self.utxos.iter().collect();
+        utxos.sort_by(|(a, _), (b, _)| (a.tx_hash, a.output_index).cmp(&(b.tx_hash, b.output_index)));
+        utxo_commitment(self.height, &self.tip, utxos)
+    }
+
     /// Get the current height
     pub fn height(&self) -> u64 {
         self.height
@@ -112,6 +703,114 @@ impl Blockchain {
         self.current_difficulty
     }
 
+    /// Network parameters
+    pub fn params(&self) -> &ChainParams {
+        &self.params
+    }
+
+    /// Set the network parameters of a loaded chain
+    pub fn set_params(&mut self, params: ChainParams) {
+        let period_changed = params.signal_period != self.params.signal_period;
+        self.params = params;
+        if period_changed {
+            self.rebuild_version_bits();
+        }
+    }
+
+    /// Replace the checkpoints enforced by `add_block`
+    pub fn set_checkpoints(&mut self, checkpoints: Checkpoints) {
+        self.checkpoints = checkpoints;
+    }
+
+    /// Get the enforced checkpoints
+    pub fn checkpoints(&self) -> &Checkpoints {
+        &self.checkpoints
+    }
+
+    /// Record headers leading to the assumed-valid block, whose blocks then
+    /// connect without signature checks (see [`Checkpoints::learn_ancestors`])
+    pub fn learn_assumed_valid(&mut self, headers: &[BlockHeader]) -> usize {
+        self.checkpoints.learn_ancestors(headers)
+    }
+
+    /// Get the cumulative work of the chain up to the tip
+    pub fn total_work(&self) -> u128 {
+        self.chain_work(&self.tip).unwrap_or(0)
+    }
+
+    /// Get the cumulative work up to and including a block
+    pub fn chain_work(&self, hash: &[u8; 32]) -> Option<u128> {
+        self.chain_work.get(hash).copied()
+    }
+
+    /// Recompute cumulative work for every block on the main chain
+    fn rebuild_chain_work(&mut self) {
+        let mut work = 0u128;
+        for height in 0..=self.height {
+            if let Some(hash) = self.height_index.get(&height) {
+                if let Some(block) = self.blocks.get(hash) {
+                    work += block.header.work();
+                    self.chain_work.insert(*hash, work);
+                }
+            }
+        }
+    }
+
+    /// Recompute cumulative issuance for every block on the main chain
+    ///
+    /// Stops at the first missing block, after which the total is unknown.
+    fn rebuild_emission(&mut self) {
+        let mut emission = HashMap::new();
+        let mut issued = 0u64;
+        for height in 0..=self.height {
+            let block = match self.get_block_at_height(height) {
+                Some(block) => block,
+                None => break,
+            };
+            issued = issued.saturating_add(Self::coinbase_issuance(block));
+            emission.insert(height, issued);
+        }
+        self.emission = emission;
+    }
+
+    /// Amount created by a block's coinbase
+    fn coinbase_issuance(block: &Block) -> u64 {
+        block.transactions.first()
+            .and_then(|coinbase| coinbase.total_output().ok())
+            .unwrap_or(0)
+    }
+
+    /// Coinbase issuance of the main chain up to and including `height`
+    ///
+    /// `None` above the tip and on chains bootstrapped from a snapshot,
+    /// which do not know what was issued before it.
+    pub fn total_emission_at(&self, height: u64) -> Option<u64> {
+        self.emission.get(&height).copied()
+    }
+
+    /// Coinbase issuance of the main chain up to the tip
+    pub fn total_emission(&self) -> Option<u64> {
+        self.total_emission_at(self.height)
+    }
+
+    /// Most the schedule lets blocks 0 to `height` issue together
+    ///
+    /// Sums [`Self::reward_for_height`] one halving era at a time.
+    pub fn scheduled_emission_at(height: u64) -> u64 {
+        let mut total = 0u64;
+        let mut era_start = 0u64;
+        while era_start <= height {
+            let reward = Self::reward_for_height(era_start);
+            if reward == 0 {
+                break;
+            }
+            let era_end = era_start.saturating_add(HALVING_INTERVAL - 1).min(height);
+            total = total.saturating_add(reward.saturating_mul(era_end - era_start + 1));
+            era_start = era_start.saturating_add(HALVING_INTERVAL);
+        }
+        total
+    }
+
     /// Calculate reward for a given height
     pub fn reward_for_height(height: u64) -> u64 {
         let halvings = height / HALVING_INTERVAL;
@@ -123,6 +822,11 @@ impl Blockchain {
 
     /// Calculate miner, treasury, and solidarity rewards
     pub fn rewards_for_height(&self, height: u64) -> (u64, u64, u64) {
+        Self::reward_split_for_height(height)
+    }
+
+    /// Miner, treasury, and solidarity rewards for a height
+    pub fn reward_split_for_height(height: u64) -> (u64, u64, u64) {
         let total = Self::reward_for_height(height);
         let treasury = (total * TREASURY_PERCENTAGE) / 100;
         let solidarity = (total * SOLIDARITY_PERCENTAGE) / 100;
@@ -130,30 +834,133 @@ impl Blockchain {
         (miner, treasury, solidarity)
     }
 
-    /// Find the smallest miner (lowest balance) among the last 100 blocks to receive solidarity
-    pub fn find_smallest_beneficiary(&self) -> Address {
-        let start_height = self.height.saturating_sub(100);
-        let mut miner_balances: HashMap<Address, u64> = HashMap::new();
+    /// Miner, treasury, and solidarity rewards for a height and miner tier
+    pub fn reward_split_for_tier(height: u64, tier: HashrateTier) -> (u64, u64, u64) {
+        ProportionalRewards::for_tier(tier).split(Self::reward_for_height(height))
+    }
 
-        for h in start_height..=self.height {
-            if let Some(block) = self.get_block_at_height(h) {
-                if let Some(coinbase) = block.transactions.get(0) {
-                    // Assuming the first output of coinbase is the miner
-                    if let Some(output) = coinbase.outputs.get(0) {
-                        let addr = output.recipient.clone();
-                        let balance = self.get_balance(&addr);
-                        miner_balances.insert(addr, balance);
-                    }
-                }
-            }
+    /// Split of the next block's reward when it pays `miner`
+    ///
+    /// Uses the tier the registry attests for `miner` at the current tip.
+    pub fn rewards_for_miner(&self, miner: &Address) -> (u64, u64, u64) {
+        let tier = self.miner_registry().tier_of(miner);
+        Self::reward_split_for_tier(self.height + 1, tier)
+    }
+
+    /// Solidarity registry for the next block
+    ///
+    /// Built from the tallies of the completed epochs in the
+    /// [`REGISTRY_WINDOW`] before the next block, at the network hashrate
+    /// of the last of them, then the solidarity payouts since.
+    pub fn miner_registry(&self) -> MinerRegistry {
+        let next = self.height + 1;
+        let boundary = next - next % REGISTRY_EPOCH;
+        let starts = (boundary.saturating_sub(REGISTRY_WINDOW)..boundary).step_by(REGISTRY_EPOCH as usize);
+
+        // Tallies older than the chain keeps are recomputed from the blocks
+        let missing: Vec<EpochTally> = starts.clone()
+            .filter(|start| !self.solidarity_epochs.contains_key(start))
+            .map(|start| EpochTally::from_blocks(self.block_iter(start, start + REGISTRY_EPOCH - 1)))
+            .collect();
+        let tallies = starts.filter_map(|start| self.solidarity_epochs.get(&start)).chain(&missing);
+
+        let difficulty = boundary.checked_sub(1)
+            .and_then(|height| self.get_block_at_height(height))
+            .map_or(self.current_difficulty, |block| block.header.difficulty);
+        let network_hashrate = difficulty as f64 / TARGET_BLOCK_TIME as f64;
+
+        let mut registry = MinerRegistry::from_tallies(tallies, boundary.saturating_sub(1), network_hashrate);
+        registry.record_payouts(self.block_iter(boundary, self.height), self.height);
+        registry
+    }
+
+    /// Tally the epoch completed by the tip, if any, and drop tallies more
+    /// than two windows old
+    fn record_epoch(&mut self) {
+        let next = self.height + 1;
+        if next % REGISTRY_EPOCH != 0 {
+            return;
         }
+        let start = next - REGISTRY_EPOCH;
+        let tally = EpochTally::from_blocks(self.block_iter(start, self.height));
+        self.solidarity_epochs.insert(start, tally);
+
+        let oldest = next.saturating_sub(2 * REGISTRY_WINDOW);
+        self.solidarity_epochs.retain(|start, _| *start >= oldest);
+    }
+
+    /// Recompute the tallies of the last two windows from the blocks
+    fn rebuild_solidarity_epochs(&mut self) {
+        let next = self.height + 1;
+        let boundary = next - next % REGISTRY_EPOCH;
+        self.solidarity_epochs = (boundary.saturating_sub(2 * REGISTRY_WINDOW)..boundary)
+            .step_by(REGISTRY_EPOCH as usize)
+            .map(|start| (start, EpochTally::from_blocks(self.block_iter(start, start + REGISTRY_EPOCH - 1))))
+            .collect();
+    }
+
+    /// Recount the version-bits signals of the stored blocks
+    fn rebuild_version_bits(&mut self) {
+        let period = self.params.signal_period;
+        let mut version_bits: BTreeMap<u64, PeriodSignals> = BTreeMap::new();
+        for block in self.iter_blocks() {
+            let height = block.header.height;
+            version_bits.entry(height - height % period).or_default().record(block.header.version);
+        }
+        self.version_bits = version_bits;
+    }
+
+    /// State of a deployment for the next block, with its first height
+    fn deployment_state(&self, deployment: &Deployment) -> (DeploymentState, u64) {
+        deployment_state(
+            deployment,
+            self.params.signal_period,
+            self.params.signal_threshold,
+            self.height + 1,
+            &self.version_bits,
+        )
+    }
+
+    /// Status of every deployment of the network for the next block
+    pub fn deployments(&self) -> Vec<DeploymentInfo> {
+        let next = self.height + 1;
+        let current = self.version_bits.get(&(next - next % self.params.signal_period));
+        self.params.deployments.iter()
+            .map(|deployment| {
+                let (state, since) = self.deployment_state(deployment);
+                DeploymentInfo {
+                    deployment: deployment.clone(),
+                    state,
+                    since,
+                    signalling: current.map_or(0, |signals| signals.count(deployment.bit)),
+                    elapsed: current.map_or(0, |signals| signals.blocks),
+                }
+            })
+            .collect()
+    }
+
+    /// Whether the rules of a deployment apply to the next block
+    pub fn is_deployment_active(&self, name: &str) -> bool {
+        self.params.deployment(name)
+            .is_some_and(|deployment| self.deployment_state(deployment).0 == DeploymentState::Active)
+    }
+
+    /// Header version for the next block, signalling every deployment
+    /// started or locked in
+    pub fn next_block_version(&self) -> u32 {
+        self.params.deployments.iter()
+            .filter(|deployment| self.deployment_state(deployment).0.is_signalling())
+            .fold(VERSION_BITS_TOP_BITS, |version, deployment| version | 1 << deployment.bit)
+    }
 
-        // Return the address with the lowest balance.
-        // If no miners found (unlikely), return treasury as fallback.
-        miner_balances
-            .into_iter()
-            .min_by_key(|&(_, balance)| balance)
-            .map(|(addr, _)| addr)
+    /// Recipient of the next block's solidarity output
+    ///
+    /// Selected from the miner registry; the treasury when no registered
+    /// miner is eligible.
+    pub fn solidarity_recipient(&self) -> Address {
+        self.miner_registry()
+            .select()
+            .cloned()
             .unwrap_or_else(|| self.treasury_address.clone())
     }
 
@@ -169,6 +976,38 @@ impl Blockchain {
             .and_then(|hash| self.blocks.get(hash))
     }
 
+    /// Lowest height still stored (above 0 once old blocks are pruned)
+    pub fn first_height(&self) -> u64 {
+        self.height_index.keys().min().copied().unwrap_or(0)
+    }
+
+    /// Every stored main-chain block, from the oldest to the tip
+    pub fn iter_blocks(&self) -> BlockIter<'_> {
+        self.block_iter(self.first_height(), self.height)
+    }
+
+    /// Main-chain blocks from `from` to `to` inclusive, clamped to the tip
+    ///
+    /// Blocks are borrowed, not cloned. Spans over [`MAX_BLOCK_RANGE`]
+    /// blocks are refused.
+    pub fn blocks_in_range(&self, from: u64, to: u64) -> Result<BlockIter<'_>, ChainError> {
+        if from > to {
+            return Err(ChainError::InvalidRange { from, to });
+        }
+        if to - from >= MAX_BLOCK_RANGE {
+            return Err(ChainError::RangeTooLarge(MAX_BLOCK_RANGE));
+        }
+        Ok(self.block_iter(from, to.min(self.height)))
+    }
+
+    fn block_iter(&self, from: u64, to: u64) -> BlockIter<'_> {
+        BlockIter {
+            chain: self,
+            next: from,
+            end: to.saturating_add(1).max(from),
+        }
+    }
+
     /// Get the tip block
     pub fn tip_block(&self) -> &Block {
         self.blocks.get(&self.tip).expect("Tip block must exist")
@@ -184,8 +1023,210 @@ impl Blockchain {
         self.utxos
             .values()
             .filter(|output| &output.recipient == address)
-            .map(|output| output.amount)
-            .sum()
+            .fold(0u64, |total, output| total.saturating_add(output.amount))
+    }
+
+    /// Balance of an address once the block at `height` was applied
+    ///
+    /// Walks back from the tip: each later block gives back, from its undo
+    /// data, the outputs it spent from the address and takes off those it
+    /// paid to it. `None` above the tip, or where undo data is missing
+    /// (below the start of a snapshot-bootstrapped chain).
+    pub fn get_balance_at_height(&self, address: &Address, height: u64) -> Option<u64> {
+        if height > self.height {
+            return None;
+        }
+
+        let mut balance = self.get_balance(address);
+        for later in (height + 1..=self.height).rev() {
+            let hash = self.height_index.get(&later)?;
+            let undo = self.undo.get(hash)?;
+            let block = self.blocks.get(hash)?;
+
+            let paid = block.transactions.iter().flat_map(|tx| &tx.outputs);
+            let received = Self::amount_to(address, paid);
+            let spent = undo.spent.iter().map(|(_, output)| output);
+            balance = balance
+                .saturating_add(Self::amount_to(address, spent))
+                .saturating_sub(received);
+        }
+        Some(balance)
+    }
+
+    /// Total of the outputs paying `address`
+    fn amount_to<'a>(address: &Address, outputs: impl IntoIterator<Item = &'a TxOutput>) -> u64 {
+        outputs.into_iter()
+            .filter(|output| &output.recipient == address)
+            .fold(0u64, |total, output| total.saturating_add(output.amount))
+    }
+
+    /// Recompute the undo data of every block on the main chain
+    ///
+    /// The governance state before each block cannot be recovered, so
+    /// blocks that changed it can no longer be disconnected.
+    fn rebuild_undo(&mut self) {
+        let mut undo = HashMap::new();
+        let mut outputs: HashMap<UtxoId, TxOutput> = HashMap::new();
+
+        for height in 0..=self.height {
+            let (hash, block) = match self.height_index.get(&height)
+                .and_then(|hash| Some((*hash, self.blocks.get(hash)?)))
+            {
+                Some(found) => found,
+                None => continue,
+            };
+
+            let mut spent = Vec::new();
+            for tx in &block.transactions {
+                for input in &tx.inputs {
+                    let utxo_id = UtxoId::new(input.prev_tx_hash, input.output_index);
+                    if let Some(output) = outputs.remove(&utxo_id) {
+                        spent.push((utxo_id, output));
+                    }
+                }
+
+                let tx_hash = tx.hash();
+                for (idx, output) in tx.outputs.iter().enumerate() {
+                    outputs.insert(UtxoId::new(tx_hash, idx as u32), output.clone());
+                }
+            }
+            undo.insert(hash, BlockUndo { spent, governance: None });
+        }
+
+        self.undo = undo;
+    }
+
+    /// Disconnect the tip block, restoring the state from before it
+    ///
+    /// Works from the block's undo data, without replaying the chain.
+    /// Returns the disconnected block.
+    pub fn disconnect_tip(&mut self) -> Result<Block, ChainError> {
+        self.check_disconnect(self.height)?;
+
+        let height = self.height;
+        let hash = self.tip;
+        let block = self.blocks.remove(&hash).expect("checked by check_disconnect");
+        let undo = self.undo.remove(&hash).expect("checked by check_disconnect");
+
+        // Drop what the block created and restore what it spent
+        for tx in &block.transactions {
+            let tx_hash = tx.hash();
+            for idx in 0..tx.outputs.len() {
+                self.utxos.remove(&UtxoId::new(tx_hash, idx as u32));
+            }
+        }
+        for (utxo_id, output) in undo.spent {
+            self.utxos.insert(utxo_id, output);
+        }
+
+        if let Some(governance) = undo.governance {
+            self.governance = governance;
+        }
+        if let Some(index) = self.address_index.as_mut() {
+            for records in index.values_mut() {
+                records.retain(|record| record.height != height);
+            }
+            index.retain(|_, records| !records.is_empty());
+        }
+
+        if (height + 1) % REGISTRY_EPOCH == 0 {
+            self.solidarity_epochs.remove(&(height + 1 - REGISTRY_EPOCH));
+        }
+        let period_start = height - height % self.params.signal_period;
+        if let Some(signals) = self.version_bits.get_mut(&period_start) {
+            signals.unrecord(block.header.version);
+            if signals.blocks == 0 {
+                self.version_bits.remove(&period_start);
+            }
+        }
+        self.emission.remove(&height);
+        self.chain_work.remove(&hash);
+        self.height_index.remove(&height);
+        self.tip = block.header.prev_hash;
+        self.height -= 1;
+
+        self.rebuild_block_times();
+        self.current_difficulty = self.next_difficulty();
+
+        Ok(block)
+    }
+
+    /// Disconnect a main-chain block and every block above it, and refuse
+    /// the block if it is offered again, until [`Self::reconsider_block`]
+    ///
+    /// Returns the disconnected blocks, tip first. Nothing is disconnected
+    /// unless all of them can be. The mark is saved with the chain.
+    pub fn invalidate_block(&mut self, hash: &[u8; 32]) -> Result<Vec<Block>, ChainError> {
+        let height = self.blocks.get(hash)
+            .map(|block| block.header.height)
+            .filter(|height| self.height_index.get(height) == Some(hash))
+            .ok_or(ChainError::NotInMainChain)?;
+        for above in height..=self.height {
+            self.check_disconnect(above)?;
+        }
+
+        let mut disconnected = Vec::new();
+        while self.height >= height {
+            disconnected.push(self.disconnect_tip()?);
+        }
+        self.invalidated.insert(*hash, disconnected.iter().rev().cloned().collect());
+        Ok(disconnected)
+    }
+
+    /// Accept an invalidated block again, reconnecting the blocks its
+    /// invalidation disconnected where they still extend the tip
+    ///
+    /// Returns the reconnected blocks, lowest first. Once the tip has moved
+    /// on, none are; the block may then come back from peers.
+    pub fn reconsider_block(&mut self, hash: &[u8; 32]) -> Result<Vec<Block>, ChainError> {
+        let blocks = self.invalidated.remove(hash).ok_or(ChainError::NotInvalidated)?;
+
+        let mut reconnected = Vec::new();
+        for block in blocks {
+            let height = block.header.height;
+            if let Err(e) = self.add_block(block.clone()) {
+                log::info!("Not reconnecting block {}: {}", height, e);
+                break;
+            }
+            reconnected.push(block);
+        }
+        Ok(reconnected)
+    }
+
+    /// Hashes of the blocks invalidated by the operator
+    pub fn invalidated_blocks(&self) -> Vec<[u8; 32]> {
+        self.invalidated.keys().copied().collect()
+    }
+
+    /// Check that the main-chain block at `height` can be disconnected
+    fn check_disconnect(&self, height: u64) -> Result<(), ChainError> {
+        if height == 0 {
+            return Err(ChainError::CannotDisconnectGenesis);
+        }
+
+        let missing = || ChainError::MissingUndoData(height);
+        let hash = self.height_index.get(&height).ok_or_else(missing)?;
+        let block = self.blocks.get(hash).ok_or_else(missing)?;
+        let undo = self.undo.get(hash).ok_or_else(missing)?;
+
+        // The parent becomes the tip again, so it must be stored
+        if !self.blocks.contains_key(&block.header.prev_hash) {
+            return Err(missing());
+        }
+        if undo.governance.is_none() && self.governance.changes_at(&block.transactions[1..], height) {
+            return Err(missing());
+        }
+        Ok(())
+    }
+
+    /// Recompute the block times window from the stored blocks
+    fn rebuild_block_times(&mut self) {
+        let start = (self.height + 1).saturating_sub(DIFFICULTY_AVERAGING_WINDOW * 2);
+        self.block_times = (start..=self.height)
+            .filter_map(|height| {
+                self.get_block_at_height(height).map(|b| (height, b.header.timestamp.timestamp()))
+            })
+            .collect();
     }
 
     /// Get UTXOs for an address
@@ -197,13 +1238,240 @@ impl Blockchain {
             .collect()
     }
 
+    /// Heights of the blocks holding the transactions `tx_hashes`
+    ///
+    /// Walks back from the tip until every one is found; those not in the
+    /// active chain are left out.
+    pub fn transaction_heights(&self, tx_hashes: &[[u8; 32]]) -> HashMap<[u8; 32], u64> {
+        let mut missing: HashSet<[u8; 32]> = tx_hashes.iter().copied().collect();
+        let mut heights = HashMap::new();
+
+        for height in (self.first_height()..=self.height).rev() {
+            if missing.is_empty() {
+                break;
+            }
+            let Some(block) = self.get_block_at_height(height) else { continue };
+            for tx in &block.transactions {
+                let hash = tx.hash();
+                if missing.remove(&hash) {
+                    heights.insert(hash, height);
+                }
+            }
+        }
+        heights
+    }
+
+    /// Seconds since the tip's timestamp (0 for a tip dated in the future)
+    pub fn seconds_since_tip(&self) -> u64 {
+        let tip_time = self.tip_block().header.timestamp.timestamp();
+        chrono::Utc::now().timestamp().saturating_sub(tip_time).max(0) as u64
+    }
+
+    /// Median timestamp of the last `MEDIAN_TIME_SPAN` blocks
+    pub fn median_time_past(&self) -> i64 {
+        let start_height = (self.height + 1).saturating_sub(MEDIAN_TIME_SPAN as u64);
+        let mut timestamps: Vec<i64> = (start_height..=self.height)
+            .filter_map(|h| self.get_block_at_height(h))
+            .map(|b| b.header.timestamp.timestamp())
+            .collect();
+
+        if timestamps.is_empty() {
+            return 0;
+        }
+
+        timestamps.sort_unstable();
+        timestamps[timestamps.len() / 2]
+    }
+
+    /// Enable the address history index, building it from existing blocks
+    pub fn enable_address_index(&mut self) {
+        if self.address_index.is_some() {
+            return;
+        }
+
+        let mut index: HashMap<Address, Vec<TxRecord>> = HashMap::new();
+        let mut outputs: HashMap<UtxoId, TxOutput> = HashMap::new();
+
+        for height in 0..=self.height {
+            let block = match self.get_block_at_height(height) {
+                Some(block) => block,
+                None => continue,
+            };
+
+            for tx in &block.transactions {
+                let spent: Vec<TxOutput> = tx.inputs.iter()
+                    .filter_map(|input| outputs.remove(&UtxoId::new(input.prev_tx_hash, input.output_index)))
+                    .collect();
+
+                let tx_hash = tx.hash();
+                for (idx, output) in tx.outputs.iter().enumerate() {
+                    outputs.insert(UtxoId::new(tx_hash, idx as u32), output.clone());
+                }
+
+                Self::index_transaction(&mut index, tx_hash, height, &spent, &tx.outputs);
+            }
+        }
+
+        self.address_index = Some(index);
+    }
+
+    /// Check if the address history index is enabled
+    pub fn has_address_index(&self) -> bool {
+        self.address_index.is_some()
+    }
+
+    /// Get transaction history for an address (empty if the index is disabled)
+    pub fn get_history(&self, address: &Address) -> Vec<TxRecord> {
+        self.address_index
+            .as_ref()
+            .and_then(|index| index.get(address))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Record the net effect of a transaction on each address involved
+    fn index_transaction(
+        index: &mut HashMap<Address, Vec<TxRecord>>,
+        tx_hash: [u8; 32],
+        height: u64,
+        spent: &[TxOutput],
+        outputs: &[TxOutput],
+    ) {
+        let mut deltas: HashMap<&Address, (u64, u64)> = HashMap::new();
+        for output in spent {
+            let sent = &mut deltas.entry(&output.recipient).or_default().0;
+            *sent = sent.saturating_add(output.amount);
+        }
+        for output in outputs {
+            let received = &mut deltas.entry(&output.recipient).or_default().1;
+            *received = received.saturating_add(output.amount);
+        }
+
+        for (address, (sent, received)) in deltas {
+            let (direction, amount) = if sent > received {
+                (TxDirection::Outgoing, sent - received)
+            } else {
+                (TxDirection::Incoming, received - sent)
+            };
+
+            index.entry(address.clone()).or_default().push(TxRecord {
+                tx_hash,
+                height,
+                direction,
+                amount,
+            });
+        }
+    }
+
     /// Calculate next difficulty
     pub fn next_difficulty(&self) -> u64 {
-        Difficulty::calculate_next(self.current_difficulty, &self.block_times)
+        let history: Vec<(i64, u64)> = self.block_times
+            .iter()
+            .filter_map(|&(height, timestamp)| {
+                self.get_block_at_height(height).map(|b| (timestamp, b.header.difficulty))
+            })
+            .collect();
+
+        if history.is_empty() {
+            return self.current_difficulty;
+        }
+        self.params.next_difficulty(&history)
+    }
+
+    /// Validate the stored history again, as far as `level` asks
+    pub fn verify_history(&self, level: CheckLevel) -> Result<(), ChainError> {
+        if level >= CheckLevel::Difficulty {
+            self.verify_difficulty_history()?;
+            for block in self.iter_blocks().filter(|b| b.header.height > 0) {
+                if !block.header.meets_difficulty() {
+                    return Err(ChainError::InvalidHistory {
+                        height: block.header.height,
+                        reason: BlockError::InsufficientProofOfWork.to_string(),
+                    });
+                }
+            }
+        }
+        if level >= CheckLevel::Full {
+            self.verify_replay()?;
+        }
+        Ok(())
+    }
+
+    /// Check each block's difficulty against the one expected at its height
+    ///
+    /// Expected difficulties are recomputed from the timestamps of the
+    /// blocks before and the difficulties expected for them, so no header
+    /// value is trusted except the oldest stored one.
+    pub fn verify_difficulty_history(&self) -> Result<(), ChainError> {
+        let mut blocks = self.iter_blocks();
+        let Some(first) = blocks.next() else {
+            return Ok(());
+        };
+
+        let window = DIFFICULTY_AVERAGING_WINDOW as usize + 1;
+        let mut history = vec![(first.header.timestamp.timestamp(), first.header.difficulty)];
+        for block in blocks {
+            let expected = self.params.next_difficulty(&history);
+            if block.header.difficulty != expected || !block.header.bits_match_difficulty() {
+                return Err(ChainError::DifficultyMismatch {
+                    height: block.header.height,
+                    expected,
+                    found: block.header.difficulty,
+                });
+            }
+            history.push((block.header.timestamp.timestamp(), expected));
+            if history.len() > window {
+                history.remove(0);
+            }
+        }
+
+        let expected = self.params.next_difficulty(&history);
+        if self.current_difficulty != expected {
+            return Err(ChainError::DifficultyMismatch {
+                height: self.height + 1,
+                expected,
+                found: self.current_difficulty,
+            });
+        }
+        Ok(())
+    }
+
+    /// Replay every block from genesis and compare the resulting state
+    fn verify_replay(&self) -> Result<(), ChainError> {
+        let mut blocks = self.iter_blocks();
+        let genesis = match blocks.next() {
+            Some(genesis) if genesis.header.height == 0 => genesis.clone(),
+            _ => return Err(ChainError::InvalidHistory {
+                height: self.first_height(),
+                reason: "pruned chains cannot be replayed from genesis".to_string(),
+            }),
+        };
+
+        let mut replayed = Self::from_genesis(genesis);
+        replayed.set_checkpoints(self.checkpoints.clone());
+        replayed.set_params(self.params.clone());
+        for block in blocks {
+            let height = block.header.height;
+            replayed.add_block(block.clone())
+                .map_err(|e| ChainError::InvalidHistory { height, reason: e.to_string() })?;
+        }
+
+        if replayed.utxo_commitment() != self.utxo_commitment() {
+            return Err(ChainError::InvalidHistory {
+                height: self.height,
+                reason: "stored UTXO set does not match the replayed blocks".to_string(),
+            });
+        }
+        Ok(())
     }
 
     /// Validate and add a new block
     pub fn add_block(&mut self, block: Block) -> Result<(), ChainError> {
+        // Blocks invalidated by hand stay out
+        if self.invalidated.contains_key(&block.hash()) {
+            return Err(ChainError::InvalidatedBlock);
+        }
+
         // Check previous hash
         if block.header.prev_hash != self.tip {
             return Err(ChainError::InvalidPrevHash);
@@ -220,8 +1488,22 @@ impl Blockchain {
             return Err(ChainError::InvalidDifficulty);
         }
 
+        // Check against checkpoints
+        self.checkpoints.check(block.header.height, &block.hash())?;
+
+        // Check timestamp (median-time-past and future drift)
+        block.validate_timestamp(self.median_time_past(), chrono::Utc::now().timestamp())?;
+
+        // Signatures of known ancestors of the assumed-valid block are trusted
+        let verify_signatures = self.checkpoints.should_verify_signatures(&block.hash());
+
         // Validate block structure
-        block.validate()?;
+        block.validate_with(verify_signatures)?;
+
+        // A committed UTXO set must be the one the block is applied on
+        if block.utxo_commitment().is_some_and(|c| c != self.utxo_commitment()) {
+            return Err(ChainError::UtxoCommitmentMismatch);
+        }
 
         // Validate transactions
         self.validate_block_transactions(&block)?;
@@ -229,13 +1511,20 @@ impl Blockchain {
         // Apply block
         let block_hash = block.hash();
         let timestamp = block.header.timestamp.timestamp();
+        let version = block.header.version;
 
         // Update UTXO set
+        let new_height = self.height + 1;
+        let mut block_spent = Vec::new();
         for tx in &block.transactions {
             // Remove spent UTXOs
+            let mut spent = Vec::new();
             for input in &tx.inputs {
                 let utxo_id = UtxoId::new(input.prev_tx_hash, input.output_index);
-                self.utxos.remove(&utxo_id);
+                if let Some(output) = self.utxos.remove(&utxo_id) {
+                    block_spent.push((utxo_id, output.clone()));
+                    spent.push(output);
+                }
             }
 
             // Add new UTXOs
@@ -244,9 +1533,26 @@ impl Blockchain {
                 let utxo_id = UtxoId::new(tx_hash, idx as u32);
                 self.utxos.insert(utxo_id, output.clone());
             }
+
+            // Update address history
+            if let Some(index) = self.address_index.as_mut() {
+                Self::index_transaction(index, tx_hash, new_height, &spent, &tx.outputs);
+            }
         }
 
+        // Record proposals and votes, then tally proposals closing here
+        let prior_governance = self.governance.changes_at(&block.transactions[1..], new_height)
+            .then(|| self.governance.clone());
+        self.undo.insert(block_hash, BlockUndo { spent: block_spent, governance: prior_governance });
+        self.governance.apply_transactions(&block.transactions[1..], new_height);
+        self.governance.close_voting(new_height, self.utxos.values());
+
         // Update chain state
+        if let Some(issued) = self.total_emission() {
+            self.emission.insert(self.height + 1, issued.saturating_add(Self::coinbase_issuance(&block)));
+        }
+        let work = self.total_work() + block.header.work();
+        self.chain_work.insert(block_hash, work);
         self.blocks.insert(block_hash, block);
         self.height_index.insert(self.height + 1, block_hash);
         self.tip = block_hash;
@@ -260,6 +1566,10 @@ impl Blockchain {
 
         // Update difficulty
         self.current_difficulty = self.next_difficulty();
+        self.record_epoch();
+
+        let period_start = self.height - self.height % self.params.signal_period;
+        self.version_bits.entry(period_start).or_default().record(version);
 
         Ok(())
     }
@@ -276,12 +1586,18 @@ impl Blockchain {
         }
 
         // Validate coinbase amount
-        let (miner_reward, treasury_reward, solidarity_reward) =
-            self.rewards_for_height(block.header.height);
         let coinbase = &block.transactions[0];
+        let (miner_reward, treasury_reward, solidarity_reward) = match coinbase.outputs.first() {
+            Some(miner) if block.header.height > 0 => {
+                let tier = self.miner_registry().tier_of(&miner.recipient);
+                Self::reward_split_for_tier(block.header.height, tier)
+            }
+            _ => self.rewards_for_height(block.header.height),
+        };
 
-        // PROTOCOLE DE DISTRIBUTION 98/1/1 - RÈGLE ABSOLUE
-        // Chaque block DOIT contenir: 98% mineur, 1% trésorerie, 1% solidarité
+        // PROTOCOLE DE DISTRIBUTION - RÈGLE ABSOLUE
+        // Chaque block DOIT contenir: mineur, trésorerie (1% à 5.6% selon le
+        // palier attesté du mineur), 1% solidarité. 98/1/1 pour les petits mineurs
 
         if block.header.height == 0 {
             // Genesis block: distribution initiale unique
@@ -300,29 +1616,62 @@ impl Blockchain {
             }
         }
 
-        let total_reward = miner_reward + treasury_reward + solidarity_reward;
-        let coinbase_amount: u64 = coinbase.outputs.iter().map(|o| o.amount).sum();
+        // Treasury and solidarity shares follow the miner's attested tier
+        if block.header.height > 0
+            && (coinbase.outputs[1].recipient != self.treasury_address
+                || coinbase.outputs[1].amount != treasury_reward
+                || coinbase.outputs[2].amount != solidarity_reward)
+        {
+            log::error!(
+                "❌ VIOLATION DISTRIBUTION: Block {} doit verser {} à la trésorerie et {} à la solidarité",
+                block.header.height,
+                format_amount(treasury_reward),
+                format_amount(solidarity_reward)
+            );
+            return Err(ChainError::InvalidCoinbaseAmount);
+        }
+
+        let total_reward = checked_sum([miner_reward, treasury_reward, solidarity_reward])
+            .ok_or(ChainError::InvalidCoinbaseAmount)?;
+        let coinbase_amount = coinbase.total_output()?;
 
         if coinbase_amount > total_reward {
             log::error!(
-                "💰 INFLATION DÉTECTÉE: Block {} tente de créer {} AEQ au lieu de {} maximum",
+                "💰 INFLATION DÉTECTÉE: Block {} tente de créer {} au lieu de {} maximum",
                 block.header.height,
-                coinbase_amount / 1_000_000_000,
-                total_reward / 1_000_000_000
+                format_amount(coinbase_amount),
+                format_amount(total_reward)
             );
             return Err(ChainError::InvalidCoinbaseAmount);
         }
 
+        // Cumulative issuance may never run ahead of the programmed schedule
+        let issued = self.total_emission().map(|issued| issued.saturating_add(coinbase_amount));
+        let scheduled = Self::scheduled_emission_at(block.header.height).min(MAX_SUPPLY);
+        if let Some(issued) = issued.filter(|&issued| issued > scheduled) {
+            log::error!(
+                "🚨 ÉMISSION HORS CALENDRIER: Block {} porterait l'émission à {} pour {} programmés",
+                block.header.height,
+                format_amount(issued),
+                format_amount(scheduled)
+            );
+            return Err(ChainError::EmissionExceedsSchedule {
+                height: block.header.height,
+                issued,
+                scheduled,
+            });
+        }
+
         // VÉRIFICATION ANTI-INFLATION - Stable coin intrinsèque
         let supply_actuelle = self.circulating_supply();
-        let future_supply = supply_actuelle + coinbase_amount;
+        let future_supply = supply_actuelle.saturating_add(coinbase_amount);
 
         if future_supply > MAX_SUPPLY {
             log::error!(
                 "🚨 LIMITE SUPPLY DÉPASSÉE: Actuelle {}, Future {}, Maximum {}",
-                supply_actuelle / 1_000_000_000,
-                future_supply / 1_000_000_000,
-                MAX_SUPPLY / 1_000_000_000
+                format_amount(supply_actuelle),
+                format_amount(future_supply),
+                format_amount(MAX_SUPPLY)
             );
             return Err(ChainError::MaxSupplyExceeded);
         }
@@ -332,7 +1681,7 @@ impl Blockchain {
         // Ceci est un principe fondamental d'Aequitas
 
         if coinbase.outputs.len() > 2 {
-            let expected_solidarity_recipient = self.find_smallest_beneficiary();
+            let expected_solidarity_recipient = self.solidarity_recipient();
             let actual_solidarity_recipient = &coinbase.outputs[2].recipient;
 
             // VÉRIFICATION ABSOLUE du protocole de solidarité
@@ -353,14 +1702,22 @@ impl Blockchain {
             );
         }
 
+        // No transaction may be locked to this height or above
+        if let Some(tx) = block.transactions.iter().find(|tx| !tx.is_final(block.header.height)) {
+            return Err(TxError::NotFinal(tx.lock_height).into());
+        }
+
         // Validate other transactions
         let mut spent_in_block = HashMap::new();
+        let mut paid_out_in_block = std::collections::HashSet::new();
 
         for (i, tx) in block.transactions.iter().enumerate() {
             if i == 0 {
                 continue; // Skip coinbase
             }
 
+            let treasury_spend = tx.tx_type == TxType::TreasurySpend;
+
             // Check for double-spends within block
             for input in &tx.inputs {
                 let utxo_id = UtxoId::new(input.prev_tx_hash, input.output_index);
@@ -370,23 +1727,84 @@ impl Blockchain {
                 }
 
                 // Check UTXO exists
-                if !self.utxos.contains_key(&utxo_id) {
-                    return Err(ChainError::MissingUtxo);
+                let spent = self.utxos.get(&utxo_id).ok_or(ChainError::MissingUtxo)?;
+
+                // Treasury funds move only through approved treasury spends,
+                // which in turn may only draw on the treasury
+                let from_treasury = spent.recipient == self.treasury_address;
+                if from_treasury != treasury_spend {
+                    return Err(ChainError::UnauthorizedTreasurySpend);
+                }
+
+                // Check the input's key or multisig policy owns the output,
+                // even where signatures are assumed valid
+                if !treasury_spend && input.owner()? != spent.recipient {
+                    return Err(ChainError::UnauthorizedSpend);
                 }
 
                 spent_in_block.insert(utxo_id, true);
             }
 
-            // Validate transaction
-            tx.validate()?;
+            // Each proposal pays out at most once, even within a block
+            if treasury_spend {
+                let payload = TreasurySpendPayload::from_memo(&tx.memo)?;
+                if !paid_out_in_block.insert(payload.proposal_id) {
+                    return Err(GovernanceError::AlreadyExecuted.into());
+                }
+            }
+
+            // Structure and signatures were checked by `Block::validate_with`
+
+            // Outputs may not exceed inputs
+            if self.transaction_fee(tx).is_none() {
+                return Err(TxError::InsufficientFunds.into());
+            }
+
+            self.governance.validate(tx, block.header.height, &self.treasury_address)?;
         }
 
         Ok(())
     }
 
+    /// Address receiving treasury rewards and proposal deposits
+    pub fn treasury_address(&self) -> &Address {
+        &self.treasury_address
+    }
+
+    /// Governance state (proposals, votes and results)
+    pub fn governance(&self) -> &Governance {
+        &self.governance
+    }
+
+    /// Check a governance transaction for inclusion in the next block
+    pub fn validate_governance(&self, tx: &Transaction) -> Result<(), GovernanceError> {
+        self.governance.validate(tx, self.height + 1, &self.treasury_address)
+    }
+
+    /// Check that a transaction's lock height allows it in the next block
+    pub fn check_final(&self, tx: &Transaction) -> Result<(), TxError> {
+        if tx.is_final(self.height + 1) {
+            Ok(())
+        } else {
+            Err(TxError::NotFinal(tx.lock_height))
+        }
+    }
+
+    /// Fee paid by a transaction spending confirmed outputs
+    ///
+    /// `None` if an input is not in the UTXO set or outputs exceed inputs.
+    pub fn transaction_fee(&self, tx: &Transaction) -> Option<u64> {
+        tx.fee(self)
+    }
+
     /// Get total circulating supply
     pub fn circulating_supply(&self) -> u64 {
-        self.utxos.values().map(|o| o.amount).sum()
+        self.utxos.values().fold(0u64, |total, o| total.saturating_add(o.amount))
+    }
+
+    /// Size of the UTXO set and the memory it holds
+    pub fn utxo_stats(&self) -> UtxoStats {
+        self.utxos.stats()
     }
 }
 
@@ -408,6 +1826,9 @@ pub enum ChainError {
     #[error("Invalid difficulty")]
     InvalidDifficulty,
 
+    #[error("UTXO commitment does not match the UTXO set")]
+    UtxoCommitmentMismatch,
+
     #[error("Block validation failed: {0}")]
     BlockError(#[from] BlockError),
 
@@ -423,6 +1844,12 @@ pub enum ChainError {
     #[error("Invalid coinbase amount")]
     InvalidCoinbaseAmount,
 
+    #[error("Input does not own the output it spends")]
+    UnauthorizedSpend,
+
+    #[error("Treasury outputs can only be spent by an approved treasury spend")]
+    UnauthorizedTreasurySpend,
+
     #[error("Double spend detected")]
     DoubleSpend,
 
@@ -434,11 +1861,90 @@ pub enum ChainError {
 
     #[error("Maximum supply exceeded - inflation prevented")]
     MaxSupplyExceeded,
+
+    #[error("Block was invalidated")]
+    InvalidatedBlock,
+
+    #[error("Block is not invalidated")]
+    NotInvalidated,
+
+    #[error("Block is not in the main chain")]
+    NotInMainChain,
+
+    #[error("The genesis block cannot be disconnected")]
+    CannotDisconnectGenesis,
+
+    #[error("No undo data to disconnect block {0}")]
+    MissingUndoData(u64),
+
+    #[error("Block {height} would bring issuance to {issued}, above the scheduled {scheduled}")]
+    EmissionExceedsSchedule { height: u64, issued: u64, scheduled: u64 },
+
+    #[error("Checkpoint violation: {0}")]
+    Checkpoint(#[from] CheckpointError),
+
+    #[error("Governance validation failed: {0}")]
+    Governance(#[from] GovernanceError),
+
+    #[error("Invalid block range {from}..={to}")]
+    InvalidRange { from: u64, to: u64 },
+
+    #[error("Block range exceeds {0} blocks")]
+    RangeTooLarge(u64),
+
+    #[error("Block {height} has difficulty {found}, expected {expected}")]
+    DifficultyMismatch { height: u64, expected: u64, found: u64 },
+
+    #[error("Stored block {height} is invalid: {reason}")]
+    InvalidHistory { height: u64, reason: String },
+}
+
+/// Main-chain blocks in height order, borrowed from a [`Blockchain`]
+///
+/// Heights without a stored block (pruned) are skipped.
+pub struct BlockIter<'a> {
+    chain: &'a Blockchain,
+    next: u64,
+    end: u64,
+}
+
+impl<'a> Iterator for BlockIter<'a> {
+    type Item = &'a Block;
+
+    fn next(&mut self) -> Option<&'a Block> {
+        while self.next < self.end {
+            self.next += 1;
+            if let Some(block) = self.chain.get_block_at_height(self.next - 1) {
+                return Some(block);
+            }
+        }
+        None
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, usize::try_from(self.end - self.next).ok())
+    }
+}
+
+impl DoubleEndedIterator for BlockIter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        while self.next < self.end {
+            self.end -= 1;
+            if let Some(block) = self.chain.get_block_at_height(self.end) {
+                return Some(block);
+            }
+        }
+        None
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::address::Keypair;
+    use crate::difficulty::Difficulty;
+    use crate::solidarity::identity_commitment;
+    use crate::transaction::TxInput;
 
     #[test]
     fn test_new_blockchain() {
@@ -447,6 +1953,510 @@ mod tests {
         assert!(chain.circulating_supply() > 0);
     }
 
+    #[test]
+    fn test_genesis_chain_work() {
+        let chain = Blockchain::new();
+        assert_eq!(chain.total_work(), chain.tip_block().header.work());
+        assert_eq!(chain.chain_work(&chain.tip()), Some(chain.total_work()));
+    }
+
+    #[test]
+    fn test_transaction_heights() {
+        let chain = Blockchain::new();
+        let coinbase = chain.tip_block().transactions[0].hash();
+        let heights = chain.transaction_heights(&[coinbase, [7u8; 32]]);
+        assert_eq!(heights.get(&coinbase), Some(&0));
+        assert_eq!(heights.len(), 1);
+    }
+
+    #[test]
+    fn test_address_history() {
+        let mut chain = Blockchain::new();
+        let genesis_addr = Address::genesis_address();
+        assert!(chain.get_history(&genesis_addr).is_empty());
+
+        chain.enable_address_index();
+        let history = chain.get_history(&genesis_addr);
+
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].height, 0);
+        assert_eq!(history[0].direction, TxDirection::Incoming);
+        assert_eq!(history[0].amount, GENESIS_REWARD);
+    }
+
+    #[test]
+    fn test_save_and_load_verified() {
+        let mut chain = Blockchain::new();
+        chain.enable_address_index();
+        let path = std::env::temp_dir().join(format!("aequitas-chain-{}.dat", rand::random::<u64>()));
+        chain.save(&path).unwrap();
+
+        let (loaded, status) = Blockchain::load_verified(&path).unwrap();
+        assert_eq!(status, LoadStatus::Clean);
+        assert_eq!(loaded.tip(), chain.tip());
+        assert_eq!(loaded.utxo_commitment(), chain.utxo_commitment());
+        assert!(loaded.has_address_index());
+
+        // Damage the state record: the chain is rebuilt from its blocks
+        let mut bytes = std::fs::read(&path).unwrap();
+        let last = bytes.len() - 40;
+        bytes[last] ^= 0xff;
+        std::fs::write(&path, &bytes).unwrap();
+
+        let (recovered, status) = Blockchain::load_verified(&path).unwrap();
+        assert!(matches!(status, LoadStatus::Recovered { .. }));
+        assert_eq!(recovered.tip(), chain.tip());
+        assert_eq!(recovered.circulating_supply(), chain.circulating_supply());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_load_falls_back_to_backup() {
+        let chain = Blockchain::new();
+        let path = std::env::temp_dir().join(format!("aequitas-chain-{}.dat", rand::random::<u64>()));
+        chain.save(&path).unwrap();
+        chain.save(&path).unwrap();
+
+        // Killed mid-write: the file is cut short before its first record
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..4]).unwrap();
+        let (restored, status) = Blockchain::load_verified(&path).unwrap();
+        assert!(matches!(status, LoadStatus::Recovered { .. }));
+        assert_eq!(restored.tip(), chain.tip());
+
+        // Only the backup is left
+        std::fs::remove_file(&path).unwrap();
+        let (restored, _) = Blockchain::load_verified(&path).unwrap();
+        assert_eq!(restored.utxo_commitment(), chain.utxo_commitment());
+
+        std::fs::remove_file(storage::backup_path(&path)).unwrap();
+    }
+
+    #[test]
+    fn test_utxo_snapshot_roundtrip() {
+        let chain = Blockchain::new();
+        let path = std::env::temp_dir().join(format!("aequitas-snapshot-{}.bin", rand::random::<u64>()));
+
+        let snapshot = chain.export_utxo_snapshot(&path).unwrap();
+        assert_eq!(snapshot.tip_hash, chain.tip());
+        assert_eq!(snapshot.commitment, chain.utxo_commitment());
+
+        let restored = Blockchain::import_utxo_snapshot(&path).unwrap();
+        assert_eq!(restored.tip(), chain.tip());
+        assert_eq!(restored.height(), chain.height());
+        assert_eq!(restored.total_work(), chain.total_work());
+        assert_eq!(restored.circulating_supply(), chain.circulating_supply());
+        assert_eq!(restored.utxo_commitment(), chain.utxo_commitment());
+
+        // Any corruption is caught by the file hash
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[10] ^= 0xff;
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(matches!(
+            Blockchain::import_utxo_snapshot(&path),
+            Err(SnapshotError::ChecksumMismatch)
+        ));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_block_ranges() {
+        let chain = Blockchain::new();
+        let genesis = chain.tip();
+        assert_eq!(chain.iter_blocks().map(|b| b.hash()).collect::<Vec<_>>(), vec![genesis]);
+        assert_eq!(chain.iter_blocks().next_back().map(|b| b.hash()), Some(genesis));
+
+        // Clamped to the tip, past it is empty
+        assert_eq!(chain.blocks_in_range(0, 50).unwrap().count(), 1);
+        assert_eq!(chain.blocks_in_range(5, 10).unwrap().count(), 0);
+
+        assert!(matches!(chain.blocks_in_range(3, 2), Err(ChainError::InvalidRange { .. })));
+        assert!(chain.blocks_in_range(0, MAX_BLOCK_RANGE - 1).is_ok());
+        assert!(matches!(
+            chain.blocks_in_range(0, MAX_BLOCK_RANGE),
+            Err(ChainError::RangeTooLarge(MAX_BLOCK_RANGE))
+        ));
+    }
+
+    #[test]
+    fn test_difficulty_history() {
+        let mut chain = Blockchain::new();
+        assert!(chain.verify_history(CheckLevel::Full).is_ok());
+
+        // Headers stored without going through add_block
+        fn push(chain: &mut Blockchain, difficulty: u64) {
+            let mut block = Block::new(chain.tip(), chain.height + 1, difficulty, Vec::new());
+            block.header.timestamp = chain.tip_block().header.timestamp + chrono::Duration::seconds(5);
+            let hash = block.hash();
+            chain.height += 1;
+            chain.height_index.insert(chain.height, hash);
+            chain.blocks.insert(hash, block);
+            chain.tip = hash;
+        }
+
+        let expected = chain.next_difficulty();
+        push(&mut chain, expected);
+        let fast = Difficulty::calculate_next(&[
+            (chain.get_block_at_height(0).unwrap().header.timestamp.timestamp(), expected),
+            (chain.tip_block().header.timestamp.timestamp(), expected),
+        ]);
+        assert!(fast > expected);
+        chain.current_difficulty = fast;
+        assert!(chain.verify_difficulty_history().is_ok());
+        assert!(chain.verify_history(CheckLevel::Quick).is_ok());
+
+        // A header keeping the old difficulty despite fast blocks is caught
+        push(&mut chain, expected);
+        assert!(matches!(
+            chain.verify_difficulty_history(),
+            Err(ChainError::DifficultyMismatch { height: 2, found, .. }) if found == expected
+        ));
+
+        assert_eq!("1".parse::<CheckLevel>(), Ok(CheckLevel::Difficulty));
+        assert_eq!("full".parse::<CheckLevel>(), Ok(CheckLevel::Full));
+        assert!("deep".parse::<CheckLevel>().is_err());
+    }
+
+    #[test]
+    fn test_seconds_since_tip() {
+        let mut chain = Blockchain::new();
+        assert!(chain.seconds_since_tip() < 5);
+
+        let tip = chain.tip();
+        chain.blocks.get_mut(&tip).unwrap().header.timestamp -= chrono::Duration::seconds(600);
+        assert!((600..605).contains(&chain.seconds_since_tip()));
+
+        chain.blocks.get_mut(&tip).unwrap().header.timestamp += chrono::Duration::days(1);
+        assert_eq!(chain.seconds_since_tip(), 0);
+    }
+
+    #[test]
+    fn test_emission_schedule() {
+        assert_eq!(Blockchain::scheduled_emission_at(0), GENESIS_REWARD);
+        assert_eq!(
+            Blockchain::scheduled_emission_at(HALVING_INTERVAL),
+            GENESIS_REWARD * HALVING_INTERVAL + GENESIS_REWARD / 2
+        );
+        assert!(Blockchain::scheduled_emission_at(u64::MAX) <= MAX_SUPPLY);
+
+        let mut chain = Blockchain::with_params(ChainParams::regtest());
+        assert_eq!(chain.total_emission_at(0), Some(GENESIS_REWARD));
+        assert_eq!(chain.total_emission_at(1), None);
+
+        let miner = Address::genesis_address();
+        let (miner_reward, treasury_reward, solidarity_reward) = chain.rewards_for_miner(&miner);
+        let mut coinbase = Transaction::coinbase(miner.clone(), miner_reward, 1);
+        coinbase.outputs.push(TxOutput::new(Address::genesis_address(), treasury_reward));
+        coinbase.outputs.push(TxOutput::new(chain.solidarity_recipient(), solidarity_reward));
+        let mut block = Block::new(chain.tip(), 1, chain.next_difficulty(), vec![coinbase]);
+        block.header.timestamp = chain.tip_block().header.timestamp + chrono::Duration::seconds(30);
+        while !block.header.meets_difficulty() {
+            block.header.nonce += 1;
+        }
+
+        // Issuance already at the schedule leaves no room for another reward
+        chain.emission.insert(0, Blockchain::scheduled_emission_at(1));
+        assert!(matches!(
+            chain.add_block(block.clone()),
+            Err(ChainError::EmissionExceedsSchedule { height: 1, .. })
+        ));
+        chain.emission.insert(0, GENESIS_REWARD);
+
+        chain.add_block(block).unwrap();
+        assert_eq!(chain.total_emission(), Some(Blockchain::scheduled_emission_at(1)));
+    }
+
+    /// Mine a regtest block paying the reward to `miner`
+    fn mine_regtest_block(chain: &Blockchain, miner: &Address, txs: Vec<Transaction>) -> Block {
+        let (miner_reward, treasury_reward, solidarity_reward) = chain.rewards_for_miner(miner);
+        let mut coinbase = Transaction::coinbase(miner.clone(), miner_reward, chain.height() + 1);
+        coinbase.outputs.push(TxOutput::new(Address::genesis_address(), treasury_reward));
+        coinbase.outputs.push(TxOutput::new(chain.solidarity_recipient(), solidarity_reward));
+
+        let mut transactions = vec![coinbase];
+        transactions.extend(txs);
+        let mut block = Block::new(chain.tip(), chain.height() + 1, chain.next_difficulty(), transactions);
+        block.header.timestamp = chain.tip_block().header.timestamp + chrono::Duration::seconds(30);
+        while !block.header.meets_difficulty() {
+            block.header.nonce += 1;
+        }
+        block
+    }
+
+    #[test]
+    fn test_balance_at_height() {
+        let mut chain = Blockchain::with_params(ChainParams::regtest());
+        let miner = Keypair::generate();
+        let payee = Keypair::generate().address();
+
+        let block = mine_regtest_block(&chain, &miner.address(), Vec::new());
+        let reward = block.transactions[0].outputs[0].amount;
+        let coinbase_hash = block.transactions[0].hash();
+        chain.add_block(block).unwrap();
+
+        let mut spend = Transaction::new_transfer(
+            vec![TxInput::new(coinbase_hash, 0)],
+            vec![TxOutput::new(payee.clone(), 1_000_000), TxOutput::new(miner.address(), reward - 1_100_000)],
+        );
+        let message = spend.signing_message();
+        spend.inputs[0].sign(miner.signing_key(), &message);
+        let block = mine_regtest_block(&chain, &Address::genesis_address(), vec![spend]);
+        chain.add_block(block).unwrap();
+
+        assert_eq!(chain.get_balance_at_height(&miner.address(), 0), Some(0));
+        assert_eq!(chain.get_balance_at_height(&miner.address(), 1), Some(reward));
+        assert_eq!(chain.get_balance_at_height(&miner.address(), 2), Some(reward - 1_100_000));
+        assert_eq!(chain.get_balance_at_height(&payee, 1), Some(0));
+        assert_eq!(chain.get_balance_at_height(&payee, 2), Some(1_000_000));
+        assert_eq!(chain.get_balance_at_height(&payee, 3), None);
+
+        // Undo data survives a save, and is rebuilt for files without it
+        let path = std::env::temp_dir().join(format!("aequitas-chain-{}.dat", rand::random::<u64>()));
+        chain.save(&path).unwrap();
+        let loaded = Blockchain::load(&path).unwrap();
+        assert_eq!(loaded.get_balance_at_height(&miner.address(), 1), Some(reward));
+        std::fs::remove_file(&path).ok();
+
+        chain.rebuild_undo();
+        assert_eq!(chain.get_balance_at_height(&miner.address(), 1), Some(reward));
+    }
+
+    #[test]
+    fn test_disconnect_tip() {
+        let mut chain = Blockchain::with_params(ChainParams::regtest());
+        assert!(matches!(chain.disconnect_tip(), Err(ChainError::CannotDisconnectGenesis)));
+
+        let miner = Keypair::generate();
+        let first = mine_regtest_block(&chain, &miner.address(), Vec::new());
+        let coinbase = first.transactions[0].clone();
+        chain.add_block(first.clone()).unwrap();
+        let commitment = chain.utxo_commitment();
+        let difficulty = chain.difficulty();
+
+        // The second block spends the first coinbase
+        let mut spend = Transaction::new_transfer(
+            vec![TxInput::new(coinbase.hash(), 0)],
+            vec![TxOutput::new(Keypair::generate().address(), coinbase.outputs[0].amount - 100_000)],
+        );
+        let message = spend.signing_message();
+        spend.inputs[0].sign(miner.signing_key(), &message);
+        let second = mine_regtest_block(&chain, &miner.address(), vec![spend]);
+        chain.add_block(second.clone()).unwrap();
+
+        assert_eq!(chain.disconnect_tip().unwrap().hash(), second.hash());
+        assert_eq!(chain.height(), 1);
+        assert_eq!(chain.utxo_commitment(), commitment);
+        assert_eq!(chain.difficulty(), difficulty);
+        assert_eq!(chain.total_emission_at(2), None);
+
+        // The block connects again after a plain disconnect...
+        chain.add_block(second.clone()).unwrap();
+
+        // ... but not once invalidated, with every block above it
+        let disconnected = chain.invalidate_block(&first.hash()).unwrap();
+        assert_eq!(disconnected.len(), 2);
+        assert_eq!(chain.height(), 0);
+        assert_eq!(chain.get_balance(&miner.address()), 0);
+        assert!(matches!(chain.add_block(first.clone()), Err(ChainError::InvalidatedBlock)));
+        assert!(matches!(chain.invalidate_block(&second.hash()), Err(ChainError::NotInMainChain)));
+        assert_eq!(chain.invalidated_blocks(), vec![first.hash()]);
+    }
+
+    #[test]
+    fn test_reconsider_block() {
+        let mut chain = Blockchain::with_params(ChainParams::regtest());
+        let miner = Address::genesis_address();
+        for _ in 0..3 {
+            let block = mine_regtest_block(&chain, &miner, Vec::new());
+            chain.add_block(block).unwrap();
+        }
+        let tip = chain.tip();
+        let invalid = chain.get_block_at_height(2).unwrap().hash();
+        chain.invalidate_block(&invalid).unwrap();
+        assert_eq!(chain.height(), 1);
+
+        // The mark survives a restart
+        let path = std::env::temp_dir().join(format!("aequitas-chain-{}.dat", rand::random::<u64>()));
+        chain.save(&path).unwrap();
+        let mut chain = Blockchain::load(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        chain.set_params(ChainParams::regtest());
+        assert_eq!(chain.invalidated_blocks(), vec![invalid]);
+
+        let reconnected = chain.reconsider_block(&invalid).unwrap();
+        assert_eq!(reconnected.len(), 2);
+        assert_eq!(chain.tip(), tip);
+        assert!(chain.invalidated_blocks().is_empty());
+        assert!(matches!(chain.reconsider_block(&invalid), Err(ChainError::NotInvalidated)));
+    }
+
+    #[test]
+    fn test_median_time_past() {
+        let chain = Blockchain::new();
+        let genesis_ts = chain.tip_block().header.timestamp.timestamp();
+        assert_eq!(chain.median_time_past(), genesis_ts);
+    }
+
+    #[test]
+    fn test_registry_epochs() {
+        let mut chain = Blockchain::with_params(ChainParams::regtest());
+        let miner = Keypair::generate().address();
+        for _ in 0..REGISTRY_EPOCH + 2 {
+            let mut block = mine_regtest_block(&chain, &miner, Vec::new());
+            block.header.extra_data = identity_commitment(&miner);
+            chain.add_block(block).unwrap();
+        }
+
+        // Block 119 completed the first epoch; later blocks wait for the next
+        assert_eq!(chain.solidarity_epochs.keys().copied().collect::<Vec<_>>(), vec![0]);
+        let registry = chain.miner_registry();
+        assert_eq!(registry.get(&miner).unwrap().blocks_found, REGISTRY_EPOCH - 1);
+
+        // Disconnecting down through the block that completed the epoch drops
+        // its tally, and reconnecting brings it back
+        let mut disconnected = Vec::new();
+        while chain.height() >= REGISTRY_EPOCH - 1 {
+            disconnected.push(chain.disconnect_tip().unwrap());
+        }
+        assert_eq!(disconnected.len(), 4);
+        assert!(chain.solidarity_epochs.is_empty());
+        assert!(chain.miner_registry().get(&miner).is_none());
+        for block in disconnected.into_iter().rev() {
+            chain.add_block(block).unwrap();
+        }
+        assert_eq!(chain.solidarity_epochs.keys().copied().collect::<Vec<_>>(), vec![0]);
+        assert_eq!(chain.miner_registry().miners(), registry.miners());
+
+        // Without the epoch's blocks, as after a snapshot, nothing changes
+        for height in 1..REGISTRY_EPOCH - 1 {
+            let hash = chain.height_index.remove(&height).unwrap();
+            chain.blocks.remove(&hash);
+        }
+        assert_eq!(chain.miner_registry().miners(), registry.miners());
+    }
+
+    #[test]
+    fn test_deployment_activation() {
+        let params = ChainParams { signal_period: 4, signal_threshold: 3, ..ChainParams::regtest() };
+        let mut chain = Blockchain::with_params(params);
+        let miner = Keypair::generate().address();
+        let mine = |chain: &mut Blockchain, signal: bool| {
+            let mut block = mine_regtest_block(chain, &miner, Vec::new());
+            block.header.version = if signal { chain.next_block_version() } else { 1 };
+            while !block.header.meets_difficulty() {
+                block.header.nonce += 1;
+            }
+            chain.add_block(block).unwrap();
+        };
+        let state = |chain: &Blockchain| chain.deployments()[0].state;
+
+        for _ in 0..3 {
+            mine(&mut chain, true);
+        }
+        // Signalling starts with the second period
+        assert_eq!((state(&chain), chain.deployments()[0].since), (DeploymentState::Started, 4));
+        assert_eq!(chain.next_block_version(), VERSION_BITS_TOP_BITS | 1 << 28);
+
+        for signal in [true, false, true, true] {
+            mine(&mut chain, signal);
+        }
+        assert_eq!(state(&chain), DeploymentState::LockedIn);
+        assert!(!chain.is_deployment_active("testdummy"));
+
+        // Disconnecting into the period uncounts its signals
+        let tip = chain.disconnect_tip().unwrap();
+        assert_eq!(state(&chain), DeploymentState::Started);
+        assert_eq!((chain.deployments()[0].signalling, chain.deployments()[0].elapsed), (2, 3));
+        chain.add_block(tip).unwrap();
+
+        for _ in 0..4 {
+            mine(&mut chain, false);
+        }
+        assert!(chain.is_deployment_active("testdummy"));
+        assert_eq!(chain.next_block_version(), VERSION_BITS_TOP_BITS);
+
+        // Counting again under the same period gives the same state
+        chain.rebuild_version_bits();
+        assert_eq!((state(&chain), chain.deployments()[0].since), (DeploymentState::Active, 12));
+    }
+
+    #[test]
+    fn test_lock_height() {
+        let mut chain = Blockchain::with_params(ChainParams::regtest());
+        let miner = Keypair::generate();
+        let first = mine_regtest_block(&chain, &miner.address(), Vec::new());
+        let coinbase = first.transactions[0].clone();
+        chain.add_block(first).unwrap();
+
+        let spend = |lock_height| {
+            let mut tx = Transaction::new_transfer(
+                vec![TxInput::new(coinbase.hash(), 0)],
+                vec![TxOutput::new(miner.address(), coinbase.outputs[0].amount - 100_000)],
+            )
+            .with_lock_height(lock_height);
+            let message = tx.signing_message();
+            tx.inputs[0].sign(miner.signing_key(), &message);
+            tx
+        };
+
+        // Locked to blocks above 2: not allowed in block 2
+        let locked = spend(2);
+        assert!(matches!(chain.check_final(&locked), Err(TxError::NotFinal(2))));
+        let block = mine_regtest_block(&chain, &miner.address(), vec![locked]);
+        assert!(matches!(chain.add_block(block), Err(ChainError::TxError(TxError::NotFinal(2)))));
+
+        // Locked to the tip: allowed in the next block
+        let at_tip = spend(chain.height());
+        assert!(chain.check_final(&at_tip).is_ok());
+        let block = mine_regtest_block(&chain, &miner.address(), vec![at_tip]);
+        chain.add_block(block).unwrap();
+    }
+
+    #[test]
+    fn test_reject_future_timestamp() {
+        let mut chain = Blockchain::new();
+        let mut block = Block::new(chain.tip(), 1, chain.next_difficulty(), Vec::new());
+        block.header.timestamp = chrono::Utc::now() + chrono::Duration::days(365);
+
+        assert!(matches!(
+            chain.add_block(block),
+            Err(ChainError::BlockError(BlockError::TimestampTooFarInFuture))
+        ));
+    }
+
+    #[test]
+    fn test_utxo_commitment_in_coinbase() {
+        let mut chain = Blockchain::with_params(ChainParams::regtest());
+        let miner = Address::genesis_address();
+        let mine = |chain: &Blockchain, commitment: [u8; 32]| {
+            let (miner_reward, treasury_reward, solidarity_reward) = chain.rewards_for_miner(&miner);
+            let mut coinbase = Transaction::coinbase(miner.clone(), miner_reward, 1);
+            coinbase.outputs.push(TxOutput::new(Address::genesis_address(), treasury_reward));
+            coinbase.outputs.push(TxOutput::new(chain.solidarity_recipient(), solidarity_reward));
+
+            let mut block = Block::new(chain.tip(), 1, chain.next_difficulty(), vec![coinbase]);
+            block.header.timestamp = chain.tip_block().header.timestamp + chrono::Duration::seconds(30);
+            block.commit_utxo_set(commitment);
+            while !block.header.meets_difficulty() {
+                block.header.nonce += 1;
+            }
+            block
+        };
+
+        assert!(matches!(
+            chain.add_block(mine(&chain, [0u8; 32])),
+            Err(ChainError::UtxoCommitmentMismatch)
+        ));
+
+        let block = mine(&chain, chain.utxo_commitment());
+        assert_eq!(block.utxo_commitment(), Some(chain.utxo_commitment()));
+        chain.add_block(block).unwrap();
+        assert_eq!(chain.height(), 1);
+        assert!(chain.tip_block().utxo_commitment().is_some());
+    }
+
     #[test]
     fn test_reward_halving() {
         assert_eq!(Blockchain::reward_for_height(0), GENESIS_REWARD);
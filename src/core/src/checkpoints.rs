@@ -0,0 +1,219 @@
+//! Chain checkpoints
+//!
+//! Known-good block hashes at fixed heights. Blocks at a checkpoint height
+//! must match the recorded hash.
+//!
+//! Signature checks may also be skipped during initial sync for the
+//! assumed-valid block and its ancestors. A height alone would let a peer
+//! serve a forged fork below it, so the block is named by its hash, and a
+//! block only skips the checks once a chain of headers has linked it to that
+//! hash. Output ownership is always checked.
+
+use std::collections::{BTreeMap, HashSet};
+use crate::block::BlockHeader;
+
+/// Hard-coded mainnet checkpoints (height, hex block hash)
+///
+/// Populated at release time from the canonical chain.
+pub const MAINNET_CHECKPOINTS: &[(u64, &str)] = &[];
+
+/// Set of height → hash checkpoints
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Checkpoints {
+    /// Expected block hash by height
+    points: BTreeMap<u64, [u8; 32]>,
+
+    /// Block whose ancestors' signatures are assumed valid
+    assume_valid: Option<[u8; 32]>,
+
+    /// Known ancestors of the assumed-valid block, itself included
+    assumed_ancestors: HashSet<[u8; 32]>,
+}
+
+impl Checkpoints {
+    /// Create an empty checkpoint set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load the hard-coded mainnet checkpoints
+    pub fn mainnet() -> Self {
+        let mut checkpoints = Self::new();
+        for (height, hash) in MAINNET_CHECKPOINTS {
+            checkpoints
+                .insert_hex(*height, hash)
+                .expect("Hard-coded checkpoint must be valid");
+        }
+        checkpoints
+    }
+
+    /// Add a checkpoint
+    pub fn insert(&mut self, height: u64, hash: [u8; 32]) {
+        self.points.insert(height, hash);
+    }
+
+    /// Add a checkpoint from a hex-encoded hash
+    pub fn insert_hex(&mut self, height: u64, hash_hex: &str) -> Result<(), CheckpointError> {
+        let bytes = hex::decode(hash_hex).map_err(|_| CheckpointError::InvalidHash(height))?;
+        let hash: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| CheckpointError::InvalidHash(height))?;
+        self.insert(height, hash);
+        Ok(())
+    }
+
+    /// Set the assumed-valid block, forgetting its known ancestors
+    pub fn set_assume_valid(&mut self, hash: Option<[u8; 32]>) {
+        self.assume_valid = hash;
+        self.assumed_ancestors.clear();
+    }
+
+    /// Set the assumed-valid block from a hex-encoded hash
+    pub fn set_assume_valid_hex(&mut self, hash_hex: &str) -> Result<(), CheckpointError> {
+        let hash: [u8; 32] = hex::decode(hash_hex)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or(CheckpointError::InvalidAssumeValid)?;
+        self.set_assume_valid(Some(hash));
+        Ok(())
+    }
+
+    /// Get the assumed-valid block hash
+    pub fn assume_valid(&self) -> Option<&[u8; 32]> {
+        self.assume_valid.as_ref()
+    }
+
+    /// Record the headers leading to the assumed-valid block as its
+    /// ancestors, returning how many were recorded
+    ///
+    /// `headers` must be in chain order, each linked to the one before. Only
+    /// the headers up to the assumed-valid one count; without it among them,
+    /// nothing is recorded.
+    pub fn learn_ancestors(&mut self, headers: &[BlockHeader]) -> usize {
+        let Some(assumed) = self.assume_valid else {
+            return 0;
+        };
+        let hashes: Vec<[u8; 32]> = headers.iter().map(BlockHeader::hash).collect();
+        let Some(end) = hashes.iter().position(|hash| *hash == assumed) else {
+            return 0;
+        };
+
+        // Walk back from the assumed-valid header while the links hold
+        let mut start = end;
+        while start > 0 && headers[start].prev_hash == hashes[start - 1] {
+            start -= 1;
+        }
+        self.assumed_ancestors.extend(&hashes[start..=end]);
+        end - start + 1
+    }
+
+    /// Get the expected hash at a height, if checkpointed
+    pub fn get(&self, height: u64) -> Option<&[u8; 32]> {
+        self.points.get(&height)
+    }
+
+    /// Check a block hash against the checkpoint at its height
+    pub fn check(&self, height: u64, hash: &[u8; 32]) -> Result<(), CheckpointError> {
+        match self.points.get(&height) {
+            Some(expected) if expected != hash => Err(CheckpointError::Mismatch(height)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether signatures must be verified for the block `hash`, which is
+    /// not a known ancestor of the assumed-valid block
+    pub fn should_verify_signatures(&self, hash: &[u8; 32]) -> bool {
+        !self.assumed_ancestors.contains(hash)
+    }
+
+    /// Height of the highest checkpoint
+    pub fn last_height(&self) -> Option<u64> {
+        self.points.keys().next_back().copied()
+    }
+
+    /// Number of checkpoints
+    pub fn len(&self) -> usize {
+        self.points.len()
+    }
+
+    /// Check if there are no checkpoints
+    pub fn is_empty(&self) -> bool {
+        self.points.is_empty()
+    }
+}
+
+/// Checkpoint errors
+#[derive(Debug, thiserror::Error)]
+pub enum CheckpointError {
+    #[error("Block hash does not match checkpoint at height {0}")]
+    Mismatch(u64),
+
+    #[error("Invalid checkpoint hash at height {0}")]
+    InvalidHash(u64),
+
+    #[error("Invalid assumed-valid block hash")]
+    InvalidAssumeValid,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checkpoint_check() {
+        let mut checkpoints = Checkpoints::new();
+        checkpoints.insert(10, [1u8; 32]);
+
+        assert!(checkpoints.check(10, &[1u8; 32]).is_ok());
+        assert!(checkpoints.check(10, &[2u8; 32]).is_err());
+        assert!(checkpoints.check(11, &[2u8; 32]).is_ok());
+        assert_eq!(checkpoints.last_height(), Some(10));
+    }
+
+    #[test]
+    fn test_insert_hex() {
+        let mut checkpoints = Checkpoints::new();
+        assert!(checkpoints.insert_hex(1, &"ab".repeat(32)).is_ok());
+        assert!(checkpoints.insert_hex(2, "abcd").is_err());
+        assert!(checkpoints.insert_hex(3, "zz").is_err());
+        assert_eq!(checkpoints.get(1), Some(&[0xab; 32]));
+    }
+
+    fn header_chain(len: usize, nonce: u64) -> Vec<BlockHeader> {
+        let mut headers: Vec<BlockHeader> = Vec::new();
+        for height in 0..len as u64 {
+            let prev_hash = headers.last().map_or([0u8; 32], BlockHeader::hash);
+            let mut header = BlockHeader::new(prev_hash, [0u8; 32], height, 1);
+            header.nonce = nonce;
+            headers.push(header);
+        }
+        headers
+    }
+
+    #[test]
+    fn test_assume_valid() {
+        let headers = header_chain(5, 0);
+        let mut checkpoints = Checkpoints::new();
+        assert_eq!(checkpoints.learn_ancestors(&headers), 0);
+        assert!(checkpoints.should_verify_signatures(&headers[0].hash()));
+
+        checkpoints.set_assume_valid(Some(headers[3].hash()));
+        assert_eq!(checkpoints.learn_ancestors(&headers[..3]), 0);
+        assert_eq!(checkpoints.learn_ancestors(&headers), 4);
+        assert!(!checkpoints.should_verify_signatures(&headers[0].hash()));
+        assert!(!checkpoints.should_verify_signatures(&headers[3].hash()));
+        assert!(checkpoints.should_verify_signatures(&headers[4].hash()));
+
+        // A fork at the same heights is not an ancestor
+        let fork = header_chain(5, 1);
+        assert!(checkpoints.should_verify_signatures(&fork[2].hash()));
+
+        // Nor is a header that does not link to the next one
+        let mut broken = headers.clone();
+        broken[2] = fork[2].clone();
+        checkpoints.set_assume_valid_hex(&hex::encode(headers[3].hash())).unwrap();
+        assert_eq!(checkpoints.learn_ancestors(&broken), 1);
+        assert!(checkpoints.should_verify_signatures(&headers[1].hash()));
+        assert!(checkpoints.set_assume_valid_hex("abcd").is_err());
+    }
+}
@@ -0,0 +1,298 @@
+//! Versioned consensus encoding
+//!
+//! `Block`, `BlockHeader` and `Transaction` are exchanged with wallets and
+//! pools as a format version followed by tagged fields, so a field can be
+//! added without breaking older decoders:
+//!
+//! ```text
+//! object = format_version:u8 field*
+//! field  = tag:u16 len:u32 value:[len]
+//! ```
+//!
+//! Integers are little-endian. Even tags are required: a decoder refuses an
+//! object carrying an even tag it does not know. Odd tags are optional and
+//! unknown ones are skipped, which is how new fields are introduced. Fields
+//! may appear in any order; a repeated field is only allowed where noted. A
+//! format version above [`CONSENSUS_FORMAT_VERSION`] marks a breaking change
+//! and is refused.
+//!
+//! This encoding only carries data: block hashes and txids are computed as
+//! before and do not depend on it.
+
+use chrono::{DateTime, Utc};
+use crate::block::{Block, BlockHeader};
+use crate::encoding::{self, EncodingError};
+use crate::transaction::Transaction;
+
+/// Newest format version this node reads and the one it writes
+pub const CONSENSUS_FORMAT_VERSION: u8 = 1;
+
+/// Transaction: canonical encoding from [`crate::encoding`]
+const TX_CANONICAL: u16 = 0;
+
+const HEADER_VERSION: u16 = 0;
+const HEADER_PREV_HASH: u16 = 2;
+const HEADER_MERKLE_ROOT: u16 = 4;
+/// Seconds (`i64`) followed by nanoseconds (`u32`)
+const HEADER_TIMESTAMP: u16 = 6;
+const HEADER_DIFFICULTY: u16 = 8;
+const HEADER_BITS: u16 = 10;
+const HEADER_NONCE: u16 = 12;
+const HEADER_HEIGHT: u16 = 14;
+const HEADER_EXTRA_DATA: u16 = 16;
+
+/// Block: the consensus-encoded header
+const BLOCK_HEADER: u16 = 0;
+/// Block: one consensus-encoded transaction, repeated in block order
+const BLOCK_TRANSACTION: u16 = 2;
+
+/// Types with a versioned consensus encoding
+pub trait ConsensusEncode: Sized {
+    /// Encode with the current format version
+    fn consensus_encode(&self) -> Vec<u8>;
+
+    /// Decode any format version up to the current one
+    fn consensus_decode(bytes: &[u8]) -> Result<Self, EncodingError>;
+}
+
+impl ConsensusEncode for Transaction {
+    fn consensus_encode(&self) -> Vec<u8> {
+        let mut out = Writer::new();
+        out.field(TX_CANONICAL, &encoding::encode(self));
+        out.finish()
+    }
+
+    fn consensus_decode(bytes: &[u8]) -> Result<Self, EncodingError> {
+        let fields = Fields::parse(bytes, &[TX_CANONICAL])?;
+        encoding::decode(fields.one(TX_CANONICAL)?)
+    }
+}
+
+impl ConsensusEncode for BlockHeader {
+    fn consensus_encode(&self) -> Vec<u8> {
+        let mut timestamp = self.timestamp.timestamp().to_le_bytes().to_vec();
+        timestamp.extend_from_slice(&self.timestamp.timestamp_subsec_nanos().to_le_bytes());
+
+        let mut out = Writer::new();
+        out.field(HEADER_VERSION, &self.version.to_le_bytes());
+        out.field(HEADER_PREV_HASH, &self.prev_hash);
+        out.field(HEADER_MERKLE_ROOT, &self.merkle_root);
+        out.field(HEADER_TIMESTAMP, &timestamp);
+        out.field(HEADER_DIFFICULTY, &self.difficulty.to_le_bytes());
+        out.field(HEADER_BITS, &self.bits.to_le_bytes());
+        out.field(HEADER_NONCE, &self.nonce.to_le_bytes());
+        out.field(HEADER_HEIGHT, &self.height.to_le_bytes());
+        out.field(HEADER_EXTRA_DATA, &self.extra_data);
+        out.finish()
+    }
+
+    fn consensus_decode(bytes: &[u8]) -> Result<Self, EncodingError> {
+        let fields = Fields::parse(bytes, &[
+            HEADER_VERSION,
+            HEADER_PREV_HASH,
+            HEADER_MERKLE_ROOT,
+            HEADER_TIMESTAMP,
+            HEADER_DIFFICULTY,
+            HEADER_BITS,
+            HEADER_NONCE,
+            HEADER_HEIGHT,
+            HEADER_EXTRA_DATA,
+        ])?;
+
+        let timestamp: [u8; 12] = fields.fixed(HEADER_TIMESTAMP)?;
+        let secs = i64::from_le_bytes(timestamp[..8].try_into().unwrap());
+        let nanos = u32::from_le_bytes(timestamp[8..].try_into().unwrap());
+        let timestamp = DateTime::<Utc>::from_timestamp(secs, nanos)
+            .ok_or(EncodingError::InvalidTimestamp)?;
+
+        Ok(BlockHeader {
+            version: u32::from_le_bytes(fields.fixed(HEADER_VERSION)?),
+            prev_hash: fields.fixed(HEADER_PREV_HASH)?,
+            merkle_root: fields.fixed(HEADER_MERKLE_ROOT)?,
+            timestamp,
+            difficulty: u64::from_le_bytes(fields.fixed(HEADER_DIFFICULTY)?),
+            bits: u32::from_le_bytes(fields.fixed(HEADER_BITS)?),
+            nonce: u64::from_le_bytes(fields.fixed(HEADER_NONCE)?),
+            height: u64::from_le_bytes(fields.fixed(HEADER_HEIGHT)?),
+            extra_data: fields.fixed(HEADER_EXTRA_DATA)?,
+        })
+    }
+}
+
+impl ConsensusEncode for Block {
+    fn consensus_encode(&self) -> Vec<u8> {
+        let mut out = Writer::new();
+        out.field(BLOCK_HEADER, &self.header.consensus_encode());
+        for tx in &self.transactions {
+            out.field(BLOCK_TRANSACTION, &tx.consensus_encode());
+        }
+        out.finish()
+    }
+
+    fn consensus_decode(bytes: &[u8]) -> Result<Self, EncodingError> {
+        let fields = Fields::parse(bytes, &[BLOCK_HEADER, BLOCK_TRANSACTION])?;
+        Ok(Block {
+            header: BlockHeader::consensus_decode(fields.one(BLOCK_HEADER)?)?,
+            transactions: fields.all(BLOCK_TRANSACTION)
+                .map(Transaction::consensus_decode)
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+/// Builds an encoded object field by field
+struct Writer {
+    out: Vec<u8>,
+}
+
+impl Writer {
+    fn new() -> Self {
+        Self { out: vec![CONSENSUS_FORMAT_VERSION] }
+    }
+
+    fn field(&mut self, tag: u16, value: &[u8]) {
+        self.out.extend_from_slice(&tag.to_le_bytes());
+        self.out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        self.out.extend_from_slice(value);
+    }
+
+    fn finish(self) -> Vec<u8> {
+        self.out
+    }
+}
+
+/// Known fields of a decoded object, unknown optional ones dropped
+struct Fields<'a> {
+    fields: Vec<(u16, &'a [u8])>,
+}
+
+impl<'a> Fields<'a> {
+    fn parse(bytes: &'a [u8], known: &[u16]) -> Result<Self, EncodingError> {
+        let (&version, mut rest) = bytes.split_first().ok_or(EncodingError::Truncated(0))?;
+        if version == 0 || version > CONSENSUS_FORMAT_VERSION {
+            return Err(EncodingError::UnsupportedVersion(version));
+        }
+
+        let mut fields = Vec::new();
+        while !rest.is_empty() {
+            let offset = bytes.len() - rest.len();
+            if rest.len() < 6 {
+                return Err(EncodingError::Truncated(offset));
+            }
+            let tag = u16::from_le_bytes([rest[0], rest[1]]);
+            let len = u32::from_le_bytes([rest[2], rest[3], rest[4], rest[5]]) as usize;
+            let value = rest.get(6..6 + len).ok_or(EncodingError::Truncated(offset))?;
+            rest = &rest[6 + len..];
+
+            if known.contains(&tag) {
+                fields.push((tag, value));
+            } else if tag % 2 == 0 {
+                return Err(EncodingError::UnknownRequiredField(tag));
+            }
+        }
+
+        Ok(Self { fields })
+    }
+
+    /// Every value of a repeatable field, in encoding order
+    fn all(&self, tag: u16) -> impl Iterator<Item = &'a [u8]> + '_ {
+        self.fields.iter().filter(move |(t, _)| *t == tag).map(|(_, value)| *value)
+    }
+
+    /// The value of a field that must appear exactly once
+    fn one(&self, tag: u16) -> Result<&'a [u8], EncodingError> {
+        let mut values = self.all(tag);
+        let value = values.next().ok_or(EncodingError::MissingField(tag))?;
+        if values.next().is_some() {
+            return Err(EncodingError::DuplicateField(tag));
+        }
+        Ok(value)
+    }
+
+    /// The value of a fixed-size field that must appear exactly once
+    fn fixed<const N: usize>(&self, tag: u16) -> Result<[u8; N], EncodingError> {
+        self.one(tag)?
+            .try_into()
+            .map_err(|_| EncodingError::InvalidFieldLength(tag))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::Address;
+
+    fn push_field(bytes: &mut Vec<u8>, tag: u16, value: &[u8]) {
+        bytes.extend_from_slice(&tag.to_le_bytes());
+        bytes.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(value);
+    }
+
+    fn sample_block() -> Block {
+        let mut block = Block::genesis();
+        block.header.nonce = 42;
+        block.header.extra_data = [7u8; 32];
+        block.transactions.push(Transaction::coinbase(Address::genesis_address(), 1_000, 1));
+        block
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let block = sample_block();
+        let decoded = Block::consensus_decode(&block.consensus_encode()).unwrap();
+        assert_eq!(decoded.header, block.header);
+        assert_eq!(decoded.hash(), block.hash());
+        assert_eq!(decoded.transactions.len(), 2);
+        assert_eq!(decoded.transactions[1].txid(), block.transactions[1].txid());
+
+        let tx = &block.transactions[0];
+        let decoded = Transaction::consensus_decode(&tx.consensus_encode()).unwrap();
+        assert_eq!(decoded.wtxid(), tx.wtxid());
+    }
+
+    #[test]
+    fn test_unknown_fields() {
+        let header = sample_block().header;
+        let mut bytes = header.consensus_encode();
+
+        // A newer optional field is skipped by this decoder
+        push_field(&mut bytes, 101, b"future");
+        assert_eq!(BlockHeader::consensus_decode(&bytes).unwrap(), header);
+
+        // A newer required field is refused
+        push_field(&mut bytes, 100, b"future");
+        assert_eq!(
+            BlockHeader::consensus_decode(&bytes).unwrap_err(),
+            EncodingError::UnknownRequiredField(100),
+        );
+    }
+
+    #[test]
+    fn test_decode_rejects_malformed() {
+        let tx = Transaction::coinbase(Address::genesis_address(), 1_000, 1);
+        let bytes = tx.consensus_encode();
+
+        let mut newer = bytes.clone();
+        newer[0] = CONSENSUS_FORMAT_VERSION + 1;
+        assert_eq!(
+            Transaction::consensus_decode(&newer).unwrap_err(),
+            EncodingError::UnsupportedVersion(CONSENSUS_FORMAT_VERSION + 1),
+        );
+
+        assert!(matches!(
+            Transaction::consensus_decode(&bytes[..bytes.len() - 1]),
+            Err(EncodingError::Truncated(_))
+        ));
+        assert_eq!(
+            Transaction::consensus_decode(&[CONSENSUS_FORMAT_VERSION]).unwrap_err(),
+            EncodingError::MissingField(TX_CANONICAL),
+        );
+
+        let mut duplicated = bytes.clone();
+        duplicated.extend_from_slice(&bytes[1..]);
+        assert_eq!(
+            Transaction::consensus_decode(&duplicated).unwrap_err(),
+            EncodingError::DuplicateField(TX_CANONICAL),
+        );
+    }
+}
@@ -1,10 +1,12 @@
 //! Difficulty adjustment algorithm for Aequitas
 //!
-//! Implements a smooth difficulty adjustment algorithm (DAA) that adjusts
-//! every block to maintain a target block time of 30 seconds.
+//! Implements a linearly weighted moving average (LWMA) difficulty adjustment
+//! that retargets every block to maintain a target block time of 30 seconds.
+//! All consensus arithmetic is integer-only so every platform computes the
+//! same next difficulty.
 
+use crate::target::{difficulty_to_target, hash_meets_target};
 use num_bigint::BigUint;
-use num_traits::{One, Zero};
 
 /// Target block time in seconds
 pub const TARGET_BLOCK_TIME: u64 = 30;
@@ -12,11 +14,11 @@ pub const TARGET_BLOCK_TIME: u64 = 30;
 /// Number of blocks to average for difficulty calculation
 pub const DIFFICULTY_AVERAGING_WINDOW: u64 = 60;
 
-/// Maximum difficulty adjustment per block (10%)
-pub const MAX_ADJUSTMENT_FACTOR: f64 = 1.10;
+/// Maximum difficulty change per block, in percent
+pub const MAX_ADJUSTMENT_PERCENT: u64 = 10;
 
-/// Minimum difficulty adjustment per block (90%)
-pub const MIN_ADJUSTMENT_FACTOR: f64 = 0.90;
+/// Solvetimes are capped at this many target block times
+pub const MAX_SOLVETIME_FACTOR: i64 = 6;
 
 /// Minimum difficulty value
 pub const MIN_DIFFICULTY: u64 = 1000;
@@ -42,58 +44,65 @@ impl Difficulty {
     }
     
     /// Calculate target from difficulty
-    /// Target = MAX_TARGET / difficulty
+    /// Target = (2^256 - 1) / difficulty
     pub fn target(&self) -> BigUint {
-        let max_target = BigUint::from(u64::MAX);
-        max_target / BigUint::from(self.value)
+        BigUint::from_bytes_be(&difficulty_to_target(self.value))
     }
     
     /// Check if a hash meets this difficulty
     pub fn hash_meets_target(&self, hash: &[u8; 32]) -> bool {
-        let hash_value = BigUint::from_bytes_be(hash);
-        hash_value <= self.target()
+        hash_meets_target(hash, &difficulty_to_target(self.value))
     }
     
-    /// Calculate next difficulty based on block times
+    /// Calculate next difficulty with LWMA
     ///
     /// # Arguments
-    /// * `block_times` - Vector of (height, timestamp) for recent blocks
+    /// * `history` - (timestamp, difficulty) of recent blocks, oldest first,
+    ///   ending with the current tip
     ///
     /// # Returns
     /// New difficulty value
-    pub fn calculate_next(
-        current: u64,
-        block_times: &[(u64, i64)],
-    ) -> u64 {
-        if block_times.len() < 2 {
-            return current;
+    pub fn calculate_next(history: &[(i64, u64)]) -> u64 {
+        let current = match history.last() {
+            Some(&(_, difficulty)) => difficulty,
+            None => return MIN_DIFFICULTY,
+        };
+        if history.len() < 2 {
+            return current.max(MIN_DIFFICULTY);
         }
         
-        // Calculate average block time
-        let window_size = block_times.len().min(DIFFICULTY_AVERAGING_WINDOW as usize);
-        let recent = &block_times[block_times.len() - window_size..];
-        
-        let time_span = (recent.last().unwrap().1 - recent.first().unwrap().1) as f64;
-        let block_count = (recent.len() - 1) as f64;
+        let n = (history.len() - 1).min(DIFFICULTY_AVERAGING_WINDOW as usize);
+        let window = &history[history.len() - n - 1..];
+        let target = TARGET_BLOCK_TIME as i64;
         
-        if block_count == 0.0 {
-            return current;
+        // Recent solvetimes weigh more; out-of-order timestamps count as
+        // one second and solvetimes are capped, which blunts timestamp attacks
+        let mut previous = window[0].0;
+        let mut weighted_solvetimes: u128 = 0;
+        let mut difficulty_sum: u128 = 0;
+        for (weight, &(timestamp, difficulty)) in window[1..].iter().enumerate() {
+            let timestamp = timestamp.max(previous + 1);
+            let solvetime = (timestamp - previous).min(MAX_SOLVETIME_FACTOR * target);
+            previous = timestamp;
+            
+            weighted_solvetimes += solvetime as u128 * (weight as u128 + 1);
+            difficulty_sum += difficulty as u128;
         }
         
-        let average_time = time_span / block_count;
-        let target_time = TARGET_BLOCK_TIME as f64;
-        
-        // Calculate adjustment factor
-        let mut adjustment = target_time / average_time;
-        
-        // Clamp adjustment to prevent extreme changes
-        adjustment = adjustment.clamp(MIN_ADJUSTMENT_FACTOR, MAX_ADJUSTMENT_FACTOR);
+        // next = average difficulty * target / weighted average solvetime
+        let n = n as u128;
+        let weight_sum = n * (n + 1) / 2;
+        let next = difficulty_sum * weight_sum * TARGET_BLOCK_TIME as u128
+            / (n * weighted_solvetimes);
         
-        // Calculate new difficulty
-        let new_difficulty = (current as f64 * adjustment) as u64;
+        // Bound the change per block
+        let current = current as u128;
+        let max = current * (100 + MAX_ADJUSTMENT_PERCENT as u128) / 100;
+        let min = current * (100 - MAX_ADJUSTMENT_PERCENT as u128) / 100;
+        let next = next.clamp(min, max).min(u64::MAX as u128) as u64;
         
         // Ensure minimum difficulty
-        new_difficulty.max(MIN_DIFFICULTY)
+        next.max(MIN_DIFFICULTY)
     }
 }
 
@@ -172,6 +181,7 @@ impl BlockTimeStats {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use num_traits::Zero;
     
     #[test]
     fn test_difficulty_target() {
@@ -184,11 +194,11 @@ mod tests {
     fn test_difficulty_adjustment_faster() {
         // If blocks are coming too fast, difficulty should increase
         let current = 10000;
-        let times: Vec<(u64, i64)> = (0..10)
-            .map(|i| (i, (i * 15) as i64)) // 15 second blocks
+        let times: Vec<(i64, u64)> = (0..10)
+            .map(|i| (i * 15, current)) // 15 second blocks
             .collect();
         
-        let new_diff = Difficulty::calculate_next(current, &times);
+        let new_diff = Difficulty::calculate_next(&times);
         assert!(new_diff > current);
     }
     
@@ -196,14 +206,77 @@ mod tests {
     fn test_difficulty_adjustment_slower() {
         // If blocks are coming too slow, difficulty should decrease
         let current = 10000;
-        let times: Vec<(u64, i64)> = (0..10)
-            .map(|i| (i, (i * 60) as i64)) // 60 second blocks
+        let times: Vec<(i64, u64)> = (0..10)
+            .map(|i| (i * 60, current)) // 60 second blocks
             .collect();
         
-        let new_diff = Difficulty::calculate_next(current, &times);
+        let new_diff = Difficulty::calculate_next(&times);
         assert!(new_diff < current);
     }
     
+    /// Steady window of on-target blocks at a fixed difficulty
+    fn steady_history(difficulty: u64) -> Vec<(i64, u64)> {
+        (0..=DIFFICULTY_AVERAGING_WINDOW as i64)
+            .map(|i| (i * TARGET_BLOCK_TIME as i64, difficulty))
+            .collect()
+    }
+    
+    #[test]
+    fn test_difficulty_stable_on_target() {
+        assert_eq!(Difficulty::calculate_next(&steady_history(30_000)), 30_000);
+        assert_eq!(Difficulty::calculate_next(&[]), MIN_DIFFICULTY);
+        assert_eq!(Difficulty::calculate_next(&[(0, 50_000)]), 50_000);
+    }
+    
+    #[test]
+    fn test_oscillating_hashrate() {
+        // Hashrate swings 10x every 30 blocks; solvetime = difficulty / hashrate
+        let blocks = 3000;
+        let mut history = vec![(0i64, 3_000u64)];
+        let mut now = 0i64;
+        
+        for height in 1..=blocks {
+            let difficulty = Difficulty::calculate_next(&history);
+            let hashrate = if (height / 30) % 2 == 0 { 100 } else { 1000 };
+            now += (difficulty / hashrate).max(1) as i64;
+            history.push((now, difficulty));
+        }
+        
+        let target = TARGET_BLOCK_TIME as i64;
+        let average = now / blocks as i64;
+        assert!(average >= target * 8 / 10 && average <= target * 12 / 10, "average {}", average);
+        
+        // Difficulty stays between the two hashrates' equilibria
+        for &(_, difficulty) in &history[300..] {
+            assert!(difficulty >= 100 * TARGET_BLOCK_TIME && difficulty <= 1000 * TARGET_BLOCK_TIME);
+        }
+    }
+    
+    #[test]
+    fn test_future_timestamp_attack() {
+        // One timestamp far in the future cannot crash difficulty
+        let mut history = steady_history(30_000);
+        let tip = history.last().unwrap().0;
+        history.push((tip + 100_000, 30_000));
+        let after_attack = Difficulty::calculate_next(&history);
+        assert_eq!(after_attack, 27_000);
+        
+        // An honest block timestamped before the fake one counts as a
+        // one-second solve, so the drop does not compound
+        history.push((tip + 60, after_attack));
+        assert!(Difficulty::calculate_next(&history) * 100 >= after_attack * 98);
+    }
+    
+    #[test]
+    fn test_backdated_timestamp_attack() {
+        // Identical or decreasing timestamps only raise difficulty by the per-block bound
+        let same: Vec<(i64, u64)> = vec![(1_000, 30_000); 61];
+        assert_eq!(Difficulty::calculate_next(&same), 33_000);
+        
+        let backwards: Vec<(i64, u64)> = (0..61).map(|i| (1_000 - i, 30_000)).collect();
+        assert_eq!(Difficulty::calculate_next(&backwards), 33_000);
+    }
+    
     #[test]
     fn test_min_difficulty() {
         let d = Difficulty::new(0);
@@ -0,0 +1,344 @@
+//! Canonical transaction encoding
+//!
+//! A transaction is encoded as a body followed by a witness section. The body
+//! holds everything the signatures commit to; the witness holds the
+//! signatures, public keys and multisig policies, one entry per input. All
+//! integers are little-endian and variable-length fields carry a `u32` length
+//! prefix:
+//!
+//! ```text
+//! body    = version:u32 tx_type:u8
+//!           n_inputs:u32  (prev_tx_hash:[32] output_index:u32)*
+//!           n_outputs:u32 (amount:u64 recipient:[20])*
+//!           timestamp:i64 memo:bytes replaceable:u8
+//!           lock_height:u64                           version >= 2 only
+//! witness = (signature:bytes public_key:bytes multisig)*   one per input
+//! multisig = 0 | 1 threshold:u8 n_keys:u8 key:[32]* n_sigs:u8 (key_index:u8 signature:bytes)*
+//! ```
+//!
+//! Version 1 bodies end at `replaceable`, so their txids are unchanged by
+//! the lock height. The txid hashes the body only, so attaching or re-encoding signatures
+//! never changes it; the wtxid hashes the full encoding.
+
+use crate::address::Address;
+use crate::transaction::{
+    MultisigPolicy, MultisigSignature, Transaction, TxInput, TxOutput, TxType, LOCK_HEIGHT_TX_VERSION,
+};
+
+/// Encode the body (everything except witness data)
+pub fn encode_body(tx: &Transaction) -> Vec<u8> {
+    let mut out = Vec::new();
+    encode_body_into(tx, &mut out);
+    out
+}
+
+/// Append the body encoding to `out`
+pub fn encode_body_into(tx: &Transaction, out: &mut Vec<u8>) {
+    out.extend_from_slice(&tx.version.to_le_bytes());
+    out.push(tx_type_byte(&tx.tx_type));
+
+    out.extend_from_slice(&(tx.inputs.len() as u32).to_le_bytes());
+    for input in &tx.inputs {
+        out.extend_from_slice(&input.prev_tx_hash);
+        out.extend_from_slice(&input.output_index.to_le_bytes());
+    }
+
+    out.extend_from_slice(&(tx.outputs.len() as u32).to_le_bytes());
+    for output in &tx.outputs {
+        out.extend_from_slice(&output.amount.to_le_bytes());
+        out.extend_from_slice(output.recipient.as_bytes());
+    }
+
+    out.extend_from_slice(&tx.timestamp.to_le_bytes());
+    write_bytes(out, &tx.memo);
+    out.push(tx.replaceable as u8);
+    if tx.version >= LOCK_HEIGHT_TX_VERSION {
+        out.extend_from_slice(&tx.lock_height.to_le_bytes());
+    }
+}
+
+/// Encode a full transaction (body followed by witness)
+pub fn encode(tx: &Transaction) -> Vec<u8> {
+    let mut out = Vec::new();
+    encode_into(tx, &mut out);
+    out
+}
+
+/// Append the full encoding to `out`
+pub fn encode_into(tx: &Transaction, out: &mut Vec<u8>) {
+    encode_body_into(tx, out);
+    for input in &tx.inputs {
+        write_bytes(out, &input.signature);
+        write_bytes(out, &input.public_key);
+
+        match &input.multisig {
+            None => out.push(0),
+            Some(policy) => {
+                out.push(1);
+                out.push(policy.threshold);
+                out.push(policy.public_keys.len() as u8);
+                for key in &policy.public_keys {
+                    out.extend_from_slice(key);
+                }
+                out.push(input.multisig_signatures.len() as u8);
+                for sig in &input.multisig_signatures {
+                    out.push(sig.key_index);
+                    write_bytes(out, &sig.signature);
+                }
+            }
+        }
+    }
+}
+
+/// Decode a full transaction, rejecting trailing bytes
+pub fn decode(bytes: &[u8]) -> Result<Transaction, EncodingError> {
+    let mut r = Reader { bytes, pos: 0 };
+
+    let version = r.u32()?;
+    let tx_type = match r.u8()? {
+        0 => TxType::Transfer,
+        1 => TxType::Coinbase,
+        2 => TxType::Vote,
+        3 => TxType::Proposal,
+        4 => TxType::TreasurySpend,
+        other => return Err(EncodingError::InvalidTxType(other)),
+    };
+
+    let mut inputs = Vec::new();
+    for _ in 0..r.u32()? {
+        let prev_tx_hash = r.array::<32>()?;
+        let output_index = r.u32()?;
+        inputs.push(TxInput::new(prev_tx_hash, output_index));
+    }
+
+    let mut outputs = Vec::new();
+    for _ in 0..r.u32()? {
+        let amount = r.u64()?;
+        let recipient = Address::from_bytes(r.array::<20>()?);
+        outputs.push(TxOutput::new(recipient, amount));
+    }
+
+    let timestamp = r.u64()? as i64;
+    let memo = r.bytes()?;
+    let replaceable = r.flag()?;
+    let lock_height = if version >= LOCK_HEIGHT_TX_VERSION { r.u64()? } else { 0 };
+
+    for input in &mut inputs {
+        input.signature = r.bytes()?;
+        input.public_key = r.bytes()?;
+
+        if r.flag()? {
+            let threshold = r.u8()?;
+            let mut public_keys = Vec::new();
+            for _ in 0..r.u8()? {
+                public_keys.push(r.array::<32>()?);
+            }
+            input.multisig = Some(MultisigPolicy { threshold, public_keys });
+
+            for _ in 0..r.u8()? {
+                let key_index = r.u8()?;
+                let signature = r.bytes()?;
+                input.multisig_signatures.push(MultisigSignature { key_index, signature });
+            }
+        }
+    }
+
+    if r.pos != bytes.len() {
+        return Err(EncodingError::TrailingBytes(bytes.len() - r.pos));
+    }
+
+    Ok(Transaction {
+        version,
+        tx_type,
+        inputs,
+        outputs,
+        timestamp,
+        memo,
+        replaceable,
+        lock_height,
+    })
+}
+
+fn tx_type_byte(tx_type: &TxType) -> u8 {
+    match tx_type {
+        TxType::Transfer => 0,
+        TxType::Coinbase => 1,
+        TxType::Vote => 2,
+        TxType::Proposal => 3,
+        TxType::TreasurySpend => 4,
+    }
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    out.extend_from_slice(bytes);
+}
+
+/// Cursor over encoded bytes
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], EncodingError> {
+        let end = self.pos.checked_add(len)
+            .filter(|end| *end <= self.bytes.len())
+            .ok_or(EncodingError::Truncated(self.pos))?;
+        let slice = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(slice)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], EncodingError> {
+        let mut out = [0u8; N];
+        out.copy_from_slice(self.take(N)?);
+        Ok(out)
+    }
+
+    fn u8(&mut self) -> Result<u8, EncodingError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, EncodingError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, EncodingError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn flag(&mut self) -> Result<bool, EncodingError> {
+        let offset = self.pos;
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(EncodingError::InvalidFlag(offset)),
+        }
+    }
+
+    fn bytes(&mut self) -> Result<Vec<u8>, EncodingError> {
+        let len = self.u32()? as usize;
+        Ok(self.take(len)?.to_vec())
+    }
+}
+
+/// Transaction decoding errors
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum EncodingError {
+    #[error("Transaction truncated at offset {0}")]
+    Truncated(usize),
+
+    #[error("Unknown transaction type {0}")]
+    InvalidTxType(u8),
+
+    #[error("Invalid flag byte at offset {0}")]
+    InvalidFlag(usize),
+
+    #[error("{0} trailing bytes after transaction")]
+    TrailingBytes(usize),
+
+    #[error("Unsupported consensus format version {0}")]
+    UnsupportedVersion(u8),
+
+    #[error("Unknown required field {0}")]
+    UnknownRequiredField(u16),
+
+    #[error("Missing field {0}")]
+    MissingField(u16),
+
+    #[error("Field {0} appears more than once")]
+    DuplicateField(u16),
+
+    #[error("Field {0} has an invalid length")]
+    InvalidFieldLength(u16),
+
+    #[error("Invalid block timestamp")]
+    InvalidTimestamp,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::TxError;
+    use ed25519_dalek::SigningKey;
+
+    fn signed_transfer() -> (Transaction, Transaction) {
+        let key = SigningKey::from_bytes(&[3u8; 32]);
+        let mut tx = Transaction::new_transfer(
+            vec![TxInput::new([1u8; 32], 0), TxInput::new([2u8; 32], 1)],
+            vec![TxOutput::new(Address::genesis_address(), 5_000)],
+        );
+        tx.memo = b"memo".to_vec();
+        tx.replaceable = true;
+
+        let unsigned = tx.clone();
+        let message = tx.signing_message();
+        for input in &mut tx.inputs {
+            input.sign(&key, &message);
+        }
+        (unsigned, tx)
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let (_, tx) = signed_transfer();
+        let decoded = decode(&encode(&tx)).unwrap();
+        assert_eq!(encode(&decoded), encode(&tx));
+        assert!(decoded.validate().is_ok());
+
+        let keys = [9u8, 8u8].map(|seed| SigningKey::from_bytes(&[seed; 32]).verifying_key().to_bytes());
+        let policy = MultisigPolicy::new(1, keys.to_vec()).unwrap();
+        let mut multisig = tx.clone();
+        multisig.inputs[0] = TxInput::new_multisig([1u8; 32], 0, policy);
+        let unsigned = multisig.clone();
+        multisig.inputs[0].multisig_signatures.push(MultisigSignature { key_index: 1, signature: vec![7; 64] });
+        let decoded = decode(&encode(&multisig)).unwrap();
+        assert_eq!(decoded.inputs, multisig.inputs);
+        assert_eq!(encode(&decoded), encode(&multisig));
+
+        // The cosigner signatures are witness data only
+        assert_eq!(multisig.txid(), unsigned.txid());
+        assert_ne!(multisig.wtxid(), unsigned.wtxid());
+    }
+
+    #[test]
+    fn test_lock_height_encoding() {
+        let (unsigned, _) = signed_transfer();
+        let locked = unsigned.clone().with_lock_height(7);
+        assert_eq!(encode_body(&locked).len(), encode_body(&unsigned).len() + 8);
+        assert_eq!(decode(&encode(&locked)).unwrap().lock_height, 7);
+        assert_ne!(locked.txid(), unsigned.clone().with_lock_height(8).txid());
+
+        // Version 1 does not commit to a lock height
+        let mut uncommitted = unsigned;
+        uncommitted.lock_height = 7;
+        assert!(matches!(uncommitted.validate_structure(), Err(TxError::UncommittedLockHeight)));
+    }
+
+    #[test]
+    fn test_txid_ignores_witness() {
+        let (unsigned, signed) = signed_transfer();
+        assert_eq!(unsigned.txid(), signed.txid());
+        assert_ne!(unsigned.wtxid(), signed.wtxid());
+
+        let coinbase = Transaction::coinbase(Address::genesis_address(), 1_000, 5);
+        assert_eq!(coinbase.txid(), coinbase.wtxid());
+    }
+
+    #[test]
+    fn test_decode_rejects_malformed() {
+        let (_, tx) = signed_transfer();
+        let bytes = encode(&tx);
+
+        assert!(matches!(decode(&bytes[..bytes.len() - 1]), Err(EncodingError::Truncated(_))));
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert_eq!(decode(&trailing).unwrap_err(), EncodingError::TrailingBytes(1));
+
+        let mut bad_type = bytes;
+        bad_type[4] = 9;
+        assert_eq!(decode(&bad_type).unwrap_err(), EncodingError::InvalidTxType(9));
+    }
+}
@@ -0,0 +1,677 @@
+//! On-chain governance
+//!
+//! Proposals and votes are ordinary signed transactions of type
+//! [`TxType::Proposal`] and [`TxType::Vote`] whose memo carries the payload:
+//!
+//! ```text
+//! proposal = "AEQP" voting_start:u64 voting_end:u64 amount:u64
+//!            title_len:u8 title description
+//! vote     = "AEQV" proposal_id:[32] choice:u8   (0 = no, 1 = yes, 2 = abstain)
+//! ```
+//!
+//! A proposal pays a [`PROPOSAL_DEPOSIT`] to the treasury and is identified by
+//! its txid. Each address has one vote per proposal (a later vote replaces
+//! an earlier one), cast from the owner of the vote's first input. Votes are
+//! weighted by the voter's balance when voting closes, so coins moved between
+//! addresses during the window are only counted once.
+//!
+//! Treasury outputs can only be spent by a [`TxType::TreasurySpend`] whose
+//! memo is `"AEQT" proposal_id:[32]`. The proposal must have passed with a
+//! non-zero `amount`; the spend pays exactly that amount to the proposer
+//! (any change goes back to the treasury) and each proposal pays out once.
+
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::address::Address;
+use crate::amount::COIN;
+use crate::transaction::{Transaction, TxOutput, TxType};
+
+/// Deposit paid to the treasury to open a proposal
+pub const PROPOSAL_DEPOSIT: u64 = 10 * COIN;
+
+/// Default voting period (~7 days at 30 second blocks)
+pub const VOTING_PERIOD: u64 = 20_160;
+
+/// Shortest allowed voting period (~1 day)
+pub const MIN_VOTING_PERIOD: u64 = 2_880;
+
+/// Longest allowed voting period (~28 days)
+pub const MAX_VOTING_PERIOD: u64 = 4 * VOTING_PERIOD;
+
+/// Furthest in the future voting may start after the proposal is mined
+pub const MAX_VOTING_DELAY: u64 = 2_880;
+
+/// Minimum turnout, in percent of circulating supply
+pub const QUORUM_PERCENTAGE: u64 = 10;
+
+/// Share of yes votes (excluding abstentions) needed to pass, in percent
+pub const PASS_PERCENTAGE: u64 = 50;
+
+/// Share of yes votes needed to pass a treasury spend, in percent
+pub const SPEND_PASS_PERCENTAGE: u64 = 66;
+
+/// Maximum title length in bytes
+pub const MAX_TITLE_LEN: usize = 64;
+
+const PROPOSAL_MAGIC: &[u8; 4] = b"AEQP";
+const VOTE_MAGIC: &[u8; 4] = b"AEQV";
+const TREASURY_MAGIC: &[u8; 4] = b"AEQT";
+
+/// Proposal contents carried in a proposal transaction memo
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProposalPayload {
+    /// First height at which votes are accepted
+    pub voting_start: u64,
+
+    /// Last height at which votes are accepted
+    pub voting_end: u64,
+
+    /// Requested treasury spend (0 for non-spending proposals)
+    pub amount: u64,
+
+    /// Short title
+    pub title: String,
+
+    /// Free-form description
+    pub description: String,
+}
+
+impl ProposalPayload {
+    /// Encode as a transaction memo
+    pub fn to_memo(&self) -> Vec<u8> {
+        let mut memo = PROPOSAL_MAGIC.to_vec();
+        memo.extend_from_slice(&self.voting_start.to_le_bytes());
+        memo.extend_from_slice(&self.voting_end.to_le_bytes());
+        memo.extend_from_slice(&self.amount.to_le_bytes());
+        memo.push(self.title.len() as u8);
+        memo.extend_from_slice(self.title.as_bytes());
+        memo.extend_from_slice(self.description.as_bytes());
+        memo
+    }
+
+    /// Decode from a transaction memo
+    pub fn from_memo(memo: &[u8]) -> Result<Self, GovernanceError> {
+        let body = memo.strip_prefix(PROPOSAL_MAGIC).ok_or(GovernanceError::InvalidPayload)?;
+        if body.len() < 25 {
+            return Err(GovernanceError::InvalidPayload);
+        }
+
+        let u64_at = |at: usize| u64::from_le_bytes(body[at..at + 8].try_into().expect("8 bytes"));
+        let title_len = body[24] as usize;
+        if title_len > MAX_TITLE_LEN || body.len() < 25 + title_len {
+            return Err(GovernanceError::InvalidPayload);
+        }
+
+        let text = |bytes: &[u8]| {
+            String::from_utf8(bytes.to_vec()).map_err(|_| GovernanceError::InvalidPayload)
+        };
+
+        Ok(Self {
+            voting_start: u64_at(0),
+            voting_end: u64_at(8),
+            amount: u64_at(16),
+            title: text(&body[25..25 + title_len])?,
+            description: text(&body[25 + title_len..])?,
+        })
+    }
+}
+
+/// Vote options
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VoteChoice {
+    No,
+    Yes,
+    Abstain,
+}
+
+/// Vote contents carried in a vote transaction memo
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VotePayload {
+    /// Txid of the proposal voted on
+    pub proposal_id: [u8; 32],
+
+    /// Chosen option
+    pub choice: VoteChoice,
+}
+
+impl VotePayload {
+    /// Encode as a transaction memo
+    pub fn to_memo(&self) -> Vec<u8> {
+        let mut memo = VOTE_MAGIC.to_vec();
+        memo.extend_from_slice(&self.proposal_id);
+        memo.push(match self.choice {
+            VoteChoice::No => 0,
+            VoteChoice::Yes => 1,
+            VoteChoice::Abstain => 2,
+        });
+        memo
+    }
+
+    /// Decode from a transaction memo
+    pub fn from_memo(memo: &[u8]) -> Result<Self, GovernanceError> {
+        let body = memo.strip_prefix(VOTE_MAGIC).ok_or(GovernanceError::InvalidPayload)?;
+        if body.len() != 33 {
+            return Err(GovernanceError::InvalidPayload);
+        }
+
+        let choice = match body[32] {
+            0 => VoteChoice::No,
+            1 => VoteChoice::Yes,
+            2 => VoteChoice::Abstain,
+            _ => return Err(GovernanceError::InvalidPayload),
+        };
+
+        Ok(Self {
+            proposal_id: body[..32].try_into().expect("32 bytes"),
+            choice,
+        })
+    }
+}
+
+/// Proposal reference carried in a treasury spend memo
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TreasurySpendPayload {
+    /// Txid of the approved proposal being paid out
+    pub proposal_id: [u8; 32],
+}
+
+impl TreasurySpendPayload {
+    /// Encode as a transaction memo
+    pub fn to_memo(&self) -> Vec<u8> {
+        let mut memo = TREASURY_MAGIC.to_vec();
+        memo.extend_from_slice(&self.proposal_id);
+        memo
+    }
+
+    /// Decode from a transaction memo
+    pub fn from_memo(memo: &[u8]) -> Result<Self, GovernanceError> {
+        let body = memo.strip_prefix(TREASURY_MAGIC).ok_or(GovernanceError::InvalidPayload)?;
+        let proposal_id = body.try_into().map_err(|_| GovernanceError::InvalidPayload)?;
+        Ok(Self { proposal_id })
+    }
+}
+
+/// Final vote count of a closed proposal
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tally {
+    /// Weight of yes votes, in base units
+    pub yes: u64,
+
+    /// Weight of no votes, in base units
+    pub no: u64,
+
+    /// Weight of abstentions, in base units
+    pub abstain: u64,
+
+    /// Number of voting addresses
+    pub voters: usize,
+
+    /// Circulating supply when voting closed
+    pub supply: u64,
+}
+
+impl Tally {
+    /// Total weight of all votes
+    pub fn turnout(&self) -> u64 {
+        self.yes + self.no + self.abstain
+    }
+
+    /// Whether turnout reached the quorum
+    pub fn has_quorum(&self) -> bool {
+        self.turnout() as u128 * 100 >= self.supply as u128 * QUORUM_PERCENTAGE as u128
+    }
+
+    /// Whether yes votes reach `percentage` of yes + no
+    pub fn approves(&self, percentage: u64) -> bool {
+        let decided = self.yes as u128 + self.no as u128;
+        decided > 0 && self.yes as u128 * 100 > decided * percentage as u128
+    }
+}
+
+/// Lifecycle of a proposal
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProposalStatus {
+    /// Voting has not started
+    Pending,
+
+    /// Accepting votes
+    Active,
+
+    /// Closed and approved
+    Passed,
+
+    /// Closed and rejected
+    Rejected,
+
+    /// Closed without reaching the quorum
+    NoQuorum,
+}
+
+impl ProposalStatus {
+    /// Stable lowercase name
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ProposalStatus::Pending => "pending",
+            ProposalStatus::Active => "active",
+            ProposalStatus::Passed => "passed",
+            ProposalStatus::Rejected => "rejected",
+            ProposalStatus::NoQuorum => "no_quorum",
+        }
+    }
+}
+
+/// A proposal recorded on chain
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Proposal {
+    /// Txid of the proposal transaction
+    pub id: [u8; 32],
+
+    /// Owner of the proposal transaction's first input
+    pub proposer: Address,
+
+    /// Height of the block containing the proposal
+    pub height: u64,
+
+    /// First height at which votes are accepted
+    pub voting_start: u64,
+
+    /// Last height at which votes are accepted
+    pub voting_end: u64,
+
+    /// Requested treasury spend (0 for non-spending proposals)
+    pub amount: u64,
+
+    /// Short title
+    pub title: String,
+
+    /// Free-form description
+    pub description: String,
+
+    /// Current vote of each address
+    pub votes: HashMap<Address, VoteChoice>,
+
+    /// Final count, once voting has closed
+    pub tally: Option<Tally>,
+
+    /// Txid of the treasury spend that paid this proposal out
+    pub executed: Option<[u8; 32]>,
+}
+
+impl Proposal {
+    /// Share of yes votes needed to pass
+    pub fn pass_percentage(&self) -> u64 {
+        if self.amount > 0 { SPEND_PASS_PERCENTAGE } else { PASS_PERCENTAGE }
+    }
+
+    /// Status at a chain height
+    pub fn status(&self, height: u64) -> ProposalStatus {
+        match &self.tally {
+            Some(tally) if !tally.has_quorum() => ProposalStatus::NoQuorum,
+            Some(tally) if tally.approves(self.pass_percentage()) => ProposalStatus::Passed,
+            Some(_) => ProposalStatus::Rejected,
+            None if height < self.voting_start => ProposalStatus::Pending,
+            None => ProposalStatus::Active,
+        }
+    }
+}
+
+/// Governance state: every proposal and its votes
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Governance {
+    proposals: HashMap<[u8; 32], Proposal>,
+}
+
+impl Governance {
+    /// Create empty governance state
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get a proposal by id
+    pub fn get(&self, id: &[u8; 32]) -> Option<&Proposal> {
+        self.proposals.get(id)
+    }
+
+    /// All proposals, oldest first
+    pub fn proposals(&self) -> Vec<&Proposal> {
+        let mut proposals: Vec<&Proposal> = self.proposals.values().collect();
+        proposals.sort_by_key(|p| (p.height, p.id));
+        proposals
+    }
+
+    /// Check a governance transaction for inclusion at `height`
+    ///
+    /// Transactions of other types are always accepted.
+    pub fn validate(&self, tx: &Transaction, height: u64, treasury: &Address) -> Result<(), GovernanceError> {
+        match tx.tx_type {
+            TxType::Proposal => {
+                let payload = ProposalPayload::from_memo(&tx.memo)?;
+                let deposit = tx.outputs.iter()
+                    .filter(|o| &o.recipient == treasury)
+                    .fold(0u64, |total, o| total.saturating_add(o.amount));
+                if deposit < PROPOSAL_DEPOSIT {
+                    return Err(GovernanceError::InsufficientDeposit(PROPOSAL_DEPOSIT));
+                }
+
+                let period = payload.voting_end.saturating_sub(payload.voting_start);
+                if payload.voting_start <= height
+                    || payload.voting_start > height + MAX_VOTING_DELAY
+                    || !(MIN_VOTING_PERIOD..=MAX_VOTING_PERIOD).contains(&period)
+                {
+                    return Err(GovernanceError::InvalidVotingWindow);
+                }
+                if self.proposals.contains_key(&tx.txid()) {
+                    return Err(GovernanceError::DuplicateProposal);
+                }
+                Self::sender(tx)?;
+                Ok(())
+            }
+            TxType::Vote => {
+                let payload = VotePayload::from_memo(&tx.memo)?;
+                let proposal = self.proposals.get(&payload.proposal_id)
+                    .ok_or(GovernanceError::UnknownProposal)?;
+                if height < proposal.voting_start || height > proposal.voting_end {
+                    return Err(GovernanceError::VotingClosed);
+                }
+                Self::sender(tx)?;
+                Ok(())
+            }
+            TxType::TreasurySpend => {
+                let payload = TreasurySpendPayload::from_memo(&tx.memo)?;
+                let proposal = self.proposals.get(&payload.proposal_id)
+                    .ok_or(GovernanceError::UnknownProposal)?;
+                if proposal.amount == 0 || proposal.status(height) != ProposalStatus::Passed {
+                    return Err(GovernanceError::NotApproved);
+                }
+                if proposal.executed.is_some() {
+                    return Err(GovernanceError::AlreadyExecuted);
+                }
+
+                let mut paid = 0u64;
+                for output in tx.outputs.iter().filter(|o| &o.recipient != treasury) {
+                    if output.recipient != proposal.proposer {
+                        return Err(GovernanceError::InvalidPayout);
+                    }
+                    paid = paid.saturating_add(output.amount);
+                }
+                if paid != proposal.amount {
+                    return Err(GovernanceError::InvalidPayout);
+                }
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Record the governance transactions of a block applied at `height`
+    ///
+    /// Transactions must already have passed [`validate`](Self::validate).
+    pub fn apply_transactions(&mut self, transactions: &[Transaction], height: u64) {
+        for tx in transactions {
+            match tx.tx_type {
+                TxType::Proposal => {
+                    let (Ok(payload), Ok(proposer)) = (ProposalPayload::from_memo(&tx.memo), Self::sender(tx)) else {
+                        continue;
+                    };
+                    let id = tx.txid();
+                    self.proposals.insert(id, Proposal {
+                        id,
+                        proposer,
+                        height,
+                        voting_start: payload.voting_start,
+                        voting_end: payload.voting_end,
+                        amount: payload.amount,
+                        title: payload.title,
+                        description: payload.description,
+                        votes: HashMap::new(),
+                        tally: None,
+                        executed: None,
+                    });
+                }
+                TxType::Vote => {
+                    let (Ok(payload), Ok(voter)) = (VotePayload::from_memo(&tx.memo), Self::sender(tx)) else {
+                        continue;
+                    };
+                    if let Some(proposal) = self.proposals.get_mut(&payload.proposal_id) {
+                        proposal.votes.insert(voter, payload.choice);
+                    }
+                }
+                TxType::TreasurySpend => {
+                    let Ok(payload) = TreasurySpendPayload::from_memo(&tx.memo) else {
+                        continue;
+                    };
+                    if let Some(proposal) = self.proposals.get_mut(&payload.proposal_id) {
+                        proposal.executed = Some(tx.txid());
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Whether applying `transactions` at `height` and closing the votes
+    /// ending there changes the governance state
+    pub fn changes_at(&self, transactions: &[Transaction], height: u64) -> bool {
+        transactions.iter().any(|tx| matches!(tx.tx_type, TxType::Proposal | TxType::Vote | TxType::TreasurySpend))
+            || self.proposals.values().any(|p| p.voting_end == height)
+    }
+
+    /// Tally proposals whose voting ends at `height`
+    ///
+    /// `utxos` is the UTXO set after the block at `height` was applied.
+    pub fn close_voting<'a>(&mut self, height: u64, utxos: impl Iterator<Item = &'a TxOutput>) {
+        let closing: Vec<[u8; 32]> = self.proposals.values()
+            .filter(|p| p.voting_end == height && p.tally.is_none())
+            .map(|p| p.id)
+            .collect();
+        if closing.is_empty() {
+            return;
+        }
+
+        let mut supply = 0u64;
+        let mut balances: HashMap<&Address, u64> = HashMap::new();
+        for output in utxos {
+            supply = supply.saturating_add(output.amount);
+            let balance = balances.entry(&output.recipient).or_default();
+            *balance = balance.saturating_add(output.amount);
+        }
+
+        for id in closing {
+            let proposal = self.proposals.get_mut(&id).expect("collected above");
+            let mut tally = Tally { yes: 0, no: 0, abstain: 0, voters: proposal.votes.len(), supply };
+            for (voter, choice) in &proposal.votes {
+                let weight = balances.get(voter).copied().unwrap_or(0);
+                match choice {
+                    VoteChoice::Yes => tally.yes += weight,
+                    VoteChoice::No => tally.no += weight,
+                    VoteChoice::Abstain => tally.abstain += weight,
+                }
+            }
+
+            proposal.tally = Some(tally);
+            log::info!("🗳️  Proposal {} closed: {:?}", hex::encode(id), proposal.status(height));
+        }
+    }
+
+    /// Address casting a proposal or vote: the owner of its first input
+    fn sender(tx: &Transaction) -> Result<Address, GovernanceError> {
+        tx.inputs.first()
+            .and_then(|input| input.owner().ok())
+            .ok_or(GovernanceError::NoSender)
+    }
+}
+
+/// Governance validation errors
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum GovernanceError {
+    #[error("Malformed governance payload")]
+    InvalidPayload,
+
+    #[error("Proposal must pay a deposit of at least {0} to the treasury")]
+    InsufficientDeposit(u64),
+
+    #[error("Invalid voting window")]
+    InvalidVotingWindow,
+
+    #[error("Proposal already exists")]
+    DuplicateProposal,
+
+    #[error("Unknown proposal")]
+    UnknownProposal,
+
+    #[error("Voting is not open for this proposal")]
+    VotingClosed,
+
+    #[error("Governance transaction has no signed input")]
+    NoSender,
+
+    #[error("Proposal has not been approved for a treasury spend")]
+    NotApproved,
+
+    #[error("Proposal has already been paid out")]
+    AlreadyExecuted,
+
+    #[error("Treasury spend must pay exactly the approved amount to the proposer")]
+    InvalidPayout,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::TxInput;
+    use ed25519_dalek::SigningKey;
+
+    fn signed(tx_type: TxType, memo: Vec<u8>, outputs: Vec<TxOutput>, key: &SigningKey) -> Transaction {
+        let mut tx = Transaction::new_transfer(vec![TxInput::new([1u8; 32], 0)], outputs);
+        tx.tx_type = tx_type;
+        tx.memo = memo;
+        let message = tx.signing_message();
+        tx.inputs[0].sign(key, &message);
+        tx
+    }
+
+    fn proposal_payload(height: u64) -> ProposalPayload {
+        ProposalPayload {
+            voting_start: height + 1,
+            voting_end: height + 1 + VOTING_PERIOD,
+            amount: 0,
+            title: "Raise block size".to_string(),
+            description: "Double it".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_payload_roundtrip() {
+        let payload = proposal_payload(10);
+        assert_eq!(ProposalPayload::from_memo(&payload.to_memo()).unwrap(), payload);
+        assert!(payload.to_memo().len() <= 256);
+
+        let vote = VotePayload { proposal_id: [4u8; 32], choice: VoteChoice::Abstain };
+        assert_eq!(VotePayload::from_memo(&vote.to_memo()).unwrap(), vote);
+        assert_eq!(VotePayload::from_memo(b"AEQV"), Err(GovernanceError::InvalidPayload));
+    }
+
+    #[test]
+    fn test_validate_proposal() {
+        let key = SigningKey::from_bytes(&[1u8; 32]);
+        let treasury = Address::genesis_address();
+        let governance = Governance::new();
+        let deposit = vec![TxOutput::new(treasury.clone(), PROPOSAL_DEPOSIT)];
+
+        let tx = signed(TxType::Proposal, proposal_payload(10).to_memo(), deposit.clone(), &key);
+        assert!(governance.validate(&tx, 10, &treasury).is_ok());
+
+        let short = vec![TxOutput::new(treasury.clone(), PROPOSAL_DEPOSIT - 1)];
+        let tx = signed(TxType::Proposal, proposal_payload(10).to_memo(), short, &key);
+        assert_eq!(governance.validate(&tx, 10, &treasury), Err(GovernanceError::InsufficientDeposit(PROPOSAL_DEPOSIT)));
+
+        let mut late = proposal_payload(10);
+        late.voting_end = late.voting_start + MIN_VOTING_PERIOD - 1;
+        let tx = signed(TxType::Proposal, late.to_memo(), deposit, &key);
+        assert_eq!(governance.validate(&tx, 10, &treasury), Err(GovernanceError::InvalidVotingWindow));
+    }
+
+    #[test]
+    fn test_votes_weighted_at_close() {
+        let treasury = Address::genesis_address();
+        let proposer = SigningKey::from_bytes(&[1u8; 32]);
+        let alice = SigningKey::from_bytes(&[2u8; 32]);
+        let bob = SigningKey::from_bytes(&[3u8; 32]);
+        let alice_addr = Address::from_public_key(&alice.verifying_key());
+        let bob_addr = Address::from_public_key(&bob.verifying_key());
+
+        let mut governance = Governance::new();
+        let payload = proposal_payload(10);
+        let proposal = signed(TxType::Proposal, payload.to_memo(), vec![TxOutput::new(treasury.clone(), PROPOSAL_DEPOSIT)], &proposer);
+        governance.apply_transactions(&[proposal.clone()], 10);
+        let id = proposal.txid();
+        assert_eq!(governance.get(&id).unwrap().status(10), ProposalStatus::Pending);
+
+        let vote = |key: &SigningKey, choice| {
+            signed(TxType::Vote, VotePayload { proposal_id: id, choice }.to_memo(), vec![TxOutput::new(treasury.clone(), 1)], key)
+        };
+        let early = vote(&alice, VoteChoice::Yes);
+        assert_eq!(governance.validate(&early, 10, &treasury), Err(GovernanceError::VotingClosed));
+        assert!(governance.validate(&early, 11, &treasury).is_ok());
+
+        // Alice changes her mind; only her last vote counts
+        governance.apply_transactions(&[vote(&alice, VoteChoice::No), vote(&bob, VoteChoice::Yes)], 11);
+        governance.apply_transactions(&[vote(&alice, VoteChoice::Yes)], 12);
+
+        let utxos = [
+            TxOutput::new(alice_addr, 30 * COIN),
+            TxOutput::new(bob_addr, 10 * COIN),
+            TxOutput::new(treasury, 60 * COIN),
+        ];
+        governance.close_voting(payload.voting_end, utxos.iter());
+
+        let proposal = governance.get(&id).unwrap();
+        let tally = proposal.tally.as_ref().unwrap();
+        assert_eq!((tally.yes, tally.no, tally.voters), (40 * COIN, 0, 2));
+        assert_eq!(tally.supply, 100 * COIN);
+        assert_eq!(proposal.status(payload.voting_end), ProposalStatus::Passed);
+    }
+
+    #[test]
+    fn test_treasury_spend_requires_passed_proposal() {
+        let treasury = Address::genesis_address();
+        let proposer = SigningKey::from_bytes(&[1u8; 32]);
+        let proposer_addr = Address::from_public_key(&proposer.verifying_key());
+
+        let mut governance = Governance::new();
+        let mut payload = proposal_payload(10);
+        payload.amount = 5 * COIN;
+        let proposal = signed(TxType::Proposal, payload.to_memo(), vec![TxOutput::new(treasury.clone(), PROPOSAL_DEPOSIT)], &proposer);
+        governance.apply_transactions(&[proposal.clone()], 10);
+        let id = proposal.txid();
+
+        let spend = |outputs: Vec<TxOutput>| {
+            let mut tx = Transaction::new_transfer(vec![TxInput::new([5u8; 32], 0)], outputs);
+            tx.tx_type = TxType::TreasurySpend;
+            tx.memo = TreasurySpendPayload { proposal_id: id }.to_memo();
+            tx
+        };
+        let payout = spend(vec![
+            TxOutput::new(proposer_addr.clone(), 5 * COIN),
+            TxOutput::new(treasury.clone(), COIN),
+        ]);
+        assert!(payout.validate().is_ok());
+
+        let end = payload.voting_end;
+        assert_eq!(governance.validate(&payout, end, &treasury), Err(GovernanceError::NotApproved));
+
+        let vote = signed(TxType::Vote, VotePayload { proposal_id: id, choice: VoteChoice::Yes }.to_memo(), vec![], &proposer);
+        governance.apply_transactions(&[vote], 11);
+        governance.close_voting(end, [TxOutput::new(proposer_addr.clone(), COIN)].iter());
+
+        assert!(governance.validate(&payout, end + 1, &treasury).is_ok());
+        let overpaid = spend(vec![TxOutput::new(proposer_addr, 6 * COIN)]);
+        assert_eq!(governance.validate(&overpaid, end + 1, &treasury), Err(GovernanceError::InvalidPayout));
+
+        governance.apply_transactions(&[payout.clone()], end + 1);
+        assert_eq!(governance.get(&id).unwrap().executed, Some(payout.txid()));
+        assert_eq!(governance.validate(&payout, end + 2, &treasury), Err(GovernanceError::AlreadyExecuted));
+    }
+}
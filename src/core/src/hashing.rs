@@ -0,0 +1,99 @@
+//! Hashing primitives
+//!
+//! The core computes its Keccak-256 and BLAKE3 digests through this module,
+//! so the implementation can be tuned in one place. Only the UTXO commitment,
+//! which streams the whole set, drives a BLAKE3 hasher directly.
+//!
+//! Acceleration: BLAKE3 detects SSE4.1, AVX2, AVX-512 or NEON at runtime and
+//! uses the widest one available. Keccak-256 is portable Rust by default;
+//! the `asm` feature switches it to the assembly backend of `sha3`, which
+//! uses the SHA-3 instructions of ARMv8.2 CPUs. x86 has no Keccak
+//! instructions (SHA-NI only covers SHA-1 and SHA-256), so nothing changes
+//! there.
+//!
+//! The hasher states themselves live on the stack and cost nothing to
+//! create; what allocated on every call was the serialization being hashed.
+//! [`keccak256_with`] writes it into a per-thread scratch buffer that is
+//! reused across calls instead.
+
+use sha3::{Digest, Keccak256};
+use std::cell::RefCell;
+
+/// Scratch buffers grown beyond this are dropped after use, so one large
+/// object does not pin its memory for the life of the thread
+const MAX_SCRATCH_CAPACITY: usize = 1 << 20;
+
+thread_local! {
+    static SCRATCH: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Keccak-256 of `data`
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    Keccak256::digest(data).into()
+}
+
+/// Keccak-256 of the concatenation of `parts`
+pub fn keccak256_parts(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Keccak256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+/// Keccak-256 of the bytes `write` appends to a reused scratch buffer
+pub fn keccak256_with(write: impl FnOnce(&mut Vec<u8>)) -> [u8; 32] {
+    SCRATCH.with(|scratch| match scratch.try_borrow_mut() {
+        Ok(mut buf) => {
+            buf.clear();
+            write(&mut buf);
+            let hash = keccak256(&buf);
+            if buf.capacity() > MAX_SCRATCH_CAPACITY {
+                *buf = Vec::new();
+            }
+            hash
+        }
+        // Called again from inside `write`: use a buffer of its own
+        Err(_) => {
+            let mut buf = Vec::new();
+            write(&mut buf);
+            keccak256(&buf)
+        }
+    })
+}
+
+/// BLAKE3 of `data`
+pub fn blake3(data: &[u8]) -> [u8; 32] {
+    *::blake3::hash(data).as_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_known_digests() {
+        assert_eq!(
+            hex::encode(keccak256(b"")),
+            "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+        );
+        assert_eq!(
+            hex::encode(blake3(b"")),
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+        );
+
+        let hash = keccak256(b"aequitas");
+        assert_eq!(keccak256_parts(&[b"aequ", b"", b"itas"]), hash);
+        assert_eq!(keccak256_with(|buf| buf.extend_from_slice(b"aequitas")), hash);
+
+        // The scratch buffer is cleared between calls and survives nesting
+        let nested = keccak256_with(|buf| {
+            buf.extend_from_slice(b"aequ");
+            let inner = keccak256_with(|inner| inner.extend_from_slice(b"other"));
+            assert_eq!(inner, keccak256(b"other"));
+            buf.extend_from_slice(b"itas");
+        });
+        assert_eq!(nested, hash);
+        assert_eq!(keccak256_with(|buf| buf.extend_from_slice(b"aequitas")), hash);
+    }
+}
@@ -2,16 +2,40 @@
 //! 
 //! This module defines the fundamental building blocks of the Aequitas blockchain.
 
+pub mod amount;
 pub mod block;
 pub mod transaction;
 pub mod blockchain;
 pub mod merkle;
 pub mod address;
 pub mod difficulty;
+pub mod encoding;
+pub mod governance;
+pub mod hashing;
+pub mod checkpoints;
+pub mod codec;
+pub mod params;
+pub mod snapshot;
+pub mod solidarity;
+pub mod storage;
+pub mod target;
+pub mod utxo;
+pub mod versionbits;
 
-pub use block::{Block, BlockHeader, BlockError, GENESIS_REWARD, INITIAL_DIFFICULTY};
-pub use transaction::{Transaction, TxInput, TxOutput, TxType, TxError};
-pub use blockchain::{Blockchain, ChainError, UtxoId, HALVING_INTERVAL, MAX_SUPPLY, TREASURY_PERCENTAGE};
-pub use address::{Address, Keypair, AddressError};
+pub use amount::{AmountFormat, AmountError, Unit, COIN, checked_sum, format_amount, parse_amount};
+pub use block::{Block, BlockHeader, BlockError, GENESIS_REWARD, INITIAL_DIFFICULTY, MAX_FUTURE_BLOCK_TIME, MEDIAN_TIME_SPAN, MAX_BLOCK_SIZE, MAX_BLOCK_TXS, UTXO_COMMITMENT_MAGIC, HEADER_WIRE_SIZE};
+pub use transaction::{Transaction, TxInput, TxOutput, TxType, TxError, Memo, MultisigPolicy, MultisigSignature, SignatureCheck, verify_batch, DUST_THRESHOLD, LOCK_HEIGHT_TX_VERSION};
+pub use blockchain::{Blockchain, BlockIter, ChainError, CheckLevel, LoadStatus, UtxoId, UtxoView, TxRecord, TxDirection, HALVING_INTERVAL, MAX_BLOCK_RANGE, MAX_SUPPLY, TREASURY_PERCENTAGE};
+pub use address::{Address, AddressKind, Keypair, AddressError};
 pub use difficulty::{Difficulty, TARGET_BLOCK_TIME};
+pub use codec::{ConsensusEncode, CONSENSUS_FORMAT_VERSION};
+pub use params::{ChainParams, DagSchedule, Network};
+pub use encoding::EncodingError;
+pub use governance::{Governance, GovernanceError, Proposal, ProposalStatus, Tally, VoteChoice};
 pub use merkle::{compute_merkle_root, MerkleProof};
+pub use checkpoints::{Checkpoints, CheckpointError};
+pub use target::{hash_meets_target, difficulty_to_target, target_to_difficulty, compact_to_target, target_to_compact};
+pub use snapshot::{UtxoSnapshot, SnapshotError, SNAPSHOT_VERSION};
+pub use utxo::{UtxoSet, UtxoStats};
+pub use versionbits::{Deployment, DeploymentInfo, DeploymentState, PeriodSignals, VERSION_BITS_TOP_BITS};
+pub use solidarity::{EpochTally, HashrateTier, MinerRegistry, ProportionalRewards, RegisteredMiner, identity_commitment};
@@ -2,7 +2,7 @@
 //!
 //! Computes merkle roots for transaction sets in blocks.
 
-use sha3::{Digest, Keccak256};
+use crate::hashing;
 use crate::transaction::Transaction;
 
 /// Compute the merkle root of a list of transactions
@@ -11,10 +11,10 @@ pub fn compute_merkle_root(transactions: &[Transaction]) -> [u8; 32] {
         return [0u8; 32];
     }
     
-    // Get hashes of all transactions
+    // Leaves are txids, so the root does not depend on witness data
     let mut hashes: Vec<[u8; 32]> = transactions
         .iter()
-        .map(|tx| tx.hash())
+        .map(|tx| tx.txid())
         .collect();
     
     // Build merkle tree
@@ -39,16 +39,13 @@ pub fn compute_merkle_root(transactions: &[Transaction]) -> [u8; 32] {
 
 /// Hash two 32-byte values together
 fn hash_pair(a: &[u8; 32], b: &[u8; 32]) -> [u8; 32] {
-    let mut hasher = Keccak256::new();
-    hasher.update(a);
-    hasher.update(b);
-    hasher.finalize().into()
+    hashing::keccak256_parts(&[a, b])
 }
 
 /// Merkle proof for a transaction
 #[derive(Clone, Debug)]
 pub struct MerkleProof {
-    /// The leaf hash (transaction hash)
+    /// The leaf hash (txid)
     pub leaf: [u8; 32],
     
     /// Proof path (sibling hashes)
@@ -80,7 +77,7 @@ pub fn build_merkle_proof(transactions: &[Transaction], index: usize) -> Option<
     
     let mut hashes: Vec<[u8; 32]> = transactions
         .iter()
-        .map(|tx| tx.hash())
+        .map(|tx| tx.txid())
         .collect();
     
     let leaf = hashes[index];
@@ -135,7 +132,7 @@ mod tests {
         let tx = Transaction::coinbase(addr, 50_000_000_000, 0);
         let root = compute_merkle_root(&[tx.clone()]);
         
-        assert_eq!(root, tx.hash());
+        assert_eq!(root, tx.txid());
     }
     
     #[test]
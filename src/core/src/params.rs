@@ -0,0 +1,228 @@
+//! Network parameters
+//!
+//! Consensus settings that differ between networks. Mainnet and testnet
+//! share the same rules; regtest is a private network for tests and local
+//! development where every block has difficulty 1, so blocks can be mined
+//! instantly.
+
+use crate::block::{Block, INITIAL_DIFFICULTY};
+use crate::checkpoints::Checkpoints;
+use crate::difficulty::Difficulty;
+use crate::versionbits::Deployment;
+
+/// Network a chain belongs to
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Network {
+    Mainnet,
+    #[default]
+    Testnet,
+    Regtest,
+}
+
+impl Network {
+    /// Name used in configuration files
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Network::Mainnet => "mainnet",
+            Network::Testnet => "testnet",
+            Network::Regtest => "regtest",
+        }
+    }
+}
+
+impl std::fmt::Display for Network {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Network {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "mainnet" => Ok(Network::Mainnet),
+            "testnet" => Ok(Network::Testnet),
+            "regtest" => Ok(Network::Regtest),
+            other => Err(format!("unknown network '{}' (mainnet, testnet or regtest)", other)),
+        }
+    }
+}
+
+/// How the AequiHash DAG grows with the epochs
+///
+/// The DAG starts at `initial_size` bytes and grows by `growth_per_epoch`
+/// each epoch until `max_size`, so it keeps outgrowing fixed-memory ASICs
+/// while staying years within reach of consumer GPUs. Every size is a
+/// multiple of the 64-byte DAG item.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DagSchedule {
+    /// DAG size of epoch 0 (bytes)
+    pub initial_size: u64,
+
+    /// Bytes added every epoch
+    pub growth_per_epoch: u64,
+
+    /// Size the DAG stops growing at (bytes)
+    pub max_size: u64,
+}
+
+impl DagSchedule {
+    /// Schedule of every network: 4 GiB, then 128 KiB per epoch (about
+    /// 560 MiB a year at 12 epochs a day), up to 12 GiB
+    pub const STANDARD: DagSchedule = DagSchedule {
+        initial_size: 4 << 30,
+        growth_per_epoch: 128 << 10,
+        max_size: 12 << 30,
+    };
+
+    /// DAG size of `epoch` (bytes), rounded down to whole items
+    pub fn size_for_epoch(&self, epoch: u64) -> u64 {
+        let size = self.growth_per_epoch
+            .saturating_mul(epoch)
+            .saturating_add(self.initial_size)
+            .min(self.max_size);
+        size - size % 64
+    }
+}
+
+/// Consensus parameters of a network
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChainParams {
+    /// Network these parameters belong to
+    pub network: Network,
+
+    /// Difficulty of the genesis block
+    pub genesis_difficulty: u64,
+
+    /// Retarget after every block; otherwise every block keeps the genesis
+    /// difficulty
+    pub retarget: bool,
+
+    /// Blocks per version-bits signalling period
+    pub signal_period: u64,
+
+    /// Signalling blocks in a period that lock a deployment in
+    pub signal_threshold: u32,
+
+    /// Soft forks activated by version-bits signalling
+    pub deployments: Vec<Deployment>,
+
+    /// AequiHash DAG size per epoch
+    pub dag_schedule: DagSchedule,
+}
+
+impl ChainParams {
+    /// Mainnet parameters
+    pub fn mainnet() -> Self {
+        Self {
+            network: Network::Mainnet,
+            genesis_difficulty: INITIAL_DIFFICULTY,
+            retarget: true,
+            // A day of blocks, 95% of them signalling
+            signal_period: 2880,
+            signal_threshold: 2736,
+            deployments: Vec::new(),
+            dag_schedule: DagSchedule::STANDARD,
+        }
+    }
+
+    /// Testnet parameters (same rules as mainnet)
+    pub fn testnet() -> Self {
+        Self { network: Network::Testnet, ..Self::mainnet() }
+    }
+
+    /// Regtest parameters: difficulty 1 at every height, and a dummy
+    /// deployment to exercise signalling
+    pub fn regtest() -> Self {
+        Self {
+            network: Network::Regtest,
+            genesis_difficulty: 1,
+            retarget: false,
+            signal_period: 144,
+            signal_threshold: 108,
+            deployments: vec![Deployment {
+                name: "testdummy",
+                bit: 28,
+                start_height: 0,
+                timeout_height: u64::MAX,
+            }],
+            dag_schedule: DagSchedule::STANDARD,
+        }
+    }
+
+    /// Parameters of a network
+    pub fn for_network(network: Network) -> Self {
+        match network {
+            Network::Mainnet => Self::mainnet(),
+            Network::Testnet => Self::testnet(),
+            Network::Regtest => Self::regtest(),
+        }
+    }
+
+    /// Genesis block of the network
+    pub fn genesis(&self) -> Block {
+        let mut block = Block::genesis();
+        block.header.set_difficulty(self.genesis_difficulty);
+        block
+    }
+
+    /// Hard-coded checkpoints (none on regtest)
+    pub fn checkpoints(&self) -> Checkpoints {
+        match self.network {
+            Network::Regtest => Checkpoints::new(),
+            Network::Mainnet | Network::Testnet => Checkpoints::mainnet(),
+        }
+    }
+
+    /// Deployment with this name
+    pub fn deployment(&self, name: &str) -> Option<&Deployment> {
+        self.deployments.iter().find(|d| d.name == name)
+    }
+
+    /// Difficulty expected after `history`, (timestamp, difficulty) of the
+    /// latest blocks, oldest first
+    pub fn next_difficulty(&self, history: &[(i64, u64)]) -> u64 {
+        if self.retarget {
+            Difficulty::calculate_next(history)
+        } else {
+            self.genesis_difficulty
+        }
+    }
+}
+
+impl Default for ChainParams {
+    fn default() -> Self {
+        Self::mainnet()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_regtest_params() {
+        let params = ChainParams::regtest();
+        let genesis = params.genesis();
+        assert_eq!(genesis.header.difficulty, 1);
+        assert!(genesis.header.meets_difficulty());
+        assert_eq!(params.next_difficulty(&[(0, 1), (1, 1)]), 1);
+
+        assert_eq!(ChainParams::testnet().genesis().header.difficulty, INITIAL_DIFFICULTY);
+        assert_eq!("regtest".parse::<Network>(), Ok(Network::Regtest));
+        assert!("devnet".parse::<Network>().is_err());
+    }
+
+    #[test]
+    fn test_dag_schedule() {
+        let schedule = DagSchedule::STANDARD;
+        assert_eq!(schedule.size_for_epoch(0), 4 << 30);
+        assert_eq!(schedule.size_for_epoch(1), (4 << 30) + (128 << 10));
+        assert_eq!(schedule.size_for_epoch(u64::MAX), 12 << 30);
+
+        let odd = DagSchedule { initial_size: 1000, growth_per_epoch: 10, max_size: 2000 };
+        assert_eq!(odd.size_for_epoch(0), 960);
+        assert_eq!(odd.size_for_epoch(3) % 64, 0);
+    }
+}
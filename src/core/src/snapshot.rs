@@ -0,0 +1,158 @@
+//! UTXO set snapshots
+//!
+//! A snapshot captures the UTXO set at a given tip together with the recent
+//! blocks needed to keep validating (difficulty window, median-time-past and
+//! solidarity beneficiary lookback), so a new node can bootstrap without
+//! replaying the whole chain.
+//!
+//! File layout: bincode-encoded [`UtxoSnapshot`] followed by the 32-byte
+//! blake3 hash of those bytes.
+
+use crate::address::Address;
+use crate::block::Block;
+use crate::blockchain::UtxoId;
+use crate::hashing;
+use crate::solidarity::EpochTally;
+use crate::transaction::TxOutput;
+use crate::versionbits::PeriodSignals;
+
+/// Snapshot format version
+pub const SNAPSHOT_VERSION: u32 = 4;
+
+/// Version tag hashed into UTXO commitments, fixed by consensus
+const COMMITMENT_VERSION: u32 = 1;
+
+/// Number of recent blocks embedded in a snapshot
+pub const SNAPSHOT_RECENT_BLOCKS: u64 = 120;
+
+/// UTXO set snapshot
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize)]
+pub struct UtxoSnapshot {
+    /// Format version
+    pub version: u32,
+
+    /// Height of the tip the snapshot was taken at
+    pub height: u64,
+
+    /// Hash of the tip block
+    pub tip_hash: [u8; 32],
+
+    /// Commitment to the UTXO set at the tip
+    pub commitment: [u8; 32],
+
+    /// Cumulative chain work up to the tip
+    pub total_work: u128,
+
+    /// Difficulty expected for the next block
+    pub current_difficulty: u64,
+
+    /// Treasury address
+    pub treasury_address: Address,
+
+    /// Block times used for difficulty adjustment
+    pub block_times: Vec<(u64, i64)>,
+
+    /// Most recent blocks, oldest first, ending with the tip
+    pub recent_blocks: Vec<Block>,
+
+    /// Unspent outputs, sorted by UTXO id
+    pub utxos: Vec<(UtxoId, TxOutput)>,
+
+    /// Solidarity registry tallies of the latest completed epochs, by
+    /// first height, standing in for the blocks before `recent_blocks`
+    pub solidarity_epochs: Vec<(u64, EpochTally)>,
+
+    /// Version-bits signal counts of every period, by first height
+    pub version_bits: Vec<(u64, PeriodSignals)>,
+}
+
+impl UtxoSnapshot {
+    /// Encode the snapshot with its trailing file hash
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SnapshotError> {
+        let mut bytes = bincode::serialize(self)?;
+        let hash = hashing::blake3(&bytes);
+        bytes.extend_from_slice(&hash);
+        Ok(bytes)
+    }
+
+    /// Decode a snapshot, checking its file hash and version
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        if bytes.len() < 32 {
+            return Err(SnapshotError::Truncated);
+        }
+
+        let (body, hash) = bytes.split_at(bytes.len() - 32);
+        if hashing::blake3(body) != hash {
+            return Err(SnapshotError::ChecksumMismatch);
+        }
+
+        // The version leads the encoding; check it before decoding a layout
+        // it may not follow
+        let version: u32 = bincode::deserialize(body)?;
+        if version != SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+
+        Ok(bincode::deserialize(body)?)
+    }
+
+    /// Recompute the commitment from the snapshot contents
+    pub fn compute_commitment(&self) -> [u8; 32] {
+        utxo_commitment(
+            self.height,
+            &self.tip_hash,
+            self.utxos.iter().map(|(id, output)| (id, output)),
+        )
+    }
+}
+
+/// Commitment to a UTXO set at a given tip
+///
+/// Entries must be supplied sorted by UTXO id so every node derives the same
+/// hash for the same set.
+pub fn utxo_commitment<'a, I>(height: u64, tip_hash: &[u8; 32], utxos: I) -> [u8; 32]
+where
+    I: IntoIterator<Item = (&'a UtxoId, &'a TxOutput)>,
+{
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&COMMITMENT_VERSION.to_le_bytes());
+    hasher.update(&height.to_le_bytes());
+    hasher.update(tip_hash);
+
+    for (id, output) in utxos {
+        hasher.update(&id.tx_hash);
+        hasher.update(&id.output_index.to_le_bytes());
+        hasher.update(&output.amount.to_le_bytes());
+        hasher.update(output.recipient.as_bytes());
+    }
+
+    *hasher.finalize().as_bytes()
+}
+
+/// Snapshot errors
+#[derive(Debug, thiserror::Error)]
+pub enum SnapshotError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("Encoding error: {0}")]
+    Encoding(#[from] bincode::Error),
+
+    #[error("Snapshot file is truncated")]
+    Truncated,
+
+    #[error("Snapshot checksum mismatch")]
+    ChecksumMismatch,
+
+    #[error("Unsupported snapshot version {0}")]
+    UnsupportedVersion(u32),
+
+    #[error("UTXO commitment mismatch")]
+    CommitmentMismatch,
+
+    #[error("Snapshot blocks do not link up to the tip")]
+    InvalidBlocks,
+
+    #[error("Snapshot tip does not match checkpoint at height {0}")]
+    Checkpoint(u64),
+}
@@ -0,0 +1,574 @@
+//! On-chain solidarity registry
+//!
+//! A miner registers by committing to its payout address in the `extra_data`
+//! of every block header it mines: `keccak256("AEQM" || address)`, where
+//! `address` receives the coinbase miner output. Blocks without a matching
+//! commitment count for nobody. The registry is rebuilt from the last
+//! [`REGISTRY_WINDOW`] blocks, so any node can audit who is eligible and why.
+//!
+//! On the chain the window moves one [`REGISTRY_EPOCH`] at a time: each
+//! completed epoch is summed up once into an [`EpochTally`] kept in chain
+//! state, and the registry for a block is built from the tallies of the
+//! completed epochs before it, plus the solidarity payouts since. It thus
+//! depends only on the chain, never on which old blocks a node still holds.
+//!
+//! Anti-Sybil rules for receiving the solidarity output:
+//! - at least [`MIN_REGISTERED_BLOCKS`] blocks mined in the window, the first
+//!   one at least [`MIN_REGISTRATION_AGE`] blocks old (fresh addresses get
+//!   nothing)
+//! - [`HashrateTier::Petit`], estimated from the share of window blocks
+//! - never more solidarity payouts in the window than blocks mined, so
+//!   splitting hashrate across identities does not earn more turns
+//!
+//! The eligible miner paid least recently is selected (ties broken by fewest
+//! blocks, then lowest commitment); without one, the treasury is paid.
+//!
+//! The registry also attests the tier of every payout address, registered or
+//! not, from its share of window blocks. That tier sets the coinbase split of
+//! the address's next block (see [`ProportionalRewards::split`]).
+
+use crate::address::Address;
+use crate::block::Block;
+use crate::blockchain::{SOLIDARITY_PERCENTAGE, TREASURY_PERCENTAGE};
+use crate::hashing;
+use std::collections::HashMap;
+
+/// Prefix of miner identity commitments
+pub const IDENTITY_MAGIC: &[u8; 4] = b"AEQM";
+
+/// Blocks scanned to build the registry (~1 day)
+pub const REGISTRY_WINDOW: u64 = 2880;
+
+/// Blocks per registry epoch (~1 hour); [`REGISTRY_WINDOW`] is a whole
+/// number of epochs
+pub const REGISTRY_EPOCH: u64 = 120;
+
+/// Blocks a miner must have found in the window to be eligible
+pub const MIN_REGISTERED_BLOCKS: u64 = 3;
+
+/// Blocks since a miner's first registered block before it is eligible (~6 hours)
+pub const MIN_REGISTRATION_AGE: u64 = 720;
+
+/// Mining hash rate tiers for proportional rewards
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum HashrateTier {
+    /// Small miners with consumer GPUs (< 50 GH/s)
+    Petit,
+    /// Medium miners with decent GPUs (50-200 GH/s)
+    Moyen,
+    /// Large miners with high-end GPUs (200-500 GH/s)
+    Gros,
+    /// Industrial mining operations (500-1000 GH/s)
+    Industriel,
+    /// Very large operations (1000+ GH/s)
+    UltraGros,
+}
+
+impl HashrateTier {
+    /// Determine tier from hashrate in GH/s
+    pub fn from_hashrate(hashrate_ghs: f64) -> Self {
+        if hashrate_ghs < 50.0 {
+            HashrateTier::Petit
+        } else if hashrate_ghs < 200.0 {
+            HashrateTier::Moyen
+        } else if hashrate_ghs < 500.0 {
+            HashrateTier::Gros
+        } else if hashrate_ghs < 1000.0 {
+            HashrateTier::Industriel
+        } else {
+            HashrateTier::UltraGros // Very large operations
+        }
+    }
+
+    /// Get base mining percentage (vs 60% standard for large miners)
+    pub fn base_mining_percentage(&self) -> f64 {
+        match self {
+            HashrateTier::Petit => 0.90,      // 90% of 60%
+            HashrateTier::Moyen => 0.60,      // 60% of 60%
+            HashrateTier::Gros => 0.35,       // 35% of 60%
+            HashrateTier::Industriel => 0.25, // 25% of 60%
+            HashrateTier::UltraGros => 0.15,  // 15% of 60%
+        }
+    }
+
+    /// Get optimal solidarity percentage for this tier
+    pub fn solidarity_percentage(&self) -> f64 {
+        match self {
+            HashrateTier::Petit => 0.35,      // 35% for strong protection
+            HashrateTier::Moyen => 0.20,      // 20% for moderate support
+            HashrateTier::Gros => 0.10,       // 10% for minimal support
+            HashrateTier::Industriel => 0.05, // 5% for symbolic contribution
+            HashrateTier::UltraGros => 0.02,  // 2% for very minimal
+        }
+    }
+
+    /// Get treasury percentage for this tier
+    pub fn treasury_percentage(&self) -> f64 {
+        match self {
+            HashrateTier::Petit => 0.05,      // 5% for development
+            HashrateTier::Moyen => 0.10,      // 10% for ecosystem growth
+            HashrateTier::Gros => 0.20,       // 20% for network security
+            HashrateTier::Industriel => 0.25, // 25% for infrastructure
+            HashrateTier::UltraGros => 0.28,  // 28% for operational costs
+        }
+    }
+
+    /// Lowercase name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HashrateTier::Petit => "petit",
+            HashrateTier::Moyen => "moyen",
+            HashrateTier::Gros => "gros",
+            HashrateTier::Industriel => "industriel",
+            HashrateTier::UltraGros => "ultra_gros",
+        }
+    }
+}
+
+/// Proportional mining reward distribution
+#[derive(Debug, Clone)]
+pub struct ProportionalRewards {
+    /// Tier the percentages come from
+    pub tier: HashrateTier,
+    /// Mining percentage (adjusted by tier)
+    pub mining_percentage: f64,
+    /// Solidarity percentage (adjusted by tier)
+    pub solidarity_percentage: f64,
+    /// Treasury percentage (adjusted by tier)
+    pub treasury_percentage: f64,
+}
+
+impl ProportionalRewards {
+    /// Calculate optimal reward distribution based on hash rate tier
+    pub fn for_hashrate(hashrate_ghs: f64) -> Self {
+        Self::for_tier(HashrateTier::from_hashrate(hashrate_ghs))
+    }
+
+    /// Reward distribution of a tier
+    pub fn for_tier(tier: HashrateTier) -> Self {
+        Self {
+            tier,
+            mining_percentage: tier.base_mining_percentage(),
+            solidarity_percentage: tier.solidarity_percentage(),
+            treasury_percentage: tier.treasury_percentage(),
+        }
+    }
+
+    /// Calculate individual rewards from block reward
+    pub fn calculate_rewards(&self, block_reward: u64) -> (u64, u64, u64) {
+        let miner_reward = (block_reward as f64 * self.mining_percentage) as u64;
+        let solidarity_reward = (block_reward as f64 * self.solidarity_percentage) as u64;
+        let treasury_reward = (block_reward as f64 * self.treasury_percentage) as u64;
+
+        (miner_reward, solidarity_reward, treasury_reward)
+    }
+
+    /// Treasury share enforced by consensus, in basis points
+    ///
+    /// [`TREASURY_PERCENTAGE`] scaled by the tier's treasury percentage
+    /// relative to `Petit` (x1, x2, x4, x5, x5.6). Kept as an integer table so
+    /// every validator computes the same split.
+    pub fn treasury_basis_points(&self) -> u64 {
+        let scale = match self.tier {
+            HashrateTier::Petit => 10,
+            HashrateTier::Moyen => 20,
+            HashrateTier::Gros => 40,
+            HashrateTier::Industriel => 50,
+            HashrateTier::UltraGros => 56,
+        };
+        TREASURY_PERCENTAGE * scale * 10
+    }
+
+    /// Consensus split of a block reward: (miner, treasury, solidarity)
+    ///
+    /// Solidarity stays at [`SOLIDARITY_PERCENTAGE`]; larger tiers pay a higher
+    /// treasury levy out of the miner share, and `Petit` keeps the base split.
+    pub fn split(&self, block_reward: u64) -> (u64, u64, u64) {
+        let treasury = (block_reward as u128 * self.treasury_basis_points() as u128 / 10_000) as u64;
+        let solidarity = block_reward * SOLIDARITY_PERCENTAGE / 100;
+        let miner = block_reward - treasury - solidarity;
+        (miner, treasury, solidarity)
+    }
+
+    /// Get tier description for display
+    pub fn tier_description(&self) -> &'static str {
+        match self.tier {
+            HashrateTier::Petit => "Petits mineurs - Protection renforcée",
+            HashrateTier::Moyen => "Mineurs moyens - Support modéré",
+            HashrateTier::Gros => "Gros mineurs - Inclusion financière",
+            HashrateTier::Industriel => "Opérateurs industriels - Maintenance réseau",
+            HashrateTier::UltraGros => "Très gros opérateurs - Sécurité infra",
+        }
+    }
+}
+
+/// Identity commitment a miner puts in `extra_data` to register `address`
+pub fn identity_commitment(address: &Address) -> [u8; 32] {
+    hashing::keccak256_parts(&[IDENTITY_MAGIC, address.as_bytes()])
+}
+
+/// A miner found in the registry window
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegisteredMiner {
+    /// Payout address the identity commits to
+    pub address: Address,
+
+    /// Commitment carried in `extra_data`
+    pub commitment: [u8; 32],
+
+    /// Height of the first registered block in the window
+    pub first_seen: u64,
+
+    /// Registered blocks found in the window
+    pub blocks_found: u64,
+
+    /// Solidarity outputs received in the window
+    pub payouts: u64,
+
+    /// Height of the last solidarity output received in the window
+    pub last_paid: Option<u64>,
+
+    /// Estimated hashrate from the share of window blocks (GH/s)
+    pub hashrate_ghs: f64,
+
+    /// Tier of the estimated hashrate
+    pub tier: HashrateTier,
+}
+
+impl RegisteredMiner {
+    /// Blocks found not yet matched by a solidarity payout
+    pub fn credits(&self) -> u64 {
+        self.blocks_found.saturating_sub(self.payouts)
+    }
+
+    /// Whether the miner may receive the next solidarity output
+    pub fn is_eligible(&self, height: u64) -> bool {
+        self.blocks_found >= MIN_REGISTERED_BLOCKS
+            && height.saturating_sub(self.first_seen) >= MIN_REGISTRATION_AGE
+            && self.tier == HashrateTier::Petit
+            && self.credits() > 0
+    }
+}
+
+/// What the blocks of one epoch contribute to the registry
+#[derive(Clone, Debug, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct EpochTally {
+    /// Blocks counted (the genesis block counts for nobody)
+    pub blocks: u64,
+
+    /// Blocks paid to each address, registered or not
+    pub mined: HashMap<Address, u64>,
+
+    /// Registered blocks of each address: (first height, count)
+    pub registered: HashMap<Address, (u64, u64)>,
+
+    /// Solidarity outputs received by each address: (count, last height)
+    pub payouts: HashMap<Address, (u64, u64)>,
+}
+
+impl EpochTally {
+    /// Tally a run of blocks
+    pub fn from_blocks<'a>(blocks: impl IntoIterator<Item = &'a Block>) -> Self {
+        let mut tally = Self::default();
+        for block in blocks {
+            let h = block.header.height;
+            let Some(coinbase) = block.transactions.first().filter(|_| h > 0) else {
+                continue;
+            };
+            tally.blocks += 1;
+
+            if let Some(output) = coinbase.outputs.get(0) {
+                *tally.mined.entry(output.recipient.clone()).or_insert(0) += 1;
+                if block.header.extra_data == identity_commitment(&output.recipient) {
+                    let entry = tally.registered.entry(output.recipient.clone()).or_insert((h, 0));
+                    entry.0 = entry.0.min(h);
+                    entry.1 += 1;
+                }
+            }
+
+            if let Some(output) = coinbase.outputs.get(2) {
+                let entry = tally.payouts.entry(output.recipient.clone()).or_insert((0, h));
+                entry.0 += 1;
+                entry.1 = entry.1.max(h);
+            }
+        }
+        tally
+    }
+
+    /// Add another tally to this one
+    fn merge(&mut self, other: &EpochTally) {
+        self.blocks += other.blocks;
+        for (address, count) in &other.mined {
+            *self.mined.entry(address.clone()).or_insert(0) += count;
+        }
+        for (address, (first, count)) in &other.registered {
+            let entry = self.registered.entry(address.clone()).or_insert((*first, 0));
+            entry.0 = entry.0.min(*first);
+            entry.1 += count;
+        }
+        for (address, (count, last)) in &other.payouts {
+            let entry = self.payouts.entry(address.clone()).or_insert((0, *last));
+            entry.0 += count;
+            entry.1 = entry.1.max(*last);
+        }
+    }
+}
+
+/// Registered miners of the last [`REGISTRY_WINDOW`] blocks
+#[derive(Clone, Debug, Default)]
+pub struct MinerRegistry {
+    height: u64,
+    miners: HashMap<Address, RegisteredMiner>,
+    /// Blocks in the window paid to each address, registered or not
+    mined: HashMap<Address, u64>,
+    window_blocks: u64,
+    network_hashrate: f64,
+}
+
+impl MinerRegistry {
+    /// Build the registry from the window blocks ending at `height`
+    ///
+    /// `network_hashrate` (H/s) scales each miner's block share into a tier.
+    pub fn build<'a>(
+        blocks: impl IntoIterator<Item = &'a Block>,
+        height: u64,
+        network_hashrate: f64,
+    ) -> Self {
+        Self::from_tallies([&EpochTally::from_blocks(blocks)], height, network_hashrate)
+    }
+
+    /// Build the registry from the tallies of the window epochs, with
+    /// `height` the height of the last block taken into account
+    pub fn from_tallies<'a>(
+        tallies: impl IntoIterator<Item = &'a EpochTally>,
+        height: u64,
+        network_hashrate: f64,
+    ) -> Self {
+        let mut window = EpochTally::default();
+        for tally in tallies {
+            window.merge(tally);
+        }
+
+        let mut registry = Self {
+            height,
+            miners: HashMap::new(),
+            mined: window.mined,
+            window_blocks: window.blocks,
+            network_hashrate,
+        };
+
+        for (address, (first_seen, blocks_found)) in window.registered {
+            let (payouts, last_paid) = match window.payouts.get(&address) {
+                Some((count, last)) => (*count, Some(*last)),
+                None => (0, None),
+            };
+            let hashrate_ghs = registry.hashrate_ghs(&address);
+            registry.miners.insert(address.clone(), RegisteredMiner {
+                commitment: identity_commitment(&address),
+                address,
+                first_seen,
+                blocks_found,
+                payouts,
+                last_paid,
+                hashrate_ghs,
+                tier: HashrateTier::from_hashrate(hashrate_ghs),
+            });
+        }
+        registry
+    }
+
+    /// Count the solidarity payouts of blocks after the tallied epochs,
+    /// up to `height`
+    pub fn record_payouts<'a>(&mut self, blocks: impl IntoIterator<Item = &'a Block>, height: u64) {
+        for block in blocks {
+            let h = block.header.height;
+            let recipient = block.transactions.first()
+                .filter(|_| h > 0)
+                .and_then(|coinbase| coinbase.outputs.get(2))
+                .map(|output| &output.recipient);
+            if let Some(miner) = recipient.and_then(|recipient| self.miners.get_mut(recipient)) {
+                miner.payouts += 1;
+                miner.last_paid = miner.last_paid.max(Some(h));
+            }
+        }
+        self.height = height;
+    }
+
+    /// Estimated hashrate of a payout address from its share of window blocks (GH/s)
+    pub fn hashrate_ghs(&self, address: &Address) -> f64 {
+        let blocks = self.mined.get(address).copied().unwrap_or(0);
+        let share = blocks as f64 / self.window_blocks.max(1) as f64;
+        share * self.network_hashrate / 1e9
+    }
+
+    /// Attested tier of a payout address (`Petit` with no blocks in the window)
+    pub fn tier_of(&self, address: &Address) -> HashrateTier {
+        HashrateTier::from_hashrate(self.hashrate_ghs(address))
+    }
+
+    /// Height of the last block in the window
+    pub fn height(&self) -> u64 {
+        self.height
+    }
+
+    /// Look up a registered miner by payout address
+    pub fn get(&self, address: &Address) -> Option<&RegisteredMiner> {
+        self.miners.get(address)
+    }
+
+    /// All registered miners, by commitment
+    pub fn miners(&self) -> Vec<&RegisteredMiner> {
+        let mut miners: Vec<_> = self.miners.values().collect();
+        miners.sort_by_key(|m| m.commitment);
+        miners
+    }
+
+    /// Miners eligible for the next solidarity output
+    pub fn eligible(&self) -> Vec<&RegisteredMiner> {
+        self.miners()
+            .into_iter()
+            .filter(|m| m.is_eligible(self.height))
+            .collect()
+    }
+
+    /// Recipient of the next solidarity output, if any miner is eligible
+    pub fn select(&self) -> Option<&Address> {
+        self.eligible()
+            .into_iter()
+            .min_by_key(|m| (m.last_paid, m.blocks_found, m.commitment))
+            .map(|m| &m.address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction::{Transaction, TxOutput};
+
+    fn block(height: u64, miner: &Address, registered: bool, solidarity: &Address) -> Block {
+        let mut coinbase = Transaction::coinbase(miner.clone(), 98, height);
+        coinbase.outputs.push(TxOutput::new(Address::genesis_address(), 1));
+        coinbase.outputs.push(TxOutput::new(solidarity.clone(), 1));
+
+        let mut block = Block::new([0u8; 32], height, 1, vec![coinbase]);
+        if registered {
+            block.header.extra_data = identity_commitment(miner);
+        }
+        block
+    }
+
+    fn address(byte: u8) -> Address {
+        Address::from_bytes([byte; 20])
+    }
+
+    #[test]
+    fn test_registration_requires_commitment_and_age() {
+        let treasury = Address::genesis_address();
+        let (old, fresh, anonymous) = (address(1), address(2), address(3));
+
+        let mut blocks = Vec::new();
+        for h in 1..=1000 {
+            let (miner, registered) = match h % 4 {
+                0 => (&old, true),
+                1 if h > 900 => (&fresh, true),
+                _ => (&anonymous, false),
+            };
+            blocks.push(block(h, miner, registered, &treasury));
+        }
+
+        let registry = MinerRegistry::build(&blocks, 1000, 1.0);
+        assert!(registry.get(&anonymous).is_none());
+        assert!(registry.get(&fresh).unwrap().blocks_found >= MIN_REGISTERED_BLOCKS);
+        assert!(!registry.get(&fresh).unwrap().is_eligible(1000));
+        assert_eq!(registry.select(), Some(&old));
+    }
+
+    #[test]
+    fn test_selection_rotates_and_respects_credits() {
+        let treasury = Address::genesis_address();
+        let (a, b) = (address(1), address(2));
+
+        let mut blocks = Vec::new();
+        for h in 1..=800 {
+            let miner = if h % 2 == 0 { &a } else { &b };
+            // `a` was paid recently, `b` never
+            let solidarity = if h == 800 { &a } else { &treasury };
+            blocks.push(block(h, miner, h <= 6, solidarity));
+        }
+
+        let registry = MinerRegistry::build(&blocks, 800, 1.0);
+        assert_eq!(registry.select(), Some(&b));
+
+        // Payouts used up every credit: nobody is eligible
+        let mut drained = blocks.clone();
+        for h in 801..=803 {
+            drained.push(block(h, &address(9), false, &b));
+        }
+        let registry = MinerRegistry::build(&drained, 803, 1.0);
+        assert_eq!(registry.get(&b).unwrap().credits(), 0);
+        assert_eq!(registry.select(), Some(&a));
+    }
+
+    #[test]
+    fn test_tallies_match_blocks() {
+        let treasury = Address::genesis_address();
+        let (a, b) = (address(1), address(2));
+        let blocks: Vec<_> = (1..=2 * REGISTRY_EPOCH)
+            .map(|h| block(h, if h % 3 == 0 { &a } else { &b }, h % 2 == 0, if h == 200 { &a } else { &treasury }))
+            .collect();
+
+        let whole = MinerRegistry::build(&blocks, 2 * REGISTRY_EPOCH, 1.0);
+        let (first, second) = blocks.split_at(REGISTRY_EPOCH as usize);
+        let tallies = [EpochTally::from_blocks(first), EpochTally::from_blocks(second)];
+        let epochs = MinerRegistry::from_tallies(&tallies, 2 * REGISTRY_EPOCH, 1.0);
+        assert_eq!(epochs.miners(), whole.miners());
+        assert_eq!(epochs.tier_of(&b), whole.tier_of(&b));
+
+        // Payouts after the tallied epochs still rotate the selection
+        let mut partial = MinerRegistry::from_tallies(&tallies[..1], REGISTRY_EPOCH, 1.0);
+        partial.record_payouts(second, 2 * REGISTRY_EPOCH);
+        assert_eq!(partial.get(&a).unwrap().payouts, 1);
+        assert_eq!(partial.height(), 2 * REGISTRY_EPOCH);
+    }
+
+    #[test]
+    fn test_large_miners_are_not_eligible() {
+        let treasury = Address::genesis_address();
+        let big = address(1);
+        let blocks: Vec<_> = (1..=800).map(|h| block(h, &big, true, &treasury)).collect();
+
+        // The only miner holds all of a 100 TH/s network
+        let registry = MinerRegistry::build(&blocks, 800, 100e12);
+        assert_eq!(registry.get(&big).unwrap().tier, HashrateTier::UltraGros);
+        assert_eq!(registry.select(), None);
+    }
+
+    #[test]
+    fn test_tier_split() {
+        let reward = 50 * crate::COIN;
+
+        let petit = ProportionalRewards::for_tier(HashrateTier::Petit).split(reward);
+        assert_eq!(petit, crate::Blockchain::reward_split_for_height(1));
+
+        let (miner, treasury, solidarity) = ProportionalRewards::for_tier(HashrateTier::UltraGros).split(reward);
+        assert_eq!(treasury, reward * 56 / 1000);
+        assert_eq!(solidarity, petit.2);
+        assert_eq!(miner + treasury + solidarity, reward);
+    }
+
+    #[test]
+    fn test_tier_attested_without_registration() {
+        let treasury = Address::genesis_address();
+        let (big, small) = (address(1), address(2));
+        let blocks: Vec<_> = (1..=100)
+            .map(|h| block(h, if h % 20 == 0 { &small } else { &big }, false, &treasury))
+            .collect();
+
+        // 95% of a 2 TH/s network is 1900 GH/s, 5% is 100 GH/s
+        let registry = MinerRegistry::build(&blocks, 100, 2e12);
+        assert!(registry.get(&big).is_none());
+        assert_eq!(registry.tier_of(&big), HashrateTier::UltraGros);
+        assert_eq!(registry.tier_of(&small), HashrateTier::Moyen);
+        assert_eq!(registry.tier_of(&address(7)), HashrateTier::Petit);
+    }
+}
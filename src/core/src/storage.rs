@@ -0,0 +1,353 @@
+//! Checksummed chain file format
+//!
+//! A chain file starts with a magic/version header followed by records laid
+//! out as `[kind: u8][len: u32 LE][payload][blake3(payload)]`. Blocks are
+//! stored one record each, in height order, followed by a single state
+//! record and a governance record, so damage can be pinned to the first bad
+//! record on load.
+//!
+//! Version 2 added the transaction lock height; blocks in version 1 files
+//! are decoded through [`v1`].
+//!
+//! Files are replaced atomically: the new content is synced to `<file>.tmp`,
+//! the current file becomes the rolling backup `<file>.bak`, and the temporary
+//! file is renamed into place. A crash at any point leaves at least one
+//! complete copy behind.
+
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use crate::block::Block;
+use crate::hashing;
+
+/// Magic bytes at the start of a chain file
+pub const CHAIN_FILE_MAGIC: [u8; 4] = *b"AEQC";
+
+/// Chain file format version
+pub const CHAIN_FILE_VERSION: u32 = 2;
+
+/// Record holding one block
+pub const RECORD_BLOCK: u8 = 1;
+
+/// Record holding the chain state
+pub const RECORD_STATE: u8 = 2;
+
+/// Record holding the governance state
+pub const RECORD_GOVERNANCE: u8 = 3;
+
+/// Record holding the undo data: outputs spent by each block
+pub const RECORD_UNDO: u8 = 4;
+
+/// Record holding the blocks invalidated by the operator
+pub const RECORD_INVALIDATED: u8 = 5;
+
+/// Record holding the solidarity registry tallies of recent epochs
+pub const RECORD_SOLIDARITY: u8 = 6;
+
+/// Record holding the version-bits signal counts of every period
+pub const RECORD_VERSION_BITS: u8 = 7;
+
+/// Bytes before the first record
+const HEADER_LEN: usize = 8;
+
+/// Check whether bytes start with a chain file header
+pub fn is_chain_file(bytes: &[u8]) -> bool {
+    bytes.len() >= HEADER_LEN && bytes[..4] == CHAIN_FILE_MAGIC
+}
+
+/// Append the chain file header
+pub fn write_header(out: &mut Vec<u8>) {
+    out.extend_from_slice(&CHAIN_FILE_MAGIC);
+    out.extend_from_slice(&CHAIN_FILE_VERSION.to_le_bytes());
+}
+
+/// Append a checksummed record
+pub fn write_record(out: &mut Vec<u8>, kind: u8, payload: &[u8]) {
+    out.push(kind);
+    out.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+    out.extend_from_slice(payload);
+    out.extend_from_slice(&hashing::blake3(payload));
+}
+
+/// Temporary file a chain file is written to before being renamed into place
+pub fn temp_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".tmp")
+}
+
+/// Rolling backup holding the previously saved chain file
+pub fn backup_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".bak")
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+/// Replace `path` with `bytes`, keeping the previous file as the backup
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> std::io::Result<()> {
+    let tmp = temp_path(path);
+    {
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(bytes)?;
+        file.sync_all()?;
+    }
+
+    if path.exists() {
+        std::fs::rename(path, backup_path(path))?;
+    }
+    std::fs::rename(&tmp, path)?;
+    sync_parent_dir(path);
+    Ok(())
+}
+
+/// Persist the renames (best effort; not possible on every platform)
+fn sync_parent_dir(path: &Path) {
+    #[cfg(unix)]
+    {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            if let Ok(dir) = std::fs::File::open(dir) {
+                let _ = dir.sync_all();
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+/// Iterator over the records of a chain file
+///
+/// Yields `(kind, payload)` for each record whose checksum matches and stops
+/// after the first error.
+pub struct RecordReader<'a> {
+    bytes: &'a [u8],
+    version: u32,
+    pos: usize,
+    failed: bool,
+}
+
+impl<'a> RecordReader<'a> {
+    /// Start reading a chain file, checking its header
+    pub fn new(bytes: &'a [u8]) -> Result<Self, StorageError> {
+        if !is_chain_file(bytes) {
+            return Err(StorageError::BadMagic);
+        }
+
+        let version = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        if version == 0 || version > CHAIN_FILE_VERSION {
+            return Err(StorageError::UnsupportedVersion(version));
+        }
+
+        Ok(Self {
+            bytes,
+            version,
+            pos: HEADER_LEN,
+            failed: false,
+        })
+    }
+
+    /// Format version of the file
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+}
+
+/// Decode a block record of a file with format `version`
+pub fn decode_block(payload: &[u8], version: u32) -> bincode::Result<Block> {
+    if version == 1 {
+        bincode::deserialize::<v1::Block>(payload).map(Block::from)
+    } else {
+        bincode::deserialize(payload)
+    }
+}
+
+/// Decode the invalidated blocks record of a file with format `version`
+pub fn decode_invalidated(payload: &[u8], version: u32) -> bincode::Result<HashMap<[u8; 32], Vec<Block>>> {
+    if version == 1 {
+        let decoded: HashMap<[u8; 32], Vec<v1::Block>> = bincode::deserialize(payload)?;
+        Ok(decoded
+            .into_iter()
+            .map(|(hash, blocks)| (hash, blocks.into_iter().map(Block::from).collect()))
+            .collect())
+    } else {
+        bincode::deserialize(payload)
+    }
+}
+
+/// Layout of version 1 files, before transactions carried a lock height
+pub mod v1 {
+    use serde::Deserialize;
+    use crate::block::BlockHeader;
+    use crate::transaction::{TxInput, TxOutput, TxType};
+
+    /// Transaction as stored in version 1 files
+    #[derive(Deserialize)]
+    pub struct Transaction {
+        pub version: u32,
+        pub tx_type: TxType,
+        pub inputs: Vec<TxInput>,
+        pub outputs: Vec<TxOutput>,
+        pub timestamp: i64,
+        pub memo: Vec<u8>,
+        pub replaceable: bool,
+    }
+
+    /// Block as stored in version 1 files
+    #[derive(Deserialize)]
+    pub struct Block {
+        pub header: BlockHeader,
+        pub transactions: Vec<Transaction>,
+    }
+
+    impl From<Transaction> for crate::transaction::Transaction {
+        fn from(tx: Transaction) -> Self {
+            Self {
+                version: tx.version,
+                tx_type: tx.tx_type,
+                inputs: tx.inputs,
+                outputs: tx.outputs,
+                timestamp: tx.timestamp,
+                memo: tx.memo,
+                replaceable: tx.replaceable,
+                lock_height: 0,
+            }
+        }
+    }
+
+    impl From<Block> for crate::block::Block {
+        fn from(block: Block) -> Self {
+            Self {
+                header: block.header,
+                transactions: block.transactions.into_iter().map(Into::into).collect(),
+            }
+        }
+    }
+}
+
+impl<'a> Iterator for RecordReader<'a> {
+    type Item = Result<(u8, &'a [u8]), StorageError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.pos == self.bytes.len() {
+            return None;
+        }
+
+        let offset = self.pos;
+        let rest = &self.bytes[offset..];
+        if rest.len() < 5 {
+            self.failed = true;
+            return Some(Err(StorageError::Truncated(offset)));
+        }
+
+        let kind = rest[0];
+        let len = u32::from_le_bytes([rest[1], rest[2], rest[3], rest[4]]) as usize;
+        let end = match 5usize.checked_add(len).and_then(|n| n.checked_add(32)) {
+            Some(end) if end <= rest.len() => end,
+            _ => {
+                self.failed = true;
+                return Some(Err(StorageError::Truncated(offset)));
+            }
+        };
+
+        let payload = &rest[5..5 + len];
+        if hashing::blake3(payload) != rest[5 + len..end] {
+            self.failed = true;
+            return Some(Err(StorageError::ChecksumMismatch(offset)));
+        }
+
+        self.pos += end;
+        Some(Ok((kind, payload)))
+    }
+}
+
+/// Chain file errors
+#[derive(Debug, thiserror::Error)]
+pub enum StorageError {
+    #[error("Not a chain file")]
+    BadMagic,
+
+    #[error("Unsupported chain file version {0}")]
+    UnsupportedVersion(u32),
+
+    #[error("Chain file truncated at offset {0}")]
+    Truncated(usize),
+
+    #[error("Checksum mismatch in record at offset {0}")]
+    ChecksumMismatch(usize),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_records_roundtrip() {
+        let mut out = Vec::new();
+        write_header(&mut out);
+        write_record(&mut out, RECORD_BLOCK, b"first");
+        write_record(&mut out, RECORD_STATE, b"second");
+
+        let records: Vec<_> = RecordReader::new(&out).unwrap().collect::<Result<_, _>>().unwrap();
+        assert_eq!(records, vec![(RECORD_BLOCK, &b"first"[..]), (RECORD_STATE, &b"second"[..])]);
+    }
+
+    #[test]
+    fn test_detects_corruption() {
+        let mut out = Vec::new();
+        write_header(&mut out);
+        write_record(&mut out, RECORD_BLOCK, b"first");
+        let second = out.len();
+        write_record(&mut out, RECORD_BLOCK, b"second");
+
+        let mut corrupted = out.clone();
+        corrupted[second + 6] ^= 0xff;
+        let mut reader = RecordReader::new(&corrupted).unwrap();
+        assert!(reader.next().unwrap().is_ok());
+        assert!(matches!(reader.next(), Some(Err(StorageError::ChecksumMismatch(o))) if o == second));
+        assert!(reader.next().is_none());
+
+        let truncated = &out[..out.len() - 1];
+        let mut reader = RecordReader::new(truncated).unwrap();
+        assert!(reader.next().unwrap().is_ok());
+        assert!(matches!(reader.next(), Some(Err(StorageError::Truncated(_)))));
+    }
+
+    #[test]
+    fn test_reads_older_versions() {
+        let mut out = Vec::new();
+        out.extend_from_slice(&CHAIN_FILE_MAGIC);
+        out.extend_from_slice(&1u32.to_le_bytes());
+        assert_eq!(RecordReader::new(&out).unwrap().version(), 1);
+
+        out[4..8].copy_from_slice(&(CHAIN_FILE_VERSION + 1).to_le_bytes());
+        assert!(matches!(RecordReader::new(&out), Err(StorageError::UnsupportedVersion(_))));
+
+        // A version 1 block decodes without a lock height
+        let block = Block::genesis();
+        let mut legacy = bincode::serialize(&block.header).unwrap();
+        legacy.extend_from_slice(&bincode::serialize(&(block.transactions.len() as u64)).unwrap());
+        for tx in &block.transactions {
+            let tx = (tx.version, &tx.tx_type, &tx.inputs, &tx.outputs, tx.timestamp, &tx.memo, tx.replaceable);
+            legacy.extend_from_slice(&bincode::serialize(&tx).unwrap());
+        }
+        assert_eq!(decode_block(&legacy, 1).unwrap().hash(), block.hash());
+        assert!(decode_block(&legacy, CHAIN_FILE_VERSION).is_err());
+    }
+
+    #[test]
+    fn test_write_atomic_keeps_backup() {
+        let path = std::env::temp_dir().join(format!("aequitas-atomic-{}.dat", std::process::id()));
+
+        write_atomic(&path, b"first").unwrap();
+        assert!(!backup_path(&path).exists());
+        write_atomic(&path, b"second").unwrap();
+
+        assert_eq!(std::fs::read(&path).unwrap(), b"second");
+        assert_eq!(std::fs::read(backup_path(&path)).unwrap(), b"first");
+        assert!(!temp_path(&path).exists());
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(backup_path(&path)).unwrap();
+    }
+}
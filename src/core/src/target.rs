@@ -0,0 +1,168 @@
+//! Proof-of-work targets
+//!
+//! Targets are 256-bit big-endian numbers; a hash is valid when it is less
+//! than or equal to the target. Headers carry the target in compact "bits"
+//! form (like Bitcoin's nBits): one exponent byte giving the length of the
+//! target in bytes, followed by a 23-bit mantissa.
+//!
+//! These routines are the single definition of target arithmetic shared by
+//! core validation, the consensus crate and the miner.
+
+use num_bigint::BigUint;
+use num_traits::{One, ToPrimitive, Zero};
+
+/// Largest possible target (every hash is valid)
+pub const MAX_TARGET: [u8; 32] = [0xff; 32];
+
+/// Check whether a hash meets a target (`hash <= target`, big-endian)
+pub fn hash_meets_target(hash: &[u8; 32], target: &[u8; 32]) -> bool {
+    hash[..] <= target[..]
+}
+
+/// Convert a difficulty to a target: `(2^256 - 1) / difficulty`
+pub fn difficulty_to_target(difficulty: u64) -> [u8; 32] {
+    if difficulty == 0 {
+        return MAX_TARGET;
+    }
+
+    let target = max_target() / BigUint::from(difficulty);
+    to_target_bytes(&target).unwrap_or(MAX_TARGET)
+}
+
+/// Convert a target back to a difficulty, saturating at `u64::MAX`
+pub fn target_to_difficulty(target: &[u8; 32]) -> u64 {
+    let target = BigUint::from_bytes_be(target);
+    if target.is_zero() {
+        return u64::MAX;
+    }
+
+    (max_target() / target).to_u64().unwrap_or(u64::MAX)
+}
+
+/// Expected number of hashes to meet a target: `2^256 / (target + 1)`
+///
+/// Saturates at `u128::MAX`.
+pub fn target_work(target: &[u8; 32]) -> u128 {
+    let target = BigUint::from_bytes_be(target);
+    let work = (BigUint::one() << 256u32) / (target + BigUint::one());
+    work.to_u128().unwrap_or(u128::MAX)
+}
+
+/// Encode a target in compact form
+pub fn target_to_compact(target: &[u8; 32]) -> u32 {
+    let value = BigUint::from_bytes_be(target);
+    if value.is_zero() {
+        return 0;
+    }
+
+    let mut size = value.to_bytes_be().len() as u32;
+    let mut mantissa = if size <= 3 {
+        value.to_u32().unwrap_or(0) << (8 * (3 - size))
+    } else {
+        (value >> (8 * (size - 3))).to_u32().unwrap_or(0)
+    };
+
+    // The high mantissa bit is a sign bit; keep it clear
+    if mantissa & 0x0080_0000 != 0 {
+        mantissa >>= 8;
+        size += 1;
+    }
+
+    (size << 24) | mantissa
+}
+
+/// Decode a compact target, rejecting negative or overflowing encodings
+pub fn compact_to_target(bits: u32) -> Option<[u8; 32]> {
+    let size = bits >> 24;
+    let mantissa = bits & 0x007f_ffff;
+
+    if mantissa == 0 {
+        return Some([0u8; 32]);
+    }
+    if bits & 0x0080_0000 != 0 {
+        return None;
+    }
+
+    let value = if size <= 3 {
+        BigUint::from(mantissa >> (8 * (3 - size)))
+    } else {
+        BigUint::from(mantissa) << (8 * (size - 3))
+    };
+
+    to_target_bytes(&value)
+}
+
+/// Compact target for a difficulty
+pub fn difficulty_to_compact(difficulty: u64) -> u32 {
+    target_to_compact(&difficulty_to_target(difficulty))
+}
+
+/// `2^256 - 1`
+fn max_target() -> BigUint {
+    BigUint::from_bytes_be(&MAX_TARGET)
+}
+
+/// Left-pad a number to 32 big-endian bytes, if it fits
+fn to_target_bytes(value: &BigUint) -> Option<[u8; 32]> {
+    let bytes = value.to_bytes_be();
+    if bytes.len() > 32 {
+        return None;
+    }
+
+    let mut result = [0u8; 32];
+    result[32 - bytes.len()..].copy_from_slice(&bytes);
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hash_meets_target() {
+        let mut target = [0u8; 32];
+        target[1] = 0x10;
+
+        let mut hash = [0u8; 32];
+        hash[1] = 0x0f;
+        hash[31] = 0xff;
+        assert!(hash_meets_target(&hash, &target));
+        assert!(hash_meets_target(&target, &target));
+
+        hash[1] = 0x10;
+        assert!(!hash_meets_target(&hash, &target));
+    }
+
+    #[test]
+    fn test_compact_roundtrip() {
+        for difficulty in [1u64, 2, 1_000, 1_000_000, u64::MAX] {
+            let bits = difficulty_to_compact(difficulty);
+            let target = compact_to_target(bits).unwrap();
+
+            // Compact form only drops low-order bits of the target
+            assert_eq!(target_to_compact(&target), bits);
+            assert!(target <= difficulty_to_target(difficulty));
+        }
+
+        // Bitcoin genesis nBits
+        let target = compact_to_target(0x1d00_ffff).unwrap();
+        assert_eq!(&target[4..6], &[0xff, 0xff]);
+        assert_eq!(target_to_compact(&target), 0x1d00_ffff);
+    }
+
+    #[test]
+    fn test_invalid_compact() {
+        assert!(compact_to_target(0x0480_0001).is_none());
+        assert!(compact_to_target(0x2201_0000).is_none());
+        assert_eq!(compact_to_target(0), Some([0u8; 32]));
+    }
+
+    #[test]
+    fn test_difficulty_and_work() {
+        assert_eq!(difficulty_to_target(1), MAX_TARGET);
+        assert_eq!(target_to_difficulty(&difficulty_to_target(1_000_000)), 1_000_000);
+        // Integer division rounds the target down and the work down
+        assert_eq!(target_work(&difficulty_to_target(1_000_000)), 999_999);
+        assert!(target_work(&difficulty_to_target(2_000)) > target_work(&difficulty_to_target(1_000)));
+    }
+}
@@ -3,9 +3,240 @@
 //! Defines transactions, inputs, and outputs with signature verification.
 
 use serde::{Deserialize, Serialize};
-use sha3::{Digest, Keccak256};
 use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
 use crate::address::Address;
+use crate::amount::checked_sum;
+use crate::blockchain::{UtxoId, UtxoView};
+use crate::encoding::{self, EncodingError};
+use crate::hashing;
+
+/// Maximum number of keys in a multisig policy
+pub const MAX_MULTISIG_KEYS: usize = 16;
+
+/// Default smallest output value nodes relay (0.00001 AEQ)
+///
+/// Outputs below it cost more in fees to spend than they are worth and would
+/// stay in the UTXO set forever. This is mempool policy, not consensus.
+pub const DUST_THRESHOLD: u64 = 10_000;
+
+/// Marks a transaction memo that carries one memo per output
+///
+/// The rest of the memo is a list of `output_index:u8 len:u8 memo:[len]`
+/// entries. Consensus treats it as an ordinary memo.
+pub const OUTPUT_MEMO_MAGIC: &[u8; 4] = b"AEQo";
+
+/// Marks a typed memo: `kind:u8` followed by the payload
+///
+/// Consensus treats it as an ordinary memo.
+pub const TYPED_MEMO_MAGIC: &[u8; 4] = b"AEQt";
+
+/// Longest payment reference
+pub const MAX_PAYMENT_REFERENCE_LEN: usize = 64;
+
+/// Memo with a declared payload type
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Memo {
+    /// UTF-8 text for people
+    Text(String),
+
+    /// Deposit or invoice identifier: ASCII letters, digits and `-_.:/`
+    PaymentReference(String),
+
+    /// JSON document
+    Json(String),
+
+    /// Opaque bytes
+    Binary(Vec<u8>),
+}
+
+impl Memo {
+    /// Name of the payload type
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Memo::Text(_) => "text",
+            Memo::PaymentReference(_) => "payment_reference",
+            Memo::Json(_) => "json",
+            Memo::Binary(_) => "binary",
+        }
+    }
+
+    /// Encode as a transaction memo, checking the payload matches its type
+    pub fn encode(&self) -> Result<Vec<u8>, TxError> {
+        let (kind, payload) = match self {
+            Memo::Text(text) => (0u8, text.as_bytes()),
+            Memo::PaymentReference(reference) => {
+                let valid = !reference.is_empty()
+                    && reference.len() <= MAX_PAYMENT_REFERENCE_LEN
+                    && reference.bytes().all(|b| b.is_ascii_alphanumeric() || b"-_.:/".contains(&b));
+                if !valid {
+                    return Err(TxError::InvalidMemo("malformed payment reference"));
+                }
+                (1, reference.as_bytes())
+            }
+            Memo::Json(json) => {
+                if serde_json::from_str::<serde_json::Value>(json).is_err() {
+                    return Err(TxError::InvalidMemo("invalid JSON"));
+                }
+                (2, json.as_bytes())
+            }
+            Memo::Binary(bytes) => (3, bytes.as_slice()),
+        };
+
+        let mut out = TYPED_MEMO_MAGIC.to_vec();
+        out.push(kind);
+        out.extend_from_slice(payload);
+        if out.len() > 256 {
+            return Err(TxError::MemoTooLarge);
+        }
+        Ok(out)
+    }
+
+    /// Decode a transaction memo packed by [`Memo::encode`]
+    ///
+    /// `None` for an untyped memo, an unknown type or a payload that does
+    /// not match its type.
+    pub fn decode(memo: &[u8]) -> Option<Self> {
+        let (&kind, payload) = memo.strip_prefix(TYPED_MEMO_MAGIC.as_slice())?.split_first()?;
+        let text = || String::from_utf8(payload.to_vec()).ok();
+        let decoded = match kind {
+            0 => Memo::Text(text()?),
+            1 => Memo::PaymentReference(text()?),
+            2 => Memo::Json(text()?),
+            3 => Memo::Binary(payload.to_vec()),
+            _ => return None,
+        };
+        // Decoding only yields what encoding would accept
+        decoded.encode().ok().map(|_| decoded)
+    }
+}
+
+impl std::fmt::Display for Memo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Memo::Text(text) | Memo::PaymentReference(text) | Memo::Json(text) => f.write_str(text),
+            Memo::Binary(bytes) => f.write_str(&hex::encode(bytes)),
+        }
+    }
+}
+
+/// Pack per-output memos into a transaction memo
+pub fn encode_output_memos(memos: &[(u8, Vec<u8>)]) -> Result<Vec<u8>, TxError> {
+    let mut out = OUTPUT_MEMO_MAGIC.to_vec();
+    for (index, memo) in memos {
+        let len = u8::try_from(memo.len()).map_err(|_| TxError::MemoTooLarge)?;
+        out.push(*index);
+        out.push(len);
+        out.extend_from_slice(memo);
+    }
+    if out.len() > 256 {
+        return Err(TxError::MemoTooLarge);
+    }
+    Ok(out)
+}
+
+/// M-of-N ed25519 multisig spending policy
+///
+/// Outputs pay to the policy's [`address`](MultisigPolicy::address); the
+/// spending input reveals the policy and carries at least `threshold`
+/// signatures from its keys.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MultisigPolicy {
+    /// Required number of signatures (M)
+    pub threshold: u8,
+    
+    /// Public keys allowed to sign (N)
+    pub public_keys: Vec<[u8; 32]>,
+}
+
+impl MultisigPolicy {
+    /// Create a policy, checking threshold and keys
+    pub fn new(threshold: u8, public_keys: Vec<[u8; 32]>) -> Result<Self, TxError> {
+        let policy = Self { threshold, public_keys };
+        policy.validate()?;
+        Ok(policy)
+    }
+    
+    /// Check that 1 <= M <= N <= MAX_MULTISIG_KEYS and keys are distinct
+    pub fn validate(&self) -> Result<(), TxError> {
+        let n = self.public_keys.len();
+        if self.threshold == 0 || self.threshold as usize > n || n > MAX_MULTISIG_KEYS {
+            return Err(TxError::InvalidMultisig);
+        }
+        
+        for (i, key) in self.public_keys.iter().enumerate() {
+            if self.public_keys[..i].contains(key) {
+                return Err(TxError::InvalidMultisig);
+            }
+            VerifyingKey::from_bytes(key).map_err(|_| TxError::InvalidPublicKey)?;
+        }
+        
+        Ok(())
+    }
+    
+    /// Address that outputs locked by this policy pay to
+    ///
+    /// Encodes the threshold and the keys in order, so the same keys with a
+    /// different threshold or order give a different address. Shown to
+    /// payers as a script-hash address, see [`Address::to_script_string`].
+    pub fn address(&self) -> Address {
+        let hash = hashing::keccak256_with(|buf| {
+            buf.extend_from_slice(b"aequitas-multisig");
+            buf.extend_from_slice(&[self.threshold, self.public_keys.len() as u8]);
+            for key in &self.public_keys {
+                buf.extend_from_slice(key);
+            }
+        });
+        
+        let mut bytes = [0u8; 20];
+        bytes.copy_from_slice(&hash[12..32]);
+        Address::from_bytes(bytes)
+    }
+    
+    /// Verify that enough distinct keys signed the message
+    pub fn verify(&self, message: &[u8], signatures: &[MultisigSignature]) -> Result<(), TxError> {
+        verify_batch(&self.signature_checks(message, signatures)?)
+    }
+    
+    /// Signatures to check for this policy, after checking the signature set
+    /// itself (threshold, key indices)
+    pub fn signature_checks<'a>(
+        &'a self,
+        message: &'a [u8],
+        signatures: &'a [MultisigSignature],
+    ) -> Result<Vec<SignatureCheck<'a>>, TxError> {
+        self.validate()?;
+        
+        if signatures.len() < self.threshold as usize {
+            return Err(TxError::NotEnoughSignatures);
+        }
+        
+        // Strictly increasing key indices rule out counting a key twice
+        let mut checks = Vec::with_capacity(signatures.len());
+        let mut last_index = None;
+        for sig in signatures {
+            if last_index.is_some_and(|last| sig.key_index <= last) {
+                return Err(TxError::InvalidMultisig);
+            }
+            last_index = Some(sig.key_index);
+            
+            let key = self.public_keys.get(sig.key_index as usize)
+                .ok_or(TxError::InvalidMultisig)?;
+            checks.push((message, sig.signature.as_slice(), key));
+        }
+        
+        Ok(checks)
+    }
+}
+
+/// One signature of a multisig input
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MultisigSignature {
+    /// Index of the signing key in the policy
+    pub key_index: u8,
+    
+    /// Ed25519 signature
+    pub signature: Vec<u8>,
+}
 
 /// Transaction input referencing a previous output
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -21,6 +252,12 @@ pub struct TxInput {
     
     /// Public key for verification
     pub public_key: Vec<u8>,
+    
+    /// Multisig policy, when spending a multisig output
+    pub multisig: Option<MultisigPolicy>,
+    
+    /// Multisig signatures, ordered by key index
+    pub multisig_signatures: Vec<MultisigSignature>,
 }
 
 impl TxInput {
@@ -31,6 +268,56 @@ impl TxInput {
             output_index,
             signature: Vec::new(),
             public_key: Vec::new(),
+            multisig: None,
+            multisig_signatures: Vec::new(),
+        }
+    }
+    
+    /// Create an input spending a multisig output
+    pub fn new_multisig(prev_tx_hash: [u8; 32], output_index: u32, policy: MultisigPolicy) -> Self {
+        Self {
+            multisig: Some(policy),
+            ..Self::new(prev_tx_hash, output_index)
+        }
+    }
+    
+    /// Add one co-signer's signature to a multisig input
+    pub fn sign_multisig(&mut self, signing_key: &SigningKey, message: &[u8]) -> Result<(), TxError> {
+        let policy = self.multisig.as_ref().ok_or(TxError::InvalidMultisig)?;
+        let public_key = signing_key.verifying_key().to_bytes();
+        let key_index = policy.public_keys.iter().position(|k| *k == public_key)
+            .ok_or(TxError::InvalidPublicKey)? as u8;
+        
+        let signature = MultisigSignature {
+            key_index,
+            signature: signing_key.sign(message).to_bytes().to_vec(),
+        };
+        
+        match self.multisig_signatures.binary_search_by_key(&key_index, |s| s.key_index) {
+            Ok(pos) => self.multisig_signatures[pos] = signature,
+            Err(pos) => self.multisig_signatures.insert(pos, signature),
+        }
+        Ok(())
+    }
+    
+    /// Address of the output this input is allowed to spend
+    pub fn owner(&self) -> Result<Address, TxError> {
+        if let Some(policy) = &self.multisig {
+            return Ok(policy.address());
+        }
+        
+        let pk_bytes: [u8; 32] = self.public_key.as_slice().try_into()
+            .map_err(|_| TxError::InvalidPublicKey)?;
+        let verifying_key = VerifyingKey::from_bytes(&pk_bytes)
+            .map_err(|_| TxError::InvalidPublicKey)?;
+        Ok(Address::from_public_key(&verifying_key))
+    }
+    
+    /// Whether the input carries enough signatures to be checked
+    pub fn is_signed(&self) -> bool {
+        match &self.multisig {
+            Some(policy) => self.multisig_signatures.len() >= policy.threshold as usize,
+            None => !self.signature.is_empty(),
         }
     }
     
@@ -43,26 +330,47 @@ impl TxInput {
     
     /// Verify the signature
     pub fn verify(&self, message: &[u8]) -> Result<(), TxError> {
-        if self.public_key.len() != 32 {
-            return Err(TxError::InvalidPublicKey);
+        verify_batch(&self.signature_checks(message)?)
+    }
+    
+    /// Signatures this input needs checked against `message`
+    pub fn signature_checks<'a>(&'a self, message: &'a [u8]) -> Result<Vec<SignatureCheck<'a>>, TxError> {
+        if let Some(policy) = &self.multisig {
+            return policy.signature_checks(message, &self.multisig_signatures);
         }
         
-        let pk_bytes: [u8; 32] = self.public_key.clone().try_into()
+        let public_key = self.public_key.as_slice().try_into()
             .map_err(|_| TxError::InvalidPublicKey)?;
-        let verifying_key = VerifyingKey::from_bytes(&pk_bytes)
-            .map_err(|_| TxError::InvalidPublicKey)?;
-        
-        if self.signature.len() != 64 {
-            return Err(TxError::InvalidSignature);
-        }
-        
-        let sig_bytes: [u8; 64] = self.signature.clone().try_into()
+        Ok(vec![(message, self.signature.as_slice(), public_key)])
+    }
+}
+
+/// One ed25519 signature to check: (message, signature, public key)
+pub type SignatureCheck<'a> = (&'a [u8], &'a [u8], &'a [u8; 32]);
+
+/// Verify many ed25519 signatures at once
+///
+/// Batch verification is several times cheaper per signature than checking
+/// them one by one, but only says whether all of them are valid; it rejects
+/// the whole batch otherwise. A single signature is checked on its own.
+pub fn verify_batch(checks: &[SignatureCheck]) -> Result<(), TxError> {
+    let mut messages = Vec::with_capacity(checks.len());
+    let mut signatures = Vec::with_capacity(checks.len());
+    let mut keys = Vec::with_capacity(checks.len());
+    for &(message, signature, public_key) in checks {
+        keys.push(VerifyingKey::from_bytes(public_key).map_err(|_| TxError::InvalidPublicKey)?);
+        let sig_bytes: [u8; 64] = signature.try_into()
             .map_err(|_| TxError::InvalidSignature)?;
-        let signature = Signature::from_bytes(&sig_bytes);
-        
-        verifying_key.verify(message, &signature)
-            .map_err(|_| TxError::InvalidSignature)
+        signatures.push(Signature::from_bytes(&sig_bytes));
+        messages.push(message);
     }
+    
+    let valid = match checks.len() {
+        0 => true,
+        1 => keys[0].verify(messages[0], &signatures[0]).is_ok(),
+        _ => ed25519_dalek::verify_batch(&messages, &signatures, &keys).is_ok(),
+    };
+    if valid { Ok(()) } else { Err(TxError::InvalidSignature) }
 }
 
 /// Transaction output specifying recipient and amount
@@ -80,6 +388,16 @@ impl TxOutput {
     pub fn new(recipient: Address, amount: u64) -> Self {
         Self { amount, recipient }
     }
+    
+    /// Create an output locked by an M-of-N multisig policy
+    pub fn multisig(policy: &MultisigPolicy, amount: u64) -> Self {
+        Self::new(policy.address(), amount)
+    }
+    
+    /// Whether the output is worth less than `threshold`
+    pub fn is_dust(&self, threshold: u64) -> bool {
+        self.amount < threshold
+    }
 }
 
 /// Transaction types
@@ -96,8 +414,14 @@ pub enum TxType {
     
     /// Governance proposal
     Proposal,
+    
+    /// Payout of an approved proposal from the treasury
+    TreasurySpend,
 }
 
+/// First transaction version whose body commits to `lock_height`
+pub const LOCK_HEIGHT_TX_VERSION: u32 = 2;
+
 /// A complete transaction
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Transaction {
@@ -118,6 +442,17 @@ pub struct Transaction {
     
     /// Optional memo/data field (max 256 bytes)
     pub memo: Vec<u8>,
+    
+    /// Opt-in replace-by-fee: while pending, this transaction may be replaced
+    /// by a higher-fee one spending any of the same inputs
+    pub replaceable: bool,
+    
+    /// The transaction may only be included in a block above this height
+    /// (0: any block). Wallets set it to the tip so that re-mining past
+    /// blocks cannot collect the transaction's fee. Requires version
+    /// [`LOCK_HEIGHT_TX_VERSION`].
+    #[serde(default)]
+    pub lock_height: u64,
 }
 
 impl Transaction {
@@ -130,6 +465,8 @@ impl Transaction {
             outputs,
             timestamp: chrono::Utc::now().timestamp(),
             memo: Vec::new(),
+            replaceable: false,
+            lock_height: 0,
         }
     }
     
@@ -146,40 +483,134 @@ impl Transaction {
             outputs: vec![TxOutput::new(recipient, reward)],
             timestamp: chrono::Utc::now().timestamp(),
             memo,
+            replaceable: false,
+            lock_height: 0,
         }
     }
     
-    /// Compute transaction hash
+    /// Lock the transaction to blocks above `height`, upgrading its version
+    /// so the lock is committed to and signed
+    pub fn with_lock_height(mut self, height: u64) -> Self {
+        self.version = self.version.max(LOCK_HEIGHT_TX_VERSION);
+        self.lock_height = height;
+        self
+    }
+    
+    /// Whether the transaction may be included in a block at `height`
+    pub fn is_final(&self, height: u64) -> bool {
+        self.lock_height == 0 || self.lock_height < height
+    }
+    
+    /// Compute transaction hash (the txid)
     pub fn hash(&self) -> [u8; 32] {
-        let serialized = bincode::serialize(self).expect("Failed to serialize tx");
-        let mut hasher = Keccak256::new();
-        hasher.update(&serialized);
-        hasher.finalize().into()
+        self.txid()
     }
     
-    /// Get the message to sign (excludes signatures)
-    pub fn signing_message(&self) -> Vec<u8> {
-        let mut msg = Vec::new();
-        msg.extend_from_slice(&self.version.to_le_bytes());
+    /// Transaction id: hash of the canonical body, excluding witness data
+    ///
+    /// Stable while signatures are attached, so outputs can be referenced
+    /// before the transaction is fully signed.
+    pub fn txid(&self) -> [u8; 32] {
+        hashing::keccak256_with(|buf| encoding::encode_body_into(self, buf))
+    }
+    
+    /// Witness transaction id: hash of the full canonical encoding
+    pub fn wtxid(&self) -> [u8; 32] {
+        hashing::keccak256_with(|buf| encoding::encode_into(self, buf))
+    }
+    
+    /// Typed memo, if the memo was packed by [`Memo::encode`]
+    pub fn typed_memo(&self) -> Option<Memo> {
+        Memo::decode(&self.memo)
+    }
+    
+    /// Per-output memos, if the memo was packed by [`encode_output_memos`]
+    ///
+    /// Empty for a plain or malformed memo.
+    pub fn output_memos(&self) -> Vec<(u8, Vec<u8>)> {
+        let Some(mut rest) = self.memo.strip_prefix(OUTPUT_MEMO_MAGIC.as_slice()) else {
+            return Vec::new();
+        };
         
-        for input in &self.inputs {
-            msg.extend_from_slice(&input.prev_tx_hash);
-            msg.extend_from_slice(&input.output_index.to_le_bytes());
+        let mut memos = Vec::new();
+        while let [index, len, tail @ ..] = rest {
+            let len = *len as usize;
+            if tail.len() < len {
+                return Vec::new();
+            }
+            memos.push((*index, tail[..len].to_vec()));
+            rest = &tail[len..];
         }
-        
-        for output in &self.outputs {
-            msg.extend_from_slice(&output.amount.to_le_bytes());
-            msg.extend_from_slice(&output.recipient.to_bytes());
+        if !rest.is_empty() {
+            return Vec::new();
         }
-        
-        msg.extend_from_slice(&self.timestamp.to_le_bytes());
-        msg.extend_from_slice(&self.memo);
-        
-        msg
+        memos
+    }
+    
+    /// Canonical wire encoding (body followed by witness)
+    pub fn encode(&self) -> Vec<u8> {
+        encoding::encode(self)
+    }
+    
+    /// Decode a transaction from its canonical wire encoding
+    pub fn decode(bytes: &[u8]) -> Result<Self, EncodingError> {
+        encoding::decode(bytes)
+    }
+    
+    /// Size of the canonical wire encoding in bytes
+    ///
+    /// The size mempool policy, fee rates and block templates are measured in.
+    pub fn size(&self) -> usize {
+        self.encode().len()
+    }
+    
+    /// Fee paid: inputs found in `utxos` minus outputs
+    ///
+    /// `None` if an input is not in `utxos` or outputs exceed inputs.
+    pub fn fee(&self, utxos: &impl UtxoView) -> Option<u64> {
+        let mut input_total = 0u64;
+        for input in &self.inputs {
+            let utxo_id = UtxoId::new(input.prev_tx_hash, input.output_index);
+            input_total = input_total.checked_add(utxos.utxo(&utxo_id)?.amount)?;
+        }
+        input_total.checked_sub(self.total_output().ok()?)
+    }
+    
+    /// Fee per byte of [`size`](Self::size) when paying `fee`
+    pub fn fee_rate(&self, fee: u64) -> f64 {
+        fee as f64 / self.size().max(1) as f64
+    }
+    
+    /// Get the message to sign: the canonical body, as identified by the txid
+    pub fn signing_message(&self) -> Vec<u8> {
+        encoding::encode_body(self)
     }
     
     /// Validate the transaction
     pub fn validate(&self) -> Result<(), TxError> {
+        self.validate_structure()?;
+
+        if self.requires_signatures() {
+            self.verify_signatures()?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether the inputs carry signatures to verify
+    ///
+    /// Treasury spends are authorized by governance rather than signatures.
+    pub fn requires_signatures(&self) -> bool {
+        !matches!(self.tx_type, TxType::Coinbase | TxType::TreasurySpend)
+    }
+
+    /// Validate the transaction structure without checking signatures
+    pub fn validate_structure(&self) -> Result<(), TxError> {
+        // Older versions do not commit to the lock height
+        if self.lock_height != 0 && self.version < LOCK_HEIGHT_TX_VERSION {
+            return Err(TxError::UncommittedLockHeight);
+        }
+        
         // Coinbase transactions have special rules
         if self.tx_type == TxType::Coinbase {
             if !self.inputs.is_empty() {
@@ -188,6 +619,7 @@ impl Transaction {
             if self.outputs.is_empty() {
                 return Err(TxError::NoOutputs);
             }
+            self.total_output()?;
             return Ok(());
         }
         
@@ -199,23 +631,64 @@ impl Transaction {
             return Err(TxError::NoOutputs);
         }
         
-        // Verify all signatures
-        let message = self.signing_message();
-        for input in &self.inputs {
-            input.verify(&message)?;
-        }
-        
         // Check memo size
         if self.memo.len() > 256 {
             return Err(TxError::MemoTooLarge);
         }
         
+        // Zero-value outputs only exist to carry a memo
+        if let Some(index) = (0..self.outputs.len())
+            .find(|&i| self.outputs[i].amount == 0 && !self.is_memo_output(i))
+        {
+            return Err(TxError::ZeroAmount(index));
+        }
+        self.total_output()?;
+        
+        for input in &self.inputs {
+            if let Some(policy) = &input.multisig {
+                policy.validate()?;
+            }
+        }
+        
         Ok(())
     }
     
+    /// Verify the signatures of all inputs, as one batch
+    pub fn verify_signatures(&self) -> Result<(), TxError> {
+        let message = self.signing_message();
+        verify_batch(&self.signature_checks(&message)?)
+    }
+    
+    /// Signatures of all inputs to check against `message`, the signing
+    /// message of this transaction
+    pub fn signature_checks<'a>(&'a self, message: &'a [u8]) -> Result<Vec<SignatureCheck<'a>>, TxError> {
+        let mut checks = Vec::with_capacity(self.inputs.len());
+        for input in &self.inputs {
+            checks.extend(input.signature_checks(message)?);
+        }
+        Ok(checks)
+    }
+    
     /// Calculate total output amount
-    pub fn total_output(&self) -> u64 {
-        self.outputs.iter().map(|o| o.amount).sum()
+    ///
+    /// Fails if the outputs overflow or exceed `MAX_SUPPLY` together.
+    pub fn total_output(&self) -> Result<u64, TxError> {
+        checked_sum(self.outputs.iter().map(|o| o.amount)).ok_or(TxError::AmountOverflow)
+    }
+    
+    /// Whether output `index` is worth nothing and carries a per-output memo
+    pub fn is_memo_output(&self, index: usize) -> bool {
+        self.outputs.get(index).is_some_and(|output| output.amount == 0)
+            && self.output_memos().iter().any(|(i, _)| *i as usize == index)
+    }
+    
+    /// Check whether two transactions spend any of the same outputs
+    pub fn conflicts_with(&self, other: &Transaction) -> bool {
+        self.inputs.iter().any(|a| {
+            other.inputs.iter().any(|b| {
+                a.prev_tx_hash == b.prev_tx_hash && a.output_index == b.output_index
+            })
+        })
     }
 }
 
@@ -245,11 +718,33 @@ pub enum TxError {
     
     #[error("Double spend detected")]
     DoubleSpend,
+    
+    #[error("Invalid multisig policy or signature set")]
+    InvalidMultisig,
+    
+    #[error("Not enough multisig signatures")]
+    NotEnoughSignatures,
+    
+    #[error("Output {0} has a zero amount and carries no memo")]
+    ZeroAmount(usize),
+    
+    #[error("Amounts overflow or exceed the maximum supply")]
+    AmountOverflow,
+    
+    #[error("Invalid memo: {0}")]
+    InvalidMemo(&'static str),
+    
+    #[error("Lock height requires transaction version {LOCK_HEIGHT_TX_VERSION} or later")]
+    UncommittedLockHeight,
+    
+    #[error("Transaction is locked to blocks above height {0}")]
+    NotFinal(u64),
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::blockchain::MAX_SUPPLY;
     
     #[test]
     fn test_coinbase_transaction() {
@@ -262,6 +757,185 @@ mod tests {
         assert!(tx.validate().is_ok());
     }
     
+    fn multisig_tx(policy: &MultisigPolicy) -> Transaction {
+        let input = TxInput::new_multisig([7u8; 32], 0, policy.clone());
+        let output = TxOutput::new(Address::genesis_address(), 1_000);
+        Transaction::new_transfer(vec![input], vec![output])
+    }
+    
+    #[test]
+    fn test_multisig_2_of_3() {
+        let keys: Vec<SigningKey> = (1..=3u8).map(|i| SigningKey::from_bytes(&[i; 32])).collect();
+        let public_keys = keys.iter().map(|k| k.verifying_key().to_bytes()).collect();
+        let policy = MultisigPolicy::new(2, public_keys).unwrap();
+        
+        let mut tx = multisig_tx(&policy);
+        let message = tx.signing_message();
+        assert_eq!(tx.inputs[0].owner().unwrap(), policy.address());
+        
+        tx.inputs[0].sign_multisig(&keys[2], &message).unwrap();
+        assert!(matches!(tx.validate(), Err(TxError::NotEnoughSignatures)));
+        
+        tx.inputs[0].sign_multisig(&keys[0], &message).unwrap();
+        assert!(tx.inputs[0].is_signed());
+        assert!(tx.validate().is_ok());
+        
+        // A key outside the policy cannot co-sign
+        let outsider = SigningKey::from_bytes(&[9u8; 32]);
+        assert!(tx.inputs[0].sign_multisig(&outsider, &message).is_err());
+    }
+    
+    #[test]
+    fn test_verify_batch() {
+        let keys: Vec<SigningKey> = (1..=4u8).map(|i| SigningKey::from_bytes(&[i; 32])).collect();
+        let public_keys: Vec<[u8; 32]> = keys.iter().map(|k| k.verifying_key().to_bytes()).collect();
+        let messages: Vec<Vec<u8>> = (0..4u8).map(|i| vec![i; 10]).collect();
+        let signatures: Vec<Vec<u8>> = keys.iter().zip(&messages)
+            .map(|(key, message)| key.sign(message).to_bytes().to_vec())
+            .collect();
+        
+        let mut checks: Vec<SignatureCheck> = (0..4)
+            .map(|i| (messages[i].as_slice(), signatures[i].as_slice(), &public_keys[i]))
+            .collect();
+        assert!(verify_batch(&checks).is_ok());
+        assert!(verify_batch(&checks[..1]).is_ok());
+        assert!(verify_batch(&[]).is_ok());
+        
+        // One wrong message fails the whole batch
+        checks[2].0 = messages[3].as_slice();
+        assert!(matches!(verify_batch(&checks), Err(TxError::InvalidSignature)));
+        assert!(matches!(verify_batch(&checks[2..3]), Err(TxError::InvalidSignature)));
+    }
+    
+    #[test]
+    fn test_multisig_rejects_duplicates() {
+        let keys: Vec<SigningKey> = (1..=3u8).map(|i| SigningKey::from_bytes(&[i; 32])).collect();
+        let public_keys: Vec<[u8; 32]> = keys.iter().map(|k| k.verifying_key().to_bytes()).collect();
+        assert!(MultisigPolicy::new(0, public_keys.clone()).is_err());
+        assert!(MultisigPolicy::new(4, public_keys.clone()).is_err());
+        assert!(MultisigPolicy::new(2, vec![public_keys[0], public_keys[0]]).is_err());
+        
+        let policy = MultisigPolicy::new(2, public_keys).unwrap();
+        let mut tx = multisig_tx(&policy);
+        let message = tx.signing_message();
+        tx.inputs[0].sign_multisig(&keys[1], &message).unwrap();
+        
+        // The same signature counted twice does not meet the threshold
+        let sig = tx.inputs[0].multisig_signatures[0].clone();
+        tx.inputs[0].multisig_signatures.push(sig);
+        assert!(matches!(tx.validate(), Err(TxError::InvalidMultisig)));
+        
+        // Policy with a different threshold locks a different address
+        let other = MultisigPolicy { threshold: 3, ..policy.clone() };
+        assert_ne!(other.address(), policy.address());
+    }
+    
+    #[test]
+    fn test_replaceable_flag_is_signed() {
+        let key = SigningKey::from_bytes(&[5u8; 32]);
+        let input = TxInput::new([1u8; 32], 0);
+        let output = TxOutput::new(Address::genesis_address(), 1_000);
+        let mut tx = Transaction::new_transfer(vec![input], vec![output]);
+        
+        let message = tx.signing_message();
+        tx.inputs[0].sign(&key, &message);
+        assert!(tx.validate().is_ok());
+        
+        // Flipping the flag after signing invalidates the signature
+        tx.replaceable = true;
+        assert!(matches!(tx.validate(), Err(TxError::InvalidSignature)));
+        
+        let mut replacement = tx.clone();
+        replacement.outputs[0].amount = 900;
+        assert!(replacement.conflicts_with(&tx));
+        replacement.inputs[0].output_index = 1;
+        assert!(!replacement.conflicts_with(&tx));
+    }
+    
+    #[test]
+    fn test_typed_memos() {
+        let memos = [
+            Memo::Text("merci ☕".to_string()),
+            Memo::PaymentReference("DEP-2026/00042".to_string()),
+            Memo::Json(r#"{"invoice":42}"#.to_string()),
+            Memo::Binary(vec![0, 255, 7]),
+        ];
+        for memo in memos {
+            let mut tx = Transaction::new_transfer(Vec::new(), Vec::new());
+            tx.memo = memo.encode().unwrap();
+            assert_eq!(tx.typed_memo(), Some(memo));
+        }
+
+        assert!(matches!(Memo::PaymentReference("dep 42".to_string()).encode(), Err(TxError::InvalidMemo(_))));
+        assert!(matches!(Memo::PaymentReference("x".repeat(65)).encode(), Err(TxError::InvalidMemo(_))));
+        assert!(matches!(Memo::Json("{oops".to_string()).encode(), Err(TxError::InvalidMemo(_))));
+        assert!(matches!(Memo::Binary(vec![0; 252]).encode(), Err(TxError::MemoTooLarge)));
+
+        // Untyped, unknown or mismatched payloads are not typed memos
+        assert_eq!(Memo::decode(b"plain memo"), None);
+        assert_eq!(Memo::decode(b"AEQt\x09data"), None);
+        assert_eq!(Memo::decode(b"AEQt\x01not a reference"), None);
+    }
+    
+    #[test]
+    fn test_output_memos_roundtrip() {
+        let memos = vec![(0, b"invoice 42".to_vec()), (2, Vec::new())];
+        let mut tx = Transaction::new_transfer(Vec::new(), Vec::new());
+        tx.memo = encode_output_memos(&memos).unwrap();
+        assert_eq!(tx.output_memos(), memos);
+        
+        tx.memo.pop();
+        assert!(tx.output_memos().is_empty());
+        tx.memo = b"plain memo".to_vec();
+        assert!(tx.output_memos().is_empty());
+        
+        assert!(matches!(encode_output_memos(&[(0, vec![0; 300])]), Err(TxError::MemoTooLarge)));
+    }
+    
+    #[test]
+    fn test_fee_from_utxo_view() {
+        let funding = UtxoId::new([3u8; 32], 1);
+        let mut utxos = std::collections::HashMap::new();
+        utxos.insert(funding.clone(), TxOutput::new(Address::genesis_address(), 5_000));
+
+        let input = TxInput::new(funding.tx_hash, funding.output_index);
+        let tx = Transaction::new_transfer(vec![input], vec![TxOutput::new(Address::genesis_address(), 4_000)]);
+        assert_eq!(tx.fee(&utxos), Some(1_000));
+        assert_eq!(tx.fee_rate(1_000), 1_000.0 / tx.size() as f64);
+
+        // Unknown inputs and outputs above inputs have no fee
+        utxos.get_mut(&funding).unwrap().amount = 3_000;
+        assert_eq!(tx.fee(&utxos), None);
+        utxos.clear();
+        assert_eq!(tx.fee(&utxos), None);
+    }
+
+    #[test]
+    fn test_amount_validation() {
+        let key = SigningKey::from_bytes(&[6u8; 32]);
+        let signed = |outputs: Vec<TxOutput>, memo: Vec<u8>| {
+            let mut tx = Transaction::new_transfer(vec![TxInput::new([1u8; 32], 0)], outputs);
+            tx.memo = memo;
+            let message = tx.signing_message();
+            tx.inputs[0].sign(&key, &message);
+            tx
+        };
+        let addr = Address::genesis_address();
+        
+        let zero = vec![TxOutput::new(addr.clone(), 1_000), TxOutput::new(addr.clone(), 0)];
+        assert!(matches!(signed(zero.clone(), Vec::new()).validate(), Err(TxError::ZeroAmount(1))));
+        
+        // A zero-value output is fine when it carries a memo
+        let memo = encode_output_memos(&[(1, b"anchor".to_vec())]).unwrap();
+        assert!(signed(zero, memo).validate().is_ok());
+        
+        let overflow = vec![TxOutput::new(addr.clone(), u64::MAX), TxOutput::new(addr.clone(), 2)];
+        assert!(matches!(signed(overflow, Vec::new()).validate(), Err(TxError::AmountOverflow)));
+        
+        let above_supply = vec![TxOutput::new(addr.clone(), MAX_SUPPLY), TxOutput::new(addr, 1)];
+        assert!(matches!(signed(above_supply, Vec::new()).total_output(), Err(TxError::AmountOverflow)));
+    }
+    
     #[test]
     fn test_transaction_hash_deterministic() {
         let addr = Address::genesis_address();
@@ -0,0 +1,275 @@
+//! Unspent output set
+//!
+//! A map of every unspent output grows to millions of entries, and a plain
+//! `HashMap<UtxoId, TxOutput>` spends much of its memory on empty buckets:
+//! each bucket holds a whole 72-byte entry, and right after a resize over
+//! half of them are vacant. [`UtxoSet`] keeps the entries densely in an
+//! arena instead and indexes them with a table of 4-byte arena slots, hashed
+//! by outpoint, so only the small slots pay for the spare capacity. Spending
+//! an output moves the last entry into its slot, keeping the arena dense.
+//!
+//! The set serializes as a map, exactly like the `HashMap` it replaces, so
+//! chain files are unchanged.
+
+use std::collections::hash_map::RandomState;
+use std::fmt;
+use std::hash::BuildHasher;
+use hashbrown::hash_table::{Entry, HashTable};
+use serde::de::{MapAccess, Visitor};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use crate::blockchain::{UtxoId, UtxoView};
+use crate::transaction::TxOutput;
+
+/// An unspent output and its id, as stored in the arena
+#[derive(Clone, Debug)]
+struct Coin {
+    id: UtxoId,
+    output: TxOutput,
+}
+
+/// Size of the set
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UtxoStats {
+    /// Unspent outputs
+    pub count: usize,
+
+    /// Heap bytes held by the set, spare capacity included
+    pub size_bytes: usize,
+}
+
+/// Unspent outputs by id
+#[derive(Clone, Default)]
+pub struct UtxoSet {
+    coins: Vec<Coin>,
+    /// Arena slot of every coin, hashed by its id
+    index: HashTable<u32>,
+    hasher: RandomState,
+}
+
+impl UtxoSet {
+    /// An empty set
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An empty set with room for `capacity` outputs
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            coins: Vec::with_capacity(capacity),
+            index: HashTable::with_capacity(capacity),
+            hasher: RandomState::new(),
+        }
+    }
+
+    /// Number of unspent outputs
+    pub fn len(&self) -> usize {
+        self.coins.len()
+    }
+
+    /// Whether the set is empty
+    pub fn is_empty(&self) -> bool {
+        self.coins.is_empty()
+    }
+
+    /// Unspent output with this id
+    pub fn get(&self, id: &UtxoId) -> Option<&TxOutput> {
+        let slot = self.index.find(self.hasher.hash_one(id), |&slot| self.coins[slot as usize].id == *id)?;
+        Some(&self.coins[*slot as usize].output)
+    }
+
+    /// Whether an output with this id is unspent
+    pub fn contains(&self, id: &UtxoId) -> bool {
+        self.get(id).is_some()
+    }
+
+    /// Add an output, returning the one it replaced
+    pub fn insert(&mut self, id: UtxoId, output: TxOutput) -> Option<TxOutput> {
+        let Self { coins, index, hasher } = self;
+        let entry = index.entry(
+            hasher.hash_one(&id),
+            |&slot| coins[slot as usize].id == id,
+            |&slot| hasher.hash_one(&coins[slot as usize].id),
+        );
+        match entry {
+            Entry::Occupied(entry) => {
+                Some(std::mem::replace(&mut coins[*entry.get() as usize].output, output))
+            }
+            Entry::Vacant(entry) => {
+                let slot = u32::try_from(coins.len()).expect("UTXO set exceeds u32::MAX entries");
+                entry.insert(slot);
+                coins.push(Coin { id, output });
+                None
+            }
+        }
+    }
+
+    /// Remove an output, returning it if it was unspent
+    pub fn remove(&mut self, id: &UtxoId) -> Option<TxOutput> {
+        let coins = &self.coins;
+        let slot = match self.index.find_entry(self.hasher.hash_one(id), |&slot| coins[slot as usize].id == *id) {
+            Ok(entry) => entry.remove().0 as usize,
+            Err(_) => return None,
+        };
+
+        let last = self.coins.len() - 1;
+        let removed = self.coins.swap_remove(slot);
+        if slot != last {
+            // The last coin moved into the freed slot
+            let hash = self.hasher.hash_one(&self.coins[slot].id);
+            let moved = self.index
+                .find_mut(hash, |&s| s as usize == last)
+                .expect("every coin is indexed");
+            *moved = slot as u32;
+        }
+        Some(removed.output)
+    }
+
+    /// Every unspent output with its id, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (&UtxoId, &TxOutput)> + '_ {
+        self.coins.iter().map(|coin| (&coin.id, &coin.output))
+    }
+
+    /// Every unspent output, in no particular order
+    pub fn values(&self) -> impl Iterator<Item = &TxOutput> + '_ {
+        self.coins.iter().map(|coin| &coin.output)
+    }
+
+    /// Release spare capacity
+    pub fn shrink_to_fit(&mut self) {
+        let Self { coins, index, hasher } = self;
+        coins.shrink_to_fit();
+        index.shrink_to_fit(|&slot| hasher.hash_one(&coins[slot as usize].id));
+    }
+
+    /// Number of outputs and memory held
+    pub fn stats(&self) -> UtxoStats {
+        // The table keeps one control byte per bucket besides the slot, and
+        // holds at most 7 entries per 8 buckets
+        let buckets = (self.index.capacity() * 8 / 7).next_power_of_two();
+        UtxoStats {
+            count: self.coins.len(),
+            size_bytes: self.coins.capacity() * std::mem::size_of::<Coin>()
+                + buckets * (std::mem::size_of::<u32>() + 1),
+        }
+    }
+}
+
+impl UtxoView for UtxoSet {
+    fn utxo(&self, id: &UtxoId) -> Option<&TxOutput> {
+        self.get(id)
+    }
+}
+
+impl FromIterator<(UtxoId, TxOutput)> for UtxoSet {
+    fn from_iter<I: IntoIterator<Item = (UtxoId, TxOutput)>>(iter: I) -> Self {
+        let mut set = UtxoSet::new();
+        set.extend(iter);
+        set
+    }
+}
+
+impl Extend<(UtxoId, TxOutput)> for UtxoSet {
+    fn extend<I: IntoIterator<Item = (UtxoId, TxOutput)>>(&mut self, iter: I) {
+        let iter = iter.into_iter();
+        let (additional, _) = iter.size_hint();
+        self.coins.reserve(additional);
+        let Self { coins, index, hasher } = self;
+        index.reserve(additional, |&slot| hasher.hash_one(&coins[slot as usize].id));
+        for (id, output) in iter {
+            self.insert(id, output);
+        }
+    }
+}
+
+impl Serialize for UtxoSet {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.len()))?;
+        for (id, output) in self.iter() {
+            map.serialize_entry(id, output)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for UtxoSet {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct SetVisitor;
+
+        impl<'de> Visitor<'de> for SetVisitor {
+            type Value = UtxoSet;
+
+            fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str("a map of unspent outputs")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<UtxoSet, A::Error> {
+                // Bound the reservation: the length comes from the input
+                let mut set = UtxoSet::with_capacity(access.size_hint().unwrap_or(0).min(1 << 20));
+                while let Some((id, output)) = access.next_entry()? {
+                    set.insert(id, output);
+                }
+                set.shrink_to_fit();
+                Ok(set)
+            }
+        }
+
+        deserializer.deserialize_map(SetVisitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address::Address;
+    use std::collections::HashMap;
+
+    fn id(n: u32) -> UtxoId {
+        UtxoId::new([(n % 251) as u8; 32], n)
+    }
+
+    fn output(n: u32) -> TxOutput {
+        TxOutput::new(Address::genesis_address(), n as u64)
+    }
+
+    #[test]
+    fn test_insert_remove() {
+        let mut set = UtxoSet::new();
+        for n in 0..100 {
+            assert_eq!(set.insert(id(n), output(n)), None);
+        }
+        assert_eq!(set.insert(id(7), output(700)), Some(output(7)));
+        assert_eq!(set.len(), 100);
+
+        // Removing from the middle moves the last coin; it stays reachable
+        for n in (0..100).step_by(3) {
+            assert_eq!(set.remove(&id(n)), Some(output(n)));
+        }
+        assert_eq!(set.remove(&id(0)), None);
+        for n in 0..100 {
+            let expected = (n % 3 != 0).then(|| output(if n == 7 { 700 } else { n }));
+            assert_eq!(set.get(&id(n)), expected.as_ref());
+        }
+        assert_eq!(set.len(), 66);
+
+        set.shrink_to_fit();
+        assert_eq!(set.get(&id(98)), Some(&output(98)));
+        assert_eq!(set.stats().count, 66);
+        assert!(set.stats().size_bytes >= 66 * std::mem::size_of::<Coin>());
+    }
+
+    #[test]
+    fn test_serializes_like_a_map() {
+        let map: HashMap<UtxoId, TxOutput> = (0..10).map(|n| (id(n), output(n))).collect();
+        let set: UtxoSet = map.clone().into_iter().collect();
+
+        // The same bytes decode into either type
+        let decoded: UtxoSet = bincode::deserialize(&bincode::serialize(&map).unwrap()).unwrap();
+        assert_eq!(decoded.len(), 10);
+        assert_eq!(decoded.get(&id(4)), Some(&output(4)));
+
+        let decoded: HashMap<UtxoId, TxOutput> =
+            bincode::deserialize(&bincode::serialize(&set).unwrap()).unwrap();
+        assert_eq!(decoded, map);
+    }
+}
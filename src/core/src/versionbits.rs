@@ -0,0 +1,247 @@
+//! Version-bits deployments
+//!
+//! Soft forks activate by miner signalling, after BIP 9. A block signals
+//! for a deployment by setting the top three bits of its header version to
+//! [`VERSION_BITS_TOP_BITS`] and the deployment's bit. Signals are counted
+//! over periods of [`ChainParams::signal_period`] blocks, and at each period
+//! boundary a deployment moves along
+//!
+//! ```text
+//! Defined -> Started -> LockedIn -> Active
+//!               |
+//!               +-----> Failed
+//! ```
+//!
+//! It is Defined until the period starting at or after its start height,
+//! then Started. A Started deployment locks in once a whole period
+//! signalled it in at least [`ChainParams::signal_threshold`] blocks, and
+//! activates one period later; one still below the threshold by the
+//! period starting at or after its timeout height fails. Heights stand in
+//! for BIP 9's median times, so the schedule is the same on every node.
+//!
+//! [`ChainParams::signal_period`]: crate::params::ChainParams::signal_period
+//! [`ChainParams::signal_threshold`]: crate::params::ChainParams::signal_threshold
+
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+
+/// Top bits of a version that signals
+pub const VERSION_BITS_TOP_BITS: u32 = 0x2000_0000;
+
+/// Mask selecting the top bits of a version
+pub const VERSION_BITS_TOP_MASK: u32 = 0xE000_0000;
+
+/// Bits available for deployments, below the top bits
+pub const VERSION_BITS_COUNT: usize = 29;
+
+/// A soft fork activated by version-bits signalling
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Deployment {
+    /// Name shown by `getdeploymentinfo`
+    pub name: &'static str,
+
+    /// Version bit signalling it, below [`VERSION_BITS_COUNT`]
+    pub bit: u8,
+
+    /// Signalling starts with the first period starting at or after this
+    pub start_height: u64,
+
+    /// Signalling fails with the first period starting at or after this
+    pub timeout_height: u64,
+}
+
+/// Where a deployment stands
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeploymentState {
+    /// Signalling has not started
+    Defined,
+
+    /// Signalling is counted
+    Started,
+
+    /// The threshold was reached; activates with the next period
+    LockedIn,
+
+    /// The new rules apply
+    Active,
+
+    /// Timed out before reaching the threshold
+    Failed,
+}
+
+impl DeploymentState {
+    /// Name used by the RPC
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DeploymentState::Defined => "defined",
+            DeploymentState::Started => "started",
+            DeploymentState::LockedIn => "locked_in",
+            DeploymentState::Active => "active",
+            DeploymentState::Failed => "failed",
+        }
+    }
+
+    /// Whether blocks should signal the deployment
+    pub fn is_signalling(self) -> bool {
+        matches!(self, DeploymentState::Started | DeploymentState::LockedIn)
+    }
+}
+
+/// Whether `version` signals `bit`
+pub fn signals(version: u32, bit: u8) -> bool {
+    version & VERSION_BITS_TOP_MASK == VERSION_BITS_TOP_BITS && version & (1 << bit) != 0
+}
+
+/// Signals counted over one period
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PeriodSignals {
+    /// Blocks of the period counted so far
+    pub blocks: u32,
+
+    /// Blocks signalling each bit
+    pub bits: [u32; VERSION_BITS_COUNT],
+}
+
+impl PeriodSignals {
+    /// Count a block with this header version
+    pub fn record(&mut self, version: u32) {
+        self.blocks += 1;
+        for bit in 0..VERSION_BITS_COUNT {
+            if signals(version, bit as u8) {
+                self.bits[bit] += 1;
+            }
+        }
+    }
+
+    /// Stop counting a disconnected block with this header version
+    pub fn unrecord(&mut self, version: u32) {
+        self.blocks = self.blocks.saturating_sub(1);
+        for bit in 0..VERSION_BITS_COUNT {
+            if signals(version, bit as u8) {
+                self.bits[bit] = self.bits[bit].saturating_sub(1);
+            }
+        }
+    }
+
+    /// Blocks signalling `bit`
+    pub fn count(&self, bit: u8) -> u32 {
+        self.bits.get(bit as usize).copied().unwrap_or(0)
+    }
+}
+
+/// State of `deployment` for the block at `height`, with the first height
+/// of that state
+///
+/// `signals` holds the counts of every period, by first height.
+pub fn deployment_state(
+    deployment: &Deployment,
+    period: u64,
+    threshold: u32,
+    height: u64,
+    signals: &BTreeMap<u64, PeriodSignals>,
+) -> (DeploymentState, u64) {
+    let mut state = DeploymentState::Defined;
+    let mut since = 0;
+
+    // Nothing happens before the first period that may start signalling
+    let first = deployment.start_height.div_ceil(period).max(1);
+    for index in first..=height / period {
+        let start = index * period;
+        let next = match state {
+            DeploymentState::Defined => DeploymentState::Started,
+            DeploymentState::Started => {
+                let count = signals.get(&(start - period)).map_or(0, |s| s.count(deployment.bit));
+                if count >= threshold {
+                    DeploymentState::LockedIn
+                } else if start >= deployment.timeout_height {
+                    DeploymentState::Failed
+                } else {
+                    DeploymentState::Started
+                }
+            }
+            DeploymentState::LockedIn => DeploymentState::Active,
+            DeploymentState::Active | DeploymentState::Failed => break,
+        };
+        if next != state {
+            state = next;
+            since = start;
+        }
+    }
+
+    (state, since)
+}
+
+/// Status of a deployment, as `getdeploymentinfo` reports it
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeploymentInfo {
+    pub deployment: Deployment,
+
+    /// State for the next block
+    pub state: DeploymentState,
+
+    /// First height of that state
+    pub since: u64,
+
+    /// Blocks of the current period signalling the deployment
+    pub signalling: u32,
+
+    /// Blocks of the current period so far
+    pub elapsed: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DUMMY: Deployment = Deployment {
+        name: "testdummy",
+        bit: 28,
+        start_height: 8,
+        timeout_height: 24,
+    };
+
+    fn period(signalling: u32) -> PeriodSignals {
+        let mut signals = PeriodSignals::default();
+        for n in 0..4 {
+            signals.record(if n < signalling { VERSION_BITS_TOP_BITS | 1 << 28 } else { 1 });
+        }
+        signals
+    }
+
+    #[test]
+    fn test_signals() {
+        assert!(signals(VERSION_BITS_TOP_BITS | 1 << 3, 3));
+        assert!(!signals(VERSION_BITS_TOP_BITS | 1 << 3, 4));
+        // Legacy versions never signal
+        assert!(!signals(1 << 3, 3));
+        assert!(!signals(0x6000_0008, 3));
+
+        let mut signals = period(3);
+        assert_eq!((signals.blocks, signals.count(28)), (4, 3));
+        signals.unrecord(VERSION_BITS_TOP_BITS | 1 << 28);
+        assert_eq!((signals.blocks, signals.count(28)), (3, 2));
+    }
+
+    #[test]
+    fn test_state_transitions() {
+        let state = |height: u64, counts: &[u32]| {
+            let signals = counts.iter().enumerate().map(|(i, &n)| (i as u64 * 4, period(n))).collect();
+            deployment_state(&DUMMY, 4, 3, height, &signals)
+        };
+
+        // Signals before the start height are ignored
+        assert_eq!(state(7, &[4, 4]), (DeploymentState::Defined, 0));
+        assert_eq!(state(8, &[4, 4]), (DeploymentState::Started, 8));
+        assert_eq!(state(12, &[4, 4, 2]), (DeploymentState::Started, 8));
+        assert_eq!(state(16, &[4, 4, 2, 3]), (DeploymentState::LockedIn, 16));
+        assert_eq!(state(20, &[4, 4, 2, 3, 0]), (DeploymentState::Active, 20));
+        assert_eq!(state(100, &[4, 4, 2, 3, 0]), (DeploymentState::Active, 20));
+
+        // Never reaching the threshold fails at the timeout
+        assert_eq!(state(20, &[0, 0, 2, 2, 2]), (DeploymentState::Started, 8));
+        assert_eq!(state(24, &[0, 0, 2, 2, 2, 2]), (DeploymentState::Failed, 24));
+        // ... unless the last period before it reaches it
+        assert_eq!(state(24, &[0, 0, 2, 2, 2, 3]), (DeploymentState::LockedIn, 24));
+    }
+}
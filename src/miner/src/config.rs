@@ -1,19 +1,40 @@
 //! Miner configuration
 
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use aequitas_consensus::ThermalPolicy;
 
 /// Default number of CPU threads (half of available)
 pub fn default_cpu_threads() -> usize {
     (num_cpus::get() / 2).max(1)
 }
 
+/// When the miner moves on to the next payout address
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "policy", rename_all = "snake_case")]
+pub enum RotationPolicy {
+    /// A different address for every block found
+    #[default]
+    RoundRobin,
+    
+    /// Switch address after this many blocks found
+    AfterBlocks { blocks: u64 },
+}
+
 /// Miner configuration
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct MinerConfig {
     /// Wallet address to receive mining rewards
     pub address: String,
     
+    /// Extra payout addresses rotated with `address`
+    #[serde(default)]
+    pub payout_addresses: Vec<String>,
+    
+    /// How the payout address rotates
+    #[serde(default)]
+    pub rotation: RotationPolicy,
+    
     /// Node RPC endpoint
     #[serde(default = "default_node_url")]
     pub node_url: String,
@@ -30,10 +51,30 @@ pub struct MinerConfig {
     #[serde(default)]
     pub gpu_devices: Vec<u32>,
     
-    /// GPU intensity (1-100)
+    /// GPU intensity (1-100), used until a device is tuned
     #[serde(default = "default_gpu_intensity")]
     pub gpu_intensity: u32,
     
+    /// Benchmark each GPU on its first run and keep the fastest settings
+    #[serde(default = "default_gpu_auto_tune")]
+    pub gpu_auto_tune: bool,
+    
+    /// Temperature GPUs are throttled to stay under (°C)
+    #[serde(default = "default_gpu_target_temp")]
+    pub gpu_target_temp: f32,
+    
+    /// Power draw GPUs are throttled to stay under (W), none by default
+    #[serde(default)]
+    pub gpu_max_power: Option<f32>,
+    
+    /// Degrees a throttled GPU cools under the target before speeding up
+    #[serde(default = "default_gpu_temp_hysteresis")]
+    pub gpu_temp_hysteresis: f32,
+    
+    /// Directory for miner state, such as tuned GPU profiles
+    #[serde(default = "default_data_dir")]
+    pub data_dir: PathBuf,
+    
     /// Worker name for pool mining
     #[serde(default = "default_worker_name")]
     pub worker_name: String,
@@ -57,6 +98,14 @@ pub struct MinerConfig {
     /// Statistics update interval (seconds)
     #[serde(default = "default_stats_interval")]
     pub stats_interval: u64,
+    
+    /// Worker thread stack size in bytes (0 = platform default)
+    #[serde(default)]
+    pub worker_stack_size: usize,
+    
+    /// Maximum time to wait for workers to stop on shutdown (milliseconds)
+    #[serde(default = "default_shutdown_timeout_ms")]
+    pub shutdown_timeout_ms: u64,
 }
 
 fn default_node_url() -> String {
@@ -71,6 +120,26 @@ fn default_gpu_intensity() -> u32 {
     80
 }
 
+fn default_gpu_auto_tune() -> bool {
+    true
+}
+
+fn default_gpu_target_temp() -> f32 {
+    ThermalPolicy::default().target_temp_c
+}
+
+fn default_gpu_temp_hysteresis() -> f32 {
+    ThermalPolicy::default().hysteresis_c
+}
+
+/// Default miner data directory
+pub fn default_data_dir() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("aequitas")
+        .join("miner")
+}
+
 fn default_worker_name() -> String {
     std::env::var("COMPUTERNAME")
         .or_else(|_| std::env::var("HOSTNAME"))
@@ -85,21 +154,34 @@ fn default_stats_interval() -> u64 {
     10
 }
 
+fn default_shutdown_timeout_ms() -> u64 {
+    2000
+}
+
 impl Default for MinerConfig {
     fn default() -> Self {
         Self {
             address: String::new(),
+            payout_addresses: Vec::new(),
+            rotation: RotationPolicy::default(),
             node_url: default_node_url(),
             cpu_threads: default_cpu_threads(),
             gpu_enabled: default_gpu_enabled(),
             gpu_devices: Vec::new(),
             gpu_intensity: default_gpu_intensity(),
+            gpu_auto_tune: default_gpu_auto_tune(),
+            gpu_target_temp: default_gpu_target_temp(),
+            gpu_max_power: None,
+            gpu_temp_hysteresis: default_gpu_temp_hysteresis(),
+            data_dir: default_data_dir(),
             worker_name: default_worker_name(),
             stratum_enabled: false,
             stratum_url: None,
             stratum_password: None,
             log_level: default_log_level(),
             stats_interval: default_stats_interval(),
+            worker_stack_size: 0,
+            shutdown_timeout_ms: default_shutdown_timeout_ms(),
         }
     }
 }
@@ -129,6 +211,14 @@ impl MinerConfig {
             anyhow::bail!("Invalid wallet address format");
         }
         
+        if let Some(bad) = self.payout_addresses.iter().find(|a| !a.starts_with("aeq1")) {
+            anyhow::bail!("Invalid payout address format: {}", bad);
+        }
+        
+        if self.rotation == (RotationPolicy::AfterBlocks { blocks: 0 }) {
+            anyhow::bail!("Rotation must switch after at least one block");
+        }
+        
         if self.cpu_threads == 0 && !self.gpu_enabled {
             anyhow::bail!("At least CPU or GPU mining must be enabled");
         }
@@ -137,6 +227,10 @@ impl MinerConfig {
             anyhow::bail!("GPU intensity must be between 1 and 100");
         }
         
+        if self.gpu_temp_hysteresis < 0.0 || self.gpu_max_power.is_some_and(|watts| watts <= 0.0) {
+            anyhow::bail!("GPU hysteresis and power limit must be positive");
+        }
+        
         if self.stratum_enabled && self.stratum_url.is_none() {
             anyhow::bail!("Stratum URL is required when stratum is enabled");
         }
@@ -144,6 +238,37 @@ impl MinerConfig {
         Ok(())
     }
     
+    /// Temperature and power limits of the GPUs
+    pub fn thermal_policy(&self) -> ThermalPolicy {
+        ThermalPolicy {
+            target_temp_c: self.gpu_target_temp,
+            max_power_w: self.gpu_max_power,
+            hysteresis_c: self.gpu_temp_hysteresis,
+        }
+    }
+    
+    /// Every payout address, `address` first
+    pub fn payout_rotation(&self) -> Vec<&str> {
+        std::iter::once(self.address.as_str())
+            .chain(self.payout_addresses.iter().map(String::as_str))
+            .collect()
+    }
+    
+    /// Address to mine to once `blocks_found` blocks have been found
+    pub fn payout_address(&self, blocks_found: u64) -> &str {
+        let addresses = self.payout_rotation();
+        let slot = match self.rotation {
+            RotationPolicy::RoundRobin => blocks_found,
+            RotationPolicy::AfterBlocks { blocks } => blocks_found / blocks.max(1),
+        };
+        addresses[(slot % addresses.len() as u64) as usize]
+    }
+    
+    /// Body of a `/getblocktemplate` request paying the current payout address
+    pub fn template_request(&self, blocks_found: u64) -> serde_json::Value {
+        serde_json::json!({ "address": self.payout_address(blocks_found) })
+    }
+    
     /// Create a sample configuration file
     pub fn create_sample<P: AsRef<Path>>(path: P) -> anyhow::Result<()> {
         let sample = r#"# Aequitas Miner Configuration
@@ -153,6 +278,11 @@ impl MinerConfig {
 # Generate one with: aequitas-wallet new
 address = "aeq1YourAddressHere"
 
+# Extra payout addresses, rotated with `address` so rewards don't all land
+# on one address. Rotating gives up solidarity registry eligibility, which
+# counts blocks per address.
+# payout_addresses = ["aeq1Second", "aeq1Third"]
+
 # Node RPC endpoint (for solo mining)
 node_url = "http://127.0.0.1:23421"
 
@@ -171,6 +301,21 @@ gpu_devices = []
 # Recommended: 70-80 for RTX 3060
 gpu_intensity = 75
 
+# Benchmark each GPU on its first run and keep the fastest work-group size
+# and intensity in <data_dir>/gpu-profiles.json; gpu_intensity then only
+# applies to untuned GPUs. Delete the file to tune again.
+gpu_auto_tune = true
+
+# Thermal throttle: a GPU over gpu_target_temp (°C) or gpu_max_power (W)
+# mines at a lower intensity until it has cooled gpu_temp_hysteresis
+# degrees under the target. Laptops: try 75 and the card's power limit.
+gpu_target_temp = 80
+# gpu_max_power = 115
+gpu_temp_hysteresis = 5
+
+# Miner state directory (default: the platform data dir, aequitas/miner)
+# data_dir = "/var/lib/aequitas-miner"
+
 # Worker name (for pool statistics)
 worker_name = "my-rig"
 
@@ -184,6 +329,20 @@ log_level = "info"
 
 # Statistics update interval (seconds)
 stats_interval = 10
+
+# Worker thread stack size in bytes (0 = platform default)
+worker_stack_size = 0
+
+# Maximum time to wait for workers on shutdown (milliseconds)
+shutdown_timeout_ms = 2000
+
+# Payout rotation policy: a new address for every block found...
+# [rotation]
+# policy = "round_robin"
+# ...or switch after N blocks found
+# [rotation]
+# policy = "after_blocks"
+# blocks = 10
 "#;
         
         std::fs::write(path, sample)?;
@@ -209,5 +368,40 @@ mod tests {
         
         config.address = "aeq1TestAddress".to_string();
         assert!(config.validate().is_ok());
+        
+        config.payout_addresses = vec!["bogus".to_string()];
+        assert!(config.validate().is_err());
+    }
+    
+    #[test]
+    fn test_payout_rotation() {
+        let mut config = MinerConfig {
+            address: "aeq1A".to_string(),
+            payout_addresses: vec!["aeq1B".to_string(), "aeq1C".to_string()],
+            ..MinerConfig::default()
+        };
+        let picks: Vec<&str> = (0..4).map(|n| config.payout_address(n)).collect();
+        assert_eq!(picks, ["aeq1A", "aeq1B", "aeq1C", "aeq1A"]);
+        
+        config.rotation = RotationPolicy::AfterBlocks { blocks: 2 };
+        let picks: Vec<&str> = (0..6).map(|n| config.payout_address(n)).collect();
+        assert_eq!(picks, ["aeq1A", "aeq1A", "aeq1B", "aeq1B", "aeq1C", "aeq1C"]);
+        assert_eq!(config.template_request(2)["address"], "aeq1B");
+        
+        config.payout_addresses.clear();
+        assert_eq!(config.payout_address(7), "aeq1A");
+    }
+    
+    #[test]
+    fn test_rotation_from_toml() {
+        let config: MinerConfig = toml::from_str(r#"
+            address = "aeq1A"
+            payout_addresses = ["aeq1B"]
+            [rotation]
+            policy = "after_blocks"
+            blocks = 5
+        "#).unwrap();
+        assert_eq!(config.rotation, RotationPolicy::AfterBlocks { blocks: 5 });
+        assert!(config.validate().is_ok());
     }
 }
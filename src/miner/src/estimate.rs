@@ -0,0 +1,155 @@
+//! Mining profitability estimates
+//!
+//! Expected earnings follow from the share of network work a miner
+//! contributes: a block at difficulty `d` takes about `d` hashes, so a miner
+//! hashing at `h` H/s finds `h * 86400 / d` blocks per day on average.
+
+use aequitas_consensus::pow::CpuMiner;
+use aequitas_core::{Blockchain, COIN, HALVING_INTERVAL, TARGET_BLOCK_TIME};
+
+/// Seconds per day
+const SECS_PER_DAY: f64 = 86_400.0;
+
+/// Electricity inputs for break-even analysis
+#[derive(Clone, Copy, Debug)]
+pub struct PowerCost {
+    /// Power draw of the rig in watts
+    pub watts: f64,
+
+    /// Electricity price per kWh (any currency)
+    pub price_per_kwh: f64,
+}
+
+impl PowerCost {
+    /// Electricity cost per day
+    pub fn cost_per_day(&self) -> f64 {
+        self.watts * 24.0 / 1000.0 * self.price_per_kwh
+    }
+}
+
+/// Expected mining returns at the current network state
+#[derive(Clone, Debug)]
+pub struct Estimate {
+    /// Miner hashrate (H/s)
+    pub hashrate: f64,
+
+    /// Current network difficulty
+    pub difficulty: u64,
+
+    /// Estimated network hashrate (H/s)
+    pub network_hashrate: f64,
+
+    /// Height of the next block
+    pub height: u64,
+
+    /// Miner share of the block reward, in base units
+    pub miner_reward: u64,
+
+    /// Expected blocks found per day
+    pub blocks_per_day: f64,
+
+    /// Expected AEQ earned per day
+    pub aeq_per_day: f64,
+
+    /// Blocks until the next halving
+    pub blocks_to_halving: u64,
+
+    /// Expected AEQ per day after the next halving
+    pub aeq_per_day_after_halving: f64,
+
+    /// Electricity cost per day, when a power cost was given
+    pub cost_per_day: Option<f64>,
+
+    /// AEQ price at which mining covers electricity
+    pub break_even_price: Option<f64>,
+}
+
+impl Estimate {
+    /// Estimate returns for a hashrate at a given height and difficulty
+    pub fn new(hashrate: f64, difficulty: u64, height: u64, power: Option<PowerCost>) -> Self {
+        let difficulty = difficulty.max(1);
+        let blocks_per_day = hashrate * SECS_PER_DAY / difficulty as f64;
+
+        let (miner_reward, _, _) = Blockchain::reward_split_for_height(height);
+        let blocks_to_halving = HALVING_INTERVAL - height % HALVING_INTERVAL;
+        let (next_reward, _, _) = Blockchain::reward_split_for_height(height + blocks_to_halving);
+
+        let aeq_per_day = blocks_per_day * miner_reward as f64 / COIN as f64;
+        let cost_per_day = power.map(|p| p.cost_per_day());
+        let break_even_price = cost_per_day
+            .filter(|_| aeq_per_day > 0.0)
+            .map(|cost| cost / aeq_per_day);
+
+        Self {
+            hashrate,
+            difficulty,
+            network_hashrate: difficulty as f64 / TARGET_BLOCK_TIME as f64,
+            height,
+            miner_reward,
+            blocks_per_day,
+            aeq_per_day,
+            blocks_to_halving,
+            aeq_per_day_after_halving: blocks_per_day * next_reward as f64 / COIN as f64,
+            cost_per_day,
+            break_even_price,
+        }
+    }
+
+    /// Share of the network hashrate
+    pub fn network_share(&self) -> f64 {
+        self.hashrate / (self.network_hashrate + self.hashrate)
+    }
+
+    /// Days until the next halving at the target block time
+    pub fn days_to_halving(&self) -> f64 {
+        self.blocks_to_halving as f64 * TARGET_BLOCK_TIME as f64 / SECS_PER_DAY
+    }
+
+    /// Average days between blocks found
+    pub fn days_per_block(&self) -> Option<f64> {
+        (self.blocks_per_day > 0.0).then(|| 1.0 / self.blocks_per_day)
+    }
+
+    /// Profit per day at an AEQ price, when a power cost was given
+    pub fn profit_per_day(&self, aeq_price: f64) -> Option<f64> {
+        self.cost_per_day.map(|cost| self.aeq_per_day * aeq_price - cost)
+    }
+}
+
+/// Estimate the CPU hashrate of `threads` threads from a single-thread benchmark
+pub fn benchmark_hashrate(seconds: u64, threads: usize) -> f64 {
+    CpuMiner::new().benchmark(seconds) * threads.max(1) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_blocks_per_day() {
+        // Hashing at the whole network rate finds one block per target time
+        let difficulty = 1_000_000;
+        let hashrate = difficulty as f64 / TARGET_BLOCK_TIME as f64;
+        let estimate = Estimate::new(hashrate, difficulty, 1, None);
+
+        assert!((estimate.blocks_per_day - SECS_PER_DAY / TARGET_BLOCK_TIME as f64).abs() < 1e-6);
+        assert!((estimate.network_share() - 0.5).abs() < 1e-9);
+        assert_eq!(estimate.miner_reward, Blockchain::reward_split_for_height(1).0);
+        assert!(estimate.break_even_price.is_none());
+    }
+
+    #[test]
+    fn test_estimate_halving_and_break_even() {
+        let height = HALVING_INTERVAL - 10;
+        let power = PowerCost { watts: 250.0, price_per_kwh: 0.2 };
+        let estimate = Estimate::new(1_000.0, 1_000_000, height, Some(power));
+
+        assert_eq!(estimate.blocks_to_halving, 10);
+        assert!((estimate.aeq_per_day_after_halving * 2.0 - estimate.aeq_per_day).abs() < 1e-6);
+
+        let cost = estimate.cost_per_day.unwrap();
+        assert!((cost - 1.2).abs() < 1e-9);
+        let price = estimate.break_even_price.unwrap();
+        assert!(estimate.profit_per_day(price).unwrap().abs() < 1e-9);
+    }
+}
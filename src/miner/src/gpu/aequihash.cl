@@ -0,0 +1,244 @@
+// AequiHash nonce search
+//
+// One work item hashes one nonce with `AequiHash::hash_full` of
+// aequitas-consensus, reading the epoch's DAG from device memory. It equals
+// `hash_light`, which blocks are verified with; any change there must be
+// mirrored here.
+
+#define MIX_WORDS 32
+#define ITEM_WORDS 16
+#define MIX_ROUNDS 64
+#define MAX_FOUND 16
+#define ROUND_BYTES 3
+
+__constant ulong KECCAK_RC[24] = {
+    0x0000000000000001UL, 0x0000000000008082UL, 0x800000000000808aUL,
+    0x8000000080008000UL, 0x000000000000808bUL, 0x0000000080000001UL,
+    0x8000000080008081UL, 0x8000000000008009UL, 0x000000000000008aUL,
+    0x0000000000000088UL, 0x0000000080008009UL, 0x000000008000000aUL,
+    0x000000008000808bUL, 0x800000000000008bUL, 0x8000000000008089UL,
+    0x8000000000008003UL, 0x8000000000008002UL, 0x8000000000000080UL,
+    0x000000000000800aUL, 0x800000008000000aUL, 0x8000000080008081UL,
+    0x8000000000008080UL, 0x0000000080000001UL, 0x8000000080008008UL,
+};
+
+__constant uint KECCAK_ROTC[24] = {
+    1, 3, 6, 10, 15, 21, 28, 36, 45, 55, 2, 14,
+    27, 41, 56, 8, 25, 43, 62, 18, 39, 61, 20, 44,
+};
+
+__constant uint KECCAK_PILN[24] = {
+    10, 7, 11, 17, 18, 3, 5, 16, 8, 21, 24, 4,
+    15, 23, 19, 13, 12, 2, 20, 14, 22, 9, 6, 1,
+};
+
+__constant uint BLAKE3_IV[8] = {
+    0x6A09E667, 0xBB67AE85, 0x3C6EF372, 0xA54FF53A,
+    0x510E527F, 0x9B05688C, 0x1F83D9AB, 0x5BE0CD19,
+};
+
+__constant uchar BLAKE3_PERM[16] = {2, 6, 3, 10, 7, 0, 4, 13, 1, 11, 12, 5, 9, 14, 15, 8};
+
+#define BLAKE3_CHUNK_START 1u
+#define BLAKE3_CHUNK_END 2u
+#define BLAKE3_ROOT 8u
+
+static void keccak_f1600(ulong st[25]) {
+    ulong bc[5];
+    for (int round = 0; round < 24; round++) {
+        for (int i = 0; i < 5; i++) {
+            bc[i] = st[i] ^ st[i + 5] ^ st[i + 10] ^ st[i + 15] ^ st[i + 20];
+        }
+        for (int i = 0; i < 5; i++) {
+            ulong t = bc[(i + 4) % 5] ^ rotate(bc[(i + 1) % 5], 1UL);
+            for (int j = 0; j < 25; j += 5) {
+                st[j + i] ^= t;
+            }
+        }
+
+        ulong t = st[1];
+        for (int i = 0; i < 24; i++) {
+            uint j = KECCAK_PILN[i];
+            bc[0] = st[j];
+            st[j] = rotate(t, (ulong)KECCAK_ROTC[i]);
+            t = bc[0];
+        }
+
+        for (int j = 0; j < 25; j += 5) {
+            for (int i = 0; i < 5; i++) {
+                bc[i] = st[j + i];
+            }
+            for (int i = 0; i < 5; i++) {
+                st[j + i] ^= (~bc[(i + 1) % 5]) & bc[(i + 2) % 5];
+            }
+        }
+
+        st[0] ^= KECCAK_RC[round];
+    }
+}
+
+#define G(a, b, c, d, x, y)                  \
+    do {                                     \
+        s[a] = s[a] + s[b] + (x);            \
+        s[d] = rotate(s[d] ^ s[a], 16u);     \
+        s[c] = s[c] + s[d];                  \
+        s[b] = rotate(s[b] ^ s[c], 20u);     \
+        s[a] = s[a] + s[b] + (y);            \
+        s[d] = rotate(s[d] ^ s[a], 24u);     \
+        s[c] = s[c] + s[d];                  \
+        s[b] = rotate(s[b] ^ s[c], 25u);     \
+    } while (0)
+
+// One BLAKE3 compression of `m` into the chaining value `cv` (chunk 0)
+static void blake3_compress(uint cv[8], const uint block[16], uint len, uint flags) {
+    uint s[16];
+    uint m[16];
+    for (int i = 0; i < 8; i++) {
+        s[i] = cv[i];
+    }
+    s[8] = BLAKE3_IV[0];
+    s[9] = BLAKE3_IV[1];
+    s[10] = BLAKE3_IV[2];
+    s[11] = BLAKE3_IV[3];
+    s[12] = 0;
+    s[13] = 0;
+    s[14] = len;
+    s[15] = flags;
+    for (int i = 0; i < 16; i++) {
+        m[i] = block[i];
+    }
+
+    for (int round = 0; round < 7; round++) {
+        // rotate() turns left: 16, 12, 8 and 7 to the right
+        G(0, 4, 8, 12, m[0], m[1]);
+        G(1, 5, 9, 13, m[2], m[3]);
+        G(2, 6, 10, 14, m[4], m[5]);
+        G(3, 7, 11, 15, m[6], m[7]);
+        G(0, 5, 10, 15, m[8], m[9]);
+        G(1, 6, 11, 12, m[10], m[11]);
+        G(2, 7, 8, 13, m[12], m[13]);
+        G(3, 4, 9, 14, m[14], m[15]);
+
+        uint permuted[16];
+        for (int i = 0; i < 16; i++) {
+            permuted[i] = m[BLAKE3_PERM[i]];
+        }
+        for (int i = 0; i < 16; i++) {
+            m[i] = permuted[i];
+        }
+    }
+
+    for (int i = 0; i < 8; i++) {
+        cv[i] = s[i] ^ s[i + 8];
+    }
+}
+
+// Operation codes follow `MathOp::code`
+static uint math_op(uchar op, uint a, uint b) {
+    switch (op) {
+        case 0: return a + b;
+        case 1: return a * b;
+        case 2: return a - b;
+        case 3: return a ^ b;
+        case 4: return rotate(a, b % 32);
+        case 5: return rotate(a, (32 - b % 32) % 32);
+        case 6: return a & b;
+        default: return a | b;
+    }
+}
+
+__kernel void search(
+    __global const uint* dag,
+    const ulong dag_items,
+    __constant uint* header,
+    __constant uchar* target,
+    __constant uchar* program,
+    const ulong start_nonce,
+    __global uint* found_count,
+    __global ulong* found)
+{
+    ulong nonce = start_nonce + get_global_id(0);
+
+    // Seed: Keccak-256 of header || nonce (40 bytes, one block)
+    ulong st[25];
+    for (int i = 0; i < 25; i++) {
+        st[i] = 0;
+    }
+    for (int i = 0; i < 4; i++) {
+        st[i] = (ulong)header[2 * i] | ((ulong)header[2 * i + 1] << 32);
+    }
+    st[4] = nonce;
+    st[5] ^= 0x01UL;
+    st[16] ^= 0x8000000000000000UL;
+    keccak_f1600(st);
+
+    uint mix[MIX_WORDS];
+    for (int i = 0; i < 8; i++) {
+        uint word = (uint)(st[i / 2] >> (32 * (i % 2)));
+        mix[i] = word;
+        mix[i + 8] = word;
+        mix[i + 16] = word * 0x85ebca6bu;
+        mix[i + 24] = word * 0xc2b2ae35u;
+    }
+
+    // Memory-hard mixing: two consecutive DAG items per round, read as
+    // the epoch program's round says (`Round` in aequitas-consensus)
+    for (uint round = 0; round < MIX_ROUNDS; round++) {
+        uchar op = program[ROUND_BYTES * round];
+        uint address = program[ROUND_BYTES * round + 1];
+        uint offset = program[ROUND_BYTES * round + 2];
+        ulong item = (ulong)mix[address] % dag_items;
+        __global const uint* low = dag + item * ITEM_WORDS;
+        __global const uint* high = dag + ((item + 1) % dag_items) * ITEM_WORDS;
+        for (uint j = 0; j < MIX_WORDS; j++) {
+            uint w = (j + offset) % MIX_WORDS;
+            uint data = w < ITEM_WORDS ? low[w] : high[w - ITEM_WORDS];
+            mix[j] = math_op(op, mix[j], data);
+        }
+        for (uint j = 0; j < MIX_WORDS; j++) {
+            mix[j] = mix[j] * 0x01000193u ^ mix[(j + 1) % MIX_WORDS];
+        }
+    }
+
+    // Final: BLAKE3 of header || nonce || mix hash (72 bytes, one chunk),
+    // the mix hash folding every four mix words with FNV
+    uint words[32];
+    for (int i = 0; i < 8; i++) {
+        words[i] = header[i];
+    }
+    words[8] = (uint)nonce;
+    words[9] = (uint)(nonce >> 32);
+    for (int i = 0; i < 8; i++) {
+        uint folded = mix[4 * i];
+        for (int k = 1; k < 4; k++) {
+            folded = folded * 0x01000193u ^ mix[4 * i + k];
+        }
+        words[10 + i] = folded;
+    }
+    for (int i = 18; i < 32; i++) {
+        words[i] = 0;
+    }
+
+    uint cv[8];
+    for (int i = 0; i < 8; i++) {
+        cv[i] = BLAKE3_IV[i];
+    }
+    blake3_compress(cv, words, 64, BLAKE3_CHUNK_START);
+    blake3_compress(cv, words + 16, 8, BLAKE3_CHUNK_END | BLAKE3_ROOT);
+
+    // hash <= target, comparing bytes in order
+    for (int i = 0; i < 32; i++) {
+        uchar h = (uchar)(cv[i / 4] >> (8 * (i % 4)));
+        if (h < target[i]) {
+            break;
+        }
+        if (h > target[i]) {
+            return;
+        }
+    }
+
+    uint slot = atomic_inc(found_count);
+    if (slot < MAX_FOUND) {
+        found[slot] = nonce;
+    }
+}
@@ -0,0 +1,302 @@
+// AequiHash nonce search, CUDA version of aequihash.cl
+//
+// Tuned for Ampere and Ada (RTX 30/40): 32-bit rotations use the funnel
+// shifter, DAG reads go through the read-only data cache, and blocks of
+// THREADS_PER_BLOCK threads keep the per-thread state (mix, Keccak and
+// BLAKE3 words) in registers without spilling. Compiled at run time by
+// NVRTC for the device's compute capability, with THREADS_PER_BLOCK set
+// to the block size the device was tuned for.
+
+#define MIX_WORDS 32
+#define ITEM_WORDS 16
+#define MIX_ROUNDS 64
+#define MAX_FOUND 16
+#ifndef THREADS_PER_BLOCK
+#define THREADS_PER_BLOCK 128
+#endif
+#define ROUND_BYTES 3
+
+typedef unsigned int uint;
+typedef unsigned char uchar;
+typedef unsigned long long ulong;
+
+__constant__ ulong KECCAK_RC[24] = {
+    0x0000000000000001ULL, 0x0000000000008082ULL, 0x800000000000808aULL,
+    0x8000000080008000ULL, 0x000000000000808bULL, 0x0000000080000001ULL,
+    0x8000000080008081ULL, 0x8000000000008009ULL, 0x000000000000008aULL,
+    0x0000000000000088ULL, 0x0000000080008009ULL, 0x000000008000000aULL,
+    0x000000008000808bULL, 0x800000000000008bULL, 0x8000000000008089ULL,
+    0x8000000000008003ULL, 0x8000000000008002ULL, 0x8000000000000080ULL,
+    0x000000000000800aULL, 0x800000008000000aULL, 0x8000000080008081ULL,
+    0x8000000000008080ULL, 0x0000000080000001ULL, 0x8000000080008008ULL,
+};
+
+__constant__ uint KECCAK_ROTC[24] = {
+    1, 3, 6, 10, 15, 21, 28, 36, 45, 55, 2, 14,
+    27, 41, 56, 8, 25, 43, 62, 18, 39, 61, 20, 44,
+};
+
+__constant__ uint KECCAK_PILN[24] = {
+    10, 7, 11, 17, 18, 3, 5, 16, 8, 21, 24, 4,
+    15, 23, 19, 13, 12, 2, 20, 14, 22, 9, 6, 1,
+};
+
+__constant__ uint BLAKE3_IV[8] = {
+    0x6A09E667, 0xBB67AE85, 0x3C6EF372, 0xA54FF53A,
+    0x510E527F, 0x9B05688C, 0x1F83D9AB, 0x5BE0CD19,
+};
+
+__constant__ uchar BLAKE3_PERM[16] = {2, 6, 3, 10, 7, 0, 4, 13, 1, 11, 12, 5, 9, 14, 15, 8};
+
+#define BLAKE3_CHUNK_START 1u
+#define BLAKE3_CHUNK_END 2u
+#define BLAKE3_ROOT 8u
+
+__device__ __forceinline__ uint rotl32(uint x, uint n) {
+    return __funnelshift_l(x, x, n);
+}
+
+__device__ __forceinline__ uint rotr32(uint x, uint n) {
+    return __funnelshift_r(x, x, n);
+}
+
+__device__ __forceinline__ ulong rotl64(ulong x, uint n) {
+    return n == 0 ? x : (x << n) | (x >> (64 - n));
+}
+
+__device__ void keccak_f1600(ulong st[25]) {
+    ulong bc[5];
+    for (int round = 0; round < 24; round++) {
+        #pragma unroll
+        for (int i = 0; i < 5; i++) {
+            bc[i] = st[i] ^ st[i + 5] ^ st[i + 10] ^ st[i + 15] ^ st[i + 20];
+        }
+        #pragma unroll
+        for (int i = 0; i < 5; i++) {
+            ulong t = bc[(i + 4) % 5] ^ rotl64(bc[(i + 1) % 5], 1);
+            #pragma unroll
+            for (int j = 0; j < 25; j += 5) {
+                st[j + i] ^= t;
+            }
+        }
+
+        ulong t = st[1];
+        #pragma unroll
+        for (int i = 0; i < 24; i++) {
+            uint j = KECCAK_PILN[i];
+            bc[0] = st[j];
+            st[j] = rotl64(t, KECCAK_ROTC[i]);
+            t = bc[0];
+        }
+
+        #pragma unroll
+        for (int j = 0; j < 25; j += 5) {
+            #pragma unroll
+            for (int i = 0; i < 5; i++) {
+                bc[i] = st[j + i];
+            }
+            #pragma unroll
+            for (int i = 0; i < 5; i++) {
+                st[j + i] ^= (~bc[(i + 1) % 5]) & bc[(i + 2) % 5];
+            }
+        }
+
+        st[0] ^= KECCAK_RC[round];
+    }
+}
+
+#define G(a, b, c, d, x, y)                  \
+    do {                                     \
+        s[a] = s[a] + s[b] + (x);            \
+        s[d] = rotr32(s[d] ^ s[a], 16);      \
+        s[c] = s[c] + s[d];                  \
+        s[b] = rotr32(s[b] ^ s[c], 12);      \
+        s[a] = s[a] + s[b] + (y);            \
+        s[d] = rotr32(s[d] ^ s[a], 8);       \
+        s[c] = s[c] + s[d];                  \
+        s[b] = rotr32(s[b] ^ s[c], 7);       \
+    } while (0)
+
+// One BLAKE3 compression of `block` into the chaining value `cv` (chunk 0)
+__device__ void blake3_compress(uint cv[8], const uint block[16], uint len, uint flags) {
+    uint s[16];
+    uint m[16];
+    #pragma unroll
+    for (int i = 0; i < 8; i++) {
+        s[i] = cv[i];
+    }
+    s[8] = BLAKE3_IV[0];
+    s[9] = BLAKE3_IV[1];
+    s[10] = BLAKE3_IV[2];
+    s[11] = BLAKE3_IV[3];
+    s[12] = 0;
+    s[13] = 0;
+    s[14] = len;
+    s[15] = flags;
+    #pragma unroll
+    for (int i = 0; i < 16; i++) {
+        m[i] = block[i];
+    }
+
+    #pragma unroll
+    for (int round = 0; round < 7; round++) {
+        G(0, 4, 8, 12, m[0], m[1]);
+        G(1, 5, 9, 13, m[2], m[3]);
+        G(2, 6, 10, 14, m[4], m[5]);
+        G(3, 7, 11, 15, m[6], m[7]);
+        G(0, 5, 10, 15, m[8], m[9]);
+        G(1, 6, 11, 12, m[10], m[11]);
+        G(2, 7, 8, 13, m[12], m[13]);
+        G(3, 4, 9, 14, m[14], m[15]);
+
+        uint permuted[16];
+        #pragma unroll
+        for (int i = 0; i < 16; i++) {
+            permuted[i] = m[BLAKE3_PERM[i]];
+        }
+        #pragma unroll
+        for (int i = 0; i < 16; i++) {
+            m[i] = permuted[i];
+        }
+    }
+
+    #pragma unroll
+    for (int i = 0; i < 8; i++) {
+        cv[i] = s[i] ^ s[i + 8];
+    }
+}
+
+// Operation codes follow `MathOp::code`
+__device__ __forceinline__ uint math_op(uchar op, uint a, uint b) {
+    switch (op) {
+        case 0: return a + b;
+        case 1: return a * b;
+        case 2: return a - b;
+        case 3: return a ^ b;
+        case 4: return rotl32(a, b % 32);
+        case 5: return rotr32(a, b % 32);
+        case 6: return a & b;
+        default: return a | b;
+    }
+}
+
+extern "C" __global__ void __launch_bounds__(THREADS_PER_BLOCK)
+search(
+    const uint* __restrict__ dag,
+    const ulong dag_items,
+    const uint* __restrict__ header,
+    const uchar* __restrict__ target,
+    const uchar* __restrict__ program,
+    const ulong start_nonce,
+    const ulong count,
+    uint* found_count,
+    ulong* found)
+{
+    ulong id = (ulong)blockIdx.x * blockDim.x + threadIdx.x;
+    if (id >= count) {
+        return;
+    }
+    ulong nonce = start_nonce + id;
+
+    // Seed: Keccak-256 of header || nonce (40 bytes, one block)
+    ulong st[25];
+    #pragma unroll
+    for (int i = 0; i < 25; i++) {
+        st[i] = 0;
+    }
+    #pragma unroll
+    for (int i = 0; i < 4; i++) {
+        st[i] = (ulong)__ldg(&header[2 * i]) | ((ulong)__ldg(&header[2 * i + 1]) << 32);
+    }
+    st[4] = nonce;
+    st[5] ^= 0x01ULL;
+    st[16] ^= 0x8000000000000000ULL;
+    keccak_f1600(st);
+
+    uint mix[MIX_WORDS];
+    #pragma unroll
+    for (int i = 0; i < 8; i++) {
+        uint word = (uint)(st[i / 2] >> (32 * (i % 2)));
+        mix[i] = word;
+        mix[i + 8] = word;
+        mix[i + 16] = word * 0x85ebca6bu;
+        mix[i + 24] = word * 0xc2b2ae35u;
+    }
+
+    // Memory-hard mixing: two consecutive DAG items per round, read as
+    // the epoch program's round says (`Round` in aequitas-consensus)
+    for (uint round = 0; round < MIX_ROUNDS; round++) {
+        uchar op = __ldg(&program[ROUND_BYTES * round]);
+        uint address = __ldg(&program[ROUND_BYTES * round + 1]);
+        uint offset = __ldg(&program[ROUND_BYTES * round + 2]);
+
+        // The address is only known at run time; select the word with
+        // static indices so the mix stays in registers
+        uint selector = 0;
+        #pragma unroll
+        for (uint j = 0; j < MIX_WORDS; j++) {
+            selector = j == address ? mix[j] : selector;
+        }
+        ulong item = (ulong)selector % dag_items;
+        const uint* low = dag + item * ITEM_WORDS;
+        const uint* high = dag + ((item + 1) % dag_items) * ITEM_WORDS;
+        #pragma unroll
+        for (uint j = 0; j < MIX_WORDS; j++) {
+            uint w = (j + offset) % MIX_WORDS;
+            uint data = w < ITEM_WORDS ? __ldg(&low[w]) : __ldg(&high[w - ITEM_WORDS]);
+            mix[j] = math_op(op, mix[j], data);
+        }
+        #pragma unroll
+        for (uint j = 0; j < MIX_WORDS; j++) {
+            mix[j] = mix[j] * 0x01000193u ^ mix[(j + 1) % MIX_WORDS];
+        }
+    }
+
+    // Final: BLAKE3 of header || nonce || mix hash (72 bytes, one chunk),
+    // the mix hash folding every four mix words with FNV
+    uint words[32];
+    #pragma unroll
+    for (int i = 0; i < 8; i++) {
+        words[i] = __ldg(&header[i]);
+    }
+    words[8] = (uint)nonce;
+    words[9] = (uint)(nonce >> 32);
+    #pragma unroll
+    for (int i = 0; i < 8; i++) {
+        uint folded = mix[4 * i];
+        #pragma unroll
+        for (int k = 1; k < 4; k++) {
+            folded = folded * 0x01000193u ^ mix[4 * i + k];
+        }
+        words[10 + i] = folded;
+    }
+    #pragma unroll
+    for (int i = 18; i < 32; i++) {
+        words[i] = 0;
+    }
+
+    uint cv[8];
+    #pragma unroll
+    for (int i = 0; i < 8; i++) {
+        cv[i] = BLAKE3_IV[i];
+    }
+    blake3_compress(cv, words, 64, BLAKE3_CHUNK_START);
+    blake3_compress(cv, words + 16, 8, BLAKE3_CHUNK_END | BLAKE3_ROOT);
+
+    // hash <= target, comparing bytes in order
+    for (int i = 0; i < 32; i++) {
+        uchar h = (uchar)(cv[i / 4] >> (8 * (i % 4)));
+        uchar t = __ldg(&target[i]);
+        if (h < t) {
+            break;
+        }
+        if (h > t) {
+            return;
+        }
+    }
+
+    uint slot = atomicAdd(found_count, 1u);
+    if (slot < MAX_FOUND) {
+        found[slot] = nonce;
+    }
+}
@@ -0,0 +1,224 @@
+//! CUDA backend
+
+use std::sync::Arc;
+use anyhow::Context as _;
+use cudarc::driver::sys::CUdevice_attribute;
+use cudarc::driver::{CudaDevice, CudaFunction, CudaSlice, LaunchAsync, LaunchConfig};
+use cudarc::nvrtc::{compile_ptx_with_opts, CompileOptions};
+use nvml_wrapper::enum_wrappers::device::TemperatureSensor;
+use nvml_wrapper::Nvml;
+use aequitas_consensus::aequihash::{MIX_ROUNDS, ROUND_BYTES};
+use aequitas_consensus::dag::DAG_ITEM_WORDS;
+use aequitas_consensus::{GpuBackend, ThermalReading};
+use super::{GpuDevice, LaunchSettings, NonceSearch, SearchParams, MAX_FOUND};
+
+/// Kernel source, compiled by NVRTC for each device and block size
+const KERNEL_SOURCE: &str = include_str!("aequihash.cu");
+
+/// Module the kernel compiled for `threads_per_block` is loaded as
+fn module(threads_per_block: u32) -> String {
+    format!("aequihash_{}", threads_per_block)
+}
+
+/// Every CUDA device
+pub fn devices() -> Vec<GpuDevice> {
+    let count = match CudaDevice::count() {
+        Ok(count) => count,
+        Err(e) => {
+            log::debug!("No CUDA driver: {}", e);
+            return Vec::new();
+        }
+    };
+
+    (0..count.max(0) as usize)
+        .filter_map(|ordinal| match CudaDevice::new(ordinal) {
+            Ok(device) => Some(GpuDevice {
+                index: ordinal as u32,
+                ordinal: ordinal as u32,
+                backend: GpuBackend::Cuda,
+                name: device.name().unwrap_or_else(|_| "Unknown CUDA device".into()),
+                vendor: "NVIDIA".into(),
+                memory_bytes: cudarc::driver::result::mem_get_info().map_or(0, |(_, total)| total as u64),
+            }),
+            Err(e) => {
+                log::debug!("Skipping CUDA device {}: {}", ordinal, e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// Virtual architecture to compile for: the device's own, so NVRTC can
+/// schedule for Ampere (8.6) and Ada (8.9) rather than a generic target
+fn arch(device: &CudaDevice) -> anyhow::Result<&'static str> {
+    let major = device.attribute(CUdevice_attribute::CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MAJOR)?;
+    let minor = device.attribute(CUdevice_attribute::CU_DEVICE_ATTRIBUTE_COMPUTE_CAPABILITY_MINOR)?;
+    Ok(match (major, minor) {
+        (9, _) => "compute_90",
+        (8, 9) => "compute_89",
+        (8, 6..=8) => "compute_86",
+        (8, _) => "compute_80",
+        (7, 5..=9) => "compute_75",
+        (7, _) => "compute_70",
+        _ => "compute_61",
+    })
+}
+
+/// Nonce search on one CUDA device
+pub struct CudaSearch {
+    device: Arc<CudaDevice>,
+    /// Name of the device, for errors
+    name: String,
+    /// Threads per block, as the loaded kernel's launch bounds declare
+    threads_per_block: u32,
+    batch_size: u64,
+    header: CudaSlice<u32>,
+    target: CudaSlice<u8>,
+    program: CudaSlice<u8>,
+    found_count: CudaSlice<u32>,
+    found: CudaSlice<u64>,
+    /// Seed of the loaded DAG, and the DAG
+    dag: Option<([u8; 32], CudaSlice<u32>)>,
+    /// NVML, for the sensors; `None` when the driver ships without it
+    nvml: Option<Nvml>,
+    /// PCI address NVML finds the device by; CUDA and NVML order devices
+    /// differently
+    pci_bus_id: String,
+}
+
+impl CudaSearch {
+    /// Compile the kernel for `device`
+    pub fn new(device: &GpuDevice, settings: LaunchSettings) -> anyhow::Result<Self> {
+        let cuda = CudaDevice::new(device.ordinal as usize)
+            .with_context(|| format!("CUDA device {} not found", device.ordinal))?;
+        let pci_bus_id = format!(
+            "{:04x}:{:02x}:{:02x}.0",
+            cuda.attribute(CUdevice_attribute::CU_DEVICE_ATTRIBUTE_PCI_DOMAIN_ID)?,
+            cuda.attribute(CUdevice_attribute::CU_DEVICE_ATTRIBUTE_PCI_BUS_ID)?,
+            cuda.attribute(CUdevice_attribute::CU_DEVICE_ATTRIBUTE_PCI_DEVICE_ID)?,
+        );
+        let nvml = Nvml::init()
+            .map_err(|e| log::debug!("No NVML for {}, mining without sensors: {}", device.name, e))
+            .ok();
+
+        let mut search = Self {
+            header: cuda.alloc_zeros(8)?,
+            target: cuda.alloc_zeros(32)?,
+            program: cuda.alloc_zeros(MIX_ROUNDS * ROUND_BYTES)?,
+            found_count: cuda.alloc_zeros(1)?,
+            found: cuda.alloc_zeros(MAX_FOUND)?,
+            device: cuda,
+            name: device.name.clone(),
+            threads_per_block: 0,
+            batch_size: 0,
+            dag: None,
+            nvml,
+            pci_bus_id,
+        };
+        search.configure(settings)?;
+        Ok(search)
+    }
+
+    /// Compile and load the kernel for `threads_per_block`, once per size
+    fn load_kernel(&self, threads_per_block: u32) -> anyhow::Result<()> {
+        let module = module(threads_per_block);
+        if self.device.has_func(&module, "search") {
+            return Ok(());
+        }
+
+        let ptx = compile_ptx_with_opts(
+            KERNEL_SOURCE,
+            CompileOptions {
+                arch: Some(arch(&self.device)?),
+                options: vec![format!("-DTHREADS_PER_BLOCK={}", threads_per_block)],
+                ..Default::default()
+            },
+        )
+        .with_context(|| format!("Failed to compile the AequiHash kernel for {}", self.name))?;
+        self.device.load_ptx(ptx, &module, &["search"])?;
+        Ok(())
+    }
+
+    fn function(&self) -> anyhow::Result<CudaFunction> {
+        self.device
+            .get_func(&module(self.threads_per_block), "search")
+            .context("AequiHash kernel not loaded")
+    }
+}
+
+impl NonceSearch for CudaSearch {
+    fn configure(&mut self, settings: LaunchSettings) -> anyhow::Result<()> {
+        self.load_kernel(settings.work_group_size)?;
+        self.threads_per_block = settings.work_group_size;
+        self.batch_size = settings.batch_size;
+        Ok(())
+    }
+
+    fn load_dag(&mut self, seed: &[u8; 32], dag: &[u32]) -> anyhow::Result<()> {
+        if matches!(self.dag, Some((loaded, _)) if &loaded == seed) {
+            return Ok(());
+        }
+
+        // Free the previous DAG before allocating the next
+        self.dag = None;
+        let slice = self.device.htod_sync_copy(dag).context("Failed to upload the DAG")?;
+        self.dag = Some((*seed, slice));
+        Ok(())
+    }
+
+    fn search(&mut self, params: &SearchParams, start: u64) -> anyhow::Result<Vec<u64>> {
+        let function = self.function()?;
+        let (_, dag) = self.dag.as_ref().context("No DAG loaded")?;
+
+        self.device.htod_sync_copy_into(&params.header[..], &mut self.header)?;
+        self.device.htod_sync_copy_into(&params.target[..], &mut self.target)?;
+        self.device.htod_sync_copy_into(&params.program[..], &mut self.program)?;
+        self.device.htod_sync_copy_into(&[0u32][..], &mut self.found_count)?;
+
+        let blocks = self.batch_size.div_ceil(self.threads_per_block as u64) as u32;
+        let config = LaunchConfig {
+            grid_dim: (blocks, 1, 1),
+            block_dim: (self.threads_per_block, 1, 1),
+            shared_mem_bytes: 0,
+        };
+
+        // SAFETY: the arguments match the kernel's signature, and every
+        // buffer is sized for its accesses
+        unsafe {
+            function.launch(
+                config,
+                (
+                    dag,
+                    (dag.len() / DAG_ITEM_WORDS) as u64,
+                    &self.header,
+                    &self.target,
+                    &self.program,
+                    start,
+                    self.batch_size,
+                    &mut self.found_count,
+                    &mut self.found,
+                ),
+            )?;
+        }
+
+        let count = self.device.dtoh_sync_copy(&self.found_count)?[0] as usize;
+        let mut nonces = self.device.dtoh_sync_copy(&self.found)?;
+        nonces.truncate(count.min(MAX_FOUND));
+        Ok(nonces)
+    }
+
+    fn batch_size(&self) -> u64 {
+        self.batch_size
+    }
+
+    fn sensors(&self) -> ThermalReading {
+        let Some(device) = self.nvml.as_ref().and_then(|nvml| nvml.device_by_pci_bus_id(self.pci_bus_id.as_str()).ok()) else {
+            return ThermalReading::default();
+        };
+        ThermalReading {
+            temperature_c: device.temperature(TemperatureSensor::Gpu).ok().map(|celsius| celsius as f32),
+            // NVML reports milliwatts
+            power_w: device.power_usage().ok().map(|milliwatts| milliwatts as f32 / 1000.0),
+        }
+    }
+}
@@ -0,0 +1,242 @@
+//! GPU mining backends
+//!
+//! A backend runs the AequiHash nonce search on one device. The epoch's
+//! DAG is uploaded to device memory once per epoch, and each kernel launch
+//! hashes a batch of consecutive nonces, returning the few that meet the
+//! target. The kernels implement `AequiHash::hash_full`, which equals the
+//! `hash_light` blocks are verified with, and the worker checks every
+//! returned nonce again on the CPU before reporting a solution.
+//!
+//! Backends are cargo features, off by default since they link against the
+//! vendor runtime:
+//!
+//! - `opencl`: any OpenCL 1.2 GPU (AMD, NVIDIA, Intel)
+//! - `cuda`: NVIDIA GPUs, with a kernel tuned for RTX 30/40 cards
+//!
+//! When `cuda` is compiled in and its driver finds NVIDIA cards, they mine
+//! through CUDA and OpenCL keeps the others.
+//!
+//! Launch settings come from [`tune`]: each device is benchmarked once and
+//! its fastest settings kept in a profile. A [`ThermalThrottle`] then scales
+//! them down while the card runs over its temperature or power limit, from
+//! the [`sensors`] each backend reads.
+//!
+//! [`ThermalThrottle`]: aequitas_consensus::ThermalThrottle
+
+#[cfg(feature = "cuda")]
+mod cuda;
+#[cfg(feature = "opencl")]
+mod opencl;
+pub mod sensors;
+pub mod tune;
+
+use aequitas_consensus::pow::difficulty_to_target;
+use aequitas_consensus::{AequiHash, GpuBackend, ThermalReading};
+use crate::worker::MiningJob;
+
+/// Most solutions a single launch reports; more are dropped
+pub const MAX_FOUND: usize = 16;
+
+/// A GPU usable for mining
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GpuDevice {
+    /// Position among all devices, as `gpu_devices` selects them
+    pub index: u32,
+
+    /// Position among the devices of its backend
+    pub ordinal: u32,
+
+    /// Backend driving the device
+    pub backend: GpuBackend,
+
+    /// Device name
+    pub name: String,
+
+    /// Device vendor
+    pub vendor: String,
+
+    /// Global memory (bytes)
+    pub memory_bytes: u64,
+}
+
+/// Work for a kernel: one job of one epoch
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SearchParams {
+    /// Header hash as little-endian words
+    pub header: [u32; 8],
+
+    /// Target, compared bytewise
+    pub target: [u8; 32],
+
+    /// Epoch program, `ROUND_BYTES` per mixing round
+    pub program: Vec<u8>,
+}
+
+impl SearchParams {
+    /// Parameters searching `job`
+    pub fn new(job: &MiningJob, aequihash: &AequiHash) -> Self {
+        let mut header = [0u32; 8];
+        for (word, bytes) in header.iter_mut().zip(job.header_hash.chunks_exact(4)) {
+            *word = u32::from_le_bytes(bytes.try_into().unwrap());
+        }
+
+        Self {
+            header,
+            target: difficulty_to_target(job.difficulty),
+            program: aequihash.program().iter().flat_map(|round| round.encode()).collect(),
+        }
+    }
+}
+
+/// Work-group size kernels launch with until a device is tuned
+pub const DEFAULT_WORK_GROUP_SIZE: u32 = 128;
+
+/// How a kernel is launched
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LaunchSettings {
+    /// Threads per work group (OpenCL) or block (CUDA)
+    pub work_group_size: u32,
+
+    /// Nonces hashed per launch, a multiple of the work-group size
+    pub batch_size: u64,
+}
+
+impl LaunchSettings {
+    /// Untuned settings for `intensity`
+    pub fn for_intensity(intensity: u32) -> Self {
+        Self {
+            work_group_size: DEFAULT_WORK_GROUP_SIZE,
+            batch_size: batch_size(intensity),
+        }
+    }
+
+    /// These settings with `percent` of the batch size, in whole work
+    /// groups and at least one
+    pub fn throttled(self, percent: u32) -> Self {
+        let group = self.work_group_size.max(1) as u64;
+        let groups = (self.batch_size * percent.min(100) as u64 / 100 / group).max(1);
+        Self {
+            work_group_size: self.work_group_size,
+            batch_size: groups * group,
+        }
+    }
+}
+
+/// A device searching nonces
+pub trait NonceSearch {
+    /// Launch with `settings` from the next `search` on, keeping the DAG
+    fn configure(&mut self, settings: LaunchSettings) -> anyhow::Result<()>;
+
+    /// Upload the DAG of the epoch seeded by `seed` to device memory
+    fn load_dag(&mut self, seed: &[u8; 32], dag: &[u32]) -> anyhow::Result<()>;
+
+    /// Nonces of `start..start + batch_size()` whose hash meets the target
+    fn search(&mut self, params: &SearchParams, start: u64) -> anyhow::Result<Vec<u64>>;
+
+    /// Nonces hashed by one `search`
+    fn batch_size(&self) -> u64;
+
+    /// Current temperature and power draw, as far as the driver reports
+    fn sensors(&self) -> ThermalReading {
+        ThermalReading::default()
+    }
+}
+
+/// Nonces per kernel launch: from 2^11 at intensity 1 up to 2^22 at 100
+pub fn batch_size(intensity: u32) -> u64 {
+    let intensity = intensity.clamp(1, 100) as u64;
+    1 << (11 + (intensity - 1) * 11 / 99)
+}
+
+/// Cargo feature building `backend`
+fn feature(backend: GpuBackend) -> &'static str {
+    match backend {
+        GpuBackend::OpenCl => "opencl",
+        GpuBackend::Cuda => "cuda",
+    }
+}
+
+/// Every GPU the compiled backends can mine on
+pub fn devices() -> Vec<GpuDevice> {
+    #[allow(unused_mut)]
+    let mut devices: Vec<GpuDevice> = Vec::new();
+
+    #[cfg(feature = "cuda")]
+    devices.extend(cuda::devices());
+
+    #[cfg(feature = "opencl")]
+    {
+        // The same NVIDIA cards show up in both; mine them once, via CUDA
+        let cuda_found = !devices.is_empty();
+        devices.extend(opencl::devices().into_iter().filter(|device| {
+            !(cuda_found && device.vendor.to_lowercase().contains("nvidia"))
+        }));
+    }
+
+    for (index, device) in devices.iter_mut().enumerate() {
+        device.index = index as u32;
+    }
+    devices
+}
+
+/// Open `device` for mining with `settings`
+#[cfg_attr(not(any(feature = "cuda", feature = "opencl")), allow(unused_variables))]
+pub fn open(device: &GpuDevice, settings: LaunchSettings) -> anyhow::Result<Box<dyn NonceSearch>> {
+    match device.backend {
+        #[cfg(feature = "cuda")]
+        GpuBackend::Cuda => Ok(Box::new(cuda::CudaSearch::new(device, settings)?)),
+        #[cfg(feature = "opencl")]
+        GpuBackend::OpenCl => Ok(Box::new(opencl::OpenClSearch::new(device, settings)?)),
+        #[allow(unreachable_patterns)]
+        backend => anyhow::bail!(
+            "{} needs the {} backend; rebuild with --features {}",
+            device.name,
+            backend.as_str(),
+            feature(backend)
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aequitas_consensus::aequihash::{MIX_ROUNDS, MIX_WORDS, ROUND_BYTES};
+
+    #[test]
+    fn test_batch_size() {
+        assert_eq!(batch_size(1), 1 << 11);
+        assert_eq!(batch_size(100), 1 << 22);
+        assert_eq!(batch_size(0), batch_size(1));
+        assert!(batch_size(75) > batch_size(50));
+
+        let settings = LaunchSettings::for_intensity(1);
+        assert_eq!(settings.batch_size % settings.work_group_size as u64, 0);
+    }
+
+    #[test]
+    fn test_throttled_settings() {
+        let settings = LaunchSettings { work_group_size: 256, batch_size: 1 << 20 };
+        assert_eq!(settings.throttled(100), settings);
+        assert_eq!(settings.throttled(50).batch_size, 1 << 19);
+        assert_eq!(settings.throttled(30).batch_size % 256, 0);
+        assert_eq!(settings.throttled(0).batch_size, 256);
+    }
+
+    #[test]
+    fn test_search_params() {
+        let mut header_hash = [0u8; 32];
+        header_hash[0] = 1;
+        header_hash[7] = 2;
+        let job = MiningJob::new(header_hash, 1000, 0);
+        let params = SearchParams::new(&job, &AequiHash::new(0, &job.seed));
+
+        assert_eq!(params.header[0], 1);
+        assert_eq!(params.header[1], 2 << 24);
+        assert_eq!(params.target, difficulty_to_target(1000));
+        assert_eq!(params.program.len(), MIX_ROUNDS * ROUND_BYTES);
+        for round in params.program.chunks_exact(ROUND_BYTES) {
+            assert!(round[0] < 8);
+            assert!((round[1] as usize) < MIX_WORDS && (round[2] as usize) < MIX_WORDS);
+        }
+    }
+}
@@ -0,0 +1,194 @@
+//! OpenCL backend
+
+use std::path::PathBuf;
+use anyhow::Context as _;
+use ocl::enums::{DeviceInfo, DeviceInfoResult};
+use ocl::{flags, Buffer, Context, Device, Kernel, Platform, Program, Queue};
+use aequitas_consensus::aequihash::{MIX_ROUNDS, ROUND_BYTES};
+use aequitas_consensus::dag::DAG_ITEM_WORDS;
+use aequitas_consensus::{GpuBackend, ThermalReading};
+use super::{sensors, GpuDevice, LaunchSettings, NonceSearch, SearchParams, MAX_FOUND};
+
+/// Kernel source, built for each device when it is opened
+const KERNEL_SOURCE: &str = include_str!("aequihash.cl");
+
+/// `CL_DEVICE_TOPOLOGY_AMD` of the `cl_amd_device_attribute_query` extension
+const DEVICE_TOPOLOGY_AMD: u32 = 0x4037;
+
+/// sysfs directory of an AMD card, from its PCI topology; `None` for other
+/// vendors
+fn amd_device_dir(device: &Device) -> Option<PathBuf> {
+    // cl_device_topology_amd: the type, 17 unused bytes, then bus, device
+    // and function
+    let topology = device.info_raw(DEVICE_TOPOLOGY_AMD).ok()?;
+    match topology.get(..24)? {
+        [kind, .., bus, dev, function] if *kind == 1 => Some(sensors::pci_device_dir(*bus, *dev, *function)),
+        _ => None,
+    }
+}
+
+/// Every OpenCL GPU with its platform, in enumeration order
+fn list() -> Vec<(Platform, Device)> {
+    let mut found = Vec::new();
+    for platform in Platform::list() {
+        match Device::list(platform, Some(flags::DEVICE_TYPE_GPU)) {
+            Ok(devices) => found.extend(devices.into_iter().map(|device| (platform, device))),
+            Err(e) => log::debug!("Skipping OpenCL platform: {}", e),
+        }
+    }
+    found
+}
+
+/// Every OpenCL GPU
+pub fn devices() -> Vec<GpuDevice> {
+    list()
+        .into_iter()
+        .enumerate()
+        .map(|(index, (_, device))| GpuDevice {
+            index: index as u32,
+            ordinal: index as u32,
+            backend: GpuBackend::OpenCl,
+            name: device.name().unwrap_or_else(|_| "Unknown OpenCL device".into()),
+            vendor: device.vendor().unwrap_or_default(),
+            memory_bytes: match device.info(DeviceInfo::GlobalMemSize) {
+                Ok(DeviceInfoResult::GlobalMemSize(bytes)) => bytes,
+                _ => 0,
+            },
+        })
+        .collect()
+}
+
+/// Nonce search on one OpenCL device
+pub struct OpenClSearch {
+    queue: Queue,
+    /// The built kernel source
+    kernel_program: Program,
+    settings: LaunchSettings,
+    header: Buffer<u32>,
+    target: Buffer<u8>,
+    program: Buffer<u8>,
+    found_count: Buffer<u32>,
+    found: Buffer<u64>,
+    /// Seed of the loaded DAG, the DAG and the kernel launched over it
+    kernel: Option<([u8; 32], Buffer<u32>, Kernel)>,
+    /// sysfs directory of the card, for its sensors
+    device_dir: Option<PathBuf>,
+}
+
+impl OpenClSearch {
+    /// Build the kernel for `device`
+    pub fn new(device: &GpuDevice, settings: LaunchSettings) -> anyhow::Result<Self> {
+        let (platform, cl_device) = list()
+            .into_iter()
+            .nth(device.ordinal as usize)
+            .with_context(|| format!("OpenCL device {} not found", device.ordinal))?;
+
+        let context = Context::builder().platform(platform).devices(cl_device).build()?;
+        let queue = Queue::new(&context, cl_device, None)?;
+        let kernel_program = Program::builder()
+            .devices(cl_device)
+            .src(KERNEL_SOURCE)
+            .build(&context)
+            .with_context(|| format!("Failed to build the AequiHash kernel for {}", device.name))?;
+
+        let buffer = |len: usize| Buffer::<u8>::builder().queue(queue.clone()).len(len).build();
+        let header = Buffer::<u32>::builder().queue(queue.clone()).flags(flags::MEM_READ_ONLY).len(8).build()?;
+        let found_count = Buffer::<u32>::builder().queue(queue.clone()).len(1).build()?;
+        let found = Buffer::<u64>::builder().queue(queue.clone()).len(MAX_FOUND).build()?;
+
+        Ok(Self {
+            target: buffer(32)?,
+            program: buffer(MIX_ROUNDS * ROUND_BYTES)?,
+            queue,
+            kernel_program,
+            settings,
+            header,
+            found_count,
+            found,
+            kernel: None,
+            device_dir: amd_device_dir(&cl_device),
+        })
+    }
+
+    /// Kernel searching over `dag`, launched with the current settings
+    fn build_kernel(&self, dag: &Buffer<u32>) -> anyhow::Result<Kernel> {
+        Ok(Kernel::builder()
+            .program(&self.kernel_program)
+            .name("search")
+            .queue(self.queue.clone())
+            .global_work_size(self.settings.batch_size as usize)
+            .local_work_size(self.settings.work_group_size as usize)
+            .arg(dag)
+            .arg((dag.len() / DAG_ITEM_WORDS) as u64)
+            .arg(&self.header)
+            .arg(&self.target)
+            .arg(&self.program)
+            .arg(0u64)
+            .arg(&self.found_count)
+            .arg(&self.found)
+            .build()?)
+    }
+}
+
+impl NonceSearch for OpenClSearch {
+    fn configure(&mut self, settings: LaunchSettings) -> anyhow::Result<()> {
+        self.settings = settings;
+        if let Some((seed, dag, _)) = self.kernel.take() {
+            let kernel = self.build_kernel(&dag)?;
+            self.kernel = Some((seed, dag, kernel));
+        }
+        Ok(())
+    }
+
+    fn load_dag(&mut self, seed: &[u8; 32], dag: &[u32]) -> anyhow::Result<()> {
+        if matches!(self.kernel, Some((loaded, ..)) if &loaded == seed) {
+            return Ok(());
+        }
+
+        // Free the previous DAG before allocating the next
+        self.kernel = None;
+        let dag_buffer = Buffer::<u32>::builder()
+            .queue(self.queue.clone())
+            .flags(flags::MEM_READ_ONLY)
+            .len(dag.len())
+            .copy_host_slice(dag)
+            .build()
+            .context("Failed to upload the DAG")?;
+
+        let kernel = self.build_kernel(&dag_buffer)?;
+
+        self.kernel = Some((*seed, dag_buffer, kernel));
+        Ok(())
+    }
+
+    fn search(&mut self, params: &SearchParams, start: u64) -> anyhow::Result<Vec<u64>> {
+        let (_, _, kernel) = self.kernel.as_ref().context("No DAG loaded")?;
+
+        self.header.write(&params.header[..]).enq()?;
+        self.target.write(&params.target[..]).enq()?;
+        self.program.write(&params.program[..]).enq()?;
+        self.found_count.write(&[0u32][..]).enq()?;
+        kernel.set_arg(5, start)?;
+
+        // SAFETY: every buffer argument is sized for the kernel's accesses
+        unsafe {
+            kernel.enq()?;
+        }
+
+        let mut count = [0u32];
+        self.found_count.read(&mut count[..]).enq()?;
+        let mut nonces = vec![0u64; (count[0] as usize).min(MAX_FOUND)];
+        if !nonces.is_empty() {
+            self.found.read(&mut nonces[..]).enq()?;
+        }
+        Ok(nonces)
+    }
+
+    fn batch_size(&self) -> u64 {
+        self.settings.batch_size
+    }
+
+    fn sensors(&self) -> ThermalReading {
+        self.device_dir.as_deref().map_or_else(ThermalReading::default, sensors::read_hwmon)
+    }
+}
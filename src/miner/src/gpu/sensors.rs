@@ -0,0 +1,62 @@
+//! GPU temperature and power sensors
+//!
+//! The CUDA backend reads NVIDIA cards through NVML. AMD cards are read on
+//! Linux from the hwmon files the amdgpu driver exposes in sysfs, found
+//! through the card's PCI address; elsewhere they report nothing, and a
+//! card without readings is never throttled.
+
+use std::path::{Path, PathBuf};
+use aequitas_consensus::ThermalReading;
+
+/// sysfs directory of the PCI device at `bus:device.function`
+pub fn pci_device_dir(bus: u8, device: u8, function: u8) -> PathBuf {
+    PathBuf::from(format!("/sys/bus/pci/devices/0000:{:02x}:{:02x}.{:x}", bus, device, function))
+}
+
+/// Reading of the first hwmon of the PCI device at `device_dir` with a
+/// temperature or power file
+pub fn read_hwmon(device_dir: &Path) -> ThermalReading {
+    let Ok(entries) = std::fs::read_dir(device_dir.join("hwmon")) else {
+        return ThermalReading::default();
+    };
+
+    for entry in entries.flatten() {
+        let hwmon = entry.path();
+        // Millidegrees and microwatts; older kernels only average power
+        let reading = ThermalReading {
+            temperature_c: read_number(&hwmon.join("temp1_input")).map(|milli| milli as f32 / 1000.0),
+            power_w: read_number(&hwmon.join("power1_input"))
+                .or_else(|| read_number(&hwmon.join("power1_average")))
+                .map(|micro| micro as f32 / 1e6),
+        };
+        if reading != ThermalReading::default() {
+            return reading;
+        }
+    }
+    ThermalReading::default()
+}
+
+fn read_number(path: &Path) -> Option<u64> {
+    std::fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_hwmon() {
+        let dir = std::env::temp_dir().join(format!("aequitas-hwmon-{}", std::process::id()));
+        assert_eq!(read_hwmon(&dir), ThermalReading::default());
+
+        let hwmon = dir.join("hwmon").join("hwmon3");
+        std::fs::create_dir_all(&hwmon).unwrap();
+        std::fs::write(hwmon.join("temp1_input"), "71500\n").unwrap();
+        std::fs::write(hwmon.join("power1_average"), "95000000\n").unwrap();
+
+        let reading = read_hwmon(&dir);
+        assert_eq!(reading.temperature_c, Some(71.5));
+        assert_eq!(reading.power_w, Some(95.0));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,234 @@
+//! Launch settings auto-tuning
+//!
+//! The first time a device mines, [`tune`] times every candidate work-group
+//! size and intensity on the loaded DAG, and keeps the fastest. Profiles are
+//! saved to [`PROFILES_FILE`] in the miner's data directory, one per device
+//! model, so later starts launch with the tuned settings right away. Delete
+//! the file to tune again, after a driver update for example.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use anyhow::Context as _;
+use serde::{Deserialize, Serialize};
+use super::{batch_size, GpuDevice, LaunchSettings, NonceSearch, SearchParams};
+
+/// File the profiles are saved to, in the data directory
+pub const PROFILES_FILE: &str = "gpu-profiles.json";
+
+/// Work-group sizes tried
+pub const WORK_GROUP_SIZES: [u32; 4] = [64, 128, 256, 512];
+
+/// Intensities tried
+pub const INTENSITIES: [u32; 4] = [50, 70, 85, 100];
+
+/// Timed launches per candidate, after one warm-up launch
+const TIMED_LAUNCHES: u64 = 3;
+
+/// Fastest launch settings measured on a device
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TuneProfile {
+    /// Threads per work group or block
+    pub work_group_size: u32,
+
+    /// Intensity, giving the batch size
+    pub intensity: u32,
+
+    /// Hashrate measured with these settings (H/s)
+    pub hashrate: f64,
+}
+
+impl TuneProfile {
+    /// Settings to launch with
+    pub fn settings(&self) -> LaunchSettings {
+        LaunchSettings {
+            work_group_size: self.work_group_size,
+            batch_size: batch_size(self.intensity),
+        }
+    }
+}
+
+/// Key of `device`'s profile; identical cards share one
+pub fn device_key(device: &GpuDevice) -> String {
+    format!("{}/{}/{}MB", device.backend.as_str(), device.name, device.memory_bytes >> 20)
+}
+
+/// Tuned profiles of every device, kept in a JSON file
+#[derive(Debug)]
+pub struct ProfileStore {
+    path: PathBuf,
+    profiles: BTreeMap<String, TuneProfile>,
+}
+
+impl ProfileStore {
+    /// Profiles saved in `data_dir`, none when the file is missing or
+    /// unreadable
+    pub fn load<P: AsRef<Path>>(data_dir: P) -> Self {
+        let path = data_dir.as_ref().join(PROFILES_FILE);
+        let profiles = match std::fs::read(&path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                log::warn!("Ignoring GPU profiles in {}: {}", path.display(), e);
+                BTreeMap::new()
+            }),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => {
+                log::warn!("Could not read GPU profiles from {}: {}", path.display(), e);
+                BTreeMap::new()
+            }
+        };
+        Self { path, profiles }
+    }
+
+    /// Profile of `device`, if it was tuned
+    pub fn get(&self, device: &GpuDevice) -> Option<&TuneProfile> {
+        self.profiles.get(&device_key(device))
+    }
+
+    /// Record the profile of `device`
+    pub fn insert(&mut self, device: &GpuDevice, profile: TuneProfile) {
+        self.profiles.insert(device_key(device), profile);
+    }
+
+    /// Write the profiles, to a temporary file renamed into place
+    pub fn save(&self) -> anyhow::Result<()> {
+        if let Some(dir) = self.path.parent() {
+            std::fs::create_dir_all(dir)
+                .with_context(|| format!("Failed to create {}", dir.display()))?;
+        }
+        let tmp = self.path.with_extension("tmp");
+        std::fs::write(&tmp, serde_json::to_vec_pretty(&self.profiles)?)?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+/// Time every candidate on `searcher`, leaving it configured with the
+/// fastest
+///
+/// The DAG must be loaded. `params` is searched with a target no hash
+/// meets, so tuning reports no solutions. Candidates the device rejects,
+/// such as work groups above its limit, are skipped.
+pub fn tune(searcher: &mut dyn NonceSearch, params: &SearchParams) -> anyhow::Result<TuneProfile> {
+    let mut params = params.clone();
+    params.target = [0u8; 32];
+
+    let mut best: Option<TuneProfile> = None;
+    for work_group_size in WORK_GROUP_SIZES {
+        for intensity in INTENSITIES {
+            let settings = LaunchSettings {
+                work_group_size,
+                batch_size: batch_size(intensity),
+            };
+            match measure(searcher, &params, settings) {
+                Ok(hashrate) => {
+                    log::debug!(
+                        "Work groups of {} at intensity {}: {:.0} H/s",
+                        work_group_size, intensity, hashrate
+                    );
+                    if best.as_ref().map_or(true, |best| hashrate > best.hashrate) {
+                        best = Some(TuneProfile { work_group_size, intensity, hashrate });
+                    }
+                }
+                Err(e) => log::debug!(
+                    "Skipping work groups of {} at intensity {}: {:#}",
+                    work_group_size, intensity, e
+                ),
+            }
+        }
+    }
+
+    let best = best.context("No candidate launch settings ran on the device")?;
+    searcher.configure(best.settings())?;
+    Ok(best)
+}
+
+/// Hashrate of `searcher` launched with `settings`
+fn measure(searcher: &mut dyn NonceSearch, params: &SearchParams, settings: LaunchSettings) -> anyhow::Result<f64> {
+    searcher.configure(settings)?;
+    searcher.search(params, 0)?;
+
+    let start = Instant::now();
+    for launch in 1..=TIMED_LAUNCHES {
+        searcher.search(params, launch * settings.batch_size)?;
+    }
+    let hashes = TIMED_LAUNCHES * settings.batch_size;
+    Ok(hashes as f64 / start.elapsed().as_secs_f64().max(f64::MIN_POSITIVE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use aequitas_consensus::GpuBackend;
+
+    /// Device hashing fastest with work groups of 256, refusing 512
+    struct FakeSearch {
+        settings: LaunchSettings,
+    }
+
+    impl NonceSearch for FakeSearch {
+        fn configure(&mut self, settings: LaunchSettings) -> anyhow::Result<()> {
+            anyhow::ensure!(settings.work_group_size <= 256, "work group too large");
+            self.settings = settings;
+            Ok(())
+        }
+
+        fn load_dag(&mut self, _seed: &[u8; 32], _dag: &[u32]) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        fn search(&mut self, params: &SearchParams, _start: u64) -> anyhow::Result<Vec<u64>> {
+            assert_eq!(params.target, [0u8; 32]);
+            let nanos_per_hash = if self.settings.work_group_size == 256 { 1 } else { 4 };
+            std::thread::sleep(Duration::from_nanos(self.settings.batch_size * nanos_per_hash / 16));
+            Ok(Vec::new())
+        }
+
+        fn batch_size(&self) -> u64 {
+            self.settings.batch_size
+        }
+    }
+
+    fn device() -> GpuDevice {
+        GpuDevice {
+            index: 0,
+            ordinal: 0,
+            backend: GpuBackend::OpenCl,
+            name: "Radeon RX 6600".into(),
+            vendor: "AMD".into(),
+            memory_bytes: 8 << 30,
+        }
+    }
+
+    #[test]
+    fn test_tune_picks_fastest() {
+        let mut searcher = FakeSearch { settings: LaunchSettings::for_intensity(80) };
+        let params = SearchParams { header: [0; 8], target: [0xff; 32], program: Vec::new() };
+
+        let profile = tune(&mut searcher, &params).unwrap();
+        assert_eq!(profile.work_group_size, 256);
+        assert!(profile.hashrate > 0.0);
+        assert_eq!(searcher.settings, profile.settings());
+    }
+
+    #[test]
+    fn test_profile_store_roundtrip() {
+        let dir = std::env::temp_dir().join(format!("aequitas-tune-{}", std::process::id()));
+        let profile = TuneProfile { work_group_size: 256, intensity: 85, hashrate: 1.5e7 };
+
+        let mut store = ProfileStore::load(&dir);
+        assert!(store.get(&device()).is_none());
+        store.insert(&device(), profile.clone());
+        store.save().unwrap();
+
+        assert_eq!(ProfileStore::load(&dir).get(&device()), Some(&profile));
+        let mut other = device();
+        other.memory_bytes = 4 << 30;
+        assert!(ProfileStore::load(&dir).get(&other).is_none());
+
+        // A damaged file is ignored, to be tuned again
+        std::fs::write(dir.join(PROFILES_FILE), b"{not json").unwrap();
+        assert!(ProfileStore::load(&dir).get(&device()).is_none());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -6,7 +6,9 @@ pub mod config;
 pub mod worker;
 pub mod stats;
 pub mod stratum;
+pub mod estimate;
+pub mod gpu;
 
-pub use config::MinerConfig;
+pub use config::{MinerConfig, RotationPolicy};
 pub use worker::MiningWorker;
 pub use stats::MiningStats;
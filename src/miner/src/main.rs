@@ -10,19 +10,25 @@
 use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use anyhow::Result;
-use clap::Parser;
+use clap::{CommandFactory, Parser, Subcommand};
 use log::info;
 
 use aequitas_core::{Address, Transaction};
+use aequitas_core::amount::format_amount;
+use aequitas_miner::estimate::{benchmark_hashrate, Estimate, PowerCost};
+use aequitas_miner::MiningStats;
 mod trust_miner;
 
 /// Command line arguments for miner
 #[derive(Parser, Debug)]
 #[command(author, version, about = "⛏️  AequiHash Trust Miner - Works with ANY GPU")]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+    
     /// Mining address for rewards
     #[arg(long, short)]
-    address: Address,
+    address: Option<Address>,
     
     /// Number of mining threads (auto-detect if not specified)
     #[arg(long, short = 't', default_value = "auto")]
@@ -45,11 +51,52 @@ struct Args {
     gpu_info: bool,
 }
 
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Estimate expected earnings and electricity break-even
+    Estimate {
+        /// Hashrate in H/s (benchmarks this machine if omitted)
+        #[arg(long)]
+        hashrate: Option<f64>,
+        
+        /// Benchmark duration in seconds
+        #[arg(long, default_value = "10")]
+        bench_secs: u64,
+        
+        /// Node RPC URL for the current difficulty and height
+        #[arg(long, default_value = "http://127.0.0.1:23421")]
+        node: String,
+        
+        /// Rig power draw in watts
+        #[arg(long)]
+        watts: Option<f64>,
+        
+        /// Electricity price per kWh
+        #[arg(long)]
+        kwh_price: Option<f64>,
+        
+        /// AEQ price in the same currency, for profit per day
+        #[arg(long)]
+        aeq_price: Option<f64>,
+    },
+    
+    /// Print shell completions to stdout
+    Completions {
+        /// Shell to generate completions for
+        shell: clap_complete::Shell,
+    },
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Parse command line arguments
     let args = Args::parse();
     
+    if let Some(Command::Completions { shell }) = args.command {
+        clap_complete::generate(shell, &mut Args::command(), "aequitas-miner", &mut std::io::stdout());
+        return Ok(());
+    }
+    
     // Initialize logging
     let log_level = if args.verbose { "debug" } else { "info" };
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(log_level))
@@ -61,6 +108,19 @@ async fn main() -> Result<()> {
         return Ok(());
     }
     
+    if let Some(Command::Estimate { hashrate, bench_secs, node, watts, kwh_price, aeq_price }) = args.command {
+        let power = match (watts, kwh_price) {
+            (Some(watts), Some(price_per_kwh)) => Some(PowerCost { watts, price_per_kwh }),
+            (None, None) => None,
+            _ => anyhow::bail!("--watts and --kwh-price must be given together"),
+        };
+        let threads = if args.threads == "auto" { num_cpus::get() } else { args.threads.parse()? };
+        return cmd_estimate(hashrate, bench_secs, threads, &node, power, aeq_price).await;
+    }
+    
+    let address = args.address
+        .ok_or_else(|| anyhow::anyhow!("--address is required for mining"))?;
+    
     // Create miner configuration
     let threads = if args.threads == "auto" {
         None // Auto-detect
@@ -69,7 +129,7 @@ async fn main() -> Result<()> {
     };
     
     let miner_config = trust_miner::MinerConfig {
-        address: args.address,
+        address,
         pool: args.pool,
         threads,
         difficulty: None, // Will be set by blockchain
@@ -122,10 +182,92 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// Print expected earnings for a hashrate at the node's current difficulty
+async fn cmd_estimate(
+    hashrate: Option<f64>,
+    bench_secs: u64,
+    threads: usize,
+    node: &str,
+    power: Option<PowerCost>,
+    aeq_price: Option<f64>,
+) -> Result<()> {
+    let info: serde_json::Value = reqwest::get(format!("{}/info", node)).await?
+        .error_for_status()?
+        .json().await?;
+    let difficulty = info["difficulty"].as_u64()
+        .ok_or_else(|| anyhow::anyhow!("Node did not report a difficulty"))?;
+    let height = info["height"].as_u64().unwrap_or(0) + 1;
+    
+    let hashrate = match hashrate {
+        Some(h) => h,
+        None => {
+            println!("⏱️  Benchmarking {} thread(s) for {}s...", threads, bench_secs);
+            tokio::task::spawn_blocking(move || benchmark_hashrate(bench_secs, threads)).await?
+        }
+    };
+    
+    let estimate = Estimate::new(hashrate, difficulty, height, power);
+    
+    println!("\n💰 Mining Profitability Estimate:\n");
+    println!("═══════════════════════════════════════════════════════");
+    println!("  Your hashrate:     {}", MiningStats::format_hashrate(estimate.hashrate));
+    println!("  Network hashrate:  {}", MiningStats::format_hashrate(estimate.network_hashrate));
+    println!("  Network share:     {:.4}%", estimate.network_share() * 100.0);
+    println!("  Difficulty:        {}", estimate.difficulty);
+    println!("  Block reward:      {} (miner share)", format_amount(estimate.miner_reward));
+    println!("  Blocks per day:    {:.4}", estimate.blocks_per_day);
+    if let Some(days) = estimate.days_per_block() {
+        println!("  Days per block:    {:.2}", days);
+    }
+    println!("  Expected per day:  {:.9} AEQ", estimate.aeq_per_day);
+    println!(
+        "  Next halving:      in {} blocks (~{:.0} days), then {:.9} AEQ/day",
+        estimate.blocks_to_halving,
+        estimate.days_to_halving(),
+        estimate.aeq_per_day_after_halving
+    );
+    
+    if let Some(cost) = estimate.cost_per_day {
+        println!("  Electricity/day:   {:.2}", cost);
+        match estimate.break_even_price {
+            Some(price) => println!("  Break-even price:  {:.6} per AEQ", price),
+            None => println!("  Break-even price:  never (no expected blocks)"),
+        }
+        if let Some(profit) = aeq_price.and_then(|p| estimate.profit_per_day(p)) {
+            println!("  Profit/day:        {:.2} at {} per AEQ", profit, aeq_price.unwrap_or_default());
+        }
+    }
+    println!("═══════════════════════════════════════════════════════");
+    println!("  Estimates assume constant difficulty; actual luck varies.\n");
+    
+    Ok(())
+}
+
 /// Show GPU detection information
 fn show_gpu_info() {
     println!("🎮 GPU Detection Results:");
-    println!("{}", trust_miner::gpu_config::GpuConfig::detect().optimization_hints());
+    println!("{}", aequitas_consensus::GpuConfig::detect().optimization_hints());
+    println!();
+    for device in aequitas_consensus::GpuConfig::enumerate() {
+        println!(
+            "   {} ({} compute units, driver {})",
+            device.name, device.compute_units, device.driver_version
+        );
+    }
+    let devices = aequitas_miner::gpu::devices();
+    if devices.is_empty() {
+        println!("⚠️  No GPU available for mining (build with --features opencl or cuda)");
+    }
+    for device in devices {
+        println!(
+            "   [{}] {} ({}, {} MB, {})",
+            device.index,
+            device.name,
+            device.vendor,
+            device.memory_bytes / (1024 * 1024),
+            device.backend.as_str()
+        );
+    }
     println!();
     println!("✅ Trust-based optimization will work with ANY detected GPU!");
     println!("🚀 Auto-detection and optimization for:");
@@ -32,6 +32,9 @@ pub struct MiningStats {
     
     /// Hashrate history (for averaging)
     hashrate_history: RwLock<Vec<(Instant, f64)>>,
+    
+    /// Latest statistics of each GPU, by index
+    gpus: RwLock<Vec<GpuStats>>,
 }
 
 impl MiningStats {
@@ -47,6 +50,7 @@ impl MiningStats {
             avg_hashrate_15m: RwLock::new(0.0),
             start_time: Instant::now(),
             hashrate_history: RwLock::new(Vec::new()),
+            gpus: RwLock::new(Vec::new()),
         }
     }
     
@@ -106,6 +110,23 @@ impl MiningStats {
         *self.avg_hashrate_15m.write().unwrap() = avg_15m;
     }
     
+    /// Record the latest statistics of a GPU
+    pub fn update_gpu(&self, gpu: GpuStats) {
+        let mut gpus = self.gpus.write().unwrap();
+        match gpus.iter_mut().find(|g| g.index == gpu.index) {
+            Some(existing) => *existing = gpu,
+            None => {
+                gpus.push(gpu);
+                gpus.sort_by_key(|g| g.index);
+            }
+        }
+    }
+    
+    /// Latest statistics of every GPU mining, by index
+    pub fn gpu_stats(&self) -> Vec<GpuStats> {
+        self.gpus.read().unwrap().clone()
+    }
+    
     /// Get current hashrate
     pub fn hashrate(&self) -> f64 {
         *self.hashrate.read().unwrap()
@@ -183,14 +204,18 @@ impl MiningStats {
     
     /// Get summary string
     pub fn summary(&self) -> String {
-        format!(
+        let mut summary = format!(
             "Hashrate: {} (1m: {}, 15m: {}) | Blocks: {} | Uptime: {}",
             Self::format_hashrate(self.hashrate()),
             Self::format_hashrate(self.avg_hashrate_1m()),
             Self::format_hashrate(self.avg_hashrate_15m()),
             self.blocks_found(),
             self.uptime_string(),
-        )
+        );
+        for gpu in self.gpus.read().unwrap().iter() {
+            summary.push_str(&format!(" | GPU{}: {}", gpu.index, Self::format_hashrate(gpu.hashrate)));
+        }
+        summary
     }
 }
 
@@ -272,4 +297,20 @@ mod tests {
         
         assert_eq!(stats.acceptance_rate(), 50.0);
     }
+    
+    #[test]
+    fn test_per_gpu_hashrate() {
+        let stats = MiningStats::new();
+        let gpu = |index, hashrate| GpuStats { index, name: format!("GPU {}", index), hashrate, ..Default::default() };
+        
+        stats.update_gpu(gpu(1, 2000.0));
+        stats.update_gpu(gpu(0, 1000.0));
+        stats.update_gpu(gpu(1, 3000.0));
+        
+        let gpus = stats.gpu_stats();
+        assert_eq!(gpus.len(), 2);
+        assert_eq!((gpus[0].index, gpus[0].hashrate), (0, 1000.0));
+        assert_eq!((gpus[1].index, gpus[1].hashrate), (1, 3000.0));
+        assert!(stats.summary().ends_with("GPU0: 1.00 KH/s | GPU1: 3.00 KH/s"));
+    }
 }
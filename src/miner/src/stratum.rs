@@ -7,7 +7,8 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
-use crate::worker::MiningJob;
+use aequitas_core::{BlockHeader, HEADER_WIRE_SIZE};
+use crate::worker::{MiningJob, MiningResult};
 
 /// Stratum protocol version
 pub const STRATUM_VERSION: &str = "2.0.0";
@@ -41,10 +42,11 @@ pub struct StratumJob {
     /// Job ID
     pub job_id: String,
     
-    /// Block header hash
+    /// Block header hash, or the header in its wire encoding
+    /// (`BlockHeader::to_wire_bytes`) for the miner to hash itself
     pub header_hash: String,
     
-    /// Seed hash (for DAG)
+    /// Seed hash (for DAG), the epoch's `AequiHash::epoch_seed`
     pub seed_hash: String,
     
     /// Difficulty target
@@ -251,6 +253,13 @@ impl StratumClient {
         Ok(response.result == Some(serde_json::Value::Bool(true)))
     }
     
+    /// Submit a result found for `job`, with its mix hash so the pool checks
+    /// the final hash before replaying the mix
+    pub fn submit_result(&mut self, job: &MiningJob, result: &MiningResult) -> anyhow::Result<bool> {
+        let (nonce, header, mixhash) = share_fields(job, result);
+        self.submit_share(&result.job_id, &nonce, &header, &mixhash)
+    }
+    
     /// Receive a job notification
     pub fn receive_job(&mut self) -> anyhow::Result<Option<MiningJob>> {
         if let Some(ref mut stream) = self.stream {
@@ -305,8 +314,22 @@ impl StratumClient {
             .and_then(|v| v.as_str())
             .ok_or_else(|| anyhow::anyhow!("Missing header_hash"))?;
         
-        let mut hash_bytes = [0u8; 32];
-        hex::decode_to_slice(header_hash, &mut hash_bytes)?;
+        // A full header lets the miner hash it itself
+        let wire_header = if header_hash.len() == 2 * HEADER_WIRE_SIZE {
+            let mut bytes = [0u8; HEADER_WIRE_SIZE];
+            hex::decode_to_slice(header_hash, &mut bytes)?;
+            Some(BlockHeader::from_wire_bytes(&bytes)?)
+        } else {
+            None
+        };
+        let hash_bytes = match &wire_header {
+            Some(header) => header.hash(),
+            None => {
+                let mut hash_bytes = [0u8; 32];
+                hex::decode_to_slice(header_hash, &mut hash_bytes)?;
+                hash_bytes
+            }
+        };
         
         let target = params.get(3)
             .and_then(|v| v.as_str())
@@ -317,27 +340,42 @@ impl StratumClient {
         
         let height = params.get(5)
             .and_then(|v| v.as_u64())
+            .or(wire_header.map(|header| header.height))
             .unwrap_or(0);
         
+        // Pools that send no seed hash mine the epoch's fallback seed
+        let epoch = height / aequitas_consensus::aequihash::EPOCH_LENGTH;
+        let seed = match params.get(2).and_then(|v| v.as_str()).filter(|s| !s.is_empty()) {
+            Some(seed_hash) => {
+                let mut seed = [0u8; 32];
+                hex::decode_to_slice(seed_hash, &mut seed)?;
+                seed
+            }
+            None => aequitas_consensus::AequiHash::compute_epoch_seed(epoch),
+        };
+        
         Ok(MiningJob {
             job_id: job_id.to_string(),
             header_hash: hash_bytes,
             difficulty,
             height,
-            epoch: height / aequitas_consensus::aequihash::EPOCH_LENGTH,
+            epoch,
+            seed,
         })
     }
     
     /// Convert target hex to difficulty
+    ///
+    /// Short targets are left-padded to 256 bits; unparseable ones count as
+    /// the easiest target.
     fn target_to_difficulty(&self, target: &str) -> u64 {
-        // Simplified conversion
-        let target_bytes = hex::decode(target).unwrap_or_else(|_| vec![0xff; 32]);
-        if target_bytes.len() >= 4 {
-            let leading = u32::from_be_bytes([target_bytes[0], target_bytes[1], target_bytes[2], target_bytes[3]]);
-            if leading == 0 { 1000000 } else { (u32::MAX / leading) as u64 }
-        } else {
-            1000
+        let bytes = hex::decode(target).unwrap_or_default();
+        let mut full = aequitas_core::target::MAX_TARGET;
+        if !bytes.is_empty() && bytes.len() <= 32 {
+            full = [0u8; 32];
+            full[32 - bytes.len()..].copy_from_slice(&bytes);
         }
+        aequitas_core::target::target_to_difficulty(&full)
     }
     
     /// Send a request
@@ -395,10 +433,35 @@ impl Drop for StratumClient {
     }
 }
 
+/// Nonce, header hash and mix hash of a share, in hex
+fn share_fields(job: &MiningJob, result: &MiningResult) -> (String, String, String) {
+    (
+        format!("{:016x}", result.nonce),
+        hex::encode(job.header_hash),
+        hex::encode(result.mix_hash),
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     
+    #[test]
+    fn test_share_fields() {
+        let job = MiningJob::new([7u8; 32], 1, 0);
+        let result = MiningResult {
+            job_id: job.job_id.clone(),
+            nonce: 0x2a,
+            hash: [1u8; 32],
+            mix_hash: [2u8; 32],
+        };
+        
+        let (nonce, header, mixhash) = share_fields(&job, &result);
+        assert_eq!(nonce, "000000000000002a");
+        assert_eq!(header, hex::encode([7u8; 32]));
+        assert_eq!(mixhash, hex::encode([2u8; 32]));
+    }
+    
     #[test]
     fn test_stratum_client_creation() {
         let client = StratumClient::new(
@@ -410,4 +473,40 @@ mod tests {
         assert!(!client.is_connected());
         assert_eq!(client.difficulty(), 1.0);
     }
+    
+    #[test]
+    fn test_parse_job_with_wire_header() {
+        let client = StratumClient::new("stratum+tcp://pool.example.com:3333", "aeq1TestWorker", "x");
+        let header = aequitas_core::Block::genesis().header;
+        
+        let params = vec![
+            serde_json::json!("job1"),
+            serde_json::json!(hex::encode(header.to_wire_bytes())),
+        ];
+        let job = client.parse_job(&params).unwrap();
+        assert_eq!(job.header_hash, header.hash());
+        assert_eq!(job.height, header.height);
+        
+        let params = vec![serde_json::json!("job2"), serde_json::json!(hex::encode([7u8; 32]))];
+        assert_eq!(client.parse_job(&params).unwrap().header_hash, [7u8; 32]);
+    }
+    
+    #[test]
+    fn test_parse_job_seed_hash() {
+        let client = StratumClient::new("stratum+tcp://pool.example.com:3333", "aeq1TestWorker", "x");
+        let seed = aequitas_consensus::AequiHash::epoch_seed(1, Some(&[3u8; 32]));
+        
+        let params = vec![
+            serde_json::json!("job1"),
+            serde_json::json!(hex::encode([7u8; 32])),
+            serde_json::json!(hex::encode(seed)),
+        ];
+        assert_eq!(client.parse_job(&params).unwrap().seed, seed);
+        
+        let params = vec![serde_json::json!("job2"), serde_json::json!(hex::encode([7u8; 32]))];
+        assert_eq!(client.parse_job(&params).unwrap().seed, aequitas_consensus::AequiHash::compute_epoch_seed(0));
+        
+        let params = vec![serde_json::json!("job3"), serde_json::json!(hex::encode([7u8; 32])), serde_json::json!("zz")];
+        assert!(client.parse_job(&params).is_err());
+    }
 }
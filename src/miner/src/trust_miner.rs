@@ -13,7 +13,9 @@ use std::time::{Duration, Instant};
 
 use crate::address::Address;
 use crate::consensus::{AequiHash, GpuConfig};
+use crate::consensus::aequihash::{compute_cache, CACHE_SIZE, EPOCH_LENGTH};
 use crate::core::{Block, BlockHeader, Transaction};
+use aequitas_core::target::{difficulty_to_target, hash_meets_target};
 
 /// Trust-based miner that adapts to ANY GPU
 pub struct TrustMiner {
@@ -26,6 +28,9 @@ pub struct TrustMiner {
     /// GPU optimization profile
     gpu_config: GpuConfig,
 
+    /// Light cache of the epoch being mined
+    cache: parking_lot::Mutex<Option<(u64, Arc<Vec<u32>>)>>,
+
     /// Mining control
     should_mine: Arc<AtomicBool>,
 }
@@ -77,10 +82,27 @@ impl TrustMiner {
             config,
             state,
             gpu_config,
+            cache: parking_lot::Mutex::new(None),
             should_mine: Arc::new(AtomicBool::new(false)),
         }
     }
 
+    /// Light cache of `epoch`, computed on first use
+    ///
+    /// This miner builds its own blocks without a chain to anchor seeds
+    /// to, so it hashes with the fallback seed of the epoch.
+    fn cache_for(&self, epoch: u64) -> Arc<Vec<u32>> {
+        let mut cache = self.cache.lock();
+        match &*cache {
+            Some((cached, data)) if *cached == epoch => data.clone(),
+            _ => {
+                let data = Arc::new(compute_cache(&AequiHash::compute_epoch_seed(epoch), CACHE_SIZE));
+                *cache = Some((epoch, data.clone()));
+                data
+            }
+        }
+    }
+
     /// Start mining with automatic GPU optimization
     pub fn start_mining(&self) -> Result<(), MinerError> {
         if self.should_mine.load(Ordering::Acquire) {
@@ -167,20 +189,21 @@ impl TrustMiner {
         batch_size: u64,
         target_difficulty: u64,
     ) -> Option<(u64, [u8; 32])> {
-        let aequihash = AequiHash::new(header.height);
+        let epoch = header.height / EPOCH_LENGTH;
+        let aequihash = AequiHash::new(epoch, &AequiHash::compute_epoch_seed(epoch));
+        let cache = self.cache_for(epoch);
         let header_hash = header.hash();
 
         // Create target from difficulty
-        let target = Self::difficulty_to_target(target_difficulty);
+        let target = difficulty_to_target(target_difficulty);
 
         // Mine batch with GPU optimization
         for offset in 0..batch_size {
             let nonce = start_nonce.wrapping_add(offset);
 
-            // Use optimized hash computation
-            let hash = aequihash.hash_light_optimized(&header_hash, nonce, &[]);
+            let hash = aequihash.hash_light(&header_hash, nonce, &cache);
 
-            if Self::meets_target(&hash, &target) {
+            if hash_meets_target(&hash, &target) {
                 return Some((nonce, hash));
             }
         }
@@ -237,28 +260,6 @@ impl TrustMiner {
         )
     }
 
-    /// Convert difficulty to target hash
-    fn difficulty_to_target(difficulty: u64) -> [u8; 32] {
-        let max_target = u64::MAX / difficulty;
-        let mut target = [0u8; 32];
-        target[24..32].copy_from_slice(&max_target.to_le_bytes());
-        target
-    }
-
-    /// Check if hash meets target
-    fn meets_target(hash: &[u8; 32], target: &[u8; 32]) -> bool {
-        // Compare first 8 bytes for efficiency
-        let hash_value = u64::from_le_bytes([
-            hash[0], hash[1], hash[2], hash[3], hash[4], hash[5], hash[6], hash[7],
-        ]);
-
-        let target_value = u64::from_le_bytes([
-            target[0], target[1], target[2], target[3], target[4], target[5], target[6], target[7],
-        ]);
-
-        hash_value <= target_value
-    }
-
     /// Start performance monitoring thread
     fn start_monitoring_thread(&self) {
         let state = Arc::clone(&self.state);
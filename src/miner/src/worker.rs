@@ -4,12 +4,25 @@ use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::thread;
 use std::time::{Duration, Instant};
-use crossbeam_channel::{bounded, Receiver, Sender};
-use aequitas_consensus::{AequiHash, DAG, ProofOfWork};
-use aequitas_consensus::aequihash::EPOCH_LENGTH;
+use crossbeam_channel::{bounded, Receiver, RecvTimeoutError, Sender};
+use aequitas_consensus::{AequiHash, DAG, ProofOfWork, ThermalPolicy, ThermalThrottle};
+use aequitas_consensus::aequihash::{CACHE_SIZE, EPOCH_LENGTH};
+use aequitas_consensus::dag::DAGManager;
 use aequitas_consensus::pow::{difficulty_to_target, hash_less_or_equal, WorkUnit};
 use crate::config::MinerConfig;
-use crate::stats::MiningStats;
+use crate::gpu::tune::{self, ProfileStore};
+use crate::gpu::{self, GpuDevice, LaunchSettings, SearchParams};
+use crate::stats::{GpuStats, MiningStats};
+
+/// Number of nonces hashed between two cancellation checks
+pub const CANCEL_CHECK_INTERVAL: u64 = 256;
+
+/// Time between two GPU sensor readings
+pub const THERMAL_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Light cache size used by CPU workers; hashes are only valid over the
+/// consensus cache size
+pub const CPU_CACHE_SIZE: usize = CACHE_SIZE;
 
 /// Mining job
 #[derive(Clone, Debug)]
@@ -28,17 +41,22 @@ pub struct MiningJob {
     
     /// Epoch for this job
     pub epoch: u64,
+    
+    /// Seed of the epoch, as the node or pool sends it
+    pub seed: [u8; 32],
 }
 
 impl MiningJob {
-    /// Create from block info
+    /// Create from block info, with the epoch's fallback seed
     pub fn new(header_hash: [u8; 32], difficulty: u64, height: u64) -> Self {
+        let epoch = height / EPOCH_LENGTH;
         Self {
             job_id: hex::encode(&header_hash[..8]),
             header_hash,
             difficulty,
             height,
-            epoch: height / EPOCH_LENGTH,
+            epoch,
+            seed: AequiHash::compute_epoch_seed(epoch),
         }
     }
 }
@@ -54,6 +72,9 @@ pub struct MiningResult {
     
     /// Result hash
     pub hash: [u8; 32],
+    
+    /// Mix hash the result hash is computed from, submitted with the nonce
+    pub mix_hash: [u8; 32],
 }
 
 /// Worker thread control
@@ -66,6 +87,41 @@ struct WorkerControl {
     
     /// Hash counter
     hash_count: AtomicU64,
+    
+    /// Cache shared by all workers for the current epoch, by seed
+    cache: std::sync::RwLock<Option<([u8; 32], Arc<Vec<u32>>)>>,
+    
+    /// Full DAG of the current epoch, generated when GPUs mine
+    dag: std::sync::RwLock<Option<DAG>>,
+}
+
+impl WorkerControl {
+    /// Create a new control block
+    fn new() -> Self {
+        Self {
+            stop: AtomicBool::new(false),
+            job_id: std::sync::RwLock::new(String::new()),
+            hash_count: AtomicU64::new(0),
+            cache: std::sync::RwLock::new(None),
+            dag: std::sync::RwLock::new(None),
+        }
+    }
+    
+    /// Get the shared cache if it was computed from the given seed
+    fn cache_for_seed(&self, seed: &[u8; 32]) -> Option<Arc<Vec<u32>>> {
+        self.cache.read().unwrap()
+            .as_ref()
+            .filter(|(s, _)| s == seed)
+            .map(|(_, cache)| cache.clone())
+    }
+    
+    /// Get the shared full DAG if it matches the given epoch and seed
+    fn dag_for(&self, epoch: u64, seed: &[u8; 32]) -> Option<DAG> {
+        self.dag.read().unwrap()
+            .as_ref()
+            .filter(|dag| dag.epoch() == epoch && dag.seed() == seed)
+            .cloned()
+    }
 }
 
 /// CPU mining worker
@@ -78,6 +134,9 @@ pub struct CpuWorker {
     
     /// Thread handle
     handle: Option<thread::JoinHandle<()>>,
+    
+    /// Disconnected when the worker thread exits
+    done_rx: Option<Receiver<()>>,
 }
 
 impl CpuWorker {
@@ -87,6 +146,7 @@ impl CpuWorker {
             id,
             control,
             handle: None,
+            done_rx: None,
         }
     }
     
@@ -97,15 +157,24 @@ impl CpuWorker {
         result_tx: Sender<MiningResult>,
         nonce_start: u64,
         nonce_range: u64,
-    ) {
+        stack_size: usize,
+    ) -> std::io::Result<()> {
         let control = self.control.clone();
         let id = self.id;
+        let (done_tx, done_rx) = bounded::<()>(1);
+        
+        let mut builder = thread::Builder::new().name(format!("cpu-worker-{}", id));
+        if stack_size > 0 {
+            builder = builder.stack_size(stack_size);
+        }
         
-        let handle = thread::spawn(move || {
+        let handle = builder.spawn(move || {
+            // Dropped when the thread exits, which signals `stop`
+            let _done_tx = done_tx;
             log::info!("CPU Worker {} started", id);
             
             let mut current_job: Option<MiningJob> = None;
-            let mut dag: Option<(u64, Arc<Vec<u32>>)> = None;
+            let mut dag: Option<([u8; 32], Arc<Vec<u32>>)> = None;
             let mut aequihash: Option<AequiHash> = None;
             
             loop {
@@ -114,19 +183,23 @@ impl CpuWorker {
                     Ok(job) => {
                         log::debug!("Worker {} got new job: {}", id, job.job_id);
                         
-                        // Update DAG if epoch changed
+                        // Update DAG if the epoch seed changed
                         let need_new_dag = dag.as_ref()
-                            .map(|(epoch, _)| *epoch != job.epoch)
+                            .map(|(seed, _)| *seed != job.seed)
                             .unwrap_or(true);
                         
                         if need_new_dag {
-                            log::info!("Worker {} generating cache for epoch {}", id, job.epoch);
-                            let cache = aequitas_consensus::aequihash::compute_cache(
-                                job.epoch, 
-                                16 * 1024 * 1024 // 16MB cache for CPU
-                            );
-                            dag = Some((job.epoch, Arc::new(cache)));
-                            aequihash = Some(AequiHash::new(job.epoch));
+                            match control.cache_for_seed(&job.seed) {
+                                Some(cache) => {
+                                    log::debug!("Worker {} switched to epoch {}", id, job.epoch);
+                                    dag = Some((job.seed, cache));
+                                    aequihash = Some(AequiHash::new(job.epoch, &job.seed));
+                                }
+                                None => {
+                                    log::warn!("Worker {} has no cache for epoch {}, skipping job", id, job.epoch);
+                                    continue;
+                                }
+                            }
                         }
                         
                         current_job = Some(job);
@@ -153,23 +226,29 @@ impl CpuWorker {
                         let start_nonce = nonce_offset + batch * batch_size;
                         
                         for nonce in start_nonce..start_nonce + batch_size {
+                            // Cooperative cancellation check
+                            if nonce % CANCEL_CHECK_INTERVAL == 0
+                                && control.stop.load(Ordering::Relaxed)
+                            {
+                                break;
+                            }
+                            
                             // Check for new job periodically
-                            if nonce % 10000 == 0 {
-                                if job_rx.try_recv().is_ok() || control.stop.load(Ordering::Relaxed) {
-                                    break;
-                                }
+                            if nonce % 10000 == 0 && job_rx.try_recv().is_ok() {
+                                break;
                             }
                             
-                            let hash = aeq.hash_light(&job.header_hash, nonce, cache);
+                            let output = aeq.compute_light(&job.header_hash, nonce, cache);
                             control.hash_count.fetch_add(1, Ordering::Relaxed);
                             
-                            if hash_less_or_equal(&hash, &target) {
+                            if hash_less_or_equal(&output.hash, &target) {
                                 log::info!("Worker {} found solution! Nonce: {}", id, nonce);
                                 
                                 let result = MiningResult {
                                     job_id: job.job_id.clone(),
                                     nonce,
-                                    hash,
+                                    hash: output.hash,
+                                    mix_hash: output.mix_hash,
                                 };
                                 
                                 let _ = result_tx.send(result);
@@ -187,17 +266,306 @@ impl CpuWorker {
             }
             
             log::info!("CPU Worker {} stopped", id);
-        });
+        })?;
         
         self.handle = Some(handle);
+        self.done_rx = Some(done_rx);
+        Ok(())
     }
     
-    /// Stop the worker
-    pub fn stop(&mut self) {
+    /// Stop the worker, waiting at most `timeout` for it to exit
+    ///
+    /// Returns `false` if the worker did not stop in time; its thread is
+    /// then detached and abandoned.
+    pub fn stop(&mut self, timeout: Duration) -> bool {
+        self.control.stop.store(true, Ordering::Relaxed);
+        
+        let handle = match self.handle.take() {
+            Some(handle) => handle,
+            None => return true,
+        };
+        
+        let exited = match self.done_rx.take() {
+            Some(done_rx) => !matches!(done_rx.recv_timeout(timeout), Err(RecvTimeoutError::Timeout)),
+            None => true,
+        };
+        
+        if exited {
+            let _ = handle.join();
+        } else {
+            log::warn!("CPU Worker {} did not stop within {:?}, aborting", self.id, timeout);
+        }
+        
+        exited
+    }
+}
+
+/// GPU mining worker, driving one device
+pub struct GpuWorker {
+    /// Device mined on
+    device: GpuDevice,
+    
+    /// Control
+    control: Arc<WorkerControl>,
+    
+    /// Hashes computed by this device
+    hash_count: Arc<AtomicU64>,
+    
+    /// Thread handle
+    handle: Option<thread::JoinHandle<()>>,
+    
+    /// Disconnected when the worker thread exits
+    done_rx: Option<Receiver<()>>,
+}
+
+impl GpuWorker {
+    /// Create a new GPU worker
+    pub fn new(device: GpuDevice, control: Arc<WorkerControl>) -> Self {
+        Self {
+            device,
+            control,
+            hash_count: Arc::new(AtomicU64::new(0)),
+            handle: None,
+            done_rx: None,
+        }
+    }
+    
+    /// Start mining `nonce_start..nonce_start + nonce_range`
+    ///
+    /// With `profiles`, the device launches with its tuned profile, or is
+    /// tuned on its first job and the profile saved; otherwise it mines at
+    /// `intensity`. Over a limit of `thermal`, smaller batches and pauses
+    /// between launches cool it down.
+    #[allow(clippy::too_many_arguments)]
+    pub fn start(
+        &mut self,
+        job_rx: Receiver<MiningJob>,
+        result_tx: Sender<MiningResult>,
+        nonce_start: u64,
+        nonce_range: u64,
+        intensity: u32,
+        profiles: Option<Arc<std::sync::Mutex<ProfileStore>>>,
+        thermal: ThermalPolicy,
+    ) -> std::io::Result<()> {
+        let control = self.control.clone();
+        let device = self.device.clone();
+        let device_hashes = self.hash_count.clone();
+        let (done_tx, done_rx) = bounded::<()>(1);
+        
+        let handle = thread::Builder::new().name(format!("gpu-worker-{}", device.index)).spawn(move || {
+            let _done_tx = done_tx;
+            
+            let untuned = LaunchSettings::for_intensity(intensity);
+            let profile = profiles.as_ref().and_then(|store| store.lock().unwrap().get(&device).cloned());
+            let settings = match &profile {
+                Some(profile) => {
+                    log::info!(
+                        "GPU {} launches as tuned: work groups of {}, intensity {}",
+                        device.index, profile.work_group_size, profile.intensity
+                    );
+                    profile.settings()
+                }
+                None => untuned,
+            };
+            let mut needs_tuning = profiles.is_some() && profile.is_none();
+            
+            // Device state belongs to the thread that opened it
+            let mut searcher = match gpu::open(&device, settings) {
+                Ok(searcher) => searcher,
+                Err(e) => {
+                    log::error!("GPU {} ({}) unavailable: {:#}", device.index, device.name, e);
+                    return;
+                }
+            };
+            log::info!("GPU Worker {} started on {}", device.index, device.name);
+            
+            // Settings at full intensity, which the throttle scales down
+            let mut full = settings;
+            let mut throttle = ThermalThrottle::new(thermal);
+            let mut last_reading = Instant::now();
+            let mut batch_size = searcher.batch_size();
+            let mut current: Option<(MiningJob, SearchParams, AequiHash, DAG)> = None;
+            let mut nonce = nonce_start;
+            
+            loop {
+                if let Ok(job) = job_rx.try_recv() {
+                    log::debug!("GPU {} got new job: {}", device.index, job.job_id);
+                    
+                    let Some(dag) = control.dag_for(job.epoch, &job.seed) else {
+                        log::warn!("GPU {} has no DAG for epoch {}, skipping job", device.index, job.epoch);
+                        continue;
+                    };
+                    let dag_bytes = DAG::size_for_epoch(job.epoch) as u64;
+                    if device.memory_bytes > 0 && dag_bytes > device.memory_bytes {
+                        log::error!(
+                            "GPU {} has {} MB, the epoch {} DAG needs {} MB",
+                            device.index, device.memory_bytes >> 20, job.epoch, dag_bytes >> 20
+                        );
+                        break;
+                    }
+                    let loaded = dag.data()
+                        .ok_or_else(|| anyhow::anyhow!("DAG is not full"))
+                        .and_then(|data| searcher.load_dag(&job.seed, data));
+                    if let Err(e) = loaded {
+                        log::error!("GPU {} failed to load epoch {}: {:#}", device.index, job.epoch, e);
+                        break;
+                    }
+                    
+                    let aequihash = AequiHash::new(job.epoch, &job.seed);
+                    let params = SearchParams::new(&job, &aequihash);
+                    
+                    // Tuning needs the DAG on the device, so it waits for
+                    // the first job
+                    if needs_tuning {
+                        needs_tuning = false;
+                        log::info!("Tuning GPU {} ({}), once for this device...", device.index, device.name);
+                        match tune::tune(searcher.as_mut(), &params) {
+                            Ok(profile) => {
+                                log::info!(
+                                    "GPU {} tuned: work groups of {}, intensity {}, {:.0} H/s",
+                                    device.index, profile.work_group_size, profile.intensity, profile.hashrate
+                                );
+                                full = profile.settings();
+                                if let Some(store) = &profiles {
+                                    let mut store = store.lock().unwrap();
+                                    store.insert(&device, profile);
+                                    if let Err(e) = store.save() {
+                                        log::warn!("Could not save the GPU {} profile: {:#}", device.index, e);
+                                    }
+                                }
+                            }
+                            Err(e) => {
+                                log::warn!("Tuning GPU {} failed, mining at intensity {}: {:#}", device.index, intensity, e);
+                                full = untuned;
+                                if let Err(e) = searcher.configure(untuned) {
+                                    log::error!("GPU {} cannot launch: {:#}", device.index, e);
+                                    break;
+                                }
+                            }
+                        }
+                        // Tuning ran at full intensity; resume the throttle
+                        if throttle.percent() < 100 {
+                            if let Err(e) = searcher.configure(full.throttled(throttle.percent())) {
+                                log::error!("GPU {} cannot launch: {:#}", device.index, e);
+                                break;
+                            }
+                        }
+                        batch_size = searcher.batch_size();
+                    }
+                    
+                    current = Some((job, params, aequihash, dag));
+                    nonce = nonce_start;
+                }
+                
+                if control.stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                
+                let Some((job, params, aeq, dag)) = &current else {
+                    thread::sleep(Duration::from_millis(100));
+                    continue;
+                };
+                
+                if last_reading.elapsed() >= THERMAL_CHECK_INTERVAL {
+                    last_reading = Instant::now();
+                    let reading = searcher.sensors();
+                    let before = throttle.percent();
+                    let percent = throttle.update(&reading);
+                    if percent != before {
+                        log::info!(
+                            "GPU {} at {:?} °C, {:?} W: mining at {}% intensity",
+                            device.index, reading.temperature_c, reading.power_w, percent
+                        );
+                        if let Err(e) = searcher.configure(full.throttled(percent)) {
+                            log::error!("GPU {} cannot launch: {:#}", device.index, e);
+                            break;
+                        }
+                        batch_size = searcher.batch_size();
+                    }
+                }
+                
+                let launched = Instant::now();
+                let found = match searcher.search(params, nonce) {
+                    Ok(found) => found,
+                    Err(e) => {
+                        log::error!("GPU {} search failed: {:#}", device.index, e);
+                        break;
+                    }
+                };
+                if throttle.percent() < 100 {
+                    thread::sleep(throttle.idle_time(launched.elapsed()));
+                }
+                control.hash_count.fetch_add(batch_size, Ordering::Relaxed);
+                device_hashes.fetch_add(batch_size, Ordering::Relaxed);
+                
+                for found_nonce in found {
+                    // Never report what the node would reject; hash_full
+                    // equals the hash_light it verifies with. The kernels
+                    // return nonces only, so the mix hash comes from here
+                    let output = aeq.compute_full(&job.header_hash, found_nonce, dag.data().expect("loaded DAGs are full"));
+                    if !hash_less_or_equal(&output.hash, &params.target) {
+                        // The DAG was checked on the CPU side; a bad result
+                        // points at the device, its VRAM most often
+                        log::warn!(
+                            "GPU {} returned nonce {} that fails verification, check its VRAM and clocks",
+                            device.index, found_nonce
+                        );
+                        continue;
+                    }
+                    
+                    log::info!("GPU {} found solution! Nonce: {}", device.index, found_nonce);
+                    let _ = result_tx.send(MiningResult {
+                        job_id: job.job_id.clone(),
+                        nonce: found_nonce,
+                        hash: output.hash,
+                        mix_hash: output.mix_hash,
+                    });
+                }
+                
+                // Stay within this worker's share of the nonce space
+                nonce = nonce.wrapping_add(batch_size);
+                if nonce.wrapping_sub(nonce_start) >= nonce_range.saturating_sub(batch_size) {
+                    nonce = nonce_start;
+                }
+            }
+            
+            log::info!("GPU Worker {} stopped", device.index);
+        })?;
+        
+        self.handle = Some(handle);
+        self.done_rx = Some(done_rx);
+        Ok(())
+    }
+    
+    /// Device mined on
+    pub fn device(&self) -> &GpuDevice {
+        &self.device
+    }
+    
+    /// Stop the worker, waiting at most `timeout` for it to exit
+    ///
+    /// Returns `false` if the worker did not stop in time; its thread is
+    /// then detached and abandoned.
+    pub fn stop(&mut self, timeout: Duration) -> bool {
         self.control.stop.store(true, Ordering::Relaxed);
-        if let Some(handle) = self.handle.take() {
+        
+        let handle = match self.handle.take() {
+            Some(handle) => handle,
+            None => return true,
+        };
+        
+        let exited = match self.done_rx.take() {
+            Some(done_rx) => !matches!(done_rx.recv_timeout(timeout), Err(RecvTimeoutError::Timeout)),
+            None => true,
+        };
+        
+        if exited {
             let _ = handle.join();
+        } else {
+            log::warn!("GPU Worker {} did not stop within {:?}, aborting", self.device.index, timeout);
         }
+        
+        exited
     }
 }
 
@@ -212,6 +580,9 @@ pub struct MiningWorker {
     /// CPU workers
     cpu_workers: Vec<CpuWorker>,
     
+    /// GPU workers
+    gpu_workers: Vec<GpuWorker>,
+    
     /// Job sender
     job_tx: Option<Sender<MiningJob>>,
     
@@ -221,6 +592,9 @@ pub struct MiningWorker {
     /// Mining statistics
     stats: Arc<MiningStats>,
     
+    /// Full DAGs for GPU workers
+    dag_manager: std::sync::Mutex<DAGManager>,
+    
     /// Is running
     running: bool,
 }
@@ -228,19 +602,17 @@ pub struct MiningWorker {
 impl MiningWorker {
     /// Create a new mining worker
     pub fn new(config: MinerConfig) -> Self {
-        let control = Arc::new(WorkerControl {
-            stop: AtomicBool::new(false),
-            job_id: std::sync::RwLock::new(String::new()),
-            hash_count: AtomicU64::new(0),
-        });
+        let control = Arc::new(WorkerControl::new());
         
         Self {
             config,
             control,
             cpu_workers: Vec::new(),
+            gpu_workers: Vec::new(),
             job_tx: None,
             result_rx: None,
             stats: Arc::new(MiningStats::new()),
+            dag_manager: std::sync::Mutex::new(DAGManager::new(true)),
             running: false,
         }
     }
@@ -254,9 +626,11 @@ impl MiningWorker {
         let (job_tx, job_rx) = bounded::<MiningJob>(10);
         let (result_tx, result_rx) = bounded::<MiningResult>(10);
         
-        // Start CPU workers
+        let devices = if self.config.gpu_enabled { self.select_gpus() } else { Vec::new() };
+        
+        // Every worker gets its own share of the nonce space
         let num_threads = self.config.cpu_threads;
-        let nonce_range = u64::MAX / num_threads as u64;
+        let nonce_range = u64::MAX / (num_threads + devices.len()).max(1) as u64;
         
         for i in 0..num_threads {
             let mut worker = CpuWorker::new(i, self.control.clone());
@@ -265,15 +639,31 @@ impl MiningWorker {
                 result_tx.clone(),
                 i as u64 * nonce_range,
                 nonce_range,
-            );
+                self.config.worker_stack_size,
+            )?;
             self.cpu_workers.push(worker);
         }
         
         log::info!("Started {} CPU workers", num_threads);
         
-        // TODO: Start GPU workers if enabled
-        if self.config.gpu_enabled {
-            log::warn!("GPU mining not yet implemented - using CPU only");
+        // Loaded once, so every GPU starts with its profile at hand
+        let profiles = (self.config.gpu_auto_tune && !devices.is_empty())
+            .then(|| Arc::new(std::sync::Mutex::new(ProfileStore::load(&self.config.data_dir))));
+        for (i, device) in devices.into_iter().enumerate() {
+            let mut worker = GpuWorker::new(device, self.control.clone());
+            worker.start(
+                job_rx.clone(),
+                result_tx.clone(),
+                (num_threads + i) as u64 * nonce_range,
+                nonce_range,
+                self.config.gpu_intensity,
+                profiles.clone(),
+                self.config.thermal_policy(),
+            )?;
+            self.gpu_workers.push(worker);
+        }
+        if !self.gpu_workers.is_empty() {
+            log::info!("Started {} GPU workers", self.gpu_workers.len());
         }
         
         self.job_tx = Some(job_tx);
@@ -283,10 +673,14 @@ impl MiningWorker {
         // Start stats thread
         let control = self.control.clone();
         let stats = self.stats.clone();
+        let gpus: Vec<(GpuDevice, Arc<AtomicU64>)> = self.gpu_workers.iter()
+            .map(|worker| (worker.device.clone(), worker.hash_count.clone()))
+            .collect();
         let interval = self.config.stats_interval;
         
         thread::spawn(move || {
             let mut last_count = 0u64;
+            let mut last_gpu_counts = vec![0u64; gpus.len()];
             let mut last_time = Instant::now();
             
             while !control.stop.load(Ordering::Relaxed) {
@@ -305,6 +699,18 @@ impl MiningWorker {
                         hashrate,
                         count
                     );
+                    
+                    for ((device, hashes), last) in gpus.iter().zip(&mut last_gpu_counts) {
+                        let device_count = hashes.load(Ordering::Relaxed);
+                        stats.update_gpu(GpuStats {
+                            index: device.index,
+                            name: device.name.clone(),
+                            hashrate: (device_count - *last) as f64 / elapsed,
+                            memory_total: Some(device.memory_bytes).filter(|&bytes| bytes > 0),
+                            ..Default::default()
+                        });
+                        *last = device_count;
+                    }
                 }
                 
                 last_count = count;
@@ -315,11 +721,54 @@ impl MiningWorker {
         Ok(result_rx)
     }
     
+    /// GPUs to mine on: those listed in `gpu_devices`, or all of them
+    fn select_gpus(&self) -> Vec<GpuDevice> {
+        let available = gpu::devices();
+        if available.is_empty() {
+            log::warn!("GPU mining enabled but no usable GPU found - using CPU only");
+            return available;
+        }
+        
+        let wanted = &self.config.gpu_devices;
+        for index in wanted {
+            if !available.iter().any(|device| device.index == *index) {
+                log::warn!("GPU {} not found, ignoring it", index);
+            }
+        }
+        
+        available
+            .into_iter()
+            .filter(|device| wanted.is_empty() || wanted.contains(&device.index))
+            .collect()
+    }
+    
+    /// Make sure the shared cache, and the full DAG when GPUs mine, are
+    /// ready for an epoch and its seed
+    ///
+    /// The cache is computed once and shared by every worker through an
+    /// `Arc`, so epoch transitions cost a single cache generation.
+    fn prepare_epoch(&self, epoch: u64, seed: &[u8; 32]) {
+        if !self.gpu_workers.is_empty() && self.control.dag_for(epoch, seed).is_none() {
+            let dag = self.dag_manager.lock().unwrap().get_dag(epoch, seed).clone();
+            *self.control.dag.write().unwrap() = Some(dag);
+        }
+        
+        if self.control.cache_for_seed(seed).is_some() {
+            return;
+        }
+        
+        log::info!("Generating shared cache for epoch {}", epoch);
+        let cache = aequitas_consensus::aequihash::compute_cache(seed, CPU_CACHE_SIZE);
+        *self.control.cache.write().unwrap() = Some((*seed, Arc::new(cache)));
+    }
+    
     /// Submit a new job
     pub fn submit_job(&self, job: MiningJob) -> anyhow::Result<()> {
+        self.prepare_epoch(job.epoch, &job.seed);
+        
         if let Some(tx) = &self.job_tx {
             // Send to all workers
-            for _ in 0..self.cpu_workers.len() {
+            for _ in 0..self.cpu_workers.len() + self.gpu_workers.len() {
                 tx.send(job.clone())?;
             }
             
@@ -337,13 +786,33 @@ impl MiningWorker {
         log::info!("Stopping miners...");
         self.control.stop.store(true, Ordering::Relaxed);
         
+        // Shared deadline so total shutdown time stays bounded
+        let deadline = Instant::now() + Duration::from_millis(self.config.shutdown_timeout_ms);
+        let mut aborted = 0;
+        
         for worker in &mut self.cpu_workers {
-            worker.stop();
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if !worker.stop(remaining) {
+                aborted += 1;
+            }
+        }
+        
+        for worker in &mut self.gpu_workers {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if !worker.stop(remaining) {
+                aborted += 1;
+            }
         }
         
         self.cpu_workers.clear();
+        self.gpu_workers.clear();
         self.running = false;
-        log::info!("All miners stopped");
+        
+        if aborted > 0 {
+            log::warn!("{} miner(s) aborted after shutdown timeout", aborted);
+        } else {
+            log::info!("All miners stopped");
+        }
     }
     
     /// Get current hashrate
@@ -356,6 +825,11 @@ impl MiningWorker {
         self.control.hash_count.load(Ordering::Relaxed)
     }
     
+    /// Template request for the next block, paying the current payout address
+    pub fn template_request(&self) -> serde_json::Value {
+        self.config.template_request(self.stats.blocks_found())
+    }
+    
     /// Get stats
     pub fn stats(&self) -> &MiningStats {
         &self.stats
@@ -382,5 +856,33 @@ mod tests {
         let job = MiningJob::new([0u8; 32], 1000, 100);
         assert_eq!(job.height, 100);
         assert_eq!(job.epoch, 100 / EPOCH_LENGTH);
+        assert_eq!(job.seed, AequiHash::compute_epoch_seed(job.epoch));
+    }
+    
+    #[test]
+    fn test_worker_stops_promptly() {
+        let control = Arc::new(WorkerControl::new());
+        let (_job_tx, job_rx) = bounded::<MiningJob>(1);
+        let (result_tx, _result_rx) = bounded::<MiningResult>(1);
+        
+        let mut worker = CpuWorker::new(0, control);
+        worker.start(job_rx, result_tx, 0, u64::MAX, 0).unwrap();
+        
+        assert!(worker.stop(Duration::from_secs(1)));
+    }
+    
+    #[test]
+    fn test_shared_cache_per_epoch() {
+        let control = WorkerControl::new();
+        let seed = AequiHash::compute_epoch_seed(0);
+        assert!(control.cache_for_seed(&seed).is_none());
+        
+        let cache = Arc::new(vec![1u32, 2, 3]);
+        *control.cache.write().unwrap() = Some((seed, cache.clone()));
+        
+        let shared = control.cache_for_seed(&seed).unwrap();
+        assert!(Arc::ptr_eq(&shared, &cache));
+        assert!(control.cache_for_seed(&AequiHash::compute_epoch_seed(1)).is_none());
+        assert!(control.cache_for_seed(&AequiHash::epoch_seed(0, Some(&[1u8; 32]))).is_none());
     }
 }
@@ -6,6 +6,6 @@ pub mod node;
 pub mod messages;
 pub mod peer;
 
-pub use node::Node;
-pub use messages::NetworkMessage;
-pub use peer::PeerManager;
+pub use node::{Node, NetworkCommand};
+pub use messages::{HandshakeMsg, NetworkMessage, NewBlockMsg};
+pub use peer::{PeerManager, VersionStatus};
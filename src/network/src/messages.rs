@@ -3,10 +3,19 @@
 //! Protocol messages for peer communication.
 
 use serde::{Deserialize, Serialize};
-use aequitas_core::{Block, Transaction};
+use aequitas_core::{Block, BlockHeader, Transaction};
 
-/// Protocol version
-pub const PROTOCOL_VERSION: u32 = 1;
+/// Protocol version (2: transactions carry a lock height)
+pub const PROTOCOL_VERSION: u32 = 2;
+
+/// Oldest protocol version accepted by default
+///
+/// Version 1 peers encode transactions without a lock height, which this
+/// node cannot decode.
+pub const MIN_PROTOCOL_VERSION: u32 = 2;
+
+/// Maximum blocks sent in one `Blocks` response
+pub const MAX_BLOCKS_PER_MSG: u32 = 32;
 
 /// Network magic bytes for mainnet
 pub const MAINNET_MAGIC: [u8; 4] = [0xAE, 0x51, 0xC0, 0x01];
@@ -61,6 +70,9 @@ pub enum NetworkMessage {
     
     /// Request peer addresses
     GetAddr,
+    
+    /// Request main-chain blocks starting at a height
+    GetBlocksFrom(GetBlocksFromMsg),
 }
 
 /// Handshake message
@@ -78,6 +90,9 @@ pub struct HandshakeMsg {
     /// Sender's best block hash
     pub best_hash: [u8; 32],
     
+    /// Sender's cumulative chain work (big-endian, see `encode_work`)
+    pub total_work: Vec<u8>,
+    
     /// Unix timestamp
     pub timestamp: i64,
     
@@ -90,12 +105,13 @@ pub struct HandshakeMsg {
 
 impl HandshakeMsg {
     /// Create a new handshake message
-    pub fn new(height: u64, best_hash: [u8; 32], testnet: bool) -> Self {
+    pub fn new(height: u64, best_hash: [u8; 32], total_work: u128, testnet: bool) -> Self {
         Self {
             version: PROTOCOL_VERSION,
             magic: if testnet { TESTNET_MAGIC } else { MAINNET_MAGIC },
             height,
             best_hash,
+            total_work: encode_work(total_work),
             timestamp: chrono::Utc::now().timestamp(),
             user_agent: format!("Aequitas/{}", env!("CARGO_PKG_VERSION")),
             services: 1, // Full node
@@ -103,6 +119,25 @@ impl HandshakeMsg {
     }
 }
 
+/// Encode cumulative chain work for the wire (big-endian)
+pub fn encode_work(work: u128) -> Vec<u8> {
+    work.to_be_bytes().to_vec()
+}
+
+/// Decode cumulative chain work from the wire
+///
+/// Values wider than 128 bits saturate to `u128::MAX`.
+pub fn decode_work(bytes: &[u8]) -> u128 {
+    let significant = bytes.iter().skip_while(|b| **b == 0).count();
+    if significant > 16 {
+        return u128::MAX;
+    }
+    
+    let mut buf = [0u8; 16];
+    buf[16 - significant..].copy_from_slice(&bytes[bytes.len() - significant..]);
+    u128::from_be_bytes(buf)
+}
+
 /// Request headers message
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct GetHeadersMsg {
@@ -119,8 +154,31 @@ pub struct GetHeadersMsg {
 /// Headers response
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct HeadersMsg {
-    /// Block headers
-    pub headers: Vec<aequitas_core::BlockHeader>,
+    /// Block headers, sent in their fixed wire encoding
+    #[serde(with = "wire_headers")]
+    pub headers: Vec<BlockHeader>,
+}
+
+/// Headers as the concatenation of their wire encodings
+mod wire_headers {
+    use aequitas_core::{BlockHeader, HEADER_WIRE_SIZE};
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(headers: &[BlockHeader], serializer: S) -> Result<S::Ok, S::Error> {
+        let bytes: Vec<u8> = headers.iter().flat_map(BlockHeader::to_wire_bytes).collect();
+        bytes.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<BlockHeader>, D::Error> {
+        let bytes = Vec::<u8>::deserialize(deserializer)?;
+        if bytes.len() % HEADER_WIRE_SIZE != 0 {
+            return Err(D::Error::custom("headers length is not a multiple of the header size"));
+        }
+        bytes
+            .chunks_exact(HEADER_WIRE_SIZE)
+            .map(|chunk| BlockHeader::from_wire_bytes(chunk.try_into().unwrap()).map_err(D::Error::custom))
+            .collect()
+    }
 }
 
 /// Request blocks message
@@ -130,6 +188,16 @@ pub struct GetBlocksMsg {
     pub hashes: Vec<[u8; 32]>,
 }
 
+/// Request blocks by height message
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GetBlocksFromMsg {
+    /// First height wanted
+    pub from_height: u64,
+    
+    /// Maximum blocks to return
+    pub max_blocks: u32,
+}
+
 /// Blocks response
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct BlocksMsg {
@@ -147,6 +215,21 @@ pub struct NewBlockMsg {
     pub total_work: Vec<u8>,
 }
 
+impl NewBlockMsg {
+    /// Create a new block announcement
+    pub fn new(block: Block, total_work: u128) -> Self {
+        Self {
+            block,
+            total_work: encode_work(total_work),
+        }
+    }
+    
+    /// Get the announced total chain work
+    pub fn work(&self) -> u128 {
+        decode_work(&self.total_work)
+    }
+}
+
 /// New transactions announcement
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct NewTxMsg {
@@ -227,6 +310,7 @@ impl NetworkMessage {
             NetworkMessage::Pong(_) => "pong",
             NetworkMessage::Addr(_) => "addr",
             NetworkMessage::GetAddr => "getaddr",
+            NetworkMessage::GetBlocksFrom(_) => "getblocksfrom",
         }
     }
 }
@@ -237,7 +321,7 @@ mod tests {
     
     #[test]
     fn test_handshake_serialization() {
-        let msg = HandshakeMsg::new(100, [0u8; 32], false);
+        let msg = HandshakeMsg::new(100, [0u8; 32], 12345, false);
         let network_msg = NetworkMessage::Handshake(msg);
         
         let bytes = network_msg.to_bytes().unwrap();
@@ -245,4 +329,23 @@ mod tests {
         
         assert_eq!(network_msg.type_name(), decoded.type_name());
     }
+    
+    #[test]
+    fn test_headers_wire_encoding() {
+        let header = Block::genesis().header;
+        let msg = NetworkMessage::Headers(HeadersMsg { headers: vec![header.clone(), header.clone()] });
+
+        let NetworkMessage::Headers(decoded) = NetworkMessage::from_bytes(&msg.to_bytes().unwrap()).unwrap() else {
+            panic!("expected headers");
+        };
+        assert_eq!(decoded.headers, vec![header.clone(), header]);
+    }
+    
+    #[test]
+    fn test_work_encoding() {
+        assert_eq!(decode_work(&encode_work(0)), 0);
+        assert_eq!(decode_work(&encode_work(u128::MAX)), u128::MAX);
+        assert_eq!(decode_work(&[0x01, 0x00]), 256);
+        assert_eq!(decode_work(&[0xff; 20]), u128::MAX);
+    }
 }
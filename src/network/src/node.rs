@@ -3,7 +3,7 @@
 //! Main P2P network node handling connections and message routing.
 
 use std::sync::Arc;
-use tokio::sync::{mpsc, RwLock};
+use tokio::sync::{broadcast, mpsc, RwLock};
 use libp2p::{
     gossipsub,
     mdns,
@@ -12,7 +12,12 @@ use libp2p::{
     tcp, noise, yamux, Multiaddr, PeerId,
 };
 use futures::stream::StreamExt;
-use crate::peer::PeerManager;
+use crate::messages::{
+    BlocksMsg, GetBlocksFromMsg, HandshakeMsg, NetworkMessage, NewBlockMsg, MAX_BLOCKS_PER_MSG,
+    MIN_PROTOCOL_VERSION, PROTOCOL_VERSION,
+};
+use crate::peer::{PeerManager, VersionStatus};
+use std::collections::BTreeMap;
 use aequitas_core::{Block, Transaction};
 use log;
 use hex;
@@ -26,6 +31,12 @@ pub const BLOCKS_TOPIC: &str = "aequitas/blocks/1";
 /// Topic for transaction announcements  
 pub const TX_TOPIC: &str = "aequitas/tx/1";
 
+/// Topic for block sync requests and responses
+pub const SYNC_TOPIC: &str = "aequitas/sync/1";
+
+/// Peer events buffered for slow subscribers
+pub const PEER_EVENT_CAPACITY: usize = 256;
+
 #[derive(NetworkBehaviour)]
 pub struct AequitasBehaviour {
     pub gossipsub: gossipsub::Behaviour,
@@ -40,6 +51,8 @@ pub struct NodeConfig {
     pub bootstrap_peers: Vec<Multiaddr>,
     pub testnet: bool,
     pub enable_mdns: bool,
+    /// Peers below this protocol version are disconnected
+    pub min_protocol_version: u32,
 }
 
 impl Default for NodeConfig {
@@ -49,6 +62,24 @@ impl Default for NodeConfig {
             bootstrap_peers: Vec::new(),
             testnet: true,
             enable_mdns: true,
+            min_protocol_version: MIN_PROTOCOL_VERSION,
+        }
+    }
+}
+
+/// Who opened the connection
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PeerDirection {
+    Inbound,
+    Outbound,
+}
+
+impl PeerDirection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PeerDirection::Inbound => "inbound",
+            PeerDirection::Outbound => "outbound",
         }
     }
 }
@@ -58,18 +89,123 @@ impl Default for NodeConfig {
 pub struct PeerInfoSimple {
     pub id: String,
     pub addr: Option<String>,
+    /// Protocol version from the peer's handshake, once received
+    pub version: Option<u32>,
+    pub user_agent: Option<String>,
+    /// Best height the peer has reported
+    pub height: Option<u64>,
+    pub direction: PeerDirection,
+    /// Unix time the first connection to the peer was established
+    pub connected_at: i64,
+}
+
+impl PeerInfoSimple {
+    pub fn new(id: String, addr: Option<String>, direction: PeerDirection) -> Self {
+        Self {
+            id,
+            addr,
+            version: None,
+            user_agent: None,
+            height: None,
+            direction,
+            connected_at: chrono::Utc::now().timestamp(),
+        }
+    }
+    
+    /// Seconds since the peer connected
+    pub fn connected_secs(&self) -> u64 {
+        (chrono::Utc::now().timestamp() - self.connected_at).max(0) as u64
+    }
+}
+
+/// Connection change broadcast to RPC subscribers
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum PeerEvent {
+    Connected {
+        peer: PeerInfoSimple,
+    },
+    Disconnected {
+        peer: PeerInfoSimple,
+        /// How long the peer stayed connected
+        connected_secs: u64,
+    },
 }
 
 pub struct NetworkState {
     pub connected_peers: Vec<PeerInfoSimple>,
+    pub min_protocol_version: u32,
+    /// Peers connected since start
+    pub total_connections: u64,
+    /// Peers disconnected since start
+    pub total_disconnections: u64,
+    events: broadcast::Sender<PeerEvent>,
 }
 
 impl NetworkState {
     pub fn new() -> Self {
         Self {
             connected_peers: Vec::new(),
+            min_protocol_version: MIN_PROTOCOL_VERSION,
+            total_connections: 0,
+            total_disconnections: 0,
+            events: broadcast::channel(PEER_EVENT_CAPACITY).0,
         }
     }
+    
+    /// Receive every later peer connection and disconnection
+    pub fn subscribe(&self) -> broadcast::Receiver<PeerEvent> {
+        self.events.subscribe()
+    }
+    
+    /// Track a newly connected peer; `false` if it was already connected
+    pub fn peer_connected(&mut self, peer: PeerInfoSimple) -> bool {
+        if self.connected_peers.iter().any(|p| p.id == peer.id) {
+            return false;
+        }
+        self.total_connections += 1;
+        let _ = self.events.send(PeerEvent::Connected { peer: peer.clone() });
+        self.connected_peers.push(peer);
+        true
+    }
+    
+    /// Forget a disconnected peer, returning what was known about it
+    pub fn peer_disconnected(&mut self, id: &str) -> Option<PeerInfoSimple> {
+        let index = self.connected_peers.iter().position(|p| p.id == id)?;
+        let peer = self.connected_peers.remove(index);
+        self.total_disconnections += 1;
+        let _ = self.events.send(PeerEvent::Disconnected {
+            connected_secs: peer.connected_secs(),
+            peer: peer.clone(),
+        });
+        Some(peer)
+    }
+    
+    /// Number of connected peers in each direction
+    pub fn direction_count(&self, direction: PeerDirection) -> usize {
+        self.connected_peers.iter().filter(|p| p.direction == direction).count()
+    }
+    
+    /// Highest chain height reported by any connected peer
+    pub fn best_peer_height(&self) -> Option<u64> {
+        self.connected_peers.iter().filter_map(|p| p.height).max()
+    }
+    
+    /// Raise the height known for a peer
+    pub fn note_peer_height(&mut self, id: &str, height: u64) {
+        if let Some(peer) = self.connected_peers.iter_mut().find(|p| p.id == id) {
+            peer.height = Some(peer.height.map_or(height, |h| h.max(height)));
+        }
+    }
+    
+    /// Number of connected peers per protocol version (`None` = no handshake yet)
+    pub fn version_spread(&self) -> BTreeMap<Option<u32>, usize> {
+        let mut spread = BTreeMap::new();
+        for peer in &self.connected_peers {
+            *spread.entry(peer.version).or_insert(0) += 1;
+        }
+        spread
+    }
 }
 
 /// Network event types
@@ -79,6 +215,23 @@ pub enum NetworkEvent {
     PeerDisconnected(PeerId),
     NewBlock(Block),
     NewTransaction(Transaction),
+    /// A peer asked for main-chain blocks starting at a height
+    BlocksRequested { from_height: u64, max_blocks: u32 },
+    /// Consecutive blocks received in reply to a sync request
+    SyncBlocks(Vec<Block>),
+}
+
+/// Commands sent to the network node
+#[derive(Clone, Debug)]
+pub enum NetworkCommand {
+    /// Gossip a newly accepted block
+    AnnounceBlock(NewBlockMsg),
+    /// Ask peers for blocks starting at a height
+    RequestBlocks { from_height: u64 },
+    /// Answer a sync request
+    SendBlocks(Vec<Block>),
+    /// Advertise our protocol version and chain tip
+    Handshake(HandshakeMsg),
 }
 
 /// Network node
@@ -97,17 +250,20 @@ impl Node {
         let local_key = libp2p::identity::Keypair::generate_ed25519();
         let local_peer_id = PeerId::from(local_key.public());
         
+        let mut state = NetworkState::new();
+        state.min_protocol_version = config.min_protocol_version;
+        
         Self {
             config,
             local_peer_id,
             _peer_manager: Arc::new(RwLock::new(PeerManager::new())),
-            state: Arc::new(RwLock::new(NetworkState::new())),
+            state: Arc::new(RwLock::new(state)),
             event_tx,
             event_rx: Some(event_rx),
         }
     }
 
-    pub async fn start(self, mut external_rx: mpsc::Receiver<Block>) -> anyhow::Result<()> {
+    pub async fn start(self, mut commands: mpsc::Receiver<NetworkCommand>) -> anyhow::Result<()> {
         let local_key = libp2p::identity::Keypair::generate_ed25519();
         let mut swarm = libp2p::SwarmBuilder::with_existing_identity(local_key)
             .with_tokio()
@@ -140,6 +296,8 @@ impl Node {
 
         let blocks_topic = gossipsub::IdentTopic::new(BLOCKS_TOPIC);
         swarm.behaviour_mut().gossipsub.subscribe(&blocks_topic)?;
+        let sync_topic = gossipsub::IdentTopic::new(SYNC_TOPIC);
+        swarm.behaviour_mut().gossipsub.subscribe(&sync_topic)?;
 
         swarm.listen_on(self.config.listen_addr.clone())?;
 
@@ -148,15 +306,41 @@ impl Node {
         // Set mode to server to be reachable by others
         swarm.behaviour_mut().kademlia.set_mode(Some(kad::Mode::Server));
 
+        // Latest handshake, re-sent whenever a peer joins the sync topic
+        let mut handshake: Option<Vec<u8>> = None;
+
         loop {
             tokio::select! {
-                block = external_rx.recv() => {
-                    if let Some(block) = block {
-                        if let Ok(data) = bincode::serialize(&block) {
+                command = commands.recv() => match command {
+                    Some(NetworkCommand::AnnounceBlock(announcement)) => {
+                        if let Ok(data) = bincode::serialize(&announcement) {
                             let _ = swarm.behaviour_mut().gossipsub.publish(blocks_topic.clone(), data);
                         }
                     }
-                }
+                    Some(NetworkCommand::RequestBlocks { from_height }) => {
+                        log::info!("🔄 P2P: Requesting blocks from height {}", from_height);
+                        let request = NetworkMessage::GetBlocksFrom(GetBlocksFromMsg {
+                            from_height,
+                            max_blocks: MAX_BLOCKS_PER_MSG,
+                        });
+                        if let Ok(data) = request.to_bytes() {
+                            let _ = swarm.behaviour_mut().gossipsub.publish(sync_topic.clone(), data);
+                        }
+                    }
+                    Some(NetworkCommand::SendBlocks(blocks)) => {
+                        let response = NetworkMessage::Blocks(BlocksMsg { blocks });
+                        if let Ok(data) = response.to_bytes() {
+                            let _ = swarm.behaviour_mut().gossipsub.publish(sync_topic.clone(), data);
+                        }
+                    }
+                    Some(NetworkCommand::Handshake(msg)) => {
+                        if let Ok(data) = NetworkMessage::Handshake(msg).to_bytes() {
+                            let _ = swarm.behaviour_mut().gossipsub.publish(sync_topic.clone(), data.clone());
+                            handshake = Some(data);
+                        }
+                    }
+                    None => {}
+                },
                 event = swarm.select_next_some() => match event {
                     SwarmEvent::Behaviour(AequitasBehaviourEvent::Mdns(mdns::Event::Discovered(list))) => {
                         for (peer_id, addr) in list {
@@ -165,28 +349,77 @@ impl Node {
                             swarm.behaviour_mut().kademlia.add_address(&peer_id, addr);
                         }
                     },
+                    SwarmEvent::Behaviour(AequitasBehaviourEvent::Gossipsub(gossipsub::Event::Subscribed {
+                        topic,
+                        ..
+                    })) => {
+                        if topic == sync_topic.hash() {
+                            if let Some(data) = handshake.clone() {
+                                let _ = swarm.behaviour_mut().gossipsub.publish(sync_topic.clone(), data);
+                            }
+                        }
+                    },
                     SwarmEvent::Behaviour(AequitasBehaviourEvent::Gossipsub(gossipsub::Event::Message {
                         message,
                         ..
                     })) => {
                         if message.topic == blocks_topic.hash() {
-                            if let Ok(block) = bincode::deserialize::<Block>(&message.data) {
-                                let _ = self.event_tx.send(NetworkEvent::NewBlock(block)).await;
+                            if let Ok(announcement) = bincode::deserialize::<NewBlockMsg>(&message.data) {
+                                log::debug!(
+                                    "Block {} announced with total work {}",
+                                    announcement.block.header.height,
+                                    announcement.work()
+                                );
+                                if let Some(peer_id) = message.source {
+                                    self.state.write().await
+                                        .note_peer_height(&peer_id.to_string(), announcement.block.header.height);
+                                }
+                                let _ = self.event_tx.send(NetworkEvent::NewBlock(announcement.block)).await;
+                            }
+                        } else if message.topic == sync_topic.hash() {
+                            match NetworkMessage::from_bytes(&message.data) {
+                                Ok(NetworkMessage::GetBlocksFrom(request)) => {
+                                    let _ = self.event_tx.send(NetworkEvent::BlocksRequested {
+                                        from_height: request.from_height,
+                                        max_blocks: request.max_blocks.min(MAX_BLOCKS_PER_MSG),
+                                    }).await;
+                                }
+                                Ok(NetworkMessage::Blocks(response)) => {
+                                    let _ = self.event_tx.send(NetworkEvent::SyncBlocks(response.blocks)).await;
+                                }
+                                Ok(NetworkMessage::Handshake(peer_handshake)) => {
+                                    if let Some(peer_id) = message.source {
+                                        self.handle_handshake(&mut swarm, peer_id, peer_handshake).await;
+                                    }
+                                }
+                                _ => {}
                             }
                         }
                     },
                     SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
                         log::info!("🤝 P2P: Connection established with {}", peer_id);
-                        let mut state = self.state.write().await;
-                        state.connected_peers.push(PeerInfoSimple {
-                            id: peer_id.to_string(),
-                            addr: Some(endpoint.get_remote_address().to_string()),
-                        });
+                        let direction = if endpoint.is_dialer() {
+                            PeerDirection::Outbound
+                        } else {
+                            PeerDirection::Inbound
+                        };
+                        let peer = PeerInfoSimple::new(
+                            peer_id.to_string(),
+                            Some(endpoint.get_remote_address().to_string()),
+                            direction,
+                        );
+                        // Extra connections to a known peer are not a new peer
+                        if self.state.write().await.peer_connected(peer) {
+                            let _ = self.event_tx.send(NetworkEvent::PeerConnected(peer_id)).await;
+                        }
                     },
-                    SwarmEvent::ConnectionClosed { peer_id, .. } => {
+                    SwarmEvent::ConnectionClosed { peer_id, num_established, .. } => {
                         log::info!("🚪 P2P: Connection closed with {}", peer_id);
-                        let mut state = self.state.write().await;
-                        state.connected_peers.retain(|p| p.id != peer_id.to_string());
+                        if num_established == 0
+                            && self.state.write().await.peer_disconnected(&peer_id.to_string()).is_some()
+                        {
+                            let _ = self.event_tx.send(NetworkEvent::PeerDisconnected(peer_id)).await;
+                        }
                     },
                     _ => {}
                 }
@@ -194,7 +427,76 @@ impl Node {
         }
     }
     
+    /// Record a peer's handshake and drop peers below the minimum version
+    async fn handle_handshake(
+        &self,
+        swarm: &mut libp2p::Swarm<AequitasBehaviour>,
+        peer_id: PeerId,
+        handshake: HandshakeMsg,
+    ) {
+        let min_version = self.config.min_protocol_version;
+        match VersionStatus::check(handshake.version, min_version) {
+            VersionStatus::Unsupported => {
+                log::warn!(
+                    "⛔ P2P: Disconnecting {} ({}): protocol version {} is below minimum {}",
+                    peer_id, handshake.user_agent, handshake.version, min_version
+                );
+                swarm.behaviour_mut().gossipsub.blacklist_peer(&peer_id);
+                let _ = swarm.disconnect_peer_id(peer_id);
+                return;
+            }
+            VersionStatus::Deprecated => {
+                log::warn!(
+                    "⚠️  P2P: Peer {} ({}) uses deprecated protocol version {} (current {}); it will be dropped once the minimum is raised",
+                    peer_id, handshake.user_agent, handshake.version, PROTOCOL_VERSION
+                );
+            }
+            VersionStatus::Newer => {
+                log::info!(
+                    "P2P: Peer {} ({}) runs newer protocol version {}; consider upgrading",
+                    peer_id, handshake.user_agent, handshake.version
+                );
+            }
+            VersionStatus::Current => {}
+        }
+
+        let id = peer_id.to_string();
+        let mut state = self.state.write().await;
+        if let Some(peer) = state.connected_peers.iter_mut().find(|p| p.id == id) {
+            peer.version = Some(handshake.version);
+            peer.user_agent = Some(handshake.user_agent);
+        }
+        state.note_peer_height(&id, handshake.height);
+    }
+
     pub fn take_event_receiver(&mut self) -> Option<mpsc::Receiver<NetworkEvent>> {
         self.event_rx.take()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peer_bookkeeping_and_events() {
+        let mut state = NetworkState::new();
+        let mut events = state.subscribe();
+
+        let peer = PeerInfoSimple::new("peer-a".into(), None, PeerDirection::Inbound);
+        assert!(state.peer_connected(peer.clone()));
+        assert!(!state.peer_connected(peer));
+        assert!(state.peer_connected(PeerInfoSimple::new("peer-b".into(), None, PeerDirection::Outbound)));
+        assert_eq!(state.connected_peers.len(), 2);
+        assert_eq!(state.direction_count(PeerDirection::Inbound), 1);
+
+        assert!(state.peer_disconnected("peer-a").is_some());
+        assert!(state.peer_disconnected("peer-a").is_none());
+        assert_eq!((state.total_connections, state.total_disconnections), (2, 1));
+
+        assert!(matches!(events.try_recv().unwrap(), PeerEvent::Connected { peer } if peer.id == "peer-a"));
+        assert!(matches!(events.try_recv().unwrap(), PeerEvent::Connected { peer } if peer.id == "peer-b"));
+        assert!(matches!(events.try_recv().unwrap(), PeerEvent::Disconnected { peer, .. } if peer.id == "peer-a"));
+        assert!(events.try_recv().is_err());
+    }
+}
@@ -5,6 +5,7 @@
 use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::time::{Duration, Instant};
+use crate::messages::PROTOCOL_VERSION;
 
 /// Maximum number of peers
 pub const MAX_PEERS: usize = 50;
@@ -34,6 +35,52 @@ pub enum PeerState {
     Disconnected,
 }
 
+/// How a peer's protocol version compares to ours
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VersionStatus {
+    /// Same version as this node
+    Current,
+    
+    /// Newer than this node (we may need to upgrade)
+    Newer,
+    
+    /// Older but still accepted; will be dropped once the minimum is raised
+    Deprecated,
+    
+    /// Below the minimum accepted version
+    Unsupported,
+}
+
+impl VersionStatus {
+    /// Classify a peer version against the minimum accepted version
+    pub fn check(version: u32, min_version: u32) -> Self {
+        if version < min_version {
+            VersionStatus::Unsupported
+        } else if version < PROTOCOL_VERSION {
+            VersionStatus::Deprecated
+        } else if version > PROTOCOL_VERSION {
+            VersionStatus::Newer
+        } else {
+            VersionStatus::Current
+        }
+    }
+    
+    /// Whether peers with this status may stay connected
+    pub fn is_accepted(&self) -> bool {
+        *self != VersionStatus::Unsupported
+    }
+    
+    /// Short lowercase name
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VersionStatus::Current => "current",
+            VersionStatus::Newer => "newer",
+            VersionStatus::Deprecated => "deprecated",
+            VersionStatus::Unsupported => "unsupported",
+        }
+    }
+}
+
 /// Peer information
 #[derive(Clone, Debug)]
 pub struct PeerInfo {
@@ -52,6 +99,9 @@ pub struct PeerInfo {
     /// Peer's best known block hash
     pub best_hash: [u8; 32],
     
+    /// Peer's cumulative chain work
+    pub total_work: u128,
+    
     /// User agent string
     pub user_agent: String,
     
@@ -86,6 +136,7 @@ impl PeerInfo {
             inbound,
             height: 0,
             best_hash: [0u8; 32],
+            total_work: 0,
             user_agent: String::new(),
             services: 0,
             connected_at: Instant::now(),
@@ -105,6 +156,15 @@ impl PeerInfo {
         }
     }
     
+    /// Update peer chain tip, keeping the tip with the most work
+    pub fn update_chain(&mut self, height: u64, hash: [u8; 32], total_work: u128) {
+        if total_work > self.total_work {
+            self.height = height;
+            self.best_hash = hash;
+            self.total_work = total_work;
+        }
+    }
+    
     /// Increase score (good behavior)
     pub fn increase_score(&mut self, amount: i32) {
         self.score = (self.score + amount).min(200);
@@ -251,11 +311,11 @@ impl PeerManager {
         self.peers.values().collect()
     }
     
-    /// Get best peer (highest height)
+    /// Get best peer (most cumulative work, height as tie-breaker)
     pub fn best_peer(&self) -> Option<&PeerInfo> {
         self.peers.values()
             .filter(|p| p.state == PeerState::Active)
-            .max_by_key(|p| p.height)
+            .max_by_key(|p| (p.total_work, p.height))
     }
     
     /// Add known address
@@ -361,4 +421,34 @@ mod tests {
         assert_eq!(peer.score, -50);
         assert!(peer.should_ban());
     }
+    
+    #[test]
+    fn test_version_status() {
+        assert_eq!(VersionStatus::check(PROTOCOL_VERSION, PROTOCOL_VERSION), VersionStatus::Current);
+        assert_eq!(VersionStatus::check(PROTOCOL_VERSION + 1, PROTOCOL_VERSION), VersionStatus::Newer);
+        assert_eq!(VersionStatus::check(PROTOCOL_VERSION - 1, 0), VersionStatus::Deprecated);
+        assert_eq!(VersionStatus::check(0, 1), VersionStatus::Unsupported);
+        assert!(!VersionStatus::Unsupported.is_accepted());
+        assert!(VersionStatus::Deprecated.is_accepted());
+    }
+    
+    #[test]
+    fn test_best_peer_by_work() {
+        let mut manager = PeerManager::new();
+        let tall = test_addr(8888);
+        let heavy = test_addr(8889);
+        
+        manager.add_peer(tall, false).unwrap();
+        manager.add_peer(heavy, false).unwrap();
+        
+        let peer = manager.get_peer_mut(&tall).unwrap();
+        peer.state = PeerState::Active;
+        peer.update_chain(200, [1u8; 32], 1_000);
+        
+        let peer = manager.get_peer_mut(&heavy).unwrap();
+        peer.state = PeerState::Active;
+        peer.update_chain(100, [2u8; 32], 5_000);
+        
+        assert_eq!(manager.best_peer().unwrap().addr, heavy);
+    }
 }
@@ -10,6 +10,16 @@ pub fn default_data_dir() -> PathBuf {
         .join("aequitas")
 }
 
+/// Checkpoint entry loaded from configuration
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CheckpointEntry {
+    /// Block height
+    pub height: u64,
+    
+    /// Expected block hash (hex)
+    pub hash: String,
+}
+
 /// Node configuration
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct NodeConfig {
@@ -29,6 +39,14 @@ pub struct NodeConfig {
     #[serde(default = "default_rpc_enabled")]
     pub rpc_enabled: bool,
     
+    /// Compress RPC responses (gzip/deflate)
+    #[serde(default = "default_true")]
+    pub rpc_compression: bool,
+    
+    /// Send ETags and honour If-None-Match on RPC responses
+    #[serde(default = "default_true")]
+    pub rpc_etag: bool,
+    
     /// Network (mainnet or testnet)
     #[serde(default = "default_network")]
     pub network: String,
@@ -56,6 +74,26 @@ pub struct NodeConfig {
     /// Enable pruning (reduce storage)
     #[serde(default)]
     pub pruning: bool,
+    
+    /// Maintain an address transaction history index
+    #[serde(default)]
+    pub address_index: bool,
+    
+    /// Extra checkpoints, on top of the hard-coded ones
+    #[serde(default)]
+    pub checkpoints: Vec<CheckpointEntry>,
+    
+    /// Hash (hex) of a block whose ancestors skip signature verification
+    #[serde(default)]
+    pub assume_valid: Option<String>,
+    
+    /// Oldest peer protocol version to stay connected to
+    #[serde(default = "default_min_protocol_version")]
+    pub min_protocol_version: u32,
+    
+    /// Smallest output value accepted into the mempool
+    #[serde(default = "default_dust_threshold")]
+    pub dust_threshold: u64,
 }
 
 fn default_p2p_addr() -> String {
@@ -70,6 +108,10 @@ fn default_rpc_enabled() -> bool {
     true
 }
 
+fn default_true() -> bool {
+    true
+}
+
 fn default_network() -> String {
     "testnet".to_string()
 }
@@ -82,6 +124,14 @@ fn default_max_peers() -> usize {
     50
 }
 
+fn default_min_protocol_version() -> u32 {
+    aequitas_network::messages::MIN_PROTOCOL_VERSION
+}
+
+fn default_dust_threshold() -> u64 {
+    aequitas_core::DUST_THRESHOLD
+}
+
 impl Default for NodeConfig {
     fn default() -> Self {
         Self {
@@ -89,6 +139,8 @@ impl Default for NodeConfig {
             p2p_addr: default_p2p_addr(),
             rpc_addr: default_rpc_addr(),
             rpc_enabled: default_rpc_enabled(),
+            rpc_compression: default_true(),
+            rpc_etag: default_true(),
             network: default_network(),
             bootstrap_peers: Vec::new(),
             mining_enabled: false,
@@ -96,6 +148,11 @@ impl Default for NodeConfig {
             log_level: default_log_level(),
             max_peers: default_max_peers(),
             pruning: false,
+            address_index: false,
+            checkpoints: Vec::new(),
+            assume_valid: None,
+            min_protocol_version: default_min_protocol_version(),
+            dust_threshold: default_dust_threshold(),
         }
     }
 }
@@ -132,7 +189,14 @@ rpc_addr = "127.0.0.1:23421"
 # Enable RPC server
 rpc_enabled = true
 
-# Network: "mainnet" or "testnet"
+# Compress RPC responses for clients sending Accept-Encoding (gzip, deflate)
+rpc_compression = true
+
+# Add ETags to RPC responses and answer If-None-Match with 304 Not Modified
+rpc_etag = true
+
+# Network: "mainnet", "testnet" or "regtest" (private, difficulty 1,
+# blocks mined on demand through POST /generate)
 network = "testnet"
 
 # Bootstrap peers (leave empty for testnet discovery)
@@ -152,12 +216,57 @@ max_peers = 50
 
 # Enable blockchain pruning (saves disk space)
 pruning = false
+
+# Maintain an address transaction history index (uses more memory)
+address_index = false
+
+# Skip signature checks for this block and its ancestors (faster sync);
+# output ownership is still checked
+# assume_valid = "<block hash hex>"
+
+# Disconnect peers below this protocol version (older peers above it only
+# trigger deprecation warnings)
+min_protocol_version = 1
+
+# Refuse transactions with outputs below this value (smallest units)
+dust_threshold = 10000
+
+# Extra checkpoints (blocks at these heights must match the hash)
+# [[checkpoints]]
+# height = 100000
+# hash = "<block hash hex>"
 "#;
         
         std::fs::write(path, sample)?;
         Ok(())
     }
     
+    /// Build the checkpoint set from the hard-coded and configured entries
+    pub fn checkpoints(&self) -> anyhow::Result<aequitas_core::Checkpoints> {
+        let mut checkpoints = self.chain_params()?.checkpoints();
+        for entry in &self.checkpoints {
+            checkpoints.insert_hex(entry.height, &entry.hash)?;
+        }
+        if let Some(hash) = &self.assume_valid {
+            checkpoints.set_assume_valid_hex(hash)?;
+        }
+        Ok(checkpoints)
+    }
+    
+    /// Consensus parameters of the configured network
+    pub fn chain_params(&self) -> anyhow::Result<aequitas_core::ChainParams> {
+        let network = self.network.parse::<aequitas_core::Network>().map_err(anyhow::Error::msg)?;
+        Ok(aequitas_core::ChainParams::for_network(network))
+    }
+    
+    /// HTTP options for the RPC server
+    pub fn http_options(&self) -> crate::http_cache::HttpOptions {
+        crate::http_cache::HttpOptions {
+            compression: self.rpc_compression,
+            etag: self.rpc_etag,
+        }
+    }
+    
     /// Validate configuration
     pub fn validate(&self) -> anyhow::Result<()> {
         if self.mining_enabled && self.mining_address.is_none() {
@@ -170,6 +279,8 @@ pruning = false
             }
         }
         
+        self.checkpoints()?;
+        
         Ok(())
     }
 }
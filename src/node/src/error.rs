@@ -0,0 +1,319 @@
+//! RPC error envelope
+//!
+//! Every failing RPC call answers with an HTTP error status and the same body,
+//! unknown routes and malformed paths, queries or JSON bodies included:
+//!
+//! ```json
+//! { "error": { "code": 2001, "reason": "block_not_found", "message": "Block not found" } }
+//! ```
+//!
+//! `code` and `reason` are stable and meant for programmatic branching;
+//! `message` is human-readable and may change. Codes are grouped by range:
+//!
+//! | Code | Reason                  | HTTP | Meaning                                      |
+//! |------|-------------------------|------|----------------------------------------------|
+//! | 1000 | `invalid_request`       | 400  | Malformed request or parameter               |
+//! | 1001 | `invalid_hash`          | 400  | Hash is not 32 bytes of hex                  |
+//! | 1002 | `invalid_address`       | 400  | Missing or malformed address                 |
+//! | 1003 | `invalid_hex`           | 400  | Payload is not valid hex                     |
+//! | 1004 | `invalid_transaction`   | 400  | Transaction cannot be decoded or is invalid  |
+//! | 1005 | `range_too_large`       | 400  | Block range above `MAX_BLOCK_RANGE`          |
+//! | 2000 | `not_found`             | 404  | No such route                                |
+//! | 2001 | `block_not_found`       | 404  | No block with this hash or height            |
+//! | 2002 | `tx_not_found`          | 404  | No transaction with this hash                |
+//! | 2003 | `proposal_not_found`    | 404  | No governance proposal with this id          |
+//! | 3001 | `mempool_full`          | 503  | Mempool cannot accept more transactions      |
+//! | 3002 | `duplicate_transaction` | 409  | Transaction is already in the mempool        |
+//! | 3003 | `insufficient_fee`      | 400  | Fee is below the mempool minimum             |
+//! | 3004 | `not_replaceable`       | 409  | Conflicts with a non-replaceable transaction |
+//! | 3005 | `replacement_fee_too_low` | 400 | Replacement does not pay enough to evict     |
+//! | 3006 | `dust_output`           | 400  | Output below the dust threshold              |
+//! | 3007 | `too_many_ancestors`    | 400  | More than 25 mempool ancestors               |
+//! | 4001 | `unknown_job`           | 404  | Block template job is unknown or expired     |
+//! | 4002 | `stale_job`             | 409  | Chain tip moved since the template was built |
+//! | 4003 | `invalid_coinbase`      | 500  | Coinbase does not match the reward split     |
+//! | 4004 | `block_rejected`        | 400  | Block failed consensus validation            |
+//! | 5000 | `internal_error`        | 500  | Unexpected node failure                      |
+
+use axum::{
+    extract::{FromRequest, FromRequestParts, Path, Query, Request},
+    http::{request::Parts, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use crate::mempool::MempoolError;
+
+/// Stable RPC error codes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    InvalidRequest,
+    InvalidHash,
+    InvalidAddress,
+    InvalidHex,
+    InvalidTransaction,
+    RangeTooLarge,
+    NotFound,
+    BlockNotFound,
+    TxNotFound,
+    ProposalNotFound,
+    MempoolFull,
+    DuplicateTransaction,
+    InsufficientFee,
+    NotReplaceable,
+    ReplacementFeeTooLow,
+    DustOutput,
+    TooManyAncestors,
+    UnknownJob,
+    StaleJob,
+    InvalidCoinbase,
+    BlockRejected,
+    Internal,
+}
+
+impl ErrorCode {
+    /// All error codes, in table order
+    pub const ALL: [ErrorCode; 22] = [
+        ErrorCode::InvalidRequest,
+        ErrorCode::InvalidHash,
+        ErrorCode::InvalidAddress,
+        ErrorCode::InvalidHex,
+        ErrorCode::InvalidTransaction,
+        ErrorCode::RangeTooLarge,
+        ErrorCode::NotFound,
+        ErrorCode::BlockNotFound,
+        ErrorCode::TxNotFound,
+        ErrorCode::ProposalNotFound,
+        ErrorCode::MempoolFull,
+        ErrorCode::DuplicateTransaction,
+        ErrorCode::InsufficientFee,
+        ErrorCode::NotReplaceable,
+        ErrorCode::ReplacementFeeTooLow,
+        ErrorCode::DustOutput,
+        ErrorCode::TooManyAncestors,
+        ErrorCode::UnknownJob,
+        ErrorCode::StaleJob,
+        ErrorCode::InvalidCoinbase,
+        ErrorCode::BlockRejected,
+        ErrorCode::Internal,
+    ];
+
+    /// Numeric code
+    pub fn code(self) -> u16 {
+        match self {
+            ErrorCode::InvalidRequest => 1000,
+            ErrorCode::InvalidHash => 1001,
+            ErrorCode::InvalidAddress => 1002,
+            ErrorCode::InvalidHex => 1003,
+            ErrorCode::InvalidTransaction => 1004,
+            ErrorCode::RangeTooLarge => 1005,
+            ErrorCode::NotFound => 2000,
+            ErrorCode::BlockNotFound => 2001,
+            ErrorCode::TxNotFound => 2002,
+            ErrorCode::ProposalNotFound => 2003,
+            ErrorCode::MempoolFull => 3001,
+            ErrorCode::DuplicateTransaction => 3002,
+            ErrorCode::InsufficientFee => 3003,
+            ErrorCode::NotReplaceable => 3004,
+            ErrorCode::ReplacementFeeTooLow => 3005,
+            ErrorCode::DustOutput => 3006,
+            ErrorCode::TooManyAncestors => 3007,
+            ErrorCode::UnknownJob => 4001,
+            ErrorCode::StaleJob => 4002,
+            ErrorCode::InvalidCoinbase => 4003,
+            ErrorCode::BlockRejected => 4004,
+            ErrorCode::Internal => 5000,
+        }
+    }
+
+    /// Machine-readable reason
+    pub fn reason(self) -> &'static str {
+        match self {
+            ErrorCode::InvalidRequest => "invalid_request",
+            ErrorCode::InvalidHash => "invalid_hash",
+            ErrorCode::InvalidAddress => "invalid_address",
+            ErrorCode::InvalidHex => "invalid_hex",
+            ErrorCode::InvalidTransaction => "invalid_transaction",
+            ErrorCode::RangeTooLarge => "range_too_large",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::BlockNotFound => "block_not_found",
+            ErrorCode::TxNotFound => "tx_not_found",
+            ErrorCode::ProposalNotFound => "proposal_not_found",
+            ErrorCode::MempoolFull => "mempool_full",
+            ErrorCode::DuplicateTransaction => "duplicate_transaction",
+            ErrorCode::InsufficientFee => "insufficient_fee",
+            ErrorCode::NotReplaceable => "not_replaceable",
+            ErrorCode::ReplacementFeeTooLow => "replacement_fee_too_low",
+            ErrorCode::DustOutput => "dust_output",
+            ErrorCode::TooManyAncestors => "too_many_ancestors",
+            ErrorCode::UnknownJob => "unknown_job",
+            ErrorCode::StaleJob => "stale_job",
+            ErrorCode::InvalidCoinbase => "invalid_coinbase",
+            ErrorCode::BlockRejected => "block_rejected",
+            ErrorCode::Internal => "internal_error",
+        }
+    }
+
+    /// HTTP status returned with this code
+    pub fn status(self) -> StatusCode {
+        match self {
+            ErrorCode::InvalidRequest
+            | ErrorCode::InvalidHash
+            | ErrorCode::InvalidAddress
+            | ErrorCode::InvalidHex
+            | ErrorCode::InvalidTransaction
+            | ErrorCode::RangeTooLarge
+            | ErrorCode::InsufficientFee
+            | ErrorCode::ReplacementFeeTooLow
+            | ErrorCode::DustOutput
+            | ErrorCode::TooManyAncestors
+            | ErrorCode::BlockRejected => StatusCode::BAD_REQUEST,
+            ErrorCode::NotFound
+            | ErrorCode::BlockNotFound
+            | ErrorCode::TxNotFound
+            | ErrorCode::ProposalNotFound
+            | ErrorCode::UnknownJob => StatusCode::NOT_FOUND,
+            ErrorCode::DuplicateTransaction
+            | ErrorCode::NotReplaceable
+            | ErrorCode::StaleJob => StatusCode::CONFLICT,
+            ErrorCode::MempoolFull => StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::InvalidCoinbase
+            | ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Error returned by RPC handlers
+#[derive(Debug, Clone)]
+pub struct RpcError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl RpcError {
+    /// Create an error with a human-readable message
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+
+    /// Build the JSON envelope for this error
+    pub fn to_response(&self) -> ErrorResponse {
+        ErrorResponse {
+            error: ErrorBody {
+                code: self.code.code(),
+                reason: self.code.reason().to_string(),
+                message: self.message.clone(),
+            },
+        }
+    }
+}
+
+impl std::fmt::Display for RpcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({}): {}", self.code.code(), self.code.reason(), self.message)
+    }
+}
+
+impl std::error::Error for RpcError {}
+
+impl From<MempoolError> for RpcError {
+    fn from(e: MempoolError) -> Self {
+        let code = match e {
+            MempoolError::MempoolFull => ErrorCode::MempoolFull,
+            MempoolError::AlreadyExists => ErrorCode::DuplicateTransaction,
+            MempoolError::InvalidTransaction(_) => ErrorCode::InvalidTransaction,
+            MempoolError::InsufficientFee => ErrorCode::InsufficientFee,
+            MempoolError::NotReplaceable => ErrorCode::NotReplaceable,
+            MempoolError::ReplacementFeeTooLow(_) => ErrorCode::ReplacementFeeTooLow,
+            MempoolError::DustOutput { .. } => ErrorCode::DustOutput,
+            MempoolError::TooManyAncestors(_) => ErrorCode::TooManyAncestors,
+        };
+        Self::new(code, e.to_string())
+    }
+}
+
+impl IntoResponse for RpcError {
+    fn into_response(self) -> Response {
+        (self.code.status(), Json(self.to_response())).into_response()
+    }
+}
+
+/// Answer to routes that do not exist
+pub async fn route_not_found() -> RpcError {
+    RpcError::new(ErrorCode::NotFound, "No such route")
+}
+
+/// [`Path`] rejecting malformed segments with the error envelope
+pub struct ApiPath<T>(pub T);
+
+#[axum::async_trait]
+impl<T, S> FromRequestParts<S> for ApiPath<T>
+where
+    T: DeserializeOwned + Send,
+    S: Send + Sync,
+{
+    type Rejection = RpcError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        Path::<T>::from_request_parts(parts, state).await
+            .map(|Path(value)| ApiPath(value))
+            .map_err(|e| RpcError::new(ErrorCode::InvalidRequest, e.body_text()))
+    }
+}
+
+/// [`Query`] rejecting malformed query strings with the error envelope
+pub struct ApiQuery<T>(pub T);
+
+#[axum::async_trait]
+impl<T, S> FromRequestParts<S> for ApiQuery<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = RpcError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        Query::<T>::from_request_parts(parts, state).await
+            .map(|Query(value)| ApiQuery(value))
+            .map_err(|e| RpcError::new(ErrorCode::InvalidRequest, e.body_text()))
+    }
+}
+
+/// [`Json`] body rejecting malformed JSON with the error envelope
+pub struct ApiJson<T>(pub T);
+
+#[axum::async_trait]
+impl<T, S> FromRequest<S> for ApiJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = RpcError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        Json::<T>::from_request(req, state).await
+            .map(|Json(value)| ApiJson(value))
+            .map_err(|e| RpcError::new(ErrorCode::InvalidRequest, e.body_text()))
+    }
+}
+
+/// Error response body
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: ErrorBody,
+}
+
+/// Error details
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ErrorBody {
+    /// Stable numeric code
+    pub code: u16,
+
+    /// Stable machine-readable reason
+    pub reason: String,
+
+    /// Human-readable description
+    pub message: String,
+}
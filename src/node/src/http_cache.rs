@@ -0,0 +1,86 @@
+//! RPC response compression and conditional requests
+//!
+//! Successful `GET` responses carry a weak `ETag` derived from their body. A
+//! client repeating the request with a matching `If-None-Match` gets an empty
+//! `304 Not Modified`, so explorers polling blocks or balances only pay for
+//! the bytes when something changed. Compression is negotiated separately
+//! through `Accept-Encoding` (gzip or deflate).
+
+use axum::{
+    body::{to_bytes, Body},
+    extract::Request,
+    http::{header, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+/// HTTP behaviour of the RPC server
+#[derive(Clone, Copy, Debug)]
+pub struct HttpOptions {
+    /// Compress responses for clients that accept gzip or deflate
+    pub compression: bool,
+
+    /// Add ETags and answer matching conditional requests with 304
+    pub etag: bool,
+}
+
+impl Default for HttpOptions {
+    fn default() -> Self {
+        Self {
+            compression: true,
+            etag: true,
+        }
+    }
+}
+
+/// Middleware adding an ETag to successful GET responses
+pub async fn etag(request: Request, next: Next) -> Response {
+    if request.method() != Method::GET && request.method() != Method::HEAD {
+        return next.run(request).await;
+    }
+
+    let if_none_match = request.headers().get(header::IF_NONE_MATCH).cloned();
+    let response = next.run(request).await;
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = match to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+
+    let tag = etag_for(&bytes);
+    let value = HeaderValue::from_str(&tag).expect("ETag is ASCII");
+
+    if if_none_match.as_ref().is_some_and(|v| etag_matches(v, &tag)) {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ETAG, value);
+        return (StatusCode::NOT_MODIFIED, headers).into_response();
+    }
+
+    parts.headers.insert(header::ETAG, value);
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+/// Weak ETag for a response body
+///
+/// Weak because the same entity may be sent with different encodings.
+pub fn etag_for(body: &[u8]) -> String {
+    format!("W/\"{}\"", &blake3::hash(body).to_hex()[..32])
+}
+
+/// Weak comparison of an `If-None-Match` header against an ETag
+pub fn etag_matches(if_none_match: &HeaderValue, tag: &str) -> bool {
+    let Ok(value) = if_none_match.to_str() else {
+        return false;
+    };
+
+    let opaque = |t: &str| t.trim().trim_start_matches("W/").to_string();
+    let tag = opaque(tag);
+    value.split(',').any(|candidate| {
+        let candidate = candidate.trim();
+        candidate == "*" || opaque(candidate) == tag
+    })
+}
@@ -3,9 +3,16 @@
 //! Full node implementation for Aequitas blockchain.
 
 pub mod config;
+pub mod error;
+pub mod http_cache;
 pub mod rpc;
 pub mod mempool;
+pub mod metrics;
+pub mod stats;
 pub mod storage;
+pub mod sync;
 
 pub use config::NodeConfig;
-pub use mempool::Mempool;
+pub use error::{ErrorCode, RpcError};
+pub use mempool::{Mempool, MempoolDump};
+pub use stats::NodeStats;
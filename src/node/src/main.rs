@@ -2,13 +2,19 @@
 //!
 //! Run a full Aequitas blockchain node.
 
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand};
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
 use std::sync::Arc;
 use tokio::sync::RwLock;
-use aequitas_node::{NodeConfig, Mempool};
+use aequitas_node::{NodeConfig, Mempool, MempoolDump, NodeStats};
 use aequitas_node::rpc::{create_router, RpcState};
-use aequitas_core::Blockchain;
+use aequitas_node::stats::STATS_SAVE_INTERVAL;
+use aequitas_node::sync::{TipMonitor, STALE_CHECK_INTERVAL};
+use aequitas_core::{Blockchain, ChainParams, CheckLevel, LoadStatus};
+use aequitas_network::{HandshakeMsg, NetworkCommand};
+use aequitas_network::node::NetworkEvent;
 
 #[derive(Parser)]
 #[command(name = "aequitas-node")]
@@ -34,6 +40,10 @@ struct Cli {
     /// P2P address override
     #[arg(long)]
     p2p_addr: Option<String>,
+    
+    /// Stored history validated again on load: quick, difficulty or full
+    #[arg(long, default_value_t = CheckLevel::default())]
+    check_level: CheckLevel,
 }
 
 #[derive(Subcommand)]
@@ -53,6 +63,45 @@ enum Commands {
     
     /// Show node status
     Status,
+    
+    /// Import blocks from another node's chain file
+    ImportBlocks {
+        /// Chain file to read blocks from
+        file: PathBuf,
+    },
+    
+    /// Capture or replay a node's mempool
+    Mempool {
+        #[command(subcommand)]
+        command: MempoolCommand,
+    },
+    
+    /// Print shell completions to stdout
+    Completions {
+        /// Shell to generate completions for
+        shell: clap_complete::Shell,
+    },
+}
+
+#[derive(Subcommand)]
+enum MempoolCommand {
+    /// Save the running node's mempool to a file
+    Dump {
+        /// Output file
+        #[arg(short, long, default_value = "mempool-dump.json")]
+        output: PathBuf,
+    },
+    
+    /// Replay a dump against a node, reporting what it accepts and rejects
+    Load {
+        /// Dump file
+        #[arg(short, long, default_value = "mempool-dump.json")]
+        input: PathBuf,
+        
+        /// Target node RPC URL (defaults to the configured node)
+        #[arg(short, long)]
+        node: Option<String>,
+    },
 }
 
 #[tokio::main]
@@ -65,6 +114,9 @@ async fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
     
     match cli.command {
+        Some(Commands::Completions { shell }) => {
+            clap_complete::generate(shell, &mut Cli::command(), "aequitas-node", &mut std::io::stdout());
+        }
         Some(Commands::Init { output }) => {
             init_config(&output)?;
         }
@@ -74,6 +126,19 @@ async fn main() -> anyhow::Result<()> {
         Some(Commands::Status) => {
             show_status(&cli.config).await?;
         }
+        Some(Commands::ImportBlocks { ref file }) => {
+            import_blocks(&cli, file)?;
+        }
+        Some(Commands::Mempool { command: MempoolCommand::Dump { ref output } }) => {
+            dump_mempool(&rpc_url(&cli)?, output).await?;
+        }
+        Some(Commands::Mempool { command: MempoolCommand::Load { ref input, ref node } }) => {
+            let node = match node {
+                Some(node) => node.clone(),
+                None => rpc_url(&cli)?,
+            };
+            load_mempool(input, &node).await?;
+        }
         Some(Commands::Run) | None => {
             run_node(&cli).await?;
         }
@@ -153,9 +218,82 @@ async fn show_status(config_path: &PathBuf) -> anyhow::Result<()> {
     Ok(())
 }
 
-/// Run the node
-async fn run_node(cli: &Cli) -> anyhow::Result<()> {
-    // Load or create config
+/// RPC URL of the configured node
+fn rpc_url(cli: &Cli) -> anyhow::Result<String> {
+    let config = if cli.config.exists() {
+        NodeConfig::load(&cli.config)?
+    } else {
+        NodeConfig::default()
+    };
+    let addr = cli.rpc_addr.clone().unwrap_or(config.rpc_addr);
+    Ok(format!("http://{}", addr))
+}
+
+/// Save a node's mempool to a file
+async fn dump_mempool(node: &str, output: &PathBuf) -> anyhow::Result<()> {
+    let dump: MempoolDump = reqwest::Client::new()
+        .get(format!("{}/mempool/dump", node))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    
+    dump.save(output)?;
+    println!("✓ Saved {} transactions at height {} to {}", dump.entries.len(), dump.height, output.display());
+    
+    Ok(())
+}
+
+/// Replay a mempool dump against a node
+///
+/// Transactions are submitted oldest first, as they originally arrived, and
+/// rejections are grouped by error reason to compare mempool policies.
+async fn load_mempool(input: &PathBuf, node: &str) -> anyhow::Result<()> {
+    let dump = MempoolDump::load(input)?;
+    println!(
+        "\n🔁 Replaying {} transactions (height {}, node v{}) against {}\n",
+        dump.entries.len(),
+        dump.height,
+        dump.node_version,
+        node
+    );
+    
+    let client = reqwest::Client::new();
+    let mut accepted = 0usize;
+    let mut rejected: HashMap<String, usize> = HashMap::new();
+    
+    for entry in &dump.entries {
+        let resp = client.post(format!("{}/tx/send", node))
+            .json(&serde_json::json!({ "tx_hex": entry.tx_hex }))
+            .send()
+            .await?;
+        
+        if resp.status().is_success() {
+            accepted += 1;
+            continue;
+        }
+        
+        let body: serde_json::Value = resp.json().await.unwrap_or_default();
+        let reason = body["error"]["reason"].as_str().unwrap_or("unknown").to_string();
+        log::debug!("{} rejected: {}", entry.txid, body["error"]["message"]);
+        *rejected.entry(reason).or_insert(0) += 1;
+    }
+    
+    println!("  ✅ Accepted: {}", accepted);
+    println!("  ❌ Rejected: {}", rejected.values().sum::<usize>());
+    let mut reasons: Vec<_> = rejected.into_iter().collect();
+    reasons.sort_by(|a, b| b.1.cmp(&a.1));
+    for (reason, count) in reasons {
+        println!("     {:<28} {}", reason, count);
+    }
+    println!();
+    
+    Ok(())
+}
+
+/// Load the config file (or defaults) with CLI overrides applied
+fn load_config(cli: &Cli) -> anyhow::Result<NodeConfig> {
     let mut config = if cli.config.exists() {
         NodeConfig::load(&cli.config)?
     } else {
@@ -176,6 +314,88 @@ async fn run_node(cli: &Cli) -> anyhow::Result<()> {
     
     // Validate config
     config.validate()?;
+    Ok(config)
+}
+
+/// Load the chain file, or create a new chain if there is none
+///
+/// A loaded chain has its history validated again up to `check_level`.
+fn open_chain(chain_path: &std::path::Path, params: ChainParams, check_level: CheckLevel) -> anyhow::Result<Blockchain> {
+    if !chain_path.exists() && !aequitas_core::storage::backup_path(chain_path).exists() {
+        log::info!("Creating new {} blockchain...", params.network);
+        let chain = Blockchain::with_params(params);
+        let _ = chain.save(chain_path);
+        return Ok(chain);
+    }
+    
+    log::info!("Loading blockchain from {}...", chain_path.display());
+    let (mut chain, status) = Blockchain::load_verified(chain_path)?;
+    if chain.get_block_at_height(0).map(|b| b.header.difficulty) != Some(params.genesis_difficulty) {
+        anyhow::bail!(
+            "{} does not belong to {}: its genesis difficulty differs",
+            chain_path.display(),
+            params.network
+        );
+    }
+    chain.set_params(params);
+    if let LoadStatus::Recovered { reason } = status {
+        log::warn!("═══════════════════════════════════════════════════════");
+        log::warn!("⚠️  Chain data was corrupted: {}", reason);
+        log::warn!("⚠️  Rolled back to height {}, re-syncing the rest from peers", chain.height());
+        log::warn!("═══════════════════════════════════════════════════════");
+        chain.save(chain_path)?;
+    }
+    
+    if check_level > CheckLevel::Quick {
+        log::info!("Verifying stored history (check level: {})...", check_level);
+        chain.verify_history(check_level).map_err(|e| anyhow::anyhow!(
+            "{} failed {} checks: {}. Remove it to re-sync from peers",
+            chain_path.display(),
+            check_level,
+            e
+        ))?;
+    }
+    Ok(chain)
+}
+
+/// Import blocks above the local tip from another chain file
+///
+/// Each block goes through full validation, its difficulty recomputed from
+/// the block times already accumulated rather than taken from its header.
+fn import_blocks(cli: &Cli, file: &std::path::Path) -> anyhow::Result<()> {
+    let config = load_config(cli)?;
+    std::fs::create_dir_all(&config.data_dir)?;
+    let chain_path = config.data_dir.join("blockchain.dat");
+    let mut chain = open_chain(&chain_path, config.chain_params()?, cli.check_level)?;
+    chain.set_checkpoints(config.checkpoints()?);
+    
+    let source = Blockchain::load(file)?;
+    println!("📥 Importing blocks {}..={} from {}", chain.height() + 1, source.height(), file.display());
+    let headers: Vec<_> = (chain.height() + 1..=source.height())
+        .filter_map(|height| source.get_block_at_height(height))
+        .map(|block| block.header.clone())
+        .collect();
+    chain.learn_assumed_valid(&headers);
+    
+    let mut imported = 0u64;
+    for height in chain.height() + 1..=source.height() {
+        let block = source.get_block_at_height(height)
+            .ok_or_else(|| anyhow::anyhow!("{} has no block at height {}", file.display(), height))?;
+        if let Err(e) = chain.add_block(block.clone()) {
+            chain.save(&chain_path)?;
+            anyhow::bail!("Block {} rejected after importing {}: {}", height, imported, e);
+        }
+        imported += 1;
+    }
+    
+    chain.save(&chain_path)?;
+    println!("✅ Imported {} block(s), height now {}", imported, chain.height());
+    Ok(())
+}
+
+/// Run the node
+async fn run_node(cli: &Cli) -> anyhow::Result<()> {
+    let config = load_config(cli)?;
     
     // Print banner
     print_banner();
@@ -192,16 +412,15 @@ async fn run_node(cli: &Cli) -> anyhow::Result<()> {
     // Initialize blockchain
     log::info!("Initializing blockchain...");
     let chain_path = config.data_dir.join("blockchain.dat");
-    let blockchain = if chain_path.exists() {
-        log::info!("Loading blockchain from {}...", chain_path.display());
-        Arc::new(RwLock::new(Blockchain::load(&chain_path)?))
-    } else {
-        log::info!("Creating new blockchain...");
-        let chain = Blockchain::new();
-        let _ = chain.save(&chain_path);
-        Arc::new(RwLock::new(chain))
-    };
-    let mempool = Arc::new(RwLock::new(Mempool::new()));
+    let mut chain = open_chain(&chain_path, config.chain_params()?, cli.check_level)?;
+    chain.set_checkpoints(config.checkpoints()?);
+    if config.address_index && !chain.has_address_index() {
+        log::info!("Building address history index...");
+        chain.enable_address_index();
+    }
+    let blockchain = Arc::new(RwLock::new(chain));
+    let mempool = Arc::new(RwLock::new(Mempool::with_dust_threshold(config.dust_threshold)));
+    let stats = Arc::new(RwLock::new(NodeStats::open(&config.data_dir)));
     
     {
         let chain = blockchain.read().await;
@@ -210,20 +429,24 @@ async fn run_node(cli: &Cli) -> anyhow::Result<()> {
     }
     
     // Create broadcast channel for RPC -> P2P propagation
-    let (p2p_broadcast_tx, mut p2p_broadcast_rx) = tokio::sync::mpsc::channel(100);
+    let (p2p_broadcast_tx, p2p_broadcast_rx) = tokio::sync::mpsc::channel(100);
 
     // Start P2P network
     let p2p_config = aequitas_network::node::NodeConfig {
         listen_addr: config.p2p_addr.parse().unwrap_or_else(|_| "/ip4/0.0.0.0/tcp/23420".parse().unwrap()),
         bootstrap_peers: Vec::new(),
         testnet: config.network == "testnet",
-        enable_mdns: true,
+        // Regtest nodes stay private unless peers are configured explicitly
+        enable_mdns: config.network != "regtest",
+        min_protocol_version: config.min_protocol_version,
     };
     
     let mut p2p_node = aequitas_network::Node::new(p2p_config);
     let mut net_events = p2p_node.take_event_receiver().unwrap();
     let net_state = p2p_node.state.clone();
 
+    let stale_tip = Arc::new(AtomicBool::new(false));
+
     // Start RPC server
     if config.rpc_enabled {
         let rpc_state = Arc::new(RpcState {
@@ -232,9 +455,12 @@ async fn run_node(cli: &Cli) -> anyhow::Result<()> {
             broadcast_tx: p2p_broadcast_tx.clone(),
             chain_path: chain_path.clone(),
             net_state: net_state.clone(),
+            templates: RwLock::new(HashMap::new()),
+            stale_tip: stale_tip.clone(),
+            stats: stats.clone(),
         });
         
-        let router = create_router(rpc_state);
+        let router = create_router(rpc_state, config.http_options());
         let rpc_addr = config.rpc_addr.clone();
         
         tokio::spawn(async move {
@@ -257,28 +483,143 @@ async fn run_node(cli: &Cli) -> anyhow::Result<()> {
     let blockchain_ev = blockchain.clone();
     let mempool_ev = mempool.clone();
     let chain_path_ev = chain_path.clone();
+    let stats_ev = stats.clone();
+    let net_commands = p2p_broadcast_tx.clone();
+    let testnet = config.network == "testnet";
     tokio::spawn(async move {
         while let Some(event) = net_events.recv().await {
             match event {
-                aequitas_network::node::NetworkEvent::NewBlock(block) => {
+                NetworkEvent::PeerConnected(_) => {
+                    let (handshake, from_height) = {
+                        let chain = blockchain_ev.read().await;
+                        let handshake = HandshakeMsg::new(chain.height(), chain.tip(), chain.total_work(), testnet);
+                        (handshake, chain.height() + 1)
+                    };
+                    let _ = net_commands.send(NetworkCommand::Handshake(handshake)).await;
+                    // Catch up with whatever the new peer has beyond our tip
+                    let _ = net_commands.send(NetworkCommand::RequestBlocks { from_height }).await;
+                }
+                NetworkEvent::BlocksRequested { from_height, max_blocks } => {
+                    let blocks: Vec<_> = {
+                        let chain = blockchain_ev.read().await;
+                        let to = from_height.saturating_add(max_blocks.max(1) as u64 - 1);
+                        chain.blocks_in_range(from_height, to)
+                            .map(|blocks| blocks.cloned().collect())
+                            .unwrap_or_default()
+                    };
+                    if !blocks.is_empty() {
+                        let _ = net_commands.send(NetworkCommand::SendBlocks(blocks)).await;
+                    }
+                }
+                NetworkEvent::SyncBlocks(blocks) => {
+                    let received = blocks.len();
+                    let mut applied = 0;
+                    let mut chain = blockchain_ev.write().await;
+                    let headers: Vec<_> = blocks.iter().map(|block| block.header.clone()).collect();
+                    chain.learn_assumed_valid(&headers);
+                    for block in blocks {
+                        if block.header.height <= chain.height() {
+                            continue;
+                        }
+                        if let Err(e) = chain.add_block(block.clone()) {
+                            log::warn!("Invalid sync block received: {}", e);
+                            stats_ev.write().await.record_invalid_block();
+                            break;
+                        }
+                        mempool_ev.write().await.remove_for_block(&block);
+                        applied += 1;
+                    }
+                    stats_ev.write().await.record_blocks(applied);
+                    if applied > 0 {
+                        log::info!("Synced {} block(s), height now {}", applied, chain.height());
+                        let _ = chain.save(&chain_path_ev);
+                        if received as u32 >= aequitas_network::messages::MAX_BLOCKS_PER_MSG {
+                            let from_height = chain.height() + 1;
+                            let _ = net_commands.send(NetworkCommand::RequestBlocks { from_height }).await;
+                        }
+                    }
+                }
+                NetworkEvent::NewBlock(block) => {
                     log::info!("Received block {} via P2P", hex::encode(block.hash()));
                     let mut chain = blockchain_ev.write().await;
-                    if let Err(e) = chain.add_block(block) {
+                    if let Err(e) = chain.add_block(block.clone()) {
                         log::warn!("Invalid block received: {}", e);
+                        stats_ev.write().await.record_invalid_block();
                     } else {
+                        mempool_ev.write().await.remove_for_block(&block);
+                        stats_ev.write().await.record_blocks(1);
                         let _ = chain.save(&chain_path_ev);
                     }
                 }
-                aequitas_network::node::NetworkEvent::NewTransaction(tx) => {
+                NetworkEvent::NewTransaction(tx) => {
                     log::info!("Received transaction {} via P2P", hex::encode(tx.hash()));
+                    let chain = blockchain_ev.read().await;
+                    if let Err(e) = chain.validate_governance(&tx) {
+                        log::debug!("Ignoring invalid governance transaction: {}", e);
+                        continue;
+                    }
+                    if let Err(e) = chain.check_final(&tx) {
+                        log::debug!("Ignoring transaction: {}", e);
+                        continue;
+                    }
                     let mut pool = mempool_ev.write().await;
-                    let _ = pool.add(tx, 0);
+                    let Some(fee) = tx.fee(&pool.with_confirmed(&*chain)) else {
+                        log::debug!("Ignoring transaction spending unknown outputs");
+                        continue;
+                    };
+                    drop(chain);
+                    let _ = pool.add(tx, fee);
                 }
                 _ => {}
             }
         }
     });
 
+    // Watch for a tip that stopped advancing while peers are ahead
+    let blockchain_mon = blockchain.clone();
+    let net_state_mon = net_state.clone();
+    let net_commands_mon = p2p_broadcast_tx.clone();
+    tokio::spawn(async move {
+        let height = blockchain_mon.read().await.height();
+        let mut monitor = TipMonitor::new(height, stale_tip);
+        let mut interval = tokio::time::interval(STALE_CHECK_INTERVAL);
+        loop {
+            interval.tick().await;
+            let height = blockchain_mon.read().await.height();
+            let best_peer_height = net_state_mon.read().await.best_peer_height();
+            let was_stale = monitor.is_stale();
+            
+            if monitor.check(height, best_peer_height) {
+                if !was_stale {
+                    log::warn!("═══════════════════════════════════════════════════════");
+                    log::warn!(
+                        "⚠️  STALE TIP: height {} has not advanced for {}s, peers report height {}",
+                        height,
+                        monitor.tip_age().as_secs(),
+                        best_peer_height.unwrap_or_default()
+                    );
+                    log::warn!("⚠️  Re-syncing from peers");
+                    log::warn!("═══════════════════════════════════════════════════════");
+                }
+                let _ = net_commands_mon.send(NetworkCommand::RequestBlocks { from_height: height + 1 }).await;
+            } else if was_stale {
+                log::info!("✓ Tip advancing again at height {}", height);
+            }
+        }
+    });
+
+    // Persist lifetime statistics
+    let stats_save = stats.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(STATS_SAVE_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = stats_save.read().await.save() {
+                log::warn!("Could not save node statistics: {}", e);
+            }
+        }
+    });
+    
     // Main loop
     log::info!("Node is running and public! Press Ctrl+C to stop.");
     
@@ -286,6 +627,9 @@ async fn run_node(cli: &Cli) -> anyhow::Result<()> {
     tokio::signal::ctrl_c().await?;
     
     log::info!("Shutting down...");
+    if let Err(e) = stats.read().await.save() {
+        log::warn!("Could not save node statistics: {}", e);
+    }
     
     Ok(())
 }
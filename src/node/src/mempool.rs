@@ -1,7 +1,10 @@
 //! Transaction mempool
 
-use std::collections::HashMap;
-use aequitas_core::{Transaction, Address};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use aequitas_core::{Block, Transaction, TxOutput, TxType, Address, UtxoId, UtxoView, DUST_THRESHOLD, MAX_BLOCK_SIZE, MAX_BLOCK_TXS};
 
 /// Maximum mempool size in transactions
 pub const MAX_MEMPOOL_SIZE: usize = 10000;
@@ -9,6 +12,24 @@ pub const MAX_MEMPOOL_SIZE: usize = 10000;
 /// Maximum transaction age in seconds
 pub const MAX_TX_AGE: i64 = 3600; // 1 hour
 
+/// Bytes reserved in a block for the header and coinbase transaction
+pub const COINBASE_RESERVED_SIZE: usize = 1000;
+
+/// Minimum fee a replacement must add on top of the fees it evicts
+pub const MIN_REPLACEMENT_FEE_BUMP: u64 = 1000;
+
+/// Double-spend attempts kept for `/mempool/conflicts`
+pub const MAX_RECORDED_DOUBLE_SPENDS: usize = 1000;
+
+/// Most blocks ahead fee rates are estimated for
+pub const FEE_ESTIMATE_MAX_BLOCKS: usize = 24;
+
+/// Version of the mempool dump format
+pub const MEMPOOL_DUMP_VERSION: u32 = 1;
+
+/// Most pending ancestors a transaction may have
+pub const MAX_ANCESTORS: usize = 25;
+
 /// Transaction pool entry
 #[derive(Clone, Debug)]
 pub struct MempoolEntry {
@@ -26,14 +47,22 @@ pub struct MempoolEntry {
     
     /// Size in bytes
     pub size: usize,
+    
+    /// Pending transactions it spends from, directly or not
+    pub ancestors: Vec<[u8; 32]>,
+    
+    /// Fee of the transaction and its pending ancestors
+    pub ancestor_fee: u64,
+    
+    /// Size of the transaction and its pending ancestors
+    pub ancestor_size: usize,
 }
 
 impl MempoolEntry {
     /// Create new mempool entry
     pub fn new(transaction: Transaction, fee: u64) -> Self {
-        let serialized = bincode::serialize(&transaction).unwrap_or_default();
-        let size = serialized.len();
-        let fee_per_byte = if size > 0 { fee as f64 / size as f64 } else { 0.0 };
+        let size = transaction.size();
+        let fee_per_byte = transaction.fee_rate(fee);
         
         Self {
             transaction,
@@ -41,9 +70,18 @@ impl MempoolEntry {
             fee,
             fee_per_byte,
             size,
+            ancestors: Vec::new(),
+            ancestor_fee: fee,
+            ancestor_size: size,
         }
     }
     
+    /// Fee per byte of the transaction and its pending ancestors, the rate
+    /// a miner gets by confirming them all (child pays for parent)
+    pub fn package_fee_rate(&self) -> f64 {
+        self.ancestor_fee as f64 / self.ancestor_size.max(1) as f64
+    }
+    
     /// Check if expired
     pub fn is_expired(&self) -> bool {
         let now = chrono::Utc::now().timestamp();
@@ -51,6 +89,86 @@ impl MempoolEntry {
     }
 }
 
+/// Why a pending transaction was left out of a block
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SkipReason {
+    /// Not enough block space left once higher fee rates were taken
+    BlockFull,
+    
+    /// The block already holds the most transactions allowed
+    TooManyTransactions,
+    
+    /// Spends outputs of a pending transaction, which must be mined first
+    UnconfirmedParent,
+}
+
+impl SkipReason {
+    /// Machine-readable name
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SkipReason::BlockFull => "block_full",
+            SkipReason::TooManyTransactions => "too_many_transactions",
+            SkipReason::UnconfirmedParent => "unconfirmed_parent",
+        }
+    }
+}
+
+/// Transactions picked for the next block, by descending fee rate
+#[derive(Debug, Default)]
+pub struct BlockSelection<'a> {
+    /// Selected entries, in block order
+    pub selected: Vec<&'a MempoolEntry>,
+    
+    /// Entries left out, in fee rate order
+    pub skipped: Vec<(&'a MempoolEntry, SkipReason)>,
+    
+    /// Fees of the selected entries
+    pub fees: u64,
+    
+    /// Encoded size of the selected entries
+    pub size: usize,
+    
+    /// Space available to transactions
+    pub max_size: usize,
+}
+
+/// A transaction seen spending outputs a pending one already spends
+#[derive(Clone, Debug)]
+pub struct DoubleSpend {
+    /// Pending transaction that spent the outputs first
+    pub original: [u8; 32],
+    
+    /// Transaction spending them again
+    pub conflicting: [u8; 32],
+    
+    /// Outputs both transactions spend
+    pub outputs: Vec<([u8; 32], u32)>,
+    
+    /// Recipients of the original that the conflicting transaction no
+    /// longer pays: the payments being double-spent
+    pub victims: Vec<Address>,
+    
+    /// The conflicting transaction replaced the original (replace-by-fee)
+    pub replaced: bool,
+    
+    /// Unix time the conflict was seen
+    pub detected_at: i64,
+}
+
+/// Confirmed outputs with those of pending transactions on top
+///
+/// Prices transactions spending outputs not mined yet.
+pub struct PendingOutputs<'a, V> {
+    confirmed: &'a V,
+    mempool: &'a Mempool,
+}
+
+impl<V: UtxoView> UtxoView for PendingOutputs<'_, V> {
+    fn utxo(&self, id: &UtxoId) -> Option<&TxOutput> {
+        self.confirmed.utxo(id).or_else(|| self.mempool.output(id))
+    }
+}
+
 /// Transaction mempool
 pub struct Mempool {
     /// Transactions indexed by hash
@@ -58,24 +176,89 @@ pub struct Mempool {
     
     /// Transaction hashes by sender
     by_sender: HashMap<Address, Vec<[u8; 32]>>,
+    
+    /// Pending transaction spending each output
+    spent_by: HashMap<([u8; 32], u32), [u8; 32]>,
+    
+    /// Smallest output value accepted
+    dust_threshold: u64,
+    
+    /// Latest double-spend attempts, oldest first
+    double_spends: VecDeque<DoubleSpend>,
+    
+    /// Double-spend alerts for subscribers
+    alerts: broadcast::Sender<DoubleSpend>,
 }
 
 impl Mempool {
     /// Create new mempool
     pub fn new() -> Self {
+        Self::with_dust_threshold(DUST_THRESHOLD)
+    }
+    
+    /// Create a mempool refusing outputs below `dust_threshold`
+    pub fn with_dust_threshold(dust_threshold: u64) -> Self {
         Self {
             transactions: HashMap::new(),
             by_sender: HashMap::new(),
+            spent_by: HashMap::new(),
+            dust_threshold,
+            double_spends: VecDeque::new(),
+            alerts: broadcast::channel(256).0,
+        }
+    }
+    
+    /// Smallest output value accepted
+    pub fn dust_threshold(&self) -> u64 {
+        self.dust_threshold
+    }
+    
+    /// Output `id` of a pending transaction
+    pub fn output(&self, id: &UtxoId) -> Option<&TxOutput> {
+        self.transactions.get(&id.tx_hash)?.transaction.outputs.get(id.output_index as usize)
+    }
+    
+    /// `confirmed` outputs with those of pending transactions, to compute
+    /// the fee of a transaction spending unconfirmed outputs
+    pub fn with_confirmed<'a, V: UtxoView>(&'a self, confirmed: &'a V) -> PendingOutputs<'a, V> {
+        PendingOutputs { confirmed, mempool: self }
+    }
+    
+    /// Pending transactions `tx` spends from, directly or through others
+    pub fn ancestors_of(&self, tx: &Transaction) -> Vec<[u8; 32]> {
+        let mut ancestors: Vec<[u8; 32]> = Vec::new();
+        for input in &tx.inputs {
+            let Some(parent) = self.transactions.get(&input.prev_tx_hash) else { continue };
+            for hash in parent.ancestors.iter().chain(std::iter::once(&input.prev_tx_hash)) {
+                if !ancestors.contains(hash) {
+                    ancestors.push(*hash);
+                }
+            }
         }
+        ancestors
+    }
+    
+    /// Pending transactions spending from any of `hashes`, directly or not
+    pub fn descendants_of(&self, hashes: &[[u8; 32]]) -> Vec<[u8; 32]> {
+        self.transactions.iter()
+            .filter(|(hash, entry)| !hashes.contains(hash) && entry.ancestors.iter().any(|a| hashes.contains(a)))
+            .map(|(hash, _)| *hash)
+            .collect()
     }
     
     /// Add transaction to mempool
+    ///
+    /// `fee` is computed over confirmed and pending outputs (see
+    /// [`with_confirmed`](Self::with_confirmed)): a transaction may spend the
+    /// outputs of pending ones, up to `MAX_ANCESTORS` of them. Its package
+    /// fee rate, with those ancestors, is what ranks their block inclusion.
+    ///
+    /// A transaction spending the same outputs as pending ones replaces them
+    /// if they all opted in to replace-by-fee and it pays more: at least the
+    /// fees evicted with them and their descendants plus
+    /// `MIN_REPLACEMENT_FEE_BUMP`, at a higher fee rate. Every conflict is
+    /// recorded as a [`DoubleSpend`], accepted or not.
     pub fn add(&mut self, tx: Transaction, fee: u64) -> Result<(), MempoolError> {
-        // Check size limit
-        if self.transactions.len() >= MAX_MEMPOOL_SIZE {
-            return Err(MempoolError::MempoolFull);
-        }
-        
         let hash = tx.hash();
         
         // Check for duplicate
@@ -85,17 +268,180 @@ impl Mempool {
         
         // Validate transaction
         tx.validate().map_err(|e| MempoolError::InvalidTransaction(e.to_string()))?;
+        self.check_dust(&tx)?;
+        
+        let mut entry = MempoolEntry::new(tx, fee);
+        let conflicts = self.conflicts(&entry.transaction);
+        
+        // Check size limit (a replacement always frees a slot)
+        if conflicts.is_empty() && self.transactions.len() >= MAX_MEMPOOL_SIZE {
+            return Err(MempoolError::MempoolFull);
+        }
+        
+        let ancestors = self.ancestors_of(&entry.transaction);
+        if ancestors.len() > MAX_ANCESTORS {
+            return Err(MempoolError::TooManyAncestors(MAX_ANCESTORS));
+        }
+        let evicted: HashSet<[u8; 32]> = conflicts.iter().copied().chain(self.descendants_of(&conflicts)).collect();
+        if ancestors.iter().any(|hash| evicted.contains(hash)) {
+            return Err(MempoolError::InvalidTransaction("spends outputs of a transaction it replaces".to_string()));
+        }
+        
+        let replacement = self.check_replacement(&conflicts, &entry);
+        for original in &conflicts {
+            self.record_double_spend(original, &entry.transaction, replacement.is_ok());
+        }
+        replacement?;
+        for replaced in &conflicts {
+            log::info!(
+                "♻️  Mempool: {} replaced by {} (fee {} -> {})",
+                hex::encode(replaced),
+                hex::encode(hash),
+                self.transactions.get(replaced).map(|e| e.fee).unwrap_or(0),
+                entry.fee
+            );
+            for descendant in self.remove_with_descendants(replaced).into_iter().skip(1) {
+                log::info!("🗑️  Mempool: evicted {}, it spends from a replaced transaction", hex::encode(descendant));
+            }
+        }
+        
+        for ancestor in &ancestors {
+            if let Some(parent) = self.transactions.get(ancestor) {
+                entry.ancestor_fee = entry.ancestor_fee.saturating_add(parent.fee);
+                entry.ancestor_size = entry.ancestor_size.saturating_add(parent.size);
+            }
+        }
+        entry.ancestors = ancestors;
         
         // Add to mempool
-        let entry = MempoolEntry::new(tx.clone(), fee);
+        for input in &entry.transaction.inputs {
+            self.spent_by.insert((input.prev_tx_hash, input.output_index), hash);
+        }
         self.transactions.insert(hash, entry);
         
         Ok(())
     }
     
+    /// Refuse outputs below the dust threshold (coinbase outputs are exempt)
+    fn check_dust(&self, tx: &Transaction) -> Result<(), MempoolError> {
+        if tx.tx_type == TxType::Coinbase {
+            return Ok(());
+        }
+        // Zero-value memo outputs carry data, not value
+        let dust = (0..tx.outputs.len())
+            .find(|&i| tx.outputs[i].is_dust(self.dust_threshold) && !tx.is_memo_output(i));
+        match dust {
+            Some(index) => Err(MempoolError::DustOutput { index, threshold: self.dust_threshold }),
+            None => Ok(()),
+        }
+    }
+    
+    /// Pending transactions spending any of the same outputs as `tx`
+    pub fn conflicts(&self, tx: &Transaction) -> Vec<[u8; 32]> {
+        let mut conflicts: Vec<[u8; 32]> = tx.inputs.iter()
+            .filter_map(|input| self.spent_by.get(&(input.prev_tx_hash, input.output_index)).copied())
+            .collect();
+        conflicts.sort();
+        conflicts.dedup();
+        conflicts
+    }
+    
+    /// Record and announce `tx` spending outputs of the pending `original`
+    fn record_double_spend(&mut self, original: &[u8; 32], tx: &Transaction, replaced: bool) {
+        let Some(existing) = self.transactions.get(original) else { return };
+        let outputs = tx.inputs.iter()
+            .map(|input| (input.prev_tx_hash, input.output_index))
+            .filter(|key| self.spent_by.get(key) == Some(original))
+            .collect();
+        let victims = existing.transaction.outputs.iter()
+            .map(|output| &output.recipient)
+            .filter(|recipient| !tx.outputs.iter().any(|o| o.recipient == **recipient))
+            .fold(Vec::new(), |mut victims: Vec<Address>, recipient| {
+                if !victims.contains(recipient) {
+                    victims.push(recipient.clone());
+                }
+                victims
+            });
+        
+        let double_spend = DoubleSpend {
+            original: *original,
+            conflicting: tx.hash(),
+            outputs,
+            victims,
+            replaced,
+            detected_at: chrono::Utc::now().timestamp(),
+        };
+        log::warn!(
+            "⚠️  Mempool: double-spend of {} by {} ({})",
+            hex::encode(original),
+            hex::encode(double_spend.conflicting),
+            if replaced { "replaced" } else { "rejected" }
+        );
+        
+        if self.double_spends.len() >= MAX_RECORDED_DOUBLE_SPENDS {
+            self.double_spends.pop_front();
+        }
+        let _ = self.alerts.send(double_spend.clone());
+        self.double_spends.push_back(double_spend);
+    }
+    
+    /// Latest double-spend attempts, oldest first
+    pub fn double_spends(&self) -> impl Iterator<Item = &DoubleSpend> {
+        self.double_spends.iter()
+    }
+    
+    /// Receive double-spend attempts as they are seen
+    pub fn subscribe_double_spends(&self) -> broadcast::Receiver<DoubleSpend> {
+        self.alerts.subscribe()
+    }
+    
+    /// Check that `entry` may evict the conflicting transactions, and the
+    /// pending transactions spending from them
+    fn check_replacement(&self, conflicts: &[[u8; 32]], entry: &MempoolEntry) -> Result<(), MempoolError> {
+        let mut replaced_fees = 0u64;
+        for hash in conflicts {
+            let Some(existing) = self.transactions.get(hash) else { continue };
+            if !existing.transaction.replaceable {
+                return Err(MempoolError::NotReplaceable);
+            }
+            if entry.fee_per_byte <= existing.fee_per_byte {
+                let required = replaced_fees.saturating_add(existing.fee).saturating_add(MIN_REPLACEMENT_FEE_BUMP);
+                return Err(MempoolError::ReplacementFeeTooLow(required));
+            }
+            replaced_fees = replaced_fees.saturating_add(existing.fee);
+        }
+        
+        let descendant_fees = self.descendants_of(conflicts).iter()
+            .filter_map(|hash| self.transactions.get(hash))
+            .fold(0u64, |total, e| total.saturating_add(e.fee));
+        let required = replaced_fees.saturating_add(descendant_fees).saturating_add(MIN_REPLACEMENT_FEE_BUMP);
+        if !conflicts.is_empty() && entry.fee < required {
+            return Err(MempoolError::ReplacementFeeTooLow(required));
+        }
+        
+        Ok(())
+    }
+    
     /// Remove transaction
+    ///
+    /// Transactions spending from it stay, no longer counting it as an
+    /// ancestor: removing a mined transaction confirms their inputs.
     pub fn remove(&mut self, hash: &[u8; 32]) -> Option<MempoolEntry> {
-        self.transactions.remove(hash)
+        let entry = self.transactions.remove(hash)?;
+        for input in &entry.transaction.inputs {
+            let key = (input.prev_tx_hash, input.output_index);
+            if self.spent_by.get(&key) == Some(hash) {
+                self.spent_by.remove(&key);
+            }
+        }
+        for descendant in self.transactions.values_mut() {
+            if let Some(pos) = descendant.ancestors.iter().position(|a| a == hash) {
+                descendant.ancestors.remove(pos);
+                descendant.ancestor_fee = descendant.ancestor_fee.saturating_sub(entry.fee);
+                descendant.ancestor_size = descendant.ancestor_size.saturating_sub(entry.size);
+            }
+        }
+        Some(entry)
     }
     
     /// Get transaction
@@ -114,33 +460,154 @@ impl Mempool {
     }
     
     /// Get transactions for block (sorted by fee)
+    ///
+    /// The selection never exceeds the consensus block limits, leaving room
+    /// for the coinbase transaction.
     pub fn get_for_block(&self, max_size: usize) -> Vec<Transaction> {
-        let mut entries: Vec<_> = self.transactions.values().collect();
+        self.select_for_block(max_size)
+            .selected
+            .into_iter()
+            .map(|entry| entry.transaction.clone())
+            .collect()
+    }
+    
+    /// Run the block selection, keeping track of what was left out and why
+    ///
+    /// Only transactions spending confirmed outputs can go in a block. Each
+    /// is ranked by the best of its own fee rate and the package fee rate
+    /// of any pending descendant, so that a child paying for its parent
+    /// gets the parent mined.
+    pub fn select_for_block(&self, max_size: usize) -> BlockSelection<'_> {
+        let mut scores: HashMap<[u8; 32], f64> = self.transactions.iter()
+            .map(|(hash, entry)| (*hash, entry.fee_per_byte))
+            .collect();
+        for entry in self.transactions.values() {
+            let rate = entry.package_fee_rate();
+            for ancestor in &entry.ancestors {
+                if let Some(score) = scores.get_mut(ancestor) {
+                    *score = score.max(rate);
+                }
+            }
+        }
+        let mut entries: Vec<(f64, &MempoolEntry)> = self.transactions.iter()
+            .map(|(hash, entry)| (scores[hash], entry))
+            .collect();
         
-        // Sort by fee per byte (descending)
-        entries.sort_by(|a, b| b.fee_per_byte.partial_cmp(&a.fee_per_byte).unwrap());
+        // Sort by score (descending)
+        entries.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
         
-        let mut result = Vec::new();
-        let mut total_size = 0;
+        let max_size = max_size.min(MAX_BLOCK_SIZE - COINBASE_RESERVED_SIZE);
+        let max_txs = MAX_BLOCK_TXS - 1;
         
-        for entry in entries {
-            if total_size + entry.size <= max_size {
-                result.push(entry.transaction.clone());
-                total_size += entry.size;
+        let mut selection = BlockSelection {
+            max_size,
+            ..Default::default()
+        };
+        for (_, entry) in entries {
+            if !entry.ancestors.is_empty() {
+                selection.skipped.push((entry, SkipReason::UnconfirmedParent));
+            } else if selection.selected.len() >= max_txs {
+                selection.skipped.push((entry, SkipReason::TooManyTransactions));
+            } else if selection.size + entry.size > max_size {
+                selection.skipped.push((entry, SkipReason::BlockFull));
+            } else {
+                selection.size += entry.size;
+                selection.fees = selection.fees.saturating_add(entry.fee);
+                selection.selected.push(entry);
             }
         }
         
-        result
+        selection
+    }
+    
+    /// Fee rates at `percentiles` of the pending transactions, by count
+    ///
+    /// Empty when the pool is.
+    pub fn fee_rate_percentiles(&self, percentiles: &[u8]) -> Vec<f64> {
+        let mut rates: Vec<f64> = self.transactions.values().map(|e| e.fee_per_byte).collect();
+        if rates.is_empty() {
+            return Vec::new();
+        }
+        rates.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        
+        percentiles.iter()
+            .map(|&p| rates[(rates.len() - 1) * p.min(100) as usize / 100])
+            .collect()
+    }
+    
+    /// Fee rate, in whole units per byte, a new transaction needs to be
+    /// picked within each of 1 to `max_blocks` blocks
+    ///
+    /// The pool is projected into full blocks by descending fee rate, as
+    /// [`select_for_block`](Self::select_for_block) fills one. Within as many
+    /// blocks as the pool fits in, any rate does and the estimate is 0;
+    /// otherwise it just beats the first transaction left out.
+    pub fn target_fee_rates(&self, max_blocks: usize) -> Vec<u64> {
+        let mut entries: Vec<_> = self.transactions.values().collect();
+        entries.sort_by(|a, b| b.fee_per_byte.partial_cmp(&a.fee_per_byte).unwrap());
+        
+        let max_size = MAX_BLOCK_SIZE - COINBASE_RESERVED_SIZE;
+        let max_txs = MAX_BLOCK_TXS - 1;
+        
+        let mut pending = entries.into_iter().peekable();
+        let mut rates = Vec::with_capacity(max_blocks);
+        for _ in 0..max_blocks {
+            let (mut size, mut count) = (0, 0);
+            while let Some(entry) = pending.next_if(|e| count < max_txs && size + e.size <= max_size) {
+                size += entry.size;
+                count += 1;
+            }
+            rates.push(pending.peek().map_or(0, |entry| entry.fee_per_byte.floor() as u64 + 1));
+        }
+        rates
     }
     
     /// Remove confirmed transactions
     pub fn remove_confirmed(&mut self, tx_hashes: &[[u8; 32]]) {
         for hash in tx_hashes {
-            self.transactions.remove(hash);
+            self.remove(hash);
+        }
+    }
+    
+    /// Drop what a newly accepted block makes invalid
+    ///
+    /// The block's transactions leave the pool, and so does every pending
+    /// transaction spending an output the block already spends, together
+    /// with its descendants. Returns how many conflicting transactions were
+    /// evicted.
+    pub fn remove_for_block(&mut self, block: &Block) -> usize {
+        let confirmed: Vec<[u8; 32]> = block.transactions.iter().map(|tx| tx.hash()).collect();
+        self.remove_confirmed(&confirmed);
+        
+        let conflicting: Vec<[u8; 32]> = block.transactions.iter()
+            .flat_map(|tx| &tx.inputs)
+            .filter_map(|input| self.spent_by.get(&(input.prev_tx_hash, input.output_index)).copied())
+            .collect();
+        let mut evicted = 0;
+        for hash in conflicting {
+            for removed in self.remove_with_descendants(&hash) {
+                log::info!("🗑️  Mempool: evicted {}, its inputs are spent by a block", hex::encode(removed));
+                evicted += 1;
+            }
+        }
+        evicted
+    }
+    
+    /// Remove a transaction and every pending transaction built on it,
+    /// returning the hashes removed, `hash` first
+    fn remove_with_descendants(&mut self, hash: &[u8; 32]) -> Vec<[u8; 32]> {
+        let mut pending = vec![*hash];
+        let mut removed = Vec::new();
+        while let Some(hash) = pending.pop() {
+            let Some(entry) = self.remove(&hash) else { continue };
+            removed.push(hash);
+            pending.extend((0..entry.transaction.outputs.len() as u32)
+                .filter_map(|index| self.spent_by.get(&(hash, index)).copied()));
         }
+        removed
     }
     
-    /// Remove expired transactions
+    /// Remove expired transactions, and those spending from them
     pub fn remove_expired(&mut self) {
         let expired: Vec<[u8; 32]> = self.transactions
             .iter()
@@ -149,7 +616,7 @@ impl Mempool {
             .collect();
         
         for hash in expired {
-            self.transactions.remove(&hash);
+            self.remove_with_descendants(&hash);
         }
     }
     
@@ -160,13 +627,103 @@ impl Mempool {
     
     /// Get total fees
     pub fn total_fees(&self) -> u64 {
-        self.transactions.values().map(|e| e.fee).sum()
+        self.transactions.values().fold(0u64, |total, e| total.saturating_add(e.fee))
     }
     
     /// Clear mempool
     pub fn clear(&mut self) {
         self.transactions.clear();
         self.by_sender.clear();
+        self.spent_by.clear();
+    }
+    
+    /// Snapshot every pending transaction, oldest first
+    pub fn dump(&self, height: u64) -> MempoolDump {
+        let mut entries: Vec<_> = self.transactions.iter().collect();
+        entries.sort_by_key(|(hash, entry)| (entry.added_at, **hash));
+        
+        MempoolDump {
+            version: MEMPOOL_DUMP_VERSION,
+            height,
+            created_at: chrono::Utc::now().timestamp(),
+            node_version: env!("CARGO_PKG_VERSION").to_string(),
+            entries: entries.into_iter()
+                .map(|(hash, entry)| DumpedTransaction {
+                    txid: hex::encode(hash),
+                    tx_hex: hex::encode(entry.transaction.encode()),
+                    fee: entry.fee,
+                    size: entry.size,
+                    fee_per_byte: entry.fee_per_byte,
+                    added_at: entry.added_at,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Mempool snapshot, replayable against another node
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MempoolDump {
+    /// Dump format version
+    pub version: u32,
+    
+    /// Chain height when the dump was taken
+    pub height: u64,
+    
+    /// Unix time of the dump
+    pub created_at: i64,
+    
+    /// Software version of the dumping node
+    pub node_version: String,
+    
+    /// Pending transactions, oldest first
+    pub entries: Vec<DumpedTransaction>,
+}
+
+/// A pending transaction and its mempool metadata
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DumpedTransaction {
+    /// Transaction id, hex
+    pub txid: String,
+    
+    /// Canonical encoding, hex (as accepted by `/tx/send`)
+    pub tx_hex: String,
+    
+    /// Fee in smallest units
+    pub fee: u64,
+    
+    /// Size in bytes
+    pub size: usize,
+    
+    /// Fee per byte
+    pub fee_per_byte: f64,
+    
+    /// Time added to the mempool
+    pub added_at: i64,
+}
+
+impl DumpedTransaction {
+    /// Decode the transaction
+    pub fn transaction(&self) -> anyhow::Result<Transaction> {
+        let bytes = hex::decode(&self.tx_hex)?;
+        Transaction::decode(&bytes).map_err(|e| anyhow::anyhow!("{}: {}", self.txid, e))
+    }
+}
+
+impl MempoolDump {
+    /// Load a dump file
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let dump: Self = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        if dump.version != MEMPOOL_DUMP_VERSION {
+            anyhow::bail!("Unsupported mempool dump version {}", dump.version);
+        }
+        Ok(dump)
+    }
+    
+    /// Write the dump to a file
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
     }
 }
 
@@ -190,4 +747,16 @@ pub enum MempoolError {
     
     #[error("Insufficient fee")]
     InsufficientFee,
+    
+    #[error("Conflicts with a pending transaction that is not replaceable")]
+    NotReplaceable,
+    
+    #[error("Replacement fee too low: need at least {0} at a higher fee rate")]
+    ReplacementFeeTooLow(u64),
+    
+    #[error("Output {index} is below the dust threshold of {threshold}")]
+    DustOutput { index: usize, threshold: u64 },
+    
+    #[error("Spends from more than {0} pending transactions")]
+    TooManyAncestors(usize),
 }
@@ -0,0 +1,61 @@
+//! Prometheus metrics
+//!
+//! `/metrics` serves the node's gauges and counters in the Prometheus text
+//! exposition format, so a scraper can track peers, chain height and mempool
+//! size without parsing the JSON API.
+
+use std::fmt::Write;
+use aequitas_network::node::{NetworkState, PeerDirection};
+
+/// Content type of the text exposition format
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Node figures exported besides the network state
+pub struct NodeMetrics {
+    pub height: u64,
+    pub difficulty: u64,
+    pub mempool_size: usize,
+    pub stale_tip: bool,
+    pub seconds_since_tip: u64,
+}
+
+/// Render every metric in the text exposition format
+pub fn render(node: &NodeMetrics, net: &NetworkState) -> String {
+    let mut out = String::new();
+
+    metric(&mut out, "aequitas_chain_height", "gauge", "Height of the chain tip", node.height);
+    metric(&mut out, "aequitas_chain_difficulty", "gauge", "Difficulty of the next block", node.difficulty);
+    metric(&mut out, "aequitas_mempool_transactions", "gauge", "Transactions waiting in the mempool", node.mempool_size as u64);
+    metric(&mut out, "aequitas_stale_tip", "gauge", "1 while the tip is stale", node.stale_tip as u64);
+    metric(&mut out, "aequitas_seconds_since_tip", "gauge", "Seconds since the tip block's timestamp", node.seconds_since_tip);
+
+    metric(&mut out, "aequitas_peers_connected", "gauge", "Connected peers", net.connected_peers.len() as u64);
+    header(&mut out, "aequitas_peers_by_direction", "gauge", "Connected peers per connection direction");
+    for direction in [PeerDirection::Inbound, PeerDirection::Outbound] {
+        let _ = writeln!(
+            out,
+            "aequitas_peers_by_direction{{direction=\"{}\"}} {}",
+            direction.as_str(),
+            net.direction_count(direction)
+        );
+    }
+    header(&mut out, "aequitas_peers_by_version", "gauge", "Connected peers per protocol version");
+    for (version, peers) in net.version_spread() {
+        let version = version.map_or("unknown".to_string(), |v| v.to_string());
+        let _ = writeln!(out, "aequitas_peers_by_version{{version=\"{}\"}} {}", version, peers);
+    }
+    metric(&mut out, "aequitas_peer_connections_total", "counter", "Peers connected since start", net.total_connections);
+    metric(&mut out, "aequitas_peer_disconnections_total", "counter", "Peers disconnected since start", net.total_disconnections);
+
+    out
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn metric(out: &mut String, name: &str, kind: &str, help: &str, value: u64) {
+    header(out, name, kind, help);
+    let _ = writeln!(out, "{} {}", name, value);
+}
@@ -1,50 +1,178 @@
 //! RPC API server
+//!
+//! Routes under `/v1/` form the public, read-only data API. Their paths and
+//! response fields are stable: fields may be added within `v1`, but never
+//! renamed or removed. Breaking changes go to a new `/v2/` prefix while `/v1/`
+//! keeps being served. Unprefixed routes are internal and may change at any
+//! time. The `v1` surface is described by `/v1/openapi.json`.
+//!
+//! Failures on every route use the error envelope from [`crate::error`].
+//! Compression and ETag handling are configured through
+//! [`HttpOptions`](crate::http_cache::HttpOptions).
 
 use axum::{
     routing::{get, post},
     Router, Json,
     extract::State,
-    http::StatusCode,
+    extract::ws::{Message, WebSocket, WebSocketUpgrade},
+    http::header,
+    response::{IntoResponse, Response},
 };
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tokio::sync::{RwLock, mpsc};
-use aequitas_core::{Blockchain, Block, BlockHeader, Transaction, Address};
-use aequitas_network::node::NetworkState;
-use crate::mempool::Mempool;
+use tokio::sync::{broadcast, RwLock, mpsc};
+use aequitas_core::blockchain::UtxoId;
+use aequitas_core::{Blockchain, Block, ChainError, Transaction, Address, Network, Proposal, RegisteredMiner, identity_commitment};
+use aequitas_consensus::AequiHash;
+use aequitas_network::node::{NetworkState, PeerDirection};
+use aequitas_network::{NetworkCommand, NewBlockMsg, VersionStatus};
+use aequitas_network::messages::PROTOCOL_VERSION;
+use crate::mempool::{DoubleSpend, Mempool, MempoolDump, MempoolEntry, FEE_ESTIMATE_MAX_BLOCKS};
+use crate::error::{self, ApiJson, ApiPath, ApiQuery, ErrorCode, ErrorBody, ErrorResponse, RpcError};
+use crate::http_cache::{self, HttpOptions};
+use crate::metrics::{self, NodeMetrics};
+use crate::stats::NodeStats;
+use crate::sync::ChainHealth;
 
 /// RPC server state
 pub struct RpcState {
     pub blockchain: Arc<RwLock<Blockchain>>,
     pub mempool: Arc<RwLock<Mempool>>,
-    pub broadcast_tx: mpsc::Sender<Block>,
+    pub broadcast_tx: mpsc::Sender<NetworkCommand>,
     pub chain_path: std::path::PathBuf,
     pub net_state: Arc<RwLock<NetworkState>>,
+    pub templates: RwLock<HashMap<String, PendingTemplate>>,
+    /// Set while the tip is stale (see `sync::TipMonitor`)
+    pub stale_tip: Arc<AtomicBool>,
+    /// Lifetime statistics (see `stats`)
+    pub stats: Arc<RwLock<NodeStats>>,
 }
 
+use tower_http::compression::CompressionLayer;
 use tower_http::cors::{CorsLayer, Any};
+use utoipa::{IntoParams, OpenApi, ToSchema};
+
+/// Public API version served under the `/v1/` prefix
+pub const API_VERSION: &str = "v1";
+
+/// OpenAPI description of the `/v1/` public data API
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Aequitas Node Public API", version = "1.0.0"),
+    paths(
+        get_info,
+        get_block,
+        get_block_by_height,
+        get_blocks,
+        get_transaction,
+        get_balance,
+        get_balance_details,
+        get_mempool,
+        get_fee_estimates,
+        get_peers,
+        get_proposals,
+        get_proposal,
+        get_solidarity_registry,
+    ),
+    components(schemas(
+        InfoResponse,
+        HealthResponse,
+        BlockResponse,
+        BlocksPageResponse,
+        TxResponse,
+        BalanceResponse,
+        BalanceDetailsResponse,
+        AddressUtxoResponse,
+        PendingTxResponse,
+        MempoolResponse,
+        FeeEstimateResponse,
+        FeeRatePercentile,
+        FeeTarget,
+        PeersResponse,
+        PeerResponse,
+        VersionSpread,
+        ProposalResponse,
+        TallyResponse,
+        RegistryResponse,
+        RegisteredMinerResponse,
+        ErrorResponse,
+        ErrorBody,
+    ))
+)]
+pub struct PublicApiDoc;
+
+/// Read-only public routes (mounted under `/v1/`)
+fn public_routes() -> Router<Arc<RpcState>> {
+    Router::new()
+        .route("/info", get(get_info))
+        .route("/block/:hash", get(get_block))
+        .route("/block/height/:height", get(get_block_by_height))
+        .route("/blocks", get(get_blocks))
+        .route("/tx/:hash", get(get_transaction))
+        .route("/balance/:address", get(get_balance))
+        .route("/balance/:address/details", get(get_balance_details))
+        .route("/mempool", get(get_mempool))
+        .route("/fees/estimate", get(get_fee_estimates))
+        .route("/peers", get(get_peers))
+        .route("/governance/proposals", get(get_proposals))
+        .route("/governance/proposal/:id", get(get_proposal))
+        .route("/solidarity/registry", get(get_solidarity_registry))
+        .route("/openapi.json", get(get_openapi))
+}
 
 /// Create RPC router
-pub fn create_router(state: Arc<RpcState>) -> Router {
+pub fn create_router(state: Arc<RpcState>, options: HttpOptions) -> Router {
     let cors = CorsLayer::new()
         .allow_origin(Any)
         .allow_methods(Any)
         .allow_headers(Any);
 
-    Router::new()
+    let mut router = Router::new()
         .route("/", get(index))
         .route("/info", get(get_info))
         .route("/block/:hash", get(get_block))
         .route("/block/height/:height", get(get_block_by_height))
+        .route("/blocks", get(get_blocks))
+        .route("/blocks/transactions", get(get_block_transactions))
         .route("/tx/:hash", get(get_transaction))
         .route("/balance/:address", get(get_balance))
+        .route("/balance/:address/details", get(get_balance_details))
         .route("/mempool", get(get_mempool))
+        .route("/mempool/dump", get(dump_mempool))
+        .route("/mempool/conflicts", get(get_mempool_conflicts))
+        .route("/fees/estimate", get(get_fee_estimates))
         .route("/tx/send", post(send_transaction))
         .route("/peers", get(get_peers))
+        .route("/governance/proposals", get(get_proposals))
+        .route("/governance/proposal/:id", get(get_proposal))
+        .route("/solidarity/registry", get(get_solidarity_registry))
+        .route("/ws/peers", get(stream_peer_events))
+        .route("/ws/double-spends", get(stream_double_spends))
+        .route("/metrics", get(get_metrics))
+        .route("/stats/node", get(get_node_stats))
+        .route("/stats/utxo", get(get_utxo_stats))
         .route("/getblocktemplate", post(get_block_template))
+        .route("/getblocktemplate/preview", get(preview_block_template))
         .route("/submitblock", post(submit_block))
-        .layer(cors)
-        .with_state(state)
+        .route("/generate", post(generate_blocks))
+        .route("/invalidateblock", post(invalidate_block))
+        .route("/reconsiderblock", post(reconsider_block))
+        .route("/invalidatedblocks", get(get_invalidated_blocks))
+        .route("/getdeploymentinfo", get(get_deployment_info))
+        .route("/epoch/:epoch/program", get(get_epoch_program))
+        .nest(&format!("/{}", API_VERSION), public_routes())
+        .fallback(error::route_not_found);
+
+    if options.etag {
+        router = router.layer(axum::middleware::from_fn(http_cache::etag));
+    }
+    if options.compression {
+        router = router.layer(CompressionLayer::new());
+    }
+
+    router.layer(cors).with_state(state)
 }
 
 /// Index endpoint
@@ -52,8 +180,13 @@ async fn index() -> &'static str {
     "Aequitas Node RPC v0.1.0"
 }
 
+/// Get the OpenAPI document for the public API
+async fn get_openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(PublicApiDoc::openapi())
+}
+
 /// Node info response
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct InfoResponse {
     version: String,
     network: String,
@@ -61,32 +194,228 @@ struct InfoResponse {
     difficulty: u64,
     mempool_size: usize,
     peers: usize,
+    /// Tip has not advanced for a while although peers are ahead
+    stale_tip: bool,
+    health: HealthResponse,
+}
+
+/// Sync and block production health
+#[derive(Serialize, ToSchema)]
+struct HealthResponse {
+    /// No peer is ahead and the tip is not stale
+    is_synced: bool,
+    /// Best height reported by peers, if any are connected
+    expected_height: Option<u64>,
+    blocks_behind: u64,
+    seconds_since_tip: u64,
+    /// No block for several target block times, peers or not
+    production_stalled: bool,
+}
+
+impl From<ChainHealth> for HealthResponse {
+    fn from(health: ChainHealth) -> Self {
+        Self {
+            is_synced: health.is_synced,
+            expected_height: health.expected_height,
+            blocks_behind: health.blocks_behind,
+            seconds_since_tip: health.seconds_since_tip,
+            production_stalled: health.production_stalled,
+        }
+    }
 }
 
 /// Get node info
+#[utoipa::path(get, path = "/v1/info", responses((status = 200, body = InfoResponse)))]
 async fn get_info(State(state): State<Arc<RpcState>>) -> Json<InfoResponse> {
     let chain = state.blockchain.read().await;
     let mempool = state.mempool.read().await;
     let net = state.net_state.read().await;
     
+    let stale_tip = state.stale_tip.load(Ordering::Relaxed);
+    let health = ChainHealth::new(chain.height(), chain.seconds_since_tip(), net.best_peer_height(), stale_tip);
+    
     Json(InfoResponse {
         version: "0.1.0".to_string(),
-        network: "testnet".to_string(),
+        network: chain.params().network.to_string(),
         height: chain.height(),
         difficulty: chain.difficulty(),
         mempool_size: mempool.size(),
         peers: net.connected_peers.len(),
+        stale_tip,
+        health: health.into(),
     })
 }
 
+/// Connected peers response
+#[derive(Serialize, ToSchema)]
+struct PeersResponse {
+    /// Protocol version of this node
+    protocol_version: u32,
+    /// Peers below this version are disconnected
+    min_protocol_version: u32,
+    peers: Vec<PeerResponse>,
+    /// Peer count per protocol version
+    versions: Vec<VersionSpread>,
+}
+
+/// Peer response
+#[derive(Serialize, ToSchema)]
+struct PeerResponse {
+    id: String,
+    addr: Option<String>,
+    /// Protocol version, once the peer's handshake has been received
+    version: Option<u32>,
+    user_agent: Option<String>,
+    /// Best height the peer has reported
+    height: Option<u64>,
+    /// inbound or outbound
+    #[schema(value_type = String)]
+    direction: PeerDirection,
+    /// Seconds since the peer connected
+    connected_secs: u64,
+}
+
+/// Number of peers on one protocol version
+#[derive(Serialize, ToSchema)]
+struct VersionSpread {
+    /// Protocol version (`null` before the handshake)
+    version: Option<u32>,
+    peers: usize,
+    /// current, newer, deprecated, unsupported or unknown
+    status: String,
+}
+
 /// Get connected peers
-async fn get_peers(State(state): State<Arc<RpcState>>) -> Json<Vec<aequitas_network::node::PeerInfoSimple>> {
+#[utoipa::path(get, path = "/v1/peers", responses((status = 200, body = PeersResponse)))]
+async fn get_peers(State(state): State<Arc<RpcState>>) -> Json<PeersResponse> {
     let net = state.net_state.read().await;
-    Json(net.connected_peers.clone())
+    let min_version = net.min_protocol_version;
+    
+    let versions = net.version_spread().into_iter().map(|(version, peers)| VersionSpread {
+        version,
+        peers,
+        status: version
+            .map(|v| VersionStatus::check(v, min_version).as_str())
+            .unwrap_or("unknown")
+            .to_string(),
+    }).collect();
+    
+    Json(PeersResponse {
+        protocol_version: PROTOCOL_VERSION,
+        min_protocol_version: min_version,
+        peers: net.connected_peers.iter().map(|p| PeerResponse {
+            id: p.id.clone(),
+            addr: p.addr.clone(),
+            version: p.version,
+            user_agent: p.user_agent.clone(),
+            height: p.height,
+            direction: p.direction,
+            connected_secs: p.connected_secs(),
+        }).collect(),
+        versions,
+    })
 }
 
-/// Block response
+/// Stream peer connections and disconnections as JSON text messages
+async fn stream_peer_events(State(state): State<Arc<RpcState>>, ws: WebSocketUpgrade) -> Response {
+    let events = state.net_state.read().await.subscribe();
+    ws.on_upgrade(move |socket| forward_peer_events(socket, events))
+}
+
+async fn forward_peer_events(mut socket: WebSocket, mut events: broadcast::Receiver<aequitas_network::node::PeerEvent>) {
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Ok(event) => {
+                    let Ok(text) = serde_json::to_string(&event) else { continue };
+                    if socket.send(Message::Text(text)).await.is_err() {
+                        break;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    log::debug!("Peer event subscriber lagged, {} event(s) dropped", missed);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+/// Prometheus metrics
+async fn get_metrics(State(state): State<Arc<RpcState>>) -> Response {
+    let node = {
+        let chain = state.blockchain.read().await;
+        NodeMetrics {
+            height: chain.height(),
+            difficulty: chain.difficulty(),
+            mempool_size: state.mempool.read().await.size(),
+            stale_tip: state.stale_tip.load(Ordering::Relaxed),
+            seconds_since_tip: chain.seconds_since_tip(),
+        }
+    };
+    let body = metrics::render(&node, &*state.net_state.read().await);
+    ([(header::CONTENT_TYPE, metrics::CONTENT_TYPE)], body).into_response()
+}
+
+/// Lifetime node statistics response
+#[derive(Serialize)]
+struct NodeStatsResponse {
+    blocks_processed: u64,
+    reorgs: u64,
+    invalid_blocks: u64,
+    restarts: u64,
+    /// Seconds running, all sessions together
+    uptime_secs: u64,
+    /// Unix time of the first start
+    first_start: i64,
+    /// Unix time this session started
+    session_started_at: i64,
+    session_uptime_secs: u64,
+}
+
+/// Get lifetime node statistics
+async fn get_node_stats(State(state): State<Arc<RpcState>>) -> Json<NodeStatsResponse> {
+    let stats = state.stats.read().await;
+    let counters = stats.counters();
+    Json(NodeStatsResponse {
+        blocks_processed: counters.blocks_processed,
+        reorgs: counters.reorgs,
+        invalid_blocks: counters.invalid_blocks,
+        restarts: counters.restarts,
+        uptime_secs: counters.uptime_secs,
+        first_start: counters.first_start,
+        session_started_at: stats.started_at(),
+        session_uptime_secs: stats.session_secs(),
+    })
+}
+
+/// UTXO set statistics response
 #[derive(Serialize)]
+struct UtxoStatsResponse {
+    height: u64,
+    /// Unspent outputs
+    count: usize,
+    /// Memory held by the set, in bytes
+    size_bytes: usize,
+}
+
+/// Get the size of the UTXO set
+async fn get_utxo_stats(State(state): State<Arc<RpcState>>) -> Json<UtxoStatsResponse> {
+    let chain = state.blockchain.read().await;
+    let stats = chain.utxo_stats();
+    Json(UtxoStatsResponse {
+        height: chain.height(),
+        count: stats.count,
+        size_bytes: stats.size_bytes,
+    })
+}
+
+/// Block response
+#[derive(Serialize, ToSchema)]
 struct BlockResponse {
     hash: String,
     height: u64,
@@ -95,6 +424,8 @@ struct BlockResponse {
     difficulty: u64,
     nonce: u64,
     tx_count: usize,
+    /// Header and transaction sizes, as templates count them
+    weight: usize,
 }
 
 impl From<&Block> for BlockResponse {
@@ -107,86 +438,392 @@ impl From<&Block> for BlockResponse {
             difficulty: block.header.difficulty,
             nonce: block.header.nonce,
             tx_count: block.transactions.len(),
+            weight: block.weight(),
         }
     }
 }
 
 /// Get block by hash
+#[utoipa::path(
+    get,
+    path = "/v1/block/{hash}",
+    params(("hash" = String, Path, description = "Block hash (hex)")),
+    responses(
+        (status = 200, body = BlockResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 404, body = ErrorResponse)
+    )
+)]
 async fn get_block(
     State(state): State<Arc<RpcState>>,
-    axum::extract::Path(hash): axum::extract::Path<String>,
-) -> Result<Json<BlockResponse>, StatusCode> {
-    let hash_bytes = hex::decode(&hash).map_err(|_| StatusCode::BAD_REQUEST)?;
-    if hash_bytes.len() != 32 {
-        return Err(StatusCode::BAD_REQUEST);
-    }
-    
-    let mut hash_arr = [0u8; 32];
-    hash_arr.copy_from_slice(&hash_bytes);
-    
+    ApiPath(hash): ApiPath<String>,
+) -> Result<Json<BlockResponse>, RpcError> {
+    let hash_arr = parse_hash(&hash)?;
     let chain = state.blockchain.read().await;
     
     chain.get_block(&hash_arr)
         .map(|b| Json(BlockResponse::from(b)))
-        .ok_or(StatusCode::NOT_FOUND)
+        .ok_or_else(|| RpcError::new(ErrorCode::BlockNotFound, format!("Block {} not found", hash)))
+}
+
+/// Parse a 32-byte hex hash
+fn parse_hash(hash: &str) -> Result<[u8; 32], RpcError> {
+    let hash_bytes = hex::decode(hash)
+        .map_err(|e| RpcError::new(ErrorCode::InvalidHash, format!("Invalid hash: {}", e)))?;
+    
+    hash_bytes.try_into()
+        .map_err(|_| RpcError::new(ErrorCode::InvalidHash, "Hash must be 32 bytes"))
 }
 
 /// Get block by height
+#[utoipa::path(
+    get,
+    path = "/v1/block/height/{height}",
+    params(("height" = u64, Path, description = "Block height")),
+    responses((status = 200, body = BlockResponse), (status = 404, body = ErrorResponse))
+)]
 async fn get_block_by_height(
     State(state): State<Arc<RpcState>>,
-    axum::extract::Path(height): axum::extract::Path<u64>,
-) -> Result<Json<BlockResponse>, StatusCode> {
+    ApiPath(height): ApiPath<u64>,
+) -> Result<Json<BlockResponse>, RpcError> {
     let chain = state.blockchain.read().await;
     
     chain.get_block_at_height(height)
         .map(|b| Json(BlockResponse::from(b)))
-        .ok_or(StatusCode::NOT_FOUND)
+        .ok_or_else(|| RpcError::new(ErrorCode::BlockNotFound, format!("No block at height {}", height)))
 }
 
-/// Transaction response
+/// Blocks returned per page when no limit is given
+const DEFAULT_BLOCKS_PAGE: u64 = 100;
+
+/// Page of consecutive blocks
+#[derive(Deserialize, IntoParams)]
+struct BlocksQuery {
+    /// First height (default 0)
+    from: Option<u64>,
+    /// Number of heights to cover, at most `MAX_BLOCK_RANGE` (default 100)
+    limit: Option<u64>,
+}
+
+/// Page of blocks
+#[derive(Serialize, ToSchema)]
+struct BlocksPageResponse {
+    blocks: Vec<BlockResponse>,
+    /// `from` of the next page, absent on the last one
+    next: Option<u64>,
+}
+
+/// List main-chain blocks by height
+#[utoipa::path(
+    get,
+    path = "/v1/blocks",
+    params(BlocksQuery),
+    responses((status = 200, body = BlocksPageResponse), (status = 400, body = ErrorResponse))
+)]
+async fn get_blocks(
+    State(state): State<Arc<RpcState>>,
+    ApiQuery(query): ApiQuery<BlocksQuery>,
+) -> Result<Json<BlocksPageResponse>, RpcError> {
+    let from = query.from.unwrap_or(0);
+    let limit = query.limit.unwrap_or(DEFAULT_BLOCKS_PAGE);
+    if limit == 0 {
+        return Err(RpcError::new(ErrorCode::InvalidRequest, "limit must be at least 1"));
+    }
+    
+    let chain = state.blockchain.read().await;
+    let to = from.saturating_add(limit - 1);
+    let blocks = chain.blocks_in_range(from, to)
+        .map_err(|e| RpcError::new(ErrorCode::RangeTooLarge, e.to_string()))?
+        .map(BlockResponse::from)
+        .collect();
+    
+    Ok(Json(BlocksPageResponse {
+        blocks,
+        next: (to < chain.height()).then_some(to + 1),
+    }))
+}
+
+/// Block with its transactions in canonical encoding
 #[derive(Serialize)]
+struct BlockTransactionsResponse {
+    height: u64,
+    hash: String,
+    /// Hex, in block order
+    transactions: Vec<String>,
+}
+
+/// Page of blocks with their transactions
+#[derive(Serialize)]
+struct BlockTransactionsPageResponse {
+    blocks: Vec<BlockTransactionsResponse>,
+    /// `from` of the next page, absent on the last one
+    next: Option<u64>,
+}
+
+/// List main-chain blocks with their raw transactions, for wallet rescans
+async fn get_block_transactions(
+    State(state): State<Arc<RpcState>>,
+    ApiQuery(query): ApiQuery<BlocksQuery>,
+) -> Result<Json<BlockTransactionsPageResponse>, RpcError> {
+    let from = query.from.unwrap_or(0);
+    let limit = query.limit.unwrap_or(DEFAULT_BLOCKS_PAGE);
+    if limit == 0 {
+        return Err(RpcError::new(ErrorCode::InvalidRequest, "limit must be at least 1"));
+    }
+    
+    let chain = state.blockchain.read().await;
+    let to = from.saturating_add(limit - 1);
+    let blocks = chain.blocks_in_range(from, to)
+        .map_err(|e| RpcError::new(ErrorCode::RangeTooLarge, e.to_string()))?
+        .map(|block| BlockTransactionsResponse {
+            height: block.header.height,
+            hash: hex::encode(block.hash()),
+            transactions: block.transactions.iter().map(|tx| hex::encode(tx.encode())).collect(),
+        })
+        .collect();
+    
+    Ok(Json(BlockTransactionsPageResponse {
+        blocks,
+        next: (to < chain.height()).then_some(to + 1),
+    }))
+}
+
+/// Transaction response
+#[derive(Serialize, ToSchema)]
 struct TxResponse {
     hash: String,
     inputs: usize,
     outputs: usize,
+    /// Time of its block, or when the mempool accepted it
     timestamp: i64,
+    /// Block holding it, absent while pending
+    height: Option<u64>,
+    /// Blocks from its own to the tip, 0 while pending
+    confirmations: u64,
+    /// Waiting in the mempool
+    in_mempool: bool,
 }
 
 /// Get transaction
+#[utoipa::path(
+    get,
+    path = "/v1/tx/{hash}",
+    params(("hash" = String, Path, description = "Transaction hash (hex)")),
+    responses(
+        (status = 200, body = TxResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 404, body = ErrorResponse)
+    )
+)]
 async fn get_transaction(
-    State(_state): State<Arc<RpcState>>,
-    axum::extract::Path(hash): axum::extract::Path<String>,
-) -> Result<Json<TxResponse>, StatusCode> {
-    // TODO: Implement transaction lookup
-    Err(StatusCode::NOT_FOUND)
+    State(state): State<Arc<RpcState>>,
+    ApiPath(hash): ApiPath<String>,
+) -> Result<Json<TxResponse>, RpcError> {
+    let not_found = || RpcError::new(ErrorCode::TxNotFound, format!("Transaction {} not found", hash));
+    let tx_hash: [u8; 32] = hex::decode(&hash)
+        .map_err(|e| RpcError::new(ErrorCode::InvalidHex, format!("Invalid hex: {}", e)))?
+        .try_into()
+        .map_err(|_| RpcError::new(ErrorCode::InvalidHex, "Transaction hash must be 32 bytes"))?;
+    
+    if let Some(entry) = state.mempool.read().await.get(&tx_hash) {
+        return Ok(Json(TxResponse {
+            hash: hex::encode(tx_hash),
+            inputs: entry.transaction.inputs.len(),
+            outputs: entry.transaction.outputs.len(),
+            timestamp: entry.added_at,
+            height: None,
+            confirmations: 0,
+            in_mempool: true,
+        }));
+    }
+    
+    let chain = state.blockchain.read().await;
+    let height = chain.transaction_heights(&[tx_hash]).get(&tx_hash).copied().ok_or_else(not_found)?;
+    let block = chain.get_block_at_height(height).ok_or_else(not_found)?;
+    let tx = block.transactions.iter().find(|tx| tx.hash() == tx_hash).ok_or_else(not_found)?;
+    
+    Ok(Json(TxResponse {
+        hash: hex::encode(tx_hash),
+        inputs: tx.inputs.len(),
+        outputs: tx.outputs.len(),
+        timestamp: block.header.timestamp.timestamp(),
+        height: Some(height),
+        confirmations: chain.height() - height + 1,
+        in_mempool: false,
+    }))
+}
+
+/// Height a balance is read at
+#[derive(Deserialize, IntoParams)]
+struct BalanceQuery {
+    /// Balance once this block was applied (default: the tip)
+    height: Option<u64>,
 }
 
 /// Balance response
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct BalanceResponse {
     address: String,
+    /// Height the balance was read at
+    height: u64,
     balance: u64,
     balance_formatted: String,
 }
 
-/// Get balance
+/// Get balance, at the tip or as of a past height
+#[utoipa::path(
+    get,
+    path = "/v1/balance/{address}",
+    params(("address" = String, Path, description = "Aequitas address (aeq1...)"), BalanceQuery),
+    responses(
+        (status = 200, body = BalanceResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 404, body = ErrorResponse)
+    )
+)]
 async fn get_balance(
     State(state): State<Arc<RpcState>>,
-    axum::extract::Path(address): axum::extract::Path<String>,
-) -> Result<Json<BalanceResponse>, StatusCode> {
-    let addr = Address::from_string(&address).map_err(|_| StatusCode::BAD_REQUEST)?;
+    ApiPath(address): ApiPath<String>,
+    ApiQuery(query): ApiQuery<BalanceQuery>,
+) -> Result<Json<BalanceResponse>, RpcError> {
+    let addr = Address::from_string(&address)
+        .map_err(|e| RpcError::new(ErrorCode::InvalidAddress, format!("Invalid address: {}", e)))?;
     let chain = state.blockchain.read().await;
-    let balance = chain.get_balance(&addr);
+    let height = query.height.unwrap_or(chain.height());
+    if height > chain.height() {
+        return Err(RpcError::new(ErrorCode::BlockNotFound, format!("No block at height {}", height)));
+    }
+    let balance = chain.get_balance_at_height(&addr, height)
+        .ok_or_else(|| RpcError::new(
+            ErrorCode::InvalidRequest,
+            format!("No undo data back to height {} (chain bootstrapped from a snapshot)", height),
+        ))?;
     
     Ok(Json(BalanceResponse {
         address,
+        height,
         balance,
-        balance_formatted: format!("{:.9} AEQ", balance as f64 / 1_000_000_000.0),
+        balance_formatted: aequitas_core::format_amount(balance),
+    }))
+}
+
+/// Unspent output of an address
+#[derive(Serialize, ToSchema)]
+struct AddressUtxoResponse {
+    txid: String,
+    output_index: u32,
+    amount: u64,
+    /// Block holding it (absent below a pruned chain's first block)
+    height: Option<u64>,
+    /// Blocks from its own to the tip, 1 in the tip
+    confirmations: Option<u64>,
+    /// A pending transaction spends it
+    spent_pending: bool,
+}
+
+/// Pending transaction touching an address
+#[derive(Serialize, ToSchema)]
+struct PendingTxResponse {
+    txid: String,
+    /// Outputs to the address
+    incoming: u64,
+    /// Outputs of the address it spends
+    outgoing: u64,
+}
+
+/// Balance of an address split into confirmed and pending amounts
+#[derive(Serialize, ToSchema)]
+struct BalanceDetailsResponse {
+    address: String,
+    height: u64,
+    /// Sum of the unspent outputs in the chain
+    confirmed: u64,
+    /// Paid to the address by mempool transactions
+    pending_incoming: u64,
+    /// Spent from the address by mempool transactions
+    pending_outgoing: u64,
+    utxos: Vec<AddressUtxoResponse>,
+    pending: Vec<PendingTxResponse>,
+}
+
+/// Get the confirmed and pending balance of an address, with its outputs
+///
+/// A pending transaction spending an output paid by another pending one
+/// counts it both in and out, as the chain will once both are mined.
+#[utoipa::path(
+    get,
+    path = "/v1/balance/{address}/details",
+    params(("address" = String, Path, description = "Aequitas address (aeq1...)")),
+    responses((status = 200, body = BalanceDetailsResponse), (status = 400, body = ErrorResponse))
+)]
+async fn get_balance_details(
+    State(state): State<Arc<RpcState>>,
+    ApiPath(address): ApiPath<String>,
+) -> Result<Json<BalanceDetailsResponse>, RpcError> {
+    let addr = Address::from_string(&address)
+        .map_err(|e| RpcError::new(ErrorCode::InvalidAddress, format!("Invalid address: {}", e)))?;
+    let chain = state.blockchain.read().await;
+    let mempool = state.mempool.read().await;
+    
+    let mut spent_pending = HashSet::new();
+    let mut pending = Vec::new();
+    for hash in mempool.hashes() {
+        let Some(entry) = mempool.get(&hash) else { continue };
+        let tx = &entry.transaction;
+        
+        let mut outgoing = 0u64;
+        for input in &tx.inputs {
+            let spent = UtxoId::new(input.prev_tx_hash, input.output_index);
+            let output = chain.get_utxo(&spent).cloned().or_else(|| {
+                mempool.get(&input.prev_tx_hash)
+                    .and_then(|parent| parent.transaction.outputs.get(input.output_index as usize).cloned())
+            });
+            if let Some(output) = output.filter(|output| output.recipient == addr) {
+                outgoing = outgoing.saturating_add(output.amount);
+                spent_pending.insert(spent);
+            }
+        }
+        let incoming = tx.outputs.iter()
+            .filter(|output| output.recipient == addr)
+            .fold(0u64, |total, output| total.saturating_add(output.amount));
+        
+        if incoming > 0 || outgoing > 0 {
+            pending.push(PendingTxResponse { txid: hex::encode(hash), incoming, outgoing });
+        }
+    }
+    pending.sort_by(|a, b| a.txid.cmp(&b.txid));
+    
+    let utxos = chain.get_utxos_for_address(&addr);
+    let tx_hashes: Vec<[u8; 32]> = utxos.iter().map(|(id, _)| id.tx_hash).collect();
+    let heights = chain.transaction_heights(&tx_hashes);
+    let mut utxos: Vec<AddressUtxoResponse> = utxos.into_iter()
+        .map(|(id, output)| {
+            let height = heights.get(&id.tx_hash).copied();
+            AddressUtxoResponse {
+                txid: hex::encode(id.tx_hash),
+                output_index: id.output_index,
+                amount: output.amount,
+                height,
+                confirmations: height.map(|h| chain.height() - h + 1),
+                spent_pending: spent_pending.contains(&id),
+            }
+        })
+        .collect();
+    utxos.sort_by(|a, b| b.height.cmp(&a.height).then(a.txid.cmp(&b.txid)).then(a.output_index.cmp(&b.output_index)));
+    
+    Ok(Json(BalanceDetailsResponse {
+        address,
+        height: chain.height(),
+        confirmed: utxos.iter().fold(0u64, |total, utxo| total.saturating_add(utxo.amount)),
+        pending_incoming: pending.iter().fold(0u64, |total, tx| total.saturating_add(tx.incoming)),
+        pending_outgoing: pending.iter().fold(0u64, |total, tx| total.saturating_add(tx.outgoing)),
+        utxos,
+        pending,
     }))
 }
 
 /// Mempool response
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 struct MempoolResponse {
     size: usize,
     total_fees: u64,
@@ -194,6 +831,7 @@ struct MempoolResponse {
 }
 
 /// Get mempool info
+#[utoipa::path(get, path = "/v1/mempool", responses((status = 200, body = MempoolResponse)))]
 async fn get_mempool(State(state): State<Arc<RpcState>>) -> Json<MempoolResponse> {
     let mempool = state.mempool.read().await;
     
@@ -204,58 +842,398 @@ async fn get_mempool(State(state): State<Arc<RpcState>>) -> Json<MempoolResponse
     })
 }
 
+/// Percentiles of the mempool fee rates reported by `/fees/estimate`
+const FEE_RATE_PERCENTILES: [u8; 5] = [10, 25, 50, 75, 90];
+
+/// Fee rate at a percentile of the mempool
+#[derive(Serialize, ToSchema)]
+struct FeeRatePercentile {
+    percentile: u8,
+    /// Units per byte
+    fee_rate: f64,
+}
+
+/// Fee rate to be mined within a number of blocks
+#[derive(Serialize, ToSchema)]
+struct FeeTarget {
+    blocks: u32,
+    /// Whole units per byte, 0 when any fee does
+    fee_rate: u64,
+}
+
+/// Fee estimates response
+#[derive(Serialize, ToSchema)]
+struct FeeEstimateResponse {
+    mempool_size: usize,
+    percentiles: Vec<FeeRatePercentile>,
+    /// From 1 block on, in order
+    targets: Vec<FeeTarget>,
+}
+
+/// Estimate fee rates from the mempool
+///
+/// Targets project the pending transactions into blocks by fee rate, as
+/// block templates pick them.
+#[utoipa::path(get, path = "/v1/fees/estimate", responses((status = 200, body = FeeEstimateResponse)))]
+async fn get_fee_estimates(State(state): State<Arc<RpcState>>) -> Json<FeeEstimateResponse> {
+    let mempool = state.mempool.read().await;
+    
+    let percentiles = FEE_RATE_PERCENTILES.iter()
+        .zip(mempool.fee_rate_percentiles(&FEE_RATE_PERCENTILES))
+        .map(|(&percentile, fee_rate)| FeeRatePercentile { percentile, fee_rate })
+        .collect();
+    let targets = mempool.target_fee_rates(FEE_ESTIMATE_MAX_BLOCKS).into_iter()
+        .enumerate()
+        .map(|(i, fee_rate)| FeeTarget { blocks: i as u32 + 1, fee_rate })
+        .collect();
+    
+    Json(FeeEstimateResponse {
+        mempool_size: mempool.size(),
+        percentiles,
+        targets,
+    })
+}
+
+/// Snapshot the mempool (raw transactions and metadata) for replay
+async fn dump_mempool(State(state): State<Arc<RpcState>>) -> Json<MempoolDump> {
+    let height = state.blockchain.read().await.height();
+    let mempool = state.mempool.read().await;
+    
+    Json(mempool.dump(height))
+}
+
+/// Filter of double-spend queries and subscriptions
+#[derive(Deserialize)]
+struct DoubleSpendQuery {
+    /// Only conflicts double-spending a payment to this address
+    address: Option<String>,
+}
+
+impl DoubleSpendQuery {
+    fn address(&self) -> Result<Option<Address>, RpcError> {
+        self.address.as_deref()
+            .map(Address::from_string)
+            .transpose()
+            .map_err(|e| RpcError::new(ErrorCode::InvalidAddress, format!("Invalid address: {}", e)))
+    }
+}
+
+/// Double-spend attempt seen by the mempool
+#[derive(Serialize)]
+struct DoubleSpendResponse {
+    /// Pending transaction that spent the outputs first
+    original: String,
+    /// Transaction spending them again
+    conflicting: String,
+    /// Outputs spent by both, as `txid:index`
+    outputs: Vec<String>,
+    /// Recipients of the original no longer paid by the conflicting one
+    victims: Vec<String>,
+    /// The conflicting transaction replaced the original
+    replaced: bool,
+    detected_at: i64,
+}
+
+impl From<&DoubleSpend> for DoubleSpendResponse {
+    fn from(double_spend: &DoubleSpend) -> Self {
+        Self {
+            original: hex::encode(double_spend.original),
+            conflicting: hex::encode(double_spend.conflicting),
+            outputs: double_spend.outputs.iter()
+                .map(|(txid, index)| format!("{}:{}", hex::encode(txid), index))
+                .collect(),
+            victims: double_spend.victims.iter().map(|a| a.to_string()).collect(),
+            replaced: double_spend.replaced,
+            detected_at: double_spend.detected_at,
+        }
+    }
+}
+
+/// Latest double-spend attempts, oldest first
+async fn get_mempool_conflicts(
+    State(state): State<Arc<RpcState>>,
+    ApiQuery(query): ApiQuery<DoubleSpendQuery>,
+) -> Result<Json<Vec<DoubleSpendResponse>>, RpcError> {
+    let address = query.address()?;
+    let mempool = state.mempool.read().await;
+    
+    Ok(Json(mempool.double_spends()
+        .filter(|d| address.as_ref().map_or(true, |a| d.victims.contains(a)))
+        .map(DoubleSpendResponse::from)
+        .collect()))
+}
+
+/// Stream double-spend attempts as JSON text messages
+///
+/// With `?address=`, only the attempts double-spending a payment to it.
+async fn stream_double_spends(
+    State(state): State<Arc<RpcState>>,
+    ApiQuery(query): ApiQuery<DoubleSpendQuery>,
+    ws: WebSocketUpgrade,
+) -> Result<Response, RpcError> {
+    let address = query.address()?;
+    let alerts = state.mempool.read().await.subscribe_double_spends();
+    Ok(ws.on_upgrade(move |socket| forward_double_spends(socket, alerts, address)))
+}
+
+async fn forward_double_spends(
+    mut socket: WebSocket,
+    mut alerts: broadcast::Receiver<DoubleSpend>,
+    address: Option<Address>,
+) {
+    loop {
+        tokio::select! {
+            alert = alerts.recv() => match alert {
+                Ok(alert) => {
+                    if address.as_ref().is_some_and(|a| !alert.victims.contains(a)) {
+                        continue;
+                    }
+                    let Ok(text) = serde_json::to_string(&DoubleSpendResponse::from(&alert)) else { continue };
+                    if socket.send(Message::Text(text)).await.is_err() {
+                        break;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(missed)) => {
+                    log::warn!("Double-spend subscriber lagged, {} alert(s) dropped", missed);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            },
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+/// Final vote count of a closed proposal
+#[derive(Serialize, ToSchema)]
+struct TallyResponse {
+    yes: u64,
+    no: u64,
+    abstain: u64,
+    voters: usize,
+    supply: u64,
+    quorum_reached: bool,
+}
+
+/// Governance proposal response
+#[derive(Serialize, ToSchema)]
+struct ProposalResponse {
+    id: String,
+    proposer: String,
+    title: String,
+    description: String,
+    /// Requested treasury spend (0 if none)
+    amount: u64,
+    height: u64,
+    voting_start: u64,
+    voting_end: u64,
+    /// pending, active, passed, rejected or no_quorum
+    status: String,
+    /// Addresses that have voted so far
+    votes: usize,
+    /// Present once voting has closed
+    result: Option<TallyResponse>,
+    /// Txid of the treasury payout, once executed
+    executed: Option<String>,
+}
+
+impl ProposalResponse {
+    fn new(proposal: &Proposal, height: u64) -> Self {
+        Self {
+            id: hex::encode(proposal.id),
+            proposer: proposal.proposer.to_string(),
+            title: proposal.title.clone(),
+            description: proposal.description.clone(),
+            amount: proposal.amount,
+            height: proposal.height,
+            voting_start: proposal.voting_start,
+            voting_end: proposal.voting_end,
+            status: proposal.status(height).as_str().to_string(),
+            votes: proposal.votes.len(),
+            result: proposal.tally.as_ref().map(|t| TallyResponse {
+                yes: t.yes,
+                no: t.no,
+                abstain: t.abstain,
+                voters: t.voters,
+                supply: t.supply,
+                quorum_reached: t.has_quorum(),
+            }),
+            executed: proposal.executed.map(hex::encode),
+        }
+    }
+}
+
+/// List governance proposals
+#[utoipa::path(
+    get,
+    path = "/v1/governance/proposals",
+    responses((status = 200, body = [ProposalResponse]))
+)]
+async fn get_proposals(State(state): State<Arc<RpcState>>) -> Json<Vec<ProposalResponse>> {
+    let chain = state.blockchain.read().await;
+    let height = chain.height();
+    
+    Json(chain.governance().proposals().into_iter()
+        .map(|p| ProposalResponse::new(p, height))
+        .collect())
+}
+
+/// Get a governance proposal and its result
+#[utoipa::path(
+    get,
+    path = "/v1/governance/proposal/{id}",
+    params(("id" = String, Path, description = "Proposal transaction id (hex)")),
+    responses(
+        (status = 200, body = ProposalResponse),
+        (status = 400, body = ErrorResponse),
+        (status = 404, body = ErrorResponse)
+    )
+)]
+async fn get_proposal(
+    State(state): State<Arc<RpcState>>,
+    ApiPath(id): ApiPath<String>,
+) -> Result<Json<ProposalResponse>, RpcError> {
+    let id_arr = parse_hash(&id)?;
+    let chain = state.blockchain.read().await;
+    
+    chain.governance().get(&id_arr)
+        .map(|p| Json(ProposalResponse::new(p, chain.height())))
+        .ok_or_else(|| RpcError::new(ErrorCode::ProposalNotFound, format!("Proposal {} not found", id)))
+}
+
 /// Send transaction request
 #[derive(Deserialize)]
 struct SendTxRequest {
+    /// Canonical transaction encoding, hex
     tx_hex: String,
 }
 
+/// Registered miner entry
+#[derive(Serialize, ToSchema)]
+struct RegisteredMinerResponse {
+    address: String,
+    /// Identity commitment carried in header `extra_data` (hex)
+    commitment: String,
+    first_seen: u64,
+    blocks_found: u64,
+    /// Solidarity outputs received in the window
+    payouts: u64,
+    last_paid: Option<u64>,
+    hashrate_ghs: f64,
+    /// petit, moyen, gros, industriel or ultra_gros
+    tier: String,
+    eligible: bool,
+}
+
+impl RegisteredMinerResponse {
+    fn new(miner: &RegisteredMiner, height: u64) -> Self {
+        Self {
+            address: miner.address.to_string(),
+            commitment: hex::encode(miner.commitment),
+            first_seen: miner.first_seen,
+            blocks_found: miner.blocks_found,
+            payouts: miner.payouts,
+            last_paid: miner.last_paid,
+            hashrate_ghs: miner.hashrate_ghs,
+            tier: miner.tier.as_str().to_string(),
+            eligible: miner.is_eligible(height),
+        }
+    }
+}
+
+/// Solidarity registry response
+#[derive(Serialize, ToSchema)]
+struct RegistryResponse {
+    height: u64,
+    /// Recipient of the next block's solidarity output
+    next_recipient: String,
+    miners: Vec<RegisteredMinerResponse>,
+}
+
+/// Get the solidarity miner registry
+#[utoipa::path(
+    get,
+    path = "/v1/solidarity/registry",
+    responses((status = 200, body = RegistryResponse))
+)]
+async fn get_solidarity_registry(State(state): State<Arc<RpcState>>) -> Json<RegistryResponse> {
+    let chain = state.blockchain.read().await;
+    let registry = chain.miner_registry();
+    
+    Json(RegistryResponse {
+        height: registry.height(),
+        next_recipient: chain.solidarity_recipient().to_string(),
+        miners: registry.miners().into_iter()
+            .map(|m| RegisteredMinerResponse::new(m, registry.height()))
+            .collect(),
+    })
+}
+
 /// Send transaction response
 #[derive(Serialize)]
 struct SendTxResponse {
-    success: bool,
-    hash: Option<String>,
-    error: Option<String>,
+    /// Transaction id (excludes signatures)
+    hash: String,
+    
+    /// Hash of the full encoding including signatures
+    wtxid: String,
 }
 
 /// Send transaction
 async fn send_transaction(
     State(state): State<Arc<RpcState>>,
-    Json(request): Json<SendTxRequest>,
-) -> Json<SendTxResponse> {
-    let tx_bytes = match hex::decode(&request.tx_hex) {
-        Ok(b) => b,
-        Err(e) => return Json(SendTxResponse {
-            success: false,
-            hash: None,
-            error: Some(format!("Invalid hex: {}", e)),
-        }),
-    };
+    ApiJson(request): ApiJson<SendTxRequest>,
+) -> Result<Json<SendTxResponse>, RpcError> {
+    let tx_bytes = hex::decode(&request.tx_hex)
+        .map_err(|e| RpcError::new(ErrorCode::InvalidHex, format!("Invalid hex: {}", e)))?;
     
-    let tx: Transaction = match bincode::deserialize(&tx_bytes) {
-        Ok(t) => t,
-        Err(e) => return Json(SendTxResponse {
-            success: false,
-            hash: None,
-            error: Some(format!("Invalid transaction: {}", e)),
-        }),
-    };
+    let tx = Transaction::decode(&tx_bytes)
+        .map_err(|e| RpcError::new(ErrorCode::InvalidTransaction, format!("Invalid transaction: {}", e)))?;
     
-    let hash = tx.hash();
+    let chain = state.blockchain.read().await;
+    chain.validate_governance(&tx)
+        .map_err(|e| RpcError::new(ErrorCode::InvalidTransaction, e.to_string()))?;
+    chain.check_final(&tx)
+        .map_err(|e| RpcError::new(ErrorCode::InvalidTransaction, e.to_string()))?;
+    
+    // Outputs of pending transactions may be spent too (child pays for parent)
     let mut mempool = state.mempool.write().await;
+    let fee = tx.fee(&mempool.with_confirmed(&*chain))
+        .ok_or_else(|| RpcError::new(
+            ErrorCode::InvalidTransaction,
+            "Transaction spends unknown outputs or outputs exceed inputs",
+        ))?;
+    drop(chain);
     
-    match mempool.add(tx, 0) {
-        Ok(_) => Json(SendTxResponse {
-            success: true,
-            hash: Some(hex::encode(hash)),
-            error: None,
-        }),
-        Err(e) => Json(SendTxResponse {
-            success: false,
-            hash: None,
-            error: Some(e.to_string()),
-        }),
-    }
+    let hash = tx.txid();
+    let wtxid = tx.wtxid();
+    mempool.add(tx, fee)?;
+    
+    Ok(Json(SendTxResponse {
+        hash: hex::encode(hash),
+        wtxid: hex::encode(wtxid),
+    }))
+}
+
+/// Maximum number of outstanding block templates
+pub const MAX_PENDING_TEMPLATES: usize = 1000;
+
+/// Block template handed out to a miner, kept until submission
+#[derive(Clone, Debug)]
+pub struct PendingTemplate {
+    /// Address the coinbase must pay
+    pub miner_address: Address,
+    
+    /// Height the template was built for
+    pub height: u64,
+    
+    /// Tip the template builds on
+    pub prev_hash: [u8; 32],
+    
+    /// Creation timestamp
+    pub created_at: i64,
 }
 
 /// Block template request
@@ -267,42 +1245,179 @@ struct BlockTemplateRequest {
 /// Block template response
 #[derive(Serialize)]
 struct BlockTemplateResponse {
+    job_id: String,
     height: u64,
     difficulty: u64,
     prev_hash: String,
     header_hash: String,
     timestamp: i64,
     reward: u64,
+    /// Header version, signalling the deployments in progress
+    version: u32,
+    /// AequiHash seed of the template's epoch
+    seed_hash: String,
+}
+
+/// AequiHash seed of `epoch`, chained to the last block of the previous one
+fn epoch_seed(chain: &Blockchain, epoch: u64) -> Result<[u8; 32], RpcError> {
+    let anchor = match AequiHash::seed_anchor_height(epoch) {
+        Some(anchor_height) if anchor_height > chain.height() => {
+            return Err(RpcError::new(
+                ErrorCode::BlockNotFound,
+                format!("Seed anchor block {} of epoch {} is not mined yet", anchor_height, epoch),
+            ));
+        }
+        Some(anchor_height) => Some(chain.get_block_at_height(anchor_height)
+            .ok_or_else(|| RpcError::new(ErrorCode::Internal, "Seed anchor block is pruned"))?
+            .hash()),
+        None => None,
+    };
+    Ok(AequiHash::epoch_seed(epoch, anchor.as_ref()))
 }
 
 /// Get block template for mining
 async fn get_block_template(
     State(state): State<Arc<RpcState>>,
-    Json(_request): Json<BlockTemplateRequest>,
-) -> Json<BlockTemplateResponse> {
+    ApiJson(request): ApiJson<BlockTemplateRequest>,
+) -> Result<Json<BlockTemplateResponse>, RpcError> {
+    let miner_address = request.address
+        .as_deref()
+        .ok_or_else(|| RpcError::new(ErrorCode::InvalidAddress, "Missing miner address"))
+        .and_then(|a| Address::from_string(a)
+            .map_err(|e| RpcError::new(ErrorCode::InvalidAddress, format!("Invalid address: {}", e))))?;
+    
     let chain = state.blockchain.read().await;
     let tip = chain.tip_block();
     
     let height = chain.height() + 1;
     let difficulty = chain.next_difficulty();
-    let (miner_reward, _dev, _solidarity) = chain.rewards_for_height(height);
+    let (miner_reward, _dev, _solidarity) = chain.rewards_for_miner(&miner_address);
     let reward = miner_reward; // Miner only sees their part
     
-    // Create a template header hash
+    let seed = epoch_seed(&chain, AequiHash::epoch_from_height(height))?;
+    
+    // Create a template header hash (unique per miner address)
     let mut header_data = Vec::new();
     header_data.extend_from_slice(&tip.hash());
     header_data.extend_from_slice(&height.to_le_bytes());
     header_data.extend_from_slice(&difficulty.to_le_bytes());
+    header_data.extend_from_slice(&miner_address.to_bytes());
     
     let header_hash = blake3::hash(&header_data);
+    let job_id = hex::encode(&header_hash.as_bytes()[..8]);
+    let now = chrono::Utc::now().timestamp();
+    
+    // Remember who this template pays, dropping templates for stale heights
+    {
+        let mut templates = state.templates.write().await;
+        templates.retain(|_, t| t.height == height);
+        if templates.len() >= MAX_PENDING_TEMPLATES {
+            if let Some(oldest) = templates.iter()
+                .min_by_key(|(_, t)| t.created_at)
+                .map(|(id, _)| id.clone())
+            {
+                templates.remove(&oldest);
+            }
+        }
+        templates.insert(job_id.clone(), PendingTemplate {
+            miner_address,
+            height,
+            prev_hash: tip.hash(),
+            created_at: now,
+        });
+    }
     
-    Json(BlockTemplateResponse {
+    Ok(Json(BlockTemplateResponse {
+        job_id,
         height,
         difficulty,
         prev_hash: hex::encode(tip.hash()),
         header_hash: hex::encode(header_hash.as_bytes()),
-        timestamp: chrono::Utc::now().timestamp(),
+        timestamp: now,
         reward,
+        version: chain.next_block_version(),
+        seed_hash: hex::encode(seed),
+    }))
+}
+
+/// Template preview query
+#[derive(Deserialize)]
+struct TemplatePreviewQuery {
+    /// Space for transactions, capped at the consensus limit
+    max_size: Option<usize>,
+}
+
+/// Mempool transaction in a template preview
+#[derive(Serialize)]
+struct PreviewTransaction {
+    txid: String,
+    fee: u64,
+    size: usize,
+    fee_per_byte: f64,
+    /// Fee per byte with its pending ancestors
+    package_fee_per_byte: f64,
+    /// Why it was left out (skipped transactions only)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<&'static str>,
+}
+
+impl PreviewTransaction {
+    fn new(entry: &MempoolEntry, reason: Option<&'static str>) -> Self {
+        Self {
+            txid: hex::encode(entry.transaction.txid()),
+            fee: entry.fee,
+            size: entry.size,
+            fee_per_byte: entry.fee_per_byte,
+            package_fee_per_byte: entry.package_fee_rate(),
+            reason,
+        }
+    }
+}
+
+/// Template preview response
+#[derive(Serialize)]
+struct TemplatePreviewResponse {
+    height: u64,
+    prev_hash: String,
+    /// Selected transactions, in block order
+    transactions: Vec<PreviewTransaction>,
+    /// Fees of the selected transactions
+    total_fees: u64,
+    /// Size of the selected transactions
+    transactions_size: usize,
+    /// Estimated block size, header and coinbase included
+    block_size: usize,
+    /// Space available to transactions
+    max_size: usize,
+    /// Pending transactions left out, in fee rate order
+    skipped: Vec<PreviewTransaction>,
+}
+
+/// Preview the transaction selection of the next block
+///
+/// Runs the same selection as a template without reserving a job.
+async fn preview_block_template(
+    State(state): State<Arc<RpcState>>,
+    ApiQuery(query): ApiQuery<TemplatePreviewQuery>,
+) -> Json<TemplatePreviewResponse> {
+    let (height, prev_hash) = {
+        let chain = state.blockchain.read().await;
+        (chain.height() + 1, chain.tip())
+    };
+    let mempool = state.mempool.read().await;
+    let selection = mempool.select_for_block(query.max_size.unwrap_or(usize::MAX));
+    
+    Json(TemplatePreviewResponse {
+        height,
+        prev_hash: hex::encode(prev_hash),
+        transactions: selection.selected.iter().map(|entry| PreviewTransaction::new(entry, None)).collect(),
+        total_fees: selection.fees,
+        transactions_size: selection.size,
+        block_size: selection.size + crate::mempool::COINBASE_RESERVED_SIZE,
+        max_size: selection.max_size,
+        skipped: selection.skipped.iter()
+            .map(|(entry, reason)| PreviewTransaction::new(entry, Some(reason.as_str())))
+            .collect(),
     })
 }
 
@@ -317,49 +1432,93 @@ struct SubmitBlockRequest {
 /// Submit block response
 #[derive(Serialize)]
 struct SubmitBlockResponse {
-    success: bool,
+    height: u64,
+    hash: String,
     message: String,
 }
 
+/// Check that a coinbase pays the miner, treasury and solidarity the exact split
+fn verify_coinbase_split(
+    coinbase: &Transaction,
+    miner_address: &Address,
+    treasury_address: &Address,
+    rewards: (u64, u64, u64),
+) -> bool {
+    let (miner_reward, dev_reward, solidarity_reward) = rewards;
+    
+    coinbase.outputs.len() == 3
+        && coinbase.outputs[0].recipient == *miner_address
+        && coinbase.outputs[0].amount == miner_reward
+        && coinbase.outputs[1].recipient == *treasury_address
+        && coinbase.outputs[1].amount == dev_reward
+        && coinbase.outputs[2].amount == solidarity_reward
+}
+
+/// Build the coinbase paying the miner, treasury and solidarity split
+fn build_coinbase(chain: &Blockchain, miner_address: &Address, height: u64) -> Result<Transaction, RpcError> {
+    let rewards = chain.rewards_for_miner(miner_address);
+    let (miner_reward, dev_reward, solidarity_reward) = rewards;
+    
+    // Solidarity recipient from the miner registry
+    let solidarity_recipient = chain.solidarity_recipient();
+    let treasury_address = Address::genesis_address(); // Use genesis for treasury
+    
+    // Construct coinbase transaction with 3 outputs, locked to the tip like
+    // wallet transactions
+    let mut coinbase = Transaction::coinbase(miner_address.clone(), miner_reward, height)
+        .with_lock_height(height.saturating_sub(1));
+    
+    // Add Treasury output
+    coinbase.outputs.push(aequitas_core::transaction::TxOutput {
+        amount: dev_reward,
+        recipient: treasury_address.clone(),
+    });
+    
+    // Add Solidarity output
+    coinbase.outputs.push(aequitas_core::transaction::TxOutput {
+        amount: solidarity_reward,
+        recipient: solidarity_recipient,
+    });
+    
+    if !verify_coinbase_split(&coinbase, miner_address, &treasury_address, rewards) {
+        log::error!("✗ Constructed coinbase does not match the expected reward split");
+        return Err(RpcError::new(ErrorCode::InvalidCoinbase, "Coinbase does not match the reward split"));
+    }
+    
+    Ok(coinbase)
+}
+
 /// Submit mined block
 async fn submit_block(
     State(state): State<Arc<RpcState>>,
-    Json(request): Json<SubmitBlockRequest>,
-) -> Json<SubmitBlockResponse> {
+    ApiJson(request): ApiJson<SubmitBlockRequest>,
+) -> Result<Json<SubmitBlockResponse>, RpcError> {
     log::info!("Block submission received: job={}, nonce={}", request.job_id, request.nonce);
     
-    // 1. Prepare block components
+    // 1. Look up the template this job was issued for
+    let template = state.templates.read().await
+        .get(&request.job_id)
+        .cloned()
+        .ok_or_else(|| RpcError::new(
+            ErrorCode::UnknownJob,
+            format!("Unknown or expired job {}", request.job_id),
+        ))?;
+    
+    // 2. Prepare block components
     let (block, height) = {
         let chain = state.blockchain.read().await;
         let height = chain.height() + 1;
-        let (miner_reward, dev_reward, solidarity_reward) = chain.rewards_for_height(height);
-        
-        // Find solidarity recipient (smallest miner in history)
-        let solidarity_recipient = chain.find_smallest_beneficiary();
-        let treasury_address = Address::genesis_address(); // Use genesis for treasury
-        
-        // Use a default address if none provided (miner would normally provide this in template request)
-        let miner_address = Address::from_string("aeq15g6yvYR5NQgtE9hjnspgUToeLCJNaqbdW").unwrap();
-
-        // Construct coinbase transaction with 3 outputs
-        let mut coinbase = Transaction::new_coinbase(miner_address, miner_reward);
         
-        // Add Treasury output
-        coinbase.outputs.push(aequitas_core::transaction::TxOutput {
-            amount: dev_reward,
-            recipient: treasury_address,
-        });
+        if template.height != height || template.prev_hash != chain.tip() {
+            return Err(RpcError::new(ErrorCode::StaleJob, "Chain tip has moved since the template was built"));
+        }
         
-        // Add Solidarity output
-        coinbase.outputs.push(aequitas_core::transaction::TxOutput {
-            amount: solidarity_reward,
-            recipient: solidarity_recipient,
-        });
+        let coinbase = build_coinbase(&chain, &template.miner_address, height)?;
 
-        let mut block = Block::new(
-            BlockHeader::new(chain.tip(), [0u8; 32], height, chain.next_difficulty()),
-            vec![coinbase]
-        );
+        let mut block = Block::new(chain.tip(), height, chain.next_difficulty(), vec![coinbase]);
+        block.header.version = chain.next_block_version();
+        block.header.extra_data = identity_commitment(&template.miner_address);
+        block.commit_utxo_set(chain.utxo_commitment());
         block.header.nonce = request.nonce;
         block.header.timestamp = chrono::Utc::now();
         
@@ -369,27 +1528,305 @@ async fn submit_block(
         (block, height)
     };
 
-    // 2. Add to blockchain
+    // 3. Add to blockchain
     let mut chain = state.blockchain.write().await;
     match chain.add_block(block.clone()) {
         Ok(_) => {
-            log::info!("✓ Block #{} accepted. Solidarity Reward sent to: {}", height, block.transactions[0].outputs[2].recipient);
-            // 3. Save to disk
+            log::info!(
+                "✓ Block #{} accepted. Miner reward sent to: {}. Solidarity Reward sent to: {}",
+                height,
+                template.miner_address,
+                block.transactions[0].outputs[2].recipient
+            );
+            state.templates.write().await.remove(&request.job_id);
+            state.mempool.write().await.remove_for_block(&block);
+            state.stats.write().await.record_blocks(1);
+            // 4. Save to disk
             let _ = chain.save(&state.chain_path);
-            // 4. Broadcast to network
-            let _ = state.broadcast_tx.send(block).await;
+            // 5. Broadcast to network
+            let hash = block.hash();
+            let announcement = NewBlockMsg::new(block, chain.total_work());
+            let _ = state.broadcast_tx.send(NetworkCommand::AnnounceBlock(announcement)).await;
             
-            Json(SubmitBlockResponse {
-                success: true,
+            Ok(Json(SubmitBlockResponse {
+                height,
+                hash: hex::encode(hash),
                 message: format!("Block #{} accepted and broadcasted", height),
-            })
+            }))
         }
         Err(e) => {
             log::warn!("✗ Block submission rejected: {}", e);
-            Json(SubmitBlockResponse {
-                success: false,
-                message: format!("Rejected: {}", e),
-            })
+            Err(RpcError::new(ErrorCode::BlockRejected, e.to_string()))
+        }
+    }
+}
+
+/// Most blocks a single `/generate` call mines
+pub const MAX_GENERATE_BLOCKS: u64 = 1000;
+
+/// Generate request
+#[derive(Deserialize)]
+struct GenerateRequest {
+    /// Number of blocks to mine
+    blocks: u64,
+    /// Address receiving the miner reward
+    address: String,
+}
+
+/// Generate response
+#[derive(Serialize)]
+struct GenerateResponse {
+    /// Hashes of the mined blocks, lowest first
+    hashes: Vec<String>,
+    height: u64,
+}
+
+/// Mine blocks instantly on regtest
+///
+/// Each block confirms what the mempool holds, like a template would, and is
+/// announced to peers. Other networks refuse the call.
+async fn generate_blocks(
+    State(state): State<Arc<RpcState>>,
+    ApiJson(request): ApiJson<GenerateRequest>,
+) -> Result<Json<GenerateResponse>, RpcError> {
+    let miner_address = Address::from_string(&request.address)
+        .map_err(|e| RpcError::new(ErrorCode::InvalidAddress, format!("Invalid address: {}", e)))?;
+    if request.blocks == 0 || request.blocks > MAX_GENERATE_BLOCKS {
+        return Err(RpcError::new(
+            ErrorCode::InvalidRequest,
+            format!("blocks must be between 1 and {}", MAX_GENERATE_BLOCKS),
+        ));
+    }
+    
+    let mut chain = state.blockchain.write().await;
+    if chain.params().network != Network::Regtest {
+        return Err(RpcError::new(
+            ErrorCode::InvalidRequest,
+            format!("generate is only available on regtest, this node runs {}", chain.params().network),
+        ));
+    }
+    
+    let mut hashes = Vec::new();
+    for _ in 0..request.blocks {
+        let height = chain.height() + 1;
+        let mut transactions = vec![build_coinbase(&chain, &miner_address, height)?];
+        let pending = state.mempool.read().await.get_for_block(usize::MAX);
+        transactions.extend(
+            pending.into_iter().filter(|tx| tx.is_final(height) && chain.transaction_fee(tx).is_some()),
+        );
+        
+        let mut block = Block::new(chain.tip(), height, chain.next_difficulty(), transactions);
+        block.header.version = chain.next_block_version();
+        block.header.extra_data = identity_commitment(&miner_address);
+        block.commit_utxo_set(chain.utxo_commitment());
+        block.header.timestamp = chrono::Utc::now().max(
+            chrono::DateTime::from_timestamp(chain.median_time_past() + 1, 0).unwrap_or_default(),
+        );
+        block.header.merkle_root = aequitas_core::merkle::compute_merkle_root(&block.transactions);
+        while !block.header.meets_difficulty() {
+            block.header.nonce += 1;
+        }
+        
+        if let Err(e) = chain.add_block(block.clone()) {
+            log::warn!("✗ Generated block #{} rejected: {}", height, e);
+            return Err(RpcError::new(ErrorCode::BlockRejected, e.to_string()));
+        }
+        state.mempool.write().await.remove_for_block(&block);
+        state.stats.write().await.record_blocks(1);
+        
+        let hash = block.hash();
+        let announcement = NewBlockMsg::new(block, chain.total_work());
+        let _ = state.broadcast_tx.send(NetworkCommand::AnnounceBlock(announcement)).await;
+        hashes.push(hex::encode(hash));
+    }
+    let _ = chain.save(&state.chain_path);
+    log::info!("✓ Generated {} block(s), height now {}", hashes.len(), chain.height());
+    
+    Ok(Json(GenerateResponse { hashes, height: chain.height() }))
+}
+
+/// Invalidate- or reconsider-block request
+#[derive(Deserialize)]
+struct BlockHashRequest {
+    /// Hash of the block
+    hash: String,
+}
+
+/// Invalidate-block response
+#[derive(Serialize)]
+struct InvalidateBlockResponse {
+    /// Hashes of the disconnected blocks, tip first
+    disconnected: Vec<String>,
+    /// Transactions of those blocks accepted back into the mempool
+    restored: usize,
+    height: u64,
+}
+
+/// Disconnect a block and every block above it, and refuse it from peers
+///
+/// The block's undo data makes this immediate. Its transactions return to
+/// the mempool when they still apply.
+async fn invalidate_block(
+    State(state): State<Arc<RpcState>>,
+    ApiJson(request): ApiJson<BlockHashRequest>,
+) -> Result<Json<InvalidateBlockResponse>, RpcError> {
+    let hash = parse_hash(&request.hash)?;
+    let mut chain = state.blockchain.write().await;
+    let blocks = chain.invalidate_block(&hash).map_err(|e| match e {
+        ChainError::NotInMainChain => RpcError::new(ErrorCode::BlockNotFound, e.to_string()),
+        e => RpcError::new(ErrorCode::InvalidRequest, e.to_string()),
+    })?;
+    
+    let mut mempool = state.mempool.write().await;
+    let mut restored = 0;
+    for tx in blocks.iter().rev().flat_map(|block| block.transactions.iter().skip(1)) {
+        if chain.check_final(tx).is_err() {
+            continue;
+        }
+        if let Some(fee) = chain.transaction_fee(tx) {
+            if mempool.add(tx.clone(), fee).is_ok() {
+                restored += 1;
+            }
         }
     }
+    drop(mempool);
+    let _ = chain.save(&state.chain_path);
+    log::warn!(
+        "⏪ Invalidated block {}: {} block(s) disconnected, height now {}",
+        request.hash,
+        blocks.len(),
+        chain.height()
+    );
+    
+    Ok(Json(InvalidateBlockResponse {
+        disconnected: blocks.iter().map(|block| block.hash_hex()).collect(),
+        restored,
+        height: chain.height(),
+    }))
+}
+
+/// Reconsider-block response
+#[derive(Serialize)]
+struct ReconsiderBlockResponse {
+    /// Hashes of the blocks connected again, lowest first
+    reconnected: Vec<String>,
+    height: u64,
+}
+
+/// Accept an invalidated block again
+///
+/// The blocks its invalidation disconnected are connected again if they
+/// still extend the tip; otherwise the block may come back through sync.
+async fn reconsider_block(
+    State(state): State<Arc<RpcState>>,
+    ApiJson(request): ApiJson<BlockHashRequest>,
+) -> Result<Json<ReconsiderBlockResponse>, RpcError> {
+    let hash = parse_hash(&request.hash)?;
+    let mut chain = state.blockchain.write().await;
+    let blocks = chain.reconsider_block(&hash)
+        .map_err(|e| RpcError::new(ErrorCode::BlockNotFound, e.to_string()))?;
+    
+    let mut mempool = state.mempool.write().await;
+    for block in &blocks {
+        mempool.remove_for_block(block);
+    }
+    drop(mempool);
+    let _ = chain.save(&state.chain_path);
+    log::info!(
+        "⏩ Reconsidered block {}: {} block(s) reconnected, height now {}",
+        request.hash,
+        blocks.len(),
+        chain.height()
+    );
+    
+    Ok(Json(ReconsiderBlockResponse {
+        reconnected: blocks.iter().map(|block| block.hash_hex()).collect(),
+        height: chain.height(),
+    }))
+}
+
+/// Hashes of the blocks invalidated by the operator
+async fn get_invalidated_blocks(State(state): State<Arc<RpcState>>) -> Json<Vec<String>> {
+    let chain = state.blockchain.read().await;
+    Json(chain.invalidated_blocks().iter().map(hex::encode).collect())
+}
+
+/// Deployment status response
+#[derive(Serialize)]
+struct DeploymentResponse {
+    name: String,
+    bit: u8,
+    start_height: u64,
+    timeout_height: u64,
+    /// State for the next block
+    state: String,
+    /// First height of that state
+    since: u64,
+    /// Blocks of the current period signalling the deployment
+    signalling: u32,
+    /// Blocks of the current period so far
+    elapsed: u32,
+}
+
+/// Deployments response
+#[derive(Serialize)]
+struct DeploymentInfoResponse {
+    height: u64,
+    /// Blocks per signalling period
+    period: u64,
+    /// Signalling blocks in a period that lock a deployment in
+    threshold: u32,
+    deployments: Vec<DeploymentResponse>,
+}
+
+/// Epoch program response
+#[derive(Serialize)]
+struct EpochProgramResponse {
+    epoch: u64,
+    /// AequiHash seed of the epoch
+    seed_hash: String,
+    /// `AequiHash::program_fingerprint`, to compare with a miner's own
+    fingerprint: String,
+    /// Dataset size (bytes)
+    dag_size: u64,
+    /// Encoded rounds (op, address, offset), one hex string per round
+    program: Vec<String>,
+}
+
+/// AequiHash program of an epoch, for pools and miners to check theirs
+async fn get_epoch_program(
+    State(state): State<Arc<RpcState>>,
+    ApiPath(epoch): ApiPath<u64>,
+) -> Result<Json<EpochProgramResponse>, RpcError> {
+    let seed = epoch_seed(&*state.blockchain.read().await, epoch)?;
+    let aequihash = AequiHash::new(epoch, &seed);
+    Ok(Json(EpochProgramResponse {
+        epoch,
+        seed_hash: hex::encode(seed),
+        fingerprint: hex::encode(aequihash.program_fingerprint()),
+        dag_size: aequitas_consensus::DAG::size_for_epoch(epoch) as u64,
+        program: aequihash.program().iter().map(|round| hex::encode(round.encode())).collect(),
+    }))
+}
+
+/// Version-bits deployments and their signalling
+async fn get_deployment_info(State(state): State<Arc<RpcState>>) -> Json<DeploymentInfoResponse> {
+    let chain = state.blockchain.read().await;
+    Json(DeploymentInfoResponse {
+        height: chain.height(),
+        period: chain.params().signal_period,
+        threshold: chain.params().signal_threshold,
+        deployments: chain.deployments().into_iter()
+            .map(|info| DeploymentResponse {
+                name: info.deployment.name.to_string(),
+                bit: info.deployment.bit,
+                start_height: info.deployment.start_height,
+                timeout_height: info.deployment.timeout_height,
+                state: info.state.as_str().to_string(),
+                since: info.since,
+                signalling: info.signalling,
+                elapsed: info.elapsed,
+            })
+            .collect(),
+    })
 }
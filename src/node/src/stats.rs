@@ -0,0 +1,122 @@
+//! Lifetime node statistics
+//!
+//! Counters that survive restarts, so operators can judge long-term
+//! reliability rather than only what happened since boot: blocks processed,
+//! reorganizations, invalid blocks received, restarts and cumulative uptime.
+//! They are kept in [`STATS_FILE`] in the data directory, rewritten every
+//! [`STATS_SAVE_INTERVAL`] and on shutdown, so a crash loses at most one
+//! interval.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use serde::{Deserialize, Serialize};
+
+/// File name of the statistics in the data directory
+pub const STATS_FILE: &str = "node-stats.json";
+
+/// How often the statistics are written to disk
+pub const STATS_SAVE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Counters persisted across restarts
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct LifetimeCounters {
+    /// Blocks added to the chain (synced, received or mined)
+    pub blocks_processed: u64,
+
+    /// Chain reorganizations (the chain only extends its tip for now)
+    pub reorgs: u64,
+
+    /// Blocks received from peers that failed validation
+    pub invalid_blocks: u64,
+
+    /// Starts after the first one
+    pub restarts: u64,
+
+    /// Seconds the node has been running, all sessions together
+    pub uptime_secs: u64,
+
+    /// Unix time of the first start
+    pub first_start: i64,
+}
+
+/// Lifetime statistics of the running node
+pub struct NodeStats {
+    path: PathBuf,
+    /// Counters, with the uptime of previous sessions only
+    counters: LifetimeCounters,
+    session_start: Instant,
+    started_at: i64,
+}
+
+impl NodeStats {
+    /// Load the statistics of a data directory and count this start
+    ///
+    /// Unreadable statistics are not worth refusing to start over: they
+    /// are logged and counting starts again.
+    pub fn open<P: AsRef<Path>>(data_dir: P) -> Self {
+        let path = data_dir.as_ref().join(STATS_FILE);
+        let started_at = chrono::Utc::now().timestamp();
+
+        let counters = match std::fs::read_to_string(&path) {
+            Ok(content) => match serde_json::from_str::<LifetimeCounters>(&content) {
+                Ok(mut counters) => {
+                    counters.restarts += 1;
+                    counters
+                }
+                Err(e) => {
+                    log::warn!("Ignoring unreadable node statistics {}: {}", path.display(), e);
+                    LifetimeCounters { first_start: started_at, ..Default::default() }
+                }
+            },
+            Err(_) => LifetimeCounters { first_start: started_at, ..Default::default() },
+        };
+
+        Self {
+            path,
+            counters,
+            session_start: Instant::now(),
+            started_at,
+        }
+    }
+
+    /// Count blocks added to the chain
+    pub fn record_blocks(&mut self, count: u64) {
+        self.counters.blocks_processed += count;
+    }
+
+    /// Count a block from a peer that failed validation
+    pub fn record_invalid_block(&mut self) {
+        self.counters.invalid_blocks += 1;
+    }
+
+    /// Count a chain reorganization
+    pub fn record_reorg(&mut self) {
+        self.counters.reorgs += 1;
+    }
+
+    /// Unix time this session started
+    pub fn started_at(&self) -> i64 {
+        self.started_at
+    }
+
+    /// Seconds since this session started
+    pub fn session_secs(&self) -> u64 {
+        self.session_start.elapsed().as_secs()
+    }
+
+    /// Current counters, uptime including this session
+    pub fn counters(&self) -> LifetimeCounters {
+        LifetimeCounters {
+            uptime_secs: self.counters.uptime_secs + self.session_secs(),
+            ..self.counters.clone()
+        }
+    }
+
+    /// Write the current counters, replacing the file atomically
+    pub fn save(&self) -> anyhow::Result<()> {
+        let tmp = self.path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(&self.counters())?)?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
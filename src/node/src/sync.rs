@@ -0,0 +1,102 @@
+//! Stale tip detection
+//!
+//! A tip is stale when it has not advanced for `STALE_TIP_FACTOR` target
+//! block times while some peer reports a higher height. The node then warns,
+//! re-requests blocks after its tip and raises the `stale_tip` flag in `/info`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use aequitas_core::TARGET_BLOCK_TIME;
+
+/// Target block times without tip progress before the tip counts as stale
+pub const STALE_TIP_FACTOR: u64 = 6;
+
+/// How often the tip is checked
+pub const STALE_CHECK_INTERVAL: Duration = Duration::from_secs(TARGET_BLOCK_TIME);
+
+/// Tracks tip progress and the shared stale flag
+pub struct TipMonitor {
+    last_height: u64,
+    last_change: Instant,
+    stale: Arc<AtomicBool>,
+}
+
+impl TipMonitor {
+    /// Start monitoring from the current height
+    pub fn new(height: u64, stale: Arc<AtomicBool>) -> Self {
+        Self {
+            last_height: height,
+            last_change: Instant::now(),
+            stale,
+        }
+    }
+
+    /// Time the tip may stand still before it counts as stale
+    pub fn threshold() -> Duration {
+        Duration::from_secs(TARGET_BLOCK_TIME * STALE_TIP_FACTOR)
+    }
+
+    /// Time since the tip last advanced
+    pub fn tip_age(&self) -> Duration {
+        self.last_change.elapsed()
+    }
+
+    /// Record the current height and update the stale flag
+    ///
+    /// Returns the new flag value.
+    pub fn check(&mut self, height: u64, best_peer_height: Option<u64>) -> bool {
+        if height != self.last_height {
+            self.last_height = height;
+            self.last_change = Instant::now();
+        }
+
+        let peers_ahead = best_peer_height.is_some_and(|h| h > height);
+        let stale = peers_ahead && self.tip_age() >= Self::threshold();
+        self.stale.store(stale, Ordering::Relaxed);
+        stale
+    }
+
+    /// Whether the tip was stale at the last check
+    pub fn is_stale(&self) -> bool {
+        self.stale.load(Ordering::Relaxed)
+    }
+}
+
+/// Sync and block production summary served on `/info`
+#[derive(Clone, Debug)]
+pub struct ChainHealth {
+    /// No peer reports a higher height and the tip is not stale
+    pub is_synced: bool,
+    
+    /// Best height reported by peers (none without peers)
+    pub expected_height: Option<u64>,
+    
+    /// Blocks between the tip and `expected_height`
+    pub blocks_behind: u64,
+    
+    /// Seconds since the tip's timestamp
+    pub seconds_since_tip: u64,
+    
+    /// Tip stale while peers are ahead (see [`TipMonitor`])
+    pub stale_tip: bool,
+    
+    /// No block for [`TipMonitor::threshold`], peers or not: production
+    /// itself has stopped
+    pub production_stalled: bool,
+}
+
+impl ChainHealth {
+    /// Summarize the tip against what peers report
+    pub fn new(height: u64, seconds_since_tip: u64, best_peer_height: Option<u64>, stale_tip: bool) -> Self {
+        let blocks_behind = best_peer_height.map_or(0, |h| h.saturating_sub(height));
+        Self {
+            is_synced: blocks_behind == 0 && !stale_tip,
+            expected_height: best_peer_height.map(|h| h.max(height)),
+            blocks_behind,
+            seconds_since_tip,
+            stale_tip,
+            production_stalled: seconds_since_tip >= TipMonitor::threshold().as_secs(),
+        }
+    }
+}
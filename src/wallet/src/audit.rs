@@ -0,0 +1,298 @@
+//! Wallet audit log
+//!
+//! Every key generation, import, export, unlock and send is appended to
+//! `<wallet>.audit.log` next to the wallet file, one JSON entry per line.
+//! Entries never contain key material or passwords.
+//!
+//! Each entry carries the hash of the previous one and its own hash,
+//! `blake3(prev_hash || json(seq, timestamp, event))`, so editing, removing or
+//! reordering any entry breaks the chain. A tampered log is refused for
+//! further writes.
+
+use aequitas_core::{Address, Blockchain, Transaction};
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Extension appended to the wallet file name
+pub const AUDIT_LOG_EXTENSION: &str = "audit.log";
+
+/// An audited wallet operation
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum AuditEvent {
+    /// A new key was generated
+    Generate {
+        address: String,
+        label: Option<String>,
+    },
+
+    /// A private key or keyfile was imported
+    Import {
+        address: String,
+    },
+
+    /// A private key was displayed in clear
+    Export {
+        address: String,
+    },
+
+    /// An encrypted keyfile was written
+    ExportKeyfile {
+        address: String,
+        file: String,
+    },
+
+    /// An address was unlocked
+    Unlock {
+        address: String,
+    },
+
+    /// The key of an address was encrypted with a new password
+    ChangePassword {
+        address: String,
+    },
+
+    /// A spend was signed
+    Send {
+        txid: String,
+        from: String,
+        to: String,
+        amount: u64,
+        fee: u64,
+    },
+}
+
+impl AuditEvent {
+    /// Send event for a signed transaction paying `amount` to `to`
+    pub fn send(tx: &Transaction, from: &Address, to: &Address, amount: u64, chain: &Blockchain) -> Self {
+        AuditEvent::Send {
+            txid: hex::encode(tx.txid()),
+            from: from.to_string(),
+            to: to.to_string(),
+            amount,
+            fee: chain.transaction_fee(tx).unwrap_or(0),
+        }
+    }
+
+    /// Short human-readable description
+    pub fn describe(&self) -> String {
+        match self {
+            AuditEvent::Generate { address, label } => match label {
+                Some(label) => format!("generate {} ({})", address, label),
+                None => format!("generate {}", address),
+            },
+            AuditEvent::Import { address } => format!("import {}", address),
+            AuditEvent::Export { address } => format!("export {}", address),
+            AuditEvent::ExportKeyfile { address, file } => format!("export-keyfile {} -> {}", address, file),
+            AuditEvent::Unlock { address } => format!("unlock {}", address),
+            AuditEvent::ChangePassword { address } => format!("change-password {}", address),
+            AuditEvent::Send { txid, from, to, amount, fee } => {
+                format!("send {} from {} to {} (fee {}) txid {}", amount, from, to, fee, txid)
+            }
+        }
+    }
+}
+
+/// Hashed part of an entry
+#[derive(Serialize)]
+struct EntryBody<'a> {
+    seq: u64,
+    timestamp: &'a str,
+    event: &'a AuditEvent,
+}
+
+/// One line of the audit log
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Position in the log, starting at 0
+    pub seq: u64,
+
+    /// RFC 3339 time of the operation
+    pub timestamp: String,
+
+    /// What happened
+    pub event: AuditEvent,
+
+    /// Hash of the previous entry (zeros for the first one)
+    pub prev: String,
+
+    /// Hash of this entry
+    pub hash: String,
+}
+
+impl AuditEntry {
+    fn compute_hash(seq: u64, timestamp: &str, event: &AuditEvent, prev: &[u8; 32]) -> [u8; 32] {
+        let body = serde_json::to_vec(&EntryBody { seq, timestamp, event })
+            .expect("Audit entry serializes");
+        let mut hasher = blake3::Hasher::new();
+        hasher.update(prev);
+        hasher.update(&body);
+        *hasher.finalize().as_bytes()
+    }
+}
+
+/// Append-only audit log of a wallet
+pub struct AuditLog {
+    path: PathBuf,
+    next_seq: u64,
+    last_hash: [u8; 32],
+}
+
+impl AuditLog {
+    /// Audit log path of a wallet file
+    pub fn path_for<P: AsRef<Path>>(wallet: P) -> PathBuf {
+        let wallet = wallet.as_ref();
+        let mut name = wallet.file_name().unwrap_or_default().to_os_string();
+        name.push(".");
+        name.push(AUDIT_LOG_EXTENSION);
+        wallet.with_file_name(name)
+    }
+
+    /// Open the audit log of a wallet file, creating it on first write
+    pub fn for_wallet<P: AsRef<Path>>(wallet: P) -> anyhow::Result<Self> {
+        Self::open(Self::path_for(wallet))
+    }
+
+    /// Open an audit log, verifying the existing entries
+    pub fn open<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let entries = if path.exists() { Self::read(&path)? } else { Vec::new() };
+        verify(&entries)?;
+
+        let (next_seq, last_hash) = match entries.last() {
+            Some(last) => (last.seq + 1, decode_hash(&last.hash)?),
+            None => (0, [0u8; 32]),
+        };
+
+        Ok(Self { path, next_seq, last_hash })
+    }
+
+    /// Append an event
+    pub fn record(&mut self, event: AuditEvent) -> anyhow::Result<AuditEntry> {
+        let timestamp = chrono::Utc::now().to_rfc3339();
+        let hash = AuditEntry::compute_hash(self.next_seq, &timestamp, &event, &self.last_hash);
+        let entry = AuditEntry {
+            seq: self.next_seq,
+            timestamp,
+            event,
+            prev: hex::encode(self.last_hash),
+            hash: hex::encode(hash),
+        };
+
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(&entry)?)?;
+        file.sync_data()?;
+
+        self.next_seq += 1;
+        self.last_hash = hash;
+        Ok(entry)
+    }
+
+    /// Path of the log file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Read every entry without verifying the chain
+    pub fn read<P: AsRef<Path>>(path: P) -> anyhow::Result<Vec<AuditEntry>> {
+        let content = std::fs::read_to_string(path)?;
+        content
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .enumerate()
+            .map(|(i, line)| {
+                serde_json::from_str(line)
+                    .map_err(|e| anyhow::anyhow!("Malformed audit entry on line {}: {}", i + 1, e))
+            })
+            .collect()
+    }
+}
+
+/// Check the hash chain of a sequence of entries
+pub fn verify(entries: &[AuditEntry]) -> anyhow::Result<()> {
+    let mut prev = [0u8; 32];
+    for (i, entry) in entries.iter().enumerate() {
+        if entry.seq != i as u64 || decode_hash(&entry.prev)? != prev {
+            anyhow::bail!("Audit log tampered: entry {} is out of sequence", i);
+        }
+
+        let hash = AuditEntry::compute_hash(entry.seq, &entry.timestamp, &entry.event, &prev);
+        if hex::encode(hash) != entry.hash {
+            anyhow::bail!("Audit log tampered: entry {} does not match its hash", i);
+        }
+        prev = hash;
+    }
+    Ok(())
+}
+
+fn decode_hash(hex_hash: &str) -> anyhow::Result<[u8; 32]> {
+    hex::decode(hex_hash)?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Audit hash must be 32 bytes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_log(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("aequitas-audit-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn test_record_and_reopen() {
+        let path = temp_log("reopen");
+        let mut log = AuditLog::open(&path).unwrap();
+        log.record(AuditEvent::Unlock { address: "AEQa".into() }).unwrap();
+        log.record(AuditEvent::Export { address: "AEQa".into() }).unwrap();
+
+        let mut log = AuditLog::open(&path).unwrap();
+        let entry = log.record(AuditEvent::Import { address: "AEQb".into() }).unwrap();
+        assert_eq!(entry.seq, 2);
+
+        let entries = AuditLog::read(&path).unwrap();
+        assert_eq!(entries.len(), 3);
+        assert!(verify(&entries).is_ok());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_tampering_is_detected() {
+        let path = temp_log("tamper");
+        let mut log = AuditLog::open(&path).unwrap();
+        for amount in [10, 20, 30] {
+            log.record(AuditEvent::Send {
+                txid: "00".repeat(32),
+                from: "AEQa".into(),
+                to: "AEQb".into(),
+                amount,
+                fee: 1,
+            }).unwrap();
+        }
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(&path, content.replacen("\"amount\":20", "\"amount\":2", 1)).unwrap();
+        assert!(verify(&AuditLog::read(&path).unwrap()).is_err());
+        assert!(AuditLog::open(&path).is_err());
+
+        let mut lines: Vec<&str> = content.lines().collect();
+        lines.remove(1);
+        std::fs::write(&path, lines.join("\n")).unwrap();
+        assert!(verify(&AuditLog::read(&path).unwrap()).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_path_for_wallet() {
+        assert_eq!(
+            AuditLog::path_for("/tmp/keys/wallet.json"),
+            PathBuf::from("/tmp/keys/wallet.json.audit.log"),
+        );
+    }
+}
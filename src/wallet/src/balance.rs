@@ -0,0 +1,176 @@
+//! Confirmed and pending balances
+//!
+//! The node's `/v1/balance/{address}/details` endpoint reports the unspent
+//! outputs of an address with their confirmations, and the mempool
+//! transactions paying to it or spending from it. [`BalanceInfo`] adds them
+//! up: what is confirmed, what is on its way in and what is on its way out.
+
+use serde::{Deserialize, Serialize};
+use aequitas_core::{Address, Blockchain};
+
+/// Path of the balance details of `address` on the node
+pub fn balance_details_path(address: &str) -> String {
+    format!("/v1/balance/{}/details", address)
+}
+
+/// Unspent output of an address
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UtxoInfo {
+    /// Transaction paying it (hex)
+    pub txid: String,
+
+    /// Output index in that transaction
+    pub output_index: u32,
+
+    /// Amount in base units
+    pub amount: u64,
+
+    /// Block holding it, unknown below a pruned chain's first block
+    pub height: Option<u64>,
+
+    /// Blocks from its own to the tip, 1 in the tip
+    pub confirmations: Option<u64>,
+
+    /// A pending transaction spends it
+    #[serde(default)]
+    pub spent_pending: bool,
+}
+
+/// Mempool transaction touching an address
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingTx {
+    /// Transaction id (hex)
+    pub txid: String,
+
+    /// Paid to the address
+    pub incoming: u64,
+
+    /// Spent from the address
+    pub outgoing: u64,
+}
+
+/// Balance of one address
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BalanceInfo {
+    /// Address the balance is of
+    pub address: String,
+
+    /// Chain height it was read at
+    pub height: u64,
+
+    /// Sum of the unspent outputs in the chain
+    pub confirmed: u64,
+
+    /// Paid to the address by pending transactions
+    pub pending_incoming: u64,
+
+    /// Spent from the address by pending transactions
+    pub pending_outgoing: u64,
+
+    /// Unspent outputs, newest first
+    pub utxos: Vec<UtxoInfo>,
+
+    /// Pending transactions touching the address
+    #[serde(default)]
+    pub pending: Vec<PendingTx>,
+}
+
+impl BalanceInfo {
+    /// Confirmed balance read from a local chain, which has no mempool
+    pub fn from_chain(address: &Address, chain: &Blockchain) -> Self {
+        let outputs = chain.get_utxos_for_address(address);
+        let tx_hashes: Vec<[u8; 32]> = outputs.iter().map(|(id, _)| id.tx_hash).collect();
+        let heights = chain.transaction_heights(&tx_hashes);
+
+        let mut utxos: Vec<UtxoInfo> = outputs.into_iter()
+            .map(|(id, output)| {
+                let height = heights.get(&id.tx_hash).copied();
+                UtxoInfo {
+                    txid: hex::encode(id.tx_hash),
+                    output_index: id.output_index,
+                    amount: output.amount,
+                    height,
+                    confirmations: height.map(|h| chain.height() - h + 1),
+                    spent_pending: false,
+                }
+            })
+            .collect();
+        utxos.sort_by(|a, b| b.height.cmp(&a.height).then(a.txid.cmp(&b.txid)).then(a.output_index.cmp(&b.output_index)));
+
+        Self {
+            address: address.to_string(),
+            height: chain.height(),
+            confirmed: utxos.iter().fold(0u64, |total, utxo| total.saturating_add(utxo.amount)),
+            pending_incoming: 0,
+            pending_outgoing: 0,
+            utxos,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Query the balance of `address` from the node at `node`
+    pub async fn fetch(node: &str, address: &str) -> anyhow::Result<Self> {
+        let url = format!("{}{}", node.trim_end_matches('/'), balance_details_path(address));
+        let resp = reqwest::get(&url).await?;
+        if !resp.status().is_success() {
+            anyhow::bail!("Node returned {} for the balance of {}", resp.status(), address);
+        }
+        Ok(resp.json().await?)
+    }
+
+    /// Balance once the pending transactions are mined
+    pub fn total(&self) -> u64 {
+        self.confirmed
+            .saturating_add(self.pending_incoming)
+            .saturating_sub(self.pending_outgoing)
+    }
+
+    /// Confirmed amount not spent by a pending transaction, with at least
+    /// `min_confirmations` confirmations
+    pub fn spendable(&self, min_confirmations: u64) -> u64 {
+        self.utxos.iter()
+            .filter(|utxo| !utxo.spent_pending)
+            .filter(|utxo| utxo.confirmations.map_or(true, |c| c >= min_confirmations))
+            .fold(0u64, |total, utxo| total.saturating_add(utxo.amount))
+    }
+
+    /// Number of unspent outputs
+    pub fn utxo_count(&self) -> usize {
+        self.utxos.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_balance_breakdown() {
+        let balance: BalanceInfo = serde_json::from_str(r#"{
+            "address": "aeq1test",
+            "height": 10,
+            "confirmed": 700,
+            "pending_incoming": 50,
+            "pending_outgoing": 200,
+            "utxos": [
+                {"txid": "bb", "output_index": 0, "amount": 200, "height": 10, "confirmations": 1, "spent_pending": true},
+                {"txid": "aa", "output_index": 1, "amount": 500, "height": 4, "confirmations": 7, "spent_pending": false}
+            ],
+            "pending": [{"txid": "cc", "incoming": 50, "outgoing": 200}]
+        }"#).unwrap();
+
+        assert_eq!(balance.total(), 550);
+        assert_eq!(balance.spendable(1), 500);
+        assert_eq!(balance.spendable(8), 0);
+        assert_eq!(balance.utxo_count(), 2);
+    }
+
+    #[test]
+    fn test_from_chain() {
+        let chain = Blockchain::new();
+        let balance = BalanceInfo::from_chain(&Address::genesis_address(), &chain);
+        assert!(balance.confirmed > 0);
+        assert_eq!(balance.total(), balance.confirmed);
+        assert!(balance.utxos.iter().all(|utxo| utxo.confirmations == Some(1)));
+    }
+}
@@ -2,10 +2,14 @@
 //!
 //! Fluent API for constructing and signing transactions.
 
-use aequitas_core::{Address, Transaction, TxInput, TxOutput, Blockchain};
-use aequitas_core::blockchain::UtxoId;
-use aequitas_core::transaction::TxType;
-use ed25519_dalek::SigningKey;
+use std::collections::{HashMap, HashSet};
+use aequitas_core::{checked_sum, Address, Memo, Transaction, TxInput, TxOutput, Blockchain, DUST_THRESHOLD, LOCK_HEIGHT_TX_VERSION};
+use aequitas_core::blockchain::{UtxoId, UtxoView};
+use aequitas_core::governance::TreasurySpendPayload;
+use aequitas_core::transaction::{encode_output_memos, MultisigPolicy, MultisigSignature, TxType};
+use ed25519_dalek::{PUBLIC_KEY_LENGTH, SIGNATURE_LENGTH};
+use crate::fees::FeeEstimates;
+use crate::hw::Signer;
 
 /// Minimum transaction fee (in smallest units)
 pub const MIN_FEE: u64 = 1000; // 0.000001 AEQ
@@ -13,19 +17,89 @@ pub const MIN_FEE: u64 = 1000; // 0.000001 AEQ
 /// Fee per byte (for fee estimation)
 pub const FEE_PER_BYTE: u64 = 10;
 
+/// Rounds of fee adjustment before giving up on an exact fee
+const MAX_FEE_ROUNDS: usize = 8;
+
+/// Fee for a transaction of `size` encoded bytes
+pub fn fee_for_size(size: usize) -> u64 {
+    fee_at_rate(size, FEE_PER_BYTE)
+}
+
+/// Fee for a transaction of `size` encoded bytes at `fee_rate` per byte
+pub fn fee_at_rate(size: usize, fee_rate: u64) -> u64 {
+    (size as u64).saturating_mul(fee_rate).max(MIN_FEE)
+}
+
+/// Fee for a transaction of `size` bytes whose unconfirmed ancestors pay
+/// `ancestor_fee` for `ancestor_size` bytes, so that the whole package
+/// pays `fee_rate` (child pays for parent)
+///
+/// Never less than the transaction's own fee at that rate.
+pub fn package_fee(size: usize, fee_rate: u64, ancestor_fee: u64, ancestor_size: usize) -> u64 {
+    let package = fee_at_rate(size.saturating_add(ancestor_size), fee_rate).saturating_sub(ancestor_fee);
+    package.max(fee_at_rate(size, fee_rate))
+}
+
+/// Unspent outputs of the chain and of pending transactions
+struct PendingOutputs<'a> {
+    chain: &'a Blockchain,
+    outputs: HashMap<UtxoId, TxOutput>,
+}
+
+impl UtxoView for PendingOutputs<'_> {
+    fn utxo(&self, id: &UtxoId) -> Option<&TxOutput> {
+        self.chain.get_utxo(id).or_else(|| self.outputs.get(id))
+    }
+}
+
+/// A pending transaction the builder may spend from, with its fee
+struct PendingParent<'a> {
+    transaction: &'a Transaction,
+    txid: [u8; 32],
+    fee: u64,
+}
+
 /// Transaction builder
+#[derive(Clone)]
 pub struct TransactionBuilder {
     /// Sender address
     from: Option<Address>,
     
+    /// Policy of the sender, when spending multisig outputs
+    multisig: Option<MultisigPolicy>,
+    
     /// Recipients and amounts
     outputs: Vec<(Address, u64)>,
     
     /// Explicit fee (if not set, calculated automatically)
     fee: Option<u64>,
     
+    /// Blocks to be mined within, priced from `fee_estimates`
+    fee_target: Option<u32>,
+    
+    /// Node fee estimates for `fee_target`
+    fee_estimates: Option<FeeEstimates>,
+    
     /// Memo data
     memo: Vec<u8>,
+    
+    /// Memos attached to individual outputs, by output index
+    output_memos: Vec<(u8, Vec<u8>)>,
+    
+    /// Where change goes (the sender if not set)
+    change_to: Option<Address>,
+    
+    /// Opt in to replace-by-fee
+    replaceable: bool,
+    
+    /// Only valid in blocks above this height (the chain tip if not set)
+    lock_height: Option<u64>,
+    
+    /// Pending transactions whose outputs may be spent
+    unconfirmed: Vec<Transaction>,
+    
+    /// Recipient of everything left once outputs and fee are paid
+    send_max: Option<Address>,
 }
 
 impl TransactionBuilder {
@@ -33,9 +107,18 @@ impl TransactionBuilder {
     pub fn new() -> Self {
         Self {
             from: None,
+            multisig: None,
             outputs: Vec::new(),
             fee: None,
+            fee_target: None,
+            fee_estimates: None,
             memo: Vec::new(),
+            output_memos: Vec::new(),
+            change_to: None,
+            replaceable: false,
+            lock_height: None,
+            unconfirmed: Vec::new(),
+            send_max: None,
         }
     }
     
@@ -45,12 +128,72 @@ impl TransactionBuilder {
         self
     }
     
+    /// Spend the outputs locked by `policy`
+    ///
+    /// Sets the sender to the policy's address; change returns there.
+    pub fn from_multisig(mut self, policy: MultisigPolicy) -> Self {
+        self.from = Some(policy.address());
+        self.multisig = Some(policy);
+        self
+    }
+    
     /// Add recipient
     pub fn to(mut self, address: Address, amount: u64) -> Self {
         self.outputs.push((address, amount));
         self
     }
     
+    /// Add a recipient with a memo for that output
+    ///
+    /// Output memos are packed into the transaction memo (see
+    /// [`encode_output_memos`]), so they cannot be combined with [`memo`](Self::memo).
+    pub fn to_with_memo(mut self, address: Address, amount: u64, memo: &str) -> Self {
+        self.output_memos.push((self.outputs.len() as u8, memo.as_bytes().to_vec()));
+        self.outputs.push((address, amount));
+        self
+    }
+    
+    /// Send everything the sender holds to `address`
+    ///
+    /// Every spendable output is used, and `address` receives what is left
+    /// after the other recipients and the fee, as the last output: the fee
+    /// comes out of that amount and there is never any change. Building
+    /// fails if what is left would be dust.
+    pub fn send_max(mut self, address: Address) -> Self {
+        self.send_max = Some(address);
+        self
+    }
+    
+    /// Send change to `address` instead of back to the sender
+    pub fn change_to(mut self, address: Address) -> Self {
+        self.change_to = Some(address);
+        self
+    }
+    
+    /// Sender address, if set
+    pub fn sender(&self) -> Option<&Address> {
+        self.from.as_ref()
+    }
+    
+    /// Change address, if set
+    pub fn change_address(&self) -> Option<&Address> {
+        self.change_to.as_ref()
+    }
+    
+    /// Memo written into the transaction
+    fn transaction_memo(&self) -> anyhow::Result<Vec<u8>> {
+        if self.output_memos.is_empty() {
+            return Ok(self.memo.clone());
+        }
+        if !self.memo.is_empty() {
+            anyhow::bail!("Use either a transaction memo or output memos, not both");
+        }
+        if self.outputs.len() > u8::MAX as usize + 1 {
+            anyhow::bail!("Output memos support at most 256 outputs");
+        }
+        Ok(encode_output_memos(&self.output_memos)?)
+    }
+    
     /// Add multiple recipients
     pub fn to_many(mut self, recipients: Vec<(Address, u64)>) -> Self {
         self.outputs.extend(recipients);
@@ -63,6 +206,29 @@ impl TransactionBuilder {
         self
     }
     
+    /// Price the fee to be mined within `blocks` blocks
+    ///
+    /// The rate comes from the [`fee_estimates`](Self::fee_estimates); without
+    /// them, or with an explicit [`fee`](Self::fee), the target is ignored.
+    pub fn fee_rate_target(mut self, blocks: u32) -> Self {
+        self.fee_target = Some(blocks);
+        self
+    }
+    
+    /// Use `estimates` from a node to price [`fee_rate_target`](Self::fee_rate_target)
+    pub fn fee_estimates(mut self, estimates: FeeEstimates) -> Self {
+        self.fee_estimates = Some(estimates);
+        self
+    }
+    
+    /// Fee per byte the fee is priced at without an explicit fee
+    pub fn fee_rate(&self) -> u64 {
+        match (self.fee_target, &self.fee_estimates) {
+            (Some(blocks), Some(estimates)) => estimates.fee_rate(blocks),
+            _ => FEE_PER_BYTE,
+        }
+    }
+    
     /// Set memo
     pub fn memo(mut self, memo: Vec<u8>) -> Self {
         self.memo = memo;
@@ -75,55 +241,199 @@ impl TransactionBuilder {
         self
     }
     
+    /// Set a typed memo (see [`Memo`])
+    pub fn typed_memo(mut self, memo: &Memo) -> anyhow::Result<Self> {
+        self.memo = memo.encode()?;
+        Ok(self)
+    }
+    
+    /// Allow the transaction to be replaced by a higher-fee version
+    pub fn replaceable(mut self, replaceable: bool) -> Self {
+        self.replaceable = replaceable;
+        self
+    }
+    
+    /// Only allow the transaction in blocks above `height`
+    ///
+    /// Defaults to the chain tip, so that miners re-mining past blocks
+    /// cannot claim the fee.
+    pub fn lock_height(mut self, height: u64) -> Self {
+        self.lock_height = Some(height);
+        self
+    }
+    
+    /// Also spend the sender's outputs of these pending transactions
+    ///
+    /// Confirmed outputs are used first, and those the pending transactions
+    /// already spend are left alone. When an unconfirmed output is spent,
+    /// the fee is raised so that the new transaction and its unconfirmed
+    /// ancestors together pay the [`fee_rate`](Self::fee_rate) (see
+    /// [`package_fee`]). Transactions already confirmed or no longer valid
+    /// against `chain` are ignored.
+    pub fn spend_unconfirmed(mut self, transactions: Vec<Transaction>) -> Self {
+        self.unconfirmed = transactions;
+        self
+    }
+    
+    /// Pending transactions still unconfirmed and valid on `chain`, with
+    /// the outputs they add
+    fn pending<'a>(&'a self, chain: &'a Blockchain) -> (PendingOutputs<'a>, Vec<PendingParent<'a>>) {
+        let mut parents: Vec<(&Transaction, [u8; 32])> = self.unconfirmed.iter()
+            .map(|tx| (tx, tx.txid()))
+            .filter(|(tx, txid)| (0..tx.outputs.len() as u32).all(|i| chain.get_utxo(&UtxoId::new(*txid, i)).is_none()))
+            .collect();
+        
+        // Dropping a parent may invalidate its children, so repeat until stable
+        loop {
+            let outputs = parents.iter()
+                .flat_map(|(tx, txid)| tx.outputs.iter().enumerate()
+                    .map(move |(i, output)| (UtxoId::new(*txid, i as u32), output.clone())))
+                .collect();
+            let view = PendingOutputs { chain, outputs };
+            let fees: Vec<Option<u64>> = parents.iter().map(|(tx, _)| tx.fee(&view)).collect();
+            if fees.iter().all(Option::is_some) {
+                let parents = parents.into_iter().zip(fees)
+                    .map(|((transaction, txid), fee)| PendingParent { transaction, txid, fee: fee.unwrap_or_default() })
+                    .collect();
+                return (view, parents);
+            }
+            parents = parents.into_iter().zip(fees)
+                .filter_map(|(parent, fee)| fee.map(|_| parent))
+                .collect();
+        }
+    }
+    
+    /// Outputs of `from` left unspent by `parents`: confirmed ones first,
+    /// then those the parents add
+    fn spendable(from: &Address, chain: &Blockchain, parents: &[PendingParent<'_>]) -> Vec<(UtxoId, TxOutput)> {
+        let spent: HashSet<UtxoId> = parents.iter()
+            .flat_map(|parent| &parent.transaction.inputs)
+            .map(|input| UtxoId::new(input.prev_tx_hash, input.output_index))
+            .collect();
+        let mut utxos = chain.get_utxos_for_address(from);
+        utxos.extend(parents.iter().flat_map(|parent| parent.transaction.outputs.iter().enumerate()
+            .map(|(i, output)| (UtxoId::new(parent.txid, i as u32), output.clone()))));
+        utxos.retain(|(id, output)| &output.recipient == from && !spent.contains(id));
+        utxos
+    }
+    
+    /// Fee and size of the unconfirmed ancestors of `tx` among `parents`
+    fn ancestor_package(parents: &[PendingParent<'_>], tx: &Transaction) -> (u64, usize) {
+        let mut seen = HashSet::new();
+        let mut pending: Vec<[u8; 32]> = tx.inputs.iter().map(|input| input.prev_tx_hash).collect();
+        let (mut fee, mut size) = (0u64, 0usize);
+        while let Some(txid) = pending.pop() {
+            if !seen.insert(txid) {
+                continue;
+            }
+            if let Some(parent) = parents.iter().find(|parent| parent.txid == txid) {
+                fee = fee.saturating_add(parent.fee);
+                size = size.saturating_add(parent.transaction.size());
+                pending.extend(parent.transaction.inputs.iter().map(|input| input.prev_tx_hash));
+            }
+        }
+        (fee, size)
+    }
+    
+    /// Preview of `tx`, built by this builder
+    ///
+    /// Unlike [`TxPreview::new`], `tx` may spend outputs of the pending
+    /// transactions, and the balances count them as confirmed.
+    pub fn preview(&self, tx: &Transaction, recipients: usize, chain: &Blockchain) -> anyhow::Result<TxPreview> {
+        let from = self.from.as_ref().ok_or_else(|| anyhow::anyhow!("Sender address not set"))?;
+        let (view, parents) = self.pending(chain);
+        let balance = Self::spendable(from, chain, &parents).iter()
+            .fold(0u64, |total, (_, output)| total.saturating_add(output.amount));
+        TxPreview::describe(tx, from, recipients, &view, balance, Self::ancestor_package(&parents, tx))
+    }
+    
     /// Calculate total output amount
-    pub fn total_output(&self) -> u64 {
-        self.outputs.iter().map(|(_, a)| a).sum()
+    ///
+    /// Fails on a zero amount without a memo, or if the amounts overflow or
+    /// exceed the maximum supply together.
+    pub fn total_output(&self) -> anyhow::Result<u64> {
+        for (index, (_, amount)) in self.outputs.iter().enumerate() {
+            if *amount == 0 && !self.output_memos.iter().any(|(i, _)| *i as usize == index) {
+                anyhow::bail!("Output {} has a zero amount", index);
+            }
+        }
+        checked_sum(self.outputs.iter().map(|(_, amount)| *amount))
+            .ok_or_else(|| anyhow::anyhow!("Outputs exceed the maximum supply"))
     }
     
     /// Estimate transaction size
     pub fn estimate_size(&self) -> usize {
         // Base tx size + inputs + outputs
-        100 + self.outputs.len() * 40 + self.memo.len()
+        let memo_len = self.transaction_memo().map_or(self.memo.len(), |memo| memo.len());
+        let outputs = self.outputs.len() + usize::from(self.send_max.is_some());
+        100 + outputs * 40 + memo_len
     }
     
     /// Estimate fee for this transaction
     pub fn estimate_fee(&self) -> u64 {
-        let size = self.estimate_size();
-        let calculated = (size as u64 * FEE_PER_BYTE).max(MIN_FEE);
-        self.fee.unwrap_or(calculated)
+        self.fee.unwrap_or_else(|| fee_at_rate(self.estimate_size(), self.fee_rate()))
+    }
+    
+    /// Build and sign, pricing the fee on the final encoded size
+    ///
+    /// Without an explicit fee, the fee covers the [`fee_rate`](Self::fee_rate)
+    /// for every byte of the signed encoding (see
+    /// [`build_unsigned_exact`](Self::build_unsigned_exact)).
+    pub fn build_and_sign_exact(
+        self,
+        signer: &dyn Signer,
+        chain: &Blockchain,
+    ) -> anyhow::Result<Transaction> {
+        let mut unsigned = self.build_unsigned_exact(chain)?;
+        unsigned.sign(signer)?;
+        Ok(unsigned.into_transaction())
     }
     
     /// Build and sign the transaction
+    ///
+    /// `signer` is asked once for the whole transaction, so a hardware
+    /// signer (see [`crate::hw`]) confirms it once however many inputs it has.
     pub fn build_and_sign(
         self,
-        signing_key: &SigningKey,
+        signer: &dyn Signer,
         chain: &Blockchain,
     ) -> anyhow::Result<Transaction> {
+        let mut unsigned = self.build_unsigned(chain)?;
+        unsigned.sign(signer)?;
+        Ok(unsigned.into_transaction())
+    }
+    
+    /// Build an unsigned transaction (for multi-sig or external signing)
+    pub fn build_unsigned(
+        self,
+        chain: &Blockchain,
+    ) -> anyhow::Result<UnsignedTransaction> {
         let from = self.from.clone().ok_or_else(|| anyhow::anyhow!("Sender address not set"))?;
         
-        if self.outputs.is_empty() {
+        if self.outputs.is_empty() && self.send_max.is_none() {
             anyhow::bail!("No recipients specified");
         }
+        let memo = self.transaction_memo()?;
         
-        // Get UTXOs for sender
-        let utxos = chain.get_utxos_for_address(&from);
+        let (_, parents) = self.pending(chain);
+        let utxos = Self::spendable(&from, chain, &parents);
         if utxos.is_empty() {
             anyhow::bail!("No UTXOs found for sender");
         }
         
-        // Calculate required amount (outputs + fee)
         let fee = self.estimate_fee();
-        let total_needed = self.total_output() + fee;
+        let total_needed = checked_sum([self.total_output()?, fee])
+            .ok_or_else(|| anyhow::anyhow!("Amount plus fee exceeds the maximum supply"))?;
         
-        // Select UTXOs (simple greedy selection)
+        // Select UTXOs
         let mut selected_utxos: Vec<(UtxoId, TxOutput)> = Vec::new();
         let mut selected_amount: u64 = 0;
         
         for (utxo_id, output) in utxos {
             selected_utxos.push((utxo_id, output.clone()));
-            selected_amount += output.amount;
+            selected_amount = selected_amount.saturating_add(output.amount);
             
-            if selected_amount >= total_needed {
+            if self.send_max.is_none() && selected_amount >= total_needed {
                 break;
             }
         }
@@ -136,99 +446,177 @@ impl TransactionBuilder {
             );
         }
         
-        // Create inputs
-        let mut inputs: Vec<TxInput> = selected_utxos.iter()
-            .map(|(utxo_id, _)| TxInput::new(utxo_id.tx_hash, utxo_id.output_index))
+        // Build transaction
+        let inputs: Vec<TxInput> = selected_utxos.iter()
+            .map(|(utxo_id, _)| match &self.multisig {
+                Some(policy) => TxInput::new_multisig(utxo_id.tx_hash, utxo_id.output_index, policy.clone()),
+                None => TxInput::new(utxo_id.tx_hash, utxo_id.output_index),
+            })
             .collect();
         
-        // Create outputs
         let mut tx_outputs: Vec<TxOutput> = self.outputs.iter()
             .map(|(addr, amount)| TxOutput::new(addr.clone(), *amount))
             .collect();
         
-        // Add change output if needed
         let change = selected_amount - total_needed;
-        if change > 0 {
-            tx_outputs.push(TxOutput::new(from.clone(), change));
+        if let Some(to) = &self.send_max {
+            if change < DUST_THRESHOLD {
+                anyhow::bail!(
+                    "Nothing to send: {} left after outputs and a fee of {} is below the dust threshold of {}",
+                    change,
+                    fee,
+                    DUST_THRESHOLD
+                );
+            }
+            tx_outputs.push(TxOutput::new(to.clone(), change));
+        } else if change >= DUST_THRESHOLD {
+            tx_outputs.push(TxOutput::new(self.change_to.clone().unwrap_or_else(|| from.clone()), change));
         }
         
-        // Create transaction
-        let mut tx = Transaction {
-            version: 1,
+        let tx = Transaction {
+            version: LOCK_HEIGHT_TX_VERSION,
             tx_type: TxType::Transfer,
             inputs,
             outputs: tx_outputs,
             timestamp: chrono::Utc::now().timestamp(),
-            memo: self.memo,
+            memo,
+            replaceable: self.replaceable,
+            lock_height: self.lock_height.unwrap_or_else(|| chain.height()),
         };
         
-        // Sign each input
-        let message = tx.signing_message();
-        for input in &mut tx.inputs {
-            input.sign(signing_key, &message);
+        Ok(UnsignedTransaction {
+            transaction: tx,
+            signing_message: Vec::new(), // Will be computed when signing
+        })
+    }
+    
+    /// Build an unsigned transaction, its fee priced on the size it will
+    /// have once signed
+    ///
+    /// The counterpart of [`build_and_sign_exact`](Self::build_and_sign_exact)
+    /// for transactions signed elsewhere.
+    pub fn build_unsigned_exact(self, chain: &Blockchain) -> anyhow::Result<UnsignedTransaction> {
+        if self.fee.is_some() {
+            return self.build_unsigned(chain);
         }
         
-        Ok(tx)
+        let fee_rate = self.fee_rate();
+        let (_, parents) = self.pending(chain);
+        let mut fee = MIN_FEE;
+        for _ in 0..MAX_FEE_ROUNDS {
+            let unsigned = self.clone().fee(fee).build_unsigned(chain)?;
+            let (ancestor_fee, ancestor_size) = Self::ancestor_package(&parents, &unsigned.transaction);
+            let required = package_fee(unsigned.signed_size(), fee_rate, ancestor_fee, ancestor_size);
+            if required <= fee {
+                return Ok(unsigned);
+            }
+            fee = required;
+        }
+        anyhow::bail!("Fee did not settle after {} rounds", MAX_FEE_ROUNDS)
     }
     
-    /// Build an unsigned transaction (for multi-sig or external signing)
-    pub fn build_unsigned(
-        self,
+    /// Build an unsigned replacement that cancels a pending transaction
+    ///
+    /// Spends the same inputs back to `refund_to` minus `fee`, which must
+    /// exceed the original fee. The original must have been replaceable.
+    pub fn cancel(
+        original: &Transaction,
         chain: &Blockchain,
+        refund_to: Address,
+        fee: u64,
     ) -> anyhow::Result<UnsignedTransaction> {
-        let from = self.from.clone().ok_or_else(|| anyhow::anyhow!("Sender address not set"))?;
+        if !original.replaceable {
+            anyhow::bail!("Transaction did not opt in to replace-by-fee");
+        }
         
-        if self.outputs.is_empty() {
-            anyhow::bail!("No recipients specified");
+        let original_fee = chain.transaction_fee(original)
+            .ok_or_else(|| anyhow::anyhow!("Transaction inputs are no longer unspent"))?;
+        if fee <= original_fee {
+            anyhow::bail!("Cancellation fee {} must exceed original fee {}", fee, original_fee);
         }
         
-        let utxos = chain.get_utxos_for_address(&from);
-        let fee = self.estimate_fee();
-        let total_needed = self.total_output() + fee;
+        let input_total = checked_sum([original_fee, original.total_output()?])
+            .ok_or_else(|| anyhow::anyhow!("Transaction amounts exceed the maximum supply"))?;
+        let refund = input_total.checked_sub(fee)
+            .filter(|r| *r > 0)
+            .ok_or_else(|| anyhow::anyhow!("Fee {} leaves nothing to refund", fee))?;
         
-        // Select UTXOs
-        let mut selected_utxos: Vec<(UtxoId, TxOutput)> = Vec::new();
-        let mut selected_amount: u64 = 0;
+        let inputs: Vec<TxInput> = original.inputs.iter()
+            .map(|input| match &input.multisig {
+                Some(policy) => TxInput::new_multisig(input.prev_tx_hash, input.output_index, policy.clone()),
+                None => TxInput::new(input.prev_tx_hash, input.output_index),
+            })
+            .collect();
         
-        for (utxo_id, output) in utxos {
-            selected_utxos.push((utxo_id, output.clone()));
-            selected_amount += output.amount;
-            
+        let tx = Transaction {
+            version: LOCK_HEIGHT_TX_VERSION,
+            tx_type: TxType::Transfer,
+            inputs,
+            outputs: vec![TxOutput::new(refund_to, refund)],
+            timestamp: chrono::Utc::now().timestamp(),
+            memo: Vec::new(),
+            replaceable: true,
+            lock_height: chain.height(),
+        };
+        
+        Ok(UnsignedTransaction {
+            transaction: tx,
+            signing_message: Vec::new(),
+        })
+    }
+    
+    /// Build the treasury payout of an approved proposal
+    ///
+    /// Needs no signatures: the passed proposal authorizes the spend. Pays
+    /// the approved amount to the proposer and returns change to the treasury.
+    pub fn treasury_spend(
+        chain: &Blockchain,
+        proposal_id: [u8; 32],
+        fee: u64,
+    ) -> anyhow::Result<Transaction> {
+        let proposal = chain.governance().get(&proposal_id)
+            .ok_or_else(|| anyhow::anyhow!("Unknown proposal"))?;
+        if proposal.executed.is_some() {
+            anyhow::bail!("Proposal has already been paid out");
+        }
+        
+        let treasury = chain.treasury_address().clone();
+        let total_needed = checked_sum([proposal.amount, fee])
+            .ok_or_else(|| anyhow::anyhow!("Proposal amount plus fee exceeds the maximum supply"))?;
+        
+        let mut inputs = Vec::new();
+        let mut selected_amount: u64 = 0;
+        for (utxo_id, output) in chain.get_utxos_for_address(&treasury) {
             if selected_amount >= total_needed {
                 break;
             }
+            inputs.push(TxInput::new(utxo_id.tx_hash, utxo_id.output_index));
+            selected_amount = selected_amount.saturating_add(output.amount);
         }
         
         if selected_amount < total_needed {
-            anyhow::bail!("Insufficient funds");
+            anyhow::bail!(
+                "Insufficient treasury funds: have {} but need {}",
+                selected_amount,
+                total_needed
+            );
         }
         
-        // Build transaction
-        let inputs: Vec<TxInput> = selected_utxos.iter()
-            .map(|(utxo_id, _)| TxInput::new(utxo_id.tx_hash, utxo_id.output_index))
-            .collect();
-        
-        let mut tx_outputs: Vec<TxOutput> = self.outputs.iter()
-            .map(|(addr, amount)| TxOutput::new(addr.clone(), *amount))
-            .collect();
-        
+        let mut outputs = vec![TxOutput::new(proposal.proposer.clone(), proposal.amount)];
         let change = selected_amount - total_needed;
-        if change > 0 {
-            tx_outputs.push(TxOutput::new(from.clone(), change));
+        if change >= DUST_THRESHOLD {
+            outputs.push(TxOutput::new(treasury, change));
         }
         
-        let tx = Transaction {
-            version: 1,
-            tx_type: TxType::Transfer,
+        Ok(Transaction {
+            version: LOCK_HEIGHT_TX_VERSION,
+            tx_type: TxType::TreasurySpend,
             inputs,
-            outputs: tx_outputs,
+            outputs,
             timestamp: chrono::Utc::now().timestamp(),
-            memo: self.memo,
-        };
-        
-        Ok(UnsignedTransaction {
-            transaction: tx,
-            signing_message: Vec::new(), // Will be computed when signing
+            memo: TreasurySpendPayload { proposal_id }.to_memo(),
+            replaceable: false,
+            lock_height: chain.height(),
         })
     }
 }
@@ -239,6 +627,105 @@ impl Default for TransactionBuilder {
     }
 }
 
+/// What a signed transfer does, for review before broadcasting
+#[derive(Clone, Debug)]
+pub struct TxPreview {
+    /// Outputs consumed, with their amounts
+    pub inputs: Vec<(UtxoId, u64)>,
+    
+    /// Recipients and amounts, change excluded
+    pub outputs: Vec<(Address, u64)>,
+    
+    /// Change amount
+    pub change: u64,
+    
+    /// Where the change goes, if there is any
+    pub change_address: Option<Address>,
+    
+    /// Inputs minus outputs
+    pub fee: u64,
+    
+    /// Size of the signed encoding in bytes
+    pub size: usize,
+    
+    /// Sender balance before the transaction
+    pub balance_before: u64,
+    
+    /// Sender balance once the transaction confirms
+    pub balance_after: u64,
+    
+    /// Fee of the transaction and its unconfirmed ancestors
+    pub package_fee: u64,
+    
+    /// Size of the transaction and its unconfirmed ancestors
+    pub package_size: usize,
+}
+
+impl TxPreview {
+    /// Describe a transaction from `from` whose first `recipients` outputs are payments
+    pub fn new(tx: &Transaction, from: &Address, recipients: usize, chain: &Blockchain) -> anyhow::Result<Self> {
+        Self::describe(tx, from, recipients, chain, chain.get_balance(from), (0, 0))
+    }
+    
+    fn describe(
+        tx: &Transaction,
+        from: &Address,
+        recipients: usize,
+        utxos: &impl UtxoView,
+        balance_before: u64,
+        (ancestor_fee, ancestor_size): (u64, usize),
+    ) -> anyhow::Result<Self> {
+        let inputs = tx.inputs.iter()
+            .map(|input| {
+                let id = UtxoId::new(input.prev_tx_hash, input.output_index);
+                let amount = utxos.utxo(&id)
+                    .map(|output| output.amount)
+                    .ok_or_else(|| anyhow::anyhow!("Input {}:{} is not unspent", hex::encode(id.tx_hash), id.output_index))?;
+                Ok((id, amount))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        
+        let recipients = recipients.min(tx.outputs.len());
+        let outputs: Vec<(Address, u64)> = tx.outputs[..recipients].iter()
+            .map(|output| (output.recipient.clone(), output.amount))
+            .collect();
+        let overflow = || anyhow::anyhow!("Amounts exceed the maximum supply");
+        let change = checked_sum(tx.outputs[recipients..].iter().map(|output| output.amount))
+            .ok_or_else(overflow)?;
+        let change_address = tx.outputs[recipients..].first().map(|output| output.recipient.clone());
+        let change_to_self = if change_address.as_ref() == Some(from) { change } else { 0 };
+        
+        let spent = checked_sum(inputs.iter().map(|(_, amount)| *amount)).ok_or_else(overflow)?;
+        let fee = tx.fee(utxos)
+            .ok_or_else(|| anyhow::anyhow!("Outputs exceed inputs"))?;
+        let to_self = checked_sum(outputs.iter().filter(|(addr, _)| addr == from).map(|(_, amount)| *amount))
+            .ok_or_else(overflow)?;
+        
+        Ok(Self {
+            inputs,
+            outputs,
+            change,
+            change_address,
+            fee,
+            size: tx.size(),
+            balance_before,
+            balance_after: balance_before.saturating_add(change_to_self).saturating_add(to_self).saturating_sub(spent),
+            package_fee: fee.saturating_add(ancestor_fee),
+            package_size: tx.size().saturating_add(ancestor_size),
+        })
+    }
+    
+    /// Fee paid per encoded byte
+    pub fn fee_rate(&self) -> f64 {
+        self.fee as f64 / self.size.max(1) as f64
+    }
+    
+    /// Fee per byte of the transaction and its unconfirmed ancestors
+    pub fn package_fee_rate(&self) -> f64 {
+        self.package_fee as f64 / self.package_size.max(1) as f64
+    }
+}
+
 /// Unsigned transaction for external signing
 pub struct UnsignedTransaction {
     /// The unsigned transaction
@@ -262,9 +749,57 @@ impl UnsignedTransaction {
         }
     }
     
+    /// Add one co-signer's signature to a multisig input
+    pub fn add_multisig_signature(&mut self, input_index: usize, key_index: u8, signature: Vec<u8>) {
+        if let Some(input) = self.transaction.inputs.get_mut(input_index) {
+            let signature = MultisigSignature { key_index, signature };
+            match input.multisig_signatures.binary_search_by_key(&key_index, |s| s.key_index) {
+                Ok(pos) => input.multisig_signatures[pos] = signature,
+                Err(pos) => input.multisig_signatures.insert(pos, signature),
+            }
+        }
+    }
+    
+    /// Sign every unsigned single-key input with `signer`
+    ///
+    /// Inputs all sign the same message, so `signer` signs once.
+    pub fn sign(&mut self, signer: &dyn Signer) -> anyhow::Result<()> {
+        let message = self.transaction.signing_message();
+        let signature = signer.sign(&message)?;
+        let public_key = signer.public_key()?;
+        
+        for input in &mut self.transaction.inputs {
+            if input.multisig.is_none() && !input.is_signed() {
+                input.signature = signature.to_vec();
+                input.public_key = public_key.to_vec();
+            }
+        }
+        Ok(())
+    }
+    
+    /// Encoded size once every input is signed, multisig inputs by
+    /// exactly their threshold of keys
+    pub fn signed_size(&self) -> usize {
+        let mut tx = self.transaction.clone();
+        for input in &mut tx.inputs {
+            match &input.multisig {
+                Some(policy) => {
+                    input.multisig_signatures = (0..policy.threshold)
+                        .map(|key_index| MultisigSignature { key_index, signature: vec![0; SIGNATURE_LENGTH] })
+                        .collect();
+                }
+                None => {
+                    input.signature = vec![0; SIGNATURE_LENGTH];
+                    input.public_key = vec![0; PUBLIC_KEY_LENGTH];
+                }
+            }
+        }
+        tx.size()
+    }
+    
     /// Check if fully signed
     pub fn is_fully_signed(&self) -> bool {
-        self.transaction.inputs.iter().all(|i| !i.signature.is_empty())
+        self.transaction.inputs.iter().all(|i| i.is_signed())
     }
     
     /// Convert to signed transaction
@@ -276,6 +811,8 @@ impl UnsignedTransaction {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ed25519_dalek::SigningKey;
+    use crate::fees::FeeTarget;
     
     #[test]
     fn test_builder_creation() {
@@ -285,6 +822,11 @@ mod tests {
         
         assert_eq!(builder.fee, Some(1000));
         assert_eq!(builder.memo, b"Test");
+        
+        let reference = Memo::PaymentReference("DEP-42".to_string());
+        let builder = builder.typed_memo(&reference).unwrap();
+        assert_eq!(Memo::decode(&builder.memo), Some(reference));
+        assert!(TransactionBuilder::new().typed_memo(&Memo::Json("{".to_string())).is_err());
     }
     
     #[test]
@@ -295,4 +837,210 @@ mod tests {
         let fee = builder.estimate_fee();
         assert!(fee >= MIN_FEE);
     }
+    
+    #[test]
+    fn test_cancel_requires_replaceable_and_higher_fee() {
+        let chain = Blockchain::new();
+        let genesis_tx = chain.get_block_at_height(0).unwrap().transactions[0].clone();
+        let input_total = genesis_tx.outputs[0].amount;
+        
+        let mut original = Transaction::new_transfer(
+            vec![TxInput::new(genesis_tx.hash(), 0)],
+            vec![TxOutput::new(Address::genesis_address(), input_total - 1000)],
+        );
+        let refund_to = Address::genesis_address();
+        assert!(TransactionBuilder::cancel(&original, &chain, refund_to.clone(), 5000).is_err());
+        
+        original.replaceable = true;
+        assert!(TransactionBuilder::cancel(&original, &chain, refund_to.clone(), 1000).is_err());
+        
+        let cancel = TransactionBuilder::cancel(&original, &chain, refund_to, 5000).unwrap();
+        assert!(cancel.transaction.replaceable);
+        assert!(cancel.transaction.conflicts_with(&original));
+        assert_eq!(chain.transaction_fee(&cancel.transaction), Some(5000));
+    }
+    
+    #[test]
+    fn test_exact_fee_and_preview() {
+        let chain = Blockchain::new();
+        let from = Address::genesis_address();
+        let balance = chain.get_balance(&from);
+        let key = SigningKey::from_bytes(&[5u8; 32]);
+        let recipient = Address::from_public_key(&key.verifying_key());
+        
+        let tx = TransactionBuilder::new()
+            .from(from.clone())
+            .to(recipient.clone(), 1_000_000)
+            .build_and_sign_exact(&key, &chain)
+            .unwrap();
+        
+        let preview = TxPreview::new(&tx, &from, 1, &chain).unwrap();
+        assert_eq!(preview.fee, fee_for_size(tx.size()));
+        assert_eq!(preview.size, tx.size());
+        assert_eq!(preview.outputs, vec![(recipient.clone(), 1_000_000)]);
+        assert_eq!(preview.balance_after, balance - 1_000_000 - preview.fee);
+        assert!(preview.fee_rate() >= FEE_PER_BYTE as f64);
+        
+        // Locked to the tip unless told otherwise
+        assert_eq!((tx.version, tx.lock_height), (LOCK_HEIGHT_TX_VERSION, chain.height()));
+        let locked = TransactionBuilder::new()
+            .from(from)
+            .to(recipient, 1_000_000)
+            .lock_height(7)
+            .build_and_sign_exact(&key, &chain)
+            .unwrap();
+        assert_eq!(locked.lock_height, 7);
+    }
+    
+    #[test]
+    fn test_fee_rate_target() {
+        let chain = Blockchain::new();
+        let from = Address::genesis_address();
+        let key = SigningKey::from_bytes(&[5u8; 32]);
+        let recipient = Address::from_public_key(&key.verifying_key());
+        let estimates = FeeEstimates {
+            mempool_size: 2,
+            percentiles: Vec::new(),
+            targets: vec![FeeTarget { blocks: 1, fee_rate: 50 }, FeeTarget { blocks: 2, fee_rate: 0 }],
+        };
+        
+        let builder = TransactionBuilder::new()
+            .from(from.clone())
+            .to(recipient, 1_000_000)
+            .fee_rate_target(1);
+        assert_eq!(builder.fee_rate(), FEE_PER_BYTE);
+        
+        let builder = builder.fee_estimates(estimates);
+        assert_eq!(builder.fee_rate(), 50);
+        assert_eq!(builder.clone().fee_rate_target(6).fee_rate(), FEE_PER_BYTE);
+        
+        let tx = builder.build_and_sign_exact(&key, &chain).unwrap();
+        let preview = TxPreview::new(&tx, &from, 1, &chain).unwrap();
+        assert_eq!(preview.fee, fee_at_rate(tx.size(), 50));
+    }
+    
+    #[test]
+    fn test_change_address_and_output_memos() {
+        let chain = Blockchain::new();
+        let from = Address::genesis_address();
+        let key = SigningKey::from_bytes(&[6u8; 32]);
+        let alice = Address::from_public_key(&key.verifying_key());
+        let change = Address::from_public_key(&SigningKey::from_bytes(&[7u8; 32]).verifying_key());
+        
+        let builder = TransactionBuilder::new()
+            .from(from.clone())
+            .to_with_memo(alice.clone(), 1_000, "order 17")
+            .to(from.clone(), 2_000)
+            .to_with_memo(alice.clone(), 3_000, "order 18")
+            .change_to(change.clone());
+        assert_eq!(builder.change_address(), Some(&change));
+        
+        let tx = builder.clone().build_and_sign_exact(&key, &chain).unwrap();
+        assert_eq!(tx.outputs.len(), 4);
+        assert_eq!(tx.outputs[3].recipient, change);
+        assert_eq!(tx.output_memos(), vec![(0, b"order 17".to_vec()), (2, b"order 18".to_vec())]);
+        
+        let preview = TxPreview::new(&tx, &from, 3, &chain).unwrap();
+        assert_eq!(preview.change_address, Some(change));
+        assert_eq!(preview.balance_after, 2_000);
+        
+        // Output memos and a transaction memo don't mix
+        assert!(builder.memo_str("both").build_and_sign(&key, &chain).is_err());
+    }
+    
+    #[test]
+    fn test_dust_change_goes_to_fee() {
+        let chain = Blockchain::new();
+        let from = Address::genesis_address();
+        let balance = chain.get_balance(&from);
+        let key = SigningKey::from_bytes(&[8u8; 32]);
+        let recipient = Address::from_public_key(&key.verifying_key());
+        let send = |change: u64| TransactionBuilder::new()
+            .from(from.clone())
+            .to(recipient.clone(), balance - MIN_FEE - change)
+            .fee(MIN_FEE)
+            .build_and_sign(&key, &chain)
+            .unwrap();
+        
+        let tx = send(DUST_THRESHOLD - 1);
+        assert_eq!(tx.outputs.len(), 1);
+        assert_eq!(chain.transaction_fee(&tx), Some(MIN_FEE + DUST_THRESHOLD - 1));
+        
+        let tx = send(DUST_THRESHOLD);
+        assert_eq!(tx.outputs.len(), 2);
+        assert_eq!(tx.outputs[1].amount, DUST_THRESHOLD);
+        assert_eq!(chain.transaction_fee(&tx), Some(MIN_FEE));
+    }
+    
+    #[test]
+    fn test_child_pays_for_parent() {
+        let chain = Blockchain::new();
+        let from = Address::genesis_address();
+        let key = SigningKey::from_bytes(&[9u8; 32]);
+        let recipient = Address::from_public_key(&key.verifying_key());
+        
+        // A parent paying well below the fee rate, its change unconfirmed
+        let parent = TransactionBuilder::new()
+            .from(from.clone())
+            .to(recipient.clone(), 1_000_000)
+            .fee(MIN_FEE)
+            .build_and_sign(&key, &chain)
+            .unwrap();
+        assert!(fee_for_size(parent.size()) > MIN_FEE);
+        
+        let builder = TransactionBuilder::new()
+            .from(from.clone())
+            .to(recipient.clone(), 2_000_000)
+            .spend_unconfirmed(vec![parent.clone()]);
+        let child = builder.clone().build_and_sign_exact(&key, &chain).unwrap();
+        assert!(child.inputs.iter().all(|input| input.prev_tx_hash == parent.txid()));
+        
+        let preview = builder.preview(&child, 1, &chain).unwrap();
+        assert_eq!(preview.package_size, child.size() + parent.size());
+        assert_eq!(preview.package_fee, preview.fee + MIN_FEE);
+        assert!(preview.package_fee_rate() >= FEE_PER_BYTE as f64);
+        assert!(preview.fee > fee_for_size(child.size()));
+        assert_eq!(preview.balance_after, preview.balance_before - 2_000_000 - preview.fee);
+        
+        // A parent already paying for itself needs nothing extra
+        assert_eq!(package_fee(200, FEE_PER_BYTE, 5_000, 300), fee_for_size(200));
+        assert_eq!(package_fee(200, FEE_PER_BYTE, 1_000, 300), 4_000);
+    }
+    
+    #[test]
+    fn test_send_max() {
+        let chain = Blockchain::new();
+        let from = Address::genesis_address();
+        let balance = chain.get_balance(&from);
+        let key = SigningKey::from_bytes(&[10u8; 32]);
+        let recipient = Address::from_public_key(&key.verifying_key());
+        
+        let tx = TransactionBuilder::new()
+            .from(from.clone())
+            .send_max(recipient.clone())
+            .build_and_sign_exact(&key, &chain)
+            .unwrap();
+        let fee = chain.transaction_fee(&tx).unwrap();
+        assert_eq!(fee, fee_for_size(tx.size()));
+        assert_eq!(tx.outputs.len(), 1);
+        assert_eq!((&tx.outputs[0].recipient, tx.outputs[0].amount), (&recipient, balance - fee));
+        
+        // Other recipients are paid first; the rest goes to the last output
+        let tx = TransactionBuilder::new()
+            .from(from.clone())
+            .to(from.clone(), 1_000_000)
+            .send_max(recipient.clone())
+            .build_and_sign_exact(&key, &chain)
+            .unwrap();
+        let fee = chain.transaction_fee(&tx).unwrap();
+        assert_eq!(tx.outputs[1].amount, balance - 1_000_000 - fee);
+        
+        // Never a dust output
+        let dust = TransactionBuilder::new()
+            .from(from)
+            .send_max(recipient)
+            .fee(balance - DUST_THRESHOLD + 1)
+            .build_and_sign(&key, &chain);
+        assert!(dust.is_err());
+    }
 }
@@ -0,0 +1,161 @@
+//! Address book
+//!
+//! Named contacts saved in the wallet file, so payments can go to `alice`
+//! instead of a pasted address. The book also remembers the label every
+//! address was paid under: paying it under another one is worth a warning,
+//! as a swapped address looks the same at a glance.
+
+use std::collections::BTreeMap;
+use serde::{Deserialize, Serialize};
+use aequitas_core::Address;
+
+/// Longest contact name
+pub const MAX_CONTACT_NAME: usize = 64;
+
+/// A named address
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Contact {
+    /// Name payments are made to
+    pub name: String,
+
+    /// Address the name stands for
+    pub address: String,
+
+    /// When the contact was added (Unix seconds)
+    pub added_at: i64,
+}
+
+/// Contacts and the labels addresses were paid under
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AddressBook {
+    /// Contacts, sorted by name
+    #[serde(default)]
+    pub contacts: Vec<Contact>,
+
+    /// Label of the last payment to each address
+    #[serde(default)]
+    pub paid: BTreeMap<String, String>,
+}
+
+impl AddressBook {
+    /// Whether the book holds nothing
+    pub fn is_empty(&self) -> bool {
+        self.contacts.is_empty() && self.paid.is_empty()
+    }
+
+    /// Add `name` for `address`, which must be a valid address
+    pub fn add(&mut self, name: &str, address: &str) -> anyhow::Result<&Contact> {
+        let name = name.trim();
+        if name.is_empty() || name.chars().count() > MAX_CONTACT_NAME {
+            anyhow::bail!("Contact names must be 1 to {} characters", MAX_CONTACT_NAME);
+        }
+        if Address::from_string(name).is_ok() {
+            anyhow::bail!("A contact name cannot be an address");
+        }
+        let address = Address::from_string(address)
+            .map_err(|e| anyhow::anyhow!("Invalid address {}: {}", address, e))?
+            .to_string();
+
+        let pos = match self.contacts.binary_search_by(|c| c.name.as_str().cmp(name)) {
+            Ok(_) => anyhow::bail!("Contact {} already exists", name),
+            Err(pos) => pos,
+        };
+        if let Some(other) = self.contacts.iter().find(|c| c.address == address) {
+            anyhow::bail!("Address is already saved as {}", other.name);
+        }
+
+        self.contacts.insert(pos, Contact {
+            name: name.to_string(),
+            address,
+            added_at: chrono::Utc::now().timestamp(),
+        });
+        Ok(&self.contacts[pos])
+    }
+
+    /// Remove the contact `name`
+    pub fn remove(&mut self, name: &str) -> anyhow::Result<Contact> {
+        let pos = self.contacts.binary_search_by(|c| c.name.as_str().cmp(name))
+            .map_err(|_| anyhow::anyhow!("No contact named {}", name))?;
+        Ok(self.contacts.remove(pos))
+    }
+
+    /// Contact named `name`
+    pub fn get(&self, name: &str) -> Option<&Contact> {
+        self.contacts.binary_search_by(|c| c.name.as_str().cmp(name))
+            .ok()
+            .map(|pos| &self.contacts[pos])
+    }
+
+    /// Contact saved for `address`
+    pub fn by_address(&self, address: &str) -> Option<&Contact> {
+        self.contacts.iter().find(|c| c.address == address)
+    }
+
+    /// Address of a payment to `recipient`, a contact name or an address,
+    /// with the label it is made under (the contact name, if any)
+    pub fn resolve(&self, recipient: &str) -> anyhow::Result<(Address, Option<String>)> {
+        if let Some(contact) = self.get(recipient.trim()) {
+            return Ok((Address::from_string(&contact.address)?, Some(contact.name.clone())));
+        }
+        let address = Address::from_string(recipient)
+            .map_err(|_| anyhow::anyhow!("{} is neither an address nor a contact", recipient))?;
+        let label = self.by_address(&address.to_string()).map(|c| c.name.clone());
+        Ok((address, label))
+    }
+
+    /// Label `address` was last paid under, when it is not `label`
+    ///
+    /// An address paid without a label has nothing to conflict with.
+    pub fn label_conflict(&self, address: &Address, label: Option<&str>) -> Option<&str> {
+        let previous = self.paid.get(&address.to_string())?;
+        (Some(previous.as_str()) != label).then_some(previous.as_str())
+    }
+
+    /// Remember that `address` was paid under `label`
+    pub fn record_payment(&mut self, address: &Address, label: Option<&str>) {
+        if let Some(label) = label {
+            self.paid.insert(address.to_string(), label.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::SigningKey;
+
+    fn address(seed: u8) -> Address {
+        Address::from_public_key(&SigningKey::from_bytes(&[seed; 32]).verifying_key())
+    }
+
+    #[test]
+    fn test_contacts() {
+        let mut book = AddressBook::default();
+        let alice = address(1);
+        book.add("alice", &alice.to_string()).unwrap();
+        assert!(book.add("alice", &address(2).to_string()).is_err());
+        assert!(book.add("bob", &alice.to_string()).is_err());
+        assert!(book.add("bob", "aeq1notanaddress").is_err());
+        assert!(book.add(&alice.to_string(), &address(2).to_string()).is_err());
+
+        assert_eq!(book.resolve("alice").unwrap(), (alice.clone(), Some("alice".to_string())));
+        assert_eq!(book.resolve(&alice.to_string()).unwrap().1.as_deref(), Some("alice"));
+        assert!(book.resolve("carol").is_err());
+
+        assert_eq!(book.remove("alice").unwrap().address, alice.to_string());
+        assert!(book.get("alice").is_none());
+        assert!(book.remove("alice").is_err());
+    }
+
+    #[test]
+    fn test_label_conflict() {
+        let mut book = AddressBook::default();
+        let addr = address(3);
+        assert_eq!(book.label_conflict(&addr, Some("alice")), None);
+
+        book.record_payment(&addr, Some("alice"));
+        assert_eq!(book.label_conflict(&addr, Some("alice")), None);
+        assert_eq!(book.label_conflict(&addr, Some("bob")), Some("alice"));
+        assert_eq!(book.label_conflict(&addr, None), Some("alice"));
+    }
+}
@@ -0,0 +1,98 @@
+//! Fee estimation from the node mempool
+//!
+//! The node's `/v1/fees/estimate` endpoint projects its mempool into blocks
+//! by fee rate and reports the rate needed to be mined within each of the
+//! next blocks. [`FeeEstimates::fee_rate`] turns that into a rate for
+//! [`TransactionBuilder::fee_rate_target`](crate::TransactionBuilder::fee_rate_target),
+//! never below [`FEE_PER_BYTE`].
+
+use serde::{Deserialize, Serialize};
+use crate::builder::FEE_PER_BYTE;
+
+/// Path of the fee estimates on the node
+pub const FEE_ESTIMATE_PATH: &str = "/v1/fees/estimate";
+
+/// Fee rate needed to be mined within a number of blocks
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FeeTarget {
+    /// Blocks from the next one
+    pub blocks: u32,
+
+    /// Whole units per byte, 0 when any fee does
+    pub fee_rate: u64,
+}
+
+/// Fee rate at a percentile of the mempool
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FeeRatePercentile {
+    /// Percentile, by transaction count
+    pub percentile: u8,
+
+    /// Units per byte
+    pub fee_rate: f64,
+}
+
+/// Fee estimates reported by a node
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FeeEstimates {
+    /// Pending transactions
+    pub mempool_size: usize,
+
+    /// Spread of the pending fee rates
+    pub percentiles: Vec<FeeRatePercentile>,
+
+    /// Rates by target, from 1 block on
+    pub targets: Vec<FeeTarget>,
+}
+
+impl FeeEstimates {
+    /// Query the estimates of the node at `node`
+    pub async fn fetch(node: &str) -> anyhow::Result<Self> {
+        let url = format!("{}{}", node.trim_end_matches('/'), FEE_ESTIMATE_PATH);
+        let resp = reqwest::get(&url).await?;
+        if !resp.status().is_success() {
+            anyhow::bail!("Node returned {} for fee estimates", resp.status());
+        }
+        Ok(resp.json().await?)
+    }
+
+    /// Fee per byte to be mined within `blocks` blocks
+    ///
+    /// Beyond the farthest target the node reports, its rate is used.
+    pub fn fee_rate(&self, blocks: u32) -> u64 {
+        let blocks = blocks.max(1);
+        let estimate = self.targets.iter()
+            .filter(|target| target.blocks <= blocks)
+            .max_by_key(|target| target.blocks)
+            .or_else(|| self.targets.iter().min_by_key(|target| target.blocks))
+            .map_or(0, |target| target.fee_rate);
+        estimate.max(FEE_PER_BYTE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fee_rate_for_target() {
+        let estimates: FeeEstimates = serde_json::from_str(r#"{
+            "mempool_size": 3,
+            "percentiles": [{"percentile": 50, "fee_rate": 42.5}],
+            "targets": [
+                {"blocks": 1, "fee_rate": 80},
+                {"blocks": 2, "fee_rate": 25},
+                {"blocks": 3, "fee_rate": 0}
+            ]
+        }"#).unwrap();
+
+        assert_eq!(estimates.fee_rate(0), 80);
+        assert_eq!(estimates.fee_rate(1), 80);
+        assert_eq!(estimates.fee_rate(2), 25);
+        assert_eq!(estimates.fee_rate(3), FEE_PER_BYTE);
+        assert_eq!(estimates.fee_rate(100), FEE_PER_BYTE);
+
+        let empty = FeeEstimates { mempool_size: 0, percentiles: Vec::new(), targets: Vec::new() };
+        assert_eq!(empty.fee_rate(1), FEE_PER_BYTE);
+    }
+}
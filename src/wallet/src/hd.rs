@@ -0,0 +1,165 @@
+//! Hierarchical deterministic key derivation
+//!
+//! Keys are derived from a BIP39 seed with SLIP-0010, the ed25519 variant
+//! of BIP32, along `m/44'/coin'/account'/change'/index'`. Ed25519 keys only
+//! derive hardened children, so every level is hardened: a child needs the
+//! parent's private key, and there is no extended public key to watch
+//! addresses with. The seed alone restores every key.
+
+use std::fmt;
+use std::str::FromStr;
+use hmac::{Hmac, Mac};
+use sha2::Sha512;
+use zeroize::{Zeroize, ZeroizeOnDrop};
+use aequitas_core::address::Keypair;
+
+/// SLIP-0044 coin type of Aequitas (not registered yet)
+pub const COIN_TYPE: u32 = 1618;
+
+/// BIP44 purpose
+pub const PURPOSE: u32 = 44;
+
+/// Offset of hardened indexes
+pub const HARDENED: u32 = 1 << 31;
+
+/// HMAC key of the master key, fixed by SLIP-0010 for ed25519
+const MASTER_KEY: &[u8] = b"ed25519 seed";
+
+/// Derivation path, as hardened indexes below the master key
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DerivationPath(Vec<u32>);
+
+impl DerivationPath {
+    /// BIP44 path of address `index` of `account`, on the change chain if
+    /// `change`
+    pub fn bip44(account: u32, change: bool, index: u32) -> Self {
+        Self(vec![PURPOSE, COIN_TYPE, account, change as u32, index])
+    }
+
+    /// Indexes, without the hardened offset
+    pub fn indexes(&self) -> &[u32] {
+        &self.0
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "m")?;
+        for index in &self.0 {
+            write!(f, "/{}'", index)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let mut levels = s.split('/');
+        if levels.next() != Some("m") {
+            anyhow::bail!("Derivation path must start with m: {}", s);
+        }
+
+        let indexes = levels
+            .map(|level| {
+                let index = level
+                    .strip_suffix('\'')
+                    .or_else(|| level.strip_suffix('h'))
+                    .ok_or_else(|| anyhow::anyhow!("Ed25519 only derives hardened levels: {}", level))?;
+                let index: u32 = index.parse()?;
+                anyhow::ensure!(index < HARDENED, "Index out of range: {}", level);
+                Ok(index)
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Self(indexes))
+    }
+}
+
+/// Private key and chain code of one node of the tree
+#[derive(Clone, Zeroize, ZeroizeOnDrop)]
+pub struct ExtendedKey {
+    key: [u8; 32],
+    chain_code: [u8; 32],
+}
+
+impl ExtendedKey {
+    /// Master key of `seed`
+    pub fn master(seed: &[u8]) -> Self {
+        Self::from_hmac(MASTER_KEY, &[seed])
+    }
+
+    /// Hardened child `index`, given with or without the hardened offset
+    pub fn child(&self, index: u32) -> Self {
+        let index = (index | HARDENED).to_be_bytes();
+        Self::from_hmac(&self.chain_code, &[&[0], &self.key, &index])
+    }
+
+    /// Key at `path` below this one
+    pub fn derive(&self, path: &DerivationPath) -> Self {
+        path.indexes().iter().fold(self.clone(), |key, &index| key.child(index))
+    }
+
+    /// Private key
+    pub fn secret(&self) -> &[u8; 32] {
+        &self.key
+    }
+
+    /// Chain code
+    pub fn chain_code(&self) -> &[u8; 32] {
+        &self.chain_code
+    }
+
+    /// Signing keypair of this node
+    pub fn keypair(&self) -> anyhow::Result<Keypair> {
+        Ok(Keypair::from_bytes(&self.key)?)
+    }
+
+    fn from_hmac(key: &[u8], data: &[&[u8]]) -> Self {
+        let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC takes keys of any length");
+        for part in data {
+            mac.update(part);
+        }
+        let mut output = mac.finalize().into_bytes();
+
+        let mut extended = Self { key: [0; 32], chain_code: [0; 32] };
+        extended.key.copy_from_slice(&output[..32]);
+        extended.chain_code.copy_from_slice(&output[32..]);
+        output.as_mut_slice().zeroize();
+        extended
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slip10_vector() {
+        // SLIP-0010 ed25519 test vector 1
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let master = ExtendedKey::master(&seed);
+        assert_eq!(hex::encode(master.chain_code()), "90046a93de5380a72b5e45010748567d5ea02bbf6522f979e05c0d8d8ca9fffb");
+        assert_eq!(hex::encode(master.secret()), "2b4be7f19ee27bbf30c667b642d5f4aa69fd169872f8fc3059c08ebae2eb19e7");
+
+        let child = master.child(0);
+        assert_eq!(hex::encode(child.chain_code()), "8b59aa11380b624e81507a27fedda59fea6d0b779a778918a2fd3590e16e9c69");
+        assert_eq!(hex::encode(child.secret()), "68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3");
+
+        let grandchild = master.derive(&"m/0'/1'".parse().unwrap());
+        assert_eq!(hex::encode(grandchild.chain_code()), "a320425f77d1b5c2505a6b1b27382b37368ee640e3557c315416801243552f14");
+        assert_eq!(hex::encode(grandchild.secret()), "b1d0bad404bf35da785a64ca1ac54b2617211d2777696fbffaf208f746ae84f2");
+    }
+
+    #[test]
+    fn test_derivation_path() {
+        let path = DerivationPath::bip44(0, true, 7);
+        assert_eq!(path.to_string(), format!("m/44'/{}'/0'/1'/7'", COIN_TYPE));
+        assert_eq!(path.to_string().parse::<DerivationPath>().unwrap(), path);
+        assert_eq!("m/44h/0h".parse::<DerivationPath>().unwrap().indexes(), &[44, 0]);
+
+        assert!("m/44'/0".parse::<DerivationPath>().is_err());
+        assert!("44'/0'".parse::<DerivationPath>().is_err());
+        assert!("m/2147483648'".parse::<DerivationPath>().is_err());
+    }
+}
@@ -0,0 +1,409 @@
+//! Ledger hardware wallets
+//!
+//! The device runs the Aequitas app and talks APDUs, the smart-card
+//! command format, framed into 64-byte USB HID reports. This module
+//! defines the app's protocol:
+//!
+//! - `GET_PUBLIC_KEY` (`INS 0x02`): the data is a derivation path, a count
+//!   byte then each index as a big-endian `u32` with the hardened bit set.
+//!   `P1` is 1 to show the address on the device for the user to check.
+//!   The reply is the 32-byte public key.
+//! - `SIGN` (`INS 0x03`): the path followed by the transaction's signing
+//!   message, split over APDUs of at most 255 bytes. `P1` is 0 on the first
+//!   and 1 on the others, `P2` is `0x80` while more follow. The device
+//!   decodes the message, shows the outputs and fee, and replies to the last
+//!   APDU with the 64-byte signature once the user approves.
+//!
+//! The HID transport needs the `ledger` cargo feature; the protocol works
+//! over any [`Transport`].
+
+use ed25519_dalek::{PUBLIC_KEY_LENGTH, SIGNATURE_LENGTH};
+use aequitas_core::Address;
+use crate::hd::{DerivationPath, HARDENED};
+use super::Signer;
+
+/// USB vendor ID of Ledger devices
+pub const LEDGER_VENDOR_ID: u16 = 0x2c97;
+
+/// Class byte of the Aequitas app's instructions
+pub const CLA: u8 = 0xe0;
+
+/// Instruction returning the public key of a path
+pub const INS_GET_PUBLIC_KEY: u8 = 0x02;
+
+/// Instruction signing a transaction
+pub const INS_SIGN: u8 = 0x03;
+
+/// `P2` of a `SIGN` APDU that more follow
+const P2_MORE: u8 = 0x80;
+
+/// Status word of success
+pub const SW_OK: u16 = 0x9000;
+
+/// Status word of a request the user rejected on the device
+pub const SW_DENIED: u16 = 0x6985;
+
+/// Most data bytes in one APDU
+pub const MAX_APDU_DATA: usize = 255;
+
+/// HID channel of the framing
+const CHANNEL: u16 = 0x0101;
+
+/// Tag of APDU frames
+const TAG_APDU: u8 = 0x05;
+
+/// Size of a HID report
+pub const REPORT_SIZE: usize = 64;
+
+/// A command to the device
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Apdu {
+    /// Instruction
+    pub ins: u8,
+
+    /// First parameter
+    pub p1: u8,
+
+    /// Second parameter
+    pub p2: u8,
+
+    /// At most [`MAX_APDU_DATA`] bytes
+    pub data: Vec<u8>,
+}
+
+impl Apdu {
+    /// Command bytes: `CLA INS P1 P2 Lc data`
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes = vec![CLA, self.ins, self.p1, self.p2, self.data.len() as u8];
+        bytes.extend_from_slice(&self.data);
+        bytes
+    }
+}
+
+/// Something carrying APDUs to a device and back
+pub trait Transport {
+    /// Send `apdu`, returning the reply data and status word
+    fn exchange(&self, apdu: &Apdu) -> anyhow::Result<(Vec<u8>, u16)>;
+}
+
+/// Split `message` into HID reports
+///
+/// Each report is the channel, the tag and a sequence number; the first
+/// then carries the message length. The last is padded with zeros.
+pub fn frame(message: &[u8]) -> Vec<[u8; REPORT_SIZE]> {
+    let mut payload = (message.len() as u16).to_be_bytes().to_vec();
+    payload.extend_from_slice(message);
+
+    payload
+        .chunks(REPORT_SIZE - 5)
+        .enumerate()
+        .map(|(sequence, chunk)| {
+            let mut report = [0u8; REPORT_SIZE];
+            report[..2].copy_from_slice(&CHANNEL.to_be_bytes());
+            report[2] = TAG_APDU;
+            report[3..5].copy_from_slice(&(sequence as u16).to_be_bytes());
+            report[5..5 + chunk.len()].copy_from_slice(chunk);
+            report
+        })
+        .collect()
+}
+
+/// Reassembles a message from HID reports
+#[derive(Default)]
+pub struct Unframer {
+    length: Option<usize>,
+    sequence: u16,
+    message: Vec<u8>,
+}
+
+impl Unframer {
+    /// Add `report`, returning the message once it is complete
+    pub fn push(&mut self, report: &[u8]) -> anyhow::Result<Option<Vec<u8>>> {
+        anyhow::ensure!(report.len() >= 5, "HID report too short: {} bytes", report.len());
+        anyhow::ensure!(
+            report[..2] == CHANNEL.to_be_bytes() && report[2] == TAG_APDU,
+            "Unexpected HID report header"
+        );
+        let sequence = u16::from_be_bytes([report[3], report[4]]);
+        anyhow::ensure!(sequence == self.sequence, "HID report {} out of order", sequence);
+        self.sequence += 1;
+
+        let mut data = &report[5..];
+        let length = match self.length {
+            Some(length) => length,
+            None => {
+                anyhow::ensure!(data.len() >= 2, "HID report misses the message length");
+                let length = u16::from_be_bytes([data[0], data[1]]) as usize;
+                data = &data[2..];
+                *self.length.insert(length)
+            }
+        };
+
+        let needed = length - self.message.len();
+        self.message.extend_from_slice(&data[..needed.min(data.len())]);
+        Ok((self.message.len() == length).then(|| std::mem::take(&mut self.message)))
+    }
+}
+
+/// Split a reply into its data and status word
+pub fn parse_reply(reply: &[u8]) -> anyhow::Result<(Vec<u8>, u16)> {
+    anyhow::ensure!(reply.len() >= 2, "Reply without a status word");
+    let (data, status) = reply.split_at(reply.len() - 2);
+    Ok((data.to_vec(), u16::from_be_bytes([status[0], status[1]])))
+}
+
+/// Path as the app reads it
+pub fn encode_path(path: &DerivationPath) -> Vec<u8> {
+    let mut bytes = vec![path.indexes().len() as u8];
+    for index in path.indexes() {
+        bytes.extend_from_slice(&(index | HARDENED).to_be_bytes());
+    }
+    bytes
+}
+
+/// The `SIGN` APDUs of `message` with the key at `path`
+pub fn sign_apdus(path: &DerivationPath, message: &[u8]) -> Vec<Apdu> {
+    let mut data = encode_path(path);
+    data.extend_from_slice(message);
+
+    let chunks: Vec<&[u8]> = data.chunks(MAX_APDU_DATA).collect();
+    let last = chunks.len() - 1;
+    chunks
+        .into_iter()
+        .enumerate()
+        .map(|(i, chunk)| Apdu {
+            ins: INS_SIGN,
+            p1: (i > 0) as u8,
+            p2: if i < last { P2_MORE } else { 0 },
+            data: chunk.to_vec(),
+        })
+        .collect()
+}
+
+/// Error for a status word other than [`SW_OK`]
+fn check_status(status: u16) -> anyhow::Result<()> {
+    match status {
+        SW_OK => Ok(()),
+        SW_DENIED => anyhow::bail!("Rejected on the device"),
+        0x6e00 | 0x6d00 => anyhow::bail!("The Aequitas app is not open on the device"),
+        status => anyhow::bail!("Device returned status {:04x}", status),
+    }
+}
+
+/// Key at one path of a Ledger device
+pub struct Ledger<T: Transport> {
+    transport: T,
+    path: DerivationPath,
+    public_key: [u8; PUBLIC_KEY_LENGTH],
+}
+
+impl<T: Transport> Ledger<T> {
+    /// Key at `path`, reading its public key from the device
+    pub fn new(transport: T, path: DerivationPath) -> anyhow::Result<Self> {
+        let public_key = get_public_key(&transport, &path, false)?;
+        Ok(Self { transport, path, public_key })
+    }
+
+    /// Derivation path of the key
+    pub fn path(&self) -> &DerivationPath {
+        &self.path
+    }
+
+    /// Address of the key
+    pub fn address(&self) -> anyhow::Result<Address> {
+        let key = ed25519_dalek::VerifyingKey::from_bytes(&self.public_key)?;
+        Ok(Address::from_public_key(&key))
+    }
+
+    /// Show the address on the device, for the user to compare
+    pub fn confirm_address(&self) -> anyhow::Result<()> {
+        let public_key = get_public_key(&self.transport, &self.path, true)?;
+        anyhow::ensure!(public_key == self.public_key, "Device returned another key");
+        Ok(())
+    }
+}
+
+impl<T: Transport> Signer for Ledger<T> {
+    fn public_key(&self) -> anyhow::Result<[u8; PUBLIC_KEY_LENGTH]> {
+        Ok(self.public_key)
+    }
+
+    fn sign(&self, message: &[u8]) -> anyhow::Result<[u8; SIGNATURE_LENGTH]> {
+        let mut reply = Vec::new();
+        for apdu in sign_apdus(&self.path, message) {
+            let (data, status) = self.transport.exchange(&apdu)?;
+            check_status(status)?;
+            reply = data;
+        }
+
+        let signature: [u8; SIGNATURE_LENGTH] = reply.as_slice().try_into()
+            .map_err(|_| anyhow::anyhow!("Signature of {} bytes from the device", reply.len()))?;
+        let key = ed25519_dalek::VerifyingKey::from_bytes(&self.public_key)?;
+        key.verify_strict(message, &ed25519_dalek::Signature::from_bytes(&signature))
+            .map_err(|_| anyhow::anyhow!("Device signature does not verify"))?;
+        Ok(signature)
+    }
+}
+
+fn get_public_key<T: Transport>(
+    transport: &T,
+    path: &DerivationPath,
+    confirm: bool,
+) -> anyhow::Result<[u8; PUBLIC_KEY_LENGTH]> {
+    let apdu = Apdu { ins: INS_GET_PUBLIC_KEY, p1: confirm as u8, p2: 0, data: encode_path(path) };
+    let (data, status) = transport.exchange(&apdu)?;
+    check_status(status)?;
+    data.as_slice().try_into()
+        .map_err(|_| anyhow::anyhow!("Public key of {} bytes from the device", data.len()))
+}
+
+#[cfg(feature = "ledger")]
+pub use hid::HidTransport;
+
+#[cfg(feature = "ledger")]
+mod hid {
+    use hidapi::{HidApi, HidDevice};
+    use super::*;
+
+    /// How long to wait for the user on the device (ms)
+    const READ_TIMEOUT_MS: i32 = 120_000;
+
+    /// USB HID connection to a Ledger device
+    pub struct HidTransport {
+        device: HidDevice,
+    }
+
+    impl HidTransport {
+        /// The first Ledger device plugged in
+        pub fn open() -> anyhow::Result<Self> {
+            let api = HidApi::new()?;
+            let info = api.device_list()
+                // Only the first interface talks APDUs
+                .find(|info| info.vendor_id() == LEDGER_VENDOR_ID && info.interface_number() <= 0)
+                .ok_or_else(|| anyhow::anyhow!("No Ledger device found"))?;
+            Ok(Self { device: info.open_device(&api)? })
+        }
+    }
+
+    impl Transport for HidTransport {
+        fn exchange(&self, apdu: &Apdu) -> anyhow::Result<(Vec<u8>, u16)> {
+            for report in frame(&apdu.encode()) {
+                // Report ID 0 precedes each report
+                let mut buf = [0u8; REPORT_SIZE + 1];
+                buf[1..].copy_from_slice(&report);
+                self.device.write(&buf)?;
+            }
+
+            let mut unframer = Unframer::default();
+            loop {
+                let mut report = [0u8; REPORT_SIZE];
+                let read = self.device.read_timeout(&mut report, READ_TIMEOUT_MS)?;
+                anyhow::ensure!(read > 0, "Timed out waiting for the device");
+                if let Some(reply) = unframer.push(&report[..read])? {
+                    return parse_reply(&reply);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use ed25519_dalek::SigningKey;
+
+    /// Device signing with one key, whatever the path
+    struct MockDevice {
+        key: SigningKey,
+        pending: RefCell<Vec<u8>>,
+        approve: bool,
+    }
+
+    impl Transport for MockDevice {
+        fn exchange(&self, apdu: &Apdu) -> anyhow::Result<(Vec<u8>, u16)> {
+            // Go through the framing like a real device
+            let mut unframer = Unframer::default();
+            let mut command = None;
+            for report in frame(&apdu.encode()) {
+                command = unframer.push(&report)?;
+            }
+            assert_eq!(command.unwrap(), apdu.encode());
+
+            match apdu.ins {
+                INS_GET_PUBLIC_KEY => Ok((self.key.verifying_key().to_bytes().to_vec(), SW_OK)),
+                INS_SIGN => {
+                    let mut pending = self.pending.borrow_mut();
+                    if apdu.p1 == 0 {
+                        pending.clear();
+                    }
+                    pending.extend_from_slice(&apdu.data);
+                    if apdu.p2 == P2_MORE {
+                        return Ok((Vec::new(), SW_OK));
+                    }
+                    if !self.approve {
+                        return Ok((Vec::new(), SW_DENIED));
+                    }
+
+                    let path_len = 1 + 4 * pending[0] as usize;
+                    let signature = ed25519_dalek::Signer::sign(&self.key, &pending[path_len..]);
+                    Ok((signature.to_bytes().to_vec(), SW_OK))
+                }
+                _ => Ok((Vec::new(), 0x6d00)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_framing() {
+        let message: Vec<u8> = (0..200u8).collect();
+        let reports = frame(&message);
+        assert_eq!(reports.len(), 4);
+        assert_eq!(&reports[0][..7], &[0x01, 0x01, 0x05, 0x00, 0x00, 0x00, 200]);
+        assert_eq!(&reports[1][..5], &[0x01, 0x01, 0x05, 0x00, 0x01]);
+
+        let mut unframer = Unframer::default();
+        assert!(unframer.push(&reports[1]).is_err());
+        let mut unframer = Unframer::default();
+        let mut message_out = None;
+        for report in &reports {
+            message_out = unframer.push(report).unwrap();
+        }
+        assert_eq!(message_out.unwrap(), message);
+
+        assert_eq!(parse_reply(&[1, 2, 0x90, 0x00]).unwrap(), (vec![1, 2], SW_OK));
+        assert!(parse_reply(&[0x90]).is_err());
+    }
+
+    #[test]
+    fn test_sign_apdus() {
+        let path = DerivationPath::bip44(0, false, 3);
+        assert_eq!(encode_path(&path)[..5], [5, 0x80, 0, 0, 44]);
+
+        let message = vec![7u8; 600];
+        let apdus = sign_apdus(&path, &message);
+        assert_eq!(apdus.len(), 3);
+        assert_eq!((apdus[0].p1, apdus[0].p2), (0, P2_MORE));
+        assert_eq!((apdus[2].p1, apdus[2].p2), (1, 0));
+        assert!(apdus.iter().all(|apdu| apdu.data.len() <= MAX_APDU_DATA));
+        assert_eq!(apdus[0].encode()[..5], [CLA, INS_SIGN, 0, P2_MORE, 255]);
+    }
+
+    #[test]
+    fn test_ledger_signer() {
+        let key = SigningKey::from_bytes(&[9u8; 32]);
+        let device = MockDevice { key: key.clone(), pending: RefCell::default(), approve: true };
+        let ledger = Ledger::new(device, DerivationPath::bip44(0, false, 0)).unwrap();
+        assert_eq!(ledger.address().unwrap(), Address::from_public_key(&key.verifying_key()));
+        ledger.confirm_address().unwrap();
+
+        let message = vec![3u8; 700];
+        let signature = ledger.sign(&message).unwrap();
+        assert_eq!(signature, Signer::sign(&key, &message).unwrap());
+
+        let device = MockDevice { key, pending: RefCell::default(), approve: false };
+        let ledger = Ledger::new(device, DerivationPath::bip44(0, false, 0)).unwrap();
+        let err = ledger.sign(&message).unwrap_err();
+        assert!(err.to_string().contains("Rejected"));
+    }
+}
@@ -0,0 +1,31 @@
+//! Transaction signers
+//!
+//! [`TransactionBuilder::build_and_sign`](crate::TransactionBuilder::build_and_sign)
+//! signs through a [`Signer`]: a key held in memory, or a hardware wallet
+//! that keeps it on the device and shows the transaction before signing.
+//!
+//! - [`ledger`]: Ledger devices running the Aequitas app, over USB HID with
+//!   the `ledger` cargo feature
+
+pub mod ledger;
+
+use ed25519_dalek::{SigningKey, PUBLIC_KEY_LENGTH, SIGNATURE_LENGTH};
+
+/// Something that signs with one ed25519 key
+pub trait Signer {
+    /// Public key the signatures verify against
+    fn public_key(&self) -> anyhow::Result<[u8; PUBLIC_KEY_LENGTH]>;
+
+    /// Signature of `message`
+    fn sign(&self, message: &[u8]) -> anyhow::Result<[u8; SIGNATURE_LENGTH]>;
+}
+
+impl Signer for SigningKey {
+    fn public_key(&self) -> anyhow::Result<[u8; PUBLIC_KEY_LENGTH]> {
+        Ok(self.verifying_key().to_bytes())
+    }
+
+    fn sign(&self, message: &[u8]) -> anyhow::Result<[u8; SIGNATURE_LENGTH]> {
+        Ok(ed25519_dalek::Signer::sign(self, message).to_bytes())
+    }
+}
@@ -1,21 +1,51 @@
 //! Secure keystore with encryption
 //!
 //! Stores private keys encrypted with a password-derived key.
+//!
+//! A single key can also be moved between machines as a standalone
+//! encrypted keyfile (see [`KeyFile`]), in a JSON layout modelled on
+//! the Web3 secret storage format.
+//!
+//! A keystore can also hold an encrypted BIP39 seed (see [`HdSeed`]). New
+//! addresses are then derived from it along BIP44 paths (see [`crate::hd`])
+//! rather than generated at random, so the mnemonic alone restores them.
+//!
+//! The whole file can be encrypted as well (see [`EncryptedKeystoreFile`]),
+//! hiding addresses, labels and creation times, with its header
+//! authenticated so that any change to the file is detected on load.
 
 use aes_gcm::{
-    aead::{Aead, KeyInit},
+    aead::{Aead, KeyInit, Payload},
     Aes256Gcm, Nonce,
 };
-use argon2::{Argon2, password_hash::SaltString};
+use argon2::{Algorithm, Argon2, Params, Version, password_hash::SaltString};
 use rand::rngs::OsRng;
 use serde::{Deserialize, Serialize};
-use zeroize::Zeroize;
+use zeroize::{Zeroize, Zeroizing};
 use ed25519_dalek::SigningKey;
+use bip39::Mnemonic;
 use aequitas_core::address::{Keypair, Address};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use crate::contacts::AddressBook;
+use crate::hd::{DerivationPath, ExtendedKey};
+
+/// Keystore version; version 2 adds the HD seed and key paths
+pub const KEYSTORE_VERSION: u32 = 2;
+
+/// Unused addresses in a row after which a scan stops, as in BIP44
+pub const DEFAULT_GAP_LIMIT: u32 = 20;
+
+/// Entropy of generated mnemonics (bytes), giving 24 words
+const MNEMONIC_ENTROPY: usize = 32;
 
-/// Keystore version
-pub const KEYSTORE_VERSION: u32 = 1;
+/// Keyfile format version
+pub const KEYFILE_VERSION: u32 = 1;
+
+/// Cipher identifier written to keyfiles
+pub const KEYFILE_CIPHER: &str = "aes-256-gcm";
+
+/// Key derivation identifier written to keyfiles
+pub const KEYFILE_KDF: &str = "argon2id";
 
 /// Encrypted key entry
 #[derive(Clone, Serialize, Deserialize)]
@@ -37,6 +67,50 @@ pub struct EncryptedKey {
     
     /// Creation timestamp
     pub created_at: i64,
+    
+    /// Derivation path, for keys derived from the HD seed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    
+    /// Free-form note
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    
+    /// No longer in use: never picked as the default address
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub archived: bool,
+    
+    /// Left out of address listings unless they ask for every address
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub hidden: bool,
+}
+
+/// Encrypted BIP39 seed and derivation state
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HdSeed {
+    /// Encrypted mnemonic entropy
+    pub ciphertext: Vec<u8>,
+    
+    /// Nonce used for encryption
+    pub nonce: Vec<u8>,
+    
+    /// Salt for key derivation
+    pub salt: String,
+    
+    /// BIP44 account keys are derived in
+    pub account: u32,
+    
+    /// Index of the next receive address
+    pub next_receive: u32,
+    
+    /// Index of the next change address
+    pub next_change: u32,
+}
+
+impl HdSeed {
+    fn next_index(&mut self, change: bool) -> &mut u32 {
+        if change { &mut self.next_change } else { &mut self.next_receive }
+    }
 }
 
 /// Keystore file format
@@ -47,6 +121,14 @@ pub struct KeystoreFile {
     
     /// Encrypted keys
     pub keys: Vec<EncryptedKey>,
+    
+    /// Seed new keys are derived from, if any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hd: Option<HdSeed>,
+    
+    /// Address book
+    #[serde(default, skip_serializing_if = "AddressBook::is_empty")]
+    pub contacts: AddressBook,
 }
 
 impl Default for KeystoreFile {
@@ -54,10 +136,419 @@ impl Default for KeystoreFile {
         Self {
             version: KEYSTORE_VERSION,
             keys: Vec::new(),
+            hd: None,
+            contacts: AddressBook::default(),
+        }
+    }
+}
+
+/// Format name of whole-file encrypted keystores
+pub const ENCRYPTED_KEYSTORE_FORMAT: &str = "aequitas-keystore-encrypted";
+
+/// Version of the encrypted keystore layout
+pub const ENCRYPTED_KEYSTORE_VERSION: u32 = 1;
+
+/// Header of an encrypted keystore file, authenticated with the contents
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncryptedHeader {
+    /// Always [`ENCRYPTED_KEYSTORE_FORMAT`]
+    pub format: String,
+    
+    /// Layout version
+    pub version: u32,
+    
+    /// Cipher name
+    pub cipher: String,
+    
+    /// Key derivation function name
+    pub kdf: String,
+    
+    /// Key derivation parameters
+    pub kdfparams: KdfParams,
+    
+    /// Cipher nonce (hex), fresh on every save
+    pub nonce: String,
+}
+
+/// Keystore file encrypted as a whole
+///
+/// The [`KeystoreFile`] JSON is encrypted with AES-256-GCM under a key
+/// derived from the file password with Argon2id. The header is the
+/// associated data, so its version and parameters cannot be altered
+/// without failing decryption like the ciphertext itself.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EncryptedKeystoreFile {
+    /// Authenticated header
+    pub header: EncryptedHeader,
+    
+    /// Encrypted keystore (hex)
+    pub ciphertext: String,
+}
+
+impl EncryptedKeystoreFile {
+    /// Whether `content` is an encrypted keystore rather than a plain one
+    pub fn detect(content: &str) -> bool {
+        serde_json::from_str::<serde_json::Value>(content)
+            .is_ok_and(|value| value["header"]["format"] == ENCRYPTED_KEYSTORE_FORMAT)
+    }
+}
+
+/// Key a keystore file is encrypted with, kept to save it again
+struct FileKey {
+    key: Zeroizing<[u8; 32]>,
+    kdfparams: KdfParams,
+}
+
+impl FileKey {
+    /// Derive a key from `password` with a fresh salt
+    fn derive(password: &str) -> anyhow::Result<Self> {
+        let kdfparams = KdfParams {
+            salt: SaltString::generate(&mut OsRng).as_str().to_string(),
+            m_cost: Params::DEFAULT_M_COST,
+            t_cost: Params::DEFAULT_T_COST,
+            p_cost: Params::DEFAULT_P_COST,
+        };
+        Self::with_params(password, kdfparams)
+    }
+    
+    fn with_params(password: &str, kdfparams: KdfParams) -> anyhow::Result<Self> {
+        let params = Params::new(kdfparams.m_cost, kdfparams.t_cost, kdfparams.p_cost, Some(32))
+            .map_err(|e| anyhow::anyhow!("Invalid key derivation parameters: {}", e))?;
+        let mut key = Zeroizing::new([0u8; 32]);
+        Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+            .hash_password_into(password.as_bytes(), kdfparams.salt.as_bytes(), &mut key[..])
+            .map_err(|e| anyhow::anyhow!("Key derivation failed: {}", e))?;
+        Ok(Self { key, kdfparams })
+    }
+    
+    fn cipher(&self) -> anyhow::Result<Aes256Gcm> {
+        Aes256Gcm::new_from_slice(&self.key[..])
+            .map_err(|e| anyhow::anyhow!("Cipher creation failed: {}", e))
+    }
+    
+    /// Encrypt `data` under a fresh nonce
+    fn seal(&self, data: &KeystoreFile) -> anyhow::Result<EncryptedKeystoreFile> {
+        let nonce: [u8; 12] = rand::random();
+        let header = EncryptedHeader {
+            format: ENCRYPTED_KEYSTORE_FORMAT.to_string(),
+            version: ENCRYPTED_KEYSTORE_VERSION,
+            cipher: KEYFILE_CIPHER.to_string(),
+            kdf: KEYFILE_KDF.to_string(),
+            kdfparams: self.kdfparams.clone(),
+            nonce: hex::encode(nonce),
+        };
+        
+        let plaintext = Zeroizing::new(serde_json::to_vec(data)?);
+        let aad = serde_json::to_vec(&header)?;
+        let ciphertext = self.cipher()?
+            .encrypt(Nonce::from_slice(&nonce), Payload { msg: &plaintext, aad: &aad })
+            .map_err(|e| anyhow::anyhow!("Encryption failed: {}", e))?;
+        
+        Ok(EncryptedKeystoreFile { header, ciphertext: hex::encode(ciphertext) })
+    }
+    
+    /// Decrypt `file` with the key of `password`, returning the key too
+    fn open(file: &EncryptedKeystoreFile, password: &str) -> anyhow::Result<(Self, KeystoreFile)> {
+        let header = &file.header;
+        if header.format != ENCRYPTED_KEYSTORE_FORMAT {
+            anyhow::bail!("Not an encrypted keystore: {}", header.format);
+        }
+        if header.version != ENCRYPTED_KEYSTORE_VERSION {
+            anyhow::bail!("Unsupported encrypted keystore version {}", header.version);
+        }
+        if header.cipher != KEYFILE_CIPHER {
+            anyhow::bail!("Unsupported cipher: {}", header.cipher);
+        }
+        if header.kdf != KEYFILE_KDF {
+            anyhow::bail!("Unsupported key derivation: {}", header.kdf);
+        }
+        
+        let nonce = hex::decode(&header.nonce)?;
+        if nonce.len() != 12 {
+            anyhow::bail!("Invalid nonce length");
+        }
+        let ciphertext = hex::decode(&file.ciphertext)?;
+        let aad = serde_json::to_vec(header)?;
+        
+        let key = Self::with_params(password, header.kdfparams.clone())?;
+        let plaintext = Zeroizing::new(
+            key.cipher()?
+                .decrypt(Nonce::from_slice(&nonce), Payload { msg: &ciphertext, aad: &aad })
+                .map_err(|_| anyhow::anyhow!("Decryption failed - wrong password or tampered file?"))?,
+        );
+        Ok((key, serde_json::from_slice(&plaintext)?))
+    }
+}
+
+/// Key derivation parameters
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KdfParams {
+    /// Salt for key derivation
+    pub salt: String,
+    
+    /// Memory cost in KiB
+    pub m_cost: u32,
+    
+    /// Number of iterations
+    pub t_cost: u32,
+    
+    /// Degree of parallelism
+    pub p_cost: u32,
+}
+
+/// Encryption parameters of a keyfile
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KeyFileCrypto {
+    /// Cipher name
+    pub cipher: String,
+    
+    /// Encrypted private key (hex)
+    pub ciphertext: String,
+    
+    /// Cipher nonce (hex)
+    pub nonce: String,
+    
+    /// Key derivation function name
+    pub kdf: String,
+    
+    /// Key derivation parameters
+    pub kdfparams: KdfParams,
+}
+
+/// Standalone encrypted keyfile holding a single key
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct KeyFile {
+    /// Version
+    pub version: u32,
+    
+    /// Address for this key
+    pub address: String,
+    
+    /// Optional label
+    pub label: Option<String>,
+    
+    /// Creation timestamp
+    pub created_at: i64,
+    
+    /// Encryption parameters
+    pub crypto: KeyFileCrypto,
+}
+
+impl KeyFile {
+    /// Load a keyfile from disk
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        let keyfile: KeyFile = serde_json::from_str(&content)?;
+        
+        if keyfile.version != KEYFILE_VERSION {
+            anyhow::bail!("Unsupported keyfile version {}", keyfile.version);
+        }
+        
+        Ok(keyfile)
+    }
+    
+    /// Save the keyfile to disk
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        let content = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, content)?;
+        Ok(())
+    }
+    
+    /// Default file name for this keyfile
+    pub fn file_name(&self) -> String {
+        format!("{}.json", self.address)
+    }
+    
+    /// Decrypt the private key with the keyfile password
+    pub fn decrypt(&self, password: &str) -> anyhow::Result<Keypair> {
+        if self.crypto.cipher != KEYFILE_CIPHER {
+            anyhow::bail!("Unsupported cipher: {}", self.crypto.cipher);
+        }
+        if self.crypto.kdf != KEYFILE_KDF {
+            anyhow::bail!("Unsupported key derivation: {}", self.crypto.kdf);
+        }
+        
+        let params = Params::new(
+            self.crypto.kdfparams.m_cost,
+            self.crypto.kdfparams.t_cost,
+            self.crypto.kdfparams.p_cost,
+            Some(32),
+        ).map_err(|e| anyhow::anyhow!("Invalid key derivation parameters: {}", e))?;
+        let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params);
+        
+        let ciphertext = hex::decode(&self.crypto.ciphertext)?;
+        let nonce = hex::decode(&self.crypto.nonce)?;
+        if nonce.len() != 12 {
+            anyhow::bail!("Invalid nonce length");
+        }
+        
+        let keypair = decrypt_secret(&argon2, password, &self.crypto.kdfparams.salt, &nonce, &ciphertext)?;
+        
+        if keypair.address().to_string() != self.address {
+            anyhow::bail!("Keyfile address does not match its key");
+        }
+        
+        Ok(keypair)
+    }
+}
+
+impl From<&EncryptedKey> for KeyFile {
+    /// Repackage a keystore entry without decrypting it
+    fn from(key: &EncryptedKey) -> Self {
+        Self {
+            version: KEYFILE_VERSION,
+            address: key.address.clone(),
+            label: key.label.clone(),
+            created_at: key.created_at,
+            crypto: KeyFileCrypto {
+                cipher: KEYFILE_CIPHER.to_string(),
+                ciphertext: hex::encode(&key.ciphertext),
+                nonce: hex::encode(&key.nonce),
+                kdf: KEYFILE_KDF.to_string(),
+                kdfparams: KdfParams {
+                    salt: key.salt.clone(),
+                    m_cost: Params::DEFAULT_M_COST,
+                    t_cost: Params::DEFAULT_T_COST,
+                    p_cost: Params::DEFAULT_P_COST,
+                },
+            },
         }
     }
 }
 
+/// Encrypt `secret` with a key derived from `password`, returning the
+/// ciphertext, nonce and salt
+fn encrypt_secret(password: &str, secret: &[u8]) -> anyhow::Result<(Vec<u8>, [u8; 12], SaltString)> {
+    // Derive encryption key from password
+    let salt = SaltString::generate(&mut OsRng);
+    let mut key_bytes = [0u8; 32];
+    
+    Argon2::default()
+        .hash_password_into(
+            password.as_bytes(),
+            salt.as_str().as_bytes(),
+            &mut key_bytes,
+        )
+        .map_err(|e| anyhow::anyhow!("Key derivation failed: {}", e))?;
+    
+    // Encrypt secret
+    let cipher = Aes256Gcm::new_from_slice(&key_bytes)
+        .map_err(|e| anyhow::anyhow!("Cipher creation failed: {}", e))?;
+    
+    let nonce_bytes: [u8; 12] = rand::random();
+    let nonce = Nonce::from_slice(&nonce_bytes);
+    
+    let ciphertext = cipher
+        .encrypt(nonce, secret)
+        .map_err(|e| anyhow::anyhow!("Encryption failed: {}", e))?;
+    
+    // Clean up sensitive data
+    key_bytes.zeroize();
+    
+    Ok((ciphertext, nonce_bytes, salt))
+}
+
+/// Decrypt a private key with a password-derived key
+fn decrypt_secret(
+    argon2: &Argon2,
+    password: &str,
+    salt: &str,
+    nonce: &[u8],
+    ciphertext: &[u8],
+) -> anyhow::Result<Keypair> {
+    let secret_bytes = decrypt_bytes(argon2, password, salt, nonce, ciphertext)?;
+    
+    // Create keypair from decrypted bytes
+    let mut secret_array: [u8; 32] = secret_bytes.as_slice().try_into()
+        .map_err(|_| anyhow::anyhow!("Invalid key length"))?;
+    
+    let keypair = Keypair::from_bytes(&secret_array);
+    secret_array.zeroize();
+    
+    Ok(keypair?)
+}
+
+/// Decrypt a secret with a password-derived key
+fn decrypt_bytes(
+    argon2: &Argon2,
+    password: &str,
+    salt: &str,
+    nonce: &[u8],
+    ciphertext: &[u8],
+) -> anyhow::Result<Zeroizing<Vec<u8>>> {
+    // Derive decryption key
+    let mut key_bytes = [0u8; 32];
+    argon2
+        .hash_password_into(
+            password.as_bytes(),
+            salt.as_bytes(),
+            &mut key_bytes,
+        )
+        .map_err(|e| anyhow::anyhow!("Key derivation failed: {}", e))?;
+    
+    // Decrypt private key
+    let cipher = Aes256Gcm::new_from_slice(&key_bytes)
+        .map_err(|e| anyhow::anyhow!("Cipher creation failed: {}", e))?;
+    
+    let nonce = Nonce::from_slice(nonce);
+    
+    let secret_bytes = cipher
+        .decrypt(nonce, ciphertext)
+        .map_err(|_| anyhow::anyhow!("Decryption failed - wrong password?"))?;
+    
+    // Clean up key
+    let mut key_bytes_clean = key_bytes;
+    key_bytes_clean.zeroize();
+    
+    Ok(Zeroizing::new(secret_bytes))
+}
+
+/// Decrypt a secret with `old_password` and encrypt it again with
+/// `new_password`, under a fresh salt and nonce
+fn reencrypt_secret(
+    old_password: &str,
+    new_password: &str,
+    salt: &str,
+    nonce: &[u8],
+    ciphertext: &[u8],
+) -> anyhow::Result<(Vec<u8>, [u8; 12], SaltString)> {
+    let secret = decrypt_bytes(&Argon2::default(), old_password, salt, nonce, ciphertext)?;
+    encrypt_secret(new_password, &secret)
+}
+
+impl EncryptedKey {
+    /// This key encrypted with `new_password` instead of `old_password`
+    fn reencrypted(&self, old_password: &str, new_password: &str) -> anyhow::Result<Self> {
+        let (ciphertext, nonce, salt) =
+            reencrypt_secret(old_password, new_password, &self.salt, &self.nonce, &self.ciphertext)?;
+        Ok(Self { ciphertext, nonce: nonce.to_vec(), salt: salt.to_string(), ..self.clone() })
+    }
+}
+
+impl HdSeed {
+    /// This seed encrypted with `new_password` instead of `old_password`
+    fn reencrypted(&self, old_password: &str, new_password: &str) -> anyhow::Result<Self> {
+        let (ciphertext, nonce, salt) =
+            reencrypt_secret(old_password, new_password, &self.salt, &self.nonce, &self.ciphertext)?;
+        Ok(Self { ciphertext, nonce: nonce.to_vec(), salt: salt.to_string(), ..self.clone() })
+    }
+}
+
+/// Write `content` to `path` through a temporary file, so that a crash
+/// leaves either the old file or the new one
+fn write_atomic(path: &Path, content: &str) -> anyhow::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    std::fs::write(&tmp, content)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// `text` trimmed, `None` when blank
+fn non_blank(text: Option<&str>) -> Option<String> {
+    text.map(str::trim).filter(|text| !text.is_empty()).map(str::to_string)
+}
+
 /// Keystore manager
 pub struct Keystore {
     /// Path to keystore file
@@ -68,6 +559,9 @@ pub struct Keystore {
     
     /// Unlocked keys (in memory)
     unlocked: Vec<UnlockedKey>,
+    
+    /// Key of the file, when it is encrypted as a whole
+    file_key: Option<FileKey>,
 }
 
 /// Unlocked key in memory
@@ -83,26 +577,71 @@ impl Keystore {
             path: None,
             data: KeystoreFile::default(),
             unlocked: Vec::new(),
+            file_key: None,
         }
     }
     
     /// Load keystore from file
+    ///
+    /// Fails on an encrypted file, see [`load_encrypted`](Self::load_encrypted).
     pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
         let content = std::fs::read_to_string(&path)?;
+        if EncryptedKeystoreFile::detect(&content) {
+            anyhow::bail!("Keystore file is encrypted; its password is needed to open it");
+        }
         let data: KeystoreFile = serde_json::from_str(&content)?;
         
         Ok(Self {
             path: Some(path.as_ref().to_path_buf()),
             data,
             unlocked: Vec::new(),
+            file_key: None,
         })
     }
     
+    /// Load a keystore file encrypted as a whole
+    ///
+    /// The keystore stays encrypted with the same password when saved.
+    pub fn load_encrypted<P: AsRef<Path>>(path: P, password: &str) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(&path)?;
+        let file: EncryptedKeystoreFile = serde_json::from_str(&content)?;
+        let (file_key, data) = FileKey::open(&file, password)?;
+        
+        Ok(Self {
+            path: Some(path.as_ref().to_path_buf()),
+            data,
+            unlocked: Vec::new(),
+            file_key: Some(file_key),
+        })
+    }
+    
+    /// Whether the keystore file at `path` is encrypted as a whole
+    pub fn is_encrypted_file<P: AsRef<Path>>(path: P) -> anyhow::Result<bool> {
+        Ok(EncryptedKeystoreFile::detect(&std::fs::read_to_string(path)?))
+    }
+    
+    /// Encrypt the whole file with `password` from the next save on
+    ///
+    /// Also migrates a plain keystore file. The keys inside stay encrypted
+    /// with their own passwords.
+    pub fn encrypt_file(&mut self, password: &str) -> anyhow::Result<()> {
+        self.file_key = Some(FileKey::derive(password)?);
+        Ok(())
+    }
+    
+    /// Whether the file is saved encrypted as a whole
+    pub fn is_file_encrypted(&self) -> bool {
+        self.file_key.is_some()
+    }
+    
     /// Save keystore to file
     pub fn save(&self) -> anyhow::Result<()> {
         if let Some(path) = &self.path {
-            let content = serde_json::to_string_pretty(&self.data)?;
-            std::fs::write(path, content)?;
+            let content = match &self.file_key {
+                Some(file_key) => serde_json::to_string_pretty(&file_key.seal(&self.data)?)?,
+                None => serde_json::to_string_pretty(&self.data)?,
+            };
+            write_atomic(path, &content)?;
         }
         Ok(())
     }
@@ -118,7 +657,7 @@ impl Keystore {
         let keypair = Keypair::generate();
         let address = keypair.address();
         
-        self.add_key(&keypair, password, label)?;
+        self.add_key(&keypair, password, label, None)?;
         
         Ok(address)
     }
@@ -133,42 +672,24 @@ impl Keystore {
         let keypair = Keypair::from_bytes(secret_bytes)?;
         let address = keypair.address();
         
-        self.add_key(&keypair, password, label)?;
+        self.add_key(&keypair, password, label, None)?;
         
         Ok(address)
     }
     
-    /// Add a keypair to the keystore
-    fn add_key(&mut self, keypair: &Keypair, password: &str, label: Option<String>) -> anyhow::Result<()> {
+    /// Add a keypair to the keystore, derived at `path` if given
+    fn add_key(
+        &mut self,
+        keypair: &Keypair,
+        password: &str,
+        label: Option<String>,
+        path: Option<&DerivationPath>,
+    ) -> anyhow::Result<()> {
         let address = keypair.address();
-        let secret_bytes = keypair.to_bytes();
-        
-        // Derive encryption key from password
-        let salt = SaltString::generate(&mut OsRng);
-        let mut key_bytes = [0u8; 32];
-        
-        Argon2::default()
-            .hash_password_into(
-                password.as_bytes(),
-                salt.as_str().as_bytes(),
-                &mut key_bytes,
-            )
-            .map_err(|e| anyhow::anyhow!("Key derivation failed: {}", e))?;
-        
-        // Encrypt private key
-        let cipher = Aes256Gcm::new_from_slice(&key_bytes)
-            .map_err(|e| anyhow::anyhow!("Cipher creation failed: {}", e))?;
-        
-        let nonce_bytes: [u8; 12] = rand::random();
-        let nonce = Nonce::from_slice(&nonce_bytes);
-        
-        let ciphertext = cipher
-            .encrypt(nonce, secret_bytes.as_ref())
-            .map_err(|e| anyhow::anyhow!("Encryption failed: {}", e))?;
-        
-        // Clean up sensitive data
-        let mut key_bytes_clean = key_bytes;
-        key_bytes_clean.zeroize();
+        let mut secret_bytes = keypair.to_bytes();
+        let sealed = encrypt_secret(password, &secret_bytes);
+        secret_bytes.zeroize();
+        let (ciphertext, nonce_bytes, salt) = sealed?;
         
         // Store encrypted key
         let encrypted = EncryptedKey {
@@ -178,6 +699,10 @@ impl Keystore {
             salt: salt.to_string(),
             label,
             created_at: chrono::Utc::now().timestamp(),
+            path: path.map(DerivationPath::to_string),
+            note: None,
+            archived: false,
+            hidden: false,
         };
         
         self.data.keys.push(encrypted);
@@ -185,6 +710,120 @@ impl Keystore {
         Ok(())
     }
     
+    /// Derive keys from a new 24-word mnemonic from now on, returning the
+    /// phrase to back up
+    pub fn create_hd(&mut self, password: &str) -> anyhow::Result<Zeroizing<String>> {
+        let entropy = Zeroizing::new(rand::random::<[u8; MNEMONIC_ENTROPY]>());
+        let mnemonic = Mnemonic::from_entropy(entropy.as_ref())?;
+        self.set_hd_seed(&mnemonic, password)?;
+        Ok(Zeroizing::new(mnemonic.to_string()))
+    }
+    
+    /// Derive keys from the mnemonic `phrase` from now on
+    ///
+    /// Keys already derived from it are not added back; see [`Self::scan_hd`].
+    pub fn restore_hd(&mut self, phrase: &str, password: &str) -> anyhow::Result<()> {
+        let mnemonic = Mnemonic::parse(phrase)
+            .map_err(|e| anyhow::anyhow!("Invalid mnemonic: {}", e))?;
+        self.set_hd_seed(&mnemonic, password)
+    }
+    
+    fn set_hd_seed(&mut self, mnemonic: &Mnemonic, password: &str) -> anyhow::Result<()> {
+        if self.data.hd.is_some() {
+            anyhow::bail!("Keystore already has an HD seed");
+        }
+        
+        let entropy = Zeroizing::new(mnemonic.to_entropy());
+        let (ciphertext, nonce, salt) = encrypt_secret(password, &entropy)?;
+        self.data.hd = Some(HdSeed {
+            ciphertext,
+            nonce: nonce.to_vec(),
+            salt: salt.to_string(),
+            account: 0,
+            next_receive: 0,
+            next_change: 0,
+        });
+        Ok(())
+    }
+    
+    /// Whether new keys are derived from an HD seed
+    pub fn is_hd(&self) -> bool {
+        self.data.hd.is_some()
+    }
+    
+    /// Mnemonic phrase of the HD seed
+    pub fn mnemonic(&self, password: &str) -> anyhow::Result<Zeroizing<String>> {
+        Ok(Zeroizing::new(self.hd_mnemonic(password)?.to_string()))
+    }
+    
+    fn hd_mnemonic(&self, password: &str) -> anyhow::Result<Mnemonic> {
+        let hd = self.data.hd.as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Keystore has no HD seed"))?;
+        let entropy = decrypt_bytes(&Argon2::default(), password, &hd.salt, &hd.nonce, &hd.ciphertext)?;
+        Ok(Mnemonic::from_entropy(&entropy)?)
+    }
+    
+    /// Master key of the HD seed
+    fn master_key(&self, password: &str) -> anyhow::Result<ExtendedKey> {
+        let seed = Zeroizing::new(self.hd_mnemonic(password)?.to_seed(""));
+        Ok(ExtendedKey::master(seed.as_ref()))
+    }
+    
+    /// Derive the next receive or change key from the HD seed
+    pub fn derive_key(&mut self, password: &str, change: bool, label: Option<String>) -> anyhow::Result<Address> {
+        let master = self.master_key(password)?;
+        let hd = self.data.hd.as_ref().expect("master key implies a seed");
+        let index = if change { hd.next_change } else { hd.next_receive };
+        let path = DerivationPath::bip44(hd.account, change, index);
+        
+        let keypair = master.derive(&path).keypair()?;
+        self.add_key(&keypair, password, label, Some(&path))?;
+        *self.data.hd.as_mut().expect("checked above").next_index(change) = index + 1;
+        
+        Ok(keypair.address())
+    }
+    
+    /// Add back the derived keys `is_used` reports activity for
+    ///
+    /// Both the receive and change chains are walked from index 0 until
+    /// `gap_limit` unused addresses in a row, and the next indexes are moved
+    /// past the last used one. Returns the used addresses, in order.
+    pub fn scan_hd(
+        &mut self,
+        password: &str,
+        gap_limit: u32,
+        mut is_used: impl FnMut(&Address) -> bool,
+    ) -> anyhow::Result<Vec<Address>> {
+        let master = self.master_key(password)?;
+        let account = self.data.hd.as_ref().expect("master key implies a seed").account;
+        
+        let mut used = Vec::new();
+        for change in [false, true] {
+            let mut index = 0;
+            let mut unused = 0;
+            while unused < gap_limit {
+                let path = DerivationPath::bip44(account, change, index);
+                let keypair = master.derive(&path).keypair()?;
+                let address = keypair.address();
+                
+                if is_used(&address) {
+                    if !self.data.keys.iter().any(|k| k.address == address.to_string()) {
+                        self.add_key(&keypair, password, None, Some(&path))?;
+                    }
+                    let next = self.data.hd.as_mut().expect("checked above").next_index(change);
+                    *next = (*next).max(index + 1);
+                    used.push(address);
+                    unused = 0;
+                } else {
+                    unused += 1;
+                }
+                index += 1;
+            }
+        }
+        
+        Ok(used)
+    }
+    
     /// Unlock a key with password
     pub fn unlock(&mut self, address: &Address, password: &str) -> anyhow::Result<()> {
         let address_str = address.to_string();
@@ -194,35 +833,13 @@ impl Keystore {
             .ok_or_else(|| anyhow::anyhow!("Key not found"))?
             .clone();
         
-        // Derive decryption key
-        let mut key_bytes = [0u8; 32];
-        Argon2::default()
-            .hash_password_into(
-                password.as_bytes(),
-                encrypted.salt.as_bytes(),
-                &mut key_bytes,
-            )
-            .map_err(|e| anyhow::anyhow!("Key derivation failed: {}", e))?;
-        
-        // Decrypt private key
-        let cipher = Aes256Gcm::new_from_slice(&key_bytes)
-            .map_err(|e| anyhow::anyhow!("Cipher creation failed: {}", e))?;
-        
-        let nonce = Nonce::from_slice(&encrypted.nonce);
-        
-        let secret_bytes = cipher
-            .decrypt(nonce, encrypted.ciphertext.as_ref())
-            .map_err(|_| anyhow::anyhow!("Decryption failed - wrong password?"))?;
-        
-        // Clean up key
-        let mut key_bytes_clean = key_bytes;
-        key_bytes_clean.zeroize();
-        
-        // Create keypair from decrypted bytes
-        let secret_array: [u8; 32] = secret_bytes.try_into()
-            .map_err(|_| anyhow::anyhow!("Invalid key length"))?;
-        
-        let keypair = Keypair::from_bytes(&secret_array)?;
+        let keypair = decrypt_secret(
+            &Argon2::default(),
+            password,
+            &encrypted.salt,
+            &encrypted.nonce,
+            &encrypted.ciphertext,
+        )?;
         
         // Store unlocked key
         self.unlocked.push(UnlockedKey {
@@ -233,6 +850,91 @@ impl Keystore {
         Ok(())
     }
     
+    /// Encrypt the key of `address` with `new_password` instead of
+    /// `old_password`, under a fresh salt and nonce
+    pub fn change_password(&mut self, address: &Address, old_password: &str, new_password: &str) -> anyhow::Result<()> {
+        let address_str = address.to_string();
+        let key = self.data.keys.iter_mut()
+            .find(|k| k.address == address_str)
+            .ok_or_else(|| anyhow::anyhow!("Key not found"))?;
+        *key = key.reencrypted(old_password, new_password)?;
+        Ok(())
+    }
+    
+    /// Encrypt every key and the HD seed with `new_password` instead of
+    /// `old_password`, returning the number of keys
+    ///
+    /// Everything is decrypted before anything is replaced: if one entry
+    /// does not open with `old_password`, none is changed.
+    pub fn change_all_passwords(&mut self, old_password: &str, new_password: &str) -> anyhow::Result<usize> {
+        let keys = self.data.keys.iter()
+            .map(|key| key.reencrypted(old_password, new_password)
+                .map_err(|e| anyhow::anyhow!("{}: {}", key.address, e)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let hd = self.data.hd.as_ref()
+            .map(|hd| hd.reencrypted(old_password, new_password)
+                .map_err(|e| anyhow::anyhow!("HD seed: {}", e)))
+            .transpose()?;
+        
+        self.data.keys = keys;
+        if hd.is_some() {
+            self.data.hd = hd;
+        }
+        Ok(self.data.keys.len())
+    }
+    
+    /// Export a single key as a standalone keyfile
+    ///
+    /// The keyfile stays encrypted with the password of the key.
+    pub fn export_keyfile(&self, address: &Address) -> anyhow::Result<KeyFile> {
+        let address_str = address.to_string();
+        
+        self.data.keys.iter()
+            .find(|k| k.address == address_str)
+            .map(KeyFile::from)
+            .ok_or_else(|| anyhow::anyhow!("Key not found"))
+    }
+    
+    /// Import a keyfile, re-encrypting its key with the keystore password
+    pub fn import_keyfile(
+        &mut self,
+        keyfile: &KeyFile,
+        keyfile_password: &str,
+        password: &str,
+    ) -> anyhow::Result<Address> {
+        if self.data.keys.iter().any(|k| k.address == keyfile.address) {
+            anyhow::bail!("Key already exists: {}", keyfile.address);
+        }
+        
+        let keypair = keyfile.decrypt(keyfile_password)?;
+        let address = keypair.address();
+        
+        self.add_key(&keypair, password, keyfile.label.clone(), None)?;
+        
+        Ok(address)
+    }
+    
+    /// Write every key of this keystore to its own keyfile in `dir`
+    ///
+    /// Keys are repackaged as-is, so no password is needed and each keyfile
+    /// keeps the password its key had in the keystore.
+    pub fn migrate_to_keyfiles<P: AsRef<Path>>(&self, dir: P) -> anyhow::Result<Vec<PathBuf>> {
+        std::fs::create_dir_all(&dir)?;
+        
+        let mut written = Vec::with_capacity(self.data.keys.len());
+        for key in &self.data.keys {
+            let keyfile = KeyFile::from(key);
+            let path = dir.as_ref().join(keyfile.file_name());
+            if path.exists() {
+                anyhow::bail!("Keyfile already exists: {}", path.display());
+            }
+            keyfile.save(&path)?;
+            written.push(path);
+        }
+        
+        Ok(written)
+    }
+    
     /// Lock all keys
     pub fn lock_all(&mut self) {
         self.unlocked.clear();
@@ -259,6 +961,66 @@ impl Keystore {
     pub fn key_count(&self) -> usize {
         self.data.keys.len()
     }
+    
+    /// Addresses that are not archived, in creation order
+    pub fn active_addresses(&self) -> Vec<String> {
+        self.data.keys.iter()
+            .filter(|k| !k.archived)
+            .map(|k| k.address.clone())
+            .collect()
+    }
+    
+    /// Stored entry of `address`, with its label and metadata
+    pub fn key_entry(&self, address: &Address) -> Option<&EncryptedKey> {
+        let address_str = address.to_string();
+        self.data.keys.iter().find(|k| k.address == address_str)
+    }
+    
+    /// Stored entries of every key
+    pub fn key_entries(&self) -> &[EncryptedKey] {
+        &self.data.keys
+    }
+    
+    fn key_entry_mut(&mut self, address: &Address) -> anyhow::Result<&mut EncryptedKey> {
+        let address_str = address.to_string();
+        self.data.keys.iter_mut()
+            .find(|k| k.address == address_str)
+            .ok_or_else(|| anyhow::anyhow!("Key not found"))
+    }
+    
+    /// Set or clear (`None` or blank) the label of `address`
+    pub fn set_label(&mut self, address: &Address, label: Option<&str>) -> anyhow::Result<()> {
+        self.key_entry_mut(address)?.label = non_blank(label);
+        Ok(())
+    }
+    
+    /// Set or clear (`None` or blank) the note of `address`
+    pub fn set_note(&mut self, address: &Address, note: Option<&str>) -> anyhow::Result<()> {
+        self.key_entry_mut(address)?.note = non_blank(note);
+        Ok(())
+    }
+    
+    /// Archive `address` or bring it back into use
+    pub fn set_archived(&mut self, address: &Address, archived: bool) -> anyhow::Result<()> {
+        self.key_entry_mut(address)?.archived = archived;
+        Ok(())
+    }
+    
+    /// Hide `address` from listings or show it again
+    pub fn set_hidden(&mut self, address: &Address, hidden: bool) -> anyhow::Result<()> {
+        self.key_entry_mut(address)?.hidden = hidden;
+        Ok(())
+    }
+    
+    /// Address book
+    pub fn contacts(&self) -> &AddressBook {
+        &self.data.contacts
+    }
+    
+    /// Address book, to edit before saving
+    pub fn contacts_mut(&mut self) -> &mut AddressBook {
+        &mut self.data.contacts
+    }
 }
 
 impl Default for Keystore {
@@ -297,4 +1059,196 @@ mod tests {
         
         assert!(keystore.unlock(&address, "wrong_password").is_err());
     }
+    
+    #[test]
+    fn test_keyfile_export_import() {
+        let mut source = Keystore::new();
+        let address = source.generate_key("source_pw", Some("Savings".to_string())).unwrap();
+        
+        let keyfile = source.export_keyfile(&address).unwrap();
+        assert_eq!(keyfile.address, address.to_string());
+        
+        // Round-trip through JSON, as when moving the file between machines
+        let json = serde_json::to_string(&keyfile).unwrap();
+        let keyfile: KeyFile = serde_json::from_str(&json).unwrap();
+        
+        let mut target = Keystore::new();
+        assert!(target.import_keyfile(&keyfile, "wrong_pw", "target_pw").is_err());
+        
+        let imported = target.import_keyfile(&keyfile, "source_pw", "target_pw").unwrap();
+        assert_eq!(imported, address);
+        assert!(target.import_keyfile(&keyfile, "source_pw", "target_pw").is_err());
+        
+        target.unlock(&address, "target_pw").unwrap();
+        source.unlock(&address, "source_pw").unwrap();
+        assert_eq!(
+            target.get_signing_key(&address).unwrap().to_bytes(),
+            source.get_signing_key(&address).unwrap().to_bytes()
+        );
+    }
+    
+    #[test]
+    fn test_hd_derive_and_restore() {
+        let mut keystore = Keystore::new();
+        assert!(keystore.derive_key("pw", false, None).is_err());
+        let phrase = keystore.create_hd("pw").unwrap();
+        assert_eq!(phrase.split_whitespace().count(), 24);
+        assert!(keystore.create_hd("pw").is_err());
+        assert!(keystore.mnemonic("wrong").is_err());
+        assert_eq!(keystore.mnemonic("pw").unwrap(), phrase);
+        
+        let first = keystore.derive_key("pw", false, None).unwrap();
+        let second = keystore.derive_key("pw", false, None).unwrap();
+        let change = keystore.derive_key("pw", true, None).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(
+            keystore.data.keys[2].path.as_deref(),
+            Some(DerivationPath::bip44(0, true, 0).to_string().as_str())
+        );
+        
+        // The phrase alone brings back the used keys, skipping the unused one
+        let mut restored = Keystore::new();
+        restored.restore_hd(&phrase, "other_pw").unwrap();
+        let used = restored.scan_hd("other_pw", 3, |address| *address != first).unwrap();
+        assert_eq!(used, vec![second.clone(), change.clone()]);
+        assert_eq!(restored.key_count(), 2);
+        
+        // Scanning moved past the used indexes
+        let next = restored.derive_key("other_pw", false, None).unwrap();
+        assert_eq!(next, keystore.derive_key("pw", false, None).unwrap());
+        
+        restored.unlock(&second, "other_pw").unwrap();
+        keystore.unlock(&second, "pw").unwrap();
+        assert_eq!(
+            restored.get_signing_key(&second).unwrap().to_bytes(),
+            keystore.get_signing_key(&second).unwrap().to_bytes()
+        );
+        
+        assert!(Keystore::new().restore_hd("not a mnemonic", "pw").is_err());
+    }
+    
+    #[test]
+    fn test_load_version_1() {
+        let file: KeystoreFile = serde_json::from_str(r#"{"version":1,"keys":[{"address":"a","ciphertext":[],"nonce":[],"salt":"s","label":null,"created_at":0}]}"#).unwrap();
+        assert!(file.hd.is_none());
+        assert!(file.keys[0].path.is_none());
+    }
+    
+    #[test]
+    fn test_migrate_to_keyfiles() {
+        let mut keystore = Keystore::new();
+        let first = keystore.generate_key("pw1", None).unwrap();
+        let second = keystore.generate_key("pw2", None).unwrap();
+        
+        let dir = std::env::temp_dir().join(format!("aequitas-keyfiles-{}", rand::random::<u64>()));
+        let written = keystore.migrate_to_keyfiles(&dir).unwrap();
+        assert_eq!(written.len(), 2);
+        
+        let keyfile = KeyFile::load(dir.join(format!("{}.json", first))).unwrap();
+        assert_eq!(keyfile.decrypt("pw1").unwrap().address(), first);
+        
+        let keyfile = KeyFile::load(dir.join(format!("{}.json", second))).unwrap();
+        assert!(keyfile.decrypt("pw1").is_err());
+        assert_eq!(keyfile.decrypt("pw2").unwrap().address(), second);
+        
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+    
+    #[test]
+    fn test_change_password() {
+        let mut keystore = Keystore::new();
+        let first = keystore.generate_key("old", None).unwrap();
+        let second = keystore.generate_key("old", None).unwrap();
+        let salt = keystore.data.keys[0].salt.clone();
+        
+        assert!(keystore.change_password(&first, "wrong", "new").is_err());
+        keystore.change_password(&first, "old", "new").unwrap();
+        assert_ne!(keystore.data.keys[0].salt, salt);
+        assert!(keystore.unlock(&first, "old").is_err());
+        keystore.unlock(&first, "new").unwrap();
+        
+        // One key under another password: nothing changes
+        assert!(keystore.change_all_passwords("old", "newer").is_err());
+        keystore.unlock(&second, "old").unwrap();
+        
+        keystore.change_password(&first, "new", "old").unwrap();
+        keystore.create_hd("old").unwrap();
+        let phrase = keystore.mnemonic("old").unwrap();
+        assert_eq!(keystore.change_all_passwords("old", "newer").unwrap(), 2);
+        keystore.unlock(&first, "newer").unwrap();
+        keystore.unlock(&second, "newer").unwrap();
+        assert_eq!(keystore.mnemonic("newer").unwrap().as_str(), phrase.as_str());
+        assert!(keystore.mnemonic("old").is_err());
+    }
+    
+    #[test]
+    fn test_key_metadata() {
+        let mut keystore = Keystore::new();
+        let first = keystore.generate_key("pw", Some("Old".to_string())).unwrap();
+        let second = keystore.generate_key("pw", None).unwrap();
+        
+        keystore.set_label(&first, Some(" Savings ")).unwrap();
+        keystore.set_note(&first, Some("cold storage")).unwrap();
+        keystore.set_archived(&first, true).unwrap();
+        keystore.set_hidden(&second, true).unwrap();
+        keystore.set_label(&second, Some("  ")).unwrap();
+        
+        let entry = keystore.key_entry(&first).unwrap();
+        assert_eq!((entry.label.as_deref(), entry.note.as_deref()), (Some("Savings"), Some("cold storage")));
+        assert!(entry.archived && !entry.hidden);
+        assert_eq!(keystore.key_entry(&second).unwrap().label, None);
+        assert_eq!(keystore.active_addresses(), vec![second.to_string()]);
+        
+        // The metadata survives a save
+        let json = serde_json::to_string(&keystore.data).unwrap();
+        let file: KeystoreFile = serde_json::from_str(&json).unwrap();
+        assert!(file.keys[0].archived && file.keys[1].hidden && !file.keys[1].archived);
+        
+        let unknown = Address::from_public_key(&SigningKey::from_bytes(&[1u8; 32]).verifying_key());
+        assert!(keystore.set_label(&unknown, Some("x")).is_err());
+    }
+    
+    #[test]
+    fn test_encrypted_file() {
+        let path = std::env::temp_dir().join(format!("aequitas-keystore-{}.json", rand::random::<u64>()));
+        let mut keystore = Keystore::new();
+        let address = keystore.generate_key("key-pw", Some("Savings".to_string())).unwrap();
+        keystore.save_to(&path).unwrap();
+        assert!(!Keystore::is_encrypted_file(&path).unwrap());
+        
+        // Migrate the plain file
+        let mut keystore = Keystore::load(&path).unwrap();
+        keystore.encrypt_file("file-pw").unwrap();
+        keystore.save().unwrap();
+        
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(!content.contains(&address.to_string()) && !content.contains("Savings"));
+        assert!(Keystore::is_encrypted_file(&path).unwrap());
+        assert!(Keystore::load(&path).is_err());
+        assert!(Keystore::load_encrypted(&path, "key-pw").is_err());
+        
+        let mut keystore = Keystore::load_encrypted(&path, "file-pw").unwrap();
+        assert!(keystore.is_file_encrypted());
+        assert_eq!(keystore.addresses(), vec![address.to_string()]);
+        keystore.unlock(&address, "key-pw").unwrap();
+        
+        // Saving again keeps it encrypted, under a fresh nonce
+        keystore.save().unwrap();
+        assert_ne!(std::fs::read_to_string(&path).unwrap(), content);
+        assert!(Keystore::load_encrypted(&path, "file-pw").is_ok());
+        
+        // Any change to the file is caught
+        let mut file: EncryptedKeystoreFile = serde_json::from_str(&content).unwrap();
+        file.header.kdfparams.t_cost += 1;
+        std::fs::write(&path, serde_json::to_string(&file).unwrap()).unwrap();
+        assert!(Keystore::load_encrypted(&path, "file-pw").is_err());
+        
+        let mut file: EncryptedKeystoreFile = serde_json::from_str(&content).unwrap();
+        let flipped = if file.ciphertext.starts_with('0') { "1" } else { "0" };
+        file.ciphertext.replace_range(..1, flipped);
+        std::fs::write(&path, serde_json::to_string(&file).unwrap()).unwrap();
+        assert!(Keystore::load_encrypted(&path, "file-pw").is_err());
+        
+        std::fs::remove_file(&path).unwrap();
+    }
 }
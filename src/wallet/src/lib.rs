@@ -3,10 +3,35 @@
 //! Secure wallet implementation with encrypted key storage.
 
 pub mod keystore;
+pub mod hd;
 pub mod wallet;
 pub mod builder;
+pub mod audit;
+pub mod fees;
+pub mod balance;
+pub mod offline;
+pub mod multisig;
+pub mod contacts;
+pub mod uri;
+pub mod outbox;
+pub mod rescan;
+pub mod service;
+pub mod session;
+pub mod hw;
 
-pub use keystore::Keystore;
+pub use keystore::{Keystore, KeyFile, DEFAULT_GAP_LIMIT};
+pub use hd::{DerivationPath, ExtendedKey};
 pub use wallet::{Wallet, format_balance, parse_balance};
-pub use builder::TransactionBuilder;
+pub use builder::{TransactionBuilder, TxPreview};
+pub use audit::{AuditLog, AuditEvent, AuditEntry};
+pub use fees::FeeEstimates;
+pub use balance::BalanceInfo;
+pub use offline::TxEnvelope;
+pub use multisig::MultisigWallet;
+pub use contacts::{AddressBook, Contact};
+pub use uri::PaymentRequest;
+pub use outbox::{Outbox, OutboxStatus};
+pub use rescan::WalletHistory;
+pub use service::WalletService;
+pub use hw::Signer;
 
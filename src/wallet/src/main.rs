@@ -2,10 +2,21 @@
 //!
 //! Command-line wallet for managing Aequitas addresses and transactions.
 
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use std::collections::HashSet;
+use std::net::SocketAddr;
 use std::path::PathBuf;
-use aequitas_wallet::{Wallet, Keystore};
-use aequitas_wallet::wallet::{format_balance, parse_balance};
+use std::time::Duration;
+use zeroize::Zeroizing;
+use aequitas_wallet::{Wallet, Keystore, AuditLog, AuditEvent, BalanceInfo, FeeEstimates, MultisigWallet, Outbox, OutboxStatus, PaymentRequest, TransactionBuilder, TxEnvelope, WalletHistory, DEFAULT_GAP_LIMIT};
+use aequitas_wallet::keystore::KeyFile;
+use aequitas_wallet::service::{self, WalletService};
+use aequitas_wallet::session::{self, UnlockToken, DEFAULT_SESSION};
+use aequitas_core::amount::{parse_amount_in, AmountFormat, Unit};
+use aequitas_core::{Address, Blockchain, Memo, MultisigPolicy, Transaction, TxDirection};
+
+mod shell;
 
 #[derive(Parser)]
 #[command(name = "aequitas-wallet")]
@@ -19,34 +30,75 @@ struct Cli {
     /// Wallet file path
     #[arg(short, long, default_value = "wallet.json")]
     wallet: PathBuf,
+    
+    /// Unit for displayed amounts (aeq, maeq, base)
+    #[arg(long, global = true, default_value = "aeq")]
+    unit: Unit,
+    
+    /// Decimal places shown for amounts (full precision if omitted)
+    #[arg(long, global = true)]
+    precision: Option<u32>,
 }
 
 #[derive(Subcommand)]
 enum Commands {
     /// Create a new wallet
     New {
-        /// Password for encryption
+        /// Password for encryption (asked for if omitted)
         #[arg(short, long)]
-        password: String,
+        password: Option<String>,
         
         /// Optional label for the address
         #[arg(short, long)]
         label: Option<String>,
     },
     
+    /// Restore a wallet from its recovery phrase
+    Restore {
+        /// Recovery phrase (BIP39 mnemonic, asked for if omitted)
+        #[arg(short, long)]
+        mnemonic: Option<String>,
+        
+        /// Password for encryption (asked for if omitted)
+        #[arg(short, long)]
+        password: Option<String>,
+        
+        /// Node chain file to find used addresses in
+        #[arg(long)]
+        chain: PathBuf,
+        
+        /// Unused addresses in a row after which the scan stops
+        #[arg(long, default_value_t = DEFAULT_GAP_LIMIT)]
+        gap_limit: u32,
+    },
+    
     /// Generate a new address in existing wallet
     Generate {
-        /// Password
+        /// Password (asked for if omitted)
         #[arg(short, long)]
-        password: String,
+        password: Option<String>,
         
         /// Optional label
         #[arg(short, long)]
         label: Option<String>,
     },
     
-    /// List all addresses
-    List,
+    /// List addresses with their labels, and balances from a node
+    List {
+        /// Node RPC URL to read balances from
+        #[arg(short, long)]
+        node: Option<String>,
+        
+        /// Include hidden addresses
+        #[arg(short, long)]
+        all: bool,
+    },
+    
+    /// Manage address labels, notes and archived/hidden flags
+    Label {
+        #[command(subcommand)]
+        command: LabelCommand,
+    },
     
     /// Show wallet info
     Info,
@@ -57,26 +109,167 @@ enum Commands {
         #[arg(short, long)]
         address: String,
         
-        /// Password
+        /// Password (asked for if omitted)
         #[arg(short, long)]
-        password: String,
+        password: Option<String>,
     },
     
     /// Import private key
     Import {
-        /// Private key in hex format
+        /// Private key in hex format (asked for if omitted)
         #[arg(short, long)]
-        key: String,
+        key: Option<String>,
         
-        /// Password for encryption
+        /// Password for encryption (asked for if omitted)
         #[arg(short, long)]
-        password: String,
+        password: Option<String>,
         
         /// Optional label
         #[arg(short, long)]
         label: Option<String>,
     },
     
+    /// Export a single key as an encrypted keyfile
+    ExportKeyfile {
+        /// Address to export
+        #[arg(short, long)]
+        address: String,
+        
+        /// Output file (defaults to <address>.json)
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+    },
+    
+    /// Import an encrypted keyfile
+    ImportKeyfile {
+        /// Keyfile to import
+        #[arg(short, long)]
+        file: PathBuf,
+        
+        /// Password of the keyfile (asked for if omitted)
+        #[arg(short, long)]
+        password: Option<String>,
+        
+        /// Password for the key in this wallet (defaults to the keyfile password)
+        #[arg(long)]
+        wallet_password: Option<String>,
+    },
+    
+    /// Split the wallet into one encrypted keyfile per address
+    Migrate {
+        /// Output directory
+        #[arg(short, long, default_value = "keys")]
+        dir: PathBuf,
+    },
+    
+    /// Change the password of a key, or of every key with --all
+    ChangePassword {
+        /// Address whose key to re-encrypt
+        #[arg(short, long, required_unless_present = "all", conflicts_with = "all")]
+        address: Option<String>,
+        
+        /// Re-encrypt every key, the recovery phrase and the wallet file
+        #[arg(long)]
+        all: bool,
+        
+        /// Current password (asked for if omitted)
+        #[arg(long)]
+        old_password: Option<String>,
+        
+        /// New password (asked for if omitted)
+        #[arg(long)]
+        new_password: Option<String>,
+    },
+    
+    /// Encrypt the whole wallet file, hiding its addresses and labels
+    Encrypt {
+        /// Password for the wallet file (asked for if omitted)
+        #[arg(short, long)]
+        password: Option<String>,
+    },
+    
+    /// Keep the wallet password in the OS keychain for a while
+    Keychain {
+        #[command(subcommand)]
+        command: KeychainCommand,
+    },
+    
+    /// Derive the address of an M-of-N multisig policy
+    MultisigAddress {
+        /// Required number of signatures
+        #[arg(short, long)]
+        threshold: u8,
+        
+        /// Public key in hex (repeat for each co-signer, order matters)
+        #[arg(short, long = "key", required = true)]
+        keys: Vec<String>,
+    },
+    
+    /// Create, spend from and co-sign M-of-N multisig wallets
+    Multisig {
+        #[command(subcommand)]
+        command: MultisigCommand,
+    },
+    
+    /// Manage the address book
+    Contacts {
+        #[command(subcommand)]
+        command: ContactsCommand,
+    },
+    
+    /// Request a payment with an `aequitas:` URI and its QR code
+    Receive {
+        /// Amount requested (in --unit when no unit is given)
+        #[arg(long)]
+        amount: Option<String>,
+        
+        /// Label of the request, e.g. `invoice 42`
+        #[arg(long)]
+        label: Option<String>,
+        
+        /// Note shown to the payer
+        #[arg(long)]
+        message: Option<String>,
+        
+        /// Payment reference the payer attaches as memo
+        #[arg(long)]
+        reference: Option<String>,
+        
+        /// Address to be paid (defaults to the wallet's first address not archived)
+        #[arg(short, long)]
+        address: Option<String>,
+        
+        /// Also save the QR code as a PNG image
+        #[arg(long)]
+        png: Option<PathBuf>,
+        
+        /// Print the URI without a QR code
+        #[arg(long)]
+        no_qr: bool,
+    },
+    
+    /// Show and retry the signed transactions waiting to be broadcast or mined
+    Outbox {
+        #[command(subcommand)]
+        command: Option<OutboxCommand>,
+    },
+    
+    /// Rebuild the history and unspent outputs of the wallet's addresses
+    /// from the blocks of a node or a chain file
+    Rescan {
+        /// Node RPC URL
+        #[arg(short, long, required_unless_present = "chain")]
+        node: Option<String>,
+        
+        /// Chain file to read instead of a node
+        #[arg(long, conflicts_with = "node")]
+        chain: Option<PathBuf>,
+        
+        /// Keep the saved history below this height and rescan from it
+        #[arg(long)]
+        from_height: Option<u64>,
+    },
+    
     /// Show balance (requires node connection)
     Balance {
         /// Address to check (optional, shows all if not specified)
@@ -86,175 +279,1712 @@ enum Commands {
         /// Node RPC URL
         #[arg(short, long, default_value = "http://127.0.0.1:8080")]
         node: String,
+        
+        /// List each unspent output with its confirmations
+        #[arg(long)]
+        utxos: bool,
     },
-}
-
-#[tokio::main]
-async fn main() -> anyhow::Result<()> {
-    let cli = Cli::parse();
     
-    match cli.command {
-        Commands::New { password, label } => {
-            cmd_new(&cli.wallet, &password, label)?;
-        }
-        Commands::Generate { password, label } => {
-            cmd_generate(&cli.wallet, &password, label)?;
-        }
-        Commands::List => {
-            cmd_list(&cli.wallet)?;
-        }
-        Commands::Info => {
-            cmd_info(&cli.wallet)?;
-        }
-        Commands::Export { address, password } => {
-            cmd_export(&cli.wallet, &address, &password)?;
-        }
-        Commands::Import { key, password, label } => {
-            cmd_import(&cli.wallet, &key, &password, label)?;
-        }
-        Commands::Balance { address, node } => {
-            cmd_balance(&cli.wallet, address, &node).await?;
-        }
-    }
+    /// Send funds after reviewing the signed transaction
+    Send {
+        /// Recipient address, contact name or `aequitas:` payment URI
+        to: String,
+        
+        /// Amount, e.g. `1.5` or `250 mAEQ` (in --unit when no unit is given;
+        /// optional when the payment URI asks for one)
+        amount: Option<String>,
+        
+        /// Send the whole balance of the sending address, less the fee
+        #[arg(long, conflicts_with_all = ["amount", "change_to"])]
+        all: bool,
+        
+        /// Sending address (defaults to the wallet's first address not archived)
+        #[arg(short, long)]
+        from: Option<String>,
+        
+        /// Fee in base units (priced on the signed size if omitted)
+        #[arg(long)]
+        fee: Option<u64>,
+        
+        /// Price the fee from the node mempool to be mined within this many blocks
+        #[arg(long, conflicts_with = "fee")]
+        confirm_within: Option<u32>,
+        
+        /// Wallet address to send change to (defaults to the sender)
+        #[arg(long)]
+        change_to: Option<String>,
+        
+        /// Node chain file to select unspent outputs from
+        #[arg(long)]
+        chain: PathBuf,
+        
+        /// Node RPC URL
+        #[arg(short, long, default_value = "http://127.0.0.1:8080")]
+        node: String,
+        
+        /// Memo attached to the transaction
+        #[arg(long)]
+        memo: Option<String>,
+        
+        /// How to read --memo (a payment reference for exchange deposit IDs)
+        #[arg(long, value_enum, default_value_t = MemoType::Text, requires = "memo")]
+        memo_type: MemoType,
+        
+        /// Also spend change of outbox transactions not mined yet, raising the
+        /// fee to pay for them too (child pays for parent)
+        #[arg(long)]
+        spend_unconfirmed: bool,
+        
+        /// Broadcast without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
     
-    Ok(())
-}
-
-fn cmd_new(path: &PathBuf, password: &str, label: Option<String>) -> anyhow::Result<()> {
-    if path.exists() {
-        anyhow::bail!("Wallet already exists: {}. Use 'generate' to add addresses.", path.display());
-    }
+    /// Build an unsigned transaction file, to sign on an offline machine
+    CreateUnsigned {
+        /// Recipient address
+        to: String,
+        
+        /// Amount, e.g. `1.5` or `250 mAEQ` (in --unit when no unit is given)
+        amount: String,
+        
+        /// Sending address (defaults to the wallet's first address not archived)
+        #[arg(short, long)]
+        from: Option<String>,
+        
+        /// Fee in base units (priced on the signed size if omitted)
+        #[arg(long)]
+        fee: Option<u64>,
+        
+        /// Price the fee from the node mempool to be mined within this many blocks
+        #[arg(long, conflicts_with = "fee")]
+        confirm_within: Option<u32>,
+        
+        /// Address to send change to (defaults to the sender)
+        #[arg(long)]
+        change_to: Option<String>,
+        
+        /// Node chain file to select unspent outputs from
+        #[arg(long)]
+        chain: PathBuf,
+        
+        /// Node RPC URL
+        #[arg(short, long, default_value = "http://127.0.0.1:8080")]
+        node: String,
+        
+        /// Memo attached to the transaction
+        #[arg(long)]
+        memo: Option<String>,
+        
+        /// How to read --memo (a payment reference for exchange deposit IDs)
+        #[arg(long, value_enum, default_value_t = MemoType::Text, requires = "memo")]
+        memo_type: MemoType,
+        
+        /// Output file
+        #[arg(short, long, default_value = "unsigned-tx.json")]
+        out: PathBuf,
+    },
     
-    println!("\n🔐 Creating new Aequitas wallet...\n");
+    /// Sign a transaction file with the keys of this wallet
+    Sign {
+        /// Transaction file from `create-unsigned`
+        #[arg(short, long)]
+        file: PathBuf,
+        
+        /// Output file (defaults to overwriting --file)
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+        
+        /// Sign without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
     
-    let mut wallet = Wallet::new();
-    let address = wallet.new_address(password, label.clone())?;
-    wallet.save_to(path)?;
+    /// Broadcast a signed transaction file
+    Broadcast {
+        /// Signed transaction file
+        #[arg(short, long)]
+        file: PathBuf,
+        
+        /// Node RPC URL
+        #[arg(short, long, default_value = "http://127.0.0.1:8080")]
+        node: String,
+    },
     
-    println!("✅ Wallet created successfully!\n");
-    println!("═══════════════════════════════════════════════════════");
-    println!("  📍 Address: {}", address);
-    if let Some(lbl) = label {
-        println!("  🏷️  Label:   {}", lbl);
-    }
-    println!("  📁 File:    {}", path.display());
-    println!("═══════════════════════════════════════════════════════\n");
-    println!("⚠️  IMPORTANT: Keep your password safe! There is NO recovery!");
-    println!("⚠️  Back up your wallet.json file in a secure location!\n");
+    /// Show the memo of a confirmed transaction
+    ReadMemo {
+        /// Transaction id (hex)
+        txid: String,
+        
+        /// Node chain file to look the transaction up in
+        #[arg(long)]
+        chain: PathBuf,
+    },
     
-    Ok(())
-}
-
-fn cmd_generate(path: &PathBuf, password: &str, label: Option<String>) -> anyhow::Result<()> {
-    if !path.exists() {
-        anyhow::bail!("Wallet not found: {}. Use 'new' to create one.", path.display());
-    }
+    /// Review the audit log and check it has not been tampered with
+    Audit {
+        /// Only show the most recent entries
+        #[arg(short, long)]
+        limit: Option<usize>,
+    },
     
-    let mut wallet = Wallet::load(path)?;
-    let address = wallet.new_address(password, label.clone())?;
-    wallet.save()?;
+    /// Start an interactive session that keeps unlocked keys in memory
+    Shell {
+        /// Node RPC URL
+        #[arg(short, long, default_value = "http://127.0.0.1:8080")]
+        node: String,
+    },
     
-    println!("\n✅ New address generated!\n");
-    println!("  📍 Address: {}", address);
-    if let Some(lbl) = label {
-        println!("  🏷️  Label:   {}", lbl);
-    }
-    println!();
+    /// Answer authenticated JSON-RPC calls for address creation, balances,
+    /// sends and history
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = service::DEFAULT_RPC_ADDR)]
+        rpc: SocketAddr,
+        
+        /// Node RPC URL
+        #[arg(short, long, default_value = "http://127.0.0.1:8080")]
+        node: String,
+        
+        /// Node chain file to select unspent outputs from
+        #[arg(long)]
+        chain: PathBuf,
+    },
     
-    Ok(())
+    /// Print shell completions to stdout
+    Completions {
+        /// Shell to generate completions for
+        shell: Shell,
+    },
 }
 
-fn cmd_list(path: &PathBuf) -> anyhow::Result<()> {
-    if !path.exists() {
-        anyhow::bail!("Wallet not found: {}", path.display());
-    }
+#[derive(Subcommand)]
+enum KeychainCommand {
+    /// Store the wallet password so that commands stop asking for it
+    Unlock {
+        /// Session length in minutes
+        #[arg(short, long, default_value_t = DEFAULT_SESSION.as_secs() / 60)]
+        minutes: u64,
+    },
     
-    let wallet = Wallet::load(path)?;
-    let addresses = wallet.addresses();
+    /// Remove the stored password
+    Lock,
+}
+
+#[derive(Subcommand)]
+enum OutboxCommand {
+    /// List the queued transactions (the default)
+    List,
     
-    println!("\n📋 Wallet Addresses ({}):\n", addresses.len());
-    println!("═══════════════════════════════════════════════════════");
+    /// Broadcast the due transactions once and check which were mined
+    Retry {
+        /// Node RPC URL
+        #[arg(short, long, default_value = "http://127.0.0.1:8080")]
+        node: String,
+    },
     
-    for (i, addr) in addresses.iter().enumerate() {
-        println!("  {}. {}", i + 1, addr);
-    }
+    /// Keep retrying until every transaction is mined or rejected
+    Watch {
+        /// Node RPC URL
+        #[arg(short, long, default_value = "http://127.0.0.1:8080")]
+        node: String,
+        
+        /// Seconds between two rounds
+        #[arg(short, long, default_value_t = 30)]
+        interval: u64,
+    },
     
-    println!("═══════════════════════════════════════════════════════\n");
+    /// Drop a transaction from the outbox
+    Remove {
+        txid: String,
+    },
     
-    Ok(())
+    /// Drop the confirmed and rejected transactions
+    Clear,
 }
 
-fn cmd_info(path: &PathBuf) -> anyhow::Result<()> {
-    if !path.exists() {
-        println!("\n❌ No wallet found at: {}", path.display());
-        println!("\nCreate one with: aequitas-wallet new --password <PASSWORD>\n");
-        return Ok(());
-    }
+#[derive(Subcommand)]
+enum LabelCommand {
+    /// Set the label of an address
+    Set {
+        address: String,
+        label: String,
+    },
     
-    let wallet = Wallet::load(path)?;
-    let addresses = wallet.addresses();
+    /// Show the label, note and flags of an address
+    Get {
+        address: String,
+    },
     
-    println!("\n📊 Wallet Information:\n");
-    println!("═══════════════════════════════════════════════════════");
-    println!("  📁 File:      {}", path.display());
-    println!("  🔢 Addresses: {}", addresses.len());
-    if let Some(default) = wallet.default_address() {
-        println!("  ⭐ Default:   {}", default);
-    }
-    println!("═══════════════════════════════════════════════════════\n");
+    /// Remove the label of an address
+    Clear {
+        address: String,
+    },
     
-    Ok(())
+    /// Set the note of an address (removed when omitted)
+    Note {
+        address: String,
+        note: Option<String>,
+    },
+    
+    /// Archive an address: it is no longer picked as the default one
+    Archive {
+        address: String,
+        
+        /// Bring the address back into use
+        #[arg(long)]
+        undo: bool,
+    },
+    
+    /// Hide an address from `list` (shown again with `list --all`)
+    Hide {
+        address: String,
+        
+        /// Show the address again
+        #[arg(long)]
+        undo: bool,
+    },
 }
 
-fn cmd_export(path: &PathBuf, address: &str, password: &str) -> anyhow::Result<()> {
-    println!("\n⚠️  WARNING: Exporting private keys is dangerous!");
-    println!("⚠️  Never share your private key with anyone!\n");
-    
-    let mut keystore = Keystore::load(path)?;
-    let addr = aequitas_core::Address::from_string(address)?;
+#[derive(Subcommand)]
+enum ContactsCommand {
+    /// Save an address under a name `send` accepts in its place
+    Add {
+        /// Contact name
+        name: String,
+        
+        /// Contact address
+        address: String,
+    },
     
-    keystore.unlock(&addr, password)?;
+    /// Remove a contact
+    Remove {
+        /// Contact name
+        name: String,
+    },
+    
+    /// List contacts
+    List,
+}
+
+#[derive(Subcommand)]
+enum MultisigCommand {
+    /// Create a multisig wallet file from the cosigners' public keys
+    Create {
+        /// Required number of signatures
+        #[arg(short, long)]
+        threshold: u8,
+        
+        /// Public key in hex (repeat for each co-signer, order matters)
+        #[arg(short, long = "key", required = true)]
+        keys: Vec<String>,
+        
+        /// Multisig wallet file to write
+        #[arg(short, long, default_value = "multisig.json")]
+        out: PathBuf,
+    },
+    
+    /// Create an unsigned transaction spending from a multisig wallet
+    Spend {
+        /// Recipient address
+        to: String,
+        
+        /// Amount to send, in --unit
+        amount: String,
+        
+        /// Multisig wallet file
+        #[arg(short, long, default_value = "multisig.json")]
+        multisig: PathBuf,
+        
+        /// Fee (calculated automatically if not specified)
+        #[arg(long)]
+        fee: Option<u64>,
+        
+        /// Price the fee from the node mempool to be mined within this many blocks
+        #[arg(long, conflicts_with = "fee")]
+        confirm_within: Option<u32>,
+        
+        /// Node chain file to select unspent outputs from
+        #[arg(long)]
+        chain: PathBuf,
+        
+        /// Node RPC URL
+        #[arg(short, long, default_value = "http://127.0.0.1:8080")]
+        node: String,
+        
+        /// Output file
+        #[arg(short, long, default_value = "multisig-tx.json")]
+        out: PathBuf,
+    },
+    
+    /// Add this wallet's cosigner signatures to a transaction file
+    Sign {
+        /// Transaction file from `multisig spend`
+        #[arg(short, long)]
+        file: PathBuf,
+        
+        /// Output file (defaults to overwriting --file)
+        #[arg(short, long)]
+        out: Option<PathBuf>,
+        
+        /// Sign without asking for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+    
+    /// Merge the signatures of copies of a transaction signed by different cosigners
+    Combine {
+        /// Signed copies (at least two)
+        #[arg(required = true, num_args = 2..)]
+        files: Vec<PathBuf>,
+        
+        /// Output file
+        #[arg(short, long, default_value = "multisig-tx.json")]
+        out: PathBuf,
+    },
+}
+
+/// Payload type of a `--memo`
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum MemoType {
+    /// UTF-8 text
+    Text,
+    /// Deposit or invoice identifier
+    Reference,
+    /// JSON document
+    Json,
+    /// Hex-encoded bytes
+    Binary,
+}
+
+impl MemoType {
+    fn memo(self, value: &str) -> anyhow::Result<Memo> {
+        let memo = match self {
+            MemoType::Text => Memo::Text(value.to_string()),
+            MemoType::Reference => Memo::PaymentReference(value.to_string()),
+            MemoType::Json => Memo::Json(value.to_string()),
+            MemoType::Binary => Memo::Binary(hex::decode(value)?),
+        };
+        // Refuse a bad memo before asking for the password
+        memo.encode()?;
+        Ok(memo)
+    }
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+    
+    match cli.command {
+        Commands::New { password, label } => {
+            let password = new_password(password, "New wallet password: ")?;
+            cmd_new(&cli.wallet, &password, label)?;
+        }
+        Commands::Restore { mnemonic, password, chain, gap_limit } => {
+            let mnemonic = secret(mnemonic, "Recovery phrase: ")?;
+            let password = new_password(password, "New wallet password: ")?;
+            cmd_restore(&cli.wallet, &mnemonic, &password, &chain, gap_limit)?;
+        }
+        Commands::Generate { password, label } => {
+            let password = ask_password(&cli.wallet, password, "Password for the new address: ")?;
+            cmd_generate(&cli.wallet, &password, label)?;
+        }
+        Commands::List { node, all } => {
+            let mut format = AmountFormat::new(cli.unit);
+            format.precision = cli.precision;
+            cmd_list(&cli.wallet, node.as_deref(), all, format).await?;
+        }
+        Commands::Label { command } => {
+            cmd_label(&cli.wallet, command)?;
+        }
+        Commands::Info => {
+            cmd_info(&cli.wallet)?;
+        }
+        Commands::Export { address, password } => {
+            let password = ask_password(&cli.wallet, password, &format!("Password for {}: ", address))?;
+            cmd_export(&cli.wallet, &address, &password)?;
+        }
+        Commands::Import { key, password, label } => {
+            let key = secret(key, "Private key (hex): ")?;
+            let password = new_password(password, "Password for the imported key: ")?;
+            cmd_import(&cli.wallet, &key, &password, label)?;
+        }
+        Commands::ExportKeyfile { address, out } => {
+            cmd_export_keyfile(&cli.wallet, &address, out)?;
+        }
+        Commands::ImportKeyfile { file, password, wallet_password } => {
+            let password = secret(password, "Keyfile password: ")?;
+            let wallet_password = wallet_password.as_deref().unwrap_or(password.as_str());
+            cmd_import_keyfile(&cli.wallet, &file, &password, wallet_password)?;
+        }
+        Commands::Migrate { dir } => {
+            cmd_migrate(&cli.wallet, &dir)?;
+        }
+        Commands::ChangePassword { address, all: _, old_password, new_password: new_pw } => {
+            let old_password = secret(old_password, "Current password: ")?;
+            let new_password = new_password(new_pw, "New password: ")?;
+            cmd_change_password(&cli.wallet, address.as_deref(), &old_password, &new_password)?;
+        }
+        Commands::Encrypt { password } => {
+            let password = new_password(password, "Wallet file password: ")?;
+            cmd_encrypt(&cli.wallet, &password)?;
+        }
+        Commands::Keychain { command } => match command {
+            KeychainCommand::Unlock { minutes } => {
+                cmd_keychain_unlock(&cli.wallet, minutes)?;
+            }
+            KeychainCommand::Lock => {
+                session::clear(&cli.wallet)?;
+                println!("\n🔒 Keychain session ended for {}\n", cli.wallet.display());
+            }
+        },
+        Commands::Contacts { command } => match command {
+            ContactsCommand::Add { name, address } => {
+                cmd_contacts_add(&cli.wallet, &name, &address)?;
+            }
+            ContactsCommand::Remove { name } => {
+                cmd_contacts_remove(&cli.wallet, &name)?;
+            }
+            ContactsCommand::List => {
+                cmd_contacts_list(&cli.wallet)?;
+            }
+        },
+        Commands::MultisigAddress { threshold, keys } => {
+            cmd_multisig_address(threshold, &keys)?;
+        }
+        Commands::Multisig { command } => {
+            let mut format = AmountFormat::new(cli.unit);
+            format.precision = cli.precision;
+            match command {
+                MultisigCommand::Create { threshold, keys, out } => {
+                    cmd_multisig_create(threshold, &keys, &out)?;
+                }
+                MultisigCommand::Spend { to, amount, multisig, fee, confirm_within, chain, node, out } => {
+                    let amount = parse_amount_in(&amount, cli.unit)?;
+                    cmd_multisig_spend(&multisig, &to, amount, fee, confirm_within, &chain, &node, &out, format).await?;
+                }
+                MultisigCommand::Sign { file, out, yes } => {
+                    cmd_sign(&cli.wallet, &file, out.as_ref().unwrap_or(&file), yes, format)?;
+                }
+                MultisigCommand::Combine { files, out } => {
+                    cmd_multisig_combine(&files, &out, format)?;
+                }
+            }
+        }
+        Commands::Receive { amount, label, message, reference, address, png, no_qr } => {
+            let mut format = AmountFormat::new(cli.unit);
+            format.precision = cli.precision;
+            let amount = amount.as_deref().map(|amount| parse_amount_in(amount, cli.unit)).transpose()?;
+            cmd_receive(&cli.wallet, address, amount, label, message, reference, png.as_ref(), no_qr, format)?;
+        }
+        Commands::Outbox { command } => {
+            cmd_outbox(&cli.wallet, command.unwrap_or(OutboxCommand::List)).await?;
+        }
+        Commands::Rescan { node, chain, from_height } => {
+            let mut format = AmountFormat::new(cli.unit);
+            format.precision = cli.precision;
+            cmd_rescan(&cli.wallet, node, chain, from_height, format).await?;
+        }
+        Commands::Balance { address, node, utxos } => {
+            let mut format = AmountFormat::new(cli.unit);
+            format.precision = cli.precision;
+            cmd_balance(&cli.wallet, address, &node, utxos, format).await?;
+        }
+        Commands::Send { to, amount, all, from, fee, confirm_within, change_to, chain, node, memo, memo_type, spend_unconfirmed, yes } => {
+            let mut format = AmountFormat::new(cli.unit);
+            format.precision = cli.precision;
+            let change_to = change_to.as_deref().map(Address::from_string).transpose()?;
+            let memo = memo.as_deref().map(|value| memo_type.memo(value)).transpose()?;
+            let (to, amount, memo) = if all {
+                if PaymentRequest::is_uri(&to) {
+                    anyhow::bail!("--all cannot pay a payment request, which sets its own amount");
+                }
+                (to, None, memo)
+            } else {
+                let (to, amount, memo) = payment_target(&to, amount.as_deref(), memo, cli.unit, &format)?;
+                (to, Some(amount), memo)
+            };
+            cmd_send(&cli.wallet, from, &to, amount, fee, confirm_within, change_to, memo, &chain, &node, spend_unconfirmed, yes, format).await?;
+        }
+        Commands::CreateUnsigned { to, amount, from, fee, confirm_within, change_to, chain, node, memo, memo_type, out } => {
+            let mut format = AmountFormat::new(cli.unit);
+            format.precision = cli.precision;
+            let amount = parse_amount_in(&amount, cli.unit)?;
+            let change_to = change_to.as_deref().map(Address::from_string).transpose()?;
+            let memo = memo.as_deref().map(|value| memo_type.memo(value)).transpose()?;
+            cmd_create_unsigned(&cli.wallet, from, &to, amount, fee, confirm_within, change_to, memo, &chain, &node, &out, format).await?;
+        }
+        Commands::Sign { file, out, yes } => {
+            let mut format = AmountFormat::new(cli.unit);
+            format.precision = cli.precision;
+            cmd_sign(&cli.wallet, &file, out.as_ref().unwrap_or(&file), yes, format)?;
+        }
+        Commands::Broadcast { file, node } => {
+            cmd_broadcast(&file, &node).await?;
+        }
+        Commands::ReadMemo { txid, chain } => {
+            cmd_read_memo(&txid, &chain)?;
+        }
+        Commands::Audit { limit } => {
+            cmd_audit(&cli.wallet, limit)?;
+        }
+        Commands::Shell { node } => {
+            let mut format = AmountFormat::new(cli.unit);
+            format.precision = cli.precision;
+            shell::run(cli.wallet, node, format).await?;
+        }
+        Commands::Serve { rpc, node, chain } => {
+            cmd_serve(&cli.wallet, rpc, node, chain).await?;
+        }
+        Commands::Completions { shell } => {
+            clap_complete::generate(shell, &mut Cli::command(), "aequitas-wallet", &mut std::io::stdout());
+        }
+    }
+    
+    Ok(())
+}
+
+fn cmd_new(path: &PathBuf, password: &str, label: Option<String>) -> anyhow::Result<()> {
+    if path.exists() {
+        anyhow::bail!("Wallet already exists: {}. Use 'generate' to add addresses.", path.display());
+    }
+    
+    println!("\n🔐 Creating new Aequitas wallet...\n");
+    
+    let mut wallet = Wallet::new();
+    wallet.encrypt_file(password)?;
+    wallet.save_to(path)?;
+    let mnemonic = wallet.init_hd(password)?;
+    let address = wallet.new_address(password, label.clone())?;
+    wallet.save()?;
+    
+    println!("✅ Wallet created successfully!\n");
+    println!("═══════════════════════════════════════════════════════");
+    println!("  📍 Address: {}", address);
+    if let Some(lbl) = label {
+        println!("  🏷️  Label:   {}", lbl);
+    }
+    println!("  📁 File:    {} (encrypted with the same password)", path.display());
+    println!("═══════════════════════════════════════════════════════\n");
+    println!("📝 Recovery phrase:\n");
+    println!("  {}\n", mnemonic.as_str());
+    println!("⚠️  IMPORTANT: Write these words down and keep them offline!");
+    println!("⚠️  They restore every address of this wallet, now and later.");
+    println!("⚠️  Anyone who has them can spend your funds.\n");
+    
+    Ok(())
+}
+
+fn cmd_restore(path: &PathBuf, mnemonic: &str, password: &str, chain_path: &PathBuf, gap_limit: u32) -> anyhow::Result<()> {
+    if path.exists() {
+        anyhow::bail!("Wallet already exists: {}", path.display());
+    }
+    
+    println!("\n🔎 Scanning {} for used addresses...\n", chain_path.display());
+    
+    let mut chain = Blockchain::load(chain_path)?;
+    chain.enable_address_index();
+    
+    let mut wallet = Wallet::new();
+    let used = wallet.restore_hd(mnemonic, password, gap_limit, |address| !chain.get_history(address).is_empty())?;
+    if used.is_empty() {
+        wallet.new_address(password, None)?;
+    }
+    wallet.encrypt_file(password)?;
+    wallet.save_to(path)?;
+    
+    println!("✅ Wallet restored: {} used address(es) found\n", used.len());
+    for address in &used {
+        println!("  📍 {}", address);
+    }
+    println!("\n  📁 File: {}\n", path.display());
+    
+    Ok(())
+}
+
+fn cmd_generate(path: &PathBuf, password: &str, label: Option<String>) -> anyhow::Result<()> {
+    if !path.exists() {
+        anyhow::bail!("Wallet not found: {}. Use 'new' to create one.", path.display());
+    }
+    
+    let mut wallet = load_wallet(path)?;
+    let address = wallet.new_address(password, label.clone())?;
+    wallet.save()?;
+    
+    println!("\n✅ New address generated!\n");
+    println!("  📍 Address: {}", address);
+    if let Some(lbl) = label {
+        println!("  🏷️  Label:   {}", lbl);
+    }
+    println!();
+    
+    Ok(())
+}
+
+async fn cmd_list(path: &PathBuf, node: Option<&str>, all: bool, format: AmountFormat) -> anyhow::Result<()> {
+    if !path.exists() {
+        anyhow::bail!("Wallet not found: {}", path.display());
+    }
+    
+    let wallet = load_wallet(path)?;
+    let entries: Vec<_> = wallet.key_entries().iter().filter(|entry| all || !entry.hidden).collect();
+    let hidden = wallet.key_entries().len() - entries.len();
+    
+    println!("\n📋 Wallet Addresses ({}):\n", entries.len());
+    println!("  {:>3}  {:<45} | {:<20} | {:>18} | {}", "#", "Address", "Label", "Balance", "Flags");
+    println!("  {}", "─".repeat(105));
+    
+    for (i, entry) in entries.iter().enumerate() {
+        let balance = match node {
+            Some(node) => match BalanceInfo::fetch(node, &entry.address).await {
+                Ok(balance) => format.format_number(balance.confirmed),
+                Err(_) => "ERROR".to_string(),
+            },
+            None => "-".to_string(),
+        };
+        let flags: Vec<&str> = [(entry.archived, "archived"), (entry.hidden, "hidden"), (entry.path.is_some(), "hd")]
+            .into_iter()
+            .filter_map(|(set, flag)| set.then_some(flag))
+            .collect();
+        println!(
+            "  {:>3}  {:<45} | {:<20} | {:>18} | {}",
+            i + 1,
+            entry.address,
+            entry.label.as_deref().unwrap_or(""),
+            balance,
+            flags.join(",")
+        );
+        if let Some(note) = &entry.note {
+            println!("       📝 {}", note);
+        }
+    }
+    
+    println!("  {}", "─".repeat(105));
+    if node.is_some() {
+        println!("  Balances are confirmed amounts in {}.", format.unit);
+    } else {
+        println!("  Pass --node to show balances.");
+    }
+    if hidden > 0 {
+        println!("  {} hidden address(es), shown with --all.", hidden);
+    }
+    println!();
+    
+    Ok(())
+}
+
+fn cmd_label(path: &PathBuf, command: LabelCommand) -> anyhow::Result<()> {
+    let mut wallet = load_wallet(path)?;
+    let owned = |address: &str| -> anyhow::Result<Address> {
+        let address = Address::from_string(address)?;
+        if !wallet.owns(&address) {
+            anyhow::bail!("{} is not an address of this wallet", address);
+        }
+        Ok(address)
+    };
+    
+    match command {
+        LabelCommand::Get { address } => {
+            let address = owned(&address)?;
+            let entry = wallet.key_entry(&address)
+                .ok_or_else(|| anyhow::anyhow!("Key not found"))?;
+            println!("\n🏷️  {}\n", entry.address);
+            println!("  Label:     {}", entry.label.as_deref().unwrap_or("(none)"));
+            println!("  Note:      {}", entry.note.as_deref().unwrap_or("(none)"));
+            println!("  Archived:  {}", if entry.archived { "yes" } else { "no" });
+            println!("  Hidden:    {}", if entry.hidden { "yes" } else { "no" });
+            if let Some(path) = &entry.path {
+                println!("  Path:      {}", path);
+            }
+            println!();
+            return Ok(());
+        }
+        LabelCommand::Set { address, label } => {
+            let address = owned(&address)?;
+            wallet.set_label(&address, Some(&label))?;
+            println!("\n✅ Label of {} set to \"{}\"\n", address, label.trim());
+        }
+        LabelCommand::Clear { address } => {
+            let address = owned(&address)?;
+            wallet.set_label(&address, None)?;
+            println!("\n✅ Label of {} removed\n", address);
+        }
+        LabelCommand::Note { address, note } => {
+            let address = owned(&address)?;
+            wallet.set_note(&address, note.as_deref())?;
+            println!("\n✅ Note of {} {}\n", address, if note.is_some() { "saved" } else { "removed" });
+        }
+        LabelCommand::Archive { address, undo } => {
+            let address = owned(&address)?;
+            wallet.set_archived(&address, !undo)?;
+            println!("\n✅ {} {}\n", address, if undo { "is back in use" } else { "archived" });
+        }
+        LabelCommand::Hide { address, undo } => {
+            let address = owned(&address)?;
+            wallet.set_hidden(&address, !undo)?;
+            println!("\n✅ {} {}\n", address, if undo { "is shown again" } else { "hidden" });
+        }
+    }
+    wallet.save()?;
+    
+    Ok(())
+}
+
+fn cmd_info(path: &PathBuf) -> anyhow::Result<()> {
+    if !path.exists() {
+        println!("\n❌ No wallet found at: {}", path.display());
+        println!("\nCreate one with: aequitas-wallet new\n");
+        return Ok(());
+    }
+    
+    let wallet = load_wallet(path)?;
+    let addresses = wallet.addresses();
+    
+    println!("\n📊 Wallet Information:\n");
+    println!("═══════════════════════════════════════════════════════");
+    println!("  📁 File:      {}", path.display());
+    println!("  🔢 Addresses: {}", addresses.len());
+    println!("  🌱 HD seed:   {}", if wallet.is_hd() { "yes" } else { "no" });
+    println!("  🔒 Encrypted: {}", if wallet.is_file_encrypted() { "yes" } else { "no (see 'encrypt')" });
+    if let Some(default) = wallet.default_address() {
+        println!("  ⭐ Default:   {}", default);
+    }
+    println!("═══════════════════════════════════════════════════════\n");
+    
+    Ok(())
+}
+
+fn cmd_change_password(path: &PathBuf, address: Option<&str>, old_password: &str, new_password: &str) -> anyhow::Result<()> {
+    if !path.exists() {
+        anyhow::bail!("Wallet not found: {}", path.display());
+    }
+    
+    let mut wallet = load_wallet(path)?;
+    match address {
+        Some(address) => {
+            let address = Address::from_string(address)?;
+            wallet.change_password(&address, old_password, new_password)?;
+            wallet.save()?;
+            println!("\n✅ Password changed for {}\n", address);
+        }
+        None => {
+            let count = wallet.change_all_passwords(old_password, new_password)?;
+            wallet.save()?;
+            println!("\n✅ Password changed for {} key(s)\n", count);
+            if wallet.is_hd() {
+                println!("  The recovery phrase is now encrypted with the new password too.");
+            }
+            if wallet.is_file_encrypted() {
+                println!("  So is the wallet file.");
+            }
+            println!();
+        }
+    }
+    
+    Ok(())
+}
+
+fn cmd_keychain_unlock(path: &PathBuf, minutes: u64) -> anyhow::Result<()> {
+    if !path.exists() {
+        anyhow::bail!("Wallet not found: {}", path.display());
+    }
+    
+    let password = Zeroizing::new(rpassword::prompt_password("Wallet password: ")?);
+    // Check the password before keeping it
+    if Keystore::is_encrypted_file(path)? {
+        Keystore::load_encrypted(path, &password)?;
+    } else {
+        let mut keystore = Keystore::load(path)?;
+        if let Some(address) = keystore.addresses().first() {
+            keystore.unlock(&Address::from_string(address)?, &password)?;
+        }
+    }
+    
+    let token = UnlockToken::new(&password, Duration::from_secs(minutes.saturating_mul(60)));
+    session::store(path, &token)?;
+    
+    let until = chrono::DateTime::from_timestamp(token.expires_at, 0)
+        .map(|time| time.with_timezone(&chrono::Local).format("%H:%M").to_string())
+        .unwrap_or_default();
+    println!("\n🔓 Keychain session started for {} until {}\n", path.display(), until);
+    println!("  End it early with: aequitas-wallet keychain lock\n");
+    
+    Ok(())
+}
+
+fn cmd_encrypt(path: &PathBuf, password: &str) -> anyhow::Result<()> {
+    if !path.exists() {
+        anyhow::bail!("Wallet not found: {}", path.display());
+    }
+    if Keystore::is_encrypted_file(path)? {
+        anyhow::bail!("Wallet file is already encrypted: {}", path.display());
+    }
+    
+    let mut wallet = Wallet::load(path)?;
+    wallet.encrypt_file(password)?;
+    wallet.save()?;
+    
+    println!("\n✅ Wallet file encrypted: {}\n", path.display());
+    println!("  Its password is now asked for to open the wallet.\n");
+    
+    Ok(())
+}
+
+fn cmd_export(path: &PathBuf, address: &str, password: &str) -> anyhow::Result<()> {
+    println!("\n⚠️  WARNING: Exporting private keys is dangerous!");
+    println!("⚠️  Never share your private key with anyone!\n");
+    
+    let mut keystore = load_keystore(path)?;
+    let addr = aequitas_core::Address::from_string(address)?;
+    
+    let mut audit = AuditLog::for_wallet(path)?;
+    
+    keystore.unlock(&addr, password)?;
+    audit.record(AuditEvent::Unlock { address: address.to_string() })?;
     
     if let Some(key) = keystore.get_signing_key(&addr) {
+        audit.record(AuditEvent::Export { address: address.to_string() })?;
         let key_hex = hex::encode(key.to_bytes());
         println!("🔑 Private Key for {}:\n", address);
         println!("  {}\n", key_hex);
         println!("⚠️  Store this securely and NEVER share it!\n");
     } else {
-        anyhow::bail!("Failed to export key");
+        anyhow::bail!("Failed to export key");
+    }
+    
+    Ok(())
+}
+
+fn cmd_import(path: &PathBuf, key: &str, password: &str, label: Option<String>) -> anyhow::Result<()> {
+    let mut wallet = if path.exists() {
+        load_wallet(path)?
+    } else {
+        let mut wallet = Wallet::new();
+        wallet.save_to(path)?;
+        wallet
+    };
+    
+    let address = wallet.import_private_key(key, password, label)?;
+    wallet.save()?;
+    
+    println!("\n✅ Key imported successfully!\n");
+    println!("  📍 Address: {}\n", address);
+    
+    Ok(())
+}
+
+fn cmd_export_keyfile(path: &PathBuf, address: &str, out: Option<PathBuf>) -> anyhow::Result<()> {
+    let keystore = load_keystore(path)?;
+    let addr = aequitas_core::Address::from_string(address)?;
+    
+    let keyfile = keystore.export_keyfile(&addr)?;
+    let out = out.unwrap_or_else(|| PathBuf::from(keyfile.file_name()));
+    if out.exists() {
+        anyhow::bail!("File already exists: {}", out.display());
+    }
+    keyfile.save(&out)?;
+    AuditLog::for_wallet(path)?.record(AuditEvent::ExportKeyfile {
+        address: address.to_string(),
+        file: out.display().to_string(),
+    })?;
+    
+    println!("\n✅ Keyfile exported!\n");
+    println!("  📍 Address: {}", address);
+    println!("  📁 File:    {}", out.display());
+    println!("\n  The keyfile is encrypted with this address's password.\n");
+    
+    Ok(())
+}
+
+fn cmd_import_keyfile(
+    path: &PathBuf,
+    file: &PathBuf,
+    password: &str,
+    wallet_password: &str,
+) -> anyhow::Result<()> {
+    let mut keystore = if path.exists() {
+        load_keystore(path)?
+    } else {
+        Keystore::new()
+    };
+    
+    let keyfile = KeyFile::load(file)?;
+    let address = keystore.import_keyfile(&keyfile, password, wallet_password)?;
+    keystore.save_to(path)?;
+    AuditLog::for_wallet(path)?.record(AuditEvent::Import { address: address.to_string() })?;
+    
+    println!("\n✅ Keyfile imported successfully!\n");
+    println!("  📍 Address: {}\n", address);
+    
+    Ok(())
+}
+
+/// Public keys given in hex
+fn parse_public_keys(keys: &[String]) -> anyhow::Result<Vec<[u8; 32]>> {
+    keys.iter()
+        .map(|k| {
+            let bytes = hex::decode(k.trim_start_matches("0x"))?;
+            bytes.try_into().map_err(|_| anyhow::anyhow!("Public key must be 32 bytes: {}", k))
+        })
+        .collect()
+}
+
+/// Print the address and keys of a multisig policy
+fn print_policy(policy: &MultisigPolicy) {
+    println!("\n🔐 {}-of-{} multisig address:\n", policy.threshold, policy.public_keys.len());
+    println!("  {}\n", policy.address().to_script_string());
+    for (i, key) in policy.public_keys.iter().enumerate() {
+        println!("  Key #{}: {}", i, hex::encode(key));
     }
+    println!("\n  Spending requires the same keys in the same order.\n");
+}
+
+fn cmd_multisig_address(threshold: u8, keys: &[String]) -> anyhow::Result<()> {
+    let policy = MultisigPolicy::new(threshold, parse_public_keys(keys)?)?;
+    print_policy(&policy);
     
     Ok(())
 }
 
-fn cmd_import(path: &PathBuf, key: &str, password: &str, label: Option<String>) -> anyhow::Result<()> {
-    let mut wallet = if path.exists() {
-        Wallet::load(path)?
+fn cmd_multisig_create(threshold: u8, keys: &[String], out: &PathBuf) -> anyhow::Result<()> {
+    if out.exists() {
+        anyhow::bail!("File already exists: {}", out.display());
+    }
+    
+    let multisig = MultisigWallet::new(threshold, parse_public_keys(keys)?)?;
+    multisig.save(out)?;
+    
+    print_policy(&multisig.policy()?);
+    println!("✅ Multisig wallet written to {}; give every cosigner a copy.\n", out.display());
+    
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn cmd_multisig_spend(
+    multisig_path: &PathBuf,
+    to: &str,
+    amount: u64,
+    fee: Option<u64>,
+    confirm_within: Option<u32>,
+    chain_path: &PathBuf,
+    node: &str,
+    out: &PathBuf,
+    format: AmountFormat,
+) -> anyhow::Result<()> {
+    let multisig = MultisigWallet::load(multisig_path)?;
+    let to = Address::from_string(to)?;
+    let chain = Blockchain::load(chain_path)?;
+    
+    let mut builder = TransactionBuilder::new().to(to, amount);
+    if let Some(fee) = fee {
+        builder = builder.fee(fee);
+    }
+    if let Some(blocks) = confirm_within {
+        let estimates = FeeEstimates::fetch(node).await?;
+        builder = builder.fee_estimates(estimates).fee_rate_target(blocks);
+    }
+    let envelope = multisig.spend(builder, &chain)?;
+    envelope.save(out)?;
+    
+    println!("\n📝 Unsigned multisig transaction written to {}\n", out.display());
+    print_envelope(&envelope, &format)?;
+    println!("Each cosigner signs a copy with: aequitas-wallet multisig sign --file <copy>");
+    println!("then: aequitas-wallet multisig combine <copy>... --out {}\n", out.display());
+    
+    Ok(())
+}
+
+fn cmd_multisig_combine(files: &[PathBuf], out: &PathBuf, format: AmountFormat) -> anyhow::Result<()> {
+    let mut envelope = TxEnvelope::load(&files[0])?;
+    for file in &files[1..] {
+        envelope.combine(&TxEnvelope::load(file)?)?;
+    }
+    envelope.save(out)?;
+    
+    println!("\n📝 Combined {} copies into {}\n", files.len(), out.display());
+    print_envelope(&envelope, &format)?;
+    if envelope.is_signed()? {
+        envelope.finalize()?;
+        println!("✅ Every input has its signatures. Broadcast it with: aequitas-wallet broadcast --file {}\n", out.display());
     } else {
-        Wallet::new()
+        println!("   More cosigners still need to sign.\n");
+    }
+    
+    Ok(())
+}
+
+async fn cmd_outbox(path: &PathBuf, command: OutboxCommand) -> anyhow::Result<()> {
+    let mut outbox = Outbox::for_wallet(path)?;
+    
+    match command {
+        OutboxCommand::List => {}
+        OutboxCommand::Retry { node } => {
+            let changed = outbox.process(&node).await?;
+            outbox.save()?;
+            println!("\n🔁 {} transaction(s) changed status", changed);
+        }
+        OutboxCommand::Watch { node, interval } => {
+            println!("\n👀 Watching the outbox every {}s (Ctrl+C to stop)", interval);
+            while outbox.has_unfinished() {
+                if outbox.process(&node).await? > 0 {
+                    outbox.save()?;
+                    print_outbox(&outbox);
+                }
+                if !outbox.has_unfinished() {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_secs(interval.max(1))).await;
+            }
+            println!("\n✅ Every transaction is mined or rejected");
+        }
+        OutboxCommand::Remove { txid } => {
+            outbox.remove(&txid)?;
+            outbox.save()?;
+            println!("\n🗑️  Removed {}", txid);
+        }
+        OutboxCommand::Clear => {
+            let removed = outbox.clear_finished();
+            outbox.save()?;
+            println!("\n🧹 Removed {} finished transaction(s)", removed);
+        }
+    }
+    print_outbox(&outbox);
+    
+    Ok(())
+}
+
+fn print_outbox(outbox: &Outbox) {
+    let now = chrono::Utc::now().timestamp();
+    println!("\n📤 Outbox ({}):\n", outbox.entries().len());
+    println!("  {:<64} | {:<9} | {:>8} | {}", "Txid", "Status", "Attempts", "Details");
+    println!("  {}", "─".repeat(110));
+    for entry in outbox.entries() {
+        let details = match entry.status {
+            OutboxStatus::Confirmed => entry.height.map_or(String::new(), |height| format!("block {}", height)),
+            OutboxStatus::Pending => format!(
+                "next try in {}s{}",
+                (entry.next_attempt - now).max(0),
+                entry.last_error.as_deref().map_or(String::new(), |error| format!(" ({})", error))
+            ),
+            _ => entry.last_error.clone().unwrap_or_default(),
+        };
+        println!("  {:<64} | {:<9} | {:>8} | {}", entry.txid, entry.status.as_str(), entry.attempts, details);
+    }
+    println!();
+}
+
+fn cmd_contacts_add(path: &PathBuf, name: &str, address: &str) -> anyhow::Result<()> {
+    let mut wallet = load_wallet(path)?;
+    let contact = wallet.contacts_mut().add(name, address)?.clone();
+    let previous = Address::from_string(&contact.address).ok()
+        .and_then(|address| wallet.contacts().label_conflict(&address, Some(contact.name.as_str())).map(str::to_string));
+    wallet.save()?;
+    
+    println!("\n✅ Contact added: {} → {}", contact.name, contact.address);
+    if let Some(previous) = previous {
+        println!("⚠️  This address was paid as \"{}\" before.", previous);
+    }
+    println!();
+    
+    Ok(())
+}
+
+fn cmd_contacts_remove(path: &PathBuf, name: &str) -> anyhow::Result<()> {
+    let mut wallet = load_wallet(path)?;
+    let contact = wallet.contacts_mut().remove(name)?;
+    wallet.save()?;
+    
+    println!("\n🗑️  Contact removed: {} ({})\n", contact.name, contact.address);
+    
+    Ok(())
+}
+
+fn cmd_contacts_list(path: &PathBuf) -> anyhow::Result<()> {
+    let wallet = load_wallet(path)?;
+    let contacts = &wallet.contacts().contacts;
+    
+    println!("\n📇 Contacts ({}):\n", contacts.len());
+    println!("═══════════════════════════════════════════════════════");
+    for contact in contacts {
+        println!("  {:<20} {}", contact.name, contact.address);
+    }
+    println!("═══════════════════════════════════════════════════════\n");
+    
+    Ok(())
+}
+
+fn cmd_migrate(path: &PathBuf, dir: &PathBuf) -> anyhow::Result<()> {
+    if !path.exists() {
+        anyhow::bail!("Wallet not found: {}", path.display());
+    }
+    
+    let keystore = load_keystore(path)?;
+    let written = keystore.migrate_to_keyfiles(dir)?;
+    
+    println!("\n✅ Wallet migrated to {} keyfile(s):\n", written.len());
+    for file in &written {
+        println!("  📁 {}", file.display());
+    }
+    println!("\n  Each keyfile keeps the password of its address.");
+    println!("  {} was left unchanged.\n", path.display());
+    
+    Ok(())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn cmd_send(
+    path: &PathBuf,
+    from: Option<String>,
+    to: &str,
+    amount: Option<u64>,
+    fee: Option<u64>,
+    confirm_within: Option<u32>,
+    change_to: Option<Address>,
+    memo: Option<Memo>,
+    chain_path: &PathBuf,
+    node: &str,
+    spend_unconfirmed: bool,
+    yes: bool,
+    format: AmountFormat,
+) -> anyhow::Result<()> {
+    let mut wallet = load_wallet(path)?;
+    let from = match from {
+        Some(address) => Address::from_string(&address)?,
+        None => {
+            let first = wallet.active_addresses().into_iter().next()
+                .ok_or_else(|| anyhow::anyhow!("Wallet has no address in use"))?;
+            Address::from_string(&first)?
+        }
     };
+    let (to, label) = wallet.contacts().resolve(to)?;
+    let chain = Blockchain::load(chain_path)?;
     
-    let address = wallet.import_private_key(key, password, label)?;
-    wallet.save_to(path)?;
+    let password = ask_password(path, None, &format!("Password for {}: ", from))?;
+    wallet.unlock(&from, &password)?;
     
-    println!("\n✅ Key imported successfully!\n");
-    println!("  📍 Address: {}\n", address);
+    // Without an amount, everything goes and the fee comes out of it
+    let mut builder = match amount {
+        Some(amount) => Wallet::transfer(&from, &to, amount, fee, change_to, memo.as_ref())?,
+        None => Wallet::sweep(&from, &to, fee, memo.as_ref())?,
+    };
+    if let Some(blocks) = confirm_within {
+        let estimates = FeeEstimates::fetch(node).await?;
+        builder = builder.fee_estimates(estimates).fee_rate_target(blocks);
+        println!("\n⛽ Fee rate for confirmation within {} block(s): {} units/byte", blocks, builder.fee_rate());
+    }
+    if spend_unconfirmed {
+        builder = builder.spend_unconfirmed(Outbox::for_wallet(path)?.unconfirmed()?);
+    }
+    let tx = wallet.sign(builder.clone(), &chain)?;
+    let preview = builder.preview(&tx, 1, &chain)?;
+    let amount = preview.outputs.first().map_or(0, |(_, value)| *value);
+    
+    println!("\n📝 Transaction preview\n");
+    println!("═══════════════════════════════════════════════════════");
+    println!("  Inputs ({}):", preview.inputs.len());
+    for (id, value) in &preview.inputs {
+        println!("    {}:{}  {}", hex::encode(id.tx_hash), id.output_index, format.format(*value));
+    }
+    println!("  Outputs:");
+    for (address, value) in &preview.outputs {
+        match label.as_deref().filter(|_| address == &to) {
+            Some(name) => println!("    {}  {} ({})", address, format.format(*value), name),
+            None => println!("    {}  {}", address, format.format(*value)),
+        }
+    }
+    if let Some(change_address) = &preview.change_address {
+        println!("    {}  {} (change)", change_address, format.format(preview.change));
+    }
+    println!("  Fee:      {} ({} bytes, {:.2} units/byte)", format.format(preview.fee), preview.size, preview.fee_rate());
+    if preview.package_size > preview.size {
+        println!(
+            "  Package:  {} with unconfirmed parents ({} bytes, {:.2} units/byte)",
+            format.format(preview.package_fee),
+            preview.package_size,
+            preview.package_fee_rate()
+        );
+    }
+    println!("  Balance:  {} → {}", format.format(preview.balance_before), format.format(preview.balance_after));
+    if let Some(memo) = &memo {
+        println!("  Memo:     {} ({})", memo, memo.kind());
+    }
+    println!("  Txid:     {}", hex::encode(tx.txid()));
+    println!("═══════════════════════════════════════════════════════\n");
+    
+    if let Some(previous) = wallet.contacts().label_conflict(&to, label.as_deref()) {
+        println!(
+            "⚠️  {} was paid as \"{}\" before, and is now {}. Check the address.\n",
+            to,
+            previous,
+            label.as_deref().map_or("unlabeled".to_string(), |name| format!("\"{}\"", name))
+        );
+    }
+    
+    if !yes && !confirm("Broadcast this transaction? [y/N] ")? {
+        println!("Cancelled, nothing was sent.\n");
+        return Ok(());
+    }
+    
+    wallet.audit(AuditEvent::send(&tx, &from, &to, amount, &chain))?;
+    
+    // Queued first, so that a node that is down does not lose the payment
+    let mut outbox = Outbox::for_wallet(path)?;
+    let txid = outbox.add(&tx);
+    outbox.save()?;
+    let status = outbox.broadcast_now(&txid, node).await?;
+    outbox.save()?;
+    let error = outbox.get(&txid).and_then(|entry| entry.last_error.clone()).unwrap_or_default();
+    match status {
+        OutboxStatus::Rejected => anyhow::bail!("Node rejected the transaction: {}", error),
+        OutboxStatus::Pending => {
+            println!("📤 Not broadcast yet: {}", error);
+            println!("   Kept in the outbox; 'aequitas-wallet outbox watch' retries until it is mined.\n");
+        }
+        _ => println!("✅ Sent: {}\n", txid),
+    }
+    
+    if label.is_some() {
+        wallet.contacts_mut().record_payment(&to, label.as_deref());
+        wallet.save()?;
+    }
+    
+    Ok(())
+}
+
+/// Recipient, amount and memo of a payment to `to`: an address or contact
+/// name paying `amount`, or a payment URI
+fn payment_target(
+    to: &str,
+    amount: Option<&str>,
+    memo: Option<Memo>,
+    unit: Unit,
+    format: &AmountFormat,
+) -> anyhow::Result<(String, u64, Option<Memo>)> {
+    let amount = amount.map(|amount| parse_amount_in(amount, unit)).transpose()?;
+    if !PaymentRequest::is_uri(to) {
+        let amount = amount.ok_or_else(|| anyhow::anyhow!("An amount is required"))?;
+        return Ok((to.to_string(), amount, memo));
+    }
+    
+    let request = PaymentRequest::parse(to)?;
+    let amount = match (request.amount, amount) {
+        (Some(requested), Some(given)) if requested != given => anyhow::bail!(
+            "The payment request asks for {}, not {}",
+            format.format(requested),
+            format.format(given)
+        ),
+        (Some(requested), _) => requested,
+        (None, Some(given)) => given,
+        (None, None) => anyhow::bail!("The payment request gives no amount; pass one"),
+    };
+    let memo = match (memo, request.memo()?) {
+        (Some(_), Some(_)) => anyhow::bail!("--memo cannot replace the reference of the payment request"),
+        (given, requested) => given.or(requested),
+    };
+    
+    println!("\n🧾 Payment request to {}", request.address);
+    if let Some(label) = &request.label {
+        println!("  Label:    {}", label);
+    }
+    if let Some(message) = &request.message {
+        println!("  Message:  {}", message);
+    }
+    
+    Ok((request.address.to_string(), amount, memo))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn cmd_receive(
+    path: &PathBuf,
+    address: Option<String>,
+    amount: Option<u64>,
+    label: Option<String>,
+    message: Option<String>,
+    reference: Option<String>,
+    png: Option<&PathBuf>,
+    no_qr: bool,
+    format: AmountFormat,
+) -> anyhow::Result<()> {
+    let address = match address {
+        Some(address) => Address::from_string(&address)?,
+        None => {
+            let first = load_wallet(path)?.active_addresses().into_iter().next()
+                .ok_or_else(|| anyhow::anyhow!("Wallet has no address in use"))?;
+            Address::from_string(&first)?
+        }
+    };
+    let request = PaymentRequest { address, amount, label, message, reference };
+    // Refuse a reference the payer could not attach
+    request.memo()?;
+    
+    println!("\n📥 Payment request\n");
+    println!("  Address:  {}", request.address);
+    if let Some(amount) = request.amount {
+        println!("  Amount:   {}", format.format(amount));
+    }
+    println!("  URI:      {}\n", request.to_uri());
+    
+    if !no_qr {
+        println!("{}", request.qr_terminal()?);
+    }
+    if let Some(png) = png {
+        request.save_qr_png(png)?;
+        println!("🖼️  QR code saved to {}\n", png.display());
+    }
+    
+    Ok(())
+}
+
+/// Submit `tx` to the node, returning its hash
+async fn broadcast(node: &str, tx: &Transaction) -> anyhow::Result<String> {
+    let resp = reqwest::Client::new()
+        .post(format!("{}/tx/send", node))
+        .json(&serde_json::json!({ "tx_hex": hex::encode(tx.encode()) }))
+        .send()
+        .await?;
+    let body: serde_json::Value = resp.json().await.unwrap_or_default();
+    if let Some(message) = body["error"]["message"].as_str() {
+        anyhow::bail!("Node rejected the transaction: {}", message);
+    }
+    Ok(body["hash"].as_str().unwrap_or_default().to_string())
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn cmd_create_unsigned(
+    path: &PathBuf,
+    from: Option<String>,
+    to: &str,
+    amount: u64,
+    fee: Option<u64>,
+    confirm_within: Option<u32>,
+    change_to: Option<Address>,
+    memo: Option<Memo>,
+    chain_path: &PathBuf,
+    node: &str,
+    out: &PathBuf,
+    format: AmountFormat,
+) -> anyhow::Result<()> {
+    // The online machine may only know the address, not hold the wallet
+    let from = match from {
+        Some(address) => Address::from_string(&address)?,
+        None => {
+            let first = load_wallet(path)?.active_addresses().into_iter().next()
+                .ok_or_else(|| anyhow::anyhow!("Wallet has no address in use"))?;
+            Address::from_string(&first)?
+        }
+    };
+    let to = Address::from_string(to)?;
+    let chain = Blockchain::load(chain_path)?;
+    
+    let mut builder = Wallet::transfer(&from, &to, amount, fee, change_to, memo.as_ref())?;
+    if let Some(blocks) = confirm_within {
+        let estimates = FeeEstimates::fetch(node).await?;
+        builder = builder.fee_estimates(estimates).fee_rate_target(blocks);
+    }
+    let unsigned = builder.build_unsigned_exact(&chain)?;
+    let envelope = TxEnvelope::new(&unsigned.transaction, &chain)?;
+    envelope.save(out)?;
+    
+    println!("\n📝 Unsigned transaction written to {}\n", out.display());
+    print_envelope(&envelope, &format)?;
+    println!("Sign it on the offline machine with: aequitas-wallet sign --file {}\n", out.display());
+    
+    Ok(())
+}
+
+fn cmd_sign(path: &PathBuf, file: &PathBuf, out: &PathBuf, yes: bool, format: AmountFormat) -> anyhow::Result<()> {
+    let mut wallet = load_wallet(path)?;
+    let mut envelope = TxEnvelope::load(file)?;
+    let tx = envelope.transaction()?;
+    
+    println!("\n📝 Transaction to sign\n");
+    print_envelope(&envelope, &format)?;
+    
+    let signers = wallet.envelope_signers(&envelope)?;
+    if signers.is_empty() {
+        anyhow::bail!("No input is left for this wallet to sign");
+    }
+    
+    if !yes && !confirm("Sign this transaction? [y/N] ")? {
+        println!("Cancelled, nothing was signed.\n");
+        return Ok(());
+    }
+    
+    for address in &signers {
+        let password = ask_password(path, None, &format!("Password for {}: ", address))?;
+        wallet.unlock(address, &password)?;
+    }
+    let signed = wallet.sign_envelope(&mut envelope)?;
+    if let Some(output) = tx.outputs.first() {
+        wallet.audit(AuditEvent::Send {
+            txid: hex::encode(tx.txid()),
+            from: signers[0].to_string(),
+            to: output.recipient.to_string(),
+            amount: output.amount,
+            fee: envelope.fee()?,
+        })?;
+    }
+    envelope.save(out)?;
+    
+    println!("\n✅ Signed {} input(s), written to {}", signed, out.display());
+    if envelope.is_signed()? {
+        println!("   Broadcast it from the online machine with: aequitas-wallet broadcast --file {}\n", out.display());
+    } else {
+        println!("   Other inputs still need their signers.\n");
+    }
+    
+    Ok(())
+}
+
+async fn cmd_broadcast(file: &PathBuf, node: &str) -> anyhow::Result<()> {
+    let tx = TxEnvelope::load(file)?.finalize()?;
+    
+    let hash = broadcast(node, &tx).await?;
+    println!("\n✅ Sent: {}\n", hash);
+    
+    Ok(())
+}
+
+/// Password given on the command line, else that of the keychain session
+/// of the wallet at `path`, else asked for without echo
+fn ask_password(path: &PathBuf, given: Option<String>, prompt: &str) -> anyhow::Result<Zeroizing<String>> {
+    if let Some(password) = given {
+        return Ok(Zeroizing::new(password));
+    }
+    // A keychain that cannot be reached only means asking
+    if let Some(password) = session::password(path).ok().flatten() {
+        return Ok(password);
+    }
+    Ok(Zeroizing::new(rpassword::prompt_password(prompt)?))
+}
+
+/// New password given on the command line, else asked for twice
+fn new_password(given: Option<String>, prompt: &str) -> anyhow::Result<Zeroizing<String>> {
+    if let Some(password) = given {
+        return Ok(Zeroizing::new(password));
+    }
+    let password = Zeroizing::new(rpassword::prompt_password(prompt)?);
+    if password.is_empty() {
+        anyhow::bail!("Password cannot be empty");
+    }
+    let again = Zeroizing::new(rpassword::prompt_password("Repeat it: ")?);
+    if *password != *again {
+        anyhow::bail!("Passwords do not match");
+    }
+    Ok(password)
+}
+
+/// Secret given on the command line, else asked for without echo
+fn secret(given: Option<String>, prompt: &str) -> anyhow::Result<Zeroizing<String>> {
+    match given {
+        Some(secret) => Ok(Zeroizing::new(secret)),
+        None => Ok(Zeroizing::new(rpassword::prompt_password(prompt)?)),
+    }
+}
+
+/// Load the wallet at `path`, asking for its file password if encrypted
+fn load_wallet(path: &PathBuf) -> anyhow::Result<Wallet> {
+    if Keystore::is_encrypted_file(path)? {
+        let password = ask_password(path, None, "Wallet file password: ")?;
+        return Wallet::load_encrypted(path, &password);
+    }
+    Wallet::load(path)
+}
+
+/// Load the keystore at `path`, asking for its file password if encrypted
+fn load_keystore(path: &PathBuf) -> anyhow::Result<Keystore> {
+    if Keystore::is_encrypted_file(path)? {
+        let password = ask_password(path, None, "Wallet file password: ")?;
+        return Keystore::load_encrypted(path, &password);
+    }
+    Keystore::load(path)
+}
+
+/// Print the content of a transaction envelope
+fn print_envelope(envelope: &TxEnvelope, format: &AmountFormat) -> anyhow::Result<()> {
+    let tx = envelope.transaction()?;
+    
+    println!("═══════════════════════════════════════════════════════");
+    println!("  Network:  {}", envelope.network);
+    println!("  Inputs ({}):", tx.inputs.len());
+    for (input, spent) in tx.inputs.iter().zip(&envelope.spent) {
+        let status = match &input.multisig {
+            Some(policy) => format!("{}/{} signatures", input.multisig_signatures.len(), policy.threshold),
+            None if input.is_signed() => "signed".to_string(),
+            None => "unsigned".to_string(),
+        };
+        println!("    {}  {} ({})", spent.address, format.format(spent.amount), status);
+    }
+    println!("  Outputs:");
+    for output in &tx.outputs {
+        println!("    {}  {}", output.recipient, format.format(output.amount));
+    }
+    println!("  Fee:      {}", format.format(envelope.fee()?));
+    println!("  Txid:     {}", hex::encode(tx.txid()));
+    println!("═══════════════════════════════════════════════════════\n");
+    
+    Ok(())
+}
+
+fn cmd_read_memo(txid: &str, chain_path: &PathBuf) -> anyhow::Result<()> {
+    let chain = Blockchain::load(chain_path)?;
+    let (height, tx) = chain.iter_blocks()
+        .find_map(|block| block.transactions.iter()
+            .find(|tx| hex::encode(tx.txid()) == txid)
+            .map(|tx| (block.header.height, tx.clone())))
+        .ok_or_else(|| anyhow::anyhow!("Transaction {} not found in {}", txid, chain_path.display()))?;
+    
+    println!("\n📝 Memo of {} (block {})\n", txid, height);
+    if let Some(memo) = tx.typed_memo() {
+        println!("  Type:     {}", memo.kind());
+        println!("  Value:    {}", memo);
+    } else if !tx.output_memos().is_empty() {
+        for (index, memo) in tx.output_memos() {
+            println!("  Output {}: {}", index, String::from_utf8_lossy(&memo));
+        }
+    } else if tx.memo.is_empty() {
+        println!("  (no memo)");
+    } else {
+        println!("  Untyped:  {}", String::from_utf8_lossy(&tx.memo));
+    }
+    println!();
+    
+    Ok(())
+}
+
+/// Ask a yes/no question on the terminal (no by default)
+fn confirm(prompt: &str) -> anyhow::Result<bool> {
+    use std::io::Write;
+    print!("{}", prompt);
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes" | "o" | "oui"))
+}
+
+fn cmd_audit(path: &PathBuf, limit: Option<usize>) -> anyhow::Result<()> {
+    let log_path = AuditLog::path_for(path);
+    if !log_path.exists() {
+        println!("\n📜 No audit log yet: {}\n", log_path.display());
+        return Ok(());
+    }
+    
+    let entries = AuditLog::read(&log_path)?;
+    let skip = limit.map_or(0, |n| entries.len().saturating_sub(n));
+    
+    println!("\n📜 Audit log ({} entries): {}\n", entries.len(), log_path.display());
+    println!("═══════════════════════════════════════════════════════");
+    for entry in &entries[skip..] {
+        println!("  #{:<5} {}  {}", entry.seq, entry.timestamp, entry.event.describe());
+    }
+    println!("═══════════════════════════════════════════════════════\n");
+    
+    match aequitas_wallet::audit::verify(&entries) {
+        Ok(()) => println!("✅ Hash chain intact\n"),
+        Err(e) => {
+            println!("🚨 {}\n", e);
+            anyhow::bail!("Audit log failed verification");
+        }
+    }
+    
+    Ok(())
+}
+
+async fn cmd_rescan(
+    path: &PathBuf,
+    node: Option<String>,
+    chain: Option<PathBuf>,
+    from_height: Option<u64>,
+    format: AmountFormat,
+) -> anyhow::Result<()> {
+    let wallet = load_wallet(path)?;
+    let owned = wallet.addresses();
+    let addresses: HashSet<String> = owned.iter().cloned().collect();
+    
+    let mut history = match from_height {
+        Some(_) => WalletHistory::load_for_wallet(path)?,
+        None => WalletHistory::default(),
+    };
+    let from = from_height.unwrap_or(0);
+    if let Some(scanned_to) = history.scanned_to.filter(|&h| h + 1 < from) {
+        println!("⚠️  History only covers blocks up to {}: outputs created before {} are missed", scanned_to, from);
+    }
+    
+    println!("\n🔎 Rescanning {} address(es) from height {}...\n", addresses.len(), from);
+    let scanned = match (node, chain) {
+        (_, Some(chain_path)) => {
+            let chain = Blockchain::load(&chain_path)?;
+            history.rescan_chain(&chain, &addresses, from)
+        }
+        (Some(node), None) => history.rescan_node(&node, &addresses, from).await?,
+        (None, None) => anyhow::bail!("Give a node with --node or a chain file with --chain"),
+    };
+    history.save_for_wallet(path)?;
+    
+    println!("  Blocks scanned: {}", scanned);
+    if let Some(height) = history.scanned_to {
+        println!("  Scanned up to:  {}", height);
+    }
+    println!("  Amounts in {}\n", format.unit);
+    println!("  {:<45} | {:>18} | {:>8} | {:>12}", "Address", "Balance", "Outputs", "Transactions");
+    println!("  {}", "─".repeat(94));
+    for addr in &owned {
+        println!(
+            "  {:<45} | {:>18} | {:>8} | {:>12}",
+            addr,
+            format.format_number(history.balance(addr)),
+            history.unspent().filter(|output| &output.address == addr).count(),
+            history.history(addr).count()
+        );
+    }
+    
+    for addr in &owned {
+        let entries: Vec<_> = history.history(addr).collect();
+        if entries.is_empty() {
+            continue;
+        }
+        println!("\n  History of {}:", addr);
+        for entry in entries {
+            let sign = if entry.direction == TxDirection::Incoming { "+" } else { "-" };
+            println!("    {:>8}  {}  {}{}", entry.height, entry.txid, sign, format.format_number(entry.amount));
+        }
+    }
     
+    println!("\n✅ History saved to {}", WalletHistory::path_for(path).display());
     Ok(())
 }
 
-async fn cmd_balance(path: &PathBuf, address: Option<String>, node: &str) -> anyhow::Result<()> {
+async fn cmd_serve(path: &PathBuf, rpc: SocketAddr, node: String, chain: PathBuf) -> anyhow::Result<()> {
+    let mut wallet = load_wallet(path)?;
+    let password = ask_password(path, None, "Password of the wallet's keys: ")?;
+    // A wrong password is better refused now than on the first send
+    if let Some(first) = wallet.active_addresses().into_iter().next() {
+        wallet.unlock(&Address::from_string(&first)?, &password)?;
+    }
+    let token = WalletService::load_or_create_token(path)?;
+    
+    if !rpc.ip().is_loopback() {
+        println!("⚠️  Listening on {}, beyond this machine: put TLS in front of it.", rpc.ip());
+    }
+    println!("\n🛰️  Wallet RPC listening on http://{}", rpc);
+    println!("  Node:  {}", node);
+    println!("  Token: {}\n", WalletService::token_path(path).display());
+    
+    service::serve(WalletService::new(path.clone(), wallet, password, node, chain, token), rpc).await
+}
+
+async fn cmd_balance(path: &PathBuf, address: Option<String>, node: &str, show_utxos: bool, format: AmountFormat) -> anyhow::Result<()> {
     println!("\n💰 Checking balance...\n");
     println!("  Node: {}\n", node);
     
     let client = reqwest::Client::new();
-    let value_status = "Market Discovery";
 
     let mut addresses = Vec::new();
     if let Some(addr) = address {
         addresses.push(addr);
     } else if path.exists() {
-        let wallet = Wallet::load(path)?;
+        let wallet = load_wallet(path)?;
         for addr in wallet.addresses() {
             addresses.push(addr);
         }
@@ -262,26 +1992,78 @@ async fn cmd_balance(path: &PathBuf, address: Option<String>, node: &str) -> any
         anyhow::bail!("No address specified and no wallet.json found.");
     }
 
-    println!("  {:<45} | {:<20} | {:<15}", "Address", "Balance (AEQ)", "Value (EUR)");
-    println!("  {}", "─".repeat(85));
-
-    for addr in addresses {
-        let url = format!("{}/balance/{}", node, addr);
-        match client.get(&url).send().await {
-            Ok(resp) => {
-                if resp.status().is_success() {
-                    let data: serde_json::Value = resp.json().await?;
-                    let balance_raw = data["balance"].as_u64().unwrap_or(0);
-                    let balance_aeq = balance_raw as f64 / 1_000_000_000.0;
-                    
-                    println!("  {:<45} | {:>20.9} | {:>15}", addr, balance_aeq, value_status);
-                } else {
-                    println!("  {:<45} | {:>20} | {:>15}", addr, "ERROR", "N/A");
-                }
+    println!("  Amounts in {}\n", format.unit);
+    println!("  {:<45} | {:>18} | {:>18} | {:>18}", "Address", "Confirmed", "Pending in", "Pending out");
+    println!("  {}", "─".repeat(108));
+
+    let mut balances = Vec::new();
+    for addr in &addresses {
+        match BalanceInfo::fetch(node, addr).await {
+            Ok(balance) => {
+                println!(
+                    "  {:<45} | {:>18} | {:>18} | {:>18}",
+                    addr,
+                    format.format_number(balance.confirmed),
+                    format.format_number(balance.pending_incoming),
+                    format.format_number(balance.pending_outgoing)
+                );
+                balances.push(balance);
+            }
+            Err(e) => {
+                println!("  {:<45} | {:>18} | {:>18} | {:>18}", addr, "ERROR", "N/A", "N/A");
+                println!("    {}", e);
+            }
+        }
+    }
+    
+    let sum = |amount: fn(&BalanceInfo) -> u64| balances.iter().fold(0u64, |total, b| total.saturating_add(amount(b)));
+    println!("  {}", "─".repeat(108));
+    println!("  Confirmed:  {}", format.format(sum(|b| b.confirmed)));
+    println!("  Pending:    +{} / -{}", format.format(sum(|b| b.pending_incoming)), format.format(sum(|b| b.pending_outgoing)));
+    println!("  Total:      {}", format.format(sum(|b| b.total())));
+    println!("  Spendable:  {}", format.format(sum(|b| b.spendable(1))));
+    
+    if show_utxos {
+        for balance in &balances {
+            println!("\n  Unspent outputs of {}:", balance.address);
+            if balance.utxos.is_empty() {
+                println!("    (none)");
             }
-            Err(_) => {
-                println!("  {:<45} | {:>20} | {:>15}", addr, "OFFLINE", "N/A");
+            for utxo in &balance.utxos {
+                let confirmations = utxo.confirmations.map_or("?".to_string(), |c| c.to_string());
+                println!(
+                    "    {}:{}  {:>18}  {:>6} conf.{}",
+                    utxo.txid,
+                    utxo.output_index,
+                    format.format_number(utxo.amount),
+                    confirmations,
+                    if utxo.spent_pending { "  (spent by a pending transaction)" } else { "" }
+                );
             }
+            for tx in &balance.pending {
+                println!(
+                    "    pending {}  +{} / -{}",
+                    tx.txid,
+                    format.format_number(tx.incoming),
+                    format.format_number(tx.outgoing)
+                );
+            }
+        }
+    }
+    
+    // Pending payments to us that someone is trying to double-spend
+    for addr in &addresses {
+        let url = format!("{}/mempool/conflicts?address={}", node, addr);
+        let Ok(resp) = client.get(&url).send().await else { continue };
+        let Ok(conflicts) = resp.json::<Vec<serde_json::Value>>().await else { continue };
+        for conflict in conflicts {
+            println!(
+                "\n  🚨 Pending payment to {} is being double-spent: {} by {}{}",
+                addr,
+                conflict["original"].as_str().unwrap_or("?"),
+                conflict["conflicting"].as_str().unwrap_or("?"),
+                if conflict["replaced"].as_bool() == Some(true) { " (replaced)" } else { "" }
+            );
         }
     }
     
@@ -0,0 +1,126 @@
+//! Multisig wallets
+//!
+//! An M-of-N wallet holds no keys: it is the [`MultisigPolicy`] built from
+//! the cosigners' public keys, saved as a JSON descriptor every cosigner
+//! keeps a copy of. Spending builds an unsigned [`TxEnvelope`] from the
+//! policy's outputs; each cosigner signs a copy with their own wallet
+//! ([`Wallet::sign_envelope`](crate::Wallet::sign_envelope)), then the
+//! copies are combined and finalized once `threshold` of them signed.
+
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use aequitas_core::{Address, Blockchain, MultisigPolicy};
+use crate::builder::TransactionBuilder;
+use crate::offline::TxEnvelope;
+
+/// Format name written to descriptors
+pub const MULTISIG_FORMAT: &str = "aequitas-multisig";
+
+/// Descriptor format version
+pub const MULTISIG_VERSION: u32 = 1;
+
+/// Descriptor of an M-of-N wallet
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MultisigWallet {
+    /// Always [`MULTISIG_FORMAT`]
+    pub format: String,
+
+    /// Format version
+    pub version: u32,
+
+    /// Required number of signatures (M)
+    pub threshold: u8,
+
+    /// Cosigner public keys in hex, in policy order (N)
+    pub public_keys: Vec<String>,
+
+    /// Address outputs pay to, checked against the keys on load
+    pub address: String,
+}
+
+impl MultisigWallet {
+    /// Wallet of `threshold` signatures among `public_keys`, whose order
+    /// is part of the address
+    pub fn new(threshold: u8, public_keys: Vec<[u8; 32]>) -> anyhow::Result<Self> {
+        let policy = MultisigPolicy::new(threshold, public_keys)?;
+        Ok(Self {
+            format: MULTISIG_FORMAT.to_string(),
+            version: MULTISIG_VERSION,
+            threshold,
+            public_keys: policy.public_keys.iter().map(hex::encode).collect(),
+            address: policy.address().to_string(),
+        })
+    }
+
+    /// Spending policy
+    pub fn policy(&self) -> anyhow::Result<MultisigPolicy> {
+        let public_keys = self.public_keys.iter()
+            .map(|key| {
+                let bytes = hex::decode(key.trim_start_matches("0x"))?;
+                bytes.try_into().map_err(|_| anyhow::anyhow!("Public key must be 32 bytes: {}", key))
+            })
+            .collect::<anyhow::Result<Vec<[u8; 32]>>>()?;
+        Ok(MultisigPolicy::new(self.threshold, public_keys)?)
+    }
+
+    /// Address outputs locked by the wallet pay to
+    pub fn address(&self) -> anyhow::Result<Address> {
+        Ok(self.policy()?.address())
+    }
+
+    /// Unsigned envelope spending the wallet's outputs as `builder` says
+    ///
+    /// The fee is priced for `threshold` signatures per input.
+    pub fn spend(&self, builder: TransactionBuilder, chain: &Blockchain) -> anyhow::Result<TxEnvelope> {
+        let unsigned = builder.from_multisig(self.policy()?).build_unsigned_exact(chain)?;
+        TxEnvelope::new(&unsigned.transaction, chain)
+    }
+
+    /// Load a descriptor, checking its keys give its address
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let wallet: Self = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        if wallet.format != MULTISIG_FORMAT {
+            anyhow::bail!("Not a multisig wallet: {}", wallet.format);
+        }
+        if wallet.version != MULTISIG_VERSION {
+            anyhow::bail!("Unsupported multisig wallet version {}", wallet.version);
+        }
+        if wallet.address()?.to_string() != wallet.address {
+            anyhow::bail!("Multisig wallet address does not match its keys");
+        }
+        Ok(wallet)
+    }
+
+    /// Save the descriptor as JSON
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::SigningKey;
+
+    #[test]
+    fn test_descriptor_roundtrip() {
+        let keys: Vec<[u8; 32]> = (1..=3u8)
+            .map(|i| SigningKey::from_bytes(&[i; 32]).verifying_key().to_bytes())
+            .collect();
+        let wallet = MultisigWallet::new(2, keys.clone()).unwrap();
+        assert_eq!(wallet.policy().unwrap(), MultisigPolicy::new(2, keys.clone()).unwrap());
+        assert!(MultisigWallet::new(4, keys).is_err());
+
+        let path = std::env::temp_dir().join(format!("aequitas-multisig-{}.json", rand::random::<u64>()));
+        wallet.save(&path).unwrap();
+        assert_eq!(MultisigWallet::load(&path).unwrap(), wallet);
+
+        // Reordered keys give another address
+        let mut tampered = wallet.clone();
+        tampered.public_keys.swap(0, 1);
+        tampered.save(&path).unwrap();
+        assert!(MultisigWallet::load(&path).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
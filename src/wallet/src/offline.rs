@@ -0,0 +1,252 @@
+//! Offline signing envelopes
+//!
+//! A transaction is built unsigned on an online machine that only needs
+//! the chain, carried to the air-gapped machine holding the keys, signed
+//! there and carried back to be broadcast. [`TxEnvelope`] is what travels
+//! between them: a JSON document holding the transaction's canonical
+//! encoding in Base64, with the address and amount of every output it
+//! spends so the signing machine can show the fee without the chain. Where
+//! only text fits, such as a QR code, the whole envelope travels as one
+//! Base64 string (see [`TxEnvelope::to_base64`]).
+//!
+//! The envelope is also how cosigners of a multisig output pass a partially
+//! signed transaction around: each signs a copy, [`TxEnvelope::combine`]
+//! gathers their signatures and [`TxEnvelope::finalize`] checks them.
+
+use std::path::Path;
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde::{Deserialize, Serialize};
+use aequitas_core::blockchain::UtxoId;
+use aequitas_core::{Blockchain, Transaction};
+use ed25519_dalek::{Signature, VerifyingKey};
+
+/// Format name written to envelopes
+pub const ENVELOPE_FORMAT: &str = "aequitas-tx";
+
+/// Envelope format version
+pub const ENVELOPE_VERSION: u32 = 1;
+
+/// Output spent by an input of the enveloped transaction
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpentOutput {
+    /// Address the output pays, whose key signs the input
+    pub address: String,
+
+    /// Amount in base units
+    pub amount: u64,
+}
+
+/// Transaction moving between an online and an offline machine
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TxEnvelope {
+    /// Always [`ENVELOPE_FORMAT`]
+    pub format: String,
+
+    /// Format version
+    pub version: u32,
+
+    /// Network the transaction was built for
+    pub network: String,
+
+    /// Canonical encoding of the transaction (Base64), signed or not
+    pub transaction: String,
+
+    /// Outputs spent, one per input in order
+    pub spent: Vec<SpentOutput>,
+}
+
+impl TxEnvelope {
+    /// Envelope of `tx`, looking its spent outputs up in `chain`
+    pub fn new(tx: &Transaction, chain: &Blockchain) -> anyhow::Result<Self> {
+        let spent = tx.inputs.iter()
+            .map(|input| {
+                let output = chain.get_utxo(&UtxoId::new(input.prev_tx_hash, input.output_index))
+                    .ok_or_else(|| anyhow::anyhow!(
+                        "Input {}:{} is not unspent",
+                        hex::encode(input.prev_tx_hash),
+                        input.output_index
+                    ))?;
+                Ok(SpentOutput { address: output.recipient.to_string(), amount: output.amount })
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self {
+            format: ENVELOPE_FORMAT.to_string(),
+            version: ENVELOPE_VERSION,
+            network: chain.params().network.to_string(),
+            transaction: STANDARD.encode(tx.encode()),
+            spent,
+        })
+    }
+
+    /// The enveloped transaction
+    pub fn transaction(&self) -> anyhow::Result<Transaction> {
+        let bytes = STANDARD.decode(&self.transaction)
+            .map_err(|e| anyhow::anyhow!("Invalid transaction encoding: {}", e))?;
+        let tx = Transaction::decode(&bytes)
+            .map_err(|e| anyhow::anyhow!("Invalid transaction: {}", e))?;
+        if tx.inputs.len() != self.spent.len() {
+            anyhow::bail!("Envelope lists {} spent outputs for {} inputs", self.spent.len(), tx.inputs.len());
+        }
+        Ok(tx)
+    }
+
+    /// Replace the transaction with `tx`, the same one with more signatures
+    pub fn set_transaction(&mut self, tx: &Transaction) -> anyhow::Result<()> {
+        if tx.txid() != self.transaction()?.txid() {
+            anyhow::bail!("Transaction does not match the envelope");
+        }
+        self.transaction = STANDARD.encode(tx.encode());
+        Ok(())
+    }
+
+    /// Merge the signatures of `other`, a copy of the same transaction
+    /// signed by other parties
+    pub fn combine(&mut self, other: &TxEnvelope) -> anyhow::Result<()> {
+        let mut tx = self.transaction()?;
+        let theirs = other.transaction()?;
+        if theirs.txid() != tx.txid() {
+            anyhow::bail!("Transaction does not match the envelope");
+        }
+
+        for (input, their) in tx.inputs.iter_mut().zip(&theirs.inputs) {
+            if input.multisig != their.multisig {
+                anyhow::bail!("Envelopes spend with different multisig policies");
+            }
+            if input.signature.is_empty() && !their.signature.is_empty() {
+                input.signature = their.signature.clone();
+                input.public_key = their.public_key.clone();
+            }
+            for signature in &their.multisig_signatures {
+                if let Err(pos) = input.multisig_signatures.binary_search_by_key(&signature.key_index, |s| s.key_index) {
+                    input.multisig_signatures.insert(pos, signature.clone());
+                }
+            }
+        }
+        self.set_transaction(&tx)
+    }
+
+    /// The transaction, ready to broadcast
+    ///
+    /// Multisig inputs keep their first `threshold` valid signatures, the
+    /// number their fee was priced for. Fails unless every input verifies.
+    pub fn finalize(&self) -> anyhow::Result<Transaction> {
+        let mut tx = self.transaction()?;
+        let message = tx.signing_message();
+
+        for input in &mut tx.inputs {
+            if let Some(policy) = &input.multisig {
+                input.multisig_signatures.retain(|signature| {
+                    let key = policy.public_keys.get(signature.key_index as usize)
+                        .and_then(|key| VerifyingKey::from_bytes(key).ok());
+                    let sig = Signature::from_slice(&signature.signature).ok();
+                    matches!((key, sig), (Some(key), Some(sig)) if key.verify_strict(&message, &sig).is_ok())
+                });
+                input.multisig_signatures.truncate(policy.threshold as usize);
+            }
+        }
+
+        let unsigned = tx.inputs.iter().filter(|input| !input.is_signed()).count();
+        if unsigned > 0 {
+            anyhow::bail!("{} input(s) still need signatures", unsigned);
+        }
+        tx.verify_signatures()
+            .map_err(|e| anyhow::anyhow!("Invalid signature: {}", e))?;
+        Ok(tx)
+    }
+
+    /// Fee paid: the spent amounts minus the outputs
+    pub fn fee(&self) -> anyhow::Result<u64> {
+        let input_total = aequitas_core::checked_sum(self.spent.iter().map(|spent| spent.amount))
+            .ok_or_else(|| anyhow::anyhow!("Spent amounts overflow"))?;
+        input_total.checked_sub(self.transaction()?.total_output()?)
+            .ok_or_else(|| anyhow::anyhow!("Outputs exceed the spent amounts"))
+    }
+
+    /// Whether every input is signed
+    pub fn is_signed(&self) -> anyhow::Result<bool> {
+        Ok(self.transaction()?.inputs.iter().all(|input| input.is_signed()))
+    }
+
+    /// Envelope as one Base64 string of its JSON
+    pub fn to_base64(&self) -> anyhow::Result<String> {
+        Ok(STANDARD.encode(serde_json::to_vec(self)?))
+    }
+
+    /// Read an envelope from its JSON or from [`to_base64`](Self::to_base64)
+    pub fn parse(text: &str) -> anyhow::Result<Self> {
+        let text = text.trim();
+        let envelope: Self = if text.starts_with('{') {
+            serde_json::from_str(text)?
+        } else {
+            let json = STANDARD.decode(text)
+                .map_err(|e| anyhow::anyhow!("Envelope is neither JSON nor Base64: {}", e))?;
+            serde_json::from_slice(&json)?
+        };
+
+        if envelope.format != ENVELOPE_FORMAT {
+            anyhow::bail!("Not a transaction envelope: {}", envelope.format);
+        }
+        if envelope.version != ENVELOPE_VERSION {
+            anyhow::bail!("Unsupported envelope version {}", envelope.version);
+        }
+        Ok(envelope)
+    }
+
+    /// Load an envelope, in either form
+    pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Save the envelope as JSON
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aequitas_core::Address;
+    use ed25519_dalek::SigningKey;
+    use crate::TransactionBuilder;
+
+    #[test]
+    fn test_envelope_roundtrip() {
+        let chain = Blockchain::new();
+        let from = Address::genesis_address();
+        let key = SigningKey::from_bytes(&[5u8; 32]);
+        let recipient = Address::from_public_key(&key.verifying_key());
+
+        let unsigned = TransactionBuilder::new()
+            .from(from.clone())
+            .to(recipient, 1_000_000)
+            .build_unsigned_exact(&chain)
+            .unwrap();
+        let mut envelope = TxEnvelope::new(&unsigned.transaction, &chain).unwrap();
+        assert_eq!(envelope.spent[0].address, from.to_string());
+        assert!(!envelope.is_signed().unwrap());
+        assert_eq!(chain.transaction_fee(&unsigned.transaction), Some(envelope.fee().unwrap()));
+
+        let json = serde_json::to_string(&envelope).unwrap();
+        assert_eq!(TxEnvelope::parse(&json).unwrap(), envelope);
+        assert_eq!(TxEnvelope::parse(&envelope.to_base64().unwrap()).unwrap(), envelope);
+
+        let mut other = unsigned.transaction.clone();
+        other.outputs[0].amount += 1;
+        assert!(envelope.set_transaction(&other).is_err());
+
+        let mut signed = unsigned.transaction.clone();
+        let message = signed.signing_message();
+        for input in &mut signed.inputs {
+            input.sign(&key, &message);
+        }
+        envelope.set_transaction(&signed).unwrap();
+        assert!(envelope.is_signed().unwrap());
+
+        envelope.version = 2;
+        assert!(TxEnvelope::parse(&serde_json::to_string(&envelope).unwrap()).is_err());
+        assert!(TxEnvelope::parse("not an envelope").is_err());
+    }
+}
@@ -0,0 +1,367 @@
+//! Outbox of signed transactions
+//!
+//! `send` writes every signed transaction to `<wallet>.outbox.json` before
+//! broadcasting it, so a node that is down or refuses it for a while does
+//! not lose the payment. [`Outbox::process`] broadcasts the entries that are
+//! due, backing off between attempts, and asks the node whether the
+//! broadcast ones were mined: an entry is done once confirmed, or once the
+//! node rejects it as invalid (its inputs were spent elsewhere, say).
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use aequitas_core::Transaction;
+
+/// Extension appended to the wallet file name
+pub const OUTBOX_EXTENSION: &str = "outbox.json";
+
+/// Wait after the first failed attempt, doubled after each further one
+pub const RETRY_BASE: Duration = Duration::from_secs(30);
+
+/// Longest wait between two attempts
+pub const RETRY_MAX: Duration = Duration::from_secs(60 * 60);
+
+/// Node error reasons retrying will not fix
+const PERMANENT_REJECTIONS: [&str; 3] = ["invalid_transaction", "invalid_hex", "dust_output"];
+
+/// Where a transaction of the outbox stands
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutboxStatus {
+    /// Not accepted by a node yet
+    Pending,
+
+    /// In the node's mempool
+    Broadcast,
+
+    /// Mined
+    Confirmed,
+
+    /// Refused by the node as invalid
+    Rejected,
+}
+
+impl OutboxStatus {
+    /// Name of the status
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OutboxStatus::Pending => "pending",
+            OutboxStatus::Broadcast => "broadcast",
+            OutboxStatus::Confirmed => "confirmed",
+            OutboxStatus::Rejected => "rejected",
+        }
+    }
+
+    /// Whether nothing is left to do for the entry
+    pub fn is_final(self) -> bool {
+        matches!(self, OutboxStatus::Confirmed | OutboxStatus::Rejected)
+    }
+}
+
+/// A signed transaction waiting to be mined
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    /// Transaction id (hex)
+    pub txid: String,
+
+    /// Canonical encoding (hex)
+    pub tx_hex: String,
+
+    /// When it was queued (Unix seconds)
+    pub added_at: i64,
+
+    /// Current state
+    pub status: OutboxStatus,
+
+    /// Broadcast attempts so far
+    pub attempts: u32,
+
+    /// Earliest time of the next attempt (Unix seconds)
+    pub next_attempt: i64,
+
+    /// Why the last attempt failed
+    pub last_error: Option<String>,
+
+    /// Block the transaction was mined in
+    pub height: Option<u64>,
+}
+
+impl OutboxEntry {
+    /// Entry for `tx`, due now
+    pub fn new(tx: &Transaction) -> Self {
+        let now = chrono::Utc::now().timestamp();
+        Self {
+            txid: hex::encode(tx.txid()),
+            tx_hex: hex::encode(tx.encode()),
+            added_at: now,
+            status: OutboxStatus::Pending,
+            attempts: 0,
+            next_attempt: now,
+            last_error: None,
+            height: None,
+        }
+    }
+
+    /// Whether the entry should be broadcast at `now`
+    pub fn is_due(&self, now: i64) -> bool {
+        self.status == OutboxStatus::Pending && now >= self.next_attempt
+    }
+
+    /// Record a failed attempt at `now`, and when to try again
+    pub fn retry_later(&mut self, now: i64, error: String) {
+        self.status = OutboxStatus::Pending;
+        self.next_attempt = now.saturating_add(retry_delay(self.attempts).as_secs() as i64);
+        self.last_error = Some(error);
+    }
+}
+
+/// Wait after `attempts` failed attempts
+pub fn retry_delay(attempts: u32) -> Duration {
+    let doublings = attempts.saturating_sub(1).min(16);
+    RETRY_BASE.saturating_mul(1 << doublings).min(RETRY_MAX)
+}
+
+/// What a node said about a transaction
+#[derive(Clone, Debug, Deserialize)]
+struct TxStatus {
+    height: Option<u64>,
+    #[serde(default)]
+    in_mempool: bool,
+}
+
+/// Outbox of a wallet
+pub struct Outbox {
+    path: PathBuf,
+    entries: Vec<OutboxEntry>,
+}
+
+impl Outbox {
+    /// Outbox path of a wallet file
+    pub fn path_for<P: AsRef<Path>>(wallet: P) -> PathBuf {
+        let wallet = wallet.as_ref();
+        let mut name = wallet.file_name().unwrap_or_default().to_os_string();
+        name.push(".");
+        name.push(OUTBOX_EXTENSION);
+        wallet.with_file_name(name)
+    }
+
+    /// Open the outbox of a wallet file, empty if it has none yet
+    pub fn for_wallet<P: AsRef<Path>>(wallet: P) -> anyhow::Result<Self> {
+        Self::open(Self::path_for(wallet))
+    }
+
+    /// Open an outbox file, empty if missing
+    pub fn open<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let entries = if path.exists() {
+            serde_json::from_str(&std::fs::read_to_string(&path)?)?
+        } else {
+            Vec::new()
+        };
+        Ok(Self { path, entries })
+    }
+
+    /// Write the outbox back
+    pub fn save(&self) -> anyhow::Result<()> {
+        let mut tmp = self.path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(&self.entries)?)?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+
+    /// Entries, oldest first
+    pub fn entries(&self) -> &[OutboxEntry] {
+        &self.entries
+    }
+
+    /// Entry of `txid`
+    pub fn get(&self, txid: &str) -> Option<&OutboxEntry> {
+        self.entries.iter().find(|entry| entry.txid == txid)
+    }
+
+    /// Queue `tx`, returning its txid; queuing it again changes nothing
+    pub fn add(&mut self, tx: &Transaction) -> String {
+        let entry = OutboxEntry::new(tx);
+        let txid = entry.txid.clone();
+        if self.get(&txid).is_none() {
+            self.entries.push(entry);
+        }
+        txid
+    }
+
+    /// Drop the entry of `txid`
+    pub fn remove(&mut self, txid: &str) -> anyhow::Result<OutboxEntry> {
+        let pos = self.entries.iter().position(|entry| entry.txid == txid)
+            .ok_or_else(|| anyhow::anyhow!("No outbox entry {}", txid))?;
+        Ok(self.entries.remove(pos))
+    }
+
+    /// Drop the confirmed and rejected entries, returning how many
+    pub fn clear_finished(&mut self) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|entry| !entry.status.is_final());
+        before - self.entries.len()
+    }
+
+    /// Broadcast the entry of `txid` now, whatever its schedule
+    pub async fn broadcast_now(&mut self, txid: &str, node: &str) -> anyhow::Result<OutboxStatus> {
+        let client = reqwest::Client::new();
+        let now = chrono::Utc::now().timestamp();
+        let entry = self.entries.iter_mut().find(|entry| entry.txid == txid)
+            .ok_or_else(|| anyhow::anyhow!("No outbox entry {}", txid))?;
+        attempt(&client, node, entry, now).await;
+        Ok(entry.status)
+    }
+
+    /// Broadcast the due entries and check the broadcast ones, returning
+    /// the number of entries whose status changed
+    pub async fn process(&mut self, node: &str) -> anyhow::Result<usize> {
+        let client = reqwest::Client::new();
+        let now = chrono::Utc::now().timestamp();
+        let mut changed = 0;
+
+        for entry in self.entries.iter_mut().filter(|entry| !entry.status.is_final()) {
+            let before = entry.status;
+            // An attempt whose answer was lost may have gone through
+            if entry.attempts > 0 {
+                match lookup(&client, node, &entry.txid).await {
+                    Ok(Some(TxStatus { height: Some(height), .. })) => {
+                        entry.status = OutboxStatus::Confirmed;
+                        entry.height = Some(height);
+                    }
+                    Ok(Some(TxStatus { in_mempool: true, .. })) => entry.status = OutboxStatus::Broadcast,
+                    // Evicted, or the node restarted with an empty mempool
+                    Ok(_) if entry.status == OutboxStatus::Broadcast => {
+                        entry.status = OutboxStatus::Pending;
+                        entry.next_attempt = now;
+                    }
+                    Ok(_) | Err(_) => {}
+                }
+            }
+            if entry.is_due(now) {
+                attempt(&client, node, entry, now).await;
+            }
+            if entry.status != before {
+                changed += 1;
+            }
+        }
+        Ok(changed)
+    }
+
+    /// Whether an entry is still waiting to be broadcast or mined
+    pub fn has_unfinished(&self) -> bool {
+        self.entries.iter().any(|entry| !entry.status.is_final())
+    }
+
+    /// Transactions still waiting to be broadcast or mined, oldest first
+    pub fn unconfirmed(&self) -> anyhow::Result<Vec<Transaction>> {
+        self.entries.iter()
+            .filter(|entry| !entry.status.is_final())
+            .map(|entry| {
+                let bytes = hex::decode(&entry.tx_hex)?;
+                Transaction::decode(&bytes).map_err(|e| anyhow::anyhow!("Outbox entry {}: {}", entry.txid, e))
+            })
+            .collect()
+    }
+}
+
+/// Submit `entry` to the node, updating it with the outcome
+async fn attempt(client: &reqwest::Client, node: &str, entry: &mut OutboxEntry, now: i64) {
+    entry.attempts = entry.attempts.saturating_add(1);
+    let resp = client
+        .post(format!("{}/tx/send", node.trim_end_matches('/')))
+        .json(&serde_json::json!({ "tx_hex": entry.tx_hex }))
+        .send()
+        .await;
+    let body: serde_json::Value = match resp {
+        Ok(resp) => resp.json().await.unwrap_or_default(),
+        Err(e) => return entry.retry_later(now, format!("Node unreachable: {}", e)),
+    };
+
+    let reason = body["error"]["reason"].as_str().unwrap_or_default();
+    let message = body["error"]["message"].as_str().map(str::to_string);
+    match message {
+        None => {
+            entry.status = OutboxStatus::Broadcast;
+            entry.last_error = None;
+        }
+        // Already accepted by an earlier attempt
+        Some(_) if reason == "duplicate_transaction" => {
+            entry.status = OutboxStatus::Broadcast;
+            entry.last_error = None;
+        }
+        Some(message) if PERMANENT_REJECTIONS.contains(&reason) => {
+            entry.status = OutboxStatus::Rejected;
+            entry.last_error = Some(message);
+        }
+        Some(message) => entry.retry_later(now, message),
+    }
+}
+
+/// Status of `txid` on the node, `None` if it does not know it
+async fn lookup(client: &reqwest::Client, node: &str, txid: &str) -> anyhow::Result<Option<TxStatus>> {
+    let resp = client
+        .get(format!("{}/v1/tx/{}", node.trim_end_matches('/'), txid))
+        .send()
+        .await?;
+    if resp.status() == reqwest::StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    if !resp.status().is_success() {
+        anyhow::bail!("Node returned {} for transaction {}", resp.status(), txid);
+    }
+    Ok(Some(resp.json().await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aequitas_core::{Address, Blockchain};
+    use crate::TransactionBuilder;
+
+    #[test]
+    fn test_retry_delay() {
+        assert_eq!(retry_delay(1), RETRY_BASE);
+        assert_eq!(retry_delay(3), RETRY_BASE * 4);
+        assert_eq!(retry_delay(100), RETRY_MAX);
+
+        let unsigned = TransactionBuilder::new()
+            .from(Address::genesis_address())
+            .to(Address::genesis_address(), 1_000)
+            .build_unsigned_exact(&Blockchain::new())
+            .unwrap();
+        let mut entry = OutboxEntry::new(&unsigned.transaction);
+        assert!(entry.is_due(entry.added_at));
+
+        entry.attempts = 2;
+        entry.retry_later(100, "Node unreachable".to_string());
+        assert!(!entry.is_due(159) && entry.is_due(160));
+    }
+
+    #[test]
+    fn test_outbox_persistence() {
+        let path = std::env::temp_dir().join(format!("aequitas-outbox-{}.json", rand::random::<u64>()));
+        let unsigned = TransactionBuilder::new()
+            .from(Address::genesis_address())
+            .to(Address::genesis_address(), 1_000)
+            .build_unsigned_exact(&Blockchain::new())
+            .unwrap();
+
+        let mut outbox = Outbox::open(&path).unwrap();
+        let txid = outbox.add(&unsigned.transaction);
+        assert_eq!(outbox.add(&unsigned.transaction), txid);
+        assert_eq!(outbox.entries().len(), 1);
+        outbox.save().unwrap();
+
+        let mut outbox = Outbox::open(&path).unwrap();
+        assert_eq!(outbox.get(&txid).unwrap().status, OutboxStatus::Pending);
+        assert!(outbox.has_unfinished());
+        assert_eq!(outbox.clear_finished(), 0);
+        outbox.remove(&txid).unwrap();
+        assert!(outbox.remove(&txid).is_err());
+
+        assert_eq!(Outbox::path_for("/tmp/wallet.json"), PathBuf::from("/tmp/wallet.json.outbox.json"));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,274 @@
+//! Rescanning the chain for a wallet's funds
+//!
+//! An imported key or a restored wallet knows nothing of what its
+//! addresses received. [`WalletHistory`] walks the blocks, from a node
+//! (`/blocks/transactions`) or a chain file, and records every transaction
+//! touching the wallet and every output paid to it, spent or not. The
+//! result is kept in `<wallet>.history.json` next to the wallet file, so a
+//! later rescan can start at a height instead of the genesis block.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use aequitas_core::{Blockchain, Transaction, TxDirection};
+
+/// Extension appended to the wallet file name
+pub const HISTORY_EXTENSION: &str = "history.json";
+
+/// Blocks asked from the node per request
+pub const RESCAN_PAGE: u64 = 500;
+
+/// A transaction's net effect on one address of the wallet
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// Transaction id (hex)
+    pub txid: String,
+
+    /// Block holding it
+    pub height: u64,
+
+    /// Wallet address concerned
+    pub address: String,
+
+    /// Whether the address gained or lost funds
+    pub direction: TxDirection,
+
+    /// Net amount in base units
+    pub amount: u64,
+}
+
+/// Output paid to an address of the wallet
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScannedOutput {
+    /// Transaction paying it (hex)
+    pub txid: String,
+
+    /// Output index in that transaction
+    pub output_index: u32,
+
+    /// Wallet address paid
+    pub address: String,
+
+    /// Amount in base units
+    pub amount: u64,
+
+    /// Block holding it
+    pub height: u64,
+
+    /// Block that spent it, if any
+    pub spent_height: Option<u64>,
+}
+
+/// History and outputs of a wallet's addresses, as of a height
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WalletHistory {
+    /// Last block scanned
+    pub scanned_to: Option<u64>,
+
+    /// Transactions, in chain order
+    pub entries: Vec<HistoryEntry>,
+
+    /// Outputs paid to the wallet, in chain order
+    pub outputs: Vec<ScannedOutput>,
+}
+
+impl WalletHistory {
+    /// History path of a wallet file
+    pub fn path_for<P: AsRef<Path>>(wallet: P) -> PathBuf {
+        let wallet = wallet.as_ref();
+        let mut name = wallet.file_name().unwrap_or_default().to_os_string();
+        name.push(".");
+        name.push(HISTORY_EXTENSION);
+        wallet.with_file_name(name)
+    }
+
+    /// Load the history of a wallet file, empty if it was never scanned
+    pub fn load_for_wallet<P: AsRef<Path>>(wallet: P) -> anyhow::Result<Self> {
+        let path = Self::path_for(wallet);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    /// Save the history next to a wallet file
+    pub fn save_for_wallet<P: AsRef<Path>>(&self, wallet: P) -> anyhow::Result<()> {
+        std::fs::write(Self::path_for(wallet), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Forget everything from block `height` on, as if scanned up to the
+    /// block before
+    pub fn rewind(&mut self, height: u64) {
+        self.entries.retain(|entry| entry.height < height);
+        self.outputs.retain(|output| output.height < height);
+        for output in &mut self.outputs {
+            if output.spent_height.is_some_and(|spent| spent >= height) {
+                output.spent_height = None;
+            }
+        }
+        self.scanned_to = height.checked_sub(1).filter(|_| self.scanned_to.is_some());
+    }
+
+    /// Record the transactions of the block at `height` touching `addresses`
+    pub fn scan_block(&mut self, height: u64, transactions: &[Transaction], addresses: &HashSet<String>) {
+        for tx in transactions {
+            let txid = hex::encode(tx.txid());
+            let mut deltas: BTreeMap<String, (u64, u64)> = BTreeMap::new();
+
+            for input in &tx.inputs {
+                let prev = hex::encode(input.prev_tx_hash);
+                let spent = self.outputs.iter_mut().find(|output| {
+                    output.spent_height.is_none() && output.output_index == input.output_index && output.txid == prev
+                });
+                if let Some(output) = spent {
+                    output.spent_height = Some(height);
+                    let sent = &mut deltas.entry(output.address.clone()).or_default().0;
+                    *sent = sent.saturating_add(output.amount);
+                }
+            }
+
+            for (index, output) in tx.outputs.iter().enumerate() {
+                let address = output.recipient.to_string();
+                if !addresses.contains(&address) {
+                    continue;
+                }
+                let received = &mut deltas.entry(address.clone()).or_default().1;
+                *received = received.saturating_add(output.amount);
+                self.outputs.push(ScannedOutput {
+                    txid: txid.clone(),
+                    output_index: index as u32,
+                    address,
+                    amount: output.amount,
+                    height,
+                    spent_height: None,
+                });
+            }
+
+            for (address, (sent, received)) in deltas {
+                let (direction, amount) = if sent > received {
+                    (TxDirection::Outgoing, sent - received)
+                } else {
+                    (TxDirection::Incoming, received - sent)
+                };
+                self.entries.push(HistoryEntry { txid: txid.clone(), height, address, direction, amount });
+            }
+        }
+        self.scanned_to = Some(height);
+    }
+
+    /// Rescan the blocks of `chain` from `from` to its tip, returning the
+    /// number of blocks scanned
+    pub fn rescan_chain(&mut self, chain: &Blockchain, addresses: &HashSet<String>, from: u64) -> u64 {
+        self.rewind(from);
+        let mut scanned = 0;
+        for height in from..=chain.height() {
+            if let Some(block) = chain.get_block_at_height(height) {
+                self.scan_block(height, &block.transactions, addresses);
+                scanned += 1;
+            }
+        }
+        scanned
+    }
+
+    /// Rescan the blocks of the node at `node` from `from` to its tip,
+    /// returning the number of blocks scanned
+    pub async fn rescan_node(&mut self, node: &str, addresses: &HashSet<String>, from: u64) -> anyhow::Result<u64> {
+        self.rewind(from);
+        let client = reqwest::Client::new();
+        let mut next = Some(from);
+        let mut scanned = 0;
+
+        while let Some(from) = next {
+            let url = format!("{}/blocks/transactions?from={}&limit={}", node.trim_end_matches('/'), from, RESCAN_PAGE);
+            let resp = client.get(&url).send().await?;
+            if !resp.status().is_success() {
+                anyhow::bail!("Node returned {} for blocks from {}", resp.status(), from);
+            }
+            let page: BlockPage = resp.json().await?;
+
+            for block in page.blocks {
+                let transactions = block.transactions.iter()
+                    .map(|tx| {
+                        let bytes = hex::decode(tx)?;
+                        Transaction::decode(&bytes)
+                            .map_err(|e| anyhow::anyhow!("Invalid transaction in block {}: {}", block.height, e))
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                self.scan_block(block.height, &transactions, addresses);
+                scanned += 1;
+            }
+            next = page.next;
+        }
+        Ok(scanned)
+    }
+
+    /// Unspent outputs
+    pub fn unspent(&self) -> impl Iterator<Item = &ScannedOutput> {
+        self.outputs.iter().filter(|output| output.spent_height.is_none())
+    }
+
+    /// Unspent amount of `address`
+    pub fn balance(&self, address: &str) -> u64 {
+        self.unspent()
+            .filter(|output| output.address == address)
+            .fold(0u64, |total, output| total.saturating_add(output.amount))
+    }
+
+    /// Transactions touching `address`
+    pub fn history(&self, address: &str) -> impl Iterator<Item = &HistoryEntry> {
+        let address = address.to_string();
+        self.entries.iter().filter(move |entry| entry.address == address)
+    }
+}
+
+/// Page of `/blocks/transactions`
+#[derive(Deserialize)]
+struct BlockPage {
+    blocks: Vec<RawBlock>,
+    next: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct RawBlock {
+    height: u64,
+    transactions: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use aequitas_core::Address;
+    use ed25519_dalek::SigningKey;
+    use crate::TransactionBuilder;
+
+    #[test]
+    fn test_rescan() {
+        let chain = Blockchain::new();
+        let genesis = Address::genesis_address();
+        let addresses: HashSet<String> = [genesis.to_string()].into_iter().collect();
+
+        let mut history = WalletHistory::default();
+        assert_eq!(history.rescan_chain(&chain, &addresses, 0), 1);
+        let received = history.balance(&genesis.to_string());
+        assert!(received > 0);
+        assert_eq!(history.history(&genesis.to_string()).count(), 1);
+
+        // A payment out of the wallet at the next height, change back to it
+        let recipient = Address::from_public_key(&SigningKey::from_bytes(&[4u8; 32]).verifying_key());
+        let unsigned = TransactionBuilder::new()
+            .from(genesis.clone())
+            .to(recipient, 1_000_000)
+            .build_unsigned_exact(&chain)
+            .unwrap();
+        history.scan_block(1, &[unsigned.transaction.clone()], &addresses);
+        let fee = chain.transaction_fee(&unsigned.transaction).unwrap();
+        assert_eq!(history.balance(&genesis.to_string()), received - 1_000_000 - fee);
+        let last = history.entries.last().unwrap();
+        assert_eq!((last.direction, last.amount), (TxDirection::Outgoing, 1_000_000 + fee));
+
+        history.rewind(1);
+        assert_eq!(history.balance(&genesis.to_string()), received);
+        assert_eq!(history.scanned_to, Some(0));
+    }
+}
@@ -0,0 +1,494 @@
+//! Wallet JSON-RPC service
+//!
+//! `aequitas-wallet serve` keeps the wallet loaded and answers JSON-RPC 2.0
+//! calls posted to `/`, so that an exchange can create deposit addresses and
+//! pay withdrawals without shelling out to the CLI:
+//!
+//! ```text
+//! curl -H "Authorization: Bearer $(cat wallet.json.rpc-token)" \
+//!      -d '{"jsonrpc":"2.0","id":1,"method":"get_balance","params":{}}' \
+//!      http://127.0.0.1:23430/
+//! ```
+//!
+//! Every call must carry the bearer token kept in `<wallet>.rpc-token` next
+//! to the wallet file, created on first start and readable by its owner
+//! only. Methods:
+//!
+//! - `new_address {label?}`: generate an address and save the wallet
+//! - `list_addresses`: addresses with their labels
+//! - `get_balance {address?}`: balances from the node, of every address in
+//!   use when none is given
+//! - `send {to, amount | all, from?, fee?, memo?, reference?}`: sign a
+//!   payment of `amount` base units, queue it in the outbox and broadcast it
+//! - `get_history {address?}`: transactions touching the wallet, rescanning
+//!   the node from the last block already seen
+//!
+//! Calls are handled one at a time, so two sends never pick the same
+//! outputs; each send also builds on the outbox transactions not mined yet.
+
+use std::io::Write;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use axum::{
+    extract::State,
+    http::{header, HeaderMap, StatusCode},
+    routing::post,
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tokio::sync::Mutex;
+use zeroize::Zeroizing;
+use aequitas_core::{Address, Blockchain, Memo};
+use crate::{AuditEvent, BalanceInfo, Outbox, OutboxStatus, Wallet, WalletHistory};
+
+/// Address the service listens on by default
+pub const DEFAULT_RPC_ADDR: &str = "127.0.0.1:23430";
+
+/// Extension appended to the wallet file name
+pub const TOKEN_EXTENSION: &str = "rpc-token";
+
+/// Invalid JSON
+pub const PARSE_ERROR: i32 = -32700;
+
+/// Not a JSON-RPC 2.0 request
+pub const INVALID_REQUEST: i32 = -32600;
+
+/// No such method
+pub const METHOD_NOT_FOUND: i32 = -32601;
+
+/// Missing or malformed parameters
+pub const INVALID_PARAMS: i32 = -32602;
+
+/// The wallet or the node refused the call
+pub const WALLET_ERROR: i32 = -32000;
+
+/// Missing or wrong bearer token
+pub const UNAUTHORIZED: i32 = -32001;
+
+/// A JSON-RPC 2.0 call
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RpcRequest {
+    /// Always `2.0`
+    pub jsonrpc: String,
+
+    /// Echoed back in the response
+    #[serde(default)]
+    pub id: Value,
+
+    /// Method called
+    pub method: String,
+
+    /// Named parameters
+    #[serde(default)]
+    pub params: Value,
+}
+
+/// Error of a failed call
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RpcError {
+    /// JSON-RPC error code
+    pub code: i32,
+
+    /// Human-readable message
+    pub message: String,
+}
+
+impl RpcError {
+    /// Error with `code`
+    pub fn new(code: i32, message: impl Into<String>) -> Self {
+        Self { code, message: message.into() }
+    }
+
+    /// Error of the wallet or the node
+    pub fn wallet(error: anyhow::Error) -> Self {
+        Self::new(WALLET_ERROR, error.to_string())
+    }
+}
+
+/// Response to a call
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RpcResponse {
+    /// Always `2.0`
+    pub jsonrpc: String,
+
+    /// Id of the call
+    pub id: Value,
+
+    /// Result, on success
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+
+    /// Error, on failure
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+}
+
+impl RpcResponse {
+    /// Response to the call `id`
+    pub fn new(id: Value, outcome: Result<Value, RpcError>) -> Self {
+        let (result, error) = match outcome {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error)),
+        };
+        Self { jsonrpc: "2.0".to_string(), id, result, error }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NewAddressParams {
+    label: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AddressParams {
+    address: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SendParams {
+    to: String,
+    amount: Option<u64>,
+    #[serde(default)]
+    all: bool,
+    from: Option<String>,
+    fee: Option<u64>,
+    memo: Option<String>,
+    reference: Option<String>,
+}
+
+/// A loaded wallet answering calls
+pub struct WalletService {
+    path: PathBuf,
+    wallet: Wallet,
+    password: Zeroizing<String>,
+    node: String,
+    chain_path: PathBuf,
+    token: Zeroizing<String>,
+}
+
+impl WalletService {
+    /// Service for the wallet loaded from `path`, whose keys open with
+    /// `password`, selecting outputs from `chain_path` and broadcasting to
+    /// `node`
+    pub fn new(
+        path: PathBuf,
+        wallet: Wallet,
+        password: Zeroizing<String>,
+        node: String,
+        chain_path: PathBuf,
+        token: Zeroizing<String>,
+    ) -> Self {
+        Self { path, wallet, password, node, chain_path, token }
+    }
+
+    /// Token path of a wallet file
+    pub fn token_path<P: AsRef<Path>>(wallet: P) -> PathBuf {
+        let wallet = wallet.as_ref();
+        let mut name = wallet.file_name().unwrap_or_default().to_os_string();
+        name.push(".");
+        name.push(TOKEN_EXTENSION);
+        wallet.with_file_name(name)
+    }
+
+    /// Token of a wallet file, created if it has none yet
+    pub fn load_or_create_token<P: AsRef<Path>>(wallet: P) -> anyhow::Result<Zeroizing<String>> {
+        let path = Self::token_path(wallet);
+        if path.exists() {
+            let token = Zeroizing::new(std::fs::read_to_string(&path)?.trim().to_string());
+            if token.is_empty() {
+                anyhow::bail!("Token file {} is empty", path.display());
+            }
+            return Ok(token);
+        }
+
+        let token = Zeroizing::new(hex::encode(rand::random::<[u8; 32]>()));
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        options.open(&path)?.write_all(token.as_bytes())?;
+        Ok(token)
+    }
+
+    /// Whether an `Authorization` header value carries the token
+    pub fn authorize(&self, header: Option<&str>) -> bool {
+        let Some(given) = header.and_then(|value| value.strip_prefix("Bearer ")) else {
+            return false;
+        };
+        // Compared in constant time, so the answer time leaks nothing
+        let (given, token) = (given.trim().as_bytes(), self.token.as_bytes());
+        given.len() == token.len() && given.iter().zip(token).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+    }
+
+    /// Answer one call
+    pub async fn handle(&mut self, request: RpcRequest) -> RpcResponse {
+        if request.jsonrpc != "2.0" {
+            return RpcResponse::new(request.id, Err(RpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\"")));
+        }
+        let params = match request.params {
+            Value::Null => json!({}),
+            params => params,
+        };
+        let outcome = match request.method.as_str() {
+            "new_address" => self.new_address(params),
+            "list_addresses" => Ok(self.list_addresses()),
+            "get_balance" => self.get_balance(params).await,
+            "send" => self.send(params).await,
+            "get_history" => self.get_history(params).await,
+            method => Err(RpcError::new(METHOD_NOT_FOUND, format!("Unknown method {}", method))),
+        };
+        RpcResponse::new(request.id, outcome)
+    }
+
+    fn new_address(&mut self, params: Value) -> Result<Value, RpcError> {
+        let params: NewAddressParams = parse(params)?;
+        let address = self.wallet.new_address(&self.password, params.label.clone()).map_err(RpcError::wallet)?;
+        self.wallet.save().map_err(RpcError::wallet)?;
+        Ok(json!({ "address": address.to_string(), "label": params.label }))
+    }
+
+    fn list_addresses(&self) -> Value {
+        let active = self.wallet.active_addresses();
+        let addresses: Vec<Value> = self.wallet.key_entries().iter()
+            .map(|entry| json!({
+                "address": entry.address,
+                "label": entry.label,
+                "archived": !active.contains(&entry.address),
+            }))
+            .collect();
+        json!({ "addresses": addresses })
+    }
+
+    async fn get_balance(&self, params: Value) -> Result<Value, RpcError> {
+        let params: AddressParams = parse(params)?;
+        let addresses = match params.address {
+            Some(address) => vec![self.owned(&address)?.to_string()],
+            None => self.wallet.active_addresses(),
+        };
+        let mut balances = Vec::new();
+        for address in &addresses {
+            balances.push(BalanceInfo::fetch(&self.node, address).await.map_err(RpcError::wallet)?);
+        }
+        Ok(json!({
+            "confirmed": balances.iter().fold(0u64, |total, b| total.saturating_add(b.confirmed)),
+            "total": balances.iter().fold(0u64, |total, b| total.saturating_add(b.total())),
+            "addresses": balances,
+        }))
+    }
+
+    async fn send(&mut self, params: Value) -> Result<Value, RpcError> {
+        let params: SendParams = parse(params)?;
+        let memo = match (params.memo, params.reference) {
+            (Some(_), Some(_)) => return Err(RpcError::new(INVALID_PARAMS, "Give either memo or reference")),
+            (Some(text), None) => Some(Memo::Text(text)),
+            (None, Some(reference)) => Some(Memo::PaymentReference(reference)),
+            (None, None) => None,
+        };
+        let amount = match (params.amount, params.all) {
+            (Some(_), true) => return Err(RpcError::new(INVALID_PARAMS, "Give either amount or all")),
+            (None, false) => return Err(RpcError::new(INVALID_PARAMS, "An amount is required")),
+            (amount, _) => amount,
+        };
+        let from = match params.from {
+            Some(address) => self.owned(&address)?,
+            None => {
+                let first = self.wallet.active_addresses().into_iter().next()
+                    .ok_or_else(|| RpcError::new(WALLET_ERROR, "Wallet has no address in use"))?;
+                Address::from_string(&first).map_err(|e| RpcError::new(WALLET_ERROR, e.to_string()))?
+            }
+        };
+        let (to, label) = self.wallet.contacts().resolve(&params.to)
+            .map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))?;
+
+        self.sign_and_broadcast(from, to, label, amount, params.fee, memo).await.map_err(RpcError::wallet)
+    }
+
+    async fn sign_and_broadcast(
+        &mut self,
+        from: Address,
+        to: Address,
+        label: Option<String>,
+        amount: Option<u64>,
+        fee: Option<u64>,
+        memo: Option<Memo>,
+    ) -> anyhow::Result<Value> {
+        let chain = Blockchain::load(&self.chain_path)?;
+        if !self.wallet.is_unlocked(&from) {
+            self.wallet.unlock(&from, &self.password)?;
+        }
+
+        let builder = match amount {
+            Some(amount) => Wallet::transfer(&from, &to, amount, fee, None, memo.as_ref())?,
+            None => Wallet::sweep(&from, &to, fee, memo.as_ref())?,
+        };
+        let mut outbox = Outbox::for_wallet(&self.path)?;
+        let builder = builder.spend_unconfirmed(outbox.unconfirmed()?);
+        let tx = self.wallet.sign(builder.clone(), &chain)?;
+        let preview = builder.preview(&tx, 1, &chain)?;
+        let amount = preview.outputs.first().map_or(0, |(_, value)| *value);
+        self.wallet.audit(AuditEvent::send(&tx, &from, &to, amount, &chain))?;
+
+        // Queued first, so that a node that is down does not lose the payment
+        let txid = outbox.add(&tx);
+        outbox.save()?;
+        let status = outbox.broadcast_now(&txid, &self.node).await?;
+        outbox.save()?;
+        let error = outbox.get(&txid).and_then(|entry| entry.last_error.clone());
+        if status == OutboxStatus::Rejected {
+            anyhow::bail!("Node rejected the transaction: {}", error.unwrap_or_default());
+        }
+
+        if label.is_some() {
+            self.wallet.contacts_mut().record_payment(&to, label.as_deref());
+            self.wallet.save()?;
+        }
+        Ok(json!({
+            "txid": txid,
+            "from": from.to_string(),
+            "to": to.to_string(),
+            "amount": amount,
+            "fee": preview.fee,
+            "status": status.as_str(),
+            "error": error,
+        }))
+    }
+
+    async fn get_history(&self, params: Value) -> Result<Value, RpcError> {
+        let params: AddressParams = parse(params)?;
+        let address = params.address.as_deref().map(|address| self.owned(address)).transpose()?;
+        let addresses = self.wallet.addresses().into_iter().collect();
+
+        // Addresses created since the last scan have nothing in earlier blocks
+        let mut history = WalletHistory::load_for_wallet(&self.path).map_err(RpcError::wallet)?;
+        let from = history.scanned_to.map_or(0, |height| height + 1);
+        history.rescan_node(&self.node, &addresses, from).await.map_err(RpcError::wallet)?;
+        history.save_for_wallet(&self.path).map_err(RpcError::wallet)?;
+
+        let entries: Vec<_> = match &address {
+            Some(address) => history.history(&address.to_string()).collect(),
+            None => history.entries.iter().collect(),
+        };
+        Ok(json!({ "scanned_to": history.scanned_to, "entries": entries }))
+    }
+
+    /// `address`, which must belong to the wallet
+    fn owned(&self, address: &str) -> Result<Address, RpcError> {
+        let address = Address::from_string(address)
+            .map_err(|e| RpcError::new(INVALID_PARAMS, format!("Invalid address {}: {}", address, e)))?;
+        if !self.wallet.owns(&address) {
+            return Err(RpcError::new(INVALID_PARAMS, format!("{} is not in this wallet", address)));
+        }
+        Ok(address)
+    }
+}
+
+/// Named parameters of a method
+fn parse<T: serde::de::DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, e.to_string()))
+}
+
+/// Serve `service` on `addr` until the process stops
+pub async fn serve(service: WalletService, addr: SocketAddr) -> anyhow::Result<()> {
+    let router = Router::new()
+        .route("/", post(rpc_handler))
+        .with_state(Arc::new(Mutex::new(service)));
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    axum::serve(listener, router).await?;
+    Ok(())
+}
+
+async fn rpc_handler(
+    State(service): State<Arc<Mutex<WalletService>>>,
+    headers: HeaderMap,
+    body: String,
+) -> (StatusCode, Json<RpcResponse>) {
+    let mut service = service.lock().await;
+    let auth = headers.get(header::AUTHORIZATION).and_then(|value| value.to_str().ok());
+    if !service.authorize(auth) {
+        let error = RpcError::new(UNAUTHORIZED, "Missing or invalid bearer token");
+        return (StatusCode::UNAUTHORIZED, Json(RpcResponse::new(Value::Null, Err(error))));
+    }
+
+    let request = match serde_json::from_str::<Value>(&body) {
+        Ok(request) => request,
+        Err(e) => {
+            let error = RpcError::new(PARSE_ERROR, e.to_string());
+            return (StatusCode::OK, Json(RpcResponse::new(Value::Null, Err(error))));
+        }
+    };
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+    let response = match serde_json::from_value::<RpcRequest>(request) {
+        Ok(request) => service.handle(request).await,
+        Err(e) => RpcResponse::new(id, Err(RpcError::new(INVALID_REQUEST, e.to_string()))),
+    };
+    (StatusCode::OK, Json(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn service() -> WalletService {
+        let path = std::env::temp_dir().join(format!("aequitas-service-{}.json", rand::random::<u64>()));
+        let mut wallet = Wallet::new();
+        wallet.save_to(&path).unwrap();
+        WalletService::new(
+            path,
+            wallet,
+            Zeroizing::new("secret".to_string()),
+            "http://127.0.0.1:1".to_string(),
+            PathBuf::from("chain.json"),
+            Zeroizing::new("token".to_string()),
+        )
+    }
+
+    fn call(method: &str, params: Value) -> RpcRequest {
+        RpcRequest { jsonrpc: "2.0".to_string(), id: json!(7), method: method.to_string(), params }
+    }
+
+    #[test]
+    fn test_authorize() {
+        let service = service();
+        assert!(service.authorize(Some("Bearer token")));
+        assert!(!service.authorize(Some("Bearer tokem")));
+        assert!(!service.authorize(Some("Bearer token2")));
+        assert!(!service.authorize(Some("token")));
+        assert!(!service.authorize(None));
+        std::fs::remove_file(&service.path).ok();
+
+        assert_eq!(WalletService::token_path("/tmp/wallet.json"), PathBuf::from("/tmp/wallet.json.rpc-token"));
+    }
+
+    #[tokio::test]
+    async fn test_dispatch() {
+        let mut service = service();
+
+        let created = service.handle(call("new_address", json!({ "label": "deposits" }))).await;
+        assert_eq!(created.id, json!(7));
+        let address = created.result.unwrap()["address"].as_str().unwrap().to_string();
+
+        let listed = service.handle(call("list_addresses", Value::Null)).await.result.unwrap();
+        assert_eq!(listed["addresses"][0]["address"], json!(address));
+        assert_eq!(listed["addresses"][0]["label"], json!("deposits"));
+
+        let error = |response: RpcResponse| response.error.unwrap().code;
+        assert_eq!(error(service.handle(call("mine", json!({}))).await), METHOD_NOT_FOUND);
+        assert_eq!(error(service.handle(call("new_address", json!({ "lable": "x" }))).await), INVALID_PARAMS);
+        assert_eq!(error(service.handle(call("send", json!({ "to": address }))).await), INVALID_PARAMS);
+        assert_eq!(error(service.handle(call("send", json!({ "to": address, "amount": 1, "all": true }))).await), INVALID_PARAMS);
+        let mut old = call("list_addresses", Value::Null);
+        old.jsonrpc = "1.0".to_string();
+        assert_eq!(error(service.handle(old).await), INVALID_REQUEST);
+
+        std::fs::remove_file(&service.path).ok();
+        std::fs::remove_file(crate::AuditLog::path_for(&service.path)).ok();
+    }
+}
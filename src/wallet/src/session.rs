@@ -0,0 +1,139 @@
+//! Unlock sessions kept in the OS keychain
+//!
+//! `aequitas-wallet keychain unlock` stores the wallet password in the
+//! keychain of the operating system (Keychain on macOS, Credential Manager
+//! on Windows, the Secret Service on Linux) with an expiry time, so the
+//! commands run within the session don't ask for it. The entry is dropped
+//! once expired or on `keychain lock`.
+//!
+//! The keychain needs the `keychain` cargo feature; without it, sessions
+//! cannot be started and every password is asked for.
+
+use std::path::Path;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+/// Keychain service the entries are stored under
+pub const KEYCHAIN_SERVICE: &str = "aequitas-wallet";
+
+/// Session length when none is given
+pub const DEFAULT_SESSION: Duration = Duration::from_secs(15 * 60);
+
+/// What the keychain holds for one wallet
+#[derive(Clone, Serialize, Deserialize, Zeroize, ZeroizeOnDrop)]
+pub struct UnlockToken {
+    /// Wallet password
+    pub password: String,
+
+    /// End of the session (Unix seconds)
+    pub expires_at: i64,
+}
+
+impl UnlockToken {
+    /// Token for `password`, valid for `duration` from now
+    pub fn new(password: &str, duration: Duration) -> Self {
+        Self {
+            password: password.to_string(),
+            expires_at: chrono::Utc::now().timestamp().saturating_add(duration.as_secs() as i64),
+        }
+    }
+
+    /// Whether the session is over at `now` (Unix seconds)
+    pub fn is_expired(&self, now: i64) -> bool {
+        now >= self.expires_at
+    }
+}
+
+/// Keychain account of the wallet at `path`: its absolute path
+fn account(path: &Path) -> String {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf()).display().to_string()
+}
+
+/// Start a session for the wallet at `path`
+pub fn store(path: &Path, token: &UnlockToken) -> anyhow::Result<()> {
+    let secret = Zeroizing::new(serde_json::to_string(token)?);
+    backend::set(&account(path), &secret)
+}
+
+/// Password of the current session of the wallet at `path`, if any
+///
+/// An expired session is removed from the keychain.
+pub fn password(path: &Path) -> anyhow::Result<Option<Zeroizing<String>>> {
+    let account = account(path);
+    let Some(secret) = backend::get(&account)? else {
+        return Ok(None);
+    };
+    let token: UnlockToken = serde_json::from_str(&secret)?;
+    if token.is_expired(chrono::Utc::now().timestamp()) {
+        backend::delete(&account)?;
+        return Ok(None);
+    }
+    Ok(Some(Zeroizing::new(token.password.clone())))
+}
+
+/// End the session of the wallet at `path`
+pub fn clear(path: &Path) -> anyhow::Result<()> {
+    backend::delete(&account(path))
+}
+
+#[cfg(feature = "keychain")]
+mod backend {
+    use keyring::{Entry, Error};
+    use zeroize::Zeroizing;
+    use super::KEYCHAIN_SERVICE;
+
+    pub fn set(account: &str, secret: &str) -> anyhow::Result<()> {
+        Entry::new(KEYCHAIN_SERVICE, account)?.set_password(secret)?;
+        Ok(())
+    }
+
+    pub fn get(account: &str) -> anyhow::Result<Option<Zeroizing<String>>> {
+        match Entry::new(KEYCHAIN_SERVICE, account)?.get_password() {
+            Ok(secret) => Ok(Some(Zeroizing::new(secret))),
+            Err(Error::NoEntry) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn delete(account: &str) -> anyhow::Result<()> {
+        match Entry::new(KEYCHAIN_SERVICE, account)?.delete_password() {
+            Ok(()) | Err(Error::NoEntry) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+#[cfg(not(feature = "keychain"))]
+mod backend {
+    use zeroize::Zeroizing;
+
+    pub fn set(_account: &str, _secret: &str) -> anyhow::Result<()> {
+        anyhow::bail!("Built without keychain support; rebuild with --features keychain")
+    }
+
+    pub fn get(_account: &str) -> anyhow::Result<Option<Zeroizing<String>>> {
+        Ok(None)
+    }
+
+    pub fn delete(_account: &str) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unlock_token() {
+        let token = UnlockToken::new("pw", Duration::from_secs(60));
+        let now = chrono::Utc::now().timestamp();
+        assert!(!token.is_expired(now));
+        assert!(token.is_expired(now + 60));
+
+        let json = serde_json::to_string(&token).unwrap();
+        let parsed: UnlockToken = serde_json::from_str(&json).unwrap();
+        assert_eq!((parsed.password.as_str(), parsed.expires_at), ("pw", token.expires_at));
+    }
+}
@@ -0,0 +1,209 @@
+//! Interactive wallet shell
+//!
+//! `aequitas-wallet shell` loads the keystore once and reads commands until
+//! `exit`. Addresses unlocked with `unlock` stay unlocked for the rest of the
+//! session, so later commands don't ask for the password again. Keys are
+//! locked (and their memory zeroed) when the shell exits.
+
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use clap::{Parser, Subcommand};
+use aequitas_core::Address;
+use aequitas_core::amount::AmountFormat;
+use aequitas_wallet::{AuditEvent, AuditLog, Keystore};
+
+/// One line of shell input
+#[derive(Parser)]
+#[command(no_binary_name = true, disable_version_flag = true)]
+struct ShellLine {
+    #[command(subcommand)]
+    command: ShellCommand,
+}
+
+#[derive(Subcommand)]
+enum ShellCommand {
+    /// List all addresses
+    List,
+
+    /// Show wallet and session info
+    Info,
+
+    /// Unlock an address for the rest of the session
+    Unlock {
+        /// Address to unlock
+        address: String,
+    },
+
+    /// Lock every unlocked address
+    Lock,
+
+    /// Generate a new address
+    Generate {
+        /// Optional label
+        #[arg(short, long)]
+        label: Option<String>,
+    },
+
+    /// Export the private key of an unlocked address (DANGEROUS!)
+    Export {
+        /// Address to export
+        address: String,
+    },
+
+    /// Show balance (all addresses if none given)
+    Balance {
+        /// Address to check
+        address: Option<String>,
+
+        /// List each unspent output with its confirmations
+        #[arg(long)]
+        utxos: bool,
+    },
+
+    /// Derive the address of an M-of-N multisig policy
+    MultisigAddress {
+        /// Required number of signatures
+        #[arg(short, long)]
+        threshold: u8,
+
+        /// Public key in hex (repeat for each co-signer, order matters)
+        #[arg(short, long = "key", required = true)]
+        keys: Vec<String>,
+    },
+
+    /// Leave the shell
+    #[command(alias = "quit")]
+    Exit,
+}
+
+/// Shell session state
+struct Session {
+    path: PathBuf,
+    keystore: Keystore,
+    audit: AuditLog,
+    node: String,
+    format: AmountFormat,
+}
+
+/// Run the shell until `exit` or end of input
+pub async fn run(path: PathBuf, node: String, format: AmountFormat) -> anyhow::Result<()> {
+    if !path.exists() {
+        anyhow::bail!("Wallet not found: {}. Use 'new' to create one.", path.display());
+    }
+
+    let mut session = Session {
+        keystore: crate::load_keystore(&path)?,
+        audit: AuditLog::for_wallet(&path)?,
+        path,
+        node,
+        format,
+    };
+
+    println!("\n🐚 Aequitas wallet shell ({})", session.path.display());
+    println!("   Type 'help' for commands, 'exit' to quit.\n");
+
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("aeq> ");
+        std::io::stdout().flush()?;
+
+        let Some(line) = lines.next().transpose()? else {
+            println!();
+            break;
+        };
+
+        let Some(words) = shlex::split(&line) else {
+            println!("❌ Unbalanced quotes");
+            continue;
+        };
+        if words.is_empty() {
+            continue;
+        }
+
+        let command = match ShellLine::try_parse_from(&words) {
+            Ok(parsed) => parsed.command,
+            Err(e) => {
+                let _ = e.print();
+                continue;
+            }
+        };
+
+        if matches!(command, ShellCommand::Exit) {
+            break;
+        }
+        if let Err(e) = session.execute(command).await {
+            println!("❌ {}", e);
+        }
+    }
+
+    session.keystore.lock_all();
+    println!("🔒 Session closed, keys locked.\n");
+    Ok(())
+}
+
+impl Session {
+    async fn execute(&mut self, command: ShellCommand) -> anyhow::Result<()> {
+        match command {
+            ShellCommand::List => {
+                for (i, addr) in self.keystore.addresses().iter().enumerate() {
+                    let lock = match Address::from_string(addr) {
+                        Ok(a) if self.keystore.is_unlocked(&a) => "🔓",
+                        _ => "🔒",
+                    };
+                    println!("  {}. {} {}", i + 1, lock, addr);
+                }
+            }
+            ShellCommand::Info => {
+                let unlocked = self.unlocked().len();
+                println!("  📁 File:      {}", self.path.display());
+                println!("  🔢 Addresses: {}", self.keystore.key_count());
+                println!("  🔓 Unlocked:  {}", unlocked);
+                println!("  🌐 Node:      {}", self.node);
+            }
+            ShellCommand::Unlock { address } => {
+                let addr = Address::from_string(&address)?;
+                let password = rpassword::prompt_password("Password: ")?;
+                self.keystore.unlock(&addr, &password)?;
+                self.audit.record(AuditEvent::Unlock { address: address.clone() })?;
+                println!("🔓 {} unlocked for this session", address);
+            }
+            ShellCommand::Lock => {
+                self.keystore.lock_all();
+                println!("🔒 All addresses locked");
+            }
+            ShellCommand::Generate { label } => {
+                let password = rpassword::prompt_password("Password for the new address: ")?;
+                let address = self.keystore.generate_key(&password, label.clone())?;
+                self.keystore.save()?;
+                self.audit.record(AuditEvent::Generate { address: address.to_string(), label })?;
+                println!("✅ New address: {}", address);
+            }
+            ShellCommand::Export { address } => {
+                let addr = Address::from_string(&address)?;
+                let key = self.keystore.get_signing_key(&addr)
+                    .ok_or_else(|| anyhow::anyhow!("{} is locked; run 'unlock {}' first", address, address))?;
+                self.audit.record(AuditEvent::Export { address: address.clone() })?;
+                println!("⚠️  Never share your private key with anyone!");
+                println!("🔑 {}", hex::encode(key.to_bytes()));
+            }
+            ShellCommand::Balance { address, utxos } => {
+                crate::cmd_balance(&self.path, address, &self.node, utxos, self.format).await?;
+            }
+            ShellCommand::MultisigAddress { threshold, keys } => {
+                crate::cmd_multisig_address(threshold, &keys)?;
+            }
+            ShellCommand::Exit => {}
+        }
+
+        Ok(())
+    }
+
+    /// Addresses unlocked in this session
+    fn unlocked(&self) -> Vec<Address> {
+        self.keystore.addresses().iter()
+            .filter_map(|a| Address::from_string(a).ok())
+            .filter(|a| self.keystore.is_unlocked(a))
+            .collect()
+    }
+}
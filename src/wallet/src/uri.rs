@@ -0,0 +1,237 @@
+//! Payment request URIs
+//!
+//! A merchant asks for an exact payment with an `aequitas:` URI, shown as
+//! a QR code or a link:
+//!
+//! ```text
+//! aequitas:aeq1...?amount=5&label=invoice%2042&reference=INV-42
+//! ```
+//!
+//! `amount` is in AEQ with a `.` decimal point, `label` and `message` are
+//! for display, `reference` becomes the payment reference memo. Unknown
+//! parameters are ignored unless prefixed with `req-`, which marks those a
+//! wallet must understand to pay.
+
+use std::path::Path;
+use aequitas_core::amount::{parse_amount_in, AmountFormat, Unit, DECIMALS};
+use aequitas_core::{Address, Memo};
+use qrcode::render::unicode;
+use qrcode::QrCode;
+
+/// URI scheme of payment requests
+pub const URI_SCHEME: &str = "aequitas";
+
+/// Side of the PNG QR codes, in pixels
+pub const QR_PNG_SIZE: u32 = 320;
+
+/// A request to pay an address
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PaymentRequest {
+    /// Address to pay
+    pub address: Address,
+
+    /// Amount requested in base units
+    pub amount: Option<u64>,
+
+    /// Name of the payee or of the request
+    pub label: Option<String>,
+
+    /// Note shown to the payer
+    pub message: Option<String>,
+
+    /// Payment reference to attach as memo
+    pub reference: Option<String>,
+}
+
+impl PaymentRequest {
+    /// Request for any amount to `address`
+    pub fn new(address: Address) -> Self {
+        Self { address, amount: None, label: None, message: None, reference: None }
+    }
+
+    /// Whether `text` looks like a payment URI rather than an address
+    pub fn is_uri(text: &str) -> bool {
+        text.trim()
+            .get(..URI_SCHEME.len() + 1)
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case(&format!("{}:", URI_SCHEME)))
+    }
+
+    /// The request as an `aequitas:` URI
+    pub fn to_uri(&self) -> String {
+        let mut params = Vec::new();
+        if let Some(amount) = self.amount {
+            let amount = AmountFormat::new(Unit::Aeq).format_number(amount);
+            let amount = if amount.contains('.') { amount.trim_end_matches('0') } else { amount.as_str() };
+            params.push(format!("amount={}", amount));
+        }
+        for (name, value) in [("label", &self.label), ("message", &self.message), ("reference", &self.reference)] {
+            if let Some(value) = value {
+                params.push(format!("{}={}", name, percent_encode(value)));
+            }
+        }
+
+        let mut uri = format!("{}:{}", URI_SCHEME, self.address);
+        if !params.is_empty() {
+            uri.push('?');
+            uri.push_str(&params.join("&"));
+        }
+        uri
+    }
+
+    /// Read an `aequitas:` URI
+    pub fn parse(uri: &str) -> anyhow::Result<Self> {
+        let uri = uri.trim();
+        if !Self::is_uri(uri) {
+            anyhow::bail!("Not an {}: URI", URI_SCHEME);
+        }
+        let rest = &uri[URI_SCHEME.len() + 1..];
+        let (address, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let address = Address::from_string(address.trim_start_matches("//"))
+            .map_err(|e| anyhow::anyhow!("Invalid address in payment URI: {}", e))?;
+
+        let mut request = Self::new(address);
+        for pair in query.split('&').filter(|pair| !pair.is_empty()) {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let value = percent_decode(value)?;
+            let slot = match name {
+                "amount" => {
+                    if request.amount.is_some() {
+                        anyhow::bail!("Payment URI gives the amount twice");
+                    }
+                    request.amount = Some(parse_uri_amount(&value)?);
+                    continue;
+                }
+                "label" => &mut request.label,
+                "message" => &mut request.message,
+                "reference" => &mut request.reference,
+                name if name.starts_with("req-") => {
+                    anyhow::bail!("Payment URI requires an unsupported parameter: {}", name)
+                }
+                _ => continue,
+            };
+            *slot = Some(value);
+        }
+        Ok(request)
+    }
+
+    /// Memo the payment carries, if the request gives a reference
+    pub fn memo(&self) -> anyhow::Result<Option<Memo>> {
+        let Some(reference) = &self.reference else {
+            return Ok(None);
+        };
+        let memo = Memo::PaymentReference(reference.clone());
+        memo.encode()?;
+        Ok(Some(memo))
+    }
+
+    /// QR code of the URI
+    pub fn qr_code(&self) -> anyhow::Result<QrCode> {
+        Ok(QrCode::new(self.to_uri().as_bytes())?)
+    }
+
+    /// QR code drawn with block characters, light on dark, for a terminal
+    pub fn qr_terminal(&self) -> anyhow::Result<String> {
+        Ok(self.qr_code()?
+            .render::<unicode::Dense1x2>()
+            .dark_color(unicode::Dense1x2::Light)
+            .light_color(unicode::Dense1x2::Dark)
+            .build())
+    }
+
+    /// Save the QR code as a PNG image
+    pub fn save_qr_png<P: AsRef<Path>>(&self, path: P) -> anyhow::Result<()> {
+        self.qr_code()?
+            .render::<image::Luma<u8>>()
+            .min_dimensions(QR_PNG_SIZE, QR_PNG_SIZE)
+            .build()
+            .save_with_format(path, image::ImageFormat::Png)?;
+        Ok(())
+    }
+}
+
+/// Amount of a URI: AEQ with a `.` decimal point and no unit
+fn parse_uri_amount(value: &str) -> anyhow::Result<u64> {
+    let (whole, frac) = value.split_once('.').unwrap_or((value, ""));
+    let digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+    if whole.is_empty() || !digits(whole) || !digits(frac) || frac.len() > DECIMALS as usize {
+        anyhow::bail!("Invalid amount in payment URI: {}", value);
+    }
+    Ok(parse_amount_in(value, Unit::Aeq)?)
+}
+
+/// Percent-encode everything but unreserved characters
+fn percent_encode(value: &str) -> String {
+    value.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn percent_decode(value: &str) -> anyhow::Result<String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = value.get(i + 1..i + 3)
+                .ok_or_else(|| anyhow::anyhow!("Truncated escape in payment URI"))?;
+            decoded.push(u8::from_str_radix(hex, 16)
+                .map_err(|_| anyhow::anyhow!("Invalid escape in payment URI: %{}", hex))?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    Ok(String::from_utf8(decoded)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::SigningKey;
+
+    fn address() -> Address {
+        Address::from_public_key(&SigningKey::from_bytes(&[9u8; 32]).verifying_key())
+    }
+
+    #[test]
+    fn test_uri_roundtrip() {
+        let mut request = PaymentRequest::new(address());
+        assert_eq!(request.to_uri(), format!("aequitas:{}", address()));
+
+        request.amount = Some(5_250_000_000);
+        request.label = Some("invoice 42".to_string());
+        request.reference = Some("INV-42".to_string());
+        let uri = request.to_uri();
+        assert_eq!(uri, format!("aequitas:{}?amount=5.25&label=invoice%2042&reference=INV-42", address()));
+        assert_eq!(PaymentRequest::parse(&uri).unwrap(), request);
+        assert_eq!(request.memo().unwrap(), Some(Memo::PaymentReference("INV-42".to_string())));
+        assert!(request.qr_terminal().unwrap().contains('█'));
+    }
+
+    #[test]
+    fn test_uri_parsing() {
+        let addr = address();
+        assert!(PaymentRequest::is_uri(&format!("AEQUITAS:{}", addr)));
+        assert!(!PaymentRequest::is_uri(&addr.to_string()));
+
+        let request = PaymentRequest::parse(&format!("aequitas:{}?amount=1&foo=bar", addr)).unwrap();
+        assert_eq!(request.amount, Some(1_000_000_000));
+
+        for bad in [
+            format!("aequitas:{}?req-expires=10", addr),
+            format!("aequitas:{}?amount=1,5", addr),
+            format!("aequitas:{}?amount=1%20AEQ", addr),
+            format!("aequitas:{}?amount=0.0000000001", addr),
+            format!("aequitas:{}?amount=1&amount=2", addr),
+            format!("aequitas:{}?label=%zz", addr),
+            "aequitas:aeq1notanaddress".to_string(),
+            format!("bitcoin:{}", addr),
+        ] {
+            assert!(PaymentRequest::parse(&bad).is_err(), "{}", bad);
+        }
+    }
+}
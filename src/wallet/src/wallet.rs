@@ -2,11 +2,15 @@
 //!
 //! High-level wallet interface for managing keys and creating transactions.
 
-use aequitas_core::{Address, Transaction, TxInput, TxOutput, Blockchain};
+use aequitas_core::{Address, Memo, Transaction, TxInput, TxOutput, Blockchain};
 use aequitas_core::blockchain::UtxoId;
-use crate::keystore::Keystore;
+use crate::audit::{AuditEvent, AuditLog};
+use crate::contacts::AddressBook;
+use crate::keystore::{EncryptedKey, Keystore};
 use crate::builder::TransactionBuilder;
+use crate::offline::TxEnvelope;
 use std::path::Path;
+use zeroize::Zeroizing;
 
 /// Wallet for managing keys and transactions
 pub struct Wallet {
@@ -15,6 +19,9 @@ pub struct Wallet {
     
     /// Default address for receiving
     default_address: Option<Address>,
+    
+    /// Audit log next to the wallet file (none until saved)
+    audit: Option<AuditLog>,
 }
 
 impl Wallet {
@@ -23,6 +30,7 @@ impl Wallet {
         Self {
             keystore: Keystore::new(),
             default_address: None,
+            audit: None,
         }
     }
     
@@ -31,13 +39,74 @@ impl Wallet {
         Self {
             keystore,
             default_address: None,
+            audit: None,
         }
     }
     
     /// Load wallet from file
     pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
-        let keystore = Keystore::load(path)?;
-        Ok(Self::from_keystore(keystore))
+        let keystore = Keystore::load(&path)?;
+        let mut wallet = Self::from_keystore(keystore);
+        wallet.audit = Some(AuditLog::for_wallet(&path)?);
+        Ok(wallet)
+    }
+    
+    /// Load a wallet whose file is encrypted as a whole
+    pub fn load_encrypted<P: AsRef<Path>>(path: P, password: &str) -> anyhow::Result<Self> {
+        let keystore = Keystore::load_encrypted(&path, password)?;
+        let mut wallet = Self::from_keystore(keystore);
+        wallet.audit = Some(AuditLog::for_wallet(&path)?);
+        Ok(wallet)
+    }
+    
+    /// Encrypt the whole wallet file with `password` from the next save on
+    pub fn encrypt_file(&mut self, password: &str) -> anyhow::Result<()> {
+        self.keystore.encrypt_file(password)
+    }
+    
+    /// Whether the wallet file is encrypted as a whole
+    pub fn is_file_encrypted(&self) -> bool {
+        self.keystore.is_file_encrypted()
+    }
+    
+    /// Stored entry of `address`, with its label and metadata
+    pub fn key_entry(&self, address: &Address) -> Option<&EncryptedKey> {
+        self.keystore.key_entry(address)
+    }
+    
+    /// Stored entries of every key
+    pub fn key_entries(&self) -> &[EncryptedKey] {
+        self.keystore.key_entries()
+    }
+    
+    /// Set or clear the label of `address`
+    pub fn set_label(&mut self, address: &Address, label: Option<&str>) -> anyhow::Result<()> {
+        self.keystore.set_label(address, label)
+    }
+    
+    /// Set or clear the note of `address`
+    pub fn set_note(&mut self, address: &Address, note: Option<&str>) -> anyhow::Result<()> {
+        self.keystore.set_note(address, note)
+    }
+    
+    /// Archive `address` or bring it back into use
+    pub fn set_archived(&mut self, address: &Address, archived: bool) -> anyhow::Result<()> {
+        self.keystore.set_archived(address, archived)
+    }
+    
+    /// Hide `address` from listings or show it again
+    pub fn set_hidden(&mut self, address: &Address, hidden: bool) -> anyhow::Result<()> {
+        self.keystore.set_hidden(address, hidden)
+    }
+    
+    /// Address book
+    pub fn contacts(&self) -> &AddressBook {
+        self.keystore.contacts()
+    }
+    
+    /// Address book, to edit before saving
+    pub fn contacts_mut(&mut self) -> &mut AddressBook {
+        self.keystore.contacts_mut()
     }
     
     /// Save wallet to file
@@ -47,17 +116,72 @@ impl Wallet {
     
     /// Save wallet to specific path
     pub fn save_to<P: AsRef<Path>>(&mut self, path: P) -> anyhow::Result<()> {
-        self.keystore.save_to(path)
+        self.keystore.save_to(&path)?;
+        if self.audit.is_none() {
+            self.audit = Some(AuditLog::for_wallet(&path)?);
+        }
+        Ok(())
+    }
+    
+    /// Record an event in the audit log, if the wallet has one
+    pub fn audit(&mut self, event: AuditEvent) -> anyhow::Result<()> {
+        if let Some(log) = &mut self.audit {
+            log.record(event)?;
+        }
+        Ok(())
+    }
+    
+    /// Derive addresses from a new mnemonic from now on, returning the
+    /// phrase to back up
+    pub fn init_hd(&mut self, password: &str) -> anyhow::Result<Zeroizing<String>> {
+        self.keystore.create_hd(password)
+    }
+    
+    /// Derive addresses from the mnemonic `phrase`, adding back those
+    /// `is_used` reports activity for, up to `gap_limit` unused in a row
+    pub fn restore_hd(
+        &mut self,
+        phrase: &str,
+        password: &str,
+        gap_limit: u32,
+        is_used: impl FnMut(&Address) -> bool,
+    ) -> anyhow::Result<Vec<Address>> {
+        self.keystore.restore_hd(phrase, password)?;
+        let used = self.keystore.scan_hd(password, gap_limit, is_used)?;
+        
+        if self.default_address.is_none() {
+            self.default_address = used.first().cloned();
+        }
+        for address in &used {
+            self.audit(AuditEvent::Generate { address: address.to_string(), label: None })?;
+        }
+        Ok(used)
     }
     
-    /// Generate a new address
+    /// Whether addresses are derived from a mnemonic
+    pub fn is_hd(&self) -> bool {
+        self.keystore.is_hd()
+    }
+    
+    /// Mnemonic phrase addresses are derived from
+    pub fn mnemonic(&self, password: &str) -> anyhow::Result<Zeroizing<String>> {
+        self.keystore.mnemonic(password)
+    }
+    
+    /// Generate a new address, derived from the mnemonic if the wallet has
+    /// one and random otherwise
     pub fn new_address(&mut self, password: &str, label: Option<String>) -> anyhow::Result<Address> {
-        let addr = self.keystore.generate_key(password, label)?;
+        let addr = if self.keystore.is_hd() {
+            self.keystore.derive_key(password, false, label.clone())?
+        } else {
+            self.keystore.generate_key(password, label.clone())?
+        };
         
         if self.default_address.is_none() {
             self.default_address = Some(addr.clone());
         }
         
+        self.audit(AuditEvent::Generate { address: addr.to_string(), label })?;
         Ok(addr)
     }
     
@@ -76,7 +200,9 @@ impl Wallet {
         let mut bytes = [0u8; 32];
         bytes.copy_from_slice(&key_bytes);
         
-        self.keystore.import_key(&bytes, password, label)
+        let addr = self.keystore.import_key(&bytes, password, label)?;
+        self.audit(AuditEvent::Import { address: addr.to_string() })?;
+        Ok(addr)
     }
     
     /// Get default address
@@ -94,9 +220,34 @@ impl Wallet {
         self.keystore.addresses()
     }
     
+    /// Addresses that are not archived
+    pub fn active_addresses(&self) -> Vec<String> {
+        self.keystore.active_addresses()
+    }
+    
     /// Unlock an address
     pub fn unlock(&mut self, address: &Address, password: &str) -> anyhow::Result<()> {
-        self.keystore.unlock(address, password)
+        self.keystore.unlock(address, password)?;
+        self.audit(AuditEvent::Unlock { address: address.to_string() })
+    }
+    
+    /// Encrypt the key of `address` with a new password
+    pub fn change_password(&mut self, address: &Address, old_password: &str, new_password: &str) -> anyhow::Result<()> {
+        self.keystore.change_password(address, old_password, new_password)?;
+        self.audit(AuditEvent::ChangePassword { address: address.to_string() })
+    }
+    
+    /// Encrypt every key, the HD seed and, if encrypted, the wallet file
+    /// with a new password, all or nothing; returns the number of keys
+    pub fn change_all_passwords(&mut self, old_password: &str, new_password: &str) -> anyhow::Result<usize> {
+        let count = self.keystore.change_all_passwords(old_password, new_password)?;
+        if self.keystore.is_file_encrypted() {
+            self.keystore.encrypt_file(new_password)?;
+        }
+        for address in self.keystore.addresses() {
+            self.audit(AuditEvent::ChangePassword { address })?;
+        }
+        Ok(count)
     }
     
     /// Lock all addresses
@@ -125,7 +276,7 @@ impl Wallet {
     
     /// Create a transaction
     pub fn create_transaction(
-        &self,
+        &mut self,
         from: &Address,
         to: &Address,
         amount: u64,
@@ -138,15 +289,178 @@ impl Wallet {
         let signing_key = self.keystore.get_signing_key(from)
             .ok_or_else(|| anyhow::anyhow!("Signing key not found"))?;
         
-        TransactionBuilder::new()
+        let tx = TransactionBuilder::new()
             .from(from.clone())
             .to(to.clone(), amount)
-            .build_and_sign(signing_key, chain)
+            .build_and_sign(signing_key, chain)?;
+        
+        self.audit(AuditEvent::send(&tx, from, to, amount, chain))?;
+        Ok(tx)
+    }
+    
+    /// Check whether an address belongs to this wallet
+    pub fn owns(&self, address: &Address) -> bool {
+        self.keystore.addresses().contains(&address.to_string())
+    }
+    
+    /// Sign the transaction described by `builder` without recording it
+    ///
+    /// The sender must be unlocked and the change address, if set, must
+    /// belong to this wallet. The fee is priced on the signed size unless
+    /// the builder sets one.
+    pub fn sign(&self, builder: TransactionBuilder, chain: &Blockchain) -> anyhow::Result<Transaction> {
+        let from = builder.sender()
+            .ok_or_else(|| anyhow::anyhow!("Sender address not set"))?;
+        let signing_key = self.keystore.get_signing_key(from)
+            .ok_or_else(|| anyhow::anyhow!("Address is not unlocked"))?;
+        
+        if let Some(change) = builder.change_address() {
+            if !self.owns(change) {
+                anyhow::bail!("Change address {} does not belong to this wallet", change);
+            }
+        }
+        
+        builder.build_and_sign_exact(signing_key, chain)
+    }
+    
+    /// Addresses of this wallet with inputs of `envelope` left to sign
+    ///
+    /// These own single-key inputs, or are the cosigner keys of multisig
+    /// inputs short of their threshold that they have not signed yet.
+    pub fn envelope_signers(&self, envelope: &TxEnvelope) -> anyhow::Result<Vec<Address>> {
+        let tx = envelope.transaction()?;
+        
+        let mut signers: Vec<Address> = Vec::new();
+        for (input, spent) in tx.inputs.iter().zip(&envelope.spent) {
+            if input.is_signed() {
+                continue;
+            }
+            let candidates = match &input.multisig {
+                Some(policy) => policy.public_keys.iter().enumerate()
+                    .filter(|(key_index, _)| !has_cosigned(input, *key_index))
+                    .map(|(_, key)| cosigner_address(key))
+                    .collect::<anyhow::Result<Vec<_>>>()?,
+                None => vec![Address::from_string(&spent.address)?],
+            };
+            for address in candidates {
+                if self.owns(&address) && !signers.contains(&address) {
+                    signers.push(address);
+                }
+            }
+        }
+        Ok(signers)
+    }
+    
+    /// Sign the inputs of `envelope` spending outputs of unlocked addresses
+    ///
+    /// Multisig inputs get a signature from each unlocked cosigner key until
+    /// they reach their threshold. Inputs already signed and inputs of other
+    /// addresses are left as they are. Returns how many were signed.
+    pub fn sign_envelope(&self, envelope: &mut TxEnvelope) -> anyhow::Result<usize> {
+        let mut tx = envelope.transaction()?;
+        let message = tx.signing_message();
+        
+        let mut signed = 0;
+        for (input, spent) in tx.inputs.iter_mut().zip(&envelope.spent) {
+            if input.is_signed() {
+                continue;
+            }
+            
+            match input.multisig.clone() {
+                Some(policy) => {
+                    let mut cosigned = false;
+                    for (key_index, key) in policy.public_keys.iter().enumerate() {
+                        if input.is_signed() {
+                            break;
+                        }
+                        if has_cosigned(input, key_index) {
+                            continue;
+                        }
+                        if let Some(signing_key) = self.keystore.get_signing_key(&cosigner_address(key)?) {
+                            input.sign_multisig(signing_key, &message)?;
+                            cosigned = true;
+                        }
+                    }
+                    signed += cosigned as usize;
+                }
+                None => {
+                    let address = Address::from_string(&spent.address)?;
+                    if let Some(signing_key) = self.keystore.get_signing_key(&address) {
+                        input.sign(signing_key, &message);
+                        signed += 1;
+                    }
+                }
+            }
+        }
+        
+        envelope.set_transaction(&tx)?;
+        Ok(signed)
+    }
+    
+    /// Sign a transfer without recording it, for review before it is sent
+    ///
+    /// Record the send in the audit log once the transaction is broadcast.
+    pub fn sign_transfer(
+        &self,
+        from: &Address,
+        to: &Address,
+        amount: u64,
+        fee: Option<u64>,
+        change_to: Option<Address>,
+        memo: Option<&Memo>,
+        chain: &Blockchain,
+    ) -> anyhow::Result<Transaction> {
+        let builder = Self::transfer(from, to, amount, fee, change_to, memo)?;
+        self.sign(builder, chain)
+    }
+    
+    /// Builder of a transfer, to adjust before [`sign`](Self::sign)
+    pub fn transfer(
+        from: &Address,
+        to: &Address,
+        amount: u64,
+        fee: Option<u64>,
+        change_to: Option<Address>,
+        memo: Option<&Memo>,
+    ) -> anyhow::Result<TransactionBuilder> {
+        let mut builder = TransactionBuilder::new()
+            .from(from.clone())
+            .to(to.clone(), amount);
+        if let Some(memo) = memo {
+            builder = builder.typed_memo(memo)?;
+        }
+        if let Some(fee) = fee {
+            builder = builder.fee(fee);
+        }
+        if let Some(change) = change_to {
+            builder = builder.change_to(change);
+        }
+        Ok(builder)
+    }
+    
+    /// Builder of a transfer of everything `from` holds to `to`, the fee
+    /// deducted from the amount sent (see [`TransactionBuilder::send_max`])
+    pub fn sweep(
+        from: &Address,
+        to: &Address,
+        fee: Option<u64>,
+        memo: Option<&Memo>,
+    ) -> anyhow::Result<TransactionBuilder> {
+        let mut builder = TransactionBuilder::new()
+            .from(from.clone())
+            .send_max(to.clone());
+        if let Some(memo) = memo {
+            builder = builder.typed_memo(memo)?;
+        }
+        if let Some(fee) = fee {
+            builder = builder.fee(fee);
+        }
+        Ok(builder)
     }
     
     /// Create a transaction with custom fee
     pub fn create_transaction_with_fee(
-        &self,
+        &mut self,
         from: &Address,
         to: &Address,
         amount: u64,
@@ -160,11 +474,14 @@ impl Wallet {
         let signing_key = self.keystore.get_signing_key(from)
             .ok_or_else(|| anyhow::anyhow!("Signing key not found"))?;
         
-        TransactionBuilder::new()
+        let tx = TransactionBuilder::new()
             .from(from.clone())
             .to(to.clone(), amount)
             .fee(fee)
-            .build_and_sign(signing_key, chain)
+            .build_and_sign(signing_key, chain)?;
+        
+        self.audit(AuditEvent::send(&tx, from, to, amount, chain))?;
+        Ok(tx)
     }
 }
 
@@ -174,65 +491,24 @@ impl Default for Wallet {
     }
 }
 
-/// Wallet balance info
-#[derive(Clone, Debug)]
-pub struct BalanceInfo {
-    /// Confirmed balance
-    pub confirmed: u64,
-    
-    /// Unconfirmed balance (pending transactions)
-    pub unconfirmed: u64,
-    
-    /// Total balance
-    pub total: u64,
-    
-    /// Number of UTXOs
-    pub utxo_count: usize,
-}
-
-impl BalanceInfo {
-    /// Create from blockchain state
-    pub fn from_chain(address: &Address, chain: &Blockchain) -> Self {
-        let utxos = chain.get_utxos_for_address(address);
-        let confirmed: u64 = utxos.iter().map(|(_, o)| o.amount).sum();
-        
-        Self {
-            confirmed,
-            unconfirmed: 0, // TODO: Track mempool
-            total: confirmed,
-            utxo_count: utxos.len(),
-        }
-    }
-}
-
 /// Format balance for display (9 decimal places)
 pub fn format_balance(amount: u64) -> String {
-    let whole = amount / 1_000_000_000;
-    let frac = amount % 1_000_000_000;
-    
-    if frac == 0 {
-        format!("{} AEQ", whole)
-    } else {
-        format!("{}.{:09} AEQ", whole, frac)
-    }
+    aequitas_core::format_amount(amount)
 }
 
 /// Parse balance from string
 pub fn parse_balance(s: &str) -> anyhow::Result<u64> {
-    let s = s.trim().to_lowercase().replace(" aeq", "").replace("aeq", "");
-    
-    if let Some(dot_pos) = s.find('.') {
-        let whole: u64 = s[..dot_pos].parse()?;
-        let frac_str = &s[dot_pos + 1..];
-        let frac_len = frac_str.len().min(9);
-        let frac: u64 = frac_str[..frac_len].parse()?;
-        let multiplier = 10u64.pow(9 - frac_len as u32);
-        
-        Ok(whole * 1_000_000_000 + frac * multiplier)
-    } else {
-        let whole: u64 = s.parse()?;
-        Ok(whole * 1_000_000_000)
-    }
+    Ok(aequitas_core::parse_amount(s)?)
+}
+
+/// Address of the single key `key`, as a cosigner's wallet holds it
+fn cosigner_address(key: &[u8; 32]) -> anyhow::Result<Address> {
+    Ok(Address::from_public_key(&ed25519_dalek::VerifyingKey::from_bytes(key)?))
+}
+
+/// Whether key `key_index` of a multisig input has signed it
+fn has_cosigned(input: &TxInput, key_index: usize) -> bool {
+    input.multisig_signatures.iter().any(|signature| signature.key_index as usize == key_index)
 }
 
 #[cfg(test)]
@@ -266,4 +542,129 @@ mod tests {
         wallet.unlock(&addr, password).unwrap();
         assert!(wallet.is_unlocked(&addr));
     }
+    
+    #[test]
+    fn test_hd_wallet_restore() {
+        let mut wallet = Wallet::new();
+        let phrase = wallet.init_hd("pw").unwrap();
+        let first = wallet.new_address("pw", None).unwrap();
+        let second = wallet.new_address("pw", None).unwrap();
+        
+        let mut restored = Wallet::new();
+        let used = restored.restore_hd(&phrase, "pw", 2, |address| *address == first || *address == second).unwrap();
+        assert!(restored.is_hd());
+        assert_eq!(used, vec![first.clone(), second]);
+        assert_eq!(restored.default_address(), Some(&first));
+    }
+    
+    #[test]
+    fn test_change_address_must_be_owned() {
+        let mut wallet = Wallet::new();
+        let addr = wallet.new_address("pw", None).unwrap();
+        let fresh = wallet.new_address("pw", None).unwrap();
+        wallet.unlock(&addr, "pw").unwrap();
+        let chain = Blockchain::new();
+        
+        let foreign = TransactionBuilder::new()
+            .from(addr.clone())
+            .to(Address::genesis_address(), 1_000)
+            .change_to(Address::genesis_address());
+        let err = wallet.sign(foreign, &chain).unwrap_err();
+        assert!(err.to_string().contains("does not belong"));
+        
+        assert!(wallet.owns(&fresh));
+        assert!(!wallet.owns(&Address::genesis_address()));
+    }
+    
+    #[test]
+    fn test_sign_envelope() {
+        use base64::{engine::general_purpose::STANDARD, Engine as _};
+        use crate::offline::{SpentOutput, ENVELOPE_FORMAT, ENVELOPE_VERSION};
+        
+        let mut wallet = Wallet::new();
+        let addr = wallet.new_address("pw", None).unwrap();
+        let tx = Transaction::new_transfer(
+            vec![TxInput::new([1; 32], 0), TxInput::new([2; 32], 0)],
+            vec![TxOutput::new(Address::genesis_address(), 5_000)],
+        );
+        let spent = |address: &Address| SpentOutput { address: address.to_string(), amount: 4_000 };
+        let mut envelope = TxEnvelope {
+            format: ENVELOPE_FORMAT.to_string(),
+            version: ENVELOPE_VERSION,
+            network: "mainnet".to_string(),
+            transaction: STANDARD.encode(tx.encode()),
+            spent: vec![spent(&addr), spent(&Address::genesis_address())],
+        };
+        
+        // Locked keys sign nothing
+        assert_eq!(wallet.sign_envelope(&mut envelope).unwrap(), 0);
+        
+        wallet.unlock(&addr, "pw").unwrap();
+        assert_eq!(wallet.sign_envelope(&mut envelope).unwrap(), 1);
+        assert_eq!(wallet.sign_envelope(&mut envelope).unwrap(), 0);
+        
+        let signed = envelope.transaction().unwrap();
+        assert!(signed.inputs[0].is_signed() && !signed.inputs[1].is_signed());
+        assert_eq!(signed.txid(), tx.txid());
+        assert_eq!(envelope.fee().unwrap(), 3_000);
+    }
+    
+    #[test]
+    fn test_multisig_envelope() {
+        use base64::{engine::general_purpose::STANDARD, Engine as _};
+        use aequitas_core::MultisigPolicy;
+        use crate::offline::{SpentOutput, ENVELOPE_FORMAT, ENVELOPE_VERSION};
+        
+        // Three cosigners, each with their own wallet
+        let mut wallets: Vec<Wallet> = (0..3).map(|_| Wallet::new()).collect();
+        let mut keys = Vec::new();
+        for wallet in &mut wallets {
+            let address = wallet.new_address("pw", None).unwrap();
+            wallet.unlock(&address, "pw").unwrap();
+            keys.push(wallet.keystore.get_signing_key(&address).unwrap().verifying_key().to_bytes());
+        }
+        let policy = MultisigPolicy::new(2, keys).unwrap();
+        
+        let tx = Transaction::new_transfer(
+            vec![TxInput::new_multisig([1; 32], 0, policy.clone())],
+            vec![TxOutput::new(Address::genesis_address(), 5_000)],
+        );
+        let unsigned = TxEnvelope {
+            format: ENVELOPE_FORMAT.to_string(),
+            version: ENVELOPE_VERSION,
+            network: "mainnet".to_string(),
+            transaction: STANDARD.encode(tx.encode()),
+            spent: vec![SpentOutput { address: policy.address().to_string(), amount: 6_000 }],
+        };
+        assert_eq!(wallets[0].envelope_signers(&unsigned).unwrap().len(), 1);
+        
+        // Cosigners 0 and 2 sign their own copies
+        let mut first = unsigned.clone();
+        assert_eq!(wallets[0].sign_envelope(&mut first).unwrap(), 1);
+        assert!(wallets[0].envelope_signers(&first).unwrap().is_empty());
+        assert!(!first.is_signed().unwrap());
+        assert!(first.finalize().is_err());
+        
+        let mut second = unsigned.clone();
+        assert_eq!(wallets[2].sign_envelope(&mut second).unwrap(), 1);
+        
+        first.combine(&second).unwrap();
+        assert!(first.is_signed().unwrap());
+        assert!(wallets[1].envelope_signers(&first).unwrap().is_empty());
+        
+        let signed = first.finalize().unwrap();
+        let key_indexes: Vec<u8> = signed.inputs[0].multisig_signatures.iter().map(|s| s.key_index).collect();
+        assert_eq!(key_indexes, vec![0, 2]);
+        assert_eq!(signed.txid(), tx.txid());
+        signed.verify_signatures().unwrap();
+        
+        // A copy of another transaction does not combine
+        let other = Transaction::new_transfer(
+            vec![TxInput::new_multisig([2; 32], 0, policy)],
+            vec![TxOutput::new(Address::genesis_address(), 5_000)],
+        );
+        let mut other_envelope = unsigned.clone();
+        other_envelope.transaction = STANDARD.encode(other.encode());
+        assert!(first.combine(&other_envelope).is_err());
+    }
 }